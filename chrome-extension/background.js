@@ -1,3 +1,21 @@
+// Show how many saved URLs are still unread on the toolbar icon
+function refreshBadge() {
+    fetch("http://localhost:8080/api/v1/badge")
+        .then(response => response.ok ? response.json() : Promise.reject(new Error(response.statusText)))
+        .then(({ unread_count, total }) => {
+            chrome.action.setBadgeText({ text: unread_count > 0 ? String(unread_count) : "" });
+            chrome.action.setTitle({ title: `Read it Later: ${unread_count} unread of ${total}` });
+        })
+        .catch(() => chrome.action.setBadgeText({ text: "" }));
+}
+
+chrome.alarms.onAlarm.addListener((alarm) => {
+    if (alarm.name === "refreshBadge") {
+        refreshBadge();
+    }
+});
+chrome.runtime.onStartup.addListener(refreshBadge);
+
 // Listen for messages from the popup or other parts of the extension
 chrome.runtime.onMessage.addListener((message, sender, sendResponse) => {
     if (message.action === "sendUrl") {
@@ -10,6 +28,7 @@ chrome.runtime.onMessage.addListener((message, sender, sendResponse) => {
         })
             .then(response => {
                 if (response.ok) {
+                    refreshBadge();
                     sendResponse({ status: "success" });
                 } else {
                     sendResponse({ status: "error", error: response.statusText });
@@ -20,16 +39,26 @@ chrome.runtime.onMessage.addListener((message, sender, sendResponse) => {
             });
 
         return true; // Keep the message channel open for async response
+    } else if (message.action === "checkUrl") {
+        fetch(`http://localhost:8080/urls/exists?url=${encodeURIComponent(message.url)}&match=loose`)
+            .then(response => response.ok ? response.json() : Promise.reject(new Error(response.statusText)))
+            .then(saved => sendResponse({ status: "success", saved }))
+            .catch(error => {
+                sendResponse({ status: "error", error: error.message });
+            });
+
+        return true;
     } else if (message.action === "removeUrl") {
         fetch("http://localhost:8080/urls/delete/by-url", {
             method: "POST",
             headers: {
                 "Content-Type": "application/json"
             },
-            body: JSON.stringify({ url: message.url })
+            body: JSON.stringify({ url: message.url, match: "loose" })
         })
             .then(response => {
                 if (response.ok) {
+                    refreshBadge();
                     sendResponse({ status: "success" });
                 } else {
                     sendResponse({ status: "error", error: response.statusText });
@@ -69,6 +98,7 @@ chrome.runtime.onMessage.addListener((message, sender, sendResponse) => {
                 "Content-Type": "application/json"
             },
             body: JSON.stringify({
+                ...message.source, // Selected markup, page title, favicon and selection range, when captured
                 url: message.url,
                 snippet: message.snippet,
                 tags: message.tags
@@ -96,17 +126,65 @@ chrome.runtime.onInstalled.addListener(() => {
         title: "Send Snippet to Read It Later",
         contexts: ["selection"] // Show only when text is selected
     });
+
+    chrome.alarms.create("refreshBadge", { periodInMinutes: 1 });
+    refreshBadge();
 });
 
+// Runs in the page: the selection's markup and where it sits in the page's text
+function captureSelection() {
+    const selection = window.getSelection();
+    if (!selection || selection.rangeCount === 0) {
+        return {};
+    }
+
+    const range = selection.getRangeAt(0);
+    const container = document.createElement("div");
+    container.appendChild(range.cloneContents());
+
+    // Offsets count characters of the body's text up to each end of the selection
+    const before = document.createRange();
+    before.selectNodeContents(document.body);
+    before.setEnd(range.startContainer, range.startOffset);
+    const after = document.createRange();
+    after.selectNodeContents(document.body);
+    after.setStart(range.endContainer, range.endOffset);
+    const textBefore = before.toString();
+    const start = textBefore.length;
+
+    return {
+        html: container.innerHTML,
+        selection: {
+            start,
+            end: start + range.toString().length,
+            prefix: textBefore.slice(-64),
+            suffix: after.toString().slice(0, 64)
+        }
+    };
+}
+
 // Handle context menu clicks
-chrome.contextMenus.onClicked.addListener((info, tab) => {
+chrome.contextMenus.onClicked.addListener(async (info, tab) => {
     if (info.menuItemId === "sendSnippet" && info.selectionText) {
         const highlightedText = info.selectionText; // Get selected text
         const tabUrl = tab.url;
 
+        // Pages the extension can't script, like the Web Store, still send the plain text
+        let captured = {};
+        try {
+            const [result] = await chrome.scripting.executeScript({
+                target: { tabId: tab.id, frameIds: [info.frameId ?? 0] },
+                func: captureSelection
+            });
+            captured = result?.result ?? {};
+        } catch (error) {
+            console.warn("Could not capture the selection's markup:", error);
+        }
+        const source = { ...captured, title: tab.title, favicon: tab.favIconUrl };
+
         // Open the context manager popup for adding tags
         chrome.windows.create({
-            url: `context-manager-popup.html?url=${encodeURIComponent(tabUrl)}&snippet=${encodeURIComponent(highlightedText)}&contextManager=true`,
+            url: `context-manager-popup.html?url=${encodeURIComponent(tabUrl)}&snippet=${encodeURIComponent(highlightedText)}&source=${encodeURIComponent(JSON.stringify(source))}&contextManager=true`,
             type: "popup",
             width: 400,
             height: 300
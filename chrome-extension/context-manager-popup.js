@@ -9,11 +9,12 @@ document.addEventListener("DOMContentLoaded", async () => {
     const urlParams = new URLSearchParams(window.location.search);
     const snippet = urlParams.get("snippet");
     const tabUrl = urlParams.get("url");
+    const source = JSON.parse(urlParams.get("source") || "{}");
 
-    handleContextManager(tabUrl, snippet, statusElement, tagsInput);
+    handleContextManager(tabUrl, snippet, source, statusElement, tagsInput);
 });
 
-function handleContextManager(tabUrl, snippet, statusElement, tagsInput) {
+function handleContextManager(tabUrl, snippet, source, statusElement, tagsInput) {
     // Enable the tags input field
     tagsInput.classList.add("enabled");
 
@@ -30,7 +31,7 @@ function handleContextManager(tabUrl, snippet, statusElement, tagsInput) {
 
         try {
             chrome.runtime.sendMessage(
-                { action: "sendSnippet", url: tabUrl, snippet, tags },
+                { action: "sendSnippet", url: tabUrl, snippet, source, tags },
                 response => {
                     if (response.status === "success") {
                         statusElement.textContent = "Snippet sent successfully with tags: " + tags;
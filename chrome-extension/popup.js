@@ -70,6 +70,22 @@ function handleUrl(tabUrl, statusElement, removeButton, tagsInput) {
     removeButton.classList.add("enabled");
     tagsInput.classList.add("enabled");
 
+    // Pages saved before show their tags and read state instead of being sent again
+    chrome.runtime.sendMessage({ action: "checkUrl", url: tabUrl }, response => {
+        if (response.status === "success" && response.saved.exists) {
+            const state = response.saved.read ? "read" : "unread";
+            statusElement.textContent = `Already saved (${state})`;
+            response.saved.tags.forEach(tag => {
+                addTag(tag);
+                tagsList.push(tag);
+            });
+        } else {
+            sendUrl(tabUrl, statusElement);
+        }
+    });
+}
+
+function sendUrl(tabUrl, statusElement) {
     // Attempt to send the URL
     try {
         chrome.runtime.sendMessage(
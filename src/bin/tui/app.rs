@@ -0,0 +1,284 @@
+use crate::client::UrlEntry;
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+/// What the bottom line is currently editing, if anything
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Mode {
+    #[default]
+    Normal,
+    /// Narrowing the loaded list by a substring of the URL, its title or its tags
+    Search,
+    /// Typing comma-separated tags for the selected URL
+    Tag,
+    /// Typing a server-side `tags=` expression, e.g. `rust+async,-video`
+    Filter,
+}
+
+/// Work for the main loop to do against the server after a key press
+#[derive(Debug, PartialEq)]
+pub enum Action {
+    None,
+    Reload,
+    SetRead { url: String, read: bool },
+    AddTags { url: String, tags: String },
+}
+
+#[derive(Debug, Default)]
+pub struct App {
+    pub entries: Vec<UrlEntry>,
+    pub mode: Mode,
+    pub search: String,
+    pub tag_filter: String,
+    /// Text being typed in `Mode::Tag` or `Mode::Filter`
+    pub input: String,
+    /// Position in `visible()`
+    pub selected: usize,
+    pub status: String,
+    pub quit: bool,
+}
+
+impl App {
+    /// Entries matching the search, case-insensitively, against the URL, its title and its tags
+    pub fn visible(&self) -> Vec<&UrlEntry> {
+        let needle = self.search.to_lowercase();
+        self.entries
+            .iter()
+            .filter(|entry| {
+                needle.is_empty()
+                    || entry.url.to_lowercase().contains(&needle)
+                    || entry.display_title.to_lowercase().contains(&needle)
+                    || entry.tags.iter().any(|tag| tag.to_lowercase().contains(&needle))
+            })
+            .collect()
+    }
+
+    pub fn selected_entry(&self) -> Option<&UrlEntry> {
+        self.visible().get(self.selected).copied()
+    }
+
+    /// Replace the list after a reload, keeping the same URL selected when it's still there
+    pub fn set_entries(&mut self, entries: Vec<UrlEntry>) {
+        let previous = self.selected_entry().map(|entry| entry.url.clone());
+        self.entries = entries;
+        self.selected = previous
+            .and_then(|url| self.visible().iter().position(|entry| entry.url == url))
+            .unwrap_or(0);
+        self.clamp_selection();
+    }
+
+    fn clamp_selection(&mut self) {
+        self.selected = self.selected.min(self.visible().len().saturating_sub(1));
+    }
+
+    fn move_selection(&mut self, delta: isize) {
+        self.selected = self.selected.saturating_add_signed(delta);
+        self.clamp_selection();
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent) -> Action {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            self.quit = true;
+            return Action::None;
+        }
+
+        match self.mode {
+            Mode::Normal => self.handle_normal_key(key.code),
+            Mode::Search => {
+                match key.code {
+                    KeyCode::Esc => {
+                        self.search.clear();
+                        self.mode = Mode::Normal;
+                    }
+                    KeyCode::Enter => self.mode = Mode::Normal,
+                    KeyCode::Backspace => {
+                        self.search.pop();
+                    }
+                    KeyCode::Char(ch) => self.search.push(ch),
+                    _ => {}
+                }
+                self.clamp_selection();
+                Action::None
+            }
+            Mode::Tag | Mode::Filter => self.handle_input_key(key.code),
+        }
+    }
+
+    fn handle_normal_key(&mut self, code: KeyCode) -> Action {
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+            KeyCode::Char('j') | KeyCode::Down => self.move_selection(1),
+            KeyCode::Char('k') | KeyCode::Up => self.move_selection(-1),
+            KeyCode::Char('g') | KeyCode::Home => self.selected = 0,
+            KeyCode::Char('G') | KeyCode::End => self.move_selection(isize::MAX),
+            KeyCode::Char('/') => self.mode = Mode::Search,
+            KeyCode::Char('f') => {
+                self.input = self.tag_filter.clone();
+                self.mode = Mode::Filter;
+            }
+            KeyCode::Char('t') if self.selected_entry().is_some() => {
+                self.input.clear();
+                self.mode = Mode::Tag;
+            }
+            KeyCode::Char('r') => {
+                if let Some(entry) = self.selected_entry() {
+                    return Action::SetRead {
+                        url: entry.url.clone(),
+                        read: entry.read_at.is_none(),
+                    };
+                }
+            }
+            KeyCode::Char('R') => return Action::Reload,
+            _ => {}
+        }
+        Action::None
+    }
+
+    fn handle_input_key(&mut self, code: KeyCode) -> Action {
+        match code {
+            KeyCode::Esc => {
+                self.input.clear();
+                self.mode = Mode::Normal;
+            }
+            KeyCode::Backspace => {
+                self.input.pop();
+            }
+            KeyCode::Char(ch) => self.input.push(ch),
+            KeyCode::Enter => {
+                let input = std::mem::take(&mut self.input);
+                let mode = std::mem::take(&mut self.mode);
+                if mode == Mode::Filter {
+                    self.tag_filter = input.trim().to_string();
+                    self.selected = 0;
+                    return Action::Reload;
+                }
+                if let Some(entry) = self.selected_entry() {
+                    if !input.trim().is_empty() {
+                        return Action::AddTags {
+                            url: entry.url.clone(),
+                            tags: input,
+                        };
+                    }
+                }
+            }
+            _ => {}
+        }
+        Action::None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(url: &str, tags: &[&str], read: bool) -> UrlEntry {
+        UrlEntry {
+            url: url.to_string(),
+            display_title: String::new(),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            read_at: read.then(|| "2024-05-01T09:30:00".to_string()),
+            short_id: "1a2b3c4d5e".to_string(),
+            pinned: false,
+            progress: 0,
+        }
+    }
+
+    fn app() -> App {
+        let mut app = App::default();
+        app.set_entries(vec![
+            entry("https://rust-lang.org/blog", &["rust"], false),
+            entry("https://example.com/Python", &["python"], true),
+            entry("https://news.example/async", &["rust", "async"], false),
+        ]);
+        app
+    }
+
+    fn press(app: &mut App, keys: &str) -> Action {
+        let mut action = Action::None;
+        for ch in keys.chars() {
+            let code = match ch {
+                '\n' => KeyCode::Enter,
+                '\x1b' => KeyCode::Esc,
+                ch => KeyCode::Char(ch),
+            };
+            action = app.handle_key(KeyEvent::from(code));
+        }
+        action
+    }
+
+    #[test]
+    fn search_matches_urls_and_tags_case_insensitively() {
+        let mut app = app();
+        press(&mut app, "/PYTHON\n");
+        let urls: Vec<_> = app.visible().iter().map(|entry| entry.url.as_str()).collect();
+        assert_eq!(urls, vec!["https://example.com/Python"]);
+
+        press(&mut app, "/\x1b");
+        assert_eq!(app.visible().len(), 3);
+
+        press(&mut app, "/async\n");
+        assert_eq!(app.selected_entry().unwrap().url, "https://news.example/async");
+
+        app.entries[0].display_title = "Fearless Concurrency".to_string();
+        press(&mut app, "/\x1b/fearless\n");
+        assert_eq!(app.selected_entry().unwrap().url, "https://rust-lang.org/blog");
+    }
+
+    #[test]
+    fn selection_stays_in_bounds() {
+        let mut app = app();
+        press(&mut app, "kk");
+        assert_eq!(app.selected, 0);
+        press(&mut app, "jjjjj");
+        assert_eq!(app.selected, 2);
+        press(&mut app, "g");
+        assert_eq!(app.selected, 0);
+        press(&mut app, "G");
+        assert_eq!(app.selected, 2);
+    }
+
+    #[test]
+    fn r_toggles_read_state_of_the_selection() {
+        let mut app = app();
+        assert_eq!(
+            press(&mut app, "r"),
+            Action::SetRead {
+                url: "https://rust-lang.org/blog".to_string(),
+                read: true
+            }
+        );
+        assert_eq!(
+            press(&mut app, "jr"),
+            Action::SetRead {
+                url: "https://example.com/Python".to_string(),
+                read: false
+            }
+        );
+    }
+
+    #[test]
+    fn tagging_and_filtering_produce_server_actions() {
+        let mut app = app();
+        assert_eq!(
+            press(&mut app, "jjtlang, systems\n"),
+            Action::AddTags {
+                url: "https://news.example/async".to_string(),
+                tags: "lang, systems".to_string()
+            }
+        );
+        assert_eq!(app.mode, Mode::Normal);
+
+        assert_eq!(press(&mut app, "frust+async\n"), Action::Reload);
+        assert_eq!(app.tag_filter, "rust+async");
+        assert_eq!(app.selected, 0);
+    }
+
+    #[test]
+    fn reload_keeps_the_selected_url() {
+        let mut app = app();
+        press(&mut app, "jj");
+        let mut entries = app.entries.clone();
+        entries.insert(0, entry("https://new.example", &[], false));
+        app.set_entries(entries);
+        assert_eq!(app.selected_entry().unwrap().url, "https://news.example/async");
+    }
+}
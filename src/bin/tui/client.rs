@@ -0,0 +1,78 @@
+use serde::Deserialize;
+use serde_json::json;
+
+/// One row of `GET /urls_with_tags`
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct UrlEntry {
+    pub url: String,
+    /// The title the server shows the URL as; older servers don't send one
+    #[serde(default)]
+    pub display_title: String,
+    pub tags: Vec<String>,
+    pub read_at: Option<String>,
+    pub short_id: String,
+    #[serde(default)]
+    pub pinned: bool,
+    #[serde(default)]
+    pub progress: i32,
+}
+
+/// Thin client for the server's HTTP API
+pub struct Client {
+    http: reqwest::Client,
+    base_url: String,
+}
+
+impl Client {
+    /// `base_url` includes any sub-path the server is hosted under, e.g. `https://example.com/read`
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Saved URLs, newest first, optionally restricted by a `tags=` expression
+    pub async fn list(&self, tags: &str) -> Result<Vec<UrlEntry>, String> {
+        let mut request = self.http.get(format!("{}/urls_with_tags", self.base_url));
+        if !tags.is_empty() {
+            request = request.query(&[("tags", tags)]);
+        }
+        let response = check(request.send().await).await?;
+        response.json().await.map_err(|err| err.to_string())
+    }
+
+    pub async fn set_read(&self, url: &str, read: bool) -> Result<(), String> {
+        let request = self
+            .http
+            .post(format!("{}/urls/read", self.base_url))
+            .json(&json!({ "url": url, "read": read }));
+        check(request.send().await).await.map(|_| ())
+    }
+
+    /// `tags` is comma separated, as typed
+    pub async fn add_tags(&self, url: &str, tags: &str) -> Result<(), String> {
+        let request = self
+            .http
+            .post(format!("{}/urls/tags", self.base_url))
+            .json(&json!({ "url": url, "tags": tags }));
+        check(request.send().await).await.map(|_| ())
+    }
+}
+
+/// Turn transport errors and non-2xx responses into the server's message
+async fn check(result: reqwest::Result<reqwest::Response>) -> Result<reqwest::Response, String> {
+    let response = result.map_err(|err| err.to_string())?;
+    if response.status().is_success() {
+        return Ok(response);
+    }
+
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    let message = serde_json::from_str::<String>(&body).unwrap_or(body);
+    Err(format!("{}: {}", status, message))
+}
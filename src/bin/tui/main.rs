@@ -0,0 +1,73 @@
+//! Terminal client for a running read_it_later server.
+//!
+//! Usage: `read_it_later-tui [server url]`; the URL defaults to `READ_IT_LATER_URL`, then `http://localhost:8080`.
+mod app;
+mod client;
+mod ui;
+
+use app::{Action, App};
+use ratatui::crossterm::event::{self, Event, KeyEventKind};
+use ratatui::DefaultTerminal;
+use std::env;
+use std::io;
+
+const DEFAULT_SERVER_URL: &str = "http://localhost:8080";
+
+#[tokio::main]
+async fn main() -> io::Result<()> {
+    let server_url = env::args()
+        .nth(1)
+        .or_else(|| env::var("READ_IT_LATER_URL").ok())
+        .unwrap_or_else(|| DEFAULT_SERVER_URL.to_string());
+    let client = client::Client::new(&server_url);
+
+    let mut app = App::default();
+    reload(&client, &mut app).await;
+
+    let mut terminal = ratatui::init();
+    let result = run(&mut terminal, &client, &mut app).await;
+    ratatui::restore();
+    result
+}
+
+async fn run(terminal: &mut DefaultTerminal, client: &client::Client, app: &mut App) -> io::Result<()> {
+    while !app.quit {
+        terminal.draw(|frame| ui::render(frame, app, client.base_url()))?;
+
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+
+        app.status.clear();
+        match app.handle_key(key) {
+            Action::None => {}
+            Action::Reload => reload(client, app).await,
+            Action::SetRead { url, read } => match client.set_read(&url, read).await {
+                Ok(()) => {
+                    app.status = if read { "Marked read" } else { "Marked unread" }.to_string();
+                    reload(client, app).await;
+                }
+                Err(err) => app.status = err,
+            },
+            Action::AddTags { url, tags } => match client.add_tags(&url, &tags).await {
+                Ok(()) => {
+                    app.status = format!("Tagged {}", url);
+                    reload(client, app).await;
+                }
+                Err(err) => app.status = err,
+            },
+        }
+    }
+    Ok(())
+}
+
+/// Fetch the list again, leaving the old one in place and the error in the status line if the server can't be reached
+async fn reload(client: &client::Client, app: &mut App) {
+    match client.list(&app.tag_filter).await {
+        Ok(entries) => app.set_entries(entries),
+        Err(err) => app.status = format!("Failed to load URLs: {}", err),
+    }
+}
@@ -0,0 +1,75 @@
+use crate::app::{App, Mode};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph};
+use ratatui::Frame;
+
+const HELP: &str = "j/k move  / search  f filter tags  t tag  r read/unread  R reload  q quit";
+
+pub fn render(frame: &mut Frame, app: &App, server: &str) {
+    let [list_area, status_area] = Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+
+    let visible = app.visible();
+    let items: Vec<ListItem> = visible
+        .iter()
+        .map(|entry| {
+            let marker = if entry.pinned {
+                "📌"
+            } else if entry.read_at.is_some() {
+                "✓ "
+            } else {
+                "  "
+            };
+            let url_style = if entry.read_at.is_some() {
+                Style::default().fg(Color::DarkGray)
+            } else {
+                Style::default()
+            };
+            let mut spans = vec![
+                Span::raw(format!("{} ", marker)),
+                Span::styled(
+                    if entry.display_title.is_empty() {
+                        entry.url.clone()
+                    } else {
+                        entry.display_title.clone()
+                    },
+                    url_style,
+                ),
+            ];
+            if entry.progress > 0 && entry.read_at.is_none() {
+                spans.push(Span::styled(
+                    format!(" {}%", entry.progress),
+                    Style::default().fg(Color::Green),
+                ));
+            }
+            for tag in &entry.tags {
+                spans.push(Span::styled(format!(" [{}]", tag), Style::default().fg(Color::Cyan)));
+            }
+            ListItem::new(Line::from(spans))
+        })
+        .collect();
+
+    let mut title = format!(" read_it_later — {} ({} URLs) ", server, visible.len());
+    if !app.tag_filter.is_empty() {
+        title.push_str(&format!("tags={} ", app.tag_filter));
+    }
+    if !app.search.is_empty() {
+        title.push_str(&format!("search={} ", app.search));
+    }
+
+    let list = List::new(items)
+        .block(Block::bordered().title(title))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    let mut state = ListState::default().with_selected((!visible.is_empty()).then_some(app.selected));
+    frame.render_stateful_widget(list, list_area, &mut state);
+
+    let status = match app.mode {
+        Mode::Search => Line::from(format!("/{}", app.search)),
+        Mode::Tag => Line::from(format!("tags: {}", app.input)),
+        Mode::Filter => Line::from(format!("filter: {}", app.input)),
+        Mode::Normal if !app.status.is_empty() => Line::from(app.status.as_str()).yellow(),
+        Mode::Normal => Line::from(HELP).dark_gray(),
+    };
+    frame.render_widget(Paragraph::new(status), status_area);
+}
@@ -0,0 +1,97 @@
+use crate::config::Config;
+use crate::services::{importers, metadata, migrate_data, models, postgres_database, sqlite_database};
+use std::fs;
+use std::io;
+
+const USAGE: &str = "usage: read_it_later [import-bookmarks <file> | clean-urls [--dry-run] | repair-snippet-tags [--dry-run] | fetch-metadata | migrate-data --from <database url> --to <database url>]";
+
+/// Connect to the database at `url`, a `sqlite://` or `postgres://` one, with its schema brought up to date
+async fn open(url: &str) -> io::Result<Box<dyn models::Database>> {
+    let database: Box<dyn models::Database> = if url.starts_with("sqlite:") {
+        Box::new(
+            sqlite_database::SqliteDatabase::new(url)
+                .await
+                .map_err(io::Error::other)?,
+        )
+    } else if url.starts_with("postgres://") || url.starts_with("postgresql://") {
+        Box::new(
+            postgres_database::PostgresDatabase::new(url)
+                .await
+                .map_err(io::Error::other)?,
+        )
+    } else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is neither a sqlite:// nor a postgres:// URL", url),
+        ));
+    };
+    database.initialize().await.map_err(io::Error::other)?;
+    Ok(database)
+}
+
+/// Run a one-off subcommand against the database instead of starting the server
+pub async fn run(database: &dyn models::Database, config: &Config, args: &[String]) -> io::Result<()> {
+    match args {
+        [command, path] if command == "import-bookmarks" => {
+            let json = fs::read(path)?;
+            let items =
+                importers::bookmarks::parse(&json).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+            let job = importers::import_items(database, "bookmarks", items)
+                .await
+                .map_err(io::Error::other)?;
+
+            println!("{}", job);
+            for error in &job.errors {
+                eprintln!("{}", error);
+            }
+            Ok(())
+        }
+        [command, options @ ..] if command == "clean-urls" && (options.is_empty() || options == ["--dry-run"]) => {
+            let dry_run = !options.is_empty();
+            let cleaned = database.clean_urls(dry_run).await.map_err(io::Error::other)?;
+
+            for url in &cleaned {
+                let merged = if url.merged { " (merged into existing)" } else { "" };
+                println!("{} -> {}{}", url.original, url.url, merged);
+            }
+            let merged = cleaned.iter().filter(|url| url.merged).count();
+            let verb = if dry_run { "would clean" } else { "cleaned" };
+            println!("{} {} URLs, {} of them duplicates", verb, cleaned.len(), merged);
+            Ok(())
+        }
+        [command, options @ ..]
+            if command == "repair-snippet-tags" && (options.is_empty() || options == ["--dry-run"]) =>
+        {
+            let dry_run = !options.is_empty();
+            let repaired = database.repair_snippet_tags(dry_run).await.map_err(io::Error::other)?;
+
+            for snippet_id in &repaired {
+                println!("snippet {}", snippet_id);
+            }
+            let verb = if dry_run { "would repair" } else { "repaired" };
+            println!("{} the tags of {} snippets", verb, repaired.len());
+            Ok(())
+        }
+        [command] if command == "fetch-metadata" => {
+            let (enriched, failed) = metadata::backfill(database, &config.media)
+                .await
+                .map_err(io::Error::other)?;
+            println!("found metadata for {} URLs, {} failed", enriched, failed);
+            Ok(())
+        }
+        [command, from_flag, from, to_flag, to]
+            if command == "migrate-data" && from_flag == "--from" && to_flag == "--to" =>
+        {
+            let source = open(from).await?;
+            let target = open(to).await?;
+            migrate_data::copy(source.as_ref(), target.as_ref(), |table, count| {
+                println!("{}: {} rows", table, count)
+            })
+            .await
+            .map_err(io::Error::other)?;
+            println!("copied every table");
+            Ok(())
+        }
+        _ => Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE)),
+    }
+}
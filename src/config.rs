@@ -0,0 +1,605 @@
+use crate::services::proxy::TrustedProxies;
+use crate::services::tracking::TrackingParams;
+use crate::services::{fetcher, oembed, snapshots};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Runtime configuration read from environment variables (and `*_FILE` secrets)
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub web_port: String,
+    pub database_type: String,
+    pub database_url: String,
+    /// Postgres replica that listings and searches are read from
+    pub database_read_url: Option<String>,
+    pub snippet_encryption_key: Option<String>,
+    /// How long the tag overview is answered from memory; `None` turns the cache off
+    pub query_cache_ttl: Option<Duration>,
+    /// Prefix every route is served under, e.g. `/readlater`; empty when hosted at the root
+    pub base_path: String,
+    /// Proxies allowed to set `X-Forwarded-*` headers; empty means the peer address is always used
+    pub trusted_proxies: TrustedProxies,
+    /// Query parameters stripped from URLs on save; `TRACKING_PARAMS` replaces the built-in list
+    pub tracking_params: TrackingParams,
+    /// Rewrite AMP, mobile and Google cache links to the desktop URL on save
+    pub unwrap_amp_urls: bool,
+    /// Refuse a snippet that names a tag twice rather than keeping it once
+    pub reject_duplicate_tags: bool,
+    /// Tags snippet text keeps when it is sanitized on save; `None` keeps ammonia's defaults and a few more
+    pub snippet_html_tags: Option<Vec<String>>,
+    pub media: MediaLookups,
+    pub previews: Previews,
+    pub security_headers: SecurityHeaders,
+    /// Largest accepted JSON request body, in bytes
+    pub json_body_limit: usize,
+    pub github: GithubSync,
+    pub snapshots: Snapshots,
+    pub retention: Retention,
+    pub briefing: Briefing,
+    pub peering: Peering,
+    pub fetching: Fetching,
+    pub access: Access,
+    pub display_urls: DisplayUrls,
+}
+
+/// How listings show each URL as `display_url`
+#[derive(Clone, Debug, PartialEq)]
+pub struct DisplayUrls {
+    /// Drop the query string
+    pub strip_query: bool,
+    /// Drop `https://` or `http://`
+    pub strip_scheme: bool,
+    /// Longer URLs are cut to this many characters, ending in an ellipsis
+    pub max_length: Option<usize>,
+}
+
+impl Default for DisplayUrls {
+    fn default() -> Self {
+        Self {
+            strip_query: true,
+            strip_scheme: false,
+            max_length: None,
+        }
+    }
+}
+
+/// Who can change the library
+#[derive(Clone, Debug)]
+pub struct Access {
+    /// Let anyone browse, keeping changes to whoever holds `token`
+    pub public_read_only: bool,
+    /// Secret the owner logs in with at `/login` or sends as a bearer token
+    pub token: Option<String>,
+    /// Address the library is reached at, including any base path, that the sitemap links to
+    pub public_url: Option<String>,
+}
+
+/// How background lookups fetch from other sites
+#[derive(Clone, Debug)]
+pub struct Fetching {
+    /// Sent with every request; robots.txt groups are matched against it up to the first `/` or space
+    pub user_agent: String,
+    /// Requests running at once across every site
+    pub concurrency: usize,
+    /// Requests running at once to any one host
+    pub host_concurrency: usize,
+    /// Skip pages a site's robots.txt disallows
+    pub robots_txt: bool,
+    /// How long a host is left alone after a failure, doubling with each failure in a row
+    pub backoff: Duration,
+    /// The longest a failing host is left alone
+    pub max_backoff: Duration,
+    /// Headers and cookies for particular sites, from `FETCH_PROFILES` (usually `FETCH_PROFILES_FILE`)
+    pub profiles: fetcher::Profiles,
+}
+
+impl Default for Fetching {
+    fn default() -> Self {
+        Self {
+            user_agent: concat!("read_it_later/", env!("CARGO_PKG_VERSION")).to_string(),
+            concurrency: 8,
+            host_concurrency: 2,
+            robots_txt: true,
+            backoff: Duration::from_secs(30),
+            max_backoff: Duration::from_secs(6 * 60 * 60),
+            profiles: fetcher::Profiles::default(),
+        }
+    }
+}
+
+/// Other instances this one stays in sync with by exchanging change feeds
+#[derive(Clone, Debug)]
+pub struct Peering {
+    /// Base URLs of the peers, including any base path they are served under
+    pub peers: Vec<String>,
+    /// Shared secret peers present to read each other's changes; `/peer/changes` is off without one
+    pub token: Option<String>,
+    /// How often each peer is asked for its changes
+    pub interval: Duration,
+}
+
+/// Importing starred repositories from GitHub
+#[derive(Clone, Debug)]
+pub struct GithubSync {
+    pub token: Option<String>,
+    /// How often to re-import stars in the background; `None` means only on request
+    pub interval: Option<Duration>,
+}
+
+/// Keeping copies of saved pages to see how they change
+#[derive(Clone, Debug)]
+pub struct Snapshots {
+    /// Copies kept per URL; older ones are dropped as new ones are taken
+    pub limit: i64,
+    pub page: PageLimits,
+    /// How often to re-snapshot URLs that already have a snapshot; `None` means only on request
+    pub interval: Option<Duration>,
+    /// Store a URL under the canonical URL its page declares when a snapshot is taken
+    pub canonical_urls: bool,
+    pub cache: SnapshotCache,
+}
+
+/// Snapshot bodies kept on disk once read, so opening the same copy again skips the database
+#[derive(Clone, Debug, Default)]
+pub struct SnapshotCache {
+    /// Where cached bodies are written; `None` turns the cache off
+    pub dir: Option<PathBuf>,
+    /// Bytes the cache may take up; the earliest cached bodies are removed past it
+    pub max_size: usize,
+}
+
+/// How much of a page is fetched to be stored, and which pages are
+#[derive(Clone, Debug)]
+pub struct PageLimits {
+    /// Bytes kept of a page; a longer one is cut off there and marked truncated
+    pub max_size: usize,
+    /// Give up on a page that takes longer than this to download
+    pub timeout: Duration,
+    /// Content types fetched, such as `text/html`, with a trailing `*` matching any suffix; empty allows any
+    pub content_types: Vec<String>,
+}
+
+/// What is looked up about a URL in the background when it is saved
+#[derive(Clone, Debug)]
+pub struct MediaLookups {
+    /// Duration, channel and thumbnail of video links, over oEmbed
+    pub video: bool,
+    /// The audio file a link is, or a podcast episode page offers; fetches the page of every saved URL
+    pub audio: bool,
+    /// Whether a link is an article, video, code and so on; fetches the start of the page when the URL doesn't say
+    pub classify: bool,
+}
+
+/// Tweets, videos and gists embedded on the reader page
+#[derive(Clone, Debug)]
+pub struct Previews {
+    /// Providers allowed to be embedded; empty turns previews off
+    pub providers: Vec<oembed::Provider>,
+    /// How long a preview is reused before asking the provider again
+    pub cache_ttl: Duration,
+}
+
+/// Rules for clearing out old items; each rule is off unless its variable is set
+#[derive(Clone, Debug, Default)]
+pub struct Retention {
+    /// Archive unread URLs, leaving them unread, once they were saved this long ago
+    pub archive_unread_after: Option<Duration>,
+    /// Archive URLs once they were read this long ago
+    pub archive_read_after: Option<Duration>,
+    /// Delete URLs once they were read this long ago
+    pub delete_read_after: Option<Duration>,
+    /// Total size snapshots may take up, in bytes, before the least recently used are dropped
+    pub snapshot_storage: Option<usize>,
+    /// How often the scheduler applies the rules; `None` means only on request
+    pub interval: Option<Duration>,
+    /// Have scheduled runs only report what they would change
+    pub dry_run: bool,
+}
+
+/// The daily briefing of what was saved over the last day
+#[derive(Clone, Debug)]
+pub struct Briefing {
+    /// How often the scheduler prepares the briefing, snapshotting new saves for their summaries and sending
+    /// it; `None` means only on request
+    pub interval: Option<Duration>,
+    /// Where the briefing is emailed after it is prepared; `None` leaves it on `/briefing`
+    pub email: Option<String>,
+    /// Program that sends mail, given the message on stdin with its recipients in the headers, like `sendmail -t`
+    pub sendmail: String,
+}
+
+/// Values for the security headers added to every response; an empty value omits that header
+#[derive(Clone, Debug)]
+pub struct SecurityHeaders {
+    pub content_security_policy: String,
+    pub frame_ancestors: String,
+    pub referrer_policy: String,
+}
+
+const DEFAULT_CONTENT_SECURITY_POLICY: &str =
+    "default-src 'self'; script-src 'self' 'unsafe-inline' https://cdn.jsdelivr.net/npm/htmx.org@2.0.4/; \
+    style-src 'self' 'unsafe-inline' https://cdn.jsdelivr.net; img-src 'self' data: https:; media-src 'self' https:; \
+    frame-src https://www.youtube.com https://www.youtube-nocookie.com https://player.vimeo.com; \
+    connect-src 'self' https://cdn.jsdelivr.net; object-src 'none'; base-uri 'self'; form-action 'self'";
+
+impl Config {
+    pub fn from_env() -> Result<Self, String> {
+        let web_port = env_or_file("WEB_PORT")?.unwrap_or_else(|| "8080".to_string());
+        let database_type = env_or_file("DATABASE_TYPE")?.unwrap_or_else(|| "sqlite".to_string());
+        let database_url = match database_type.as_str() {
+            "sqlite" => env_or_file("SQLITE_URL")?.ok_or("SQLITE_URL must be set for SQLite")?,
+            _ => env_or_file("POSTGRES_URL")?.ok_or("POSTGRES_URL must be set for PostgreSQL")?,
+        };
+        let database_read_url = env_or_file("POSTGRES_READ_URL")?.filter(|url| !url.is_empty());
+        if database_read_url.is_some() && database_type == "sqlite" {
+            return Err("POSTGRES_READ_URL only applies to PostgreSQL".to_string());
+        }
+
+        Ok(Self {
+            web_port,
+            database_type,
+            database_url,
+            database_read_url,
+            snippet_encryption_key: env_or_file("SNIPPET_ENCRYPTION_KEY")?,
+            query_cache_ttl: parse_flag("QUERY_CACHE", true)?
+                .then(|| parse_interval("QUERY_CACHE_TTL"))
+                .transpose()?
+                .map(|ttl| ttl.unwrap_or(Duration::from_secs(5 * 60))),
+            base_path: normalize_base_path(&env_or_file("BASE_PATH")?.unwrap_or_default()),
+            trusted_proxies: TrustedProxies::parse(&env_or_file("TRUSTED_PROXIES")?.unwrap_or_default())?,
+            tracking_params: env_or_file("TRACKING_PARAMS")?
+                .map(|list| TrackingParams::parse(&list))
+                .unwrap_or_default(),
+            unwrap_amp_urls: parse_flag("UNWRAP_AMP_URLS", true)?,
+            reject_duplicate_tags: parse_flag("REJECT_DUPLICATE_TAGS", false)?,
+            snippet_html_tags: match env_or_file("SNIPPET_HTML_TAGS")? {
+                Some(_) => Some(parse_list("SNIPPET_HTML_TAGS", &[])?),
+                None => None,
+            },
+            media: MediaLookups {
+                video: parse_flag("VIDEO_METADATA", true)?,
+                audio: parse_flag("AUDIO_METADATA", true)?,
+                classify: parse_flag("CLASSIFY_URLS", true)?,
+            },
+            previews: Previews {
+                providers: parse_providers("OEMBED_PROVIDERS")?,
+                cache_ttl: parse_interval("OEMBED_CACHE_TTL")?.unwrap_or(Duration::from_secs(24 * 60 * 60)),
+            },
+            json_body_limit: parse_size("JSON_BODY_LIMIT", 64 * 1024)?,
+            security_headers: SecurityHeaders {
+                content_security_policy: env_or_file("CONTENT_SECURITY_POLICY")?
+                    .unwrap_or_else(|| DEFAULT_CONTENT_SECURITY_POLICY.to_string()),
+                frame_ancestors: env_or_file("FRAME_ANCESTORS")?.unwrap_or_else(|| "'none'".to_string()),
+                referrer_policy: env_or_file("REFERRER_POLICY")?.unwrap_or_else(|| "no-referrer".to_string()),
+            },
+            github: GithubSync {
+                token: env_or_file("GITHUB_TOKEN")?,
+                interval: parse_interval("GITHUB_SYNC_INTERVAL")?,
+            },
+            snapshots: Snapshots {
+                limit: parse_count("SNAPSHOT_LIMIT", 10)?,
+                page: PageLimits {
+                    max_size: parse_size("SNAPSHOT_MAX_SIZE", 5 * 1024 * 1024)?,
+                    timeout: parse_interval("SNAPSHOT_TIMEOUT")?.unwrap_or(Duration::from_secs(30)),
+                    content_types: parse_list("SNAPSHOT_CONTENT_TYPES", &snapshots::DEFAULT_CONTENT_TYPES)?,
+                },
+                interval: parse_interval("SNAPSHOT_INTERVAL")?,
+                canonical_urls: parse_flag("CANONICAL_URLS", true)?,
+                cache: SnapshotCache {
+                    dir: env_or_file("SNAPSHOT_CACHE_DIR")?
+                        .filter(|dir| !dir.is_empty())
+                        .map(PathBuf::from),
+                    max_size: parse_size("SNAPSHOT_CACHE_SIZE", 256 * 1024 * 1024)?,
+                },
+            },
+            retention: Retention {
+                archive_unread_after: parse_interval("RETENTION_ARCHIVE_UNREAD_AFTER")?,
+                archive_read_after: parse_interval("RETENTION_ARCHIVE_READ_AFTER")?,
+                delete_read_after: parse_interval("RETENTION_DELETE_READ_AFTER")?,
+                snapshot_storage: parse_optional_size("RETENTION_SNAPSHOT_STORAGE")?,
+                interval: parse_interval("RETENTION_INTERVAL")?,
+                dry_run: parse_flag("RETENTION_DRY_RUN", false)?,
+            },
+            briefing: Briefing {
+                interval: parse_interval("BRIEFING_INTERVAL")?,
+                email: env_or_file("BRIEFING_EMAIL")?.filter(|email| !email.trim().is_empty()),
+                sendmail: env_or_file("BRIEFING_SENDMAIL")?.unwrap_or_else(|| "/usr/sbin/sendmail".to_string()),
+            },
+            peering: parse_peering()?,
+            fetching: parse_fetching()?,
+            access: parse_access()?,
+            display_urls: DisplayUrls {
+                strip_query: parse_flag("DISPLAY_URL_STRIP_QUERY", true)?,
+                strip_scheme: parse_flag("DISPLAY_URL_STRIP_SCHEME", false)?,
+                max_length: match env_or_file("DISPLAY_URL_MAX_LENGTH")? {
+                    Some(_) => Some(parse_count("DISPLAY_URL_MAX_LENGTH", 1)? as usize),
+                    None => None,
+                },
+            },
+        })
+    }
+}
+
+/// Read a positive whole number, falling back to `default` when unset
+fn parse_count(name: &str, default: i64) -> Result<i64, String> {
+    match env_or_file(name)? {
+        Some(value) => value
+            .trim()
+            .parse::<i64>()
+            .ok()
+            .filter(|count| *count > 0)
+            .ok_or_else(|| format!("{} must be a positive number: {}", name, value)),
+        None => Ok(default),
+    }
+}
+
+/// Read a byte count such as `65536`, `64k` or `50M`, falling back to `default` when unset
+fn parse_size(name: &str, default: usize) -> Result<usize, String> {
+    match env_or_file(name)? {
+        Some(value) => parse_byte_size(&value).ok_or_else(|| format!("{} is not a valid size: {}", name, value)),
+        None => Ok(default),
+    }
+}
+
+/// Read an optional byte count, `None` when unset
+fn parse_optional_size(name: &str) -> Result<Option<usize>, String> {
+    match env_or_file(name)? {
+        Some(value) => parse_byte_size(&value)
+            .map(Some)
+            .ok_or_else(|| format!("{} is not a valid size: {}", name, value)),
+        None => Ok(None),
+    }
+}
+
+/// Read `PEERS`, a comma-separated list of base URLs, and the `PEER_TOKEN` they share
+fn parse_peering() -> Result<Peering, String> {
+    let peers: Vec<String> = env_or_file("PEERS")?
+        .unwrap_or_default()
+        .split(',')
+        .map(|peer| peer.trim().trim_end_matches('/'))
+        .filter(|peer| !peer.is_empty())
+        .map(|peer| match reqwest::Url::parse(peer) {
+            Ok(url) if matches!(url.scheme(), "http" | "https") => Ok(peer.to_string()),
+            _ => Err(format!("PEERS has an invalid URL: {}", peer)),
+        })
+        .collect::<Result<_, _>>()?;
+    let token = env_or_file("PEER_TOKEN")?.filter(|token| !token.is_empty());
+    if !peers.is_empty() && token.is_none() {
+        return Err("PEER_TOKEN must be set to sync with PEERS".to_string());
+    }
+
+    Ok(Peering {
+        peers,
+        token,
+        interval: parse_interval("PEER_SYNC_INTERVAL")?.unwrap_or(Duration::from_secs(5 * 60)),
+    })
+}
+
+/// Read `PUBLIC_READ_ONLY`, the `ADMIN_TOKEN` it needs and the `PUBLIC_URL` its sitemap links to
+fn parse_access() -> Result<Access, String> {
+    let public_read_only = parse_flag("PUBLIC_READ_ONLY", false)?;
+    let token = env_or_file("ADMIN_TOKEN")?.filter(|token| !token.trim().is_empty());
+    if public_read_only && token.is_none() {
+        return Err("ADMIN_TOKEN must be set for PUBLIC_READ_ONLY".to_string());
+    }
+    let public_url = match env_or_file("PUBLIC_URL")? {
+        Some(url) if !url.trim().is_empty() => {
+            let url = url.trim().trim_end_matches('/');
+            match reqwest::Url::parse(url) {
+                Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => Some(url.to_string()),
+                _ => return Err(format!("PUBLIC_URL is not a valid URL: {}", url)),
+            }
+        }
+        _ => None,
+    };
+    Ok(Access {
+        public_read_only,
+        token,
+        public_url,
+    })
+}
+
+/// Read the `FETCH_*` settings, each falling back to its default when unset
+fn parse_fetching() -> Result<Fetching, String> {
+    let defaults = Fetching::default();
+    let user_agent = match env_or_file("FETCH_USER_AGENT")?.filter(|agent| !agent.trim().is_empty()) {
+        Some(agent) if reqwest::header::HeaderValue::from_str(&agent).is_err() => {
+            return Err(format!("FETCH_USER_AGENT is not a valid header value: {}", agent))
+        }
+        Some(agent) => agent,
+        None => defaults.user_agent,
+    };
+
+    Ok(Fetching {
+        user_agent,
+        concurrency: parse_count("FETCH_CONCURRENCY", defaults.concurrency as i64)? as usize,
+        host_concurrency: parse_count("FETCH_HOST_CONCURRENCY", defaults.host_concurrency as i64)? as usize,
+        robots_txt: parse_flag("FETCH_ROBOTS_TXT", defaults.robots_txt)?,
+        backoff: parse_interval("FETCH_BACKOFF")?.unwrap_or(defaults.backoff),
+        max_backoff: parse_interval("FETCH_MAX_BACKOFF")?.unwrap_or(defaults.max_backoff),
+        profiles: match env_or_file("FETCH_PROFILES")? {
+            Some(json) => {
+                fetcher::Profiles::parse(&json).map_err(|err| format!("FETCH_PROFILES is invalid: {}", err))?
+            }
+            None => defaults.profiles,
+        },
+    })
+}
+
+/// Read a comma-separated list, lowercased, falling back to `default` when unset; set but empty means none
+fn parse_list(name: &str, default: &[&str]) -> Result<Vec<String>, String> {
+    Ok(match env_or_file(name)? {
+        Some(list) => list
+            .split(',')
+            .map(|entry| entry.trim().to_ascii_lowercase())
+            .filter(|entry| !entry.is_empty())
+            .collect(),
+        None => default.iter().map(|entry| entry.to_string()).collect(),
+    })
+}
+
+/// Read a comma-separated list of oEmbed provider names, defaulting to all of them
+fn parse_providers(name: &str) -> Result<Vec<oembed::Provider>, String> {
+    let Some(list) = env_or_file(name)? else {
+        return Ok(oembed::Provider::ALL.to_vec());
+    };
+    list.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            oembed::Provider::parse(entry).ok_or_else(|| format!("{} has an unknown provider: {}", name, entry))
+        })
+        .collect()
+}
+
+/// Read an on/off switch such as `true` or `0`, falling back to `default` when unset
+fn parse_flag(name: &str, default: bool) -> Result<bool, String> {
+    match env_or_file(name)?.map(|value| value.trim().to_ascii_lowercase()) {
+        None => Ok(default),
+        Some(value) => match value.as_str() {
+            "1" | "true" | "yes" | "on" => Ok(true),
+            "0" | "false" | "no" | "off" | "" => Ok(false),
+            _ => Err(format!("{} must be true or false: {}", name, value)),
+        },
+    }
+}
+
+/// Parse a byte count with an optional `k`, `m` or `g` (1024-based) suffix
+pub fn parse_byte_size(value: &str) -> Option<usize> {
+    let value = value.trim().to_ascii_lowercase();
+    let value = value.strip_suffix('b').unwrap_or(&value);
+    let (digits, multiplier) = match value.chars().last()? {
+        'k' => (&value[..value.len() - 1], 1024),
+        'm' => (&value[..value.len() - 1], 1024 * 1024),
+        'g' => (&value[..value.len() - 1], 1024 * 1024 * 1024),
+        _ => (value, 1),
+    };
+    digits.trim().parse::<usize>().ok()?.checked_mul(multiplier)
+}
+
+/// Read an optional interval such as `30m` or `6h`
+fn parse_interval(name: &str) -> Result<Option<Duration>, String> {
+    match env_or_file(name)? {
+        Some(value) => parse_duration(&value)
+            .map(Some)
+            .ok_or_else(|| format!("{} is not a valid interval: {}", name, value)),
+        None => Ok(None),
+    }
+}
+
+/// Parse a positive duration with an `s`, `m`, `h` or `d` suffix; bare numbers are seconds
+pub fn parse_duration(value: &str) -> Option<Duration> {
+    let value = value.trim().to_ascii_lowercase();
+    let (digits, seconds) = match value.chars().last()? {
+        's' => (&value[..value.len() - 1], 1),
+        'm' => (&value[..value.len() - 1], 60),
+        'h' => (&value[..value.len() - 1], 60 * 60),
+        'd' => (&value[..value.len() - 1], 24 * 60 * 60),
+        _ => (value.as_str(), 1),
+    };
+    let count = digits.trim().parse::<u64>().ok().filter(|count| *count > 0)?;
+    Some(Duration::from_secs(count.checked_mul(seconds)?))
+}
+
+/// Normalize a base path to either `""` or `/segment[/segment...]` without a trailing slash
+pub fn normalize_base_path(raw: &str) -> String {
+    let trimmed = raw.trim().trim_matches('/');
+    if trimmed.is_empty() {
+        String::new()
+    } else {
+        format!("/{}", trimmed)
+    }
+}
+
+/// Read `name` from the environment, or from the file named by `{name}_FILE`.
+///
+/// The file form lets Docker/Kubernetes secrets be mounted instead of putting credentials in the
+/// environment. Trailing newlines are trimmed, and setting both forms is an error.
+pub fn env_or_file(name: &str) -> Result<Option<String>, String> {
+    let file_var = format!("{}_FILE", name);
+
+    match (env::var(name).ok(), env::var(&file_var).ok()) {
+        (Some(_), Some(_)) => Err(format!("only one of {} and {} may be set", name, file_var)),
+        (Some(value), None) => Ok(Some(value)),
+        (None, Some(path)) => fs::read_to_string(&path)
+            .map(|contents| Some(contents.trim_end_matches(['\r', '\n']).to_string()))
+            .map_err(|err| format!("failed to read {} from {}: {}", file_var, path, err)),
+        (None, None) => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_env_or_file_reads_plain_variable() {
+        env::set_var("RIL_TEST_PLAIN", "value");
+        assert_eq!(env_or_file("RIL_TEST_PLAIN").unwrap(), Some("value".to_string()));
+    }
+
+    #[test]
+    fn test_env_or_file_reads_secret_file() {
+        let path = env::temp_dir().join("ril_test_secret");
+        fs::write(&path, "s3cret\n").unwrap();
+        env::set_var("RIL_TEST_SECRET_FILE", &path);
+
+        assert_eq!(env_or_file("RIL_TEST_SECRET").unwrap(), Some("s3cret".to_string()));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_env_or_file_rejects_both_forms() {
+        env::set_var("RIL_TEST_BOTH", "value");
+        env::set_var("RIL_TEST_BOTH_FILE", "/nonexistent");
+        assert!(env_or_file("RIL_TEST_BOTH").is_err());
+    }
+
+    #[test]
+    fn test_normalize_base_path() {
+        assert_eq!(normalize_base_path(""), "");
+        assert_eq!(normalize_base_path("/"), "");
+        assert_eq!(normalize_base_path("readlater"), "/readlater");
+        assert_eq!(normalize_base_path("/readlater/"), "/readlater");
+        assert_eq!(normalize_base_path(" /apps/readlater "), "/apps/readlater");
+    }
+
+    #[test]
+    fn test_parse_byte_size() {
+        assert_eq!(parse_byte_size("65536"), Some(65536));
+        assert_eq!(parse_byte_size("64k"), Some(64 * 1024));
+        assert_eq!(parse_byte_size("50MB"), Some(50 * 1024 * 1024));
+        assert_eq!(parse_byte_size("1G"), Some(1024 * 1024 * 1024));
+        assert_eq!(parse_byte_size("lots"), None);
+        assert_eq!(parse_byte_size(""), None);
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90"), Some(Duration::from_secs(90)));
+        assert_eq!(parse_duration("30m"), Some(Duration::from_secs(30 * 60)));
+        assert_eq!(parse_duration("6H"), Some(Duration::from_secs(6 * 60 * 60)));
+        assert_eq!(parse_duration("1d"), Some(Duration::from_secs(24 * 60 * 60)));
+        assert_eq!(parse_duration("0h"), None);
+        assert_eq!(parse_duration("soon"), None);
+    }
+
+    #[test]
+    fn test_parse_flag() {
+        env::set_var("RIL_TEST_FLAG_ON", "Yes");
+        env::set_var("RIL_TEST_FLAG_OFF", "0");
+        env::set_var("RIL_TEST_FLAG_BAD", "maybe");
+        assert_eq!(parse_flag("RIL_TEST_FLAG_ON", false), Ok(true));
+        assert_eq!(parse_flag("RIL_TEST_FLAG_OFF", true), Ok(false));
+        assert_eq!(parse_flag("RIL_TEST_FLAG_UNSET", false), Ok(false));
+        assert_eq!(parse_flag("RIL_TEST_FLAG_UNSET", true), Ok(true));
+        assert!(parse_flag("RIL_TEST_FLAG_BAD", false).is_err());
+    }
+
+    #[test]
+    fn test_env_or_file_missing() {
+        assert_eq!(env_or_file("RIL_TEST_MISSING").unwrap(), None);
+    }
+}
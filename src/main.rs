@@ -1,15 +1,24 @@
 use actix_cors::Cors;
-use actix_web::{middleware::Logger, App, HttpServer};
+use actix_web::{
+    middleware::{from_fn, Logger},
+    web, App, HttpServer,
+};
 use dotenv::dotenv;
-use std::env;
 use tera::Tera;
+mod cli;
+mod config;
 mod services;
-use services::{api, models, postgres_database, sqlite_database};
+use services::{
+    access, api, briefing, cached_database, crypto, display, errors, exporters, fetcher, headers, importers, limits,
+    listen, metadata, models, partials, peering, postgres_database, proxy, pwa, retention, review, scheduler,
+    self_check, sitemap, snapshot_cache, snapshots, snippet_images, sqlite_database, stats, websocket,
+};
 use std::sync::Arc;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     println!("read_it_later has started");
+    std::sync::LazyLock::force(&api::STARTED);
 
     // Load the .env file
     dotenv().ok();
@@ -19,39 +28,210 @@ async fn main() -> std::io::Result<()> {
     // env_logger::init_from_env(Env::default().default_filter_or("info"));
 
     // Read configuration from environment variables
-    let port = env::var("WEB_PORT").unwrap_or_else(|_| "8080".to_string());
+    let config = config::Config::from_env().expect("Invalid configuration");
+    fetcher::configure(&config.fetching);
+    display::configure(&config.display_urls);
+    snapshot_cache::configure(&config.snapshots.cache);
+    if !config.fetching.profiles.is_empty() {
+        println!("Fetch profiles for {} site(s)", config.fetching.profiles.len());
+    }
+    let port = config.web_port.clone();
     let bind_address = format!("0.0.0.0:{}", port);
+    let database_type = config.database_type.clone();
+    let database_url = config.database_url.clone();
 
-    let database_type = env::var("DATABASE_TYPE").unwrap_or_else(|_| "sqlite".to_string());
-    let database_url = match database_type.as_str() {
-        "sqlite" => env::var("SQLITE_URL").expect("SQLITE_URL must be set for SQLite"),
-        _ => env::var("POSTGRES_URL").expect("POSTGRES_URL must be set for PostgreSQL"),
-    };
+    // Optional at-rest encryption of snippet text and URL notes
+    let snippet_cipher = config
+        .snippet_encryption_key
+        .as_deref()
+        .map(|key| crypto::SnippetCipher::from_base64_key(key).expect("SNIPPET_ENCRYPTION_KEY is invalid"));
+    if snippet_cipher.is_some() {
+        println!("Snippet encryption enabled");
+    }
 
     let database: Arc<dyn models::Database> = match database_type.as_str() {
-        "sqlite" => Arc::new(sqlite_database::SqliteDatabase::new(&database_url).await.unwrap()),
-        _ => Arc::new(postgres_database::PostgresDatabase::new(&database_url).await.unwrap()),
+        "sqlite" => Arc::new(
+            sqlite_database::SqliteDatabase::new(&database_url)
+                .await
+                .unwrap()
+                .with_snippet_cipher(snippet_cipher)
+                .with_tracking_params(config.tracking_params.clone())
+                .with_amp_unwrapping(config.unwrap_amp_urls)
+                .with_snippet_html_tags(config.snippet_html_tags.clone()),
+        ),
+        _ => {
+            let mut database = postgres_database::PostgresDatabase::new(&database_url)
+                .await
+                .unwrap()
+                .with_snippet_cipher(snippet_cipher)
+                .with_tracking_params(config.tracking_params.clone())
+                .with_amp_unwrapping(config.unwrap_amp_urls)
+                .with_snippet_html_tags(config.snippet_html_tags.clone());
+            if let Some(read_url) = &config.database_read_url {
+                database = database
+                    .with_read_replica(read_url)
+                    .await
+                    .expect("Failed to connect to the read replica");
+                println!("Reading listings from a replica");
+            }
+            Arc::new(database)
+        }
+    };
+
+    // Keep the expensive tag overview in memory between writes
+    let database: Arc<dyn models::Database> = match config.query_cache_ttl {
+        Some(ttl) => Arc::new(cached_database::CachedDatabase::new(database, ttl)),
+        None => database,
     };
 
     println!("Database: {}, {}", database_type, database_url);
-    println!("Listening on: http://localhost:{}", port);
+    if config.tracking_params.is_empty() {
+        println!("Tracking parameter stripping disabled");
+    }
 
     // Initialize DB pool
     database.initialize().await.expect("Failed to initialize database");
 
+    // Subcommands such as `import-bookmarks <file>` run once and exit instead of serving
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        return cli::run(database.as_ref(), &config, &args).await;
+    }
+
+    println!("Listening on: http://localhost:{}", port);
+
     // Initialize Tera template engine
     let tera = Tera::new(concat!(env!("CARGO_MANIFEST_DIR"), "/templates/**/*")).expect("Failed to initialize Tera");
+    if let Err(problems) = self_check::templates(&tera) {
+        eprintln!("Templates failed the startup check:\n  {}", problems.join("\n  "));
+        std::process::exit(1);
+    }
+
+    if !config.trusted_proxies.is_empty() {
+        println!("Trusting forwarded headers from: {}", config.trusted_proxies);
+    }
+
+    // Background jobs
+    match importers::resume_jobs(database.clone()).await {
+        Ok(0) => {}
+        Ok(resumed) => println!("Resuming {} unfinished import(s)", resumed),
+        Err(err) => eprintln!("Failed to resume imports: {:?}", err),
+    }
+    if let (Some(token), Some(interval)) = (config.github.token.clone(), config.github.interval) {
+        let database = database.clone();
+        scheduler::every("GitHub stars sync", interval, move || {
+            let database = database.clone();
+            let token = token.clone();
+            async move {
+                importers::github::sync_stars(database.as_ref(), &token)
+                    .await
+                    .map(|job| job.to_string())
+            }
+        });
+    }
+
+    if let Some(interval) = config.snapshots.interval {
+        let database = database.clone();
+        let settings = config.snapshots.clone();
+        scheduler::every("Page snapshots", interval, move || {
+            let database = database.clone();
+            let settings = settings.clone();
+            async move { snapshots::refresh_all(database.as_ref(), &settings).await }
+        });
+    }
+
+    if let Some(interval) = config.retention.interval {
+        let database = database.clone();
+        let settings = config.retention.clone();
+        scheduler::every("Retention rules", interval, move || {
+            let database = database.clone();
+            let settings = settings.clone();
+            async move { retention::apply_scheduled(database.as_ref(), &settings).await }
+        });
+    }
+
+    if let Some(interval) = config.briefing.interval {
+        let database = database.clone();
+        let config = config.clone();
+        let tera = tera.clone();
+        scheduler::every("Daily briefing", interval, move || {
+            let database = database.clone();
+            let config = config.clone();
+            let tera = tera.clone();
+            async move { briefing::prepare(database.as_ref(), &config, &tera).await }
+        });
+    }
+
+    if !config.peering.peers.is_empty() {
+        let database = database.clone();
+        let settings = config.peering.clone();
+        scheduler::every("Peer sync", settings.interval, move || {
+            let database = database.clone();
+            let settings = settings.clone();
+            async move {
+                let mut outcomes = Vec::new();
+                for peer in &settings.peers {
+                    outcomes.push(peering::sync(database.as_ref(), &settings, peer).await?);
+                }
+                Ok(outcomes.join("; "))
+            }
+        });
+    }
+
+    if config.media.video || config.media.audio || config.media.classify {
+        metadata::watch_saves(database.clone(), config.media.clone());
+    }
+
+    if config.access.public_read_only {
+        println!("Public read-only mode: changes need ADMIN_TOKEN");
+    }
+
+    let base_path = config.base_path.clone();
+    if !base_path.is_empty() {
+        println!("Serving under base path: {}", base_path);
+    }
 
     // Start the Actix Web server
     HttpServer::new(move || {
-        let app = App::new()
-            .wrap(Logger::default())
+        let mut app = App::new()
+            // Readable pages or JSON in place of bare error bodies
+            .wrap(from_fn(errors::error_pages))
+            // Log the client address reported by a trusted proxy rather than the proxy itself
+            .wrap(Logger::new(r#"%{r}a "%r" %s %b "%{Referer}i" "%{User-Agent}i" %T"#))
+            .wrap(from_fn(access::require_login_for_changes))
+            .wrap(from_fn(proxy::strip_untrusted_forwarded_headers))
+            .wrap(headers::security_headers(&config.security_headers))
             .wrap(Cors::default().allow_any_origin().allow_any_method().allow_any_header())
             .app_data(actix_web::web::Data::new(database.clone()))
             .app_data(actix_web::web::Data::new(tera.clone()))
-            .app_data(actix_web::web::Data::new(database_type.clone()));
+            .app_data(actix_web::web::Data::new(database_type.clone()))
+            .app_data(actix_web::web::Data::new(config.clone()))
+            .app_data(limits::json_config(config.json_body_limit));
+
+        // Send the bare base path to the index page, e.g. /readlater -> /readlater/
+        if !base_path.is_empty() {
+            app = app.service(web::redirect(base_path.clone(), format!("{}/", base_path)));
+        }
 
-        app.configure(api::configure_routes) // API routes
+        app.service(
+            web::scope(&base_path)
+                .configure(api::configure_routes) // API routes
+                .configure(pwa::configure_routes) // Manifest, service worker and icons
+                .configure(importers::configure_routes) // Imports from other services
+                .configure(exporters::configure_routes) // Exports to other formats
+                .configure(snapshots::configure_routes) // Copies of saved pages
+                .configure(snippet_images::configure_routes) // Pictures saved as snippets
+                .configure(retention::configure_routes) // Clearing out old items
+                .configure(listen::configure_routes) // Queued audio and its podcast feed
+                .configure(stats::configure_routes) // Reading streaks and weekly tallies
+                .configure(review::configure_routes) // Old snippets brought back each day
+                .configure(briefing::configure_routes) // The day's saves summarised on one page
+                .configure(partials::configure_routes) // Page fragments swapped in by HTMX
+                .configure(peering::configure_routes) // Change feed for other instances
+                .configure(websocket::configure_routes) // Live connection for interactive clients
+                .configure(access::configure_routes) // Logging in to a public instance
+                .configure(sitemap::configure_routes), // Index of a public library for search engines
+        )
     })
     .bind(&bind_address)?
     .run()
@@ -0,0 +1,352 @@
+//! Public read-only mode: anyone can browse the library, but only the owner, signed in with `ADMIN_TOKEN`, can
+//! change it
+
+use crate::config::{self, Config};
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::cookie::{time, Cookie, SameSite};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::{header, Method};
+use actix_web::middleware::Next;
+use actix_web::{get, post, web, HttpRequest, HttpResponse, Responder};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tera::{Context, Tera};
+
+/// Cookie the login page sets, holding a digest of the token rather than the token itself
+const SESSION_COOKIE: &str = "read_it_later_session";
+
+/// How long a browser stays signed in
+const SESSION_DAYS: i64 = 30;
+
+/// Reads that stay private in public mode: the websocket can change things, the exports hand over the whole
+/// library at once, the stats and briefing are built from the activity timeline and page copies, and the rest show
+/// the owner's activity, imports, retention rules and fetch history rather than the library itself
+const PRIVATE_READS: [&str; 9] = [
+    "/ws",
+    "/account/export",
+    "/export",
+    "/activity",
+    "/api/v1/activity",
+    "/stats",
+    "/api/v1/stats",
+    "/briefing",
+    "/retention",
+];
+
+/// Prefixes of further private reads: every export format and every import job
+const PRIVATE_PREFIXES: [&str; 2] = ["/export/", "/imports/"];
+
+fn is_private_read(path: &str) -> bool {
+    if PRIVATE_READS.contains(&path) || PRIVATE_PREFIXES.iter().any(|prefix| path.starts_with(prefix)) {
+        return true;
+    }
+    // Snapshots are whole copies of pages, which may have been fetched with the owner's cookies
+    let snapshots = path
+        .strip_prefix("/urls/")
+        .and_then(|rest| rest.split_once('/'))
+        .is_some_and(|(_, rest)| rest == "snapshots" || rest.starts_with("snapshots/"));
+    snapshots
+        || path
+            .strip_prefix("/api/v1/urls/")
+            .is_some_and(|rest| rest.ends_with("/metadata"))
+}
+
+fn is_read(method: &Method) -> bool {
+    [Method::GET, Method::HEAD, Method::OPTIONS].contains(method)
+}
+
+/// Whether a request, by its path below the base path, needs the owner to be signed in
+fn needs_login(method: &Method, path: &str) -> bool {
+    if path == "/login" {
+        return false;
+    }
+    !is_read(method) || is_private_read(path)
+}
+
+/// Compare secrets without the time taken giving away how much of them matched
+pub fn constant_time_eq(presented: &str, expected: &str) -> bool {
+    presented.len() == expected.len()
+        && presented
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+fn session_value(token: &str) -> String {
+    format!("{:x}", Sha256::digest(format!("read_it_later session:{}", token)))
+}
+
+/// Whether the request carries the token as a bearer token or the session cookie set by logging in with it
+pub fn signed_in(req: &HttpRequest, token: &str) -> bool {
+    let bearer = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    if bearer.is_some_and(|bearer| constant_time_eq(bearer, token)) {
+        return true;
+    }
+    req.cookie(SESSION_COOKIE)
+        .is_some_and(|cookie| constant_time_eq(cookie.value(), &session_value(token)))
+}
+
+/// Whether the request comes from the library's owner: anyone, unless the instance is public
+pub fn is_owner(req: &HttpRequest, config: &Config) -> bool {
+    match (&config.access.public_read_only, &config.access.token) {
+        (true, Some(token)) => signed_in(req, token),
+        _ => true,
+    }
+}
+
+/// Turn away changes from anyone not signed in, when the instance is public
+pub async fn require_login_for_changes<B: MessageBody + 'static>(
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<EitherBody<B>>, actix_web::Error> {
+    let Some(config) = req.app_data::<web::Data<Config>>().cloned() else {
+        return next.call(req).await.map(ServiceResponse::map_into_left_body);
+    };
+    // Checked against the path as routed, percent-encoding undone, so `/w%73` is still `/ws`
+    let (
+        config::Access {
+            public_read_only: true,
+            token: Some(token),
+            ..
+        },
+        Some(path),
+    ) = (
+        &config.access,
+        req.match_info().as_str().strip_prefix(&config.base_path),
+    )
+    else {
+        return next.call(req).await.map(ServiceResponse::map_into_left_body);
+    };
+    if !needs_login(req.method(), path) || signed_in(req.request(), token) {
+        return next.call(req).await.map(ServiceResponse::map_into_left_body);
+    }
+
+    let mut response = HttpResponse::Unauthorized();
+    response.insert_header((header::WWW_AUTHENTICATE, "Bearer"));
+    // HTMX follows this, so pressing a button on a page takes the owner to the login page
+    if req.headers().contains_key("HX-Request") {
+        response.insert_header(("HX-Redirect", format!("{}/login", config.base_path)));
+    }
+    let message = if is_read(req.method()) {
+        "Log in to see this"
+    } else {
+        "Log in to make changes"
+    };
+    Ok(req.into_response(response.json(message)).map_into_right_body())
+}
+
+fn render_login(
+    tmpl: &Tera,
+    config: &Config,
+    signed_in: bool,
+    error: Option<&str>,
+    mut response: actix_web::HttpResponseBuilder,
+) -> HttpResponse {
+    let mut context = Context::new();
+    context.insert("title", "Log in");
+    context.insert("base_path", &config.base_path);
+    context.insert("signed_in", &signed_in);
+    context.insert("error", &error);
+
+    match tmpl.render("login.html", &context) {
+        Ok(rendered) => response.content_type("text/html").body(rendered),
+        Err(e) => {
+            eprintln!("Template error: {:?}", e);
+            HttpResponse::InternalServerError().body("Template error")
+        }
+    }
+}
+
+/// The cookie that keeps a browser signed in, or an expired one that signs it out
+fn session_cookie(req: &HttpRequest, config: &Config, value: String) -> Cookie<'static> {
+    let path = if config.base_path.is_empty() {
+        "/".to_string()
+    } else {
+        config.base_path.clone()
+    };
+    let max_age = if value.is_empty() {
+        time::Duration::ZERO
+    } else {
+        time::Duration::days(SESSION_DAYS)
+    };
+    Cookie::build(SESSION_COOKIE, value)
+        .path(path)
+        .http_only(true)
+        .same_site(SameSite::Strict)
+        .secure(req.connection_info().scheme() == "https")
+        .max_age(max_age)
+        .finish()
+}
+
+#[get("/login")]
+async fn login_page(req: HttpRequest, tmpl: web::Data<Tera>, config: web::Data<Config>) -> impl Responder {
+    let Some(token) = config
+        .access
+        .token
+        .as_deref()
+        .filter(|_| config.access.public_read_only)
+    else {
+        return HttpResponse::NotFound().body("Logging in is only needed with PUBLIC_READ_ONLY");
+    };
+    render_login(&tmpl, &config, signed_in(&req, token), None, HttpResponse::Ok())
+}
+
+#[derive(Deserialize)]
+struct LoginForm {
+    token: String,
+}
+
+#[post("/login")]
+async fn log_in(
+    req: HttpRequest,
+    tmpl: web::Data<Tera>,
+    config: web::Data<Config>,
+    form: web::Form<LoginForm>,
+) -> impl Responder {
+    let Some(token) = config
+        .access
+        .token
+        .as_deref()
+        .filter(|_| config.access.public_read_only)
+    else {
+        return HttpResponse::NotFound().body("Logging in is only needed with PUBLIC_READ_ONLY");
+    };
+    if !constant_time_eq(form.token.trim(), token) {
+        let error = Some("That token isn't right.");
+        return render_login(&tmpl, &config, false, error, HttpResponse::Unauthorized());
+    }
+
+    HttpResponse::SeeOther()
+        .cookie(session_cookie(&req, &config, session_value(token)))
+        .insert_header((header::LOCATION, format!("{}/", config.base_path)))
+        .finish()
+}
+
+#[post("/logout")]
+async fn log_out(req: HttpRequest, config: web::Data<Config>) -> impl Responder {
+    HttpResponse::SeeOther()
+        .cookie(session_cookie(&req, &config, String::new()))
+        .insert_header((header::LOCATION, format!("{}/", config.base_path)))
+        .finish()
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(login_page).service(log_in).service(log_out);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    #[test]
+    fn only_changes_and_private_reads_need_login() {
+        assert!(!needs_login(&Method::GET, "/"));
+        assert!(!needs_login(&Method::GET, "/read/100680ad54"));
+        assert!(!needs_login(&Method::HEAD, "/feed.json"));
+        assert!(!needs_login(&Method::POST, "/login"));
+
+        assert!(needs_login(&Method::POST, "/urls/url"));
+        assert!(needs_login(&Method::PATCH, "/api/v1/urls/100680ad54"));
+        assert!(needs_login(&Method::DELETE, "/snippets/1"));
+        assert!(needs_login(&Method::POST, "/logout"));
+        assert!(needs_login(&Method::GET, "/ws"));
+        assert!(needs_login(&Method::GET, "/account/export"));
+    }
+
+    #[test]
+    fn exports_activity_stats_imports_snapshots_and_metadata_need_login() {
+        assert!(needs_login(&Method::GET, "/export"));
+        assert!(needs_login(&Method::GET, "/export/csv"));
+        assert!(needs_login(&Method::HEAD, "/export/anki"));
+
+        assert!(needs_login(&Method::GET, "/activity"));
+        assert!(needs_login(&Method::GET, "/api/v1/activity"));
+
+        assert!(needs_login(&Method::GET, "/imports/3"));
+        assert!(needs_login(&Method::GET, "/retention"));
+
+        assert!(needs_login(&Method::GET, "/api/v1/urls/100680ad54/metadata"));
+        assert!(needs_login(&Method::GET, "/urls/100680ad54/snapshots"));
+        assert!(needs_login(&Method::GET, "/urls/100680ad54/snapshots/3"));
+        assert!(needs_login(&Method::GET, "/urls/100680ad54/snapshots/diff"));
+
+        assert!(needs_login(&Method::GET, "/stats"));
+        assert!(needs_login(&Method::GET, "/api/v1/stats"));
+        assert!(needs_login(&Method::GET, "/briefing"));
+
+        assert!(!needs_login(&Method::GET, "/api/v1/urls/100680ad54"));
+        assert!(!needs_login(&Method::GET, "/urls/100680ad54"));
+    }
+
+    #[tokio::test]
+    async fn anonymous_private_reads_are_turned_away() {
+        use actix_web::middleware::from_fn;
+        use actix_web::{test, App};
+
+        std::env::set_var("SQLITE_URL", "sqlite::memory:");
+        let mut config = Config::from_env().unwrap();
+        config.access = config::Access {
+            public_read_only: true,
+            token: Some("owner-secret".to_string()),
+            public_url: None,
+        };
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(require_login_for_changes))
+                .app_data(web::Data::new(config))
+                .default_service(web::to(HttpResponse::Ok)),
+        )
+        .await;
+
+        let private = [
+            "/urls/100680ad54/snapshots",
+            "/urls/100680ad54/snapshots/3",
+            "/urls/100680ad54/snapshots/diff",
+            "/stats",
+            "/api/v1/stats",
+            "/briefing",
+        ];
+        for path in private {
+            let anonymous = test::call_service(&app, TestRequest::get().uri(path).to_request()).await;
+            assert_eq!(anonymous.status(), 401, "{}", path);
+            let owner = TestRequest::get()
+                .uri(path)
+                .insert_header((header::AUTHORIZATION, "Bearer owner-secret"))
+                .to_request();
+            assert_eq!(test::call_service(&app, owner).await.status(), 200, "{}", path);
+        }
+        let reader = test::call_service(&app, TestRequest::get().uri("/read/100680ad54").to_request()).await;
+        assert_eq!(reader.status(), 200);
+    }
+
+    #[test]
+    fn bearer_token_or_session_cookie_signs_in() {
+        let token = "owner-secret";
+        assert!(!signed_in(&TestRequest::default().to_http_request(), token));
+
+        let bearer = TestRequest::default()
+            .insert_header((header::AUTHORIZATION, "Bearer owner-secret"))
+            .to_http_request();
+        assert!(signed_in(&bearer, token));
+        let wrong = TestRequest::default()
+            .insert_header((header::AUTHORIZATION, "Bearer owner-secreT"))
+            .to_http_request();
+        assert!(!signed_in(&wrong, token));
+
+        let session = TestRequest::default()
+            .cookie(Cookie::new(SESSION_COOKIE, session_value(token)))
+            .to_http_request();
+        assert!(signed_in(&session, token));
+        // The cookie holds a digest, so the token itself doesn't work as one
+        let raw = TestRequest::default()
+            .cookie(Cookie::new(SESSION_COOKIE, token))
+            .to_http_request();
+        assert!(!signed_in(&raw, token));
+    }
+}
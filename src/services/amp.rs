@@ -0,0 +1,181 @@
+use reqwest::Url;
+use std::borrow::Cow;
+
+/// Host labels that only mark a mobile or AMP edition, as in `m.example.com` or `en.m.wikipedia.org`
+const MOBILE_LABELS: [&str; 3] = ["m", "mobile", "amp"];
+
+/// Paths on an AMP cache host that wrap another URL, e.g. `/c/s/example.com/story`
+const AMP_CACHE_PREFIXES: [&str; 4] = ["/c/", "/v/", "/wp/", "/i/"];
+
+/// The desktop URL behind an AMP, mobile or Google cache link; anything else is returned as it was.
+///
+/// Only the URL itself is looked at, nothing is fetched, so a site serving different articles on its
+/// `m.` host would be rewritten wrongly. That is rare enough to be worth the deduplication.
+pub fn unwrap(url: &str) -> Cow<'_, str> {
+    let Ok(mut parsed) = Url::parse(url) else {
+        return Cow::Borrowed(url);
+    };
+
+    // Viewer and cache links carry the real URL inside them
+    if let Some(inner) = wrapped_url(&parsed) {
+        return match Url::parse(&inner) {
+            Ok(inner) if inner.host_str().is_some() => {
+                parsed = inner;
+                unwrap_edition(&mut parsed);
+                Cow::Owned(parsed.to_string())
+            }
+            _ => Cow::Borrowed(url),
+        };
+    }
+
+    if unwrap_edition(&mut parsed) {
+        Cow::Owned(parsed.to_string())
+    } else {
+        Cow::Borrowed(url)
+    }
+}
+
+/// The URL a Google AMP viewer, AMP cache or Google cache link points at
+fn wrapped_url(url: &Url) -> Option<String> {
+    let host = url.host_str()?.to_ascii_lowercase();
+    let query = url.query().map(|query| format!("?{}", query)).unwrap_or_default();
+
+    // https://www.google.com/amp/s/example.com/story
+    let is_google = host == "google.com" || host.starts_with("google.") || host.starts_with("www.google.");
+    if is_google {
+        return with_scheme(url.path().strip_prefix("/amp/")?, &query);
+    }
+
+    // https://example-com.cdn.ampproject.org/c/s/example.com/story
+    if host.ends_with(".cdn.ampproject.org") {
+        let rest = AMP_CACHE_PREFIXES
+            .iter()
+            .find_map(|prefix| url.path().strip_prefix(prefix))?;
+        return with_scheme(rest, &query);
+    }
+
+    // https://webcache.googleusercontent.com/search?q=cache:AbC123:example.com/story+keywords
+    if host == "webcache.googleusercontent.com" {
+        let (_, q) = url.query_pairs().find(|(key, _)| key == "q")?;
+        let target = q.strip_prefix("cache:")?;
+        let target = match target.split_once(':') {
+            // Skip the cache id, but not the scheme of a full URL
+            Some((id, rest)) if !id.contains(['.', '/']) && !rest.starts_with("//") => rest,
+            _ => target,
+        };
+        let target = target.split_whitespace().next()?;
+        return Some(if target.contains("://") {
+            target.to_string()
+        } else {
+            format!("https://{}", target)
+        });
+    }
+
+    None
+}
+
+/// `s/example.com/story` is an https URL, `example.com/story` a plain http one
+fn with_scheme(rest: &str, query: &str) -> Option<String> {
+    let (scheme, rest) = match rest.strip_prefix("s/") {
+        Some(rest) => ("https", rest),
+        None => ("http", rest),
+    };
+    (!rest.is_empty()).then(|| format!("{}://{}{}", scheme, rest, query))
+}
+
+/// Drop mobile host labels, a trailing `/amp` path segment, and `amp` query flags; returns whether anything changed
+fn unwrap_edition(url: &mut Url) -> bool {
+    let mut changed = false;
+
+    if let Some(host) = url.host_str().map(str::to_ascii_lowercase) {
+        let labels: Vec<&str> = host.split('.').collect();
+        let kept: Vec<&str> = labels
+            .iter()
+            .copied()
+            .filter(|label| !MOBILE_LABELS.contains(label))
+            .collect();
+        // `m.example.com` becomes `example.com`, but `amp.dev` is left alone
+        if kept.len() < labels.len() && kept.len() >= 2 && url.set_host(Some(&kept.join("."))).is_ok() {
+            changed = true;
+        }
+    }
+
+    let path = url.path();
+    if let Some(desktop) = path.strip_suffix("/amp").or_else(|| path.strip_suffix("/amp/")) {
+        let desktop = if desktop.is_empty() { "/" } else { desktop }.to_string();
+        url.set_path(&desktop);
+        changed = true;
+    }
+
+    if let Some(query) = url.query() {
+        let pairs: Vec<&str> = query.split('&').collect();
+        let kept: Vec<&str> = pairs.iter().copied().filter(|pair| !is_amp_flag(pair)).collect();
+        if kept.len() < pairs.len() {
+            let kept = kept.join("&");
+            url.set_query((!kept.is_empty()).then_some(kept.as_str()));
+            changed = true;
+        }
+    }
+
+    changed
+}
+
+/// `amp`, `amp=1`, `outputType=amp`, and the parameters AMP caches add, such as `usqp` and `amp_js_v`
+fn is_amp_flag(pair: &str) -> bool {
+    let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+    let key = key.to_ascii_lowercase();
+    match key.as_str() {
+        "amp" => matches!(value, "" | "1" | "true"),
+        "outputtype" => value.eq_ignore_ascii_case("amp"),
+        "usqp" => true,
+        _ => key.starts_with("amp_"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unwraps_viewer_and_cache_links() {
+        assert_eq!(
+            unwrap("https://www.google.com/amp/s/www.example.com/2024/story.amp.html"),
+            "https://www.example.com/2024/story.amp.html"
+        );
+        assert_eq!(
+            unwrap(
+                "https://www-example-com.cdn.ampproject.org/c/s/www.example.com/story/amp/?amp_js_v=0.1&usqp=mq331AQ"
+            ),
+            "https://www.example.com/story"
+        );
+        assert_eq!(
+            unwrap("https://webcache.googleusercontent.com/search?q=cache:Ab12_-x:news.example/post+rust&hl=en"),
+            "https://news.example/post"
+        );
+    }
+
+    #[test]
+    fn unwraps_mobile_and_amp_editions() {
+        assert_eq!(
+            unwrap("https://en.m.wikipedia.org/wiki/Rust"),
+            "https://en.wikipedia.org/wiki/Rust"
+        );
+        assert_eq!(
+            unwrap("https://mobile.example.com/post/1/amp?id=3&amp=1"),
+            "https://example.com/post/1?id=3"
+        );
+        assert_eq!(unwrap("https://amp.example.com/"), "https://example.com/");
+    }
+
+    #[test]
+    fn leaves_other_urls_alone() {
+        for url in [
+            "https://amp.dev/documentation",
+            "https://example.com/campaign?ampersand=1",
+            "https://www.google.com/search?q=amp",
+            "not a url",
+        ] {
+            assert!(matches!(unwrap(url), Cow::Borrowed(_)), "{}", url);
+        }
+    }
+}
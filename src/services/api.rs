@@ -1,8 +1,14 @@
-use crate::services::models;
-use actix_web::{get, post, web, HttpResponse, Responder};
+use crate::config::Config;
+use crate::services::{access, changes, models, oembed, partials, qr, scheduler, snapshot_cache, snapshots, stats};
+use actix_web::http::header::{ETAG, IF_NONE_MATCH};
+use actix_web::{delete, get, patch, post, put, web, HttpRequest, HttpResponse, Responder};
 use ammonia::Builder;
+use chrono::{DateTime, Datelike, Utc};
+use serde::Serialize;
 use serde_json::json;
-use std::sync::Arc;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, LazyLock};
+use std::time::Instant;
 use tera::{Context, Tera};
 
 fn sanitize_with_allowed_tags(input: &str) -> ammonia::Document {
@@ -12,72 +18,233 @@ fn sanitize_with_allowed_tags(input: &str) -> ammonia::Document {
         .clean(input)
 }
 
+/// Record an activity timeline entry and tell live clients about it; failures are logged rather than failing the request
+pub(crate) async fn log_activity(database: &dyn models::Database, action: &str, subject: &str, detail: Option<&str>) {
+    if let Err(err) = database.record_activity(action, subject, detail).await {
+        eprintln!("Failed to record activity: {:?}", err);
+    }
+    changes::publish(action, subject, detail);
+}
+
+/// Turn listing query parameters into a filter, or a 400 explaining what was wrong
+// The response is returned straight from the handler, boxing it would only add noise at every call site
+#[allow(clippy::result_large_err)]
+fn list_filter(query: &models::ListQuery) -> Result<models::ListFilter, HttpResponse> {
+    models::ListFilter::try_from(query).map_err(|err| HttpResponse::BadRequest().json(err))
+}
+
+/// `type=` values offered as links on the index page, with their labels
+const INDEX_TYPE_OPTIONS: [(&str, &str); 7] = [
+    ("article", "Articles"),
+    ("video", "Videos"),
+    ("audio", "Audio"),
+    ("code", "Code"),
+    ("shopping", "Shopping"),
+    ("social", "Social"),
+    ("document", "Documents"),
+];
+
+/// `sort=` values offered as links on the index page, with their labels
+const INDEX_SORT_OPTIONS: [(&str, &str); 7] = [
+    ("added:desc", "Newest"),
+    ("added:asc", "Oldest"),
+    ("read:desc", "Recently read"),
+    ("title:asc", "Title"),
+    ("domain:asc", "Domain A-Z"),
+    ("domain:desc", "Domain Z-A"),
+    ("url:asc", "URL"),
+];
+
+/// Colors and descriptions of the tags that have any, keyed by tag, for the badge macro
+pub(crate) async fn tag_details_by_name(database: &dyn models::Database) -> HashMap<String, models::TagDetails> {
+    match database.get_tag_details().await {
+        Ok(tags) => tags
+            .into_iter()
+            .filter(|tag| tag.color.is_some() || tag.description.is_some())
+            .map(|tag| (tag.tag.clone(), tag))
+            .collect(),
+        Err(err) => {
+            eprintln!("Failed to fetch tag details: {:?}", err);
+            HashMap::new()
+        }
+    }
+}
+
+/// How many years back "on this day" looks
+const MEMORY_YEARS: [i32; 3] = [1, 2, 3];
+
+/// URLs saved on this day some years ago
+#[derive(Serialize, Debug)]
+pub(crate) struct Memory {
+    years_ago: i32,
+    urls: Vec<models::UrlWithTags>,
+}
+
+/// What was saved on `today`'s date one, two and three years before, latest first; years with nothing saved
+/// are left out
+pub(crate) async fn memories(
+    database: &dyn models::Database,
+    today: chrono::NaiveDate,
+) -> Result<Vec<Memory>, sqlx::Error> {
+    let filter = models::ListFilter {
+        anniversary: Some(models::Anniversary::of(today, &MEMORY_YEARS)),
+        ..Default::default()
+    };
+    let mut urls = database.get_urls_with_tags(&filter).await?;
+
+    let mut memories = Vec::new();
+    for years_ago in MEMORY_YEARS {
+        let (saved, rest) = urls
+            .into_iter()
+            .partition(|url| today.year() - url.datetime.year() == years_ago);
+        urls = rest;
+        if !saved.is_empty() {
+            memories.push(Memory { years_ago, urls: saved });
+        }
+    }
+    Ok(memories)
+}
+
+#[get("/memories")]
+async fn list_memories(database: web::Data<Arc<dyn models::Database>>) -> impl Responder {
+    match memories(database.get_ref().as_ref(), chrono::Utc::now().date_naive()).await {
+        Ok(memories) => HttpResponse::Ok().json(memories),
+        Err(err) => {
+            eprintln!("Failed to fetch memories: {:?}", err);
+            HttpResponse::InternalServerError().json("Failed to fetch memories")
+        }
+    }
+}
+
 #[get("/")]
 async fn index(
     database: web::Data<Arc<dyn models::Database>>,
     tmpl: web::Data<Tera>,
     database_type: web::Data<String>,
+    config: web::Data<Config>,
+    query: web::Query<models::ListQuery>,
 ) -> impl Responder {
-    let result = database.get_urls_with_tags().await;
+    let mut context = match partials::url_rows(database.get_ref().as_ref(), &query, &config.base_path).await {
+        Ok(context) => context,
+        Err(response) => return response,
+    };
+    context.insert("tag_list", &partials::tag_list(database.get_ref().as_ref()).await);
+    // A side section, so failing to fetch it only hides it
+    let memories = memories(database.get_ref().as_ref(), chrono::Utc::now().date_naive())
+        .await
+        .unwrap_or_else(|err| {
+            eprintln!("Failed to fetch memories: {:?}", err);
+            Vec::new()
+        });
+    context.insert("memories", &memories);
+    context.insert("list_query", &*query);
+    context.insert("sort_options", &INDEX_SORT_OPTIONS);
+    context.insert("type_options", &INDEX_TYPE_OPTIONS);
+    context.insert("title", "Read it Later");
+    context.insert("database_type", &**database_type);
 
-    match result {
-        Ok(urls_with_tags) => {
-            // Enrich the data to include display_url
-            let enriched_urls_with_tags: Vec<_> = urls_with_tags
-                .into_iter()
-                .map(|mut url_with_tags| {
-                    url_with_tags.display_url = url_with_tags
-                        .url
-                        .split('?')
-                        .next()
-                        .unwrap_or(&url_with_tags.url)
-                        .to_string();
-                    url_with_tags
-                })
-                .collect();
-
-            // Insert enriched data into the context
-            let mut context = Context::new();
-            context.insert("urls_with_tags", &enriched_urls_with_tags);
-            context.insert("title", "Read it Later");
-            context.insert("database_type", &**database_type);
-
-            // Render the template
-            match tmpl.render("index.html", &context) {
-                Ok(rendered) => HttpResponse::Ok().content_type("text/html").body(rendered),
-                Err(e) => {
-                    eprintln!("Template error: {:?}", e);
-                    HttpResponse::InternalServerError().body("Template error")
-                }
-            }
-        }
-        Err(err) => {
-            eprintln!("Failed to fetch URLs with tags: {:?}", err);
-            HttpResponse::InternalServerError().body("Failed to fetch URLs with tags")
+    // Render the template
+    match tmpl.render("index.html", &context) {
+        Ok(rendered) => HttpResponse::Ok().content_type("text/html").body(rendered),
+        Err(e) => {
+            eprintln!("Template error: {:?}", e);
+            HttpResponse::InternalServerError().body("Template error")
         }
     }
 }
 
+/// When the server started, for the uptime `/health` reports; forced at startup
+pub static STARTED: LazyLock<(Instant, DateTime<Utc>)> = LazyLock::new(|| (Instant::now(), Utc::now()));
+
+/// Build and database status, with a heartbeat from each scheduled job, so monitoring can tell a slow database
+/// from a scheduler that has stopped
 #[get("/health")]
 async fn health(database: web::Data<Arc<dyn models::Database>>) -> impl Responder {
+    let checked = Instant::now();
     let db_status = database.check_health().await;
+    let latency = checked.elapsed();
+    let (started, started_at) = *STARTED;
 
     let health_response = json!({
         "status": "ok",
         "database": db_status,
+        "database_latency_ms": (latency.as_secs_f64() * 100_000.0).round() / 100.0,
+        "version": env!("CARGO_PKG_VERSION"),
+        "git_sha": option_env!("GIT_SHA").filter(|sha| !sha.is_empty()),
+        "started_at": started_at,
+        "uptime_seconds": started.elapsed().as_secs(),
+        "jobs": scheduler::heartbeats(),
     });
 
     HttpResponse::Ok().json(health_response)
 }
 
+/// A 201 for a newly saved record, pointing at where it can be fetched again
+pub(crate) fn created(location: String, record: &impl serde::Serialize) -> HttpResponse {
+    HttpResponse::Created()
+        .insert_header((actix_web::http::header::LOCATION, location))
+        .json(record)
+}
+
+/// A 201 with the snippet as it was saved, its text sanitized as for listings
+pub(crate) async fn created_snippet(database: &dyn models::Database, base_path: &str, snippet_id: i32) -> HttpResponse {
+    match database.get_snippet(snippet_id).await {
+        Ok(Some(snippet)) => created(
+            format!("{}/snippets/{}", base_path, snippet_id),
+            &sanitize_snippets(vec![snippet])[0],
+        ),
+        Ok(None) => HttpResponse::NotFound().json("Snippet not found"),
+        Err(err) => {
+            eprintln!("Failed to fetch saved snippet: {:?}", err);
+            HttpResponse::InternalServerError().json("Failed to fetch saved snippet")
+        }
+    }
+}
+
+// Saving a URL that is already saved is not an error: it answers 200 with `already_existed` set instead of 201
 #[post("/urls/url")]
 async fn insert_record(
     database: web::Data<Arc<dyn models::Database>>,
+    config: web::Data<Config>,
     req: web::Json<models::NewUrl>,
 ) -> impl Responder {
-    match database.insert_url(&req.url).await {
-        Ok(_) => HttpResponse::Ok().json("Record inserted successfully"),
-        Err(sqlx::Error::RowNotFound) => HttpResponse::Conflict().json("Record already exists"),
+    let database = database.get_ref().as_ref();
+    let tags: Vec<&str> = req.tags.iter().map(String::as_str).collect();
+
+    let already_existed = match database.get_url_with_tags(&req.url).await {
+        Ok(existing) => existing.is_some(),
+        Err(err) => {
+            eprintln!("Failed to look up URL: {:?}", err);
+            return HttpResponse::InternalServerError().json("Failed to insert record");
+        }
+    };
+    let saved = if tags.is_empty() {
+        database.insert_url(&req.url).await.map(|_| ())
+    } else {
+        database.insert_tags(&req.url, &tags).await
+    };
+    let saved = match saved {
+        Ok(()) => database.get_url_with_tags(&req.url).await,
+        Err(err) => Err(err),
+    };
+
+    match saved {
+        Ok(Some(url)) => {
+            if !already_existed {
+                log_activity(database, "save_url", &req.url, None).await;
+            }
+            if !tags.is_empty() {
+                log_activity(database, "tag_url", &req.url, Some(&tags.join(","))).await;
+            }
+            let location = format!("{}/urls/{}", config.base_path, url.short_id);
+            let saved = models::SavedUrl { url, already_existed };
+            if already_existed {
+                HttpResponse::Ok().json(saved)
+            } else {
+                created(location, &saved)
+            }
+        }
+        Ok(None) => HttpResponse::NotFound().json("URL not found"),
         Err(err) => {
             eprintln!("Failed to insert record: {:?}", err);
             HttpResponse::InternalServerError().json("Failed to insert record")
@@ -85,9 +252,84 @@ async fn insert_record(
     }
 }
 
+#[get("/urls/{id:[0-9a-f]+}")]
+async fn get_url(database: web::Data<Arc<dyn models::Database>>, path: web::Path<String>) -> impl Responder {
+    match partials::find_url(database.get_ref().as_ref(), &path).await {
+        Ok(Some(url)) => HttpResponse::Ok().json(url),
+        Ok(None) => HttpResponse::NotFound().json("URL not found"),
+        Err(err) => {
+            eprintln!("Failed to fetch URL: {:?}", err);
+            HttpResponse::InternalServerError().json("Failed to fetch URL")
+        }
+    }
+}
+
+/// Pick the shared link: the `url` field if present, otherwise the first link inside `text` or `title`.
+/// Android apps commonly put the link in `text`, sometimes with a headline in front of it.
+fn extract_shared_link(shared: &models::SharedContent) -> Option<String> {
+    let is_link = |candidate: &str| candidate.starts_with("http://") || candidate.starts_with("https://");
+
+    if let Some(url) = shared.url.as_deref().map(str::trim).filter(|url| is_link(url)) {
+        return Some(url.to_string());
+    }
+
+    [shared.text.as_deref(), shared.title.as_deref()]
+        .into_iter()
+        .flatten()
+        .flat_map(str::split_whitespace)
+        .find(|word| is_link(word))
+        .map(|word| word.trim_end_matches(['.', ',', ';', ')', '"', '\'']).to_string())
+}
+
+#[post("/share-target")]
+async fn share_target(
+    database: web::Data<Arc<dyn models::Database>>,
+    tmpl: web::Data<Tera>,
+    config: web::Data<Config>,
+    form: web::Form<models::SharedContent>,
+) -> impl Responder {
+    let mut context = Context::new();
+    context.insert("title", "Read it Later");
+    context.insert("base_path", &config.base_path);
+
+    let mut response = match extract_shared_link(&form) {
+        Some(url) => match database.insert_url(&url).await {
+            Ok(_) => {
+                log_activity(database.get_ref().as_ref(), "save_url", &url, Some("share target")).await;
+                context.insert("saved_url", &url);
+                HttpResponse::Ok()
+            }
+            Err(err) => {
+                eprintln!("Failed to save shared URL: {:?}", err);
+                context.insert("message", "The link could not be saved, please try again.");
+                HttpResponse::InternalServerError()
+            }
+        },
+        None => {
+            context.insert("message", "The shared content did not contain a link.");
+            HttpResponse::BadRequest()
+        }
+    };
+
+    match tmpl.render("share_target.html", &context) {
+        Ok(rendered) => response.content_type("text/html").body(rendered),
+        Err(e) => {
+            eprintln!("Template error: {:?}", e);
+            HttpResponse::InternalServerError().body("Template error")
+        }
+    }
+}
+
 #[get("/urls")]
-async fn list_urls(database: web::Data<Arc<dyn models::Database>>) -> impl Responder {
-    let result = database.get_all_urls().await;
+async fn list_urls(
+    database: web::Data<Arc<dyn models::Database>>,
+    query: web::Query<models::ListQuery>,
+) -> impl Responder {
+    let filter = match list_filter(&query) {
+        Ok(filter) => filter,
+        Err(response) => return response,
+    };
+    let result = database.get_all_urls(&filter).await;
 
     match result {
         Ok(urls) => HttpResponse::Ok().json(urls), // Serialize and return the list of URLs
@@ -98,6 +340,24 @@ async fn list_urls(database: web::Data<Arc<dyn models::Database>>) -> impl Respo
     }
 }
 
+/// The URL a request naming `url` means: `url` itself if it's saved or only an exact match was asked for, else
+/// the one saved URL that matches it loosely. Several loose matches come back as `Err`, oldest first.
+async fn matched_url(
+    database: &dyn models::Database,
+    url: &str,
+    matching: models::UrlMatch,
+) -> Result<Result<String, Vec<String>>, sqlx::Error> {
+    if matching == models::UrlMatch::Exact || database.url_exists(url).await? {
+        return Ok(Ok(url.to_string()));
+    }
+    let mut matches = database.get_loose_url_matches(url).await?;
+    Ok(match matches.len() {
+        0 => Ok(url.to_string()),
+        1 => Ok(matches.remove(0)),
+        _ => Err(matches),
+    })
+}
+
 #[post("/urls/delete/by-url")]
 async fn delete_record_by_url(
     database: web::Data<Arc<dyn models::Database>>,
@@ -105,14 +365,24 @@ async fn delete_record_by_url(
 ) -> impl Responder {
     println!("Body: {:?}", req);
 
-    let result = database.delete_url_by_url(&req.url).await;
+    let url = match matched_url(database.get_ref().as_ref(), &req.url, req.matching).await {
+        Ok(Ok(url)) => url,
+        Ok(Err(matches)) => {
+            return HttpResponse::Conflict().json(json!({
+                "message": "Several saved URLs match loosely, delete one of them exactly",
+                "matches": matches,
+            }))
+        }
+        Err(err) => {
+            eprintln!("Failed to look up URL: {:?}", err);
+            return HttpResponse::InternalServerError().json("Failed to delete URL");
+        }
+    };
+    let result = database.delete_url_by_url(&url).await;
 
     match result {
         Ok(_) => {
-            // Call the background job to remove unused tags
-            if let Err(err) = database.remove_unused_tags().await {
-                eprintln!("Failed to remove unused tags: {:?}", err);
-            }
+            log_activity(database.get_ref().as_ref(), "delete_url", &url, None).await;
             HttpResponse::Ok().json("URL deleted successfully")
         }
         Err(err) => {
@@ -127,10 +397,13 @@ async fn insert_tags(
     database: web::Data<Arc<dyn models::Database>>,
     req: web::Json<models::UrlTags>,
 ) -> impl Responder {
-    let tags: Vec<&str> = req.tags.split(',').map(|tag| tag.trim()).collect();
+    let tags: Vec<&str> = req.tags.iter().map(String::as_str).collect();
 
     match database.insert_tags(&req.url, &tags).await {
-        Ok(_) => HttpResponse::Ok().json("Tags inserted successfully"),
+        Ok(_) => {
+            log_activity(database.get_ref().as_ref(), "tag_url", &req.url, Some(&tags.join(","))).await;
+            HttpResponse::Ok().json("Tags inserted successfully")
+        }
         Err(sqlx::Error::RowNotFound) => HttpResponse::Conflict().json("One or more tags already exist for this URL"),
         Err(err) => {
             eprintln!("Failed to insert tags: {:?}", err);
@@ -139,10 +412,209 @@ async fn insert_tags(
     }
 }
 
+/// Replace a URL's tags with the given set; an empty `tags` removes them all
+#[put("/urls/tags")]
+async fn set_url_tags(
+    database: web::Data<Arc<dyn models::Database>>,
+    req: web::Json<models::UrlTags>,
+) -> impl Responder {
+    let tags: Vec<&str> = req.tags.iter().map(String::as_str).collect();
+
+    match database.set_url_tags(&req.url, &tags).await {
+        Ok(_) => {
+            log_activity(database.get_ref().as_ref(), "set_tags", &req.url, Some(&tags.join(","))).await;
+            HttpResponse::Ok().json("Tags replaced successfully")
+        }
+        Err(err) => {
+            eprintln!("Failed to replace tags: {:?}", err);
+            HttpResponse::InternalServerError().json("Failed to replace tags")
+        }
+    }
+}
+
+// Lets the extension show a page is already saved without fetching every URL
+#[get("/urls/exists")]
+async fn url_exists(
+    database: web::Data<Arc<dyn models::Database>>,
+    query: web::Query<models::UrlLookup>,
+) -> impl Responder {
+    let url = match matched_url(database.get_ref().as_ref(), &query.url, query.matching).await {
+        Ok(Ok(url)) => url,
+        // Any of them shows the page is saved
+        Ok(Err(matches)) => matches[0].clone(),
+        Err(err) => {
+            eprintln!("Failed to look up URL: {:?}", err);
+            return HttpResponse::InternalServerError().json("Failed to look up URL");
+        }
+    };
+    match database.get_url_with_tags(&url).await {
+        Ok(Some(saved)) => HttpResponse::Ok().json(json!({
+            "exists": true,
+            "url": saved.url,
+            "short_id": saved.short_id,
+            "tags": saved.tags,
+            "read": saved.read_at.is_some(),
+            "read_at": saved.read_at,
+            "pinned": saved.pinned,
+        })),
+        Ok(None) => HttpResponse::Ok().json(json!({ "exists": false })),
+        Err(err) => {
+            eprintln!("Failed to look up URL: {:?}", err);
+            HttpResponse::InternalServerError().json("Failed to look up URL")
+        }
+    }
+}
+
+/// Change some of a URL's fields, found by short id, answering with the URL as it is afterwards
+#[patch("/api/v1/urls/{id}")]
+async fn update_url(
+    database: web::Data<Arc<dyn models::Database>>,
+    path: web::Path<String>,
+    req: web::Json<models::UrlUpdate>,
+) -> impl Responder {
+    let update = match req.into_inner().validate() {
+        Ok(update) => update,
+        Err(err) => return HttpResponse::BadRequest().json(err),
+    };
+    let database = database.get_ref().as_ref();
+    let url = match partials::find_url(database, &path).await {
+        Ok(Some(url)) => url,
+        Ok(None) => return HttpResponse::NotFound().json("URL not found"),
+        Err(err) => {
+            eprintln!("Failed to look up URL: {:?}", err);
+            return HttpResponse::InternalServerError().json("Failed to update URL");
+        }
+    };
+
+    match database.update_url(url.id, &update).await {
+        Ok(()) => {}
+        Err(sqlx::Error::RowNotFound) => return HttpResponse::NotFound().json("URL not found"),
+        Err(err) => {
+            eprintln!("Failed to update URL: {:?}", err);
+            return HttpResponse::InternalServerError().json("Failed to update URL");
+        }
+    }
+
+    // Read state and tags are logged as their own actions, so peers pick them up
+    if let Some(read) = update.read {
+        log_activity(database, if read { "read_url" } else { "unread_url" }, &url.url, None).await;
+    }
+    if let Some(tags) = update.tag_list() {
+        log_activity(database, "set_tags", &url.url, Some(&tags.join(","))).await;
+    }
+    if update.title.is_some() || update.notes.is_some() || update.archived.is_some() || update.snoozed_until.is_some() {
+        log_activity(database, "update_url", &url.url, None).await;
+    }
+
+    match partials::find_url(database, &path).await {
+        Ok(Some(url)) => HttpResponse::Ok().json(url),
+        Ok(None) => HttpResponse::NotFound().json("URL not found"),
+        Err(err) => {
+            eprintln!("Failed to fetch updated URL: {:?}", err);
+            HttpResponse::InternalServerError().json("Failed to fetch updated URL")
+        }
+    }
+}
+
+/// Save a URL under another address, such as after its site moved, merging it into that address if it's saved too
+#[put("/api/v1/urls/{id}/url")]
+async fn move_url(
+    database: web::Data<Arc<dyn models::Database>>,
+    path: web::Path<String>,
+    req: web::Json<models::UrlMove>,
+) -> impl Responder {
+    let target = req.url.trim();
+    if !target.starts_with("http://") && !target.starts_with("https://") {
+        return HttpResponse::BadRequest().json("URL must start with http:// or https://");
+    }
+    let database = database.get_ref().as_ref();
+    let url = match partials::find_url(database, &path).await {
+        Ok(Some(url)) => url,
+        Ok(None) => return HttpResponse::NotFound().json("URL not found"),
+        Err(err) => {
+            eprintln!("Failed to look up URL: {:?}", err);
+            return HttpResponse::InternalServerError().json("Failed to move URL");
+        }
+    };
+
+    let merged = match database.move_url(url.id, target).await {
+        Ok(Some(moved)) => {
+            log_activity(database, "move_url", &moved.original, Some(&moved.url)).await;
+            moved.merged
+        }
+        Ok(None) => false,
+        Err(err) => {
+            eprintln!("Failed to move URL: {:?}", err);
+            return HttpResponse::InternalServerError().json("Failed to move URL");
+        }
+    };
+
+    match database.get_url_with_tags(target).await {
+        Ok(Some(url)) => HttpResponse::Ok().json(models::MovedUrl { url, merged }),
+        Ok(None) => HttpResponse::NotFound().json("URL not found"),
+        Err(err) => {
+            eprintln!("Failed to fetch moved URL: {:?}", err);
+            HttpResponse::InternalServerError().json("Failed to fetch moved URL")
+        }
+    }
+}
+
+/// Everything recorded about a URL beyond its own fields, such as how its last snapshot went, by name
+#[get("/api/v1/urls/{id}/metadata")]
+async fn get_url_metadata(database: web::Data<Arc<dyn models::Database>>, path: web::Path<String>) -> impl Responder {
+    let database = database.get_ref().as_ref();
+    let url = match partials::find_url(database, &path).await {
+        Ok(Some(url)) => url,
+        Ok(None) => return HttpResponse::NotFound().json("URL not found"),
+        Err(err) => {
+            eprintln!("Failed to look up URL: {:?}", err);
+            return HttpResponse::InternalServerError().json("Failed to fetch URL metadata");
+        }
+    };
+
+    match database.get_url_metadata_entries(url.id).await {
+        Ok(entries) => HttpResponse::Ok().json(entries),
+        Err(err) => {
+            eprintln!("Failed to fetch URL metadata: {:?}", err);
+            HttpResponse::InternalServerError().json("Failed to fetch URL metadata")
+        }
+    }
+}
+
+/// Whether an `If-None-Match` header on `req` already names `etag`, weak or strong
+pub(crate) fn etag_matches(req: &HttpRequest, etag: &str) -> bool {
+    req.headers()
+        .get_all(IF_NONE_MATCH)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|tag| tag.trim())
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+/// Listings carry an `ETag` from a fingerprint that costs one aggregate query, so the extension's polling gets a
+/// bodiless 304 until something changes
 #[get("/urls_with_tags")]
-async fn list_urls_with_tags(database: web::Data<Arc<dyn models::Database>>) -> impl Responder {
-    match database.get_urls_with_tags().await {
-        Ok(urls_with_tags) => HttpResponse::Ok().json(urls_with_tags),
+async fn list_urls_with_tags(
+    req: HttpRequest,
+    database: web::Data<Arc<dyn models::Database>>,
+    query: web::Query<models::ListQuery>,
+) -> impl Responder {
+    let filter = match list_filter(&query) {
+        Ok(filter) => filter,
+        Err(response) => return response,
+    };
+    let etag = match database.get_listing_fingerprint(&filter).await {
+        Ok(fingerprint) => fingerprint.etag(),
+        Err(err) => {
+            eprintln!("Failed to fingerprint URLs with tags: {:?}", err);
+            return HttpResponse::InternalServerError().json("Failed to fetch URLs with tags");
+        }
+    };
+    if etag_matches(&req, &etag) {
+        return HttpResponse::NotModified().insert_header((ETAG, etag)).finish();
+    }
+    match database.get_urls_with_tags(&filter).await {
+        Ok(urls_with_tags) => HttpResponse::Ok().insert_header((ETAG, etag)).json(urls_with_tags),
         Err(err) => {
             eprintln!("Failed to fetch URLs with tags: {:?}", err);
             HttpResponse::InternalServerError().json("Failed to fetch URLs with tags")
@@ -150,20 +622,353 @@ async fn list_urls_with_tags(database: web::Data<Arc<dyn models::Database>>) ->
     }
 }
 
+#[get("/urls/random")]
+async fn random_url(
+    database: web::Data<Arc<dyn models::Database>>,
+    query: web::Query<models::RandomQuery>,
+) -> impl Responder {
+    let filter = models::ListFilter {
+        tags: query.tag.as_deref().map(models::TagFilter::parse).unwrap_or_default(),
+        unread: query.unread.unwrap_or(false),
+        ..Default::default()
+    };
+
+    match database.get_random_url(&filter).await {
+        Ok(Some(url)) => HttpResponse::Ok().json(url),
+        Ok(None) => HttpResponse::NotFound().json("No matching URLs"),
+        Err(err) => {
+            eprintln!("Failed to pick a random URL: {:?}", err);
+            HttpResponse::InternalServerError().json("Failed to pick a random URL")
+        }
+    }
+}
+
+#[post("/urls/read")]
+async fn mark_url_read(
+    database: web::Data<Arc<dyn models::Database>>,
+    req: web::Json<models::MarkRead>,
+) -> impl Responder {
+    let read = req.read.unwrap_or(true);
+
+    match database.set_url_read(&req.url, read).await {
+        Ok(_) => {
+            let action = if read { "read_url" } else { "unread_url" };
+            log_activity(database.get_ref().as_ref(), action, &req.url, None).await;
+            HttpResponse::Ok().json(if read { "URL marked read" } else { "URL marked unread" })
+        }
+        Err(sqlx::Error::RowNotFound) => HttpResponse::NotFound().json("URL not found"),
+        Err(err) => {
+            eprintln!("Failed to update read state: {:?}", err);
+            HttpResponse::InternalServerError().json("Failed to update read state")
+        }
+    }
+}
+
+/// The unread URL a triage pass should open next, honouring the same `tags`, date and `sort` parameters as listings
+#[allow(clippy::result_large_err)]
+async fn next_unread(
+    database: &dyn models::Database,
+    query: &models::ListQuery,
+) -> Result<Option<models::Url>, HttpResponse> {
+    let filter = models::ListFilter {
+        unread: true,
+        ..list_filter(query)?
+    };
+
+    database.get_next_url(&filter).await.map_err(|err| {
+        eprintln!("Failed to fetch the next unread URL: {:?}", err);
+        HttpResponse::InternalServerError().json("Failed to fetch the next unread URL")
+    })
+}
+
+#[get("/urls/next-unread")]
+async fn next_unread_url(
+    database: web::Data<Arc<dyn models::Database>>,
+    query: web::Query<models::ListQuery>,
+) -> impl Responder {
+    match next_unread(database.get_ref().as_ref(), &query).await {
+        Ok(Some(url)) => HttpResponse::Ok().json(url),
+        Ok(None) => HttpResponse::NotFound().json("Nothing unread"),
+        Err(response) => response,
+    }
+}
+
+/// Mark a URL read and return the next unread one (`null` once the queue is empty) in a single round trip
+#[post("/urls/{id}/mark-read-and-next")]
+async fn mark_read_and_next(
+    database: web::Data<Arc<dyn models::Database>>,
+    path: web::Path<String>,
+    query: web::Query<models::ListQuery>,
+) -> impl Responder {
+    let url = match database.get_url_by_short_id(&path).await {
+        Ok(Some(url)) => url,
+        Ok(None) => return HttpResponse::NotFound().json("URL not found"),
+        Err(err) => {
+            eprintln!("Failed to look up URL: {:?}", err);
+            return HttpResponse::InternalServerError().json("Failed to look up URL");
+        }
+    };
+
+    if let Err(err) = database.set_url_read(&url.url, true).await {
+        eprintln!("Failed to update read state: {:?}", err);
+        return HttpResponse::InternalServerError().json("Failed to update read state");
+    }
+    log_activity(database.get_ref().as_ref(), "read_url", &url.url, None).await;
+
+    match next_unread(database.get_ref().as_ref(), &query).await {
+        Ok(next) => HttpResponse::Ok().json(json!({ "read": url.short_id, "next": next })),
+        Err(response) => response,
+    }
+}
+
+/// Pinned URLs sort to the top of the index whatever the chosen sort
+#[post("/urls/pin")]
+async fn pin_url(database: web::Data<Arc<dyn models::Database>>, req: web::Json<models::PinUrl>) -> impl Responder {
+    let pinned = req.pinned.unwrap_or(true);
+
+    match database.set_url_pinned(&req.url, pinned).await {
+        Ok(_) => {
+            let action = if pinned { "pin_url" } else { "unpin_url" };
+            log_activity(database.get_ref().as_ref(), action, &req.url, None).await;
+            HttpResponse::Ok().json(if pinned { "URL pinned" } else { "URL unpinned" })
+        }
+        Err(sqlx::Error::RowNotFound) => HttpResponse::NotFound().json("URL not found"),
+        Err(err) => {
+            eprintln!("Failed to update pinned state: {:?}", err);
+            HttpResponse::InternalServerError().json("Failed to update pinned state")
+        }
+    }
+}
+
+/// Redirect a short share link to the original URL. The owner following it counts as opening the link, for
+/// the reading stats.
+#[get("/s/{id}")]
+async fn short_link(
+    req: HttpRequest,
+    database: web::Data<Arc<dyn models::Database>>,
+    config: web::Data<Config>,
+    path: web::Path<String>,
+) -> impl Responder {
+    match database.get_url_by_short_id(&path).await {
+        Ok(Some(url)) => {
+            if access::is_owner(&req, &config) {
+                log_activity(database.get_ref().as_ref(), stats::OPEN_ACTION, &url.url, None).await;
+            }
+            HttpResponse::Found()
+                .insert_header((actix_web::http::header::LOCATION, url.url))
+                .finish()
+        }
+        Ok(None) => HttpResponse::NotFound().json("URL not found"),
+        Err(err) => {
+            eprintln!("Failed to look up short link: {:?}", err);
+            HttpResponse::InternalServerError().json("Failed to look up short link")
+        }
+    }
+}
+
+/// Save how far through an article the reader got, so it can be resumed on another device.
+/// Not logged to the activity timeline, the reader sends these as it scrolls
+#[put("/urls/{id}/progress")]
+async fn set_url_progress(
+    database: web::Data<Arc<dyn models::Database>>,
+    path: web::Path<String>,
+    req: web::Json<models::ReadingProgress>,
+) -> impl Responder {
+    let progress = match req.validate() {
+        Ok(progress) => progress,
+        Err(err) => return HttpResponse::BadRequest().json(err),
+    };
+
+    let url = match database.get_url_by_short_id(&path).await {
+        Ok(Some(url)) => url,
+        Ok(None) => return HttpResponse::NotFound().json("URL not found"),
+        Err(err) => {
+            eprintln!("Failed to look up URL: {:?}", err);
+            return HttpResponse::InternalServerError().json("Failed to look up URL");
+        }
+    };
+
+    match database.set_url_progress(&url.url, progress).await {
+        Ok(_) => HttpResponse::Ok().json(json!({ "id": url.short_id, "progress": progress })),
+        Err(sqlx::Error::RowNotFound) => HttpResponse::NotFound().json("URL not found"),
+        Err(err) => {
+            eprintln!("Failed to update reading progress: {:?}", err);
+            HttpResponse::InternalServerError().json("Failed to update reading progress")
+        }
+    }
+}
+
+/// Reader page for one saved URL: the link itself with every snippet taken from it alongside
+#[get("/read/{id}")]
+async fn reader_page(
+    database: web::Data<Arc<dyn models::Database>>,
+    tmpl: web::Data<Tera>,
+    database_type: web::Data<String>,
+    config: web::Data<Config>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let url = match database.get_url_by_short_id(&path).await {
+        Ok(Some(url)) => url,
+        Ok(None) => return HttpResponse::NotFound().body("URL not found"),
+        Err(err) => {
+            eprintln!("Failed to look up URL: {:?}", err);
+            return HttpResponse::InternalServerError().body("Failed to look up URL");
+        }
+    };
+
+    match database.get_snippets_for_url(&url.url).await {
+        Ok(snippets) => {
+            // Shown as the listings show it, or as the bare URL if that can't be looked up
+            let display_title = match database.get_url_with_tags(&url.url).await {
+                Ok(Some(saved)) => saved.display_title,
+                _ => models::trimmed_url(&url.url).to_string(),
+            };
+            // Only used to decide whether there is anything to diff, so a failure just hides the link
+            let snapshot_count = database
+                .get_snapshots(url.id)
+                .await
+                .map(|snapshots| snapshots.len())
+                .unwrap_or(0);
+            // Likewise only a hint, so an unreadable record is treated as no failure
+            let snapshot_error = match database
+                .get_ref()
+                .as_ref()
+                .get_metadata(url.id, &snapshots::LAST_FETCH)
+                .await
+            {
+                Ok(Some(last)) => last.error,
+                _ => None,
+            };
+
+            let mut context = Context::new();
+            context.insert("url", &url);
+            context.insert("display_title", &display_title);
+            context.insert("snapshot_count", &snapshot_count);
+            context.insert("snapshot_error", &snapshot_error);
+            context.insert("preview", &oembed::preview(&config.previews, &url.url).await);
+            context.insert("snippets_with_tags", &sanitize_snippets(snippets));
+            context.insert("tag_details", &tag_details_by_name(database.get_ref().as_ref()).await);
+            context.insert("title", &display_title);
+            context.insert("database_type", &**database_type);
+            context.insert("base_path", &config.base_path);
+
+            match tmpl.render("reader.html", &context) {
+                Ok(rendered) => HttpResponse::Ok().content_type("text/html").body(rendered),
+                Err(e) => {
+                    eprintln!("Template error: {:?}", e);
+                    HttpResponse::InternalServerError().body("Template error")
+                }
+            }
+        }
+        Err(err) => {
+            eprintln!("Failed to fetch snippets for URL: {:?}", err);
+            HttpResponse::InternalServerError().body("Failed to fetch snippets for URL")
+        }
+    }
+}
+
+/// QR code for the original link, for moving an article to another device
+#[get("/urls/{id}/qr.png")]
+async fn url_qr_code(database: web::Data<Arc<dyn models::Database>>, path: web::Path<String>) -> impl Responder {
+    match database.get_url_by_short_id(&path).await {
+        Ok(Some(url)) => match qr::png(&url.url) {
+            Ok(image) => HttpResponse::Ok().content_type("image/png").body(image),
+            Err(err) => {
+                eprintln!("{}", err);
+                HttpResponse::InternalServerError().json("Failed to render QR code")
+            }
+        },
+        Ok(None) => HttpResponse::NotFound().json("URL not found"),
+        Err(err) => {
+            eprintln!("Failed to look up URL for QR code: {:?}", err);
+            HttpResponse::InternalServerError().json("Failed to look up URL")
+        }
+    }
+}
+
+#[get("/urls/untagged")]
+async fn list_untagged_urls(database: web::Data<Arc<dyn models::Database>>) -> impl Responder {
+    match database.get_urls_with_tags(&models::ListFilter::untagged()).await {
+        Ok(urls) => HttpResponse::Ok().json(urls),
+        Err(err) => {
+            eprintln!("Failed to fetch untagged URLs: {:?}", err);
+            HttpResponse::InternalServerError().json("Failed to fetch untagged URLs")
+        }
+    }
+}
+
+#[get("/snippets/untagged")]
+async fn list_untagged_snippets(database: web::Data<Arc<dyn models::Database>>) -> impl Responder {
+    match database.get_snippets_with_tags(&models::ListFilter::untagged()).await {
+        Ok(snippets) => HttpResponse::Ok().json(sanitize_snippets(snippets)),
+        Err(err) => {
+            eprintln!("Failed to fetch untagged snippets: {:?}", err);
+            HttpResponse::InternalServerError().json("Failed to fetch untagged snippets")
+        }
+    }
+}
+
+/// Triage page listing only the URLs and snippets that still need tags
+#[get("/inbox")]
+async fn inbox_page(
+    database: web::Data<Arc<dyn models::Database>>,
+    tmpl: web::Data<Tera>,
+    database_type: web::Data<String>,
+    config: web::Data<Config>,
+) -> impl Responder {
+    let filter = models::ListFilter::untagged();
+    let urls = database.get_urls_with_tags(&filter).await;
+    let snippets = database.get_snippets_with_tags(&filter).await;
+
+    match (urls, snippets) {
+        (Ok(urls), Ok(snippets)) => {
+            let mut context = Context::new();
+            context.insert("urls", &urls);
+            context.insert("snippets", &sanitize_snippets(snippets));
+            context.insert("title", "Inbox");
+            context.insert("database_type", &**database_type);
+            context.insert("base_path", &config.base_path);
+
+            match tmpl.render("inbox.html", &context) {
+                Ok(rendered) => HttpResponse::Ok().content_type("text/html").body(rendered),
+                Err(e) => {
+                    eprintln!("Template error: {:?}", e);
+                    HttpResponse::InternalServerError().body("Template error")
+                }
+            }
+        }
+        (Err(err), _) | (_, Err(err)) => {
+            eprintln!("Failed to fetch untagged items: {:?}", err);
+            HttpResponse::InternalServerError().body("Failed to fetch untagged items")
+        }
+    }
+}
+
 #[get("/tags")]
 async fn tags_page(
     database: web::Data<Arc<dyn models::Database>>,
     tmpl: web::Data<Tera>,
     database_type: web::Data<String>,
+    config: web::Data<Config>,
+    query: web::Query<models::ListQuery>,
 ) -> impl Responder {
-    let result = database.get_tags_with_urls_and_snippets().await;
+    let filter = match list_filter(&query) {
+        Ok(filter) => filter,
+        Err(response) => return response,
+    };
+    let result = tags_matching(database.get_ref().as_ref(), &filter).await;
 
     match result {
         Ok(tags_with_urls_and_snippets) => {
+            let tags_with_urls_and_snippets = models::roll_up_tags(tags_with_urls_and_snippets);
             let mut context = Context::new();
             context.insert("tags_with_urls_and_snippets", &tags_with_urls_and_snippets);
+            context.insert("tag_filter", query.tags.as_deref().unwrap_or_default());
+            context.insert("tag_details", &tag_details_by_name(database.get_ref().as_ref()).await);
+            context.insert("tag_cloud", &tag_cloud(database.get_ref().as_ref()).await);
             context.insert("title", "Tags");
             context.insert("database_type", &**database_type);
+            context.insert("base_path", &config.base_path);
 
             match tmpl.render("tags.html", &context) {
                 Ok(rendered) => HttpResponse::Ok().content_type("text/html").body(rendered),
@@ -180,63 +985,161 @@ async fn tags_page(
     }
 }
 
+/// The tags page's cloud, from the counts kept up to date on every write; an empty cloud if they can't be read
+async fn tag_cloud(database: &dyn models::Database) -> Vec<models::CloudTag> {
+    match database.get_tag_counts().await {
+        Ok(counts) => models::tag_cloud(counts),
+        Err(err) => {
+            eprintln!("Failed to fetch tag counts: {:?}", err);
+            Vec::new()
+        }
+    }
+}
+
+/// Group URLs and snippets by tag, keeping only the items that match `filter`
+async fn tags_matching(
+    database: &dyn models::Database,
+    filter: &models::ListFilter,
+) -> Result<Vec<models::TagWithUrlsAndSnippets>, sqlx::Error> {
+    let tags = database.get_tags_with_urls_and_snippets().await?;
+    if filter.is_empty() {
+        return Ok(tags);
+    }
+
+    let urls: HashSet<String> = database
+        .get_urls_with_tags(filter)
+        .await?
+        .into_iter()
+        .map(|url| url.url)
+        .collect();
+    let snippet_ids: HashSet<i32> = database
+        .get_snippets_with_tags(filter)
+        .await?
+        .into_iter()
+        .map(|snippet| snippet.id)
+        .collect();
+
+    Ok(tags
+        .into_iter()
+        .filter_map(|mut tag| {
+            tag.urls.retain(|url| urls.contains(url));
+            tag.snippets.retain(|snippet| snippet_ids.contains(&snippet.id));
+            (!tag.urls.is_empty() || !tag.snippets.is_empty()).then_some(tag)
+        })
+        .collect())
+}
+
 #[get("/snippets")]
 async fn snippets_page(
     database: web::Data<Arc<dyn models::Database>>,
     tmpl: web::Data<Tera>,
     database_type: web::Data<String>,
+    config: web::Data<Config>,
+    query: web::Query<models::ListQuery>,
 ) -> impl Responder {
-    let result = database.get_snippets_with_tags().await;
+    let mut context = match partials::snippet_rows(database.get_ref().as_ref(), &query, &config.base_path).await {
+        Ok(context) => context,
+        Err(response) => return response,
+    };
+    context.insert("title", "Snippets");
+    context.insert("database_type", &**database_type);
 
-    match result {
-        Ok(snippets_with_tags) => {
-            let sanitized_snippets = sanitize_snippets(snippets_with_tags);
-
-            let mut context = Context::new();
-            context.insert("snippets_with_tags", &sanitized_snippets);
-            context.insert("title", "Snippets");
-            context.insert("database_type", &**database_type);
-
-            match tmpl.render("snippets.html", &context) {
-                Ok(rendered) => HttpResponse::Ok().content_type("text/html").body(rendered),
-                Err(e) => {
-                    eprintln!("Template error: {:?}", e);
-                    HttpResponse::InternalServerError().body("Template error")
-                }
-            }
+    match tmpl.render("snippets.html", &context) {
+        Ok(rendered) => HttpResponse::Ok().content_type("text/html").body(rendered),
+        Err(e) => {
+            eprintln!("Template error: {:?}", e);
+            HttpResponse::InternalServerError().body("Template error")
         }
+    }
+}
+
+/// Snippets a page at a time, pinned first and then newest first, with the number of the next page while there
+/// are more
+#[get("/api/v1/snippets")]
+async fn list_snippets(
+    database: web::Data<Arc<dyn models::Database>>,
+    query: web::Query<models::SnippetsQuery>,
+) -> impl Responder {
+    let filter = models::ListFilter {
+        tags: query.tags.as_deref().map(models::TagFilter::parse).unwrap_or_default(),
+        url: query
+            .url
+            .as_deref()
+            .map(str::trim)
+            .filter(|url| !url.is_empty())
+            .map(String::from),
+        ..Default::default()
+    };
+    let page = query.page.unwrap_or(1).max(1);
+    match partials::snippet_page(database.get_ref().as_ref(), filter, page).await {
+        Ok((snippets, next_page)) => HttpResponse::Ok().json(json!({
+            "snippets": snippets,
+            "page": page,
+            "next_page": next_page,
+        })),
         Err(err) => {
-            eprintln!("Failed to fetch snippets with tags: {:?}", err);
-            HttpResponse::InternalServerError().body("Failed to fetch snippets with tags")
+            eprintln!("Failed to fetch snippets: {:?}", err);
+            HttpResponse::InternalServerError().json("Failed to fetch snippets")
         }
     }
 }
 
-fn sanitize_snippets(snippets_with_tags: Vec<models::SnippetWithTags>) -> Vec<models::SnippetWithTags> {
+pub(crate) fn sanitize_snippets(snippets_with_tags: Vec<models::SnippetWithTags>) -> Vec<models::SnippetWithTags> {
     snippets_with_tags
         .into_iter()
         .map(|snippet_with_tags| models::SnippetWithTags {
             id: snippet_with_tags.id,
-            snippet: sanitize_with_allowed_tags(&snippet_with_tags.snippet).to_string(),
+            // Snippets read back carry the form sanitized as they were saved
+            snippet: snippet_with_tags
+                .snippet_html
+                .unwrap_or_else(|| models::sanitize_snippet_html(&snippet_with_tags.snippet, None)),
+            snippet_html: None,
             url: sanitize_with_allowed_tags(&snippet_with_tags.url).to_string(),
             tags: snippet_with_tags
                 .tags
                 .into_iter()
                 .map(|tag| sanitize_with_allowed_tags(&tag).to_string())
                 .collect(),
+            pinned: snippet_with_tags.pinned,
+            source: snippet_with_tags
+                .source
+                .and_then(|source| source.sanitized(&snippet_with_tags.url)),
+            image_type: snippet_with_tags.image_type,
         })
         .collect()
 }
 
+/// A new snippet's tags, each once, or a 400 naming the repeats when `REJECT_DUPLICATE_TAGS` is on
+#[allow(clippy::result_large_err)]
+pub(crate) fn snippet_tags<'a>(config: &Config, tags: &[&'a str]) -> Result<Vec<&'a str>, HttpResponse> {
+    let repeated = models::repeated_tags(tags);
+    if config.reject_duplicate_tags && !repeated.is_empty() {
+        return Err(HttpResponse::BadRequest().json(format!("Tags given more than once: {}", repeated.join(", "))));
+    }
+    Ok(models::unique_tags(tags.iter().copied()))
+}
+
 #[post("/snippets")]
 async fn insert_snippet(
     database: web::Data<Arc<dyn models::Database>>,
+    config: web::Data<Config>,
     req: web::Json<models::NewSnippet>,
 ) -> impl Responder {
-    let tags: Vec<&str> = req.tags.split(',').map(|tag| tag.trim()).collect();
+    let tags: Vec<&str> = req.tags.iter().map(String::as_str).collect();
+    let tags = match snippet_tags(&config, &tags) {
+        Ok(tags) => tags,
+        Err(response) => return response,
+    };
+    let source = req.source.clone().sanitized(&req.url);
 
-    match database.insert_snippet(&req.url, &req.snippet, &tags).await {
-        Ok(_) => HttpResponse::Ok().json("Snippet inserted successfully"),
+    match database
+        .insert_snippet(&req.url, &req.snippet, &tags, source.as_ref())
+        .await
+    {
+        Ok(snippet_id) => {
+            log_activity(database.get_ref().as_ref(), "save_snippet", &req.url, None).await;
+            created_snippet(database.get_ref().as_ref(), &config.base_path, snippet_id).await
+        }
         Err(err) => {
             eprintln!("Failed to insert snippet: {:?}", err);
             HttpResponse::InternalServerError().json("Failed to insert snippet")
@@ -244,6 +1147,18 @@ async fn insert_snippet(
     }
 }
 
+#[get("/snippets/{id:\\d+}")]
+async fn get_snippet(database: web::Data<Arc<dyn models::Database>>, path: web::Path<i32>) -> impl Responder {
+    match database.get_snippet(path.into_inner()).await {
+        Ok(Some(snippet)) => HttpResponse::Ok().json(&sanitize_snippets(vec![snippet])[0]),
+        Ok(None) => HttpResponse::NotFound().json("Snippet not found"),
+        Err(err) => {
+            eprintln!("Failed to fetch snippet: {:?}", err);
+            HttpResponse::InternalServerError().json("Failed to fetch snippet")
+        }
+    }
+}
+
 #[post("/snippets/delete")]
 async fn delete_snippet(
     database: web::Data<Arc<dyn models::Database>>,
@@ -255,10 +1170,7 @@ async fn delete_snippet(
 
     match result {
         Ok(_) => {
-            // Call the background job to remove unused tags
-            if let Err(err) = database.remove_unused_tags().await {
-                eprintln!("Failed to remove unused tags: {:?}", err);
-            }
+            log_activity(database.get_ref().as_ref(), "delete_snippet", &req.id.to_string(), None).await;
             HttpResponse::Ok().json("Snippet deleted successfully")
         }
         Err(err) => {
@@ -268,16 +1180,476 @@ async fn delete_snippet(
     }
 }
 
+#[post("/snippets/pin")]
+async fn pin_snippet(
+    database: web::Data<Arc<dyn models::Database>>,
+    req: web::Json<models::PinSnippet>,
+) -> impl Responder {
+    let pinned = req.pinned.unwrap_or(true);
+
+    match database.set_snippet_pinned(req.id, pinned).await {
+        Ok(_) => {
+            let action = if pinned { "pin_snippet" } else { "unpin_snippet" };
+            log_activity(database.get_ref().as_ref(), action, &req.id.to_string(), None).await;
+            HttpResponse::Ok().json(if pinned { "Snippet pinned" } else { "Snippet unpinned" })
+        }
+        Err(sqlx::Error::RowNotFound) => HttpResponse::NotFound().json("Snippet not found"),
+        Err(err) => {
+            eprintln!("Failed to update pinned state: {:?}", err);
+            HttpResponse::InternalServerError().json("Failed to update pinned state")
+        }
+    }
+}
+
+/// The response to a batch that was rolled back, with `error` against the operation at `failed`
+fn batch_rolled_back(names: &[&str], failed: Option<usize>, error: String) -> HttpResponse {
+    let results: Vec<_> = names
+        .iter()
+        .enumerate()
+        .map(|(position, name)| {
+            let status = match failed {
+                Some(failed) if position < failed => "rolled_back",
+                Some(failed) if position == failed => "error",
+                Some(_) => "skipped",
+                None => "rolled_back",
+            };
+            let mut result = json!({ "index": position, "op": name, "status": status });
+            if status == "error" {
+                result["error"] = json!(error);
+            }
+            result
+        })
+        .collect();
+    HttpResponse::UnprocessableEntity().json(json!({ "committed": false, "results": results }))
+}
+
+#[post("/api/v1/batch")]
+async fn batch(database: web::Data<Arc<dyn models::Database>>, req: web::Json<models::BatchRequest>) -> impl Responder {
+    let names: Vec<&str> = req.operations.iter().map(models::BatchOperation::name).collect();
+    let mut operations = Vec::with_capacity(names.len());
+    for (position, operation) in req.into_inner().operations.into_iter().enumerate() {
+        match operation.validate() {
+            Ok(operation) => operations.push(operation),
+            Err(message) => return batch_rolled_back(&names, Some(position), message),
+        }
+    }
+
+    match database.execute_batch(&operations).await {
+        Ok(ids) => {
+            let results: Vec<_> = names
+                .iter()
+                .zip(ids)
+                .enumerate()
+                .map(|(position, (name, id))| json!({ "index": position, "op": name, "status": "ok", "id": id }))
+                .collect();
+            // Each operation is already on the timeline, recorded inside the transaction
+            changes::publish("batch", &format!("{} operations", results.len()), None);
+            HttpResponse::Ok().json(json!({ "committed": true, "results": results }))
+        }
+        Err(err) => {
+            eprintln!("Batch rolled back: {:?}", err);
+            batch_rolled_back(&names, err.index, err.error.to_string())
+        }
+    }
+}
+
+/// Default and maximum number of entries returned by the activity timeline
+const ACTIVITY_DEFAULT_LIMIT: i64 = 100;
+const ACTIVITY_MAX_LIMIT: i64 = 1000;
+
+fn activity_limit(query: &models::ActivityQuery) -> i64 {
+    query
+        .limit
+        .unwrap_or(ACTIVITY_DEFAULT_LIMIT)
+        .clamp(1, ACTIVITY_MAX_LIMIT)
+}
+
+// Polled by the browser extension for its toolbar badge, so the counts come from the cache and may be briefly stale
+#[get("/api/v1/badge")]
+async fn badge(database: web::Data<Arc<dyn models::Database>>) -> impl Responder {
+    match database.get_url_counts().await {
+        Ok(counts) => HttpResponse::Ok()
+            .insert_header(("Cache-Control", "private, max-age=10"))
+            .json(counts),
+        Err(err) => {
+            eprintln!("Failed to count URLs: {:?}", err);
+            HttpResponse::InternalServerError().json("Failed to count URLs")
+        }
+    }
+}
+
+#[get("/api/v1/tags")]
+async fn list_tags(database: web::Data<Arc<dyn models::Database>>) -> impl Responder {
+    let tags = database.get_tag_details().await;
+    let counts = database.get_tag_counts().await;
+    match tags.and_then(|tags| Ok((tags, counts?))) {
+        Ok((tags, counts)) => {
+            let counts: HashMap<String, models::TagCount> =
+                counts.into_iter().map(|count| (count.tag.clone(), count)).collect();
+            let tags: Vec<serde_json::Value> = tags
+                .into_iter()
+                .map(|tag| {
+                    let count = counts.get(&tag.tag);
+                    json!({
+                        "tag": tag.tag,
+                        "color": tag.color,
+                        "description": tag.description,
+                        "urls": count.map_or(0, |count| count.urls),
+                        "snippets": count.map_or(0, |count| count.snippets),
+                    })
+                })
+                .collect();
+            HttpResponse::Ok().json(tags)
+        }
+        Err(err) => {
+            eprintln!("Failed to fetch tags: {:?}", err);
+            HttpResponse::InternalServerError().json("Failed to fetch tags")
+        }
+    }
+}
+
+// `.*` so nested tags such as `lang/rust` can be addressed without encoding the slash
+#[get("/api/v1/tags/{tag:.*}")]
+async fn get_tag(database: web::Data<Arc<dyn models::Database>>, tag: web::Path<String>) -> impl Responder {
+    match database.get_tag(&tag).await {
+        Ok(Some(tag)) => HttpResponse::Ok().json(tag),
+        Ok(None) => HttpResponse::NotFound().json("Tag not found"),
+        Err(err) => {
+            eprintln!("Failed to fetch tag: {:?}", err);
+            HttpResponse::InternalServerError().json("Failed to fetch tag")
+        }
+    }
+}
+
+/// Set a tag's color and description
+#[put("/api/v1/tags/{tag:.*}")]
+async fn update_tag(
+    database: web::Data<Arc<dyn models::Database>>,
+    tag: web::Path<String>,
+    req: web::Json<models::TagDetailsUpdate>,
+) -> impl Responder {
+    let (color, description) = match req.validate() {
+        Ok(details) => details,
+        Err(err) => return HttpResponse::BadRequest().json(err),
+    };
+
+    match database
+        .set_tag_details(&tag, color.as_deref(), description.as_deref())
+        .await
+    {
+        Ok(_) => {
+            log_activity(database.get_ref().as_ref(), "update_tag", &tag, color.as_deref()).await;
+            HttpResponse::Ok().json(models::TagDetails {
+                tag: tag.into_inner(),
+                color,
+                description,
+            })
+        }
+        Err(sqlx::Error::RowNotFound) => HttpResponse::NotFound().json("Tag not found"),
+        Err(err) => {
+            eprintln!("Failed to update tag: {:?}", err);
+            HttpResponse::InternalServerError().json("Failed to update tag")
+        }
+    }
+}
+
+/// Clear a tag's color and description; the tag itself stays on its URLs and snippets
+#[delete("/api/v1/tags/{tag:.*}")]
+async fn clear_tag(database: web::Data<Arc<dyn models::Database>>, tag: web::Path<String>) -> impl Responder {
+    match database.set_tag_details(&tag, None, None).await {
+        Ok(_) => {
+            log_activity(database.get_ref().as_ref(), "clear_tag", &tag, None).await;
+            HttpResponse::Ok().json("Tag details cleared")
+        }
+        Err(sqlx::Error::RowNotFound) => HttpResponse::NotFound().json("Tag not found"),
+        Err(err) => {
+            eprintln!("Failed to clear tag: {:?}", err);
+            HttpResponse::InternalServerError().json("Failed to clear tag")
+        }
+    }
+}
+
+#[get("/activity")]
+async fn activity_page(
+    database: web::Data<Arc<dyn models::Database>>,
+    tmpl: web::Data<Tera>,
+    database_type: web::Data<String>,
+    config: web::Data<Config>,
+    query: web::Query<models::ActivityQuery>,
+) -> impl Responder {
+    match database.get_activity(activity_limit(&query)).await {
+        Ok(activity) => {
+            let mut context = Context::new();
+            context.insert("activity", &activity);
+            context.insert("title", "Activity");
+            context.insert("database_type", &**database_type);
+            context.insert("base_path", &config.base_path);
+
+            match tmpl.render("activity.html", &context) {
+                Ok(rendered) => HttpResponse::Ok().content_type("text/html").body(rendered),
+                Err(e) => {
+                    eprintln!("Template error: {:?}", e);
+                    HttpResponse::InternalServerError().body("Template error")
+                }
+            }
+        }
+        Err(err) => {
+            eprintln!("Failed to fetch activity: {:?}", err);
+            HttpResponse::InternalServerError().body("Failed to fetch activity")
+        }
+    }
+}
+
+#[get("/api/v1/activity")]
+async fn list_activity(
+    database: web::Data<Arc<dyn models::Database>>,
+    query: web::Query<models::ActivityQuery>,
+) -> impl Responder {
+    match database.get_activity(activity_limit(&query)).await {
+        Ok(activity) => HttpResponse::Ok().json(activity),
+        Err(err) => {
+            eprintln!("Failed to fetch activity: {:?}", err);
+            HttpResponse::InternalServerError().json("Failed to fetch activity")
+        }
+    }
+}
+
+/// Every saved URL with its tags and dates, as written to the exports
+pub(crate) async fn exported_urls(database: &dyn models::Database) -> Result<Vec<models::ExportedUrl>, sqlx::Error> {
+    let everything = models::ListFilter::default();
+    let mut tags: HashMap<String, Vec<String>> = database
+        .get_urls_with_tags(&everything)
+        .await?
+        .into_iter()
+        .map(|url| (url.url, url.tags))
+        .collect();
+    let urls = database
+        .get_all_urls(&everything)
+        .await?
+        .into_iter()
+        .map(|url| models::ExportedUrl {
+            tags: tags.remove(&url.url).unwrap_or_default(),
+            url: url.url,
+            url_hash: url.url_hash,
+            datetime: url.datetime,
+            read_at: url.read_at,
+        })
+        .collect();
+
+    Ok(urls)
+}
+
+/// Every saved URL with all that is stored about it, snapshots listed but not included
+async fn account_urls(database: &dyn models::Database) -> Result<Vec<models::AccountUrl>, sqlx::Error> {
+    // Only these need their snapshots looked up
+    let snapshotted: HashSet<i32> = database
+        .get_snapshotted_urls()
+        .await?
+        .into_iter()
+        .map(|url| url.id)
+        .collect();
+
+    let mut urls = Vec::new();
+    for url in database.get_urls_with_tags(&models::ListFilter::default()).await? {
+        let snapshots = if snapshotted.contains(&url.id) {
+            database.get_snapshots(url.id).await?
+        } else {
+            Vec::new()
+        };
+        urls.push(models::AccountUrl {
+            url: models::ExportedUrl {
+                url: url.url,
+                url_hash: url.url_hash,
+                datetime: url.datetime,
+                read_at: url.read_at,
+                tags: url.tags,
+            },
+            title: url.title,
+            notes: url.notes,
+            pinned: url.pinned,
+            progress: url.progress,
+            archived_at: url.archived_at,
+            snoozed_until: url.snoozed_until,
+            snapshots,
+        });
+    }
+    Ok(urls)
+}
+
+/// Assemble the whole library into a single export document
+pub(crate) async fn build_account_export(
+    database: &dyn models::Database,
+) -> Result<models::AccountExport, sqlx::Error> {
+    let everything = models::ListFilter::default();
+
+    Ok(models::AccountExport {
+        exported_at: chrono::Utc::now().naive_utc(),
+        urls: account_urls(database).await?,
+        snippets: database.get_snippets_with_tags(&everything).await?,
+        tags: database.get_all_tags().await?,
+        activity: database.get_activity(i64::MAX).await?,
+    })
+}
+
+#[get("/account/export")]
+async fn export_account(database: web::Data<Arc<dyn models::Database>>) -> impl Responder {
+    match build_account_export(database.get_ref().as_ref()).await {
+        Ok(export) => {
+            let filename = format!("read_it_later-export-{}.json", export.exported_at.format("%Y%m%d"));
+            HttpResponse::Ok()
+                .insert_header(("Content-Disposition", format!("attachment; filename=\"{}\"", filename)))
+                .json(export)
+        }
+        Err(err) => {
+            eprintln!("Failed to export account: {:?}", err);
+            HttpResponse::InternalServerError().json("Failed to export account")
+        }
+    }
+}
+
+#[delete("/account")]
+async fn delete_account(database: web::Data<Arc<dyn models::Database>>) -> impl Responder {
+    match database.delete_all_data().await {
+        Ok(_) => {
+            snapshot_cache::clear().await;
+            HttpResponse::Ok().json("Account data deleted successfully")
+        }
+        Err(err) => {
+            eprintln!("Failed to delete account data: {:?}", err);
+            HttpResponse::InternalServerError().json("Failed to delete account data")
+        }
+    }
+}
+
 pub fn configure_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(index)
+        .service(list_memories)
         .service(tags_page)
         .service(snippets_page)
+        .service(list_snippets)
+        .service(inbox_page)
         .service(health)
         .service(list_urls)
+        .service(url_exists)
+        .service(get_url)
+        .service(update_url)
+        .service(move_url)
+        .service(get_url_metadata)
         .service(insert_record)
+        .service(share_target)
         .service(insert_tags)
+        .service(set_url_tags)
         .service(list_urls_with_tags)
+        .service(list_untagged_urls)
+        .service(random_url)
+        .service(next_unread_url)
+        .service(mark_read_and_next)
+        .service(mark_url_read)
+        .service(pin_url)
+        .service(short_link)
+        .service(url_qr_code)
+        .service(reader_page)
+        .service(set_url_progress)
+        .service(list_untagged_snippets)
         .service(delete_record_by_url)
         .service(insert_snippet)
-        .service(delete_snippet);
+        .service(get_snippet)
+        .service(delete_snippet)
+        .service(pin_snippet)
+        .service(batch)
+        .service(badge)
+        .service(list_tags)
+        .service(get_tag)
+        .service(update_tag)
+        .service(clear_tag)
+        .service(activity_page)
+        .service(list_activity)
+        .service(export_account)
+        .service(delete_account);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shared(title: Option<&str>, text: Option<&str>, url: Option<&str>) -> models::SharedContent {
+        models::SharedContent {
+            title: title.map(String::from),
+            text: text.map(String::from),
+            url: url.map(String::from),
+        }
+    }
+
+    #[test]
+    fn test_extract_shared_link_prefers_url_field() {
+        let content = shared(
+            Some("Title"),
+            Some("see https://other.com"),
+            Some("https://example.com"),
+        );
+        assert_eq!(extract_shared_link(&content), Some("https://example.com".to_string()));
+    }
+
+    #[test]
+    fn test_extract_shared_link_from_text() {
+        let content = shared(Some("Title"), Some("Great read: https://example.com/post."), Some(""));
+        assert_eq!(
+            extract_shared_link(&content),
+            Some("https://example.com/post".to_string())
+        );
+    }
+
+    #[test]
+    fn test_extract_shared_link_missing() {
+        let content = shared(Some("Just a title"), Some("no links here"), None);
+        assert_eq!(extract_shared_link(&content), None);
+    }
+
+    #[tokio::test]
+    async fn test_account_export_carries_everything_set_on_a_url() {
+        use crate::services::models::Database;
+        let database = crate::services::sqlite_database::SqliteDatabase::new(":memory:")
+            .await
+            .unwrap();
+        database.initialize().await.unwrap();
+
+        let url_id = database.insert_url("https://example.com/a").await.unwrap();
+        database.insert_url("https://example.com/b").await.unwrap();
+        database.insert_tags("https://example.com/a", &["rust"]).await.unwrap();
+        let update = models::UrlUpdate {
+            title: Some(Some("A title".to_string())),
+            notes: Some(Some("A note".to_string())),
+            archived: Some(true),
+            ..Default::default()
+        };
+        database.update_url(url_id, &update).await.unwrap();
+        database.set_url_pinned("https://example.com/a", true).await.unwrap();
+        let snapshot_id = database
+            .insert_snapshot(url_id, Some("text/html"), "<p>page</p>", false, 10)
+            .await
+            .unwrap()
+            .unwrap();
+
+        let export = build_account_export(&database).await.unwrap();
+        let saved = export
+            .urls
+            .iter()
+            .find(|url| url.url.url == "https://example.com/a")
+            .unwrap();
+        assert_eq!(saved.url.tags, vec!["rust"]);
+        assert_eq!(saved.title.as_deref(), Some("A title"));
+        assert_eq!(saved.notes.as_deref(), Some("A note"));
+        assert!(saved.pinned);
+        assert!(saved.archived_at.is_some());
+        let snapshots: Vec<i32> = saved.snapshots.iter().map(|snapshot| snapshot.id).collect();
+        assert_eq!(snapshots, vec![snapshot_id]);
+
+        let other = export
+            .urls
+            .iter()
+            .find(|url| url.url.url == "https://example.com/b")
+            .unwrap();
+        assert!(other.snapshots.is_empty());
+        assert!(other.url.tags.is_empty());
+    }
 }
@@ -0,0 +1,214 @@
+//! Podcast episodes and other audio, found from the saved URL itself or the page it points at
+
+use crate::services::{fetcher, html, metadata, models};
+use reqwest::Url;
+use std::time::Duration;
+
+/// Give up on a page that takes longer than this to answer
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How much of an episode page or feed is searched; the tags that matter come early
+const PAGE_LIMIT: usize = 1024 * 1024;
+
+/// File extensions served as audio, with the MIME type to assume when the server doesn't say
+const AUDIO_EXTENSIONS: [(&str, &str); 8] = [
+    ("mp3", "audio/mpeg"),
+    ("m4a", "audio/mp4"),
+    ("aac", "audio/aac"),
+    ("ogg", "audio/ogg"),
+    ("oga", "audio/ogg"),
+    ("opus", "audio/opus"),
+    ("flac", "audio/flac"),
+    ("wav", "audio/wav"),
+];
+
+/// The audio behind a URL: the URL itself when it serves an audio file, otherwise the file an episode
+/// page or podcast feed offers. `None` means it isn't audio.
+///
+/// An audio file is recognised from its headers alone, so saving a long episode doesn't download it.
+pub async fn look_up(url: &str) -> Result<Option<models::AudioMetadata>, String> {
+    let mut response = fetcher::page(url, FETCH_TIMEOUT).await?.error_for_status()?;
+
+    let mime_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.split(';').next().unwrap_or(value).trim().to_ascii_lowercase());
+    let guessed = extension_mime_type(response.url().path());
+
+    let served_as_audio = mime_type.as_deref().is_some_and(|mime| mime.starts_with("audio/"));
+    let untyped = mime_type
+        .as_deref()
+        .is_none_or(|mime| mime == "application/octet-stream");
+    if served_as_audio || (untyped && guessed.is_some()) {
+        return Ok(Some(models::AudioMetadata {
+            audio_url: url.to_string(),
+            mime_type: if served_as_audio {
+                mime_type
+            } else {
+                guessed.map(String::from)
+            },
+            length: response.content_length(),
+            duration: None,
+            title: None,
+        }));
+    }
+
+    if !mime_type
+        .as_deref()
+        .is_none_or(|mime| mime.contains("html") || mime.contains("xml"))
+    {
+        return Ok(None);
+    }
+
+    let mut body = Vec::new();
+    while body.len() < PAGE_LIMIT {
+        match response.chunk().await.map_err(|err| err.to_string())? {
+            Some(chunk) => body.extend_from_slice(&chunk),
+            None => break,
+        }
+    }
+    body.truncate(PAGE_LIMIT);
+    let page_url = response.url().to_string();
+    Ok(find(&String::from_utf8_lossy(&body), &page_url))
+}
+
+/// The episode an HTML page or RSS feed offers: a feed's first `<enclosure>`, else `og:audio`, else an
+/// `<audio>` or `<source>` element. Relative links are resolved against `page_url`.
+pub fn find(page: &str, page_url: &str) -> Option<models::AudioMetadata> {
+    let base = Url::parse(page_url).ok()?;
+    let absolute = |link: &str| {
+        base.join(link.trim())
+            .ok()
+            .filter(|url| matches!(url.scheme(), "http" | "https"))
+            .map(String::from)
+    };
+
+    let (mut enclosure, mut og_audio, mut element) = (None, None, None);
+    let (mut og_type, mut title, mut duration) = (None, None, None);
+    for (name, attributes) in html::tags(page) {
+        let attribute = |key: &str| html::attribute(&attributes, key);
+        let audio_type = attribute("type").filter(|mime| mime.starts_with("audio/"));
+        match name.as_str() {
+            "enclosure" if enclosure.is_none() => {
+                let Some(link) = attribute("url").and_then(absolute) else {
+                    continue;
+                };
+                if audio_type.is_some() || extension_mime_type(&link).is_some() {
+                    let length = attribute("length").and_then(|length| length.parse().ok());
+                    enclosure = Some((link, audio_type.map(String::from), length));
+                }
+            }
+            "audio" | "source" if element.is_none() => {
+                let link = attribute("src").and_then(absolute);
+                if name == "audio" || audio_type.is_some() || link.as_deref().and_then(extension_mime_type).is_some() {
+                    element = link.map(|link| (link, audio_type.map(String::from)));
+                }
+            }
+            "meta" => {
+                let content = attribute("content").unwrap_or_default();
+                match attribute("property").or(attribute("name")).or(attribute("itemprop")) {
+                    Some("og:audio" | "og:audio:url" | "og:audio:secure_url") if og_audio.is_none() => {
+                        og_audio = absolute(content);
+                    }
+                    Some("og:audio:type") => og_type = Some(content.to_string()),
+                    Some("og:title") if title.is_none() => title = Some(content.to_string()),
+                    Some("duration") => duration = duration.or_else(|| metadata::parse_iso_duration(content)),
+                    Some("music:duration") => duration = duration.or_else(|| content.trim().parse().ok()),
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // A feed gives the episode length as `<itunes:duration>1:02:03</itunes:duration>` or plain seconds
+    let duration = duration.or_else(|| {
+        let rest = page.split_once("<itunes:duration>")?.1;
+        parse_clock_duration(&rest[..rest.find('<')?])
+    });
+
+    let (audio_url, mime_type, length) = enclosure
+        .or_else(|| og_audio.map(|link| (link, og_type, None)))
+        .or_else(|| element.map(|(link, mime)| (link, mime, None)))?;
+    Some(models::AudioMetadata {
+        mime_type: mime_type.or_else(|| extension_mime_type(&audio_url).map(String::from)),
+        audio_url,
+        length,
+        duration,
+        title,
+    })
+}
+
+/// The MIME type a path's extension implies, if it is an audio extension
+pub fn extension_mime_type(path: &str) -> Option<&'static str> {
+    let path = path.split(['?', '#']).next().unwrap_or(path);
+    let (_, extension) = path.rsplit_once('.')?;
+    AUDIO_EXTENSIONS
+        .iter()
+        .find(|(known, _)| extension.eq_ignore_ascii_case(known))
+        .map(|(_, mime)| *mime)
+}
+
+/// Seconds in `h:mm:ss`, `m:ss` or a plain number of seconds
+fn parse_clock_duration(value: &str) -> Option<u64> {
+    value
+        .trim()
+        .split(':')
+        .try_fold(0u64, |total, part| Some(total * 60 + part.parse::<u64>().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_episode_in_a_feed_or_page() {
+        let feed = r#"<?xml version="1.0"?><rss><channel><item><title>Episode 12</title>
+            <enclosure url="https://cdn.example/ep12.mp3?src=rss" length="31457280" type="audio/mpeg"/>
+            <itunes:duration>1:02:03</itunes:duration></item>
+            <item><enclosure url="https://cdn.example/ep11.mp3" type="audio/mpeg"/></item></channel></rss>"#;
+        let episode = find(feed, "https://podcast.example/feed.xml").unwrap();
+        assert_eq!(episode.audio_url, "https://cdn.example/ep12.mp3?src=rss");
+        assert_eq!(episode.mime_type.as_deref(), Some("audio/mpeg"));
+        assert_eq!(episode.length, Some(31_457_280));
+        assert_eq!(episode.duration, Some(3723));
+
+        let page = r#"<head><meta property="og:title" content="Episode 12">
+            <meta property="og:audio" content="/files/ep12.m4a"><meta itemprop="duration" content="PT45M">
+            </head><body><audio controls src="/player/ep12.ogg"></audio></body>"#;
+        let episode = find(page, "https://podcast.example/episodes/12").unwrap();
+        assert_eq!(episode.audio_url, "https://podcast.example/files/ep12.m4a");
+        assert_eq!(episode.mime_type.as_deref(), Some("audio/mp4"));
+        assert_eq!(episode.duration, Some(2700));
+        assert_eq!(episode.title.as_deref(), Some("Episode 12"));
+
+        let player = r#"<audio controls><source src="ep12.opus" type="audio/ogg; codecs=opus"></audio>"#;
+        assert_eq!(
+            find(player, "https://podcast.example/episodes/12").unwrap().audio_url,
+            "https://podcast.example/episodes/ep12.opus"
+        );
+    }
+
+    #[test]
+    fn ignores_pages_without_audio() {
+        for page in [
+            "<p>Just an article</p>",
+            r#"<video><source src="clip.mp4" type="video/mp4"></video>"#,
+            r#"<enclosure url="https://cdn.example/cover.jpg" type="image/jpeg"/>"#,
+            r#"<meta property="og:audio" content="javascript:alert(1)">"#,
+        ] {
+            assert_eq!(find(page, "https://example.com/post"), None, "{}", page);
+        }
+    }
+
+    #[test]
+    fn parses_durations_and_extensions() {
+        assert_eq!(parse_clock_duration("1:02:03"), Some(3723));
+        assert_eq!(parse_clock_duration("45:10"), Some(2710));
+        assert_eq!(parse_clock_duration("3600"), Some(3600));
+        assert_eq!(parse_clock_duration("about an hour"), None);
+        assert_eq!(extension_mime_type("/ep/12.MP3?dl=1"), Some("audio/mpeg"));
+        assert_eq!(extension_mime_type("/ep/12.html"), None);
+    }
+}
@@ -0,0 +1,290 @@
+//! The daily briefing: everything saved over the last day on one page, each with a summary and reading time worked
+//! out from its latest snapshot, prepared on a schedule and optionally sent by email
+
+use crate::config::{self, Config};
+use crate::services::{api, html, models, snapshot_cache, snapshots};
+use actix_web::{get, post, web, HttpResponse, Responder};
+use chrono::{Days, NaiveDateTime};
+use serde::Serialize;
+use std::process::Stdio;
+use std::sync::Arc;
+use tera::{Context, Tera};
+use tokio::io::AsyncWriteExt;
+
+/// Reading speed the reading times assume
+const WORDS_PER_MINUTE: usize = 230;
+
+/// Longest summary, in characters; longer ones are cut at a word
+const SUMMARY_LIMIT: usize = 300;
+
+/// A line of page text shorter than this is a heading or menu rather than the start of the article
+const SUMMARY_MIN_WORDS: usize = 12;
+
+#[derive(Serialize, Debug)]
+pub struct Item {
+    pub url: models::UrlWithTags,
+    pub summary: Option<String>,
+    /// Minutes to read the page, when there is a snapshot of it to count
+    pub minutes: Option<usize>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Briefing {
+    /// Saves from this moment on are included, in UTC
+    pub since: NaiveDateTime,
+    /// Newest first
+    pub items: Vec<Item>,
+    pub total_minutes: usize,
+}
+
+fn shorten(text: &str) -> String {
+    if text.chars().count() <= SUMMARY_LIMIT {
+        return text.to_string();
+    }
+    let cut: String = text.chars().take(SUMMARY_LIMIT).collect();
+    let cut = cut.rsplit_once(' ').map_or(cut.as_str(), |(kept, _)| kept);
+    format!("{}…", cut.trim_end_matches([',', ';', ':', '.', ' ']))
+}
+
+/// A page's own description of itself, or else its first line of text that reads like a sentence
+pub fn summary(page: &str) -> Option<String> {
+    let described = html::tags(page)
+        .take_while(|(name, _)| name != "body")
+        .find_map(|(name, attributes)| {
+            let property = html::attribute(&attributes, "name").or(html::attribute(&attributes, "property"));
+            (name == "meta" && matches!(property, Some("description" | "og:description")))
+                .then(|| html::attribute(&attributes, "content").map(snapshots::page_text))
+                .flatten()
+        })
+        .map(|description| description.trim().to_string())
+        .filter(|description| !description.is_empty());
+
+    described
+        .or_else(|| {
+            snapshots::page_text(page)
+                .lines()
+                .find(|line| line.split_whitespace().count() >= SUMMARY_MIN_WORDS)
+                .map(str::to_string)
+        })
+        .map(|summary| shorten(&summary))
+}
+
+/// Whole minutes to read `text`, at least one
+pub fn reading_minutes(text: &str) -> usize {
+    text.split_whitespace().count().div_ceil(WORDS_PER_MINUTE).max(1)
+}
+
+/// The summary and reading time from a URL's latest snapshot, if it has one of a web page
+async fn brief(database: &dyn models::Database, url: models::UrlWithTags) -> Result<Item, sqlx::Error> {
+    let latest = database.get_snapshots(url.id).await?.into_iter().find(|snapshot| {
+        snapshot
+            .content_type
+            .as_deref()
+            .is_none_or(|content_type| content_type.starts_with("text/"))
+    });
+    let content = match latest {
+        Some(snapshot) => snapshot_cache::get(database, url.id, snapshot.id).await?,
+        None => None,
+    };
+
+    Ok(match content {
+        Some(content) => Item {
+            summary: summary(&content.content),
+            minutes: Some(reading_minutes(&snapshots::page_text(&content.content))),
+            url,
+        },
+        None => Item {
+            url,
+            summary: None,
+            minutes: None,
+        },
+    })
+}
+
+/// The briefing of URLs saved in the day up to `now`
+pub async fn build(database: &dyn models::Database, now: NaiveDateTime) -> Result<Briefing, sqlx::Error> {
+    let since = now - Days::new(1);
+    let filter = models::ListFilter {
+        saved_after: Some(since),
+        ..Default::default()
+    };
+
+    let mut items = Vec::new();
+    for url in database.get_urls_with_tags(&filter).await? {
+        items.push(brief(database, url).await?);
+    }
+    Ok(Briefing {
+        since,
+        total_minutes: items.iter().filter_map(|item| item.minutes).sum(),
+        items,
+    })
+}
+
+async fn render(
+    database: &dyn models::Database,
+    tmpl: &Tera,
+    template: &str,
+    briefing: &Briefing,
+    config: &Config,
+) -> Result<String, tera::Error> {
+    let mut context = Context::new();
+    context.insert("briefing", briefing);
+    context.insert("tag_details", &api::tag_details_by_name(database).await);
+    context.insert("title", "Briefing");
+    context.insert("database_type", &config.database_type);
+    context.insert("base_path", &config.base_path);
+    tmpl.render(template, &context)
+}
+
+/// Hand the briefing to the mail program as an HTML message
+async fn send(settings: &config::Briefing, to: &str, body: &str) -> Result<(), String> {
+    if to.contains(['\r', '\n']) {
+        return Err("BRIEFING_EMAIL must be a single address".to_string());
+    }
+    let message = format!(
+        "To: {}\r\nSubject: Read it Later briefing for {}\r\nMIME-Version: 1.0\r\n\
+         Content-Type: text/html; charset=utf-8\r\n\r\n{}",
+        to,
+        chrono::Utc::now().format("%Y-%m-%d"),
+        body
+    );
+
+    let mut child = tokio::process::Command::new(&settings.sendmail)
+        .args(["-t", "-i"])
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|err| format!("Failed to run {}: {}", settings.sendmail, err))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(message.as_bytes())
+            .await
+            .map_err(|err| err.to_string())?;
+    }
+    let status = child.wait().await.map_err(|err| err.to_string())?;
+    if !status.success() {
+        return Err(format!("{} exited with {}", settings.sendmail, status));
+    }
+    Ok(())
+}
+
+/// Snapshot the day's saves that have no snapshot yet, so they have summaries, then email the briefing if
+/// `BRIEFING_EMAIL` is set; for the scheduler
+pub async fn prepare(database: &dyn models::Database, config: &Config, tmpl: &Tera) -> Result<String, String> {
+    let now = chrono::Utc::now().naive_utc();
+    let briefing = build(database, now).await.map_err(|err| err.to_string())?;
+
+    let mut snapshotted = 0;
+    for item in briefing.items.iter().filter(|item| item.minutes.is_none()) {
+        let url = match database.get_url_by_short_id(&item.url.short_id).await {
+            Ok(Some(url)) => url,
+            Ok(None) => continue,
+            Err(err) => return Err(err.to_string()),
+        };
+        // A snapshot that isn't a web page has nothing to summarise, so taking another wouldn't help
+        if !database
+            .get_snapshots(url.id)
+            .await
+            .map_err(|err| err.to_string())?
+            .is_empty()
+        {
+            continue;
+        }
+        match snapshots::take(database, &url, &config.snapshots).await {
+            Ok(_) => snapshotted += 1,
+            Err(err) => eprintln!("Failed to snapshot {} for the briefing: {}", url.url, err),
+        }
+    }
+    let briefing = if snapshotted > 0 {
+        build(database, now).await.map_err(|err| err.to_string())?
+    } else {
+        briefing
+    };
+
+    let mut outcome = format!("{} URLs, {} snapshotted", briefing.items.len(), snapshotted);
+    if let Some(to) = config.briefing.email.as_deref().filter(|_| !briefing.items.is_empty()) {
+        let body = render(database, tmpl, "briefing_email.html", &briefing, config)
+            .await
+            .map_err(|err| err.to_string())?;
+        send(&config.briefing, to, &body).await?;
+        outcome.push_str(&format!(", emailed to {}", to));
+    }
+    Ok(outcome)
+}
+
+#[get("/briefing")]
+async fn briefing_page(
+    database: web::Data<Arc<dyn models::Database>>,
+    tmpl: web::Data<Tera>,
+    config: web::Data<Config>,
+) -> impl Responder {
+    let briefing = match build(database.get_ref().as_ref(), chrono::Utc::now().naive_utc()).await {
+        Ok(briefing) => briefing,
+        Err(err) => {
+            eprintln!("Failed to build the briefing: {:?}", err);
+            return HttpResponse::InternalServerError().body("Failed to build the briefing");
+        }
+    };
+
+    match render(database.get_ref().as_ref(), &tmpl, "briefing.html", &briefing, &config).await {
+        Ok(rendered) => HttpResponse::Ok().content_type("text/html").body(rendered),
+        Err(e) => {
+            eprintln!("Template error: {:?}", e);
+            HttpResponse::InternalServerError().body("Template error")
+        }
+    }
+}
+
+/// Prepare and send the briefing now, as the scheduler would
+#[post("/briefing/prepare")]
+async fn prepare_now(
+    database: web::Data<Arc<dyn models::Database>>,
+    tmpl: web::Data<Tera>,
+    config: web::Data<Config>,
+) -> impl Responder {
+    match prepare(database.get_ref().as_ref(), &config, &tmpl).await {
+        Ok(outcome) => HttpResponse::Ok().json(outcome),
+        Err(err) => {
+            eprintln!("Failed to prepare the briefing: {}", err);
+            HttpResponse::InternalServerError().json("Failed to prepare the briefing")
+        }
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(briefing_page).service(prepare_now);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summaries_prefer_the_page_description() {
+        let page = r#"<html><head><meta name="description" content="Why Rust &amp; friends"></head>
+            <body><p>Some long opening paragraph that goes on for well over twelve words in total here.</p></body></html>"#;
+        assert_eq!(summary(page).as_deref(), Some("Why Rust & friends"));
+
+        let page = "<html><body><nav>Home About</nav><h1>A title</h1>\
+            <p>Some long opening paragraph that goes on for well over twelve words in total here.</p></body></html>";
+        assert_eq!(
+            summary(page).as_deref(),
+            Some("Some long opening paragraph that goes on for well over twelve words in total here.")
+        );
+        assert_eq!(summary("<p>Too short</p>"), None);
+    }
+
+    #[test]
+    fn long_summaries_are_cut_at_a_word() {
+        let long = format!("<p>{}</p>", "word ".repeat(100));
+        let cut = summary(&long).unwrap();
+        assert!(cut.chars().count() <= SUMMARY_LIMIT + 1);
+        assert!(cut.ends_with("word…"));
+    }
+
+    #[test]
+    fn reading_time_rounds_up() {
+        assert_eq!(reading_minutes(""), 1);
+        assert_eq!(reading_minutes(&"word ".repeat(WORDS_PER_MINUTE)), 1);
+        assert_eq!(reading_minutes(&"word ".repeat(WORDS_PER_MINUTE + 1)), 2);
+    }
+}
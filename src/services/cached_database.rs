@@ -0,0 +1,629 @@
+//! An in-process cache in front of the database for aggregate queries that read every tag, URL and snippet
+
+use crate::services::models;
+use futures_util::stream::BoxStream;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Wraps a database, answering `get_tags_with_urls_and_snippets` and `get_url_counts` from memory until a write
+/// could change them.
+///
+/// Each write is passed through first and the cached result is dropped once it finishes, so this process never
+/// serves a result from before its own write. Writes made by other processes sharing the database are only seen
+/// once `ttl` runs out.
+pub struct CachedDatabase {
+    inner: Arc<dyn models::Database>,
+    ttl: Duration,
+    tags: Aggregate<Vec<models::TagWithUrlsAndSnippets>>,
+    counts: Aggregate<models::UrlCounts>,
+}
+
+impl CachedDatabase {
+    pub fn new(inner: Arc<dyn models::Database>, ttl: Duration) -> Self {
+        Self {
+            inner,
+            ttl,
+            tags: Aggregate::default(),
+            counts: Aggregate::default(),
+        }
+    }
+}
+
+/// One cached query result. Every write bumps the generation, so a result read while a write was in flight
+/// is thrown away instead of being stored.
+struct Aggregate<T> {
+    generation: AtomicU64,
+    entry: Mutex<Option<Entry<T>>>,
+}
+
+struct Entry<T> {
+    generation: u64,
+    stored_at: Instant,
+    value: T,
+}
+
+impl<T> Default for Aggregate<T> {
+    fn default() -> Self {
+        Self {
+            generation: AtomicU64::new(0),
+            entry: Mutex::new(None),
+        }
+    }
+}
+
+impl<T: Clone> Aggregate<T> {
+    /// Taken before running the query, to hand back to `store`
+    fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    fn get(&self, ttl: Duration) -> Option<T> {
+        let entry = self.entry.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        entry
+            .as_ref()
+            .filter(|entry| entry.generation == self.generation() && entry.stored_at.elapsed() < ttl)
+            .map(|entry| entry.value.clone())
+    }
+
+    /// Keep a result, unless a write has happened since `generation` was taken
+    fn store(&self, generation: u64, value: T) {
+        let mut entry = self.entry.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if generation == self.generation() {
+            *entry = Some(Entry {
+                generation,
+                stored_at: Instant::now(),
+                value,
+            });
+        }
+    }
+
+    fn invalidate(&self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        *self.entry.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = None;
+    }
+}
+
+#[async_trait::async_trait]
+impl models::Database for CachedDatabase {
+    async fn initialize(&self) -> Result<(), sqlx::Error> {
+        self.inner.initialize().await
+    }
+
+    async fn check_health(&self) -> &'static str {
+        self.inner.check_health().await
+    }
+
+    async fn insert_url(&self, url: &str) -> Result<i32, sqlx::Error> {
+        let result = self.inner.insert_url(url).await;
+        self.tags.invalidate();
+        self.counts.invalidate();
+        result
+    }
+
+    async fn url_exists(&self, url: &str) -> Result<bool, sqlx::Error> {
+        self.inner.url_exists(url).await
+    }
+
+    async fn get_url_counts(&self) -> Result<models::UrlCounts, sqlx::Error> {
+        let generation = self.counts.generation();
+        if let Some(counts) = self.counts.get(self.ttl) {
+            return Ok(counts);
+        }
+        let counts = self.inner.get_url_counts().await?;
+        self.counts.store(generation, counts);
+        Ok(counts)
+    }
+
+    async fn get_url_with_tags(&self, url: &str) -> Result<Option<models::UrlWithTags>, sqlx::Error> {
+        self.inner.get_url_with_tags(url).await
+    }
+
+    async fn get_urls_with_tags(&self, filter: &models::ListFilter) -> Result<Vec<models::UrlWithTags>, sqlx::Error> {
+        self.inner.get_urls_with_tags(filter).await
+    }
+
+    async fn get_listing_fingerprint(
+        &self,
+        filter: &models::ListFilter,
+    ) -> Result<models::ListingFingerprint, sqlx::Error> {
+        self.inner.get_listing_fingerprint(filter).await
+    }
+
+    async fn get_all_urls(&self, filter: &models::ListFilter) -> Result<Vec<models::Url>, sqlx::Error> {
+        self.inner.get_all_urls(filter).await
+    }
+
+    fn stream_exported_urls(&self) -> BoxStream<'_, Result<models::ExportedUrl, sqlx::Error>> {
+        self.inner.stream_exported_urls()
+    }
+
+    async fn get_loose_url_matches(&self, url: &str) -> Result<Vec<String>, sqlx::Error> {
+        self.inner.get_loose_url_matches(url).await
+    }
+
+    async fn delete_url_by_url(&self, url: &str) -> Result<(), sqlx::Error> {
+        let result = self.inner.delete_url_by_url(url).await;
+        self.tags.invalidate();
+        self.counts.invalidate();
+        result
+    }
+
+    async fn get_random_url(&self, filter: &models::ListFilter) -> Result<Option<models::Url>, sqlx::Error> {
+        self.inner.get_random_url(filter).await
+    }
+
+    async fn get_next_url(&self, filter: &models::ListFilter) -> Result<Option<models::Url>, sqlx::Error> {
+        self.inner.get_next_url(filter).await
+    }
+
+    async fn get_url_by_short_id(&self, short_id: &str) -> Result<Option<models::Url>, sqlx::Error> {
+        self.inner.get_url_by_short_id(short_id).await
+    }
+
+    async fn set_url_read(&self, url: &str, read: bool) -> Result<(), sqlx::Error> {
+        let result = self.inner.set_url_read(url, read).await;
+        self.counts.invalidate();
+        result
+    }
+
+    async fn set_url_pinned(&self, url: &str, pinned: bool) -> Result<(), sqlx::Error> {
+        self.inner.set_url_pinned(url, pinned).await
+    }
+
+    async fn set_url_progress(&self, url: &str, progress: i32) -> Result<(), sqlx::Error> {
+        self.inner.set_url_progress(url, progress).await
+    }
+
+    async fn set_url_metadata(&self, url: &str, metadata: &models::UrlMetadata) -> Result<(), sqlx::Error> {
+        self.inner.set_url_metadata(url, metadata).await
+    }
+
+    async fn update_url(&self, url_id: i32, update: &models::UrlUpdate) -> Result<(), sqlx::Error> {
+        let result = self.inner.update_url(url_id, update).await;
+        self.tags.invalidate();
+        self.counts.invalidate();
+        result
+    }
+
+    async fn insert_tags(&self, url: &str, tags: &[&str]) -> Result<(), sqlx::Error> {
+        let result = self.inner.insert_tags(url, tags).await;
+        self.tags.invalidate();
+        self.counts.invalidate();
+        result
+    }
+
+    async fn set_url_tags(&self, url: &str, tags: &[&str]) -> Result<(), sqlx::Error> {
+        let result = self.inner.set_url_tags(url, tags).await;
+        self.tags.invalidate();
+        self.counts.invalidate();
+        result
+    }
+
+    async fn insert_snippet(
+        &self,
+        url: &str,
+        snippet: &str,
+        tags: &[&str],
+        source: Option<&models::SnippetSource>,
+    ) -> Result<i32, sqlx::Error> {
+        let result = self.inner.insert_snippet(url, snippet, tags, source).await;
+        self.tags.invalidate();
+        result
+    }
+
+    async fn insert_image_snippet(
+        &self,
+        url: &str,
+        caption: &str,
+        tags: &[&str],
+        image: &models::SnippetImage,
+    ) -> Result<i32, sqlx::Error> {
+        let result = self.inner.insert_image_snippet(url, caption, tags, image).await;
+        self.tags.invalidate();
+        result
+    }
+
+    async fn get_snippet_image(&self, snippet_id: i32) -> Result<Option<models::SnippetImage>, sqlx::Error> {
+        self.inner.get_snippet_image(snippet_id).await
+    }
+
+    async fn delete_snippet(&self, snippet_id: i32) -> Result<(), sqlx::Error> {
+        let result = self.inner.delete_snippet(snippet_id).await;
+        self.tags.invalidate();
+        result
+    }
+
+    async fn set_snippet_pinned(&self, snippet_id: i32, pinned: bool) -> Result<(), sqlx::Error> {
+        let result = self.inner.set_snippet_pinned(snippet_id, pinned).await;
+        self.tags.invalidate();
+        result
+    }
+
+    async fn get_snippets_with_tags(
+        &self,
+        filter: &models::ListFilter,
+    ) -> Result<Vec<models::SnippetWithTags>, sqlx::Error> {
+        self.inner.get_snippets_with_tags(filter).await
+    }
+
+    async fn get_snippets_for_url(&self, url: &str) -> Result<Vec<models::SnippetWithTags>, sqlx::Error> {
+        self.inner.get_snippets_for_url(url).await
+    }
+
+    async fn get_snippet(&self, snippet_id: i32) -> Result<Option<models::SnippetWithTags>, sqlx::Error> {
+        self.inner.get_snippet(snippet_id).await
+    }
+
+    async fn get_snippets_saved_before(
+        &self,
+        cutoff: chrono::NaiveDateTime,
+    ) -> Result<Vec<models::SnippetWithTags>, sqlx::Error> {
+        self.inner.get_snippets_saved_before(cutoff).await
+    }
+
+    async fn get_tags_with_urls_and_snippets(&self) -> Result<Vec<models::TagWithUrlsAndSnippets>, sqlx::Error> {
+        let generation = self.tags.generation();
+        if let Some(tags) = self.tags.get(self.ttl) {
+            return Ok(tags);
+        }
+        let tags = self.inner.get_tags_with_urls_and_snippets().await?;
+        self.tags.store(generation, tags.clone());
+        Ok(tags)
+    }
+
+    async fn get_all_tags(&self) -> Result<Vec<String>, sqlx::Error> {
+        self.inner.get_all_tags().await
+    }
+
+    async fn get_tag_counts(&self) -> Result<Vec<models::TagCount>, sqlx::Error> {
+        self.inner.get_tag_counts().await
+    }
+
+    async fn get_tag_details(&self) -> Result<Vec<models::TagDetails>, sqlx::Error> {
+        self.inner.get_tag_details().await
+    }
+
+    async fn get_tag(&self, tag: &str) -> Result<Option<models::TagDetails>, sqlx::Error> {
+        self.inner.get_tag(tag).await
+    }
+
+    async fn set_tag_details(
+        &self,
+        tag: &str,
+        color: Option<&str>,
+        description: Option<&str>,
+    ) -> Result<(), sqlx::Error> {
+        self.inner.set_tag_details(tag, color, description).await
+    }
+
+    async fn import_tags(&self, tags: &[models::TagDetails]) -> Result<models::TagImport, sqlx::Error> {
+        let imported = self.inner.import_tags(tags).await;
+        self.tags.invalidate();
+        imported
+    }
+
+    async fn execute_batch(
+        &self,
+        operations: &[models::BatchOperation],
+    ) -> Result<Vec<Option<i32>>, models::BatchError> {
+        let result = self.inner.execute_batch(operations).await;
+        self.tags.invalidate();
+        self.counts.invalidate();
+        result
+    }
+
+    async fn record_activity(&self, action: &str, subject: &str, detail: Option<&str>) -> Result<(), sqlx::Error> {
+        self.inner.record_activity(action, subject, detail).await
+    }
+
+    async fn get_activity(&self, limit: i64) -> Result<Vec<models::Activity>, sqlx::Error> {
+        self.inner.get_activity(limit).await
+    }
+
+    async fn get_local_activity_since(&self, after_id: i32, limit: i64) -> Result<Vec<models::Activity>, sqlx::Error> {
+        self.inner.get_local_activity_since(after_id, limit).await
+    }
+
+    async fn get_latest_activity(
+        &self,
+        subject: &str,
+        actions: &[&str],
+    ) -> Result<Option<models::Activity>, sqlx::Error> {
+        self.inner.get_latest_activity(subject, actions).await
+    }
+
+    async fn get_activity_by_actions(&self, actions: &[&str]) -> Result<Vec<models::Activity>, sqlx::Error> {
+        self.inner.get_activity_by_actions(actions).await
+    }
+
+    async fn record_peer_activity(&self, peer: &str, activity: &models::Activity) -> Result<(), sqlx::Error> {
+        self.inner.record_peer_activity(peer, activity).await
+    }
+
+    async fn get_peer_cursor(&self, peer: &str) -> Result<i32, sqlx::Error> {
+        self.inner.get_peer_cursor(peer).await
+    }
+
+    async fn set_peer_cursor(&self, peer: &str, cursor: i32) -> Result<(), sqlx::Error> {
+        self.inner.set_peer_cursor(peer, cursor).await
+    }
+
+    async fn create_import_job(&self, source: &str, items: &[models::ImportItem]) -> Result<i32, sqlx::Error> {
+        self.inner.create_import_job(source, items).await
+    }
+
+    async fn get_import_job(&self, id: i32) -> Result<Option<models::ImportJob>, sqlx::Error> {
+        self.inner.get_import_job(id).await
+    }
+
+    async fn get_running_import_jobs(&self) -> Result<Vec<i32>, sqlx::Error> {
+        self.inner.get_running_import_jobs().await
+    }
+
+    async fn get_pending_import_items(
+        &self,
+        job_id: i32,
+        limit: i64,
+    ) -> Result<Vec<models::PendingImportItem>, sqlx::Error> {
+        self.inner.get_pending_import_items(job_id, limit).await
+    }
+
+    async fn start_import_item(&self, job_id: i32, item_id: i32) -> Result<(), sqlx::Error> {
+        self.inner.start_import_item(job_id, item_id).await
+    }
+
+    async fn finish_import_item(
+        &self,
+        job_id: i32,
+        item_id: i32,
+        outcome: &models::ImportOutcome,
+    ) -> Result<(), sqlx::Error> {
+        let result = self.inner.finish_import_item(job_id, item_id, outcome).await;
+        self.tags.invalidate();
+        self.counts.invalidate();
+        result
+    }
+
+    async fn complete_import_job(&self, id: i32) -> Result<(), sqlx::Error> {
+        self.inner.complete_import_job(id).await
+    }
+
+    async fn insert_snapshot(
+        &self,
+        url_id: i32,
+        content_type: Option<&str>,
+        content: &str,
+        truncated: bool,
+        keep: i64,
+    ) -> Result<Option<i32>, sqlx::Error> {
+        self.inner
+            .insert_snapshot(url_id, content_type, content, truncated, keep)
+            .await
+    }
+
+    async fn get_snapshots(&self, url_id: i32) -> Result<Vec<models::Snapshot>, sqlx::Error> {
+        self.inner.get_snapshots(url_id).await
+    }
+
+    async fn get_snapshot(&self, url_id: i32, id: i32) -> Result<Option<models::SnapshotContent>, sqlx::Error> {
+        self.inner.get_snapshot(url_id, id).await
+    }
+
+    async fn touch_snapshot(&self, url_id: i32, id: i32) -> Result<Option<chrono::NaiveDateTime>, sqlx::Error> {
+        self.inner.touch_snapshot(url_id, id).await
+    }
+
+    async fn get_snapshotted_urls(&self) -> Result<Vec<models::Url>, sqlx::Error> {
+        self.inner.get_snapshotted_urls().await
+    }
+
+    async fn get_url_metadata_entries(&self, url_id: i32) -> Result<BTreeMap<String, serde_json::Value>, sqlx::Error> {
+        self.inner.get_url_metadata_entries(url_id).await
+    }
+
+    async fn get_url_metadata_entry(&self, url_id: i32, key: &str) -> Result<Option<serde_json::Value>, sqlx::Error> {
+        self.inner.get_url_metadata_entry(url_id, key).await
+    }
+
+    async fn set_url_metadata_entry(
+        &self,
+        url_id: i32,
+        key: &str,
+        value: &serde_json::Value,
+    ) -> Result<(), sqlx::Error> {
+        self.inner.set_url_metadata_entry(url_id, key, value).await
+    }
+
+    async fn archive_unread_before(
+        &self,
+        cutoff: chrono::NaiveDateTime,
+        dry_run: bool,
+    ) -> Result<Vec<String>, sqlx::Error> {
+        let result = self.inner.archive_unread_before(cutoff, dry_run).await;
+        if !dry_run {
+            self.counts.invalidate();
+        }
+        result
+    }
+
+    async fn archive_read_before(
+        &self,
+        cutoff: chrono::NaiveDateTime,
+        dry_run: bool,
+    ) -> Result<Vec<String>, sqlx::Error> {
+        self.inner.archive_read_before(cutoff, dry_run).await
+    }
+
+    async fn delete_read_before(
+        &self,
+        cutoff: chrono::NaiveDateTime,
+        dry_run: bool,
+    ) -> Result<Vec<String>, sqlx::Error> {
+        let result = self.inner.delete_read_before(cutoff, dry_run).await;
+        if !dry_run {
+            self.tags.invalidate();
+            self.counts.invalidate();
+        }
+        result
+    }
+
+    async fn evict_snapshots(
+        &self,
+        max_bytes: i64,
+        dry_run: bool,
+    ) -> Result<Vec<models::EvictedSnapshot>, sqlx::Error> {
+        self.inner.evict_snapshots(max_bytes, dry_run).await
+    }
+
+    async fn adopt_canonical_url(
+        &self,
+        url_id: i32,
+        canonical: &str,
+    ) -> Result<Option<models::CleanedUrl>, sqlx::Error> {
+        let result = self.inner.adopt_canonical_url(url_id, canonical).await;
+        self.tags.invalidate();
+        self.counts.invalidate();
+        result
+    }
+
+    async fn move_url(&self, url_id: i32, url: &str) -> Result<Option<models::CleanedUrl>, sqlx::Error> {
+        let result = self.inner.move_url(url_id, url).await;
+        self.tags.invalidate();
+        self.counts.invalidate();
+        result
+    }
+
+    async fn clean_urls(&self, dry_run: bool) -> Result<Vec<models::CleanedUrl>, sqlx::Error> {
+        let result = self.inner.clean_urls(dry_run).await;
+        if !dry_run {
+            self.tags.invalidate();
+            self.counts.invalidate();
+        }
+        result
+    }
+
+    async fn repair_snippet_tags(&self, dry_run: bool) -> Result<Vec<i32>, sqlx::Error> {
+        let result = self.inner.repair_snippet_tags(dry_run).await;
+        if !dry_run {
+            self.tags.invalidate();
+            self.counts.invalidate();
+        }
+        result
+    }
+
+    async fn delete_all_data(&self) -> Result<(), sqlx::Error> {
+        let result = self.inner.delete_all_data().await;
+        self.tags.invalidate();
+        self.counts.invalidate();
+        result
+    }
+
+    async fn read_rows(
+        &self,
+        table: &models::CopiedTable,
+        offset: i64,
+        limit: i64,
+    ) -> Result<Vec<Vec<models::ColumnValue>>, sqlx::Error> {
+        self.inner.read_rows(table, offset, limit).await
+    }
+
+    async fn write_rows(
+        &self,
+        table: &models::CopiedTable,
+        rows: &[Vec<models::ColumnValue>],
+    ) -> Result<(), sqlx::Error> {
+        let result = self.inner.write_rows(table, rows).await;
+        self.tags.invalidate();
+        self.counts.invalidate();
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::models::Database;
+    use crate::services::sqlite_database::SqliteDatabase;
+
+    async fn database() -> CachedDatabase {
+        let inner = SqliteDatabase::new(":memory:").await.unwrap();
+        inner.initialize().await.unwrap();
+        CachedDatabase::new(Arc::new(inner), Duration::from_secs(60))
+    }
+
+    /// Tag names in the overview, leaving out the row collecting untagged items
+    fn tag_names(tags: &[models::TagWithUrlsAndSnippets]) -> Vec<&str> {
+        tags.iter()
+            .map(|tag| tag.tag.as_str())
+            .filter(|tag| !tag.is_empty())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn writes_invalidate_the_cached_tags() {
+        let database = database().await;
+        database.insert_tags("https://example.com/a", &["rust"]).await.unwrap();
+        assert_eq!(
+            tag_names(&database.get_tags_with_urls_and_snippets().await.unwrap()),
+            ["rust"]
+        );
+        assert!(database.tags.get(database.ttl).is_some());
+
+        database
+            .insert_snippet("https://example.com/a", "A quote", &["web"], None)
+            .await
+            .unwrap();
+        assert!(database.tags.get(database.ttl).is_none());
+        assert_eq!(
+            tag_names(&database.get_tags_with_urls_and_snippets().await.unwrap()),
+            ["rust", "web"]
+        );
+
+        // Marking read doesn't change the aggregate, so it stays cached
+        database.set_url_read("https://example.com/a", true).await.unwrap();
+        assert!(database.tags.get(database.ttl).is_some());
+
+        database.clean_urls(true).await.unwrap();
+        assert!(database.tags.get(database.ttl).is_some());
+        database.delete_all_data().await.unwrap();
+        assert!(tag_names(&database.get_tags_with_urls_and_snippets().await.unwrap()).is_empty());
+    }
+
+    #[tokio::test]
+    async fn url_writes_invalidate_the_cached_counts() {
+        let database = database().await;
+        let counts = |unread_count, total| models::UrlCounts { unread_count, total };
+        database.insert_url("https://example.com/a").await.unwrap();
+        database.insert_url("https://example.com/b").await.unwrap();
+        assert_eq!(database.get_url_counts().await.unwrap(), counts(2, 2));
+
+        // Snippets don't change how many URLs are saved
+        database
+            .insert_snippet("https://example.com/a", "A quote", &[], None)
+            .await
+            .unwrap();
+        assert!(database.counts.get(database.ttl).is_some());
+
+        database.set_url_read("https://example.com/a", true).await.unwrap();
+        assert!(database.counts.get(database.ttl).is_none());
+        assert_eq!(database.get_url_counts().await.unwrap(), counts(1, 2));
+
+        database.delete_url_by_url("https://example.com/b").await.unwrap();
+        assert_eq!(database.get_url_counts().await.unwrap(), counts(0, 1));
+    }
+
+    #[test]
+    fn results_read_during_a_write_are_not_kept() {
+        let aggregate = Aggregate::default();
+        let generation = aggregate.generation();
+        aggregate.invalidate();
+        aggregate.store(generation, vec!["stale"]);
+        assert_eq!(aggregate.get(Duration::from_secs(60)), None);
+
+        aggregate.store(aggregate.generation(), vec!["fresh"]);
+        assert_eq!(aggregate.get(Duration::from_secs(60)), Some(vec!["fresh"]));
+        assert_eq!(aggregate.get(Duration::ZERO), None);
+    }
+}
@@ -0,0 +1,96 @@
+use crate::services::html;
+use reqwest::Url;
+
+/// How much of a page to search for its canonical URL; the tags belong in `<head>`
+const HEAD_LIMIT: usize = 64 * 1024;
+
+/// Host prefixes that serve the same site, so `m.example.com` may name `example.com` as canonical
+const HOST_ALIASES: [&str; 4] = ["www.", "m.", "mobile.", "amp."];
+
+/// The canonical URL a page declares for itself, from `<link rel="canonical">` or else `og:url`.
+///
+/// Relative links are resolved against `page_url`. A canonical URL on another site is ignored, since
+/// a page could otherwise claim to be any URL that happens to be saved.
+pub fn find(html: &str, page_url: &str) -> Option<String> {
+    let page = Url::parse(page_url).ok()?;
+    let head = &html[..html::floor_char_boundary(html, HEAD_LIMIT)];
+
+    let mut link = None;
+    let mut og_url = None;
+    for (name, attributes) in html::tags(head) {
+        let attribute = |key: &str| html::attribute(&attributes, key);
+        match name.as_str() {
+            "link" if link.is_none() && attribute("rel").is_some_and(is_canonical) => {
+                link = attribute("href").map(String::from);
+            }
+            "meta" if og_url.is_none() && attribute("property").or(attribute("name")) == Some("og:url") => {
+                og_url = attribute("content").map(String::from);
+            }
+            "body" => break,
+            _ => {}
+        }
+    }
+
+    let canonical = page.join(link.or(og_url)?.trim()).ok()?;
+    if !matches!(canonical.scheme(), "http" | "https") || !same_site(&page, &canonical) {
+        return None;
+    }
+    Some(canonical.to_string())
+}
+
+/// Whether a `rel` attribute, a space-separated list, includes `canonical`
+fn is_canonical(rel: &str) -> bool {
+    rel.split_whitespace().any(|rel| rel.eq_ignore_ascii_case("canonical"))
+}
+
+fn same_site(page: &Url, canonical: &Url) -> bool {
+    let site = |url: &Url| {
+        let mut host = url.host_str().unwrap_or_default().to_ascii_lowercase();
+        while let Some(prefix) = HOST_ALIASES.iter().find(|prefix| host.starts_with(*prefix)) {
+            host = host[prefix.len()..].to_string();
+        }
+        host
+    };
+    !site(page).is_empty() && site(page) == site(canonical)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_canonical_link_before_og_url() {
+        let html = r#"<html><head>
+            <meta property="og:url" content="https://example.com/og">
+            <LINK REL="canonical" href='/articles/42?ref=a&amp;b=1' />
+            </head><body><link rel="canonical" href="/ignored"></body></html>"#;
+        assert_eq!(
+            find(html, "https://www.example.com/articles/42?utm_source=x").as_deref(),
+            Some("https://www.example.com/articles/42?ref=a&b=1")
+        );
+
+        let og_only = r#"<meta name="og:url" content="https://example.com/story">"#;
+        assert_eq!(
+            find(og_only, "https://m.example.com/story").as_deref(),
+            Some("https://example.com/story")
+        );
+    }
+
+    #[test]
+    fn ignores_other_sites_and_missing_tags() {
+        let elsewhere = r#"<link rel="canonical" href="https://attacker.example/">"#;
+        assert_eq!(find(elsewhere, "https://example.com/"), None);
+        assert_eq!(
+            find(
+                r#"<link rel="canonical" href="javascript:alert(1)">"#,
+                "https://example.com/"
+            ),
+            None
+        );
+        assert_eq!(find("<p>No metadata</p>", "https://example.com/"), None);
+        assert_eq!(
+            find(r#"<link rel="stylesheet" href="/s.css">"#, "https://example.com/"),
+            None
+        );
+    }
+}
@@ -0,0 +1,53 @@
+use serde::Serialize;
+use std::sync::LazyLock;
+use tokio::sync::broadcast;
+
+/// How far a slow subscriber can fall behind before it starts missing changes
+const CAPACITY: usize = 256;
+
+static FEED: LazyLock<broadcast::Sender<Change>> = LazyLock::new(|| broadcast::channel(CAPACITY).0);
+
+/// Something that changed, mirroring the activity entry recorded for it
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Change {
+    pub action: String,
+    pub subject: String,
+    pub detail: Option<String>,
+}
+
+/// Tell every live subscriber about a change; a no-op when nobody is listening
+pub fn publish(action: &str, subject: &str, detail: Option<&str>) {
+    let _ = FEED.send(Change {
+        action: action.to_string(),
+        subject: subject.to_string(),
+        detail: detail.map(String::from),
+    });
+}
+
+/// Receive every change published from now on
+pub fn subscribe() -> broadcast::Receiver<Change> {
+    FEED.subscribe()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn subscribers_receive_changes_published_after_subscribing() {
+        publish("save_url", "https://before.example", None);
+        let mut changes = subscribe();
+        publish("tag_url", "https://changes.example", Some("rust"));
+
+        // Other tests publish to the same feed, so skip anything that isn't ours
+        loop {
+            let change = changes.recv().await.unwrap();
+            assert_ne!(change.subject, "https://before.example");
+            if change.subject == "https://changes.example" {
+                assert_eq!(change.action, "tag_url");
+                assert_eq!(change.detail.as_deref(), Some("rust"));
+                break;
+            }
+        }
+    }
+}
@@ -0,0 +1,187 @@
+//! Sorting saved URLs into kinds, such as articles, videos and code, from the URL and the start of its page
+
+use crate::config;
+use crate::services::{audio, html, models, snapshots};
+use models::UrlKind;
+use reqwest::Url;
+use std::time::Duration;
+
+/// How much of a page is read for its content type and `og:type`; the tag belongs in `<head>`
+const HEAD_LIMIT: usize = 64 * 1024;
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Sites that are one kind throughout, matched along with their subdomains. An entry ending in `.` matches
+/// that name under any top-level domain, like `amazon.co.uk`.
+const HOSTS: [(&str, UrlKind); 27] = [
+    ("youtube.com", UrlKind::Video),
+    ("youtu.be", UrlKind::Video),
+    ("vimeo.com", UrlKind::Video),
+    ("twitch.tv", UrlKind::Video),
+    ("tiktok.com", UrlKind::Video),
+    ("soundcloud.com", UrlKind::Audio),
+    ("bandcamp.com", UrlKind::Audio),
+    ("podcasts.apple.com", UrlKind::Audio),
+    ("open.spotify.com", UrlKind::Audio),
+    ("github.com", UrlKind::Code),
+    ("gitlab.com", UrlKind::Code),
+    ("bitbucket.org", UrlKind::Code),
+    ("codeberg.org", UrlKind::Code),
+    ("sr.ht", UrlKind::Code),
+    ("crates.io", UrlKind::Code),
+    ("npmjs.com", UrlKind::Code),
+    ("pypi.org", UrlKind::Code),
+    ("amazon.", UrlKind::Shopping),
+    ("ebay.", UrlKind::Shopping),
+    ("etsy.com", UrlKind::Shopping),
+    ("aliexpress.com", UrlKind::Shopping),
+    ("twitter.com", UrlKind::Social),
+    ("x.com", UrlKind::Social),
+    ("bsky.app", UrlKind::Social),
+    ("mastodon.social", UrlKind::Social),
+    ("reddit.com", UrlKind::Social),
+    ("news.ycombinator.com", UrlKind::Social),
+];
+
+/// File extensions that give a link's kind away without fetching it; audio ones come from `audio`
+const EXTENSIONS: [(&str, UrlKind); 6] = [
+    ("pdf", UrlKind::Document),
+    ("epub", UrlKind::Document),
+    ("mp4", UrlKind::Video),
+    ("webm", UrlKind::Video),
+    ("mov", UrlKind::Video),
+    ("mkv", UrlKind::Video),
+];
+
+fn host_matches(host: &str, entry: &str) -> bool {
+    match entry.strip_suffix('.') {
+        Some(name) => {
+            let labels: Vec<&str> = host.split('.').collect();
+            // Followed by a top-level domain, or a country's one with a short label before it like `co.uk`
+            labels.iter().enumerate().any(|(index, label)| {
+                *label == name
+                    && match &labels[index + 1..] {
+                        [_] => true,
+                        [second, country] => second.len() <= 3 && country.len() == 2,
+                        _ => false,
+                    }
+            })
+        }
+        None => host == entry || host.ends_with(&format!(".{}", entry)),
+    }
+}
+
+/// The kind a URL is from its host or file extension alone, if either gives it away
+pub fn by_url(url: &str) -> Option<UrlKind> {
+    let parsed = Url::parse(url).ok()?;
+    let host = parsed.host_str()?.to_ascii_lowercase();
+    if let Some((_, kind)) = HOSTS.iter().find(|(entry, _)| host_matches(&host, entry)) {
+        return Some(*kind);
+    }
+
+    let path = parsed.path();
+    if audio::extension_mime_type(path).is_some() {
+        return Some(UrlKind::Audio);
+    }
+    let (_, extension) = path.rsplit_once('.')?;
+    EXTENSIONS
+        .iter()
+        .find(|(known, _)| extension.eq_ignore_ascii_case(known))
+        .map(|(_, kind)| *kind)
+}
+
+/// The kind a page is from its content type and, for HTML, its `og:type`. Any other HTML page counts as an
+/// article; other text, like JSON or a feed, is left unclassified.
+pub fn by_page(content_type: Option<&str>, body: &str) -> Option<UrlKind> {
+    let content_type = content_type
+        .map(|value| value.split(';').next().unwrap_or(value).trim().to_ascii_lowercase())
+        .unwrap_or_else(|| "text/html".to_string());
+    if content_type.starts_with("video/") {
+        return Some(UrlKind::Video);
+    }
+    if content_type.starts_with("audio/") {
+        return Some(UrlKind::Audio);
+    }
+    if matches!(content_type.as_str(), "application/pdf" | "application/epub+zip") {
+        return Some(UrlKind::Document);
+    }
+    if !content_type.contains("html") {
+        return None;
+    }
+
+    let head = &body[..html::floor_char_boundary(body, HEAD_LIMIT)];
+    let og_type = html::tags(head)
+        .take_while(|(name, _)| name != "body")
+        .find_map(|(name, attributes)| {
+            let property = html::attribute(&attributes, "property").or(html::attribute(&attributes, "name"));
+            (name == "meta" && property == Some("og:type"))
+                .then(|| html::attribute(&attributes, "content").map(str::to_ascii_lowercase))
+                .flatten()
+        });
+    Some(match og_type.as_deref() {
+        Some(og_type) if og_type.starts_with("video") => UrlKind::Video,
+        Some(og_type) if og_type.starts_with("music") => UrlKind::Audio,
+        Some(og_type) if og_type.contains("product") => UrlKind::Shopping,
+        Some("profile") => UrlKind::Social,
+        Some("book") => UrlKind::Document,
+        _ => UrlKind::Article,
+    })
+}
+
+/// Work out a URL's kind, fetching the start of its page when the URL alone doesn't say
+pub async fn classify(url: &str) -> Result<Option<UrlKind>, String> {
+    if let Some(kind) = by_url(url) {
+        return Ok(Some(kind));
+    }
+    let limits = config::PageLimits {
+        max_size: HEAD_LIMIT,
+        timeout: FETCH_TIMEOUT,
+        content_types: Vec::new(),
+    };
+    let page = snapshots::fetch(url, &limits).await?;
+    Ok(by_page(page.content_type.as_deref(), &page.body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hosts_and_extensions_give_the_kind_away() {
+        assert_eq!(by_url("https://www.youtube.com/watch?v=abc"), Some(UrlKind::Video));
+        assert_eq!(by_url("https://gist.github.com/someone/1"), Some(UrlKind::Code));
+        assert_eq!(by_url("https://www.amazon.co.uk/dp/B000"), Some(UrlKind::Shopping));
+        assert_eq!(by_url("https://smile.amazon.de/dp/B000"), Some(UrlKind::Shopping));
+        assert_eq!(by_url("https://news.ycombinator.com/item?id=1"), Some(UrlKind::Social));
+        assert_eq!(by_url("https://example.com/paper.PDF"), Some(UrlKind::Document));
+        assert_eq!(
+            by_url("https://cdn.example.com/episode.mp3?token=1"),
+            Some(UrlKind::Audio)
+        );
+
+        // Only whole labels count
+        assert_eq!(by_url("https://notgithub.com/repo"), None);
+        assert_eq!(by_url("https://amazon.example.org/post"), None);
+        assert_eq!(by_url("https://example.com/blog/post"), None);
+    }
+
+    #[test]
+    fn pages_are_classified_by_content_type_and_og_type() {
+        assert_eq!(by_page(Some("video/mp4"), ""), Some(UrlKind::Video));
+        assert_eq!(by_page(Some("Application/PDF; qs=0.9"), ""), Some(UrlKind::Document));
+        assert_eq!(by_page(Some("application/json"), "{}"), None);
+
+        let page = |og_type: &str| {
+            format!(r#"<html><head><meta property="og:type" content="{og_type}"></head><body></body></html>"#)
+        };
+        assert_eq!(by_page(Some("text/html"), &page("video.other")), Some(UrlKind::Video));
+        assert_eq!(by_page(Some("text/html"), &page("music.song")), Some(UrlKind::Audio));
+        assert_eq!(by_page(Some("text/html"), &page("og:product")), Some(UrlKind::Shopping));
+        assert_eq!(by_page(Some("text/html"), &page("article")), Some(UrlKind::Article));
+        assert_eq!(by_page(None, "<p>Just a page</p>"), Some(UrlKind::Article));
+
+        // Tags in the body don't speak for the page
+        let body_only = r#"<html><head></head><body><meta property="og:type" content="video"></body></html>"#;
+        assert_eq!(by_page(Some("text/html"), body_only), Some(UrlKind::Article));
+    }
+}
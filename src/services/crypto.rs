@@ -0,0 +1,403 @@
+use crate::services::models;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+/// Marker prepended to encrypted values so plaintext rows written before encryption was enabled still read back
+const ENCRYPTED_PREFIX: &str = "enc:v1:";
+const NONCE_LEN: usize = 12;
+
+/// Encrypts snippet text at rest with AES-256-GCM
+#[derive(Clone)]
+pub struct SnippetCipher {
+    cipher: Aes256Gcm,
+}
+
+impl SnippetCipher {
+    /// Build a cipher from a base64-encoded 32 byte key
+    pub fn from_base64_key(encoded_key: &str) -> Result<Self, String> {
+        let key_bytes = STANDARD
+            .decode(encoded_key.trim())
+            .map_err(|err| format!("encryption key is not valid base64: {}", err))?;
+        if key_bytes.len() != 32 {
+            return Err(format!("encryption key must be 32 bytes, got {}", key_bytes.len()));
+        }
+
+        let key = Key::<Aes256Gcm>::from_slice(&key_bytes);
+        Ok(Self {
+            cipher: Aes256Gcm::new(key),
+        })
+    }
+
+    /// Encrypt a value, returning `enc:v1:<base64(nonce || ciphertext)>`
+    pub fn encrypt(&self, plaintext: &str) -> Result<String, sqlx::Error> {
+        let payload = self.seal_payload(plaintext.as_bytes())?;
+        Ok(format!("{}{}", ENCRYPTED_PREFIX, STANDARD.encode(payload)))
+    }
+
+    /// Decrypt a value produced by `encrypt`; values without the marker are returned unchanged
+    pub fn decrypt(&self, stored: &str) -> Result<String, sqlx::Error> {
+        let Some(encoded) = stored.strip_prefix(ENCRYPTED_PREFIX) else {
+            return Ok(stored.to_string());
+        };
+
+        let payload = STANDARD
+            .decode(encoded)
+            .map_err(|err| sqlx::Error::Decode(Box::new(err)))?;
+        let plaintext = self.open_payload(&payload)?;
+        String::from_utf8(plaintext).map_err(|err| sqlx::Error::Decode(Box::new(err)))
+    }
+
+    /// Encrypt binary data, returning the marker followed by `nonce || ciphertext` without base64
+    pub fn encrypt_bytes(&self, plaintext: &[u8]) -> Result<Vec<u8>, sqlx::Error> {
+        let mut stored = ENCRYPTED_PREFIX.as_bytes().to_vec();
+        stored.extend(self.seal_payload(plaintext)?);
+        Ok(stored)
+    }
+
+    /// Decrypt data produced by `encrypt_bytes`; data without the marker is returned unchanged
+    pub fn decrypt_bytes(&self, stored: &[u8]) -> Result<Vec<u8>, sqlx::Error> {
+        match stored.strip_prefix(ENCRYPTED_PREFIX.as_bytes()) {
+            Some(payload) => self.open_payload(payload),
+            None => Ok(stored.to_vec()),
+        }
+    }
+
+    fn seal_payload(&self, plaintext: &[u8]) -> Result<Vec<u8>, sqlx::Error> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| sqlx::Error::Protocol("failed to encrypt snippet".to_string()))?;
+
+        let mut payload = nonce.to_vec();
+        payload.extend_from_slice(&ciphertext);
+        Ok(payload)
+    }
+
+    fn open_payload(&self, payload: &[u8]) -> Result<Vec<u8>, sqlx::Error> {
+        if payload.len() < NONCE_LEN {
+            return Err(sqlx::Error::Decode("encrypted snippet is truncated".into()));
+        }
+
+        let (nonce, ciphertext) = payload.split_at(NONCE_LEN);
+        self.cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| sqlx::Error::Decode("failed to decrypt snippet, is the encryption key correct?".into()))
+    }
+}
+
+/// Encrypt snippet text when a cipher is configured
+pub fn seal(cipher: Option<&SnippetCipher>, snippet: &str) -> Result<String, sqlx::Error> {
+    match cipher {
+        Some(cipher) => cipher.encrypt(snippet),
+        None => Ok(snippet.to_string()),
+    }
+}
+
+/// Encrypt the page text kept with a snippet, its markup and the text around it, when a cipher is configured
+pub fn seal_source(
+    cipher: Option<&SnippetCipher>,
+    source: &models::SnippetSource,
+) -> Result<models::SnippetSource, sqlx::Error> {
+    let mut source = source.clone();
+    let Some(cipher) = cipher else {
+        return Ok(source);
+    };
+
+    source.html = source.html.map(|html| cipher.encrypt(&html)).transpose()?;
+    if let Some(selection) = &mut source.selection {
+        selection.prefix = cipher.encrypt(&selection.prefix)?;
+        selection.suffix = cipher.encrypt(&selection.suffix)?;
+    }
+    Ok(source)
+}
+
+/// Encrypt an image snippet's picture when a cipher is configured; the content type stays readable to serve it
+pub fn seal_image(
+    cipher: Option<&SnippetCipher>,
+    image: &models::SnippetImage,
+) -> Result<models::SnippetImage, sqlx::Error> {
+    let data = match cipher {
+        Some(cipher) => cipher.encrypt_bytes(&image.data)?,
+        None => image.data.clone(),
+    };
+    Ok(models::SnippetImage {
+        content_type: image.content_type.clone(),
+        data,
+    })
+}
+
+/// Decrypt an image snippet's picture when a cipher is configured
+pub fn open_image(
+    cipher: Option<&SnippetCipher>,
+    mut image: models::SnippetImage,
+) -> Result<models::SnippetImage, sqlx::Error> {
+    if let Some(cipher) = cipher {
+        image.data = cipher.decrypt_bytes(&image.data)?;
+    }
+    Ok(image)
+}
+
+/// Encrypt the notes an update sets on a URL, when a cipher is configured
+pub fn seal_notes(
+    cipher: Option<&SnippetCipher>,
+    update: &models::UrlUpdate,
+) -> Result<models::UrlUpdate, sqlx::Error> {
+    let mut update = update.clone();
+    if let (Some(cipher), Some(Some(notes))) = (cipher, &mut update.notes) {
+        *notes = cipher.encrypt(notes)?;
+    }
+    Ok(update)
+}
+
+/// Encrypt the notes import items carry while they wait in their job, when a cipher is configured
+pub fn seal_import_items(
+    cipher: Option<&SnippetCipher>,
+    items: &[models::ImportItem],
+) -> Result<Vec<models::ImportItem>, sqlx::Error> {
+    items
+        .iter()
+        .map(|item| {
+            let note = item.note.as_deref().map(|note| seal(cipher, note)).transpose()?;
+            Ok(models::ImportItem { note, ..item.clone() })
+        })
+        .collect()
+}
+
+/// Decrypt the notes of an import job's pending items when a cipher is configured
+pub fn open_import_items(
+    cipher: Option<&SnippetCipher>,
+    mut items: Vec<models::PendingImportItem>,
+) -> Result<Vec<models::PendingImportItem>, sqlx::Error> {
+    if let Some(cipher) = cipher {
+        for pending in &mut items {
+            pending.item.note = pending
+                .item
+                .note
+                .as_deref()
+                .map(|note| cipher.decrypt(note))
+                .transpose()?;
+        }
+    }
+    Ok(items)
+}
+
+/// Decrypt a URL's notes when a cipher is configured
+pub fn open_url(
+    cipher: Option<&SnippetCipher>,
+    mut url: models::UrlWithTags,
+) -> Result<models::UrlWithTags, sqlx::Error> {
+    if let Some(cipher) = cipher {
+        url.notes = url.notes.as_deref().map(|notes| cipher.decrypt(notes)).transpose()?;
+    }
+    Ok(url)
+}
+
+/// Decrypt the snippet text of each row, sanitized and not, and the page text kept with it, when a cipher is
+/// configured
+pub fn open_snippets(
+    cipher: Option<&SnippetCipher>,
+    snippets: Vec<models::SnippetWithTags>,
+) -> Result<Vec<models::SnippetWithTags>, sqlx::Error> {
+    let Some(cipher) = cipher else {
+        return Ok(snippets);
+    };
+
+    snippets
+        .into_iter()
+        .map(|mut snippet| {
+            snippet.snippet = cipher.decrypt(&snippet.snippet)?;
+            snippet.snippet_html = snippet
+                .snippet_html
+                .as_deref()
+                .map(|html| cipher.decrypt(html))
+                .transpose()?;
+            if let Some(source) = &mut snippet.source {
+                source.html = source.html.as_deref().map(|html| cipher.decrypt(html)).transpose()?;
+                if let Some(selection) = &mut source.selection {
+                    selection.prefix = cipher.decrypt(&selection.prefix)?;
+                    selection.suffix = cipher.decrypt(&selection.suffix)?;
+                }
+            }
+            Ok(snippet)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_cipher() -> SnippetCipher {
+        SnippetCipher::from_base64_key(&STANDARD.encode([7u8; 32])).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let cipher = test_cipher();
+        let encrypted = cipher.encrypt("a sensitive excerpt").unwrap();
+
+        assert!(encrypted.starts_with(ENCRYPTED_PREFIX));
+        assert!(!encrypted.contains("sensitive"));
+        assert_eq!(cipher.decrypt(&encrypted).unwrap(), "a sensitive excerpt");
+    }
+
+    #[test]
+    fn test_plaintext_passes_through() {
+        let cipher = test_cipher();
+        assert_eq!(
+            cipher.decrypt("written before encryption").unwrap(),
+            "written before encryption"
+        );
+    }
+
+    #[test]
+    fn test_source_page_text_round_trips() {
+        let cipher = test_cipher();
+        let source = models::SnippetSource {
+            html: Some("<p>a <em>sensitive</em> excerpt</p>".to_string()),
+            title: Some("A page".to_string()),
+            favicon: None,
+            selection: Some(models::SelectionRange {
+                start: 10,
+                end: 28,
+                prefix: "before the ".to_string(),
+                suffix: String::new(),
+            }),
+        };
+
+        let sealed = seal_source(Some(&cipher), &source).unwrap();
+        assert!(!sealed.html.as_deref().unwrap().contains("sensitive"));
+        assert!(!sealed.selection.as_ref().unwrap().prefix.contains("before"));
+        assert_eq!(sealed.title, source.title);
+
+        let stored = models::SnippetWithTags {
+            id: 1,
+            snippet: cipher.encrypt("a sensitive excerpt").unwrap(),
+            snippet_html: None,
+            url: "https://example.com".to_string(),
+            tags: Vec::new(),
+            pinned: false,
+            source: Some(sealed),
+            image_type: None,
+        };
+        let opened = open_snippets(Some(&cipher), vec![stored]).unwrap();
+        assert_eq!(opened[0].source.as_ref(), Some(&source));
+    }
+
+    #[test]
+    fn test_image_round_trip() {
+        let cipher = test_cipher();
+        let image = models::SnippetImage {
+            content_type: "image/png".to_string(),
+            data: b"\x89PNG\r\n\x1a\nsecret pixels".to_vec(),
+        };
+
+        let sealed = seal_image(Some(&cipher), &image).unwrap();
+        assert!(sealed.data.starts_with(ENCRYPTED_PREFIX.as_bytes()));
+        assert!(!sealed.data.windows(6).any(|window| window == b"secret"));
+        assert_eq!(open_image(Some(&cipher), sealed).unwrap(), image);
+        // Pictures saved before encryption was enabled still read back
+        assert_eq!(open_image(Some(&cipher), image.clone()).unwrap(), image);
+    }
+
+    #[test]
+    fn test_url_notes_round_trip() {
+        let cipher = test_cipher();
+        let update = models::UrlUpdate {
+            notes: Some(Some("a sensitive thought".to_string())),
+            ..Default::default()
+        };
+
+        let sealed = seal_notes(Some(&cipher), &update).unwrap();
+        let stored = sealed.notes.clone().flatten().unwrap();
+        assert!(stored.starts_with(ENCRYPTED_PREFIX));
+        assert!(!stored.contains("sensitive"));
+        // Clearing the notes stays a clear
+        let cleared = models::UrlUpdate {
+            notes: Some(None),
+            ..Default::default()
+        };
+        assert_eq!(seal_notes(Some(&cipher), &cleared).unwrap(), cleared);
+
+        let url = |notes: &str| models::UrlWithTags {
+            id: 1,
+            url: "https://example.com".to_string(),
+            url_hash: "0".repeat(64),
+            datetime: chrono::NaiveDateTime::default(),
+            tags: Vec::new(),
+            display_url: "https://example.com".to_string(),
+            display_title: "example.com".to_string(),
+            read_at: None,
+            short_id: "0".repeat(10),
+            pinned: false,
+            progress: 0,
+            metadata: None,
+            title: None,
+            notes: Some(notes.to_string()),
+            archived_at: None,
+            snoozed_until: None,
+        };
+        let opened = open_url(Some(&cipher), url(&stored)).unwrap();
+        assert_eq!(opened.notes.as_deref(), Some("a sensitive thought"));
+        // Notes written before encryption was enabled still read back
+        let opened = open_url(Some(&cipher), url("written before")).unwrap();
+        assert_eq!(opened.notes.as_deref(), Some("written before"));
+    }
+
+    #[test]
+    fn test_import_notes_round_trip() {
+        let cipher = test_cipher();
+        let items = vec![
+            models::ImportItem {
+                url: "https://example.com".to_string(),
+                tags: vec!["rust".to_string()],
+                note: Some("a private comment".to_string()),
+            },
+            models::ImportItem {
+                url: "https://plain.com".to_string(),
+                tags: Vec::new(),
+                note: None,
+            },
+        ];
+
+        let sealed = seal_import_items(Some(&cipher), &items).unwrap();
+        let stored = sealed[0].note.clone().unwrap();
+        assert!(stored.starts_with(ENCRYPTED_PREFIX));
+        assert_eq!(
+            (sealed[0].url.as_str(), sealed[0].tags.as_slice()),
+            ("https://example.com", &items[0].tags[..])
+        );
+        assert_eq!(sealed[1], items[1]);
+        assert_eq!(seal_import_items(None, &items).unwrap(), items);
+
+        let pending = sealed
+            .into_iter()
+            .enumerate()
+            .map(|(id, item)| models::PendingImportItem {
+                id: id as i32,
+                started: false,
+                item,
+            })
+            .collect();
+        let opened: Vec<models::ImportItem> = open_import_items(Some(&cipher), pending)
+            .unwrap()
+            .into_iter()
+            .map(|pending| pending.item)
+            .collect();
+        assert_eq!(opened, items);
+    }
+
+    #[test]
+    fn test_wrong_key_fails() {
+        let encrypted = test_cipher().encrypt("secret").unwrap();
+        let other = SnippetCipher::from_base64_key(&STANDARD.encode([8u8; 32])).unwrap();
+        assert!(other.decrypt(&encrypted).is_err());
+    }
+
+    #[test]
+    fn test_rejects_short_key() {
+        assert!(SnippetCipher::from_base64_key(&STANDARD.encode([1u8; 16])).is_err());
+    }
+}
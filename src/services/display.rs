@@ -0,0 +1,78 @@
+//! `display_url`, the URL as listings show it, worked out in one place for both databases
+
+use crate::config::DisplayUrls;
+use std::sync::OnceLock;
+
+static SETTINGS: OnceLock<DisplayUrls> = OnceLock::new();
+
+/// Show URLs with `settings` from now on; only the first call has any effect
+pub fn configure(settings: &DisplayUrls) {
+    let _ = SETTINGS.set(settings.clone());
+}
+
+/// `url` as listings show it, with the configured settings
+pub fn display_url(url: &str) -> String {
+    format(SETTINGS.get_or_init(DisplayUrls::default), url)
+}
+
+/// `url` as `settings` show it
+pub fn format(settings: &DisplayUrls, url: &str) -> String {
+    let mut shown = url;
+    if settings.strip_scheme {
+        shown = shown
+            .strip_prefix("https://")
+            .or_else(|| shown.strip_prefix("http://"))
+            .unwrap_or(shown);
+    }
+    if settings.strip_query {
+        shown = shown.split('?').next().unwrap_or(shown);
+    }
+
+    match settings.max_length {
+        Some(max_length) if shown.chars().count() > max_length => {
+            let kept: String = shown.chars().take(max_length.saturating_sub(1)).collect();
+            format!("{}…", kept)
+        }
+        _ => shown.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URL: &str = "https://example.com/posts/rust?utm_source=feed&id=7";
+
+    #[test]
+    fn query_strings_are_dropped_by_default() {
+        assert_eq!(format(&DisplayUrls::default(), URL), "https://example.com/posts/rust");
+        assert_eq!(
+            format(&DisplayUrls::default(), "https://example.com"),
+            "https://example.com"
+        );
+    }
+
+    #[test]
+    fn scheme_and_query_are_kept_or_dropped_as_set() {
+        let settings = DisplayUrls {
+            strip_query: false,
+            strip_scheme: true,
+            max_length: None,
+        };
+        assert_eq!(format(&settings, URL), "example.com/posts/rust?utm_source=feed&id=7");
+        assert_eq!(format(&settings, "http://example.com/"), "example.com/");
+        assert_eq!(format(&settings, "ftp://example.com/"), "ftp://example.com/");
+    }
+
+    #[test]
+    fn long_urls_are_cut_with_an_ellipsis() {
+        let settings = DisplayUrls {
+            max_length: Some(20),
+            ..Default::default()
+        };
+        let shown = format(&settings, URL);
+        assert_eq!(shown, "https://example.com…");
+        assert_eq!(shown.chars().count(), 20);
+        assert_eq!(format(&settings, "https://example.com"), "https://example.com");
+    }
+}
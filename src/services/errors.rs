@@ -0,0 +1,203 @@
+//! Error responses worth reading: a page in the site's style for browsers, and a JSON message for everything else,
+//! in place of the bare text or empty bodies that actix and the plainer handlers send, and of JSON messages shown
+//! raw in a browser
+
+use crate::config::Config;
+use actix_web::body::{self, BodySize, EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header;
+use actix_web::http::StatusCode;
+use actix_web::middleware::Next;
+use actix_web::{web, HttpRequest, HttpResponse};
+use tera::{Context, Tera};
+
+/// Longest body taken as the error's message; anything longer is shown as the status alone
+const MESSAGE_LIMIT: usize = 1024;
+
+/// Whether the client would rather have a page than JSON, as a browser opening a link does
+pub fn wants_html(req: &HttpRequest) -> bool {
+    req.headers()
+        .get_all(header::ACCEPT)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|media_type| {
+            let media_type = media_type.split(';').next().unwrap_or_default().trim();
+            media_type.eq_ignore_ascii_case("text/html") || media_type.eq_ignore_ascii_case("application/xhtml+xml")
+        })
+}
+
+fn is_error(response: &HttpResponse<impl MessageBody>) -> bool {
+    let status = response.status();
+    status.is_client_error() || status.is_server_error()
+}
+
+fn content_type(response: &HttpResponse<impl MessageBody>) -> Option<&str> {
+    response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+}
+
+/// Whether the response is an error carrying nothing better than plain text; pages and JSON are left as they are
+fn is_bare_error(response: &HttpResponse<impl MessageBody>) -> bool {
+    is_error(response) && content_type(response).is_none_or(|content_type| content_type.starts_with("text/plain"))
+}
+
+/// Whether the response is an error in JSON short enough to be only a message, as `.json("...")` sends; a browser
+/// is better served by the page, while bigger JSON bodies are left alone as they carry more than a message
+fn is_json_error(response: &HttpResponse<impl MessageBody>) -> bool {
+    is_error(response)
+        && content_type(response).is_some_and(|content_type| content_type.starts_with("application/json"))
+        && matches!(response.body().size(), BodySize::Sized(size) if size <= MESSAGE_LIMIT as u64)
+}
+
+fn render(req: &HttpRequest, status: StatusCode, message: &str) -> Option<String> {
+    let tmpl = req.app_data::<web::Data<Tera>>()?;
+    let base_path = req
+        .app_data::<web::Data<Config>>()
+        .map(|config| config.base_path.clone())
+        .unwrap_or_default();
+
+    let mut context = Context::new();
+    context.insert("title", status.canonical_reason().unwrap_or("Error"));
+    context.insert("status", &status.as_u16());
+    context.insert("message", message);
+    context.insert("base_path", &base_path);
+    match tmpl.render("error.html", &context) {
+        Ok(rendered) => Some(rendered),
+        Err(e) => {
+            eprintln!("Template error: {:?}", e);
+            None
+        }
+    }
+}
+
+/// Swap bare error bodies for the error page or a JSON message, by what the request accepts, and JSON messages for
+/// the page when a browser asks. The handler's text becomes the message, and headers such as `Allow` or
+/// `WWW-Authenticate` are kept.
+pub async fn error_pages<B: MessageBody + 'static>(
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<EitherBody<B>>, actix_web::Error> {
+    let response = next.call(req).await?;
+    let html = wants_html(response.request());
+    let json = html && is_json_error(response.response());
+    if !json && !is_bare_error(response.response()) {
+        return Ok(response.map_into_left_body());
+    }
+
+    let (req, response) = response.into_parts();
+    let status = response.status();
+    let (head, body) = response.into_parts();
+    let bytes = match body::to_bytes_limited(body, MESSAGE_LIMIT).await {
+        Ok(Ok(bytes)) => bytes,
+        _ => Default::default(),
+    };
+    let text = if json {
+        match serde_json::from_slice::<String>(&bytes) {
+            Ok(message) => message.trim().to_string(),
+            // Not just a message after all, so it goes back as it was
+            Err(_) => {
+                let mut original = HttpResponse::build(status);
+                for (name, value) in head.headers() {
+                    if name != header::CONTENT_LENGTH {
+                        original.append_header((name.clone(), value.clone()));
+                    }
+                }
+                return Ok(ServiceResponse::new(req, original.body(bytes)).map_into_right_body());
+            }
+        }
+    } else {
+        String::from_utf8_lossy(&bytes).trim().to_string()
+    };
+    let message = if text.is_empty() {
+        status.canonical_reason().unwrap_or("Error").to_string()
+    } else {
+        text
+    };
+
+    let mut replacement = HttpResponse::build(status);
+    for (name, value) in head.headers() {
+        if name != header::CONTENT_TYPE && name != header::CONTENT_LENGTH {
+            replacement.append_header((name.clone(), value.clone()));
+        }
+    }
+    let replacement = if !html {
+        replacement.json(message)
+    } else if let Some(page) = render(&req, status, &message) {
+        replacement.content_type("text/html; charset=utf-8").body(page)
+    } else {
+        replacement.content_type("text/plain; charset=utf-8").body(message)
+    };
+    Ok(ServiceResponse::new(req, replacement).map_into_right_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::middleware::from_fn;
+    use actix_web::{test, App};
+
+    #[tokio::test]
+    async fn bare_errors_become_pages_or_json_by_what_is_accepted() {
+        let mut tera = Tera::default();
+        tera.add_raw_template("error.html", "<h1>{{ status }}</h1><p>{{ message }}</p>")
+            .unwrap();
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(error_pages))
+                .app_data(web::Data::new(tera))
+                .route(
+                    "/missing",
+                    web::get().to(|| async { HttpResponse::NotFound().body("URL not found") }),
+                )
+                .route(
+                    "/json",
+                    web::get().to(|| async { HttpResponse::NotFound().json("Snippet not found") }),
+                )
+                .route(
+                    "/detailed",
+                    web::get().to(|| async {
+                        HttpResponse::Conflict()
+                            .json(serde_json::json!({ "error": "Taken", "url": "https://a.example" }))
+                    }),
+                ),
+        )
+        .await;
+        let get = |path: &str, accept: &str| {
+            test::TestRequest::get()
+                .uri(path)
+                .insert_header((header::ACCEPT, accept))
+                .to_request()
+        };
+
+        let page = test::call_service(&app, get("/missing", "text/html,application/xhtml+xml;q=0.9")).await;
+        assert_eq!(page.status(), StatusCode::NOT_FOUND);
+        assert_eq!(test::read_body(page).await, "<h1>404</h1><p>URL not found</p>");
+
+        let api = test::call_service(&app, get("/missing", "*/*")).await;
+        assert_eq!(test::read_body(api).await, "\"URL not found\"");
+
+        // No route at all has an empty body, so the status names the problem
+        let unrouted = test::call_service(&app, get("/nowhere", "*/*")).await;
+        assert_eq!(test::read_body(unrouted).await, "\"Not Found\"");
+
+        // A JSON message is a page for a browser, and stays JSON for everyone else
+        let json = test::call_service(&app, get("/json", "text/html")).await;
+        assert_eq!(test::read_body(json).await, "<h1>404</h1><p>Snippet not found</p>");
+        let json = test::call_service(&app, get("/json", "application/json")).await;
+        assert_eq!(test::read_body(json).await, "\"Snippet not found\"");
+
+        // JSON carrying more than a message is left as it is, even to a browser
+        let detailed = test::call_service(&app, get("/detailed", "text/html")).await;
+        assert_eq!(detailed.status(), StatusCode::CONFLICT);
+        assert_eq!(
+            detailed.headers().get(header::CONTENT_TYPE).unwrap(),
+            "application/json"
+        );
+        assert_eq!(
+            test::read_body(detailed).await,
+            r#"{"error":"Taken","url":"https://a.example"}"#
+        );
+    }
+}
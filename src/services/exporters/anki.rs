@@ -0,0 +1,107 @@
+use crate::services::models;
+
+/// File headers telling Anki's importer how to read the notes, so they come in without picking options
+pub const HEADER: &str = "#separator:tab\n#html:true\n#notetype:Basic\n#columns:GUID\tFront\tBack\tTags\n\
+    #guid column:1\n#tags column:4\n";
+
+/// Escape text for a field read as HTML
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Make text safe as one field of a tab-separated line
+fn field(html: &str) -> String {
+    html.replace(['\t', '\r'], " ").replace('\n', "<br>")
+}
+
+/// A tag as Anki takes it: no spaces, and `::` between levels where this library uses `/`
+fn anki_tag(tag: &str) -> String {
+    tag.split_whitespace().collect::<Vec<_>>().join("_").replace('/', "::")
+}
+
+/// One note per snippet: the snippet on the front, where it came from and its tags on the back. The GUID is the
+/// snippet's id, so importing a newer export updates the cards already made instead of adding them again. Image
+/// snippets link their picture below `base_url`, so cards show it while this instance can be reached.
+pub fn note(snippet: &models::SnippetWithTags, base_url: &str) -> String {
+    let html = snippet.source.as_ref().and_then(|source| source.html.as_deref());
+    let front = if snippet.image_type.is_some() {
+        format!(
+            "<img src=\"{}/snippets/{}/image\"><br>{}",
+            escape(base_url),
+            snippet.id,
+            escape(&snippet.snippet)
+        )
+    } else {
+        html.map_or_else(|| escape(&snippet.snippet), str::to_string)
+    };
+    let page = snippet
+        .source
+        .as_ref()
+        .and_then(|source| source.title.as_deref())
+        .unwrap_or(&snippet.url);
+    let mut back = format!("<a href=\"{}\">{}</a>", escape(&snippet.url), escape(page));
+    if !snippet.tags.is_empty() {
+        back.push_str(&format!("<br>{}", escape(&snippet.tags.join(", "))));
+    }
+    let tags: Vec<String> = snippet.tags.iter().map(|tag| anki_tag(tag)).collect();
+
+    format!(
+        "read_it_later-snippet-{}\t{}\t{}\t{}\n",
+        snippet.id,
+        field(&front),
+        field(&back),
+        field(&tags.join(" "))
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE_URL: &str = "https://later.example";
+
+    fn snippet(text: &str, tags: &[&str]) -> models::SnippetWithTags {
+        models::SnippetWithTags {
+            id: 7,
+            snippet: text.to_string(),
+            snippet_html: None,
+            url: "https://example.com/post?a=1&b=2".to_string(),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            pinned: false,
+            source: None,
+            image_type: None,
+        }
+    }
+
+    #[test]
+    fn notes_are_one_escaped_line() {
+        assert_eq!(
+            note(&snippet("Use <T>\tand\nmore", &["lang/rust", "to read"]), BASE_URL),
+            "read_it_later-snippet-7\tUse &lt;T&gt; and<br>more\t\
+             <a href=\"https://example.com/post?a=1&amp;b=2\">https://example.com/post?a=1&amp;b=2</a>\
+             <br>lang/rust, to read\tlang::rust to_read\n"
+        );
+    }
+
+    #[test]
+    fn captured_markup_and_page_title_are_used() {
+        let mut snippet = snippet("A quote", &[]);
+        snippet.source = Some(models::SnippetSource {
+            html: Some("<p>A <em>quote</em></p>".to_string()),
+            title: Some("A post".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(
+            note(&snippet, BASE_URL),
+            "read_it_later-snippet-7\t<p>A <em>quote</em></p>\t\
+             <a href=\"https://example.com/post?a=1&amp;b=2\">A post</a>\t\n"
+        );
+
+        snippet.image_type = Some("image/png".to_string());
+        assert!(note(&snippet, BASE_URL)
+            .starts_with("read_it_later-snippet-7\t<img src=\"https://later.example/snippets/7/image\"><br>A quote\t"));
+    }
+}
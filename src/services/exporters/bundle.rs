@@ -0,0 +1,343 @@
+//! Everything under one tag, archived URLs and saved page copies included, packaged on its own so a finished
+//! topic can be kept outside the library
+
+use crate::services::exporters::{epub, markdown};
+use crate::services::{models, snapshot_cache, snapshots};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::io::{Cursor, Write};
+use zip::write::SimpleFileOptions;
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    #[default]
+    Json,
+    Markdown,
+    Epub,
+}
+
+/// Readable text of a URL's latest snapshot of a web page
+#[derive(Serialize, Debug)]
+pub struct SavedCopy {
+    pub datetime: chrono::NaiveDateTime,
+    pub text: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Item {
+    #[serde(flatten)]
+    pub url: models::UrlWithTags,
+    pub saved_copy: Option<SavedCopy>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Bundle {
+    pub tag: String,
+    pub exported_at: chrono::NaiveDateTime,
+    /// URLs carrying the tag or one nested under it, oldest first
+    pub urls: Vec<Item>,
+    /// Snippets taken from those URLs, and ones tagged themselves wherever they came from
+    pub snippets: Vec<models::SnippetWithTags>,
+}
+
+async fn saved_copy(database: &dyn models::Database, url_id: i32) -> Result<Option<SavedCopy>, sqlx::Error> {
+    let latest = database.get_snapshots(url_id).await?.into_iter().find(|snapshot| {
+        snapshot
+            .content_type
+            .as_deref()
+            .is_none_or(|content_type| content_type.starts_with("text/"))
+    });
+    Ok(match latest {
+        Some(snapshot) => snapshot_cache::get(database, url_id, snapshot.id)
+            .await?
+            .map(|content| SavedCopy {
+                datetime: content.datetime,
+                text: snapshots::page_text(&content.content),
+            }),
+        None => None,
+    })
+}
+
+/// Gather the bundle for `tag`
+pub async fn build(database: &dyn models::Database, tag: &str) -> Result<Bundle, sqlx::Error> {
+    let filter = models::ListFilter {
+        tags: models::TagFilter {
+            any_of: vec![vec![tag.to_string()]],
+            none_of: Vec::new(),
+        },
+        sort: models::Sort {
+            key: models::SortKey::Added,
+            descending: false,
+        },
+        ..Default::default()
+    };
+
+    let mut urls = Vec::new();
+    let mut snippets = database.get_snippets_with_tags(&filter).await?;
+    let mut seen: HashSet<i32> = snippets.iter().map(|snippet| snippet.id).collect();
+    for url in database.get_urls_with_tags(&filter).await? {
+        for snippet in database.get_snippets_for_url(&url.url).await? {
+            if seen.insert(snippet.id) {
+                snippets.push(snippet);
+            }
+        }
+        urls.push(Item {
+            saved_copy: saved_copy(database, url.id).await?,
+            url,
+        });
+    }
+
+    Ok(Bundle {
+        tag: tag.to_string(),
+        exported_at: chrono::Utc::now().naive_utc(),
+        urls,
+        snippets,
+    })
+}
+
+/// The tag made safe for a file name, such as `lang-rust` for `lang/rust`
+pub fn slug(tag: &str) -> String {
+    let slug: String = tag
+        .chars()
+        .map(|ch| {
+            if ch.is_ascii_alphanumeric() {
+                ch.to_ascii_lowercase()
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    let slug = slug.trim_matches('-');
+    if slug.is_empty() {
+        "tag".to_string()
+    } else {
+        slug.to_string()
+    }
+}
+
+impl Bundle {
+    /// Snippets taken from pages not in the bundle, which only show up because of their own tag
+    fn other_snippets(&self) -> Vec<&models::SnippetWithTags> {
+        let urls: HashSet<&str> = self.urls.iter().map(|item| item.url.url.as_str()).collect();
+        self.snippets
+            .iter()
+            .filter(|snippet| !urls.contains(snippet.url.as_str()))
+            .collect()
+    }
+
+    /// A zip of Markdown notes as `/export/markdown` writes them, each followed by its saved copy, and the
+    /// snippets from elsewhere in `snippets.md`
+    pub fn markdown(&self) -> Result<Vec<u8>, String> {
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+        let mut names = HashSet::new();
+
+        for item in &self.urls {
+            let url = models::ExportedUrl {
+                url: item.url.url.clone(),
+                url_hash: item.url.url_hash.clone(),
+                datetime: item.url.datetime,
+                read_at: item.url.read_at,
+                tags: item.url.tags.clone(),
+            };
+            let note = markdown::Note {
+                url: &url,
+                snippets: self.snippets.iter().filter(|snippet| snippet.url == url.url).collect(),
+            };
+            let name = markdown::filename(&note);
+            if !names.insert(name.clone()) {
+                continue;
+            }
+            let mut content = markdown::render(&note);
+            if let Some(copy) = &item.saved_copy {
+                content.push_str(&format!(
+                    "\n## Saved copy\n\n_Saved {}_\n\n",
+                    copy.datetime.format("%Y-%m-%d")
+                ));
+                for line in copy.text.lines() {
+                    content.push_str(&format!("{}\n\n", line));
+                }
+            }
+            zip.start_file(name, options).map_err(|err| err.to_string())?;
+            zip.write_all(content.as_bytes()).map_err(|err| err.to_string())?;
+        }
+
+        let others = self.other_snippets();
+        if !others.is_empty() {
+            let mut content = format!("# Other snippets tagged {}\n", self.tag);
+            for snippet in others {
+                content.push('\n');
+                for line in snippet.snippet.trim().lines() {
+                    content.push_str(&format!("> {}\n", line));
+                }
+                content.push_str(&format!("\n— <{}>\n", snippet.url));
+            }
+            zip.start_file("snippets.md", options).map_err(|err| err.to_string())?;
+            zip.write_all(content.as_bytes()).map_err(|err| err.to_string())?;
+        }
+
+        zip.finish()
+            .map(|cursor| cursor.into_inner())
+            .map_err(|err| err.to_string())
+    }
+
+    /// A book with a chapter per URL, its snippets and then its saved copy, and a last chapter of the snippets
+    /// from elsewhere
+    pub fn epub(&self) -> Result<Vec<u8>, String> {
+        let quote = |snippet: &models::SnippetWithTags| {
+            let lines: Vec<String> = snippet.snippet.trim().lines().map(epub::escape).collect();
+            format!("<blockquote><p>{}</p></blockquote>\n", lines.join("<br/>"))
+        };
+
+        let mut chapters: Vec<epub::Chapter> = self
+            .urls
+            .iter()
+            .map(|item| {
+                let url = &item.url;
+                let mut body = format!(
+                    "<h1>{}</h1>\n<p><a href=\"{}\">{}</a></p>\n",
+                    epub::escape(&url.display_title),
+                    epub::escape(&url.url),
+                    epub::escape(models::trimmed_url(&url.url))
+                );
+                if !url.tags.is_empty() {
+                    body.push_str(&format!("<p>Tags: {}</p>\n", epub::escape(&url.tags.join(", "))));
+                }
+                if let Some(notes) = &url.notes {
+                    body.push_str(&format!("<p><em>{}</em></p>\n", epub::escape(notes)));
+                }
+                for snippet in self.snippets.iter().filter(|snippet| snippet.url == url.url) {
+                    body.push_str(&quote(snippet));
+                }
+                if let Some(copy) = &item.saved_copy {
+                    body.push_str(&format!("<h2>Saved copy, {}</h2>\n", copy.datetime.format("%Y-%m-%d")));
+                    for line in copy.text.lines() {
+                        body.push_str(&format!("<p>{}</p>\n", epub::escape(line)));
+                    }
+                }
+                epub::Chapter {
+                    title: url.display_title.clone(),
+                    body,
+                }
+            })
+            .collect();
+
+        let others = self.other_snippets();
+        if !others.is_empty() {
+            let mut body = String::from("<h1>Other snippets</h1>\n");
+            for snippet in others {
+                body.push_str(&quote(snippet));
+                body.push_str(&format!(
+                    "<p>— <a href=\"{}\">{}</a></p>\n",
+                    epub::escape(&snippet.url),
+                    epub::escape(models::trimmed_url(&snippet.url))
+                ));
+            }
+            chapters.push(epub::Chapter {
+                title: "Other snippets".to_string(),
+                body,
+            });
+        }
+
+        epub::book(
+            &self.tag,
+            &format!("urn:read_it_later:tag:{}", self.tag),
+            self.exported_at,
+            &chapters,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn bundle() -> Bundle {
+        let datetime = chrono::NaiveDate::from_ymd_opt(2024, 5, 1)
+            .unwrap()
+            .and_hms_opt(9, 30, 0)
+            .unwrap();
+        let url = models::UrlWithTags {
+            id: 1,
+            url: "https://example.com/kyoto".to_string(),
+            url_hash: "1a2b3c4d5e6f".to_string(),
+            datetime,
+            tags: vec!["travel".to_string()],
+            display_url: "https://example.com/kyoto".to_string(),
+            display_title: "Kyoto in autumn".to_string(),
+            read_at: None,
+            short_id: "1a2b3c4d5e".to_string(),
+            pinned: false,
+            progress: 0,
+            metadata: None,
+            title: Some("Kyoto in autumn".to_string()),
+            notes: None,
+            archived_at: Some(datetime),
+            snoozed_until: None,
+        };
+        let snippet = |id: i32, url: &str| models::SnippetWithTags {
+            id,
+            snippet: format!("Snippet {}", id),
+            snippet_html: None,
+            url: url.to_string(),
+            tags: Vec::new(),
+            pinned: false,
+            source: None,
+            image_type: None,
+        };
+        Bundle {
+            tag: "travel".to_string(),
+            exported_at: datetime,
+            urls: vec![Item {
+                url,
+                saved_copy: Some(SavedCopy {
+                    datetime,
+                    text: "Temples & gardens\n".to_string(),
+                }),
+            }],
+            snippets: vec![
+                snippet(1, "https://example.com/kyoto"),
+                snippet(2, "https://example.org/elsewhere"),
+            ],
+        }
+    }
+
+    fn read(bytes: Vec<u8>, name: &str) -> String {
+        let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).unwrap();
+        let mut content = String::new();
+        archive.by_name(name).unwrap().read_to_string(&mut content).unwrap();
+        content
+    }
+
+    #[test]
+    fn markdown_notes_carry_the_saved_copy() {
+        let bundle = bundle();
+        let zip = bundle.markdown().unwrap();
+
+        let note = read(zip.clone(), "example-com-kyoto-1a2b3c4d5e.md");
+        assert!(note.contains("> Snippet 1\n"));
+        assert!(note.contains("## Saved copy\n\n_Saved 2024-05-01_\n\nTemples & gardens\n"));
+        let others = read(zip, "snippets.md");
+        assert!(others.contains("> Snippet 2\n"));
+        assert!(!others.contains("Snippet 1"));
+    }
+
+    #[test]
+    fn epub_chapters_are_escaped() {
+        let zip = bundle().epub().unwrap();
+
+        let chapter = read(zip.clone(), "OEBPS/chapter-1.xhtml");
+        assert!(chapter.contains("<h1>Kyoto in autumn</h1>"));
+        assert!(chapter.contains("<blockquote><p>Snippet 1</p></blockquote>"));
+        assert!(chapter.contains("<p>Temples &amp; gardens</p>"));
+        assert!(read(zip, "OEBPS/chapter-2.xhtml").contains("Snippet 2"));
+    }
+
+    #[test]
+    fn slugs_are_safe_file_names() {
+        assert_eq!(slug("lang/Rust"), "lang-rust");
+        assert_eq!(slug("//"), "tag");
+    }
+}
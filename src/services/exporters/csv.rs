@@ -0,0 +1,72 @@
+use crate::services::models;
+
+/// Column names, in the order `row` writes them
+pub const HEADER: [&str; 5] = ["url", "tags", "datetime", "read", "read_at"];
+
+const DATETIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// Encode one CSV record; the writer quotes commas, quotes and newlines as RFC 4180 requires
+fn record<I, T>(fields: I) -> Result<Vec<u8>, String>
+where
+    I: IntoIterator<Item = T>,
+    T: AsRef<[u8]>,
+{
+    let mut writer = ::csv::Writer::from_writer(Vec::new());
+    writer.write_record(fields).map_err(|err| err.to_string())?;
+    writer.into_inner().map_err(|err| err.to_string())
+}
+
+/// The header line
+pub fn header() -> Result<Vec<u8>, String> {
+    record(HEADER)
+}
+
+/// One line per saved URL; tags are joined the same way they're entered, with `, `
+pub fn row(url: &models::ExportedUrl) -> Result<Vec<u8>, String> {
+    record([
+        url.url.clone(),
+        url.tags.join(", "),
+        url.datetime.format(DATETIME_FORMAT).to_string(),
+        url.read_at.is_some().to_string(),
+        url.read_at
+            .map(|read_at| read_at.format(DATETIME_FORMAT).to_string())
+            .unwrap_or_default(),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exported(url: &str, tags: &[&str], read: bool) -> models::ExportedUrl {
+        let datetime = chrono::NaiveDate::from_ymd_opt(2024, 5, 1)
+            .unwrap()
+            .and_hms_opt(9, 30, 0)
+            .unwrap();
+        models::ExportedUrl {
+            url: url.to_string(),
+            url_hash: String::new(),
+            datetime,
+            read_at: read.then_some(datetime),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn writes_header_and_rows() {
+        assert_eq!(header().unwrap(), b"url,tags,datetime,read,read_at\n");
+        assert_eq!(
+            row(&exported("https://example.com/", &[], false)).unwrap(),
+            b"https://example.com/,,2024-05-01 09:30:00,false,\n"
+        );
+    }
+
+    #[test]
+    fn escapes_commas_and_quotes() {
+        let url = exported("https://example.com/?q=\"a,b\"", &["rust", "web"], true);
+        assert_eq!(
+            String::from_utf8(row(&url).unwrap()).unwrap(),
+            "\"https://example.com/?q=\"\"a,b\"\"\",\"rust, web\",2024-05-01 09:30:00,true,2024-05-01 09:30:00\n"
+        );
+    }
+}
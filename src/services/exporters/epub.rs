@@ -0,0 +1,172 @@
+use std::io::{Cursor, Write};
+use zip::write::SimpleFileOptions;
+
+/// One chapter of a book: its title in the table of contents, and its body as an XHTML fragment
+pub struct Chapter {
+    pub title: String,
+    pub body: String,
+}
+
+/// Escape text for XHTML content or a double-quoted attribute
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+const CONTAINER: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#;
+
+fn page(title: &str, body: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!DOCTYPE html>\n\
+         <html xmlns=\"http://www.w3.org/1999/xhtml\" xmlns:epub=\"http://www.idpf.org/2007/ops\">\n\
+         <head><meta charset=\"utf-8\"/><title>{}</title></head>\n<body>\n{}\n</body>\n</html>\n",
+        escape(title),
+        body
+    )
+}
+
+fn package(title: &str, identifier: &str, modified: chrono::NaiveDateTime, chapters: &[Chapter]) -> String {
+    let mut manifest = String::from(
+        "    <item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>\n",
+    );
+    let mut spine = String::new();
+    for index in 1..=chapters.len() {
+        manifest.push_str(&format!(
+            "    <item id=\"chapter-{index}\" href=\"chapter-{index}.xhtml\" media-type=\"application/xhtml+xml\"/>\n"
+        ));
+        spine.push_str(&format!("    <itemref idref=\"chapter-{index}\"/>\n"));
+    }
+
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <package xmlns=\"http://www.idpf.org/2007/opf\" version=\"3.0\" unique-identifier=\"id\">\n\
+         \x20 <metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n\
+         \x20   <dc:identifier id=\"id\">{}</dc:identifier>\n\
+         \x20   <dc:title>{}</dc:title>\n\
+         \x20   <dc:language>en</dc:language>\n\
+         \x20   <meta property=\"dcterms:modified\">{}</meta>\n\
+         \x20 </metadata>\n\
+         \x20 <manifest>\n{}  </manifest>\n\
+         \x20 <spine>\n{}  </spine>\n\
+         </package>\n",
+        escape(identifier),
+        escape(title),
+        modified.format("%Y-%m-%dT%H:%M:%SZ"),
+        manifest,
+        spine
+    )
+}
+
+fn navigation(title: &str, chapters: &[Chapter]) -> String {
+    let entries: String = chapters
+        .iter()
+        .enumerate()
+        .map(|(index, chapter)| {
+            format!(
+                "<li><a href=\"chapter-{}.xhtml\">{}</a></li>\n",
+                index + 1,
+                escape(&chapter.title)
+            )
+        })
+        .collect();
+    page(
+        title,
+        &format!(
+            "<nav epub:type=\"toc\"><h1>{}</h1>\n<ol>\n{}</ol></nav>",
+            escape(title),
+            entries
+        ),
+    )
+}
+
+/// An EPUB 3 book of `chapters` in order, with a table of contents. `identifier` should stay the same across
+/// exports of the same thing, so readers see a newer export as an update.
+pub fn book(
+    title: &str,
+    identifier: &str,
+    modified: chrono::NaiveDateTime,
+    chapters: &[Chapter],
+) -> Result<Vec<u8>, String> {
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    let stored = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+
+    // Readers find out what the file is from an uncompressed `mimetype` at the very start
+    let mut files = vec![
+        ("mimetype".to_string(), "application/epub+zip".to_string(), stored),
+        ("META-INF/container.xml".to_string(), CONTAINER.to_string(), options),
+        (
+            "OEBPS/content.opf".to_string(),
+            package(title, identifier, modified, chapters),
+            options,
+        ),
+        ("OEBPS/nav.xhtml".to_string(), navigation(title, chapters), options),
+    ];
+    for (index, chapter) in chapters.iter().enumerate() {
+        files.push((
+            format!("OEBPS/chapter-{}.xhtml", index + 1),
+            page(&chapter.title, &chapter.body),
+            options,
+        ));
+    }
+
+    for (name, content, options) in files {
+        zip.start_file(name, options).map_err(|err| err.to_string())?;
+        zip.write_all(content.as_bytes()).map_err(|err| err.to_string())?;
+    }
+
+    zip.finish()
+        .map(|cursor| cursor.into_inner())
+        .map_err(|err| err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn books_start_with_their_mimetype_and_list_every_chapter() {
+        let chapters = vec![
+            Chapter {
+                title: "Tokyo & Kyoto".to_string(),
+                body: "<p>First</p>".to_string(),
+            },
+            Chapter {
+                title: "Osaka".to_string(),
+                body: "<p>Second</p>".to_string(),
+            },
+        ];
+        let modified = chrono::NaiveDate::from_ymd_opt(2024, 5, 1)
+            .unwrap()
+            .and_hms_opt(9, 30, 0)
+            .unwrap();
+        let bytes = book("Japan", "urn:read_it_later:tag:japan", modified, &chapters).unwrap();
+        let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).unwrap();
+
+        let mimetype = archive.by_index(0).unwrap();
+        assert_eq!(mimetype.name(), "mimetype");
+        assert_eq!(mimetype.compression(), zip::CompressionMethod::Stored);
+        drop(mimetype);
+
+        let mut read = |name: &str| {
+            let mut content = String::new();
+            archive.by_name(name).unwrap().read_to_string(&mut content).unwrap();
+            content
+        };
+        let package = read("OEBPS/content.opf");
+        assert!(package.contains("<dc:title>Japan</dc:title>"));
+        assert!(package.contains("<meta property=\"dcterms:modified\">2024-05-01T09:30:00Z</meta>"));
+        assert!(package.contains("<itemref idref=\"chapter-2\"/>"));
+        assert!(read("OEBPS/nav.xhtml").contains("<a href=\"chapter-1.xhtml\">Tokyo &amp; Kyoto</a>"));
+        assert!(read("OEBPS/chapter-2.xhtml").contains("<p>Second</p>"));
+    }
+}
@@ -0,0 +1,198 @@
+//! Saved URLs as a JSON Feed 1.1 (https://www.jsonfeed.org/version/1.1/), for feed readers
+
+use crate::services::models;
+use serde::Serialize;
+
+pub const VERSION: &str = "https://jsonfeed.org/version/1.1";
+
+/// Items in the feed at most; readers only look at the newest, and the whole feed is fetched on every poll
+pub const ITEM_LIMIT: usize = 100;
+
+#[derive(Serialize, Debug)]
+pub struct Feed {
+    pub version: &'static str,
+    pub title: &'static str,
+    pub home_page_url: String,
+    pub feed_url: String,
+    pub description: &'static str,
+    pub items: Vec<Item>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Item {
+    pub id: String,
+    pub url: String,
+    pub title: String,
+    /// The first snippet, leaving the rest to `content_text`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    /// Every snippet, a blank line apart, or the URL itself when there are none; the spec requires some content
+    pub content_text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    /// RFC 3339
+    pub date_published: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<Attachment>,
+}
+
+/// An episode's audio file, so podcast-aware readers can play it
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Attachment {
+    pub url: String,
+    pub mime_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size_in_bytes: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_in_seconds: Option<u64>,
+}
+
+/// The item for a saved URL; `snippets` may include other URLs' snippets, which are skipped
+pub fn item(url: models::UrlWithTags, snippets: &[models::SnippetWithTags]) -> Item {
+    let snippets: Vec<&str> = snippets
+        .iter()
+        .filter(|snippet| snippet.url == url.url)
+        .map(|snippet| snippet.snippet.as_str())
+        .collect();
+
+    let (image, attachments) = match url.metadata {
+        Some(models::UrlMetadata::Video(video)) => (video.thumbnail_url, Vec::new()),
+        Some(models::UrlMetadata::Audio(audio)) => (
+            None,
+            vec![Attachment {
+                mime_type: audio.mime_type.unwrap_or_else(|| "audio/mpeg".to_string()),
+                url: audio.audio_url,
+                title: audio.title,
+                size_in_bytes: audio.length,
+                duration_in_seconds: audio.duration,
+            }],
+        ),
+        None => (None, Vec::new()),
+    };
+
+    Item {
+        title: url.display_title,
+        summary: snippets.first().map(|snippet| snippet.to_string()),
+        content_text: if snippets.is_empty() {
+            url.url.clone()
+        } else {
+            snippets.join("\n\n")
+        },
+        image,
+        date_published: url.datetime.and_utc().to_rfc3339(),
+        tags: url.tags,
+        attachments,
+        id: url.short_id,
+        url: url.url,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn saved(url: &str, metadata: Option<models::UrlMetadata>) -> models::UrlWithTags {
+        models::UrlWithTags {
+            id: 1,
+            url: url.to_string(),
+            url_hash: "abc123def456".to_string(),
+            datetime: chrono::NaiveDate::from_ymd_opt(2024, 5, 1)
+                .unwrap()
+                .and_hms_opt(9, 30, 0)
+                .unwrap(),
+            tags: vec!["rust".to_string()],
+            display_url: url.trim_start_matches("https://").to_string(),
+            display_title: models::display_title(None, metadata.as_ref(), url),
+            read_at: None,
+            short_id: "abc123".to_string(),
+            pinned: false,
+            progress: 0,
+            metadata,
+            title: None,
+            notes: None,
+            archived_at: None,
+            snoozed_until: None,
+        }
+    }
+
+    fn snippet(url: &str, text: &str) -> models::SnippetWithTags {
+        models::SnippetWithTags {
+            id: 1,
+            snippet: text.to_string(),
+            snippet_html: None,
+            url: url.to_string(),
+            tags: Vec::new(),
+            pinned: false,
+            source: None,
+            image_type: None,
+        }
+    }
+
+    #[test]
+    fn items_carry_snippets_and_tags() {
+        let snippets = [
+            snippet("https://example.com/post", "First quote"),
+            snippet("https://example.com/other", "Not this one"),
+            snippet("https://example.com/post", "Second quote"),
+        ];
+        let item = item(saved("https://example.com/post", None), &snippets);
+        assert_eq!(item.id, "abc123");
+        assert_eq!(item.title, "example.com/post");
+        assert_eq!(item.summary.as_deref(), Some("First quote"));
+        assert_eq!(item.content_text, "First quote\n\nSecond quote");
+        assert_eq!(item.date_published, "2024-05-01T09:30:00+00:00");
+        assert_eq!(item.tags, ["rust"]);
+
+        let json = serde_json::to_value(&item).unwrap();
+        assert!(json.get("attachments").is_none());
+        assert!(json.get("image").is_none());
+    }
+
+    #[test]
+    fn items_without_snippets_still_have_content() {
+        let item = item(saved("https://example.com/post", None), &[]);
+        assert_eq!(item.summary, None);
+        assert_eq!(item.content_text, "https://example.com/post");
+    }
+
+    #[test]
+    fn media_becomes_titles_images_and_attachments() {
+        let video = models::UrlMetadata::Video(models::VideoMetadata {
+            provider: "YouTube".to_string(),
+            title: Some("A talk".to_string()),
+            channel: None,
+            duration: Some(600),
+            thumbnail_url: Some("https://i.ytimg.com/vi/abc/hqdefault.jpg".to_string()),
+        });
+        let mut named = saved("https://youtu.be/abc", Some(video.clone()));
+        named.display_title = models::display_title(Some("Keynote"), named.metadata.as_ref(), &named.url);
+        assert_eq!(item(named, &[]).title, "Keynote");
+        let clip = item(saved("https://youtu.be/abc", Some(video)), &[]);
+        assert_eq!(clip.title, "A talk");
+        assert_eq!(clip.image.as_deref(), Some("https://i.ytimg.com/vi/abc/hqdefault.jpg"));
+
+        let audio = models::UrlMetadata::Audio(models::AudioMetadata {
+            audio_url: "https://cdn.example/ep12.mp3".to_string(),
+            mime_type: None,
+            length: Some(31_457_280),
+            duration: Some(3723),
+            title: Some("Episode 12".to_string()),
+        });
+        let episode = item(saved("https://podcast.example/12", Some(audio)), &[]);
+        assert_eq!(episode.title, "Episode 12");
+        assert_eq!(
+            episode.attachments,
+            [Attachment {
+                url: "https://cdn.example/ep12.mp3".to_string(),
+                mime_type: "audio/mpeg".to_string(),
+                title: Some("Episode 12".to_string()),
+                size_in_bytes: Some(31_457_280),
+                duration_in_seconds: Some(3723),
+            }]
+        );
+    }
+}
@@ -0,0 +1,192 @@
+use crate::services::models;
+use std::collections::HashSet;
+use std::io::{Cursor, Write};
+use zip::write::SimpleFileOptions;
+
+/// Longest slug taken from the URL before the short id is appended
+const MAX_SLUG_LENGTH: usize = 60;
+
+/// A saved URL with the snippets attached to it, ready to be written as a note
+pub struct Note<'a> {
+    pub url: &'a models::ExportedUrl,
+    pub snippets: Vec<&'a models::SnippetWithTags>,
+}
+
+/// Quote a front-matter value; a JSON string is also a valid YAML double-quoted scalar
+fn quote(value: &str) -> String {
+    serde_json::to_string(value).unwrap_or_default()
+}
+
+/// A filesystem-safe name such as `example-com-post-1a2b3c4d5e.md`; the short id keeps names unique
+pub fn filename(note: &Note) -> String {
+    let short_id = &note.url.url_hash[..models::SHORT_ID_LENGTH.min(note.url.url_hash.len())];
+    let mut slug = String::new();
+    for ch in models::trimmed_url(&note.url.url).chars() {
+        if slug.len() >= MAX_SLUG_LENGTH {
+            break;
+        }
+        if ch.is_ascii_alphanumeric() {
+            slug.push(ch.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+
+    if slug.is_empty() {
+        format!("{}.md", short_id)
+    } else {
+        format!("{}-{}.md", slug, short_id)
+    }
+}
+
+/// Render a note: YAML front-matter with the URL, tags and dates, then the attached snippets as quotes
+pub fn render(note: &Note) -> String {
+    let url = note.url;
+    let mut out = String::from("---\n");
+    out.push_str(&format!("url: {}\n", quote(&url.url)));
+    out.push_str(&format!("saved: {}\n", url.datetime.format("%Y-%m-%dT%H:%M:%S")));
+    if let Some(read_at) = url.read_at {
+        out.push_str(&format!("read: {}\n", read_at.format("%Y-%m-%dT%H:%M:%S")));
+    }
+    if url.tags.is_empty() {
+        out.push_str("tags: []\n");
+    } else {
+        out.push_str("tags:\n");
+        for tag in &url.tags {
+            out.push_str(&format!("  - {}\n", quote(tag)));
+        }
+    }
+    out.push_str("---\n\n");
+
+    out.push_str(&format!("# {}\n\n<{}>\n", models::trimmed_url(&url.url), url.url));
+
+    if !note.snippets.is_empty() {
+        out.push_str("\n## Snippets\n");
+        for snippet in &note.snippets {
+            out.push('\n');
+            for line in snippet.snippet.trim().lines() {
+                if line.trim().is_empty() {
+                    out.push_str(">\n");
+                } else {
+                    out.push_str(&format!("> {}\n", line));
+                }
+            }
+        }
+    }
+
+    out
+}
+
+/// Zip one Markdown file per note
+pub fn archive(notes: &[Note]) -> Result<Vec<u8>, String> {
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    let mut names = HashSet::new();
+
+    for note in notes {
+        let name = filename(note);
+        // Two URLs can only share a name if their hashes share a prefix; skip rather than corrupt the archive
+        if !names.insert(name.clone()) {
+            continue;
+        }
+        zip.start_file(name, options).map_err(|err| err.to_string())?;
+        zip.write_all(render(note).as_bytes()).map_err(|err| err.to_string())?;
+    }
+
+    zip.finish()
+        .map(|cursor| cursor.into_inner())
+        .map_err(|err| err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn exported(url: &str, tags: &[&str]) -> models::ExportedUrl {
+        models::ExportedUrl {
+            url: url.to_string(),
+            url_hash: "1a2b3c4d5e6f".to_string(),
+            datetime: chrono::NaiveDate::from_ymd_opt(2024, 5, 1)
+                .unwrap()
+                .and_hms_opt(9, 30, 0)
+                .unwrap(),
+            read_at: None,
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+        }
+    }
+
+    fn snippet(text: &str) -> models::SnippetWithTags {
+        models::SnippetWithTags {
+            id: 1,
+            snippet: text.to_string(),
+            snippet_html: None,
+            url: "https://example.com/post".to_string(),
+            tags: Vec::new(),
+            pinned: false,
+            source: None,
+            image_type: None,
+        }
+    }
+
+    #[test]
+    fn renders_front_matter_and_snippets() {
+        let url = exported("https://example.com/post?ref=feed", &["rust", "say \"hi\""]);
+        let quoted = snippet("First line\n\nSecond line");
+        let note = Note {
+            url: &url,
+            snippets: vec![&quoted],
+        };
+
+        assert_eq!(
+            render(&note),
+            "---\n\
+             url: \"https://example.com/post?ref=feed\"\n\
+             saved: 2024-05-01T09:30:00\n\
+             tags:\n  - \"rust\"\n  - \"say \\\"hi\\\"\"\n\
+             ---\n\n\
+             # example.com/post\n\n\
+             <https://example.com/post?ref=feed>\n\n\
+             ## Snippets\n\n\
+             > First line\n>\n> Second line\n"
+        );
+    }
+
+    #[test]
+    fn filenames_are_slugged_and_unique_per_url() {
+        let url = exported("https://Example.com/Some Post/", &[]);
+        let note = Note {
+            url: &url,
+            snippets: Vec::new(),
+        };
+        assert_eq!(filename(&note), "example-com-some-post-1a2b3c4d5e.md");
+
+        let url = exported("https://", &[]);
+        let note = Note {
+            url: &url,
+            snippets: Vec::new(),
+        };
+        assert_eq!(filename(&note), "1a2b3c4d5e.md");
+    }
+
+    #[test]
+    fn archive_contains_one_file_per_url() {
+        let url = exported("https://example.com/post", &[]);
+        let note = Note {
+            url: &url,
+            snippets: Vec::new(),
+        };
+
+        let bytes = archive(&[note]).unwrap();
+        let mut zip = zip::ZipArchive::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(zip.len(), 1);
+
+        let mut contents = String::new();
+        zip.by_name("example-com-post-1a2b3c4d5e.md")
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert!(contents.starts_with("---\nurl: \"https://example.com/post\"\n"));
+    }
+}
@@ -0,0 +1,265 @@
+//! Exports of the library into formats other tools can read
+
+pub mod anki;
+pub mod bundle;
+pub mod csv;
+pub mod epub;
+pub mod json_feed;
+pub mod markdown;
+pub mod ndjson;
+
+use crate::config::Config;
+use crate::services::{api, models};
+use actix_web::{get, web, HttpRequest, HttpResponse, Responder};
+use futures_util::stream;
+use serde::Deserialize;
+use std::sync::Arc;
+
+/// A zip of Markdown notes, one per saved URL, for Obsidian and other plain-text tools
+#[get("/export/markdown")]
+async fn export_markdown(database: web::Data<Arc<dyn models::Database>>) -> impl Responder {
+    let export = match api::build_account_export(database.get_ref().as_ref()).await {
+        Ok(export) => export,
+        Err(err) => {
+            eprintln!("Failed to export Markdown: {:?}", err);
+            return HttpResponse::InternalServerError().json("Failed to export Markdown");
+        }
+    };
+
+    let notes: Vec<markdown::Note> = export
+        .urls
+        .iter()
+        .map(|saved| markdown::Note {
+            url: &saved.url,
+            snippets: export
+                .snippets
+                .iter()
+                .filter(|snippet| snippet.url == saved.url.url)
+                .collect(),
+        })
+        .collect();
+
+    match markdown::archive(&notes) {
+        Ok(zip) => {
+            let filename = format!("read_it_later-markdown-{}.zip", export.exported_at.format("%Y%m%d"));
+            HttpResponse::Ok()
+                .content_type("application/zip")
+                .insert_header(("Content-Disposition", format!("attachment; filename=\"{}\"", filename)))
+                .body(zip)
+        }
+        Err(err) => {
+            eprintln!("Failed to build Markdown archive: {}", err);
+            HttpResponse::InternalServerError().json("Failed to export Markdown")
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct BundleQuery {
+    tag: String,
+    #[serde(default)]
+    format: bundle::Format,
+}
+
+/// Everything under one tag, archived URLs and the text of their saved copies included, as JSON, a zip of
+/// Markdown notes or an EPUB book
+#[get("/export")]
+async fn export_tag_bundle(
+    database: web::Data<Arc<dyn models::Database>>,
+    query: web::Query<BundleQuery>,
+) -> impl Responder {
+    let tag = query.tag.trim();
+    if tag.is_empty() {
+        return HttpResponse::BadRequest().json("tag is required");
+    }
+    let bundle = match bundle::build(database.get_ref().as_ref(), tag).await {
+        Ok(bundle) => bundle,
+        Err(err) => {
+            eprintln!("Failed to export tag {}: {:?}", tag, err);
+            return HttpResponse::InternalServerError().json("Failed to export tag");
+        }
+    };
+    let filename = format!(
+        "read_it_later-{}-{}",
+        bundle::slug(tag),
+        bundle.exported_at.format("%Y%m%d")
+    );
+
+    let (body, content_type, extension) = match query.format {
+        bundle::Format::Json => (
+            serde_json::to_vec(&bundle).map_err(|err| err.to_string()),
+            "application/json",
+            "json",
+        ),
+        bundle::Format::Markdown => (bundle.markdown(), "application/zip", "zip"),
+        bundle::Format::Epub => (bundle.epub(), "application/epub+zip", "epub"),
+    };
+    match body {
+        Ok(body) => HttpResponse::Ok()
+            .content_type(content_type)
+            .insert_header((
+                "Content-Disposition",
+                format!("attachment; filename=\"{}.{}\"", filename, extension),
+            ))
+            .body(body),
+        Err(err) => {
+            eprintln!("Failed to build the export of tag {}: {}", tag, err);
+            HttpResponse::InternalServerError().json("Failed to export tag")
+        }
+    }
+}
+
+/// Just the tag vocabulary, with colors and descriptions, for `POST /imports/tags` on another instance
+#[get("/export/tags")]
+async fn export_tags(database: web::Data<Arc<dyn models::Database>>) -> impl Responder {
+    match database.get_tag_details().await {
+        Ok(tags) => {
+            let filename = format!("read_it_later-tags-{}.json", chrono::Utc::now().format("%Y%m%d"));
+            HttpResponse::Ok()
+                .insert_header(("Content-Disposition", format!("attachment; filename=\"{}\"", filename)))
+                .json(models::TagTaxonomy { tags })
+        }
+        Err(err) => {
+            eprintln!("Failed to export tags: {:?}", err);
+            HttpResponse::InternalServerError().json("Failed to export tags")
+        }
+    }
+}
+
+/// One line per saved URL, sent row by row as it's encoded
+#[get("/export/csv")]
+async fn export_csv(database: web::Data<Arc<dyn models::Database>>) -> impl Responder {
+    let urls = match api::exported_urls(database.get_ref().as_ref()).await {
+        Ok(urls) => urls,
+        Err(err) => {
+            eprintln!("Failed to export CSV: {:?}", err);
+            return HttpResponse::InternalServerError().json("Failed to export CSV");
+        }
+    };
+
+    let lines = std::iter::once(csv::header())
+        .chain(urls.into_iter().map(|url| csv::row(&url)))
+        .map(|line| {
+            line.map(web::Bytes::from)
+                .map_err(actix_web::error::ErrorInternalServerError)
+        });
+    let filename = format!("read_it_later-{}.csv", chrono::Utc::now().format("%Y%m%d"));
+
+    HttpResponse::Ok()
+        .content_type("text/csv; charset=utf-8")
+        .insert_header(("Content-Disposition", format!("attachment; filename=\"{}\"", filename)))
+        .streaming(stream::iter(lines))
+}
+
+/// One JSON object per saved URL, streamed from a database cursor so large libraries export in constant memory
+#[get("/export/ndjson")]
+async fn export_ndjson(database: web::Data<Arc<dyn models::Database>>) -> impl Responder {
+    let filename = format!("read_it_later-{}.ndjson", chrono::Utc::now().format("%Y%m%d"));
+
+    HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .insert_header(("Content-Disposition", format!("attachment; filename=\"{}\"", filename)))
+        .streaming(ndjson::lines(database.get_ref().clone()))
+}
+
+/// Snippets as Anki notes, in the tab-separated form its importer reads; takes the same filters as `/snippets`
+#[get("/export/anki")]
+async fn export_anki(
+    req: HttpRequest,
+    database: web::Data<Arc<dyn models::Database>>,
+    config: web::Data<Config>,
+    query: web::Query<models::ListQuery>,
+) -> impl Responder {
+    let filter = match models::ListFilter::try_from(&*query) {
+        Ok(filter) => filter,
+        Err(err) => return HttpResponse::BadRequest().json(err),
+    };
+    let snippets = match database.get_snippets_with_tags(&filter).await {
+        // Text fields are escaped as they are written, so only the captured markup needs cleaning
+        Ok(snippets) => snippets
+            .into_iter()
+            .map(|mut snippet| {
+                snippet.source = snippet.source.take().and_then(|source| source.sanitized(&snippet.url));
+                snippet
+            })
+            .collect::<Vec<_>>(),
+        Err(err) => {
+            eprintln!("Failed to export Anki notes: {:?}", err);
+            return HttpResponse::InternalServerError().json("Failed to export Anki notes");
+        }
+    };
+
+    let connection = req.connection_info();
+    let base_url = format!("{}://{}{}", connection.scheme(), connection.host(), config.base_path);
+    let mut body = String::from(anki::HEADER);
+    for snippet in &snippets {
+        body.push_str(&anki::note(snippet, &base_url));
+    }
+    let filename = format!("read_it_later-anki-{}.txt", chrono::Utc::now().format("%Y%m%d"));
+
+    HttpResponse::Ok()
+        .content_type("text/tab-separated-values; charset=utf-8")
+        .insert_header(("Content-Disposition", format!("attachment; filename=\"{}\"", filename)))
+        .body(body)
+}
+
+/// The newest saved URLs, with their tags and snippets, as a JSON Feed; takes the same filters as `/urls_with_tags`
+#[get("/feed.json")]
+async fn export_json_feed(
+    req: HttpRequest,
+    database: web::Data<Arc<dyn models::Database>>,
+    config: web::Data<Config>,
+    query: web::Query<models::ListQuery>,
+) -> impl Responder {
+    let filter = match models::ListFilter::try_from(&*query) {
+        Ok(filter) => filter,
+        Err(err) => return HttpResponse::BadRequest().json(err),
+    };
+    let fetched = match database.get_urls_with_tags(&filter).await {
+        Ok(urls) => database
+            .get_snippets_with_tags(&models::ListFilter::default())
+            .await
+            .map(|snippets| (urls, snippets)),
+        Err(err) => Err(err),
+    };
+    let (urls, snippets) = match fetched {
+        Ok(fetched) => fetched,
+        Err(err) => {
+            eprintln!("Failed to build the JSON Feed: {:?}", err);
+            return HttpResponse::InternalServerError().json("Failed to build the JSON Feed");
+        }
+    };
+
+    let connection = req.connection_info();
+    let home_page_url = format!("{}://{}{}/", connection.scheme(), connection.host(), config.base_path);
+    let feed = json_feed::Feed {
+        version: json_feed::VERSION,
+        title: "Read it Later",
+        feed_url: format!("{}feed.json", home_page_url),
+        home_page_url,
+        description: "Saved URLs and their snippets",
+        items: urls
+            .into_iter()
+            .take(json_feed::ITEM_LIMIT)
+            .map(|url| json_feed::item(url, &snippets))
+            .collect(),
+    };
+
+    match serde_json::to_string(&feed) {
+        Ok(body) => HttpResponse::Ok().content_type("application/feed+json").body(body),
+        Err(err) => {
+            eprintln!("Failed to encode the JSON Feed: {:?}", err);
+            HttpResponse::InternalServerError().json("Failed to build the JSON Feed")
+        }
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(export_markdown)
+        .service(export_tag_bundle)
+        .service(export_csv)
+        .service(export_anki)
+        .service(export_ndjson)
+        .service(export_tags)
+        .service(export_json_feed);
+}
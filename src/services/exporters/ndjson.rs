@@ -0,0 +1,93 @@
+use crate::services::models;
+use actix_web::web::Bytes;
+use futures_util::stream::{self, Stream, StreamExt};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+/// Lines encoded ahead of a slow client before the database cursor waits
+const BUFFER_LINES: usize = 64;
+
+/// One JSON object followed by a newline
+pub fn line(url: &models::ExportedUrl) -> Result<Vec<u8>, serde_json::Error> {
+    let mut line = serde_json::to_vec(url)?;
+    line.push(b'\n');
+    Ok(line)
+}
+
+/// Encode URLs straight off the database cursor. The cursor borrows the database, so a task owns
+/// both and hands lines over a bounded channel; it stops when the client goes away.
+pub fn lines(database: Arc<dyn models::Database>) -> impl Stream<Item = Result<Bytes, actix_web::Error>> {
+    let (sender, receiver) = mpsc::channel(BUFFER_LINES);
+
+    actix_web::rt::spawn(async move {
+        let mut urls = database.stream_exported_urls();
+        while let Some(url) = urls.next().await {
+            let line = match url {
+                Ok(url) => line(&url)
+                    .map(Bytes::from)
+                    .map_err(actix_web::error::ErrorInternalServerError),
+                Err(err) => {
+                    eprintln!("Failed to stream NDJSON export: {:?}", err);
+                    Err(actix_web::error::ErrorInternalServerError("Failed to export NDJSON"))
+                }
+            };
+            let failed = line.is_err();
+            if sender.send(line).await.is_err() || failed {
+                break;
+            }
+        }
+    });
+
+    stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|line| (line, receiver))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::models::Database;
+    use crate::services::sqlite_database::SqliteDatabase;
+
+    #[test]
+    fn writes_one_object_per_line() {
+        let url = models::ExportedUrl {
+            url: "https://example.com/".to_string(),
+            url_hash: "abc".to_string(),
+            datetime: chrono::NaiveDate::from_ymd_opt(2024, 5, 1)
+                .unwrap()
+                .and_hms_opt(9, 30, 0)
+                .unwrap(),
+            read_at: None,
+            tags: vec!["rust".to_string()],
+        };
+
+        assert_eq!(
+            String::from_utf8(line(&url).unwrap()).unwrap(),
+            "{\"url\":\"https://example.com/\",\"url_hash\":\"abc\",\"datetime\":\"2024-05-01T09:30:00\",\"read_at\":null,\"tags\":[\"rust\"]}\n"
+        );
+    }
+
+    #[actix_web::test]
+    async fn streams_every_url() {
+        let database = SqliteDatabase::new(":memory:").await.unwrap();
+        database.initialize().await.unwrap();
+        database.insert_url("https://example.com/one").await.unwrap();
+        database
+            .insert_tags("https://example.com/two", &["rust", "web"])
+            .await
+            .unwrap();
+
+        let chunks: Vec<Bytes> = lines(Arc::new(database)).map(|chunk| chunk.unwrap()).collect().await;
+        let urls: Vec<serde_json::Value> = chunks
+            .iter()
+            .map(|chunk| serde_json::from_slice(chunk).unwrap())
+            .collect();
+
+        assert_eq!(urls.len(), 2);
+        assert_eq!(urls[0]["url"], "https://example.com/one");
+        assert_eq!(urls[0]["tags"], serde_json::json!([]));
+        assert_eq!(urls[1]["url"], "https://example.com/two");
+        assert_eq!(urls[1]["tags"], serde_json::json!(["rust", "web"]));
+    }
+}
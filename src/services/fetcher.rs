@@ -0,0 +1,558 @@
+//! The way background lookups reach other sites: one client sending the configured user agent, limits on how
+//! many requests run at once overall and per host, robots.txt for page fetches, and backing off from hosts
+//! that keep failing
+
+use crate::config;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, COOKIE};
+use reqwest::{StatusCode, Url};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// How long a site's robots.txt is followed before it is fetched again
+const ROBOTS_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Give up on a robots.txt that takes longer than this
+const ROBOTS_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How much of a robots.txt is read; RFC 9309 lets crawlers stop at 500 KiB
+const ROBOTS_LIMIT: usize = 500 * 1024;
+
+/// Hosts kept track of at most; idle ones are forgotten when it fills up
+const HOST_CAPACITY: usize = 4096;
+
+static FETCHER: OnceLock<Fetcher> = OnceLock::new();
+
+/// Fetch with `settings` from now on; only the first call has any effect
+pub fn configure(settings: &config::Fetching) {
+    let _ = FETCHER.set(Fetcher::new(settings.clone()));
+}
+
+fn fetcher() -> &'static Fetcher {
+    FETCHER.get_or_init(|| Fetcher::new(config::Fetching::default()))
+}
+
+/// Fetch a page, such as a saved URL, unless the site's robots.txt disallows it. The site's profile, if it
+/// has one, adds its headers and cookies.
+pub async fn page(url: &str, timeout: Duration) -> Result<Response, String> {
+    let url = Url::parse(url).map_err(|err| err.to_string())?;
+    let fetcher = fetcher();
+    if !fetcher.allows(&url).await? {
+        return Err(format!("robots.txt disallows fetching {}", url));
+    }
+    let headers = url.host_str().and_then(|host| fetcher.settings.profiles.for_host(host));
+    fetcher.get(url, timeout, headers).await
+}
+
+/// Call a site's API, such as an oEmbed endpoint. robots.txt is meant for crawlers, so it isn't checked.
+pub async fn api(url: Url, timeout: Duration) -> Result<Response, String> {
+    fetcher().get(url, timeout, None).await
+}
+
+/// Headers and cookies sent with page fetches from particular sites, such as subscriptions you log in to.
+/// A profile for `example.com` covers its subdomains too, unless one of them has its own.
+#[derive(Clone, Debug, Default)]
+pub struct Profiles {
+    domains: Vec<(String, HeaderMap)>,
+}
+
+/// One site's entry in the profiles file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProfileEntry {
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    #[serde(default)]
+    cookies: BTreeMap<String, String>,
+}
+
+impl Profiles {
+    /// Parse a JSON object of domains, each with `headers` and `cookies` objects of names to values
+    pub fn parse(json: &str) -> Result<Self, String> {
+        let entries: BTreeMap<String, ProfileEntry> = serde_json::from_str(json).map_err(|err| err.to_string())?;
+        let mut domains = Vec::new();
+
+        for (domain, entry) in entries {
+            let domain = domain.trim().trim_start_matches('.').to_ascii_lowercase();
+            let mut headers = HeaderMap::new();
+            for (name, value) in &entry.headers {
+                let name = HeaderName::from_bytes(name.as_bytes())
+                    .map_err(|_| format!("{} has an invalid header name: {}", domain, name))?;
+                headers.insert(name, sensitive(&domain, value)?);
+            }
+            if !entry.cookies.is_empty() {
+                let cookies: Vec<String> = entry
+                    .cookies
+                    .iter()
+                    .map(|(name, value)| format!("{}={}", name, value))
+                    .collect();
+                headers.insert(COOKIE, sensitive(&domain, &cookies.join("; "))?);
+            }
+            domains.push((domain, headers));
+        }
+        Ok(Self { domains })
+    }
+
+    pub fn len(&self) -> usize {
+        self.domains.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.domains.is_empty()
+    }
+
+    /// The headers for the most specific domain covering `host`
+    fn for_host(&self, host: &str) -> Option<&HeaderMap> {
+        let host = host.to_ascii_lowercase();
+        self.domains
+            .iter()
+            .filter(|(domain, _)| host == *domain || host.ends_with(&format!(".{}", domain)))
+            .max_by_key(|(domain, _)| domain.len())
+            .map(|(_, headers)| headers)
+    }
+}
+
+/// A header value kept out of `Debug` output, as profiles carry logins
+fn sensitive(domain: &str, value: &str) -> Result<HeaderValue, String> {
+    let mut value = HeaderValue::from_str(value).map_err(|_| format!("{} has an invalid header value", domain))?;
+    value.set_sensitive(true);
+    Ok(value)
+}
+
+/// A response that holds its place in the limits until dropped, so reading the body counts as part of the request
+pub struct Response {
+    inner: reqwest::Response,
+    _slots: [OwnedSemaphorePermit; 2],
+}
+
+impl Response {
+    /// Turn a 4xx status into an error; 429 and 5xx already are one
+    pub fn error_for_status(self) -> Result<Self, String> {
+        if let Err(err) = self.inner.error_for_status_ref() {
+            return Err(err.to_string());
+        }
+        Ok(self)
+    }
+
+    pub async fn json<T: DeserializeOwned>(self) -> Result<T, String> {
+        self.inner.json().await.map_err(|err| err.to_string())
+    }
+}
+
+impl Deref for Response {
+    type Target = reqwest::Response;
+
+    fn deref(&self) -> &reqwest::Response {
+        &self.inner
+    }
+}
+
+impl DerefMut for Response {
+    fn deref_mut(&mut self) -> &mut reqwest::Response {
+        &mut self.inner
+    }
+}
+
+struct Fetcher {
+    client: reqwest::Client,
+    settings: config::Fetching,
+    /// Shared by every host, on top of each host's own
+    slots: Arc<Semaphore>,
+    hosts: Mutex<HashMap<String, Host>>,
+}
+
+/// What is known about one host
+struct Host {
+    slots: Arc<Semaphore>,
+    robots: Option<(Instant, Arc<Robots>)>,
+    /// Failed requests in a row, and when the host may be tried again after the last one
+    failures: u32,
+    retry_at: Option<Instant>,
+}
+
+impl Fetcher {
+    fn new(settings: config::Fetching) -> Self {
+        let client = reqwest::Client::builder()
+            .user_agent(&settings.user_agent)
+            .build()
+            .expect("Failed to build the HTTP client");
+        Self {
+            client,
+            slots: Arc::new(Semaphore::new(settings.concurrency)),
+            settings,
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Work on the entry for `key`, adding it if new
+    fn host<R>(&self, key: &str, update: impl FnOnce(&mut Host) -> R) -> R {
+        let mut hosts = self.hosts.lock().unwrap();
+        if !hosts.contains_key(key) && hosts.len() >= HOST_CAPACITY {
+            let now = Instant::now();
+            // A host's slots are only shared while a request to it is waiting or running
+            hosts.retain(|_, host| Arc::strong_count(&host.slots) > 1 || host.retry_at.is_some_and(|at| at > now));
+        }
+        let host = hosts.entry(key.to_string()).or_insert_with(|| Host {
+            slots: Arc::new(Semaphore::new(self.settings.host_concurrency)),
+            robots: None,
+            failures: 0,
+            retry_at: None,
+        });
+        update(host)
+    }
+
+    /// Send a GET once there are free slots, with `timeout` covering the wait as well as the request
+    async fn get(&self, url: Url, timeout: Duration, headers: Option<&HeaderMap>) -> Result<Response, String> {
+        let key = host_key(&url).ok_or_else(|| format!("{} has no host", url))?;
+        let (host_slots, retry_at) = self.host(&key, |host| (host.slots.clone(), host.retry_at));
+        if let Some(wait) = retry_at.and_then(|at| at.checked_duration_since(Instant::now())) {
+            return Err(format!("backing off from {} for another {}s", key, wait.as_secs() + 1));
+        }
+
+        let started = Instant::now();
+        let waiting = async {
+            Ok::<_, tokio::sync::AcquireError>([
+                host_slots.acquire_owned().await?,
+                self.slots.clone().acquire_owned().await?,
+            ])
+        };
+        let slots = tokio::time::timeout(timeout, waiting)
+            .await
+            .map_err(|_| format!("timed out waiting to fetch from {}", key))?
+            .map_err(|err| err.to_string())?;
+
+        let result = self
+            .client
+            .get(url)
+            .headers(headers.cloned().unwrap_or_default())
+            .timeout(timeout.saturating_sub(started.elapsed()))
+            .send()
+            .await;
+        match result {
+            Ok(response) if is_failure(response.status()) => {
+                self.failed(&key, retry_after(&response));
+                Err(format!("{} answered {}", key, response.status()))
+            }
+            Ok(response) => {
+                self.host(&key, |host| {
+                    host.failures = 0;
+                    host.retry_at = None;
+                });
+                Ok(Response {
+                    inner: response,
+                    _slots: slots,
+                })
+            }
+            Err(err) => {
+                self.failed(&key, None);
+                Err(err.to_string())
+            }
+        }
+    }
+
+    /// Leave a host alone for a while, longer for each failure in a row and at least as long as it asked
+    fn failed(&self, key: &str, retry_after: Option<Duration>) {
+        let (base, max) = (self.settings.backoff, self.settings.max_backoff);
+        self.host(key, |host| {
+            host.failures += 1;
+            let delay = backoff_delay(base, max, host.failures).max(retry_after.unwrap_or_default().min(max));
+            host.retry_at = Some(Instant::now() + delay);
+        });
+    }
+
+    /// Whether robots.txt lets this user agent fetch `url`, fetching the file when it isn't known yet
+    async fn allows(&self, url: &Url) -> Result<bool, String> {
+        if !self.settings.robots_txt {
+            return Ok(true);
+        }
+        let key = host_key(url).ok_or_else(|| format!("{} has no host", url))?;
+        let cached = self.host(&key, |host| {
+            host.robots
+                .as_ref()
+                .filter(|(fetched_at, _)| fetched_at.elapsed() < ROBOTS_TTL)
+                .map(|(_, robots)| robots.clone())
+        });
+        let robots = match cached {
+            Some(robots) => robots,
+            None => {
+                let robots = Arc::new(self.fetch_robots(url).await?);
+                self.host(&key, |host| host.robots = Some((Instant::now(), robots.clone())));
+                robots
+            }
+        };
+
+        let path = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        };
+        Ok(robots.allows(agent_token(&self.settings.user_agent), &path))
+    }
+
+    async fn fetch_robots(&self, url: &Url) -> Result<Robots, String> {
+        let mut robots_url = url.clone();
+        robots_url.set_path("/robots.txt");
+        robots_url.set_query(None);
+        robots_url.set_fragment(None);
+
+        let mut response = self.get(robots_url, ROBOTS_TIMEOUT, None).await?;
+        // A site without a robots.txt, or that won't show it, sets no rules
+        if !response.status().is_success() {
+            return Ok(Robots::default());
+        }
+        let mut body = Vec::new();
+        while body.len() < ROBOTS_LIMIT {
+            match response.chunk().await.map_err(|err| err.to_string())? {
+                Some(chunk) => body.extend_from_slice(&chunk),
+                None => break,
+            }
+        }
+        body.truncate(ROBOTS_LIMIT);
+        Ok(Robots::parse(&String::from_utf8_lossy(&body)))
+    }
+}
+
+/// The host and any port of a URL, which limits and backoff are kept per
+fn host_key(url: &Url) -> Option<String> {
+    let host = url.host_str()?.to_ascii_lowercase();
+    Some(match url.port() {
+        Some(port) => format!("{}:{}", host, port),
+        None => host,
+    })
+}
+
+/// Statuses that mean the host is struggling or wants fewer requests, rather than that the page is missing
+fn is_failure(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// A `Retry-After` header given in seconds; the date form is rare enough to fall back to the usual backoff
+fn retry_after(response: &reqwest::Response) -> Option<Duration> {
+    let value = response.headers().get(reqwest::header::RETRY_AFTER)?.to_str().ok()?;
+    value.trim().parse().ok().map(Duration::from_secs)
+}
+
+/// How long to leave a host alone after `failures` failures in a row: `base`, doubling each time up to `max`
+fn backoff_delay(base: Duration, max: Duration, failures: u32) -> Duration {
+    base.saturating_mul(2u32.saturating_pow(failures.saturating_sub(1)))
+        .min(max)
+}
+
+/// The name robots.txt groups are matched against: the user agent up to its version or comment,
+/// e.g. `read_it_later` for `read_it_later/0.1 (+https://example.com)`
+fn agent_token(user_agent: &str) -> &str {
+    user_agent.split(['/', ' ']).next().unwrap_or(user_agent)
+}
+
+/// The rules of a robots.txt, in the groups of user agents they apply to
+#[derive(Debug, Default)]
+struct Robots {
+    groups: Vec<Group>,
+}
+
+#[derive(Debug, Default)]
+struct Group {
+    agents: Vec<String>,
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug)]
+struct Rule {
+    allow: bool,
+    pattern: String,
+}
+
+impl Robots {
+    fn parse(text: &str) -> Self {
+        let mut groups: Vec<Group> = Vec::new();
+        // Consecutive `User-agent` lines share the rules that follow them
+        let mut naming_agents = false;
+
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or_default();
+            let Some((field, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            match field.trim().to_ascii_lowercase().as_str() {
+                "user-agent" => {
+                    if !naming_agents {
+                        groups.push(Group::default());
+                    }
+                    if let Some(group) = groups.last_mut() {
+                        group.agents.push(value.to_ascii_lowercase());
+                    }
+                    naming_agents = true;
+                }
+                rule @ ("allow" | "disallow") => {
+                    naming_agents = false;
+                    // An empty `Disallow:` allows everything, which is the same as having no rule
+                    if let (Some(group), false) = (groups.last_mut(), value.is_empty()) {
+                        group.rules.push(Rule {
+                            allow: rule == "allow",
+                            pattern: value.to_string(),
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+        Self { groups }
+    }
+
+    /// Whether `agent` may fetch `path`, following the groups naming it, or else the `*` groups.
+    ///
+    /// The most specific (longest) matching rule wins, with `Allow` winning a tie; no match allows.
+    fn allows(&self, agent: &str, path: &str) -> bool {
+        let agent = agent.to_ascii_lowercase();
+        let naming = |name: &str| -> Vec<&Group> {
+            self.groups
+                .iter()
+                .filter(|group| group.agents.iter().any(|listed| listed == name))
+                .collect()
+        };
+        let mut groups = naming(&agent);
+        if groups.is_empty() {
+            groups = naming("*");
+        }
+
+        groups
+            .iter()
+            .flat_map(|group| &group.rules)
+            .filter(|rule| pattern_matches(&rule.pattern, path))
+            .max_by_key(|rule| (rule.pattern.len(), rule.allow))
+            .is_none_or(|rule| rule.allow)
+    }
+}
+
+/// Whether a robots.txt pattern matches the start of `path`; `*` matches any run of characters and a
+/// trailing `$` anchors the pattern to the end
+fn pattern_matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+    let mut parts = pattern.split('*');
+    let Some(mut rest) = path.strip_prefix(parts.next().unwrap_or_default()) else {
+        return false;
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    for (index, part) in parts.iter().enumerate() {
+        if anchored && index + 1 == parts.len() {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    !anchored || rest.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROBOTS: &str = "
+        # Everyone
+        User-agent: *
+        Disallow: /private/
+        Allow: /private/open
+        Disallow: /*.pdf$
+
+        User-agent: Read_it_later
+        User-agent: otherbot
+        Disallow: /drafts
+        Disallow:
+
+        Sitemap: https://example.com/sitemap.xml
+    ";
+
+    #[test]
+    fn robots_rules_follow_the_most_specific_match() {
+        let robots = Robots::parse(ROBOTS);
+        let anyone = |path| robots.allows("somebot", path);
+        assert!(anyone("/"));
+        assert!(!anyone("/private/notes"));
+        assert!(anyone("/private/open/letter"));
+        assert!(!anyone("/papers/paper.pdf"));
+        assert!(anyone("/papers/paper.pdf?download=1"));
+
+        // A group naming the agent replaces the `*` one
+        assert!(robots.allows("read_it_later", "/private/notes"));
+        assert!(!robots.allows("read_it_later", "/drafts/1"));
+        assert!(!robots.allows("otherbot", "/drafts"));
+
+        assert!(Robots::parse("").allows("read_it_later", "/anything"));
+        assert!(!Robots::parse("User-agent: *\nDisallow: /").allows("read_it_later", "/anything"));
+    }
+
+    #[test]
+    fn robots_patterns_support_wildcards_and_anchors() {
+        assert!(pattern_matches("/fish", "/fish.html"));
+        assert!(!pattern_matches("/fish", "/Fish"));
+        assert!(pattern_matches("/*.php", "/folder/index.php?x=1"));
+        assert!(pattern_matches("/*.php$", "/index.php"));
+        assert!(!pattern_matches("/*.php$", "/index.php5"));
+        assert!(pattern_matches("/a*b*c", "/a-b-c-d"));
+        assert!(!pattern_matches("/a*c*b", "/a-b-c"));
+        assert!(pattern_matches("/exact$", "/exact"));
+        assert!(!pattern_matches("/exact$", "/exactly"));
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let (base, max) = (Duration::from_secs(30), Duration::from_secs(300));
+        assert_eq!(backoff_delay(base, max, 1), Duration::from_secs(30));
+        assert_eq!(backoff_delay(base, max, 2), Duration::from_secs(60));
+        assert_eq!(backoff_delay(base, max, 4), Duration::from_secs(240));
+        assert_eq!(backoff_delay(base, max, 5), max);
+        assert_eq!(backoff_delay(base, max, 100), max);
+    }
+
+    #[test]
+    fn profiles_cover_a_domain_and_its_subdomains() {
+        let profiles = Profiles::parse(
+            r#"{
+                "Example.com": {"cookies": {"session": "abc", "theme": "dark"}},
+                "news.example.com": {"headers": {"Authorization": "Bearer xyz"}}
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(profiles.len(), 2);
+
+        let cookie = |host| {
+            profiles
+                .for_host(host)
+                .and_then(|headers| headers.get(COOKIE))
+                .map(|value| value.to_str().unwrap().to_string())
+        };
+        assert_eq!(cookie("example.com").as_deref(), Some("session=abc; theme=dark"));
+        assert_eq!(cookie("www.EXAMPLE.com").as_deref(), Some("session=abc; theme=dark"));
+        assert_eq!(cookie("notexample.com"), None);
+        // The more specific profile replaces the broader one
+        assert_eq!(cookie("news.example.com"), None);
+        let news = profiles.for_host("news.example.com").unwrap();
+        assert_eq!(news.get("authorization").unwrap(), "Bearer xyz");
+        assert!(!format!("{:?}", news).contains("xyz"));
+
+        assert!(Profiles::parse(r#"{"example.com": {"header": {}}}"#).is_err());
+        assert!(Profiles::parse(r#"{"example.com": {"headers": {"Bad Name": "x"}}}"#).is_err());
+        assert!(Profiles::parse(
+            r#"{"example.com": {"cookies": {"a": "line
+break"}}}"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn robots_agent_is_the_product_name() {
+        assert_eq!(agent_token("read_it_later/0.1.0"), "read_it_later");
+        assert_eq!(agent_token("mybot (+https://example.com)"), "mybot");
+        assert_eq!(agent_token("plain"), "plain");
+    }
+}
@@ -0,0 +1,32 @@
+use crate::config::SecurityHeaders;
+use actix_web::middleware::DefaultHeaders;
+
+/// Build the middleware that adds security headers to every response.
+///
+/// Snippets are user-saved HTML, so the CSP is the backstop if sanitization ever misses something.
+pub fn security_headers(settings: &SecurityHeaders) -> DefaultHeaders {
+    let mut headers = DefaultHeaders::new().add(("X-Content-Type-Options", "nosniff"));
+
+    let content_security_policy = match (
+        settings.content_security_policy.is_empty(),
+        settings.frame_ancestors.is_empty(),
+    ) {
+        (true, true) => String::new(),
+        (true, false) => format!("frame-ancestors {}", settings.frame_ancestors),
+        (false, true) => settings.content_security_policy.clone(),
+        (false, false) => format!(
+            "{}; frame-ancestors {}",
+            settings.content_security_policy.trim_end_matches([';', ' ']),
+            settings.frame_ancestors
+        ),
+    };
+    if !content_security_policy.is_empty() {
+        headers = headers.add(("Content-Security-Policy", content_security_policy));
+    }
+
+    if !settings.referrer_policy.is_empty() {
+        headers = headers.add(("Referrer-Policy", settings.referrer_policy.clone()));
+    }
+
+    headers
+}
@@ -0,0 +1,76 @@
+//! Just enough HTML parsing to read the metadata tags in a page's `<head>`
+
+/// Opening tags in document order, with lowercased names and attribute keys
+pub fn tags(html: &str) -> impl Iterator<Item = (String, Vec<(String, String)>)> + '_ {
+    html.split('<').skip(1).filter_map(|chunk| {
+        let end = chunk.find('>')?;
+        let tag = &chunk[..end];
+        let name_end = tag
+            .find(|ch: char| ch.is_whitespace() || ch == '/')
+            .unwrap_or(tag.len());
+        let name = tag[..name_end].to_ascii_lowercase();
+        if name.is_empty() || !name.chars().all(|ch| ch.is_ascii_alphanumeric()) {
+            return None;
+        }
+        Some((name, attributes(&tag[name_end..])))
+    })
+}
+
+/// The value of one attribute from `tags`
+pub fn attribute<'a>(attributes: &'a [(String, String)], key: &str) -> Option<&'a str> {
+    attributes
+        .iter()
+        .find(|(name, _)| name == key)
+        .map(|(_, value)| value.as_str())
+}
+
+/// Parse `key="value" key='value' key=value key` pairs
+fn attributes(mut rest: &str) -> Vec<(String, String)> {
+    let mut attributes = Vec::new();
+    loop {
+        rest = rest.trim_start_matches(|ch: char| ch.is_whitespace() || ch == '/');
+        if rest.is_empty() {
+            return attributes;
+        }
+        let key_end = rest
+            .find(|ch: char| ch.is_whitespace() || ch == '=' || ch == '/')
+            .unwrap_or(rest.len());
+        let key = rest[..key_end].to_ascii_lowercase();
+        rest = rest[key_end..].trim_start();
+
+        let mut value = String::new();
+        if let Some(after) = rest.strip_prefix('=') {
+            let after = after.trim_start();
+            let (raw, remainder) = match after.chars().next() {
+                Some(quote @ ('"' | '\'')) => {
+                    let inner = &after[1..];
+                    let close = inner.find(quote).unwrap_or(inner.len());
+                    (&inner[..close], inner.get(close + 1..).unwrap_or(""))
+                }
+                _ => {
+                    let close = after.find(char::is_whitespace).unwrap_or(after.len());
+                    (&after[..close], &after[close..])
+                }
+            };
+            value = raw.replace("&amp;", "&");
+            rest = remainder;
+        }
+        if key.is_empty() {
+            // Stray `=` or quote; skip a character so parsing always moves forward
+            rest = rest.get(1..).unwrap_or("");
+            continue;
+        }
+        attributes.push((key, value));
+    }
+}
+
+/// The largest index at or below `index` that falls on a character boundary
+pub fn floor_char_boundary(text: &str, index: usize) -> usize {
+    if index >= text.len() {
+        return text.len();
+    }
+    (0..=index)
+        .rev()
+        .find(|index| text.is_char_boundary(*index))
+        .unwrap_or(0)
+}
@@ -0,0 +1,155 @@
+use super::ImportItem;
+use serde_json::Value;
+
+/// Parse a Chrome `Bookmarks` file or a Firefox bookmark backup (`bookmarks-*.json`).
+///
+/// Each bookmark is tagged with its folder path, e.g. `dev/rust` for a bookmark in
+/// Bookmarks bar > Dev > Rust. The browser's own top-level folders aren't part of the path.
+pub fn parse(json: &[u8]) -> Result<Vec<ImportItem>, String> {
+    let root: Value = serde_json::from_slice(json).map_err(|err| format!("Invalid bookmarks JSON: {}", err))?;
+    let mut items = Vec::new();
+
+    if let Some(roots) = root.get("roots").and_then(Value::as_object) {
+        // Chrome: {"roots": {"bookmark_bar": {...}, "other": {...}, "synced": {...}}}
+        for node in roots.values() {
+            walk_chrome(node, &mut Vec::new(), &mut items);
+        }
+    } else if root.get("type").and_then(Value::as_str) == Some("text/x-moz-place-container") {
+        walk_firefox(&root, &mut Vec::new(), &mut items);
+    } else {
+        return Err("Not a Chrome or Firefox bookmarks file".to_string());
+    }
+
+    Ok(items)
+}
+
+fn walk_chrome(node: &Value, path: &mut Vec<String>, items: &mut Vec<ImportItem>) {
+    for child in children(node) {
+        match child.get("type").and_then(Value::as_str) {
+            Some("url") => push_bookmark(child.get("url"), path, items),
+            Some("folder") => {
+                path.push(folder_tag(child.get("name")));
+                walk_chrome(child, path, items);
+                path.pop();
+            }
+            _ => {}
+        }
+    }
+}
+
+fn walk_firefox(node: &Value, path: &mut Vec<String>, items: &mut Vec<ImportItem>) {
+    for child in children(node) {
+        match child.get("type").and_then(Value::as_str) {
+            Some("text/x-moz-place") => push_bookmark(child.get("uri"), path, items),
+            // Built-in containers such as the menu and toolbar carry a `root` key
+            Some("text/x-moz-place-container") if child.get("root").is_some() => walk_firefox(child, path, items),
+            Some("text/x-moz-place-container") => {
+                path.push(folder_tag(child.get("title")));
+                walk_firefox(child, path, items);
+                path.pop();
+            }
+            _ => {}
+        }
+    }
+}
+
+fn children(node: &Value) -> impl Iterator<Item = &Value> {
+    node.get("children").and_then(Value::as_array).into_iter().flatten()
+}
+
+/// Folder names become tag segments: lowercase, with spaces and slashes turned into dashes
+fn folder_tag(name: Option<&Value>) -> String {
+    name.and_then(Value::as_str)
+        .unwrap_or_default()
+        .trim()
+        .to_lowercase()
+        .replace([' ', '/'], "-")
+}
+
+/// Keep web links only; `javascript:`, `place:` and browser-internal URLs are dropped
+fn push_bookmark(url: Option<&Value>, path: &[String], items: &mut Vec<ImportItem>) {
+    let Some(url) = url.and_then(Value::as_str) else {
+        return;
+    };
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        return;
+    }
+
+    let segments: Vec<&str> = path
+        .iter()
+        .map(String::as_str)
+        .filter(|segment| !segment.is_empty())
+        .collect();
+    items.push(ImportItem {
+        url: url.to_string(),
+        tags: if segments.is_empty() {
+            Vec::new()
+        } else {
+            vec![segments.join("/")]
+        },
+        note: None,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_chrome_bookmarks() {
+        let json = br#"{
+            "checksum": "abc",
+            "roots": {
+                "bookmark_bar": {
+                    "name": "Bookmarks bar", "type": "folder",
+                    "children": [
+                        {"name": "Rust", "type": "url", "url": "https://www.rust-lang.org/"},
+                        {"name": "Dev", "type": "folder", "children": [
+                            {"name": "Web Stuff", "type": "folder", "children": [
+                                {"name": "MDN", "type": "url", "url": "https://developer.mozilla.org/"}
+                            ]},
+                            {"name": "Bookmarklet", "type": "url", "url": "javascript:alert(1)"}
+                        ]}
+                    ]
+                },
+                "other": {"name": "Other bookmarks", "type": "folder", "children": []}
+            },
+            "version": 1
+        }"#;
+        let items = parse(json).unwrap();
+
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].url, "https://www.rust-lang.org/");
+        assert!(items[0].tags.is_empty());
+        assert_eq!(items[1].url, "https://developer.mozilla.org/");
+        assert_eq!(items[1].tags, vec!["dev/web-stuff"]);
+    }
+
+    #[test]
+    fn test_parse_firefox_backup() {
+        let json = br#"{
+            "guid": "root________", "title": "", "type": "text/x-moz-place-container", "root": "placesRoot",
+            "children": [
+                {"guid": "toolbar_____", "title": "toolbar", "type": "text/x-moz-place-container", "root": "toolbarFolder",
+                 "children": [
+                    {"title": "Reading", "type": "text/x-moz-place-container", "children": [
+                        {"title": "Article", "type": "text/x-moz-place", "uri": "https://example.com/article"},
+                        {"type": "text/x-moz-place-separator"}
+                    ]},
+                    {"title": "Recent Tags", "type": "text/x-moz-place", "uri": "place:type=6&sort=14"}
+                ]}
+            ]
+        }"#;
+        let items = parse(json).unwrap();
+
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].url, "https://example.com/article");
+        assert_eq!(items[0].tags, vec!["reading"]);
+    }
+
+    #[test]
+    fn test_parse_rejects_other_json() {
+        assert!(parse(br#"{"urls": []}"#).is_err());
+        assert!(parse(b"not json").is_err());
+    }
+}
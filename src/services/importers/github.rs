@@ -0,0 +1,90 @@
+use super::{import_items, ImportItem};
+use crate::services::models;
+use serde::Deserialize;
+
+const API_URL: &str = "https://api.github.com";
+const PAGE_SIZE: usize = 100;
+
+/// The fields we use from `GET /user/starred`
+#[derive(Deserialize, Debug)]
+struct StarredRepo {
+    html_url: String,
+    language: Option<String>,
+    #[serde(default)]
+    topics: Vec<String>,
+}
+
+/// Tag each repository with its language and topics
+fn to_item(repo: StarredRepo) -> ImportItem {
+    let tags = repo
+        .language
+        .into_iter()
+        .chain(repo.topics)
+        .map(|tag| tag.to_lowercase().replace(' ', "-"))
+        .collect();
+
+    ImportItem {
+        url: repo.html_url,
+        tags,
+        note: None,
+    }
+}
+
+/// Fetch every repository starred by the token's owner
+pub async fn fetch_stars(token: &str) -> Result<Vec<ImportItem>, String> {
+    let client = reqwest::Client::new();
+    let mut items = Vec::new();
+
+    for page in 1.. {
+        let repos: Vec<StarredRepo> = client
+            .get(format!("{}/user/starred", API_URL))
+            .query(&[("per_page", PAGE_SIZE.to_string()), ("page", page.to_string())])
+            .bearer_auth(token)
+            .header(reqwest::header::ACCEPT, "application/vnd.github+json")
+            .header(reqwest::header::USER_AGENT, "read_it_later")
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|err| err.to_string())?
+            .json()
+            .await
+            .map_err(|err| err.to_string())?;
+
+        let last_page = repos.len() < PAGE_SIZE;
+        items.extend(repos.into_iter().map(to_item));
+        if last_page {
+            break;
+        }
+    }
+
+    Ok(items)
+}
+
+/// Import any newly starred repositories; already-saved ones are skipped, so this is safe to repeat
+pub async fn sync_stars(database: &dyn models::Database, token: &str) -> Result<models::ImportJob, String> {
+    let items = fetch_stars(token).await?;
+    import_items(database, "github", items)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_item_tags_language_and_topics() {
+        let repos: Vec<StarredRepo> = serde_json::from_str(
+            r#"[
+                {"html_url": "https://github.com/tokio-rs/tokio", "language": "Rust", "topics": ["async", "Networking"]},
+                {"html_url": "https://github.com/torvalds/linux", "language": null}
+            ]"#,
+        )
+        .unwrap();
+        let items: Vec<ImportItem> = repos.into_iter().map(to_item).collect();
+
+        assert_eq!(items[0].url, "https://github.com/tokio-rs/tokio");
+        assert_eq!(items[0].tags, vec!["rust", "async", "networking"]);
+        assert!(items[1].tags.is_empty());
+    }
+}
@@ -0,0 +1,165 @@
+use super::ImportItem;
+use serde::Deserialize;
+
+const SITE_URL: &str = "https://news.ycombinator.com";
+/// Favorites are listed 30 to a page; stop somewhere sane for enormous lists
+const MAX_PAGES: usize = 100;
+
+/// Body of `POST /imports/hackernews`: a username whose public favorites are fetched, or exported items
+#[derive(Deserialize, Debug)]
+pub struct HackerNewsImport {
+    pub username: Option<String>,
+    #[serde(default)]
+    pub items: Vec<ExportedStory>,
+}
+
+/// A story from an HN favorites export; `url` is missing for Ask/Show HN text posts
+#[derive(Deserialize, Debug)]
+pub struct ExportedStory {
+    pub id: u64,
+    pub url: Option<String>,
+}
+
+fn thread_url(id: u64) -> String {
+    format!("{}/item?id={}", SITE_URL, id)
+}
+
+/// Tag stories `hn` and note the comment thread; text posts save the thread itself
+fn to_item(id: u64, url: Option<String>) -> ImportItem {
+    let thread = thread_url(id);
+    let note = url.as_ref().map(|_| format!("Hacker News discussion: {}", thread));
+
+    ImportItem {
+        url: url.unwrap_or(thread),
+        tags: vec!["hn".to_string()],
+        note,
+    }
+}
+
+/// Pull the stories out of one favorites page, and whether there is a next page
+fn parse_favorites_page(html: &str) -> (Vec<ImportItem>, bool) {
+    let mut items = Vec::new();
+
+    for row in html.split("<tr class='athing").skip(1) {
+        let Some(id) = attribute(row, "id").and_then(|id| id.parse().ok()) else {
+            continue;
+        };
+        let url = row
+            .split_once("class=\"titleline\"")
+            .and_then(|(_, title)| attribute(title, "href"))
+            .map(|href| href.replace("&amp;", "&"))
+            .filter(|href| href.starts_with("http://") || href.starts_with("https://"));
+        items.push(to_item(id, url));
+    }
+
+    (items, html.contains("class='morelink'"))
+}
+
+/// Value of the first `name='...'` or `name="..."` attribute in `html`
+fn attribute<'a>(html: &'a str, name: &str) -> Option<&'a str> {
+    ['\'', '"']
+        .into_iter()
+        .filter_map(|quote| Some((html.find(&format!("{}={}", name, quote))?, quote)))
+        .min()
+        .and_then(|(position, quote)| {
+            let start = position + name.len() + 2;
+            let end = html[start..].find(quote)?;
+            Some(&html[start..start + end])
+        })
+}
+
+async fn fetch_favorites(username: &str) -> Result<Vec<ImportItem>, String> {
+    let client = reqwest::Client::new();
+    let mut items = Vec::new();
+
+    for page in 1..=MAX_PAGES {
+        let html = client
+            .get(format!("{}/favorites", SITE_URL))
+            .query(&[("id", username.to_string()), ("p", page.to_string())])
+            .header(reqwest::header::USER_AGENT, "read_it_later")
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|err| err.to_string())?
+            .text()
+            .await
+            .map_err(|err| err.to_string())?;
+
+        let (stories, more) = parse_favorites_page(&html);
+        items.extend(stories);
+        if !more {
+            break;
+        }
+    }
+
+    Ok(items)
+}
+
+/// Collect the exported items plus, with a username, every favorite on the site
+pub async fn fetch(request: HackerNewsImport) -> Result<Vec<ImportItem>, String> {
+    let mut items: Vec<ImportItem> = request
+        .items
+        .into_iter()
+        .map(|story| to_item(story.id, story.url))
+        .collect();
+    if let Some(username) = request
+        .username
+        .as_deref()
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+    {
+        items.extend(fetch_favorites(username).await?);
+    }
+    if items.is_empty() {
+        return Err("Provide a username or a list of exported items".to_string());
+    }
+
+    Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: &str = r#"
+        <table>
+        <tr class='athing submission' id='8863'>
+          <td class="title"><span class="titleline"><a href="http://www.getdropbox.com/u/2/screencast.html">My YC app: Dropbox</a></span></td>
+        </tr>
+        <tr class='athing submission' id='121003'>
+          <td class="title"><span class="titleline"><a href="item?id=121003">Ask HN: The Arc Effect</a></span></td>
+        </tr>
+        <tr class='athing submission' id='42'>
+          <td class="title"><span class="titleline"><a href="https://example.com/?a=1&amp;b=2">Query</a></span></td>
+        </tr>
+        </table>
+        <a href='favorites?id=pg&amp;p=2' class='morelink' rel='next'>More</a>
+    "#;
+
+    #[test]
+    fn test_parse_favorites_page() {
+        let (items, more) = parse_favorites_page(PAGE);
+        assert!(more);
+        assert_eq!(items.len(), 3);
+
+        assert_eq!(items[0].url, "http://www.getdropbox.com/u/2/screencast.html");
+        assert_eq!(items[0].tags, vec!["hn"]);
+        assert_eq!(
+            items[0].note.as_deref(),
+            Some("Hacker News discussion: https://news.ycombinator.com/item?id=8863")
+        );
+
+        // Text posts have no external link, so the thread itself is saved
+        assert_eq!(items[1].url, "https://news.ycombinator.com/item?id=121003");
+        assert!(items[1].note.is_none());
+
+        assert_eq!(items[2].url, "https://example.com/?a=1&b=2");
+    }
+
+    #[test]
+    fn test_parse_last_page() {
+        let (items, more) = parse_favorites_page("<table></table>");
+        assert!(items.is_empty());
+        assert!(!more);
+    }
+}
@@ -0,0 +1,501 @@
+//! Bulk imports from other services. Every import runs as a persisted job, so progress can be polled at
+//! `GET /imports/{id}` and an import interrupted by a restart resumes without duplicating anything. A tag
+//! taxonomy carries no content, so it is imported directly instead.
+
+pub mod bookmarks;
+pub mod github;
+pub mod hackernews;
+pub mod reddit;
+
+use crate::config::Config;
+pub use crate::services::models::ImportItem;
+use crate::services::{api, changes, models};
+use actix_multipart::form::{bytes::Bytes, MultipartForm, MultipartFormConfig};
+use actix_web::{get, post, web, HttpResponse, Responder};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// Items fetched from the queue at a time
+const JOB_BATCH_SIZE: i64 = 100;
+
+/// Store the items as a job and work through it in the background; returns the job as first stored
+pub async fn start_import(
+    database: Arc<dyn models::Database>,
+    source: &str,
+    items: Vec<ImportItem>,
+) -> Result<models::ImportJob, sqlx::Error> {
+    let id = database.create_import_job(source, &items).await?;
+    let job = database.get_import_job(id).await?.ok_or(sqlx::Error::RowNotFound)?;
+    spawn_job(database, id);
+    Ok(job)
+}
+
+/// Store the items as a job and wait for it to finish
+pub async fn import_items(
+    database: &dyn models::Database,
+    source: &str,
+    items: Vec<ImportItem>,
+) -> Result<models::ImportJob, sqlx::Error> {
+    let id = database.create_import_job(source, &items).await?;
+    run_job(database, id).await
+}
+
+/// Restart jobs left running by a previous process; returns how many were picked up
+pub async fn resume_jobs(database: Arc<dyn models::Database>) -> Result<usize, sqlx::Error> {
+    let ids = database.get_running_import_jobs().await?;
+    for &id in &ids {
+        spawn_job(database.clone(), id);
+    }
+    Ok(ids.len())
+}
+
+fn spawn_job(database: Arc<dyn models::Database>, id: i32) {
+    actix_web::rt::spawn(async move {
+        if let Err(err) = run_job(database.as_ref(), id).await {
+            eprintln!("Import job {} stopped: {:?}", id, err);
+        }
+    });
+}
+
+/// Process every item still pending on the job. Each item is marked done once its URL and note are both saved, and a
+/// URL that was already in the library is skipped, so running a job again after an interruption is safe.
+pub async fn run_job(database: &dyn models::Database, id: i32) -> Result<models::ImportJob, sqlx::Error> {
+    loop {
+        let pending = database.get_pending_import_items(id, JOB_BATCH_SIZE).await?;
+        if pending.is_empty() {
+            break;
+        }
+
+        for pending in pending {
+            let outcome = match import_item(database, id, &pending).await {
+                Ok(true) => models::ImportOutcome::Created,
+                Ok(false) => models::ImportOutcome::Skipped,
+                Err(err) => models::ImportOutcome::Failed(err.to_string()),
+            };
+            database.finish_import_item(id, pending.id, &outcome).await?;
+        }
+    }
+
+    database.complete_import_job(id).await?;
+    let job = database.get_import_job(id).await?.ok_or(sqlx::Error::RowNotFound)?;
+
+    let detail = job.to_string();
+    if let Err(err) = database.record_activity("import", &job.source, Some(&detail)).await {
+        eprintln!("Failed to record activity: {:?}", err);
+    }
+    changes::publish("import", &job.source, Some(&detail));
+
+    Ok(job)
+}
+
+/// The item's tags as they'll be saved: trimmed, without blanks or duplicates
+fn normalized_tags(item: &ImportItem) -> Vec<&str> {
+    let mut tags: Vec<&str> = item
+        .tags
+        .iter()
+        .map(|tag| tag.trim())
+        .filter(|tag| !tag.is_empty())
+        .collect();
+    tags.sort_unstable();
+    tags.dedup();
+    tags
+}
+
+/// Returns whether the item was new. An item its job had already started on is finished off instead of skipped,
+/// as its URL being saved is then this job's doing, without adding its note a second time.
+async fn import_item(
+    database: &dyn models::Database,
+    job_id: i32,
+    pending: &models::PendingImportItem,
+) -> Result<bool, sqlx::Error> {
+    let item = &pending.item;
+    if !pending.started {
+        if database.url_exists(&item.url).await? {
+            return Ok(false);
+        }
+        database.start_import_item(job_id, pending.id).await?;
+    }
+
+    let tags = normalized_tags(item);
+    if tags.is_empty() {
+        database.insert_url(&item.url).await?;
+    } else {
+        database.insert_tags(&item.url, &tags).await?;
+    }
+    if let Some(note) = &item.note {
+        let written = pending.started
+            && database
+                .get_snippets_for_url(&item.url)
+                .await?
+                .iter()
+                .any(|snippet| snippet.snippet == *note);
+        if !written {
+            database.insert_snippet(&item.url, note, &tags, None).await?;
+        }
+    }
+
+    Ok(true)
+}
+
+/// Query string accepted by every import endpoint
+#[derive(Deserialize, Debug)]
+pub struct ImportOptions {
+    /// Report what would happen without saving anything
+    pub dry_run: Option<bool>,
+}
+
+/// What an import would do with one item
+#[derive(Serialize, Debug, PartialEq)]
+pub struct PlannedItem {
+    pub url: String,
+    pub tags: Vec<String>,
+    pub note: Option<String>,
+    /// `create`, or `skip` if the URL is already saved or appears earlier in the import
+    pub action: &'static str,
+}
+
+/// The result of a dry run
+#[derive(Serialize, Debug)]
+pub struct ImportPlan {
+    pub source: String,
+    pub total: usize,
+    pub create: usize,
+    pub skip: usize,
+    pub items: Vec<PlannedItem>,
+}
+
+/// Work out what importing the items would do, without writing anything
+pub async fn plan_import(
+    database: &dyn models::Database,
+    source: &str,
+    items: Vec<ImportItem>,
+) -> Result<ImportPlan, sqlx::Error> {
+    let mut plan = ImportPlan {
+        source: source.to_string(),
+        total: items.len(),
+        create: 0,
+        skip: 0,
+        items: Vec::with_capacity(items.len()),
+    };
+    let mut seen = HashSet::new();
+
+    for item in items {
+        let new = seen.insert(item.url.clone()) && !database.url_exists(&item.url).await?;
+        if new {
+            plan.create += 1;
+        } else {
+            plan.skip += 1;
+        }
+        plan.items.push(PlannedItem {
+            tags: normalized_tags(&item).into_iter().map(String::from).collect(),
+            action: if new { "create" } else { "skip" },
+            url: item.url,
+            note: item.note,
+        });
+    }
+
+    Ok(plan)
+}
+
+/// Queue fetched items as a job and answer `202 Accepted` with it, or with `dry_run` report the plan instead
+async fn accept(
+    database: &web::Data<Arc<dyn models::Database>>,
+    options: &ImportOptions,
+    source: &str,
+    items: Vec<ImportItem>,
+) -> HttpResponse {
+    if options.dry_run.unwrap_or(false) {
+        return match plan_import(database.get_ref().as_ref(), source, items).await {
+            Ok(plan) => HttpResponse::Ok().json(plan),
+            Err(err) => {
+                eprintln!("Failed to plan {} import: {:?}", source, err);
+                HttpResponse::InternalServerError().json("Failed to plan import")
+            }
+        };
+    }
+
+    match start_import(database.get_ref().clone(), source, items).await {
+        Ok(job) => HttpResponse::Accepted().json(job),
+        Err(err) => {
+            eprintln!("Failed to start {} import: {:?}", source, err);
+            HttpResponse::InternalServerError().json("Failed to start import")
+        }
+    }
+}
+
+/// Progress of an import job
+#[get("/imports/{id}")]
+async fn get_import(database: web::Data<Arc<dyn models::Database>>, id: web::Path<i32>) -> impl Responder {
+    match database.get_import_job(id.into_inner()).await {
+        Ok(Some(job)) => HttpResponse::Ok().json(job),
+        Ok(None) => HttpResponse::NotFound().json("Import not found"),
+        Err(err) => {
+            eprintln!("Failed to fetch import: {:?}", err);
+            HttpResponse::InternalServerError().json("Failed to fetch import")
+        }
+    }
+}
+
+/// Import starred repositories using the configured `GITHUB_TOKEN`
+#[post("/imports/github")]
+async fn import_github_stars(
+    database: web::Data<Arc<dyn models::Database>>,
+    config: web::Data<Config>,
+    options: web::Query<ImportOptions>,
+) -> impl Responder {
+    let Some(token) = config.github.token.as_deref() else {
+        return HttpResponse::BadRequest().json("GITHUB_TOKEN is not configured");
+    };
+
+    match github::fetch_stars(token).await {
+        Ok(items) => accept(&database, &options, "github", items).await,
+        Err(err) => {
+            eprintln!("GitHub import failed: {}", err);
+            HttpResponse::BadGateway().json(format!("GitHub import failed: {}", err))
+        }
+    }
+}
+
+/// Import Hacker News favorites by username, or from an exported list
+#[post("/imports/hackernews")]
+async fn import_hackernews(
+    database: web::Data<Arc<dyn models::Database>>,
+    req: web::Json<hackernews::HackerNewsImport>,
+    options: web::Query<ImportOptions>,
+) -> impl Responder {
+    match hackernews::fetch(req.into_inner()).await {
+        Ok(items) => accept(&database, &options, "hackernews", items).await,
+        Err(err) => {
+            eprintln!("Hacker News import failed: {}", err);
+            HttpResponse::BadRequest().json(format!("Hacker News import failed: {}", err))
+        }
+    }
+}
+
+/// Import saved posts and comments through the Reddit API
+#[post("/imports/reddit")]
+async fn import_reddit(
+    database: web::Data<Arc<dyn models::Database>>,
+    req: web::Json<reddit::RedditImport>,
+    options: web::Query<ImportOptions>,
+) -> impl Responder {
+    match reddit::fetch_saved(&req).await {
+        Ok(items) => accept(&database, &options, "reddit", items).await,
+        Err(err) => {
+            eprintln!("Reddit import failed: {}", err);
+            HttpResponse::BadGateway().json(format!("Reddit import failed: {}", err))
+        }
+    }
+}
+
+/// Import `saved_posts.csv` / `saved_comments.csv` from Reddit's data export, sent as the request body
+#[post("/imports/reddit/csv")]
+async fn import_reddit_csv(
+    database: web::Data<Arc<dyn models::Database>>,
+    options: web::Query<ImportOptions>,
+    body: web::Bytes,
+) -> impl Responder {
+    match reddit::parse_export(&body) {
+        Ok(items) => accept(&database, &options, "reddit", items).await,
+        Err(err) => HttpResponse::BadRequest().json(format!("Reddit import failed: {}", err)),
+    }
+}
+
+/// Largest file accepted by the upload endpoints; browser bookmark files can run to tens of megabytes
+const UPLOAD_LIMIT: usize = 32 * 1024 * 1024;
+
+/// A single uploaded file in a `multipart/form-data` body
+#[derive(MultipartForm)]
+struct FileUpload {
+    file: Bytes,
+}
+
+/// Import a Chrome `Bookmarks` file or Firefox bookmark backup, uploaded as the `file` field
+#[post("/imports/bookmarks")]
+async fn import_bookmarks(
+    database: web::Data<Arc<dyn models::Database>>,
+    form: MultipartForm<FileUpload>,
+    options: web::Query<ImportOptions>,
+) -> impl Responder {
+    match bookmarks::parse(&form.file.data) {
+        Ok(items) => accept(&database, &options, "bookmarks", items).await,
+        Err(err) => HttpResponse::BadRequest().json(format!("Bookmarks import failed: {}", err)),
+    }
+}
+
+/// Bring in another instance's tags from `GET /export/tags`, creating missing ones and taking the colors and
+/// descriptions it sets. Content isn't touched.
+#[post("/imports/tags")]
+async fn import_tags(
+    database: web::Data<Arc<dyn models::Database>>,
+    req: web::Json<models::TagTaxonomy>,
+) -> impl Responder {
+    let tags = match req.validate() {
+        Ok(tags) => tags,
+        Err(err) => return HttpResponse::BadRequest().json(err),
+    };
+
+    match database.import_tags(&tags).await {
+        Ok(imported) => {
+            let summary = format!("{} created, {} updated", imported.created, imported.updated);
+            api::log_activity(database.get_ref().as_ref(), "import_tags", "tags", Some(&summary)).await;
+            HttpResponse::Ok().json(imported)
+        }
+        Err(err) => {
+            eprintln!("Failed to import tags: {:?}", err);
+            HttpResponse::InternalServerError().json("Failed to import tags")
+        }
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.app_data(
+        MultipartFormConfig::default()
+            .total_limit(UPLOAD_LIMIT)
+            .memory_limit(UPLOAD_LIMIT),
+    )
+    .service(get_import)
+    .service(import_github_stars)
+    .service(import_hackernews)
+    .service(import_reddit)
+    .service(import_reddit_csv)
+    .service(import_bookmarks)
+    .service(import_tags);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::models::Database;
+    use crate::services::sqlite_database::SqliteDatabase;
+
+    async fn setup_test_db() -> SqliteDatabase {
+        let database = SqliteDatabase::new(":memory:").await.unwrap();
+        database.initialize().await.unwrap();
+        database
+    }
+
+    fn item(url: &str, tags: &[&str], note: Option<&str>) -> ImportItem {
+        ImportItem {
+            url: url.to_string(),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            note: note.map(String::from),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_import_items_skips_saved_urls() {
+        let database = setup_test_db().await;
+        database.insert_url("https://saved.com").await.unwrap();
+
+        let summary = import_items(
+            &database,
+            "test",
+            vec![
+                item("https://saved.com", &["ignored"], None),
+                item("https://new.com", &["rust", "rust", " "], Some("Discussion")),
+                item("https://plain.com", &[], None),
+            ],
+        )
+        .await
+        .unwrap();
+
+        assert_eq!((summary.created, summary.skipped, summary.failed), (2, 1, 0));
+
+        let urls = database.get_urls_with_tags(&Default::default()).await.unwrap();
+        let new = urls.iter().find(|url| url.url == "https://new.com").unwrap();
+        assert_eq!(new.tags, vec!["rust"]);
+        let saved = urls.iter().find(|url| url.url == "https://saved.com").unwrap();
+        assert!(saved.tags.is_empty());
+
+        let snippets = database.get_snippets_with_tags(&Default::default()).await.unwrap();
+        assert_eq!(snippets.len(), 1);
+        assert_eq!(snippets[0].snippet, "Discussion");
+
+        let activity = database.get_activity(1).await.unwrap();
+        assert_eq!(activity[0].action, "import");
+        assert_eq!(
+            activity[0].detail.as_deref(),
+            Some("test: 2 created, 1 skipped, 0 failed")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_job_resumes_after_interruption() {
+        let database = setup_test_db().await;
+        database.insert_url("https://saved.com").await.unwrap();
+        let items = vec![
+            item("https://one.com", &["rust"], None),
+            item("https://two.com", &[], Some("Two's note")),
+            item("https://three.com", &[], Some("Three's note")),
+            item("https://saved.com", &[], Some("Not added")),
+        ];
+        let id = database.create_import_job("test", &items).await.unwrap();
+
+        // The first item was finished; the second had its URL saved but not its note when the server stopped, and
+        // the third its note as well but wasn't yet marked done
+        let pending = database.get_pending_import_items(id, 3).await.unwrap();
+        database.insert_tags("https://one.com", &["rust"]).await.unwrap();
+        database
+            .finish_import_item(id, pending[0].id, &models::ImportOutcome::Created)
+            .await
+            .unwrap();
+        database.start_import_item(id, pending[1].id).await.unwrap();
+        database.insert_url("https://two.com").await.unwrap();
+        database.start_import_item(id, pending[2].id).await.unwrap();
+        database.insert_url("https://three.com").await.unwrap();
+        database
+            .insert_snippet("https://three.com", "Three's note", &[], None)
+            .await
+            .unwrap();
+        assert_eq!(database.get_running_import_jobs().await.unwrap(), vec![id]);
+
+        let job = run_job(&database, id).await.unwrap();
+        assert_eq!(job.status, "completed");
+        assert_eq!((job.total, job.processed), (4, 4));
+        assert_eq!((job.created, job.skipped, job.failed), (3, 1, 0));
+        assert!(database.get_running_import_jobs().await.unwrap().is_empty());
+
+        let urls = database.get_all_urls(&Default::default()).await.unwrap();
+        assert_eq!(urls.len(), 4);
+        let notes: Vec<String> = database
+            .get_snippets_with_tags(&Default::default())
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|snippet| snippet.snippet)
+            .collect();
+        assert_eq!(notes.len(), 2);
+        assert!(notes.contains(&"Two's note".to_string()));
+        assert!(notes.contains(&"Three's note".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_plan_import_writes_nothing() {
+        let database = setup_test_db().await;
+        database.insert_url("https://saved.com").await.unwrap();
+
+        let plan = plan_import(
+            &database,
+            "test",
+            vec![
+                item("https://saved.com", &[], None),
+                item("https://new.com", &[" web", "rust", "rust"], Some("Discussion")),
+                item("https://new.com", &[], None),
+            ],
+        )
+        .await
+        .unwrap();
+
+        assert_eq!((plan.total, plan.create, plan.skip), (3, 1, 2));
+        let actions: Vec<&str> = plan.items.iter().map(|item| item.action).collect();
+        assert_eq!(actions, vec!["skip", "create", "skip"]);
+        assert_eq!(plan.items[1].tags, vec!["rust", "web"]);
+
+        let urls = database.get_all_urls(&Default::default()).await.unwrap();
+        assert_eq!(urls.len(), 1);
+        assert!(database.get_all_tags().await.unwrap().is_empty());
+        assert!(database.get_activity(10).await.unwrap().is_empty());
+    }
+}
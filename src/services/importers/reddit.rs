@@ -0,0 +1,178 @@
+use super::ImportItem;
+use serde::Deserialize;
+
+const API_URL: &str = "https://oauth.reddit.com";
+const PAGE_SIZE: usize = 100;
+
+/// Body of `POST /imports/reddit`: an OAuth token allowed to read the user's history
+#[derive(Deserialize, Debug)]
+pub struct RedditImport {
+    pub username: String,
+    pub token: String,
+}
+
+/// A row of `saved_posts.csv` or `saved_comments.csv` from Reddit's data export
+#[derive(Deserialize, Debug)]
+struct SavedRow {
+    permalink: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct Listing {
+    data: ListingData,
+}
+
+#[derive(Deserialize, Debug)]
+struct ListingData {
+    children: Vec<ListingChild>,
+    after: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ListingChild {
+    data: SavedThing,
+}
+
+/// A saved post or comment; comments have no `url`
+#[derive(Deserialize, Debug)]
+struct SavedThing {
+    subreddit: String,
+    permalink: String,
+    url: Option<String>,
+}
+
+/// `rust` from `https://www.reddit.com/r/rust/comments/...` or `/r/rust/comments/...`
+fn subreddit_of(permalink: &str) -> Option<&str> {
+    let (_, rest) = permalink.split_once("/r/")?;
+    rest.split('/').next().filter(|subreddit| !subreddit.is_empty())
+}
+
+fn absolute_permalink(permalink: &str) -> String {
+    if permalink.starts_with("http://") || permalink.starts_with("https://") {
+        permalink.to_string()
+    } else {
+        format!("https://www.reddit.com{}", permalink)
+    }
+}
+
+/// Link posts save the linked page with a note pointing at the thread; everything else saves the thread
+fn to_item(subreddit: Option<&str>, permalink: &str, url: Option<&str>) -> ImportItem {
+    let thread = absolute_permalink(permalink);
+    let external = url.filter(|url| !url.contains("reddit.com/") && !url.starts_with('/'));
+
+    ImportItem {
+        url: external.map(String::from).unwrap_or_else(|| thread.clone()),
+        tags: subreddit
+            .map(|subreddit| subreddit.to_lowercase())
+            .into_iter()
+            .collect(),
+        note: external.map(|_| format!("Reddit discussion: {}", thread)),
+    }
+}
+
+/// Parse a saved-items CSV from the data export; rows without a permalink are skipped
+pub fn parse_export(csv_data: &[u8]) -> Result<Vec<ImportItem>, String> {
+    let mut reader = csv::Reader::from_reader(csv_data);
+    let headers = reader.headers().map_err(|err| format!("Invalid CSV: {}", err))?;
+    if !headers.iter().any(|header| header == "permalink") {
+        return Err("Expected a `permalink` column, as in saved_posts.csv".to_string());
+    }
+
+    reader
+        .deserialize::<SavedRow>()
+        .map(|row| row.map_err(|err| format!("Invalid CSV: {}", err)))
+        .filter(|row| !matches!(row, Ok(row) if row.permalink.trim().is_empty()))
+        .map(|row| row.map(|row| to_item(subreddit_of(&row.permalink), row.permalink.trim(), None)))
+        .collect()
+}
+
+/// Page through everything the user has saved
+pub async fn fetch_saved(request: &RedditImport) -> Result<Vec<ImportItem>, String> {
+    let client = reqwest::Client::new();
+    let mut items = Vec::new();
+    let mut after: Option<String> = None;
+
+    loop {
+        let mut query = vec![("limit", PAGE_SIZE.to_string()), ("raw_json", "1".to_string())];
+        if let Some(after) = &after {
+            query.push(("after", after.clone()));
+        }
+
+        let listing: Listing = client
+            .get(format!("{}/user/{}/saved", API_URL, request.username))
+            .query(&query)
+            .bearer_auth(&request.token)
+            .header(reqwest::header::USER_AGENT, "read_it_later")
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|err| err.to_string())?
+            .json()
+            .await
+            .map_err(|err| err.to_string())?;
+
+        items.extend(listing.data.children.iter().map(|child| {
+            to_item(
+                Some(&child.data.subreddit),
+                &child.data.permalink,
+                child.data.url.as_deref(),
+            )
+        }));
+
+        match listing.data.after {
+            Some(next) if !listing.data.children.is_empty() => after = Some(next),
+            _ => break,
+        }
+    }
+
+    Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_export() {
+        let csv_data = b"id,permalink\n\
+            1gtaai2,https://www.reddit.com/r/rust/comments/1gtaai2/question/\n\
+            lxk2fa1,https://www.reddit.com/r/Python/comments/1gs0abc/post/lxk2fa1/\n\
+            blank,\n";
+        let items = parse_export(csv_data).unwrap();
+
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].url, "https://www.reddit.com/r/rust/comments/1gtaai2/question/");
+        assert_eq!(items[0].tags, vec!["rust"]);
+        assert_eq!(items[1].tags, vec!["python"]);
+        assert!(items[0].note.is_none());
+    }
+
+    #[test]
+    fn test_link_posts_save_the_link() {
+        let item = to_item(
+            Some("rust"),
+            "/r/rust/comments/abc/title/",
+            Some("https://blog.rust-lang.org/2024/11/28/Rust-1.83.0.html"),
+        );
+        assert_eq!(item.url, "https://blog.rust-lang.org/2024/11/28/Rust-1.83.0.html");
+        assert_eq!(
+            item.note.as_deref(),
+            Some("Reddit discussion: https://www.reddit.com/r/rust/comments/abc/title/")
+        );
+
+        // Self posts link back to their own thread
+        let item = to_item(
+            Some("rust"),
+            "/r/rust/comments/abc/title/",
+            Some("https://www.reddit.com/r/rust/comments/abc/title/"),
+        );
+        assert_eq!(item.url, "https://www.reddit.com/r/rust/comments/abc/title/");
+        assert!(item.note.is_none());
+    }
+
+    #[test]
+    fn test_parse_export_rejects_other_csv() {
+        assert!(parse_export(b"url,title\nhttps://example.com,Example\n").is_err());
+        assert!(parse_export(b"a,b").is_err());
+    }
+}
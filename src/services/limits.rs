@@ -0,0 +1,17 @@
+use actix_web::error::{InternalError, JsonPayloadError};
+use actix_web::{web, HttpResponse};
+
+/// JSON extractor config with a size limit and JSON error bodies.
+///
+/// Register it app-wide for the default limit, or on a single resource to give that route its own limit.
+pub fn json_config(limit: usize) -> web::JsonConfig {
+    web::JsonConfig::default().limit(limit).error_handler(move |err, _req| {
+        let response = match &err {
+            JsonPayloadError::OverflowKnownLength { .. } | JsonPayloadError::Overflow { .. } => {
+                HttpResponse::PayloadTooLarge().json(format!("Request body exceeds the {} byte limit", limit))
+            }
+            _ => HttpResponse::BadRequest().json(format!("Invalid JSON payload: {}", err)),
+        };
+        InternalError::from_response(err, response).into()
+    })
+}
@@ -0,0 +1,126 @@
+//! The listen-later queue: unread audio, as a page with players and as a podcast feed
+
+use crate::config::Config;
+use crate::services::{metadata, models};
+use actix_web::{get, web, HttpRequest, HttpResponse, Responder};
+use serde::Serialize;
+use std::sync::Arc;
+use tera::{Context, Tera};
+
+/// One queued episode, ready for the page and the feed
+#[derive(Serialize, Debug)]
+struct Episode {
+    url: String,
+    short_id: String,
+    title: String,
+    audio_url: String,
+    mime_type: String,
+    /// Size in bytes; feeds require one, so 0 when unknown
+    length: u64,
+    duration: Option<String>,
+    /// When it was saved, in the RFC 2822 form RSS uses
+    published: String,
+}
+
+/// Unread URLs with audio, oldest first, so the queue plays in the order things were saved
+async fn episodes(database: &dyn models::Database) -> Result<Vec<Episode>, sqlx::Error> {
+    let filter = models::ListFilter {
+        kind: Some(models::UrlKind::Audio),
+        unread: true,
+        sort: models::Sort::parse("added:asc").unwrap_or_default(),
+        ..Default::default()
+    };
+
+    Ok(database
+        .get_urls_with_tags(&filter)
+        .await?
+        .into_iter()
+        .filter_map(|url| {
+            let Some(models::UrlMetadata::Audio(audio)) = url.metadata else {
+                return None;
+            };
+            Some(Episode {
+                title: url.display_title,
+                mime_type: audio.mime_type.unwrap_or_else(|| "audio/mpeg".to_string()),
+                length: audio.length.unwrap_or(0),
+                duration: audio.duration.map(metadata::duration_label),
+                published: url.datetime.and_utc().to_rfc2822(),
+                audio_url: audio.audio_url,
+                short_id: url.short_id,
+                url: url.url,
+            })
+        })
+        .collect())
+}
+
+#[get("/listen")]
+async fn queue(
+    database: web::Data<Arc<dyn models::Database>>,
+    tmpl: web::Data<Tera>,
+    database_type: web::Data<String>,
+    config: web::Data<Config>,
+) -> impl Responder {
+    let episodes = match episodes(database.get_ref().as_ref()).await {
+        Ok(episodes) => episodes,
+        Err(err) => {
+            eprintln!("Failed to fetch the listen queue: {:?}", err);
+            return HttpResponse::InternalServerError().body("Failed to fetch the listen queue");
+        }
+    };
+
+    let mut context = Context::new();
+    context.insert("episodes", &episodes);
+    context.insert("title", "Listen later");
+    context.insert("database_type", &**database_type);
+    context.insert("base_path", &config.base_path);
+
+    match tmpl.render("listen.html", &context) {
+        Ok(rendered) => HttpResponse::Ok().content_type("text/html").body(rendered),
+        Err(e) => {
+            eprintln!("Template error: {:?}", e);
+            HttpResponse::InternalServerError().body("Template error")
+        }
+    }
+}
+
+/// The queue as an RSS feed with enclosures, for subscribing from a podcast app
+#[get("/listen/feed.xml")]
+async fn feed(
+    req: HttpRequest,
+    database: web::Data<Arc<dyn models::Database>>,
+    tmpl: web::Data<Tera>,
+    config: web::Data<Config>,
+) -> impl Responder {
+    let episodes = match episodes(database.get_ref().as_ref()).await {
+        Ok(episodes) => episodes,
+        Err(err) => {
+            eprintln!("Failed to fetch the listen queue: {:?}", err);
+            return HttpResponse::InternalServerError().body("Failed to fetch the listen queue");
+        }
+    };
+
+    let connection = req.connection_info();
+    let mut context = Context::new();
+    context.insert("episodes", &episodes);
+    context.insert(
+        "queue_url",
+        &format!(
+            "{}://{}{}/listen",
+            connection.scheme(),
+            connection.host(),
+            config.base_path
+        ),
+    );
+
+    match tmpl.render("listen_feed.xml", &context) {
+        Ok(rendered) => HttpResponse::Ok().content_type("application/rss+xml").body(rendered),
+        Err(e) => {
+            eprintln!("Template error: {:?}", e);
+            HttpResponse::InternalServerError().body("Template error")
+        }
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(queue).service(feed);
+}
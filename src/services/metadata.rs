@@ -0,0 +1,176 @@
+//! Details looked up about saved URLs in the background: video metadata, podcast audio and what kind of URL it is
+
+use crate::config;
+use crate::services::{audio, changes, classify, models, video};
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+
+/// Look up and store metadata for a saved URL; `false` means there was nothing to find
+pub async fn enrich(
+    database: &dyn models::Database,
+    settings: &config::MediaLookups,
+    url: &str,
+) -> Result<bool, String> {
+    let looked_up = match video::Provider::of(url) {
+        Some(provider) if settings.video => video::look_up(provider, url)
+            .await
+            .map(|video| Some(models::UrlMetadata::Video(video))),
+        // A video page is never also an episode, so don't fetch it looking for audio
+        Some(_) => Ok(None),
+        None if settings.audio => audio::look_up(url)
+            .await
+            .map(|audio| audio.map(models::UrlMetadata::Audio)),
+        None => Ok(None),
+    };
+    if settings.classify {
+        // A kind is a nicety, and the URL alone often gives it, so neither side's failure stops the other
+        let found = looked_up.as_ref().ok().and_then(Option::as_ref);
+        if let Err(err) = classify(database, url, found).await {
+            eprintln!("Failed to classify {}: {}", url, err);
+        }
+    }
+
+    let Some(found) = looked_up? else {
+        return Ok(false);
+    };
+    database
+        .set_url_metadata(url, &found)
+        .await
+        .map_err(|err| err.to_string())?;
+    Ok(true)
+}
+
+/// Store what kind of URL this is, unless it already has one, taking it from the metadata just found when
+/// that says
+async fn classify(
+    database: &dyn models::Database,
+    url: &str,
+    found: Option<&models::UrlMetadata>,
+) -> Result<(), String> {
+    let Some(saved) = database.get_url_with_tags(url).await.map_err(|err| err.to_string())? else {
+        return Ok(());
+    };
+    let existing = database
+        .get_metadata(saved.id, &models::URL_KIND)
+        .await
+        .map_err(|err| err.to_string())?;
+    if existing.is_some() {
+        return Ok(());
+    }
+
+    let kind = match found {
+        Some(models::UrlMetadata::Video(_)) => Some(models::UrlKind::Video),
+        Some(models::UrlMetadata::Audio(_)) => Some(models::UrlKind::Audio),
+        None => classify::classify(url).await?,
+    };
+    let Some(kind) = kind else {
+        return Ok(());
+    };
+    database
+        .set_metadata(saved.id, &models::URL_KIND, &kind)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// Enrich URLs in the background as they are saved or tagged
+pub fn watch_saves(database: Arc<dyn models::Database>, settings: config::MediaLookups) {
+    let mut saves = changes::subscribe();
+    actix_web::rt::spawn(async move {
+        loop {
+            let change = match saves.recv().await {
+                Ok(change) => change,
+                // Missing a few saves only means their metadata waits for `fetch-metadata`
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return,
+            };
+            if !matches!(change.action.as_str(), "save_url" | "tag_url" | "set_tags") {
+                continue;
+            }
+            if let Err(err) = enrich(database.as_ref(), &settings, &change.subject).await {
+                eprintln!("Failed to look up metadata for {}: {}", change.subject, err);
+            }
+        }
+    });
+}
+
+/// Look up every saved URL that has no metadata yet, such as ones imported in bulk
+pub async fn backfill(
+    database: &dyn models::Database,
+    settings: &config::MediaLookups,
+) -> Result<(usize, usize), String> {
+    let urls = database
+        .get_urls_with_tags(&models::ListFilter::default())
+        .await
+        .map_err(|err| err.to_string())?;
+    let (mut enriched, mut failed) = (0, 0);
+
+    for url in urls.iter().filter(|url| url.metadata.is_none()) {
+        match enrich(database, settings, &url.url).await {
+            Ok(true) => enriched += 1,
+            Ok(false) => {}
+            Err(err) => {
+                failed += 1;
+                eprintln!("Failed to look up metadata for {}: {}", url.url, err);
+            }
+        }
+    }
+    Ok((enriched, failed))
+}
+
+/// A length as `m:ss`, or `h:mm:ss` from an hour up
+pub fn duration_label(seconds: u64) -> String {
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, seconds)
+    } else {
+        format!("{}:{:02}", minutes, seconds)
+    }
+}
+
+/// Seconds in an ISO 8601 duration such as `PT1H2M3S`; pages also write `PT0M42S` and `P1DT2H`
+pub fn parse_iso_duration(value: &str) -> Option<u64> {
+    let rest = value.trim().strip_prefix('P')?;
+    let (mut seconds, mut number, mut in_time) = (0u64, String::new(), false);
+
+    for ch in rest.chars() {
+        let unit = match ch {
+            '0'..='9' => {
+                number.push(ch);
+                continue;
+            }
+            'T' if number.is_empty() => {
+                in_time = true;
+                continue;
+            }
+            'D' if !in_time => 86_400,
+            'H' if in_time => 3_600,
+            'M' if in_time => 60,
+            'S' if in_time => 1,
+            _ => return None,
+        };
+        seconds += number.parse::<u64>().ok()? * unit;
+        number.clear();
+    }
+    number.is_empty().then_some(seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_iso_durations() {
+        assert_eq!(parse_iso_duration("PT4M13S"), Some(253));
+        assert_eq!(parse_iso_duration("PT1H0M5S"), Some(3605));
+        assert_eq!(parse_iso_duration("P1DT2H"), Some(93_600));
+        assert_eq!(parse_iso_duration("PT4M13"), None);
+        assert_eq!(parse_iso_duration("4:13"), None);
+    }
+
+    #[test]
+    fn formats_durations_for_badges() {
+        assert_eq!(duration_label(42), "0:42");
+        assert_eq!(duration_label(253), "4:13");
+        assert_eq!(duration_label(3723), "1:02:03");
+    }
+}
@@ -0,0 +1,197 @@
+//! Copying a whole library into an empty database on the other backend, such as from SQLite to Postgres once it
+//! has outgrown a single file. Rows keep their ids, so links between tables and short ids stay as they were.
+
+use crate::services::models::{self, ColumnKind::*, CopiedTable};
+
+/// Rows read and written at a time; snapshots can be large, so pages stay small
+const PAGE_SIZE: i64 = 200;
+
+/// Every table with data of its own, parents before the tables referring to them. `tag_counts` is left out: the
+/// target's triggers count the link rows as they arrive.
+pub const TABLES: [CopiedTable; 13] = [
+    CopiedTable {
+        name: "urls",
+        columns: &[
+            ("id", Integer),
+            ("datetime", Timestamp),
+            ("url", Text),
+            ("url_hash", Text),
+            ("read_at", Timestamp),
+            ("pinned", Boolean),
+            ("progress", Integer),
+            ("original_url", Text),
+            ("metadata", Text),
+            ("title", Text),
+            ("notes", Text),
+            ("archived_at", Timestamp),
+            ("snoozed_until", Timestamp),
+            ("normalized_url", Text),
+            ("revision", Integer),
+            ("short_id", Text),
+        ],
+        order: "id",
+        serial: true,
+    },
+    CopiedTable {
+        name: "tags",
+        columns: &[("id", Integer), ("tag", Text), ("color", Text), ("description", Text)],
+        order: "id",
+        serial: true,
+    },
+    CopiedTable {
+        name: "url_tags",
+        columns: &[("id", Integer), ("url_id", Integer), ("tag_id", Integer)],
+        order: "id",
+        serial: true,
+    },
+    CopiedTable {
+        name: "snippets",
+        columns: &[
+            ("id", Integer),
+            ("url", Text),
+            ("snippet", Text),
+            ("tags", TagList),
+            ("pinned", Boolean),
+            ("source", Text),
+            ("saved_at", Timestamp),
+            ("snippet_html", Text),
+        ],
+        order: "id",
+        serial: true,
+    },
+    CopiedTable {
+        name: "snippet_tags",
+        columns: &[("id", Integer), ("snippet_id", Integer), ("tag_id", Integer)],
+        order: "id",
+        serial: true,
+    },
+    CopiedTable {
+        name: "snippet_images",
+        columns: &[("snippet_id", Integer), ("content_type", Text), ("data", Bytes)],
+        order: "snippet_id",
+        serial: false,
+    },
+    CopiedTable {
+        name: "activity",
+        columns: &[
+            ("id", Integer),
+            ("datetime", Timestamp),
+            ("action", Text),
+            ("subject", Text),
+            ("detail", Text),
+            ("origin", Text),
+        ],
+        order: "id",
+        serial: true,
+    },
+    CopiedTable {
+        name: "peers",
+        columns: &[("url", Text), ("last_change_id", Integer), ("synced_at", Timestamp)],
+        order: "url",
+        serial: false,
+    },
+    CopiedTable {
+        name: "import_jobs",
+        columns: &[
+            ("id", Integer),
+            ("source", Text),
+            ("status", Text),
+            ("total", Integer),
+            ("processed", Integer),
+            ("created", Integer),
+            ("skipped", Integer),
+            ("failed", Integer),
+            ("started_at", Timestamp),
+            ("finished_at", Timestamp),
+        ],
+        order: "id",
+        serial: true,
+    },
+    CopiedTable {
+        name: "import_job_items",
+        columns: &[
+            ("id", Integer),
+            ("job_id", Integer),
+            ("url", Text),
+            ("tags", Text),
+            ("note", Text),
+            ("outcome", Text),
+            ("error", Text),
+            ("started", Boolean),
+        ],
+        order: "id",
+        serial: true,
+    },
+    CopiedTable {
+        name: "url_snapshots",
+        columns: &[
+            ("id", Integer),
+            ("url_id", Integer),
+            ("datetime", Timestamp),
+            ("content_type", Text),
+            ("content", Text),
+            ("accessed_at", Timestamp),
+            ("truncated", Boolean),
+        ],
+        order: "id",
+        serial: true,
+    },
+    CopiedTable {
+        name: "url_aliases",
+        columns: &[("alias_hash", Text), ("url_id", Integer)],
+        order: "alias_hash",
+        serial: false,
+    },
+    CopiedTable {
+        name: "url_metadata",
+        columns: &[
+            ("url_id", Integer),
+            ("key", Text),
+            ("value", Json),
+            ("updated_at", Timestamp),
+        ],
+        order: "url_id, key",
+        serial: false,
+    },
+];
+
+/// Copy every table from `from` into `to`, calling `copied` with each table's name and row count as it finishes.
+/// `to` must be empty, so nothing there can clash with the ids being copied.
+pub async fn copy(
+    from: &dyn models::Database,
+    to: &dyn models::Database,
+    mut copied: impl FnMut(&str, usize),
+) -> Result<(), String> {
+    for table in &TABLES {
+        if !to
+            .read_rows(table, 0, 1)
+            .await
+            .map_err(|err| err.to_string())?
+            .is_empty()
+        {
+            return Err(format!(
+                "The target database already has {}; copy into an empty one",
+                table.name
+            ));
+        }
+    }
+
+    for table in &TABLES {
+        let mut count = 0;
+        loop {
+            let rows = from
+                .read_rows(table, count as i64, PAGE_SIZE)
+                .await
+                .map_err(|err| format!("Failed to read {}: {}", table.name, err))?;
+            if rows.is_empty() {
+                break;
+            }
+            to.write_rows(table, &rows)
+                .await
+                .map_err(|err| format!("Failed to write {}: {}", table.name, err))?;
+            count += rows.len();
+        }
+        copied(table.name, count);
+    }
+    Ok(())
+}
@@ -1,4 +1,42 @@
+pub mod access;
+pub mod amp;
 pub mod api;
+pub mod audio;
+pub mod briefing;
+pub mod cached_database;
+pub mod canonical;
+pub mod changes;
+pub mod classify;
+pub mod crypto;
+pub mod display;
+pub mod errors;
+pub mod exporters;
+pub mod fetcher;
+pub mod headers;
+pub mod html;
+pub mod importers;
+pub mod limits;
+pub mod listen;
+pub mod metadata;
+pub mod migrate_data;
 pub mod models;
+pub mod oembed;
+pub mod partials;
+pub mod peering;
 pub mod postgres_database;
+pub mod proxy;
+pub mod pwa;
+pub mod qr;
+pub mod retention;
+pub mod review;
+pub mod scheduler;
+pub mod self_check;
+pub mod sitemap;
+pub mod snapshot_cache;
+pub mod snapshots;
+pub mod snippet_images;
 pub mod sqlite_database;
+pub mod stats;
+pub mod tracking;
+pub mod video;
+pub mod websocket;
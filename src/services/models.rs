@@ -1,11 +1,23 @@
-use chrono;
-use serde::{Deserialize, Serialize};
+use chrono::{self, Datelike};
+use futures_util::stream::BoxStream;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use sqlx::FromRow;
+use std::collections::{BTreeMap, HashSet};
+use std::marker::PhantomData;
 
 #[derive(Deserialize)]
 pub struct UrlTags {
     pub url: String,
-    pub tags: String,
+    #[serde(deserialize_with = "tag_list")]
+    pub tags: Vec<String>,
+}
+
+/// Length of the public short id used in `/s/{id}` links, taken from the URL's hash when it is first saved
+pub const SHORT_ID_LENGTH: usize = 10;
+
+/// Whether `id` looks like a short id, so lookups can reject anything else up front
+pub fn is_short_id(id: &str) -> bool {
+    id.len() == SHORT_ID_LENGTH && id.bytes().all(|byte| matches!(byte, b'0'..=b'9' | b'a'..=b'f'))
 }
 
 /// Struct representing a URL
@@ -15,43 +27,420 @@ pub struct Url {
     pub datetime: chrono::NaiveDateTime,
     pub url: String,
     pub url_hash: String,
+    pub read_at: Option<chrono::NaiveDateTime>,
+    pub short_id: String,
+    pub pinned: bool,
+    /// How far through the article the reader got, 0 to 100
+    pub progress: i32,
+    /// The URL as first given, when it is stored under a cleaner or canonical form
+    pub original_url: Option<String>,
 }
 
 #[derive(Serialize, Debug)]
 pub struct UrlWithTags {
+    pub id: i32,
     pub url: String,
+    pub url_hash: String,
+    pub datetime: chrono::NaiveDateTime,
     pub tags: Vec<String>,
     pub display_url: String,
+    /// What the URL is shown as; see `display_title`
+    pub display_title: String,
+    pub read_at: Option<chrono::NaiveDateTime>,
+    pub short_id: String,
+    pub pinned: bool,
+    /// How far through the article the reader got, 0 to 100
+    pub progress: i32,
+    pub metadata: Option<UrlMetadata>,
+    /// A title given to the URL by hand
+    pub title: Option<String>,
+    pub notes: Option<String>,
+    pub archived_at: Option<chrono::NaiveDateTime>,
+    /// Kept out of the way until then
+    pub snoozed_until: Option<chrono::NaiveDateTime>,
+}
+
+/// Details looked up for a saved URL, stored as JSON in `urls.metadata`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum UrlMetadata {
+    Video(VideoMetadata),
+    Audio(AudioMetadata),
+}
+
+impl UrlMetadata {
+    /// Parse the stored column; anything unreadable is treated as no metadata
+    pub fn from_column(json: Option<String>) -> Option<Self> {
+        serde_json::from_str(&json?).ok()
+    }
+
+    /// Length in seconds, when known
+    pub fn duration(&self) -> Option<u64> {
+        match self {
+            Self::Video(video) => video.duration,
+            Self::Audio(audio) => audio.duration,
+        }
+    }
+
+    /// The title the platform or feed gave, when there was one
+    pub fn title(&self) -> Option<&str> {
+        match self {
+            Self::Video(video) => video.title.as_deref(),
+            Self::Audio(audio) => audio.title.as_deref(),
+        }
+    }
+}
+
+/// The URL without its scheme, query string, fragment or trailing slash
+pub fn trimmed_url(url: &str) -> &str {
+    let without_scheme = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let without_query = without_scheme.split(['?', '#']).next().unwrap_or(without_scheme);
+    without_query.trim_end_matches('/')
+}
+
+/// What a URL is shown as in listings and feeds: the title given by hand, then the one looked up with its
+/// metadata, then the trimmed URL
+pub fn display_title(title: Option<&str>, metadata: Option<&UrlMetadata>, url: &str) -> String {
+    title
+        .or(metadata.and_then(UrlMetadata::title))
+        .map(str::trim)
+        .filter(|title| !title.is_empty())
+        .unwrap_or_else(|| trimmed_url(url))
+        .to_string()
+}
+
+/// What a video platform reports about a video
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct VideoMetadata {
+    pub provider: String,
+    pub title: Option<String>,
+    pub channel: Option<String>,
+    /// Length in seconds, when the platform reports it
+    pub duration: Option<u64>,
+    pub thumbnail_url: Option<String>,
+}
+
+/// An audio file a URL is, or a podcast episode page links to
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AudioMetadata {
+    pub audio_url: String,
+    /// MIME type, such as `audio/mpeg`
+    pub mime_type: Option<String>,
+    /// Size of the file in bytes, as the server or feed reported it
+    pub length: Option<u64>,
+    /// Length in seconds
+    pub duration: Option<u64>,
+    pub title: Option<String>,
 }
 
 #[derive(Deserialize)]
 pub struct NewUrl {
     pub url: String,
+    /// Tags added to the URL's own, whether it is new or already saved
+    #[serde(default, deserialize_with = "tag_list")]
+    pub tags: Vec<String>,
+}
+
+/// Response to `POST /urls/url`: the URL as saved, and whether it was saved before this request
+#[derive(Serialize, Debug)]
+pub struct SavedUrl {
+    #[serde(flatten)]
+    pub url: UrlWithTags,
+    pub already_existed: bool,
 }
 
+/// Form posted by the index's save box; `tags` is comma-separated and may be left empty
+#[derive(Deserialize, Debug)]
+pub struct SaveForm {
+    pub url: String,
+    #[serde(default)]
+    pub tags: String,
+}
+
+/// Form posted by a URL's tag chips on the index: the tags it keeps, plus any typed into `add`
+#[derive(Deserialize, Debug)]
+pub struct TagSetForm {
+    #[serde(default)]
+    pub tags: String,
+    #[serde(default)]
+    pub add: String,
+}
+
+/// Form posted by a URL's row on the index, applying whichever fields are present
+#[derive(Deserialize, Debug)]
+pub struct RowUpdate {
+    pub read: Option<bool>,
+    pub pinned: Option<bool>,
+    /// Comma-separated tags to add
+    pub tags: Option<String>,
+}
+
+/// Body of `POST /urls/read`; `read` defaults to true, false marks the URL unread again
+#[derive(Deserialize, Debug)]
+pub struct MarkRead {
+    pub url: String,
+    pub read: Option<bool>,
+}
+
+/// Body of `PUT /urls/{id}/progress`
+#[derive(Deserialize, Debug)]
+pub struct ReadingProgress {
+    pub progress: i32,
+}
+
+impl ReadingProgress {
+    /// Progress is a percentage
+    pub fn validate(&self) -> Result<i32, String> {
+        if (0..=100).contains(&self.progress) {
+            Ok(self.progress)
+        } else {
+            Err("progress must be between 0 and 100".to_string())
+        }
+    }
+}
+
+/// Body of `POST /urls/pin`; `pinned` defaults to true, false unpins the URL
+#[derive(Deserialize, Debug)]
+pub struct PinUrl {
+    pub url: String,
+    pub pinned: Option<bool>,
+}
+
+/// Body of `POST /snippets/pin`; `pinned` defaults to true, false unpins the snippet
+#[derive(Deserialize, Debug)]
+pub struct PinSnippet {
+    pub id: i32,
+    pub pinned: Option<bool>,
+}
+
+/// Query string for `GET /urls/random`; `tag` takes the same expressions as `tags=` on listings
+#[derive(Deserialize, Debug)]
+pub struct RandomQuery {
+    pub unread: Option<bool>,
+    pub tag: Option<String>,
+}
+
+/// Form fields sent by the Web Share Target API
+#[derive(Deserialize, Debug)]
+pub struct SharedContent {
+    pub title: Option<String>,
+    pub text: Option<String>,
+    pub url: Option<String>,
+}
+
+/// Body of `POST /snippets`; the browser extension also sends where on the page the selection came from
 #[derive(Deserialize)]
 pub struct NewSnippet {
     pub url: String,
     pub snippet: String,
+    #[serde(deserialize_with = "tag_list")]
+    pub tags: Vec<String>,
+    #[serde(flatten)]
+    pub source: SnippetSource,
+}
+
+/// Body of `POST /snippets/image` as JSON; `image` is base64, bare or as a `data:` URL
+#[derive(Deserialize)]
+pub struct NewImageSnippet {
+    pub url: String,
+    pub image: String,
+    #[serde(default)]
+    pub caption: String,
+    #[serde(default)]
     pub tags: String,
 }
 
+/// Longest page title kept with a snippet, in characters
+const SOURCE_TITLE_LIMIT: usize = 300;
+
+/// Longest stretch of page text kept either side of a selection, in characters
+const SELECTION_CONTEXT_LIMIT: usize = 64;
+
+/// Largest selection markup kept, in bytes after sanitizing; bigger selections keep only their text
+pub const SOURCE_HTML_LIMIT: usize = 64 * 1024;
+
+/// The page a snippet was taken from, as captured by the browser extension, stored as JSON in `snippets.source`
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SnippetSource {
+    /// The selection's markup
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub html: Option<String>,
+    /// The page's title when the snippet was taken
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub favicon: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub selection: Option<SelectionRange>,
+}
+
+/// Where a selection sits in the page's text, so it can be found again
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SelectionRange {
+    /// Character offsets of the selection within the page's text
+    pub start: u32,
+    pub end: u32,
+    /// Text just before and after the selection, for finding it once the page has changed
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub prefix: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub suffix: String,
+}
+
+impl SnippetSource {
+    /// Parse the stored column; anything unreadable is treated as no source
+    pub fn from_column(json: Option<String>) -> Option<Self> {
+        serde_json::from_str(&json?).ok()
+    }
+
+    /// The source made safe to store and show for a snippet taken from `page_url`, or `None` when nothing is left.
+    ///
+    /// Markup keeps only text formatting and links, with relative links resolved against the page. The favicon
+    /// must be an http(s) image, titles and context are trimmed to a readable length, and a selection whose
+    /// offsets are reversed is dropped.
+    pub fn sanitized(self, page_url: &str) -> Option<Self> {
+        let base = reqwest::Url::parse(page_url).ok();
+        let mut cleaner = ammonia::Builder::default();
+        cleaner.rm_tags(["img"]).link_rel(Some("noopener noreferrer nofollow"));
+        if let Some(base) = base.clone() {
+            cleaner.url_relative(ammonia::UrlRelative::RewriteWithBase(base));
+        }
+
+        let html = self
+            .html
+            .map(|html| cleaner.clean(&html).to_string())
+            .filter(|html| !html.trim().is_empty() && html.len() <= SOURCE_HTML_LIMIT);
+        let title = self
+            .title
+            .map(|title| {
+                clipped(
+                    &title.split_whitespace().collect::<Vec<_>>().join(" "),
+                    SOURCE_TITLE_LIMIT,
+                )
+            })
+            .filter(|title| !title.is_empty());
+        let favicon = self
+            .favicon
+            .and_then(|favicon| match &base {
+                Some(base) => base.join(favicon.trim()).ok(),
+                None => reqwest::Url::parse(favicon.trim()).ok(),
+            })
+            .filter(|favicon| matches!(favicon.scheme(), "http" | "https"))
+            .map(String::from);
+        let selection = self
+            .selection
+            .filter(|selection| selection.start <= selection.end)
+            .map(|selection| SelectionRange {
+                prefix: clipped_end(&selection.prefix, SELECTION_CONTEXT_LIMIT),
+                suffix: clipped(&selection.suffix, SELECTION_CONTEXT_LIMIT),
+                ..selection
+            });
+
+        let source = Self {
+            html,
+            title,
+            favicon,
+            selection,
+        };
+        (source != Self::default()).then_some(source)
+    }
+}
+
+/// The first `limit` characters of `text`
+fn clipped(text: &str, limit: usize) -> String {
+    text.chars().take(limit).collect()
+}
+
+/// The last `limit` characters of `text`, the ones nearest a selection that follows it
+fn clipped_end(text: &str, limit: usize) -> String {
+    let skip = text.chars().count().saturating_sub(limit);
+    text.chars().skip(skip).collect()
+}
+
 #[derive(Deserialize, Debug)]
 pub struct DeleteSnippet {
     pub id: i32,
 }
 
+/// How a request naming a saved URL finds it
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum UrlMatch {
+    /// Only the URL exactly as saved
+    #[default]
+    Exact,
+    /// Failing that, a saved URL with the same `loose_url`
+    Loose,
+}
+
+/// A URL compared by what a person would call the same address: without its scheme, in lower case and without
+/// trailing slashes on the path. Kept in `urls.normalized_url` for `UrlMatch::Loose` lookups.
+pub fn loose_url(url: &str) -> String {
+    let url = url.trim().to_lowercase();
+    let url = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))
+        .unwrap_or(&url);
+    let (path, rest) = url.split_at(url.find(['?', '#']).unwrap_or(url.len()));
+    format!("{}{}", path.trim_end_matches('/'), rest)
+}
+
+/// Query of `GET /urls/exists`
+#[derive(Deserialize, Debug)]
+pub struct UrlLookup {
+    pub url: String,
+    #[serde(default, rename = "match")]
+    pub matching: UrlMatch,
+}
+
 #[derive(Deserialize, Debug)]
 pub struct DeleteUrlByUrl {
     pub url: String,
+    #[serde(default, rename = "match")]
+    pub matching: UrlMatch,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+/// Tags kept in snippet text on top of ammonia's defaults, unless `SNIPPET_HTML_TAGS` names the set instead
+pub const SNIPPET_HTML_TAGS: [&str; 5] = ["b", "i", "em", "strong", "a"];
+
+/// Snippet text made safe to hand out as HTML. `tags` replaces the allowed tags when given; links keep their
+/// `href` and anything may keep a `title`.
+pub fn sanitize_snippet_html(text: &str, tags: Option<&[String]>) -> String {
+    let mut cleaner = ammonia::Builder::default();
+    match tags {
+        Some(tags) => cleaner.tags(tags.iter().map(String::as_str).collect()),
+        None => cleaner.add_tags(SNIPPET_HTML_TAGS),
+    };
+    cleaner
+        .add_generic_attributes(["href", "title"])
+        .clean(text)
+        .to_string()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SnippetWithTags {
     pub id: i32,
+    /// The text as it was saved
     pub snippet: String,
+    /// The text sanitized as it was saved, which is what responses show in place of `snippet`
+    #[serde(skip)]
+    pub snippet_html: Option<String>,
     pub url: String,
     pub tags: Vec<String>,
+    #[serde(default)]
+    pub pinned: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<SnippetSource>,
+    /// Content type of the picture, for image snippets; it is served from `/snippets/{id}/image`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_type: Option<String>,
+}
+
+/// The picture of an image snippet, such as a screenshot of part of a page
+#[derive(FromRow, Debug, Clone, PartialEq)]
+pub struct SnippetImage {
+    pub content_type: String,
+    pub data: Vec<u8>,
 }
 
 #[derive(Serialize, Debug, Clone)]
@@ -61,6 +450,948 @@ pub struct TagWithUrlsAndSnippets {
     pub snippets: Vec<SnippetWithTags>,
 }
 
+/// A tag with its optional badge color and description
+#[derive(FromRow, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TagDetails {
+    pub tag: String,
+    pub color: Option<String>,
+    pub description: Option<String>,
+}
+
+/// Every tag with its details and none of the content, written by `GET /export/tags` and read by
+/// `POST /imports/tags`. Nesting is carried by the `/` in tag names.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TagTaxonomy {
+    pub tags: Vec<TagDetails>,
+}
+
+/// How many imported tags were new to this instance, and how many already existed
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Default)]
+pub struct TagImport {
+    pub created: u64,
+    pub updated: u64,
+}
+
+/// How many URLs and snippets carry a tag
+#[derive(FromRow, Serialize, Debug, Clone, PartialEq)]
+pub struct TagCount {
+    pub tag: String,
+    pub urls: i64,
+    pub snippets: i64,
+}
+
+/// How many URLs are saved and how many of them are still unread, for the extension's toolbar badge
+#[derive(FromRow, Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct UrlCounts {
+    pub unread_count: i64,
+    pub total: i64,
+}
+
+/// A cheap stand-in for a listing's contents: it changes when a URL matching the filter is saved, changed, tagged
+/// or removed, and when anything is recorded in the activity timeline
+#[derive(FromRow, Debug, Clone, Copy, PartialEq)]
+pub struct ListingFingerprint {
+    pub count: i64,
+    pub max_id: Option<i32>,
+    /// Sum of the matching URLs' revisions, which count up on every change to a row or its tags
+    pub revisions: Option<i64>,
+    pub activity_id: Option<i32>,
+}
+
+impl ListingFingerprint {
+    /// The fingerprint as a quoted `ETag` value
+    pub fn etag(&self) -> String {
+        format!(
+            "\"{}-{}-{}-{}\"",
+            self.count,
+            self.max_id.unwrap_or(0),
+            self.revisions.unwrap_or(0),
+            self.activity_id.unwrap_or(0)
+        )
+    }
+}
+
+/// What a column holds, as far as copying it to the other backend needs to know
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColumnKind {
+    Integer,
+    Text,
+    Timestamp,
+    Boolean,
+    Bytes,
+    /// Text in SQLite, `jsonb` in Postgres
+    Json,
+    /// A JSON array in SQLite, a `TEXT[]` in Postgres
+    TagList,
+}
+
+/// One value of a copied row; `Null` stands for a missing value of the column's kind
+#[derive(Debug, Clone, PartialEq)]
+pub enum ColumnValue {
+    Null,
+    Integer(i64),
+    Text(String),
+    Timestamp(chrono::NaiveDateTime),
+    Boolean(bool),
+    Bytes(Vec<u8>),
+    TagList(Vec<String>),
+}
+
+/// A table as `migrate-data` copies it: every column, read in `order` so pages don't overlap
+#[derive(Debug)]
+pub struct CopiedTable {
+    pub name: &'static str,
+    pub columns: &'static [(&'static str, ColumnKind)],
+    pub order: &'static str,
+    /// Whether `id` comes from a sequence that has to be moved past the copied ids
+    pub serial: bool,
+}
+
+/// Body of `PUT /api/v1/tags/{tag}`; a missing or blank field clears it
+#[derive(Deserialize, Debug)]
+pub struct TagDetailsUpdate {
+    pub color: Option<String>,
+    pub description: Option<String>,
+}
+
+/// Longest tag description accepted
+pub const TAG_DESCRIPTION_LIMIT: usize = 500;
+
+impl TagDetailsUpdate {
+    /// The trimmed color and description, with blanks as `None`; colors must be `#rgb` or `#rrggbb`
+    pub fn validate(&self) -> Result<(Option<String>, Option<String>), String> {
+        let trimmed = |value: &Option<String>| {
+            value
+                .as_deref()
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+                .map(String::from)
+        };
+        let color = trimmed(&self.color).map(|color| color.to_lowercase());
+        let description = trimmed(&self.description);
+
+        // Stored as #rrggbb so templates can append an alpha channel for the badge background
+        let color = match color {
+            Some(color) => {
+                let hex = color.strip_prefix('#').unwrap_or_default();
+                if !matches!(hex.len(), 3 | 6) || !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+                    return Err(format!("Invalid color {:?}, expected #rgb or #rrggbb", color));
+                }
+                if hex.len() == 3 {
+                    Some(hex.chars().fold(String::from("#"), |mut full, digit| {
+                        full.push(digit);
+                        full.push(digit);
+                        full
+                    }))
+                } else {
+                    Some(color)
+                }
+            }
+            None => None,
+        };
+        if description
+            .as_ref()
+            .is_some_and(|description| description.chars().count() > TAG_DESCRIPTION_LIMIT)
+        {
+            return Err(format!(
+                "Description is longer than {} characters",
+                TAG_DESCRIPTION_LIMIT
+            ));
+        }
+
+        Ok((color, description))
+    }
+}
+
+impl TagTaxonomy {
+    /// The tags with trimmed names, and details checked as `PUT /api/v1/tags/{tag}` checks them
+    pub fn validate(&self) -> Result<Vec<TagDetails>, String> {
+        self.tags
+            .iter()
+            .map(|details| {
+                let tag = details.tag.trim();
+                if tag.is_empty() || tag.contains(',') {
+                    return Err(format!("Invalid tag name {:?}", details.tag));
+                }
+                let update = TagDetailsUpdate {
+                    color: details.color.clone(),
+                    description: details.description.clone(),
+                };
+                let (color, description) = update.validate().map_err(|err| format!("{}: {}", tag, err))?;
+                Ok(TagDetails {
+                    tag: tag.to_string(),
+                    color,
+                    description,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Body of `PATCH /api/v1/urls/{id}`. Fields left out stay as they are; `null` clears a title, notes or snooze.
+#[derive(Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct UrlUpdate {
+    #[serde(default, deserialize_with = "present")]
+    pub title: Option<Option<String>>,
+    #[serde(default, deserialize_with = "present")]
+    pub notes: Option<Option<String>>,
+    pub read: Option<bool>,
+    pub archived: Option<bool>,
+    #[serde(default, deserialize_with = "present")]
+    pub snoozed_until: Option<Option<chrono::DateTime<chrono::Utc>>>,
+    /// Comma-separated, replacing the URL's tags
+    pub tags: Option<String>,
+}
+
+/// Tells a field sent as `null` apart from one left out, which `#[serde(default)]` leaves as `None`
+fn present<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: serde::Deserializer<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+/// Tags in a JSON body, as a list or, as older clients send them, one comma-separated string. Each is trimmed
+/// and blanks are dropped; a listed tag can't hold a comma, since listings join tags with them.
+pub(crate) fn tag_list<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    struct TagList;
+
+    impl<'de> serde::de::Visitor<'de> for TagList {
+        type Value = Vec<String>;
+
+        fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+            formatter.write_str("a list of tags or a comma-separated string")
+        }
+
+        fn visit_str<E: serde::de::Error>(self, tags: &str) -> Result<Self::Value, E> {
+            Ok(split_tags(tags)
+                .into_iter()
+                .filter(|tag| !tag.is_empty())
+                .map(str::to_string)
+                .collect())
+        }
+
+        fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            let mut tags = Vec::new();
+            while let Some(tag) = seq.next_element::<String>()? {
+                let tag = tag.trim();
+                if tag.contains(',') {
+                    return Err(serde::de::Error::custom(format!("tag {:?} has a comma in it", tag)));
+                }
+                if !tag.is_empty() {
+                    tags.push(tag.to_string());
+                }
+            }
+            Ok(tags)
+        }
+    }
+
+    deserializer.deserialize_any(TagList)
+}
+
+/// Longest title accepted for a URL
+pub const URL_TITLE_LIMIT: usize = 300;
+/// Longest notes accepted for a URL
+pub const URL_NOTES_LIMIT: usize = 10_000;
+
+impl UrlUpdate {
+    /// The update with the title and notes trimmed and blanks as cleared, or why it was refused
+    pub fn validate(self) -> Result<Self, String> {
+        let trimmed = |value: Option<Option<String>>| {
+            value.map(|value| {
+                value
+                    .map(|value| value.trim().to_string())
+                    .filter(|value| !value.is_empty())
+            })
+        };
+        let update = Self {
+            title: trimmed(self.title),
+            notes: trimmed(self.notes),
+            ..self
+        };
+
+        if update == Self::default() {
+            return Err("Nothing to update".to_string());
+        }
+        let too_long = |value: &Option<Option<String>>, limit| {
+            value
+                .as_ref()
+                .and_then(Option::as_ref)
+                .is_some_and(|value| value.chars().count() > limit)
+        };
+        if too_long(&update.title, URL_TITLE_LIMIT) {
+            return Err(format!("Title is longer than {} characters", URL_TITLE_LIMIT));
+        }
+        if too_long(&update.notes, URL_NOTES_LIMIT) {
+            return Err(format!("Notes are longer than {} characters", URL_NOTES_LIMIT));
+        }
+        Ok(update)
+    }
+
+    /// The tags to replace the URL's with, if they are being changed; an empty list removes them all
+    pub fn tag_list(&self) -> Option<Vec<&str>> {
+        self.tags
+            .as_deref()
+            .map(|tags| split_tags(tags).into_iter().filter(|tag| !tag.is_empty()).collect())
+    }
+}
+
+/// Body of `PUT /api/v1/urls/{id}/url`
+#[derive(Deserialize, Debug)]
+pub struct UrlMove {
+    pub url: String,
+}
+
+/// Response to moving a URL: the URL at its new address, and whether it was folded into one already saved there
+#[derive(Serialize, Debug)]
+pub struct MovedUrl {
+    #[serde(flatten)]
+    pub url: UrlWithTags,
+    pub merged: bool,
+}
+
+/// Separates a parent tag from its child, as in `lang/rust`
+pub const TAG_SEPARATOR: char = '/';
+
+/// `lang/rust` -> `["lang"]`, `a/b/c` -> `["a", "a/b"]`
+pub fn parent_tags(tag: &str) -> Vec<&str> {
+    tag.match_indices(TAG_SEPARATOR)
+        .map(|(index, _)| &tag[..index])
+        .filter(|parent| !parent.is_empty() && !parent.ends_with(TAG_SEPARATOR))
+        .collect()
+}
+
+/// Add a group for every parent tag holding everything tagged beneath it, so `lang/rust` and `lang/go`
+/// can be browsed under `lang`. Each parent comes back just before its children.
+pub fn roll_up_tags(tags: Vec<TagWithUrlsAndSnippets>) -> Vec<TagWithUrlsAndSnippets> {
+    let mut groups: std::collections::HashMap<String, TagWithUrlsAndSnippets> =
+        tags.iter().map(|tag| (tag.tag.clone(), tag.clone())).collect();
+
+    for tag in &tags {
+        for parent in parent_tags(&tag.tag) {
+            let group = groups
+                .entry(parent.to_string())
+                .or_insert_with(|| TagWithUrlsAndSnippets {
+                    tag: parent.to_string(),
+                    urls: Vec::new(),
+                    snippets: Vec::new(),
+                });
+            for url in &tag.urls {
+                if !group.urls.contains(url) {
+                    group.urls.push(url.clone());
+                }
+            }
+            for snippet in &tag.snippets {
+                if !group.snippets.iter().any(|existing| existing.id == snippet.id) {
+                    group.snippets.push(snippet.clone());
+                }
+            }
+        }
+    }
+
+    let mut groups: Vec<TagWithUrlsAndSnippets> = groups.into_values().collect();
+    // Compare segment by segment so `lang/go` sorts right after `lang`, ahead of `lang-tools`
+    groups.sort_by(|a, b| a.tag.split(TAG_SEPARATOR).cmp(b.tag.split(TAG_SEPARATOR)));
+    groups
+}
+
+/// A tag in the tags page's cloud, sized by how much carries it
+#[derive(Serialize, Debug, PartialEq)]
+pub struct CloudTag {
+    pub tag: String,
+    pub count: i64,
+    /// 1 for the least used tag up to `CLOUD_WEIGHTS` for the most used
+    pub weight: u8,
+}
+
+pub const CLOUD_WEIGHTS: u8 = 5;
+
+/// The tag cloud for `counts`, skipping tags nothing carries any more. Weights follow the logarithm of the
+/// count, so one huge tag doesn't shrink every other to the smallest size.
+pub fn tag_cloud(counts: Vec<TagCount>) -> Vec<CloudTag> {
+    let counts: Vec<(String, i64)> = counts
+        .into_iter()
+        .map(|count| (count.tag, count.urls + count.snippets))
+        .filter(|(_, count)| *count > 0)
+        .collect();
+    let scale = |count: i64| (count as f64).ln();
+    let least = counts
+        .iter()
+        .map(|(_, count)| scale(*count))
+        .fold(f64::INFINITY, f64::min);
+    let most = counts.iter().map(|(_, count)| scale(*count)).fold(0.0, f64::max);
+
+    counts
+        .into_iter()
+        .map(|(tag, count)| {
+            let position = if most > least {
+                (scale(count) - least) / (most - least)
+            } else {
+                0.0
+            };
+            CloudTag {
+                tag,
+                count,
+                weight: 1 + (position * f64::from(CLOUD_WEIGHTS - 1)).round() as u8,
+            }
+        })
+        .collect()
+}
+
+/// An entry in the activity timeline
+#[derive(FromRow, Serialize, Debug, Clone)]
+pub struct Activity {
+    pub id: i32,
+    pub datetime: chrono::NaiveDateTime,
+    pub action: String,
+    pub subject: String,
+    pub detail: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ActivityQuery {
+    pub limit: Option<i64>,
+}
+
+/// A saved URL as written to an account export
+#[derive(Serialize, Debug)]
+pub struct ExportedUrl {
+    pub url: String,
+    pub url_hash: String,
+    pub datetime: chrono::NaiveDateTime,
+    pub read_at: Option<chrono::NaiveDateTime>,
+    pub tags: Vec<String>,
+}
+
+/// A saved URL in an account export: what the other exports carry, plus everything set on it by hand and a list
+/// of the copies kept of its page
+#[derive(Serialize, Debug)]
+pub struct AccountUrl {
+    #[serde(flatten)]
+    pub url: ExportedUrl,
+    pub title: Option<String>,
+    pub notes: Option<String>,
+    pub pinned: bool,
+    pub progress: i32,
+    pub archived_at: Option<chrono::NaiveDateTime>,
+    pub snoozed_until: Option<chrono::NaiveDateTime>,
+    /// The URL's snapshots, listed without their content
+    pub snapshots: Vec<Snapshot>,
+}
+
+/// Everything stored in the library, as returned by `GET /account/export`
+#[derive(Serialize, Debug)]
+pub struct AccountExport {
+    pub exported_at: chrono::NaiveDateTime,
+    pub urls: Vec<AccountUrl>,
+    pub snippets: Vec<SnippetWithTags>,
+    pub tags: Vec<String>,
+    pub activity: Vec<Activity>,
+}
+
+/// One link to save, as produced by an importer
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ImportItem {
+    pub url: String,
+    pub tags: Vec<String>,
+    /// Saved as a snippet on the URL, e.g. a link to its discussion thread
+    pub note: Option<String>,
+}
+
+/// An import item that hasn't been processed yet
+#[derive(Debug, Clone)]
+pub struct PendingImportItem {
+    pub id: i32,
+    /// Its URL was being saved when the job last stopped
+    pub started: bool,
+    pub item: ImportItem,
+}
+
+/// What processing an import item did
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImportOutcome {
+    Created,
+    /// Already saved, matched by URL hash
+    Skipped,
+    Failed(String),
+}
+
+/// Import errors kept on a job for `GET /imports/{id}`
+pub const IMPORT_JOB_ERROR_LIMIT: i64 = 100;
+
+/// A persisted import and its progress; `status` is `running` until every item is processed, then `completed`
+#[derive(FromRow, Serialize, Debug, Clone)]
+pub struct ImportJob {
+    pub id: i32,
+    pub source: String,
+    pub status: String,
+    pub total: i32,
+    pub processed: i32,
+    pub created: i32,
+    pub skipped: i32,
+    pub failed: i32,
+    /// The first `IMPORT_JOB_ERROR_LIMIT` failures as `url: error`
+    #[sqlx(skip)]
+    pub errors: Vec<String>,
+    pub started_at: chrono::NaiveDateTime,
+    pub finished_at: Option<chrono::NaiveDateTime>,
+}
+
+impl std::fmt::Display for ImportJob {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {} created, {} skipped, {} failed",
+            self.source, self.created, self.skipped, self.failed
+        )
+    }
+}
+
+/// Split a comma-separated tag string into trimmed tags
+pub fn split_tags(tags: &str) -> Vec<&str> {
+    tags.split(',').map(|tag| tag.trim()).collect()
+}
+
+/// Tags as a snippet stores them: trimmed, without blanks, and each once in the order first given
+pub fn unique_tags<'a>(tags: impl IntoIterator<Item = &'a str>) -> Vec<&'a str> {
+    let mut seen = HashSet::new();
+    tags.into_iter()
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty() && seen.insert(*tag))
+        .collect()
+}
+
+/// Tags named more than once, each listed once
+pub fn repeated_tags<'a>(tags: &[&'a str]) -> Vec<&'a str> {
+    let mut seen = HashSet::new();
+    unique_tags(tags.iter().copied().filter(|tag| !seen.insert(tag.trim())))
+}
+
+/// Query string accepted by the listing endpoints
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ListQuery {
+    pub tags: Option<String>,
+    /// First day to include, as `YYYY-MM-DD`
+    pub from: Option<String>,
+    /// Last day to include, as `YYYY-MM-DD`
+    pub to: Option<String>,
+    /// Sort key with an optional direction, e.g. `domain:asc`
+    pub sort: Option<String>,
+    /// Only URLs of this kind, e.g. `video`
+    #[serde(rename = "type")]
+    pub kind: Option<String>,
+    /// `true` for only archived URLs, `false` for only the rest; the index leaves archived URLs out by default
+    pub archived: Option<bool>,
+    /// Page of the index to show, from 1; the JSON listings always return everything
+    pub page: Option<u32>,
+}
+
+impl ListQuery {
+    /// The query string for these parameters without `page`, for linking to other pages of the same listing
+    pub fn without_page(&self) -> String {
+        let archived = self.archived.map(|archived| archived.to_string());
+        let pairs = [
+            ("tags", &self.tags),
+            ("from", &self.from),
+            ("to", &self.to),
+            ("sort", &self.sort),
+            ("type", &self.kind),
+            ("archived", &archived),
+        ];
+        let pairs = pairs
+            .into_iter()
+            .filter_map(|(name, value)| Some((name, value.as_deref()?)));
+        reqwest::Url::parse_with_params("http://localhost/", pairs)
+            .ok()
+            .and_then(|url| url.query().map(String::from))
+            .unwrap_or_default()
+    }
+}
+
+/// Query string of `/api/v1/snippets`
+#[derive(Deserialize, Debug, Default)]
+pub struct SnippetsQuery {
+    /// Tag expression as `/urls_with_tags` takes it
+    pub tags: Option<String>,
+    /// Only snippets taken from this URL
+    pub url: Option<String>,
+    /// Page to return, from 1
+    pub page: Option<u32>,
+}
+
+/// Restrictions applied to a listing, pushed down into SQL by each backend
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ListFilter {
+    pub tags: TagFilter,
+    /// Only items without any tags
+    pub untagged: bool,
+    /// Only URLs that haven't been marked read
+    pub unread: bool,
+    /// Saved at or after this moment
+    pub saved_after: Option<chrono::NaiveDateTime>,
+    /// Saved strictly before this moment
+    pub saved_before: Option<chrono::NaiveDateTime>,
+    /// Only URLs whose metadata is of this kind
+    pub kind: Option<UrlKind>,
+    /// Only URLs saved on this day of the year, in one of its years
+    pub anniversary: Option<Anniversary>,
+    /// Only archived URLs with `true`, only ones not archived with `false`
+    pub archived: Option<bool>,
+    pub sort: Sort,
+    /// Only the URL with this short id
+    pub short_id: Option<String>,
+    /// Only the URL stored with this hash
+    pub url_hash: Option<String>,
+    /// Only snippets taken from this URL; URL listings ignore it
+    pub url: Option<String>,
+    /// Items to skip, then at most `limit` are returned
+    pub offset: i64,
+    pub limit: Option<i64>,
+}
+
+/// A month and day in some earlier years, for "on this day" listings
+#[derive(Debug, Clone, PartialEq)]
+pub struct Anniversary {
+    pub month: u32,
+    pub day: u32,
+    pub years: Vec<i32>,
+}
+
+impl Anniversary {
+    /// `today`'s month and day, `years_ago` years back
+    pub fn of(today: chrono::NaiveDate, years_ago: &[i32]) -> Self {
+        Self {
+            month: today.month(),
+            day: today.day(),
+            years: years_ago.iter().map(|years| today.year() - years).collect(),
+        }
+    }
+}
+
+/// Kinds of URL a listing can be narrowed to. URLs are classified in the background as they're saved, and
+/// ones with video or audio `UrlMetadata` count as that kind too.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum UrlKind {
+    Article,
+    Video,
+    Audio,
+    Code,
+    Shopping,
+    Social,
+    Document,
+}
+
+/// Where a URL's kind is kept in `url_metadata`
+pub const URL_KIND: MetadataKey<UrlKind> = MetadataKey::new("kind");
+
+impl UrlKind {
+    pub const ALL: [UrlKind; 7] = [
+        Self::Article,
+        Self::Video,
+        Self::Audio,
+        Self::Code,
+        Self::Shopping,
+        Self::Social,
+        Self::Document,
+    ];
+
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let value = value.trim();
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.as_str() == value)
+            .ok_or_else(|| {
+                let names: Vec<_> = Self::ALL.iter().map(|kind| kind.as_str()).collect();
+                format!("Unknown type {:?}, expected one of {}", value, names.join(", "))
+            })
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Article => "article",
+            Self::Video => "video",
+            Self::Audio => "audio",
+            Self::Code => "code",
+            Self::Shopping => "shopping",
+            Self::Social => "social",
+            Self::Document => "document",
+        }
+    }
+}
+
+/// What a URL listing is ordered by. There is no reading time to sort by: it is only worked out from a snapshot
+/// when a briefing is put together, not stored with the URL.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum SortKey {
+    #[default]
+    Added,
+    /// When the URL was marked read, unread URLs last
+    Read,
+    /// The title given by hand, or the URL without its scheme where there is none
+    Title,
+    Domain,
+    Url,
+}
+
+/// Listing order parsed from `sort=<key>[:asc|:desc]`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sort {
+    pub key: SortKey,
+    pub descending: bool,
+}
+
+impl Default for Sort {
+    fn default() -> Self {
+        Self {
+            key: SortKey::Added,
+            descending: true,
+        }
+    }
+}
+
+impl Sort {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let (key, direction) = match value.trim().split_once(':') {
+            Some((key, direction)) => (key, Some(direction)),
+            None => (value.trim(), None),
+        };
+
+        let key = match key {
+            "added" => SortKey::Added,
+            "read" => SortKey::Read,
+            "title" => SortKey::Title,
+            "domain" => SortKey::Domain,
+            "url" => SortKey::Url,
+            other => {
+                return Err(format!(
+                    "Unknown sort key {:?}, expected added, read, title, domain or url",
+                    other
+                ))
+            }
+        };
+        // Newest first is the natural default for dates, A to Z for everything else
+        let descending = match direction {
+            None => matches!(key, SortKey::Added | SortKey::Read),
+            Some("desc") => true,
+            Some("asc") => false,
+            Some(other) => return Err(format!("Unknown sort direction {:?}, expected asc or desc", other)),
+        };
+
+        Ok(Self { key, descending })
+    }
+}
+
+impl ListFilter {
+    pub fn untagged() -> Self {
+        Self {
+            untagged: true,
+            ..Self::default()
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty()
+            && !self.untagged
+            && !self.unread
+            && self.saved_after.is_none()
+            && self.saved_before.is_none()
+            && self.kind.is_none()
+            && self.short_id.is_none()
+            && self.url_hash.is_none()
+            && self.url.is_none()
+    }
+}
+
+impl TryFrom<&ListQuery> for ListFilter {
+    type Error = String;
+
+    fn try_from(query: &ListQuery) -> Result<Self, Self::Error> {
+        let day = |name: &str, value: &str| {
+            chrono::NaiveDate::parse_from_str(value.trim(), "%Y-%m-%d")
+                .map_err(|_| format!("Invalid {} date {:?}, expected YYYY-MM-DD", name, value))
+        };
+
+        let saved_after = match query.from.as_deref() {
+            Some(from) => Some(day("from", from)?.and_time(chrono::NaiveTime::MIN)),
+            None => None,
+        };
+        // `to` names the last day to include, so the bound is midnight of the day after
+        let saved_before = match query.to.as_deref() {
+            Some(to) => Some(day("to", to)?.and_time(chrono::NaiveTime::MIN) + chrono::Days::new(1)),
+            None => None,
+        };
+
+        if let (Some(after), Some(before)) = (saved_after, saved_before) {
+            if after >= before {
+                return Err("`from` must not be later than `to`".to_string());
+            }
+        }
+
+        Ok(Self {
+            tags: query.tags.as_deref().map(TagFilter::parse).unwrap_or_default(),
+            untagged: false,
+            unread: false,
+            saved_after,
+            saved_before,
+            kind: query.kind.as_deref().map(UrlKind::parse).transpose()?,
+            anniversary: None,
+            archived: query.archived,
+            sort: query.sort.as_deref().map(Sort::parse).transpose()?.unwrap_or_default(),
+            short_id: None,
+            url_hash: None,
+            url: None,
+            offset: 0,
+            limit: None,
+        })
+    }
+}
+
+/// Parsed `tags=rust+async,-video` expression.
+///
+/// Comma-separated terms are alternatives (OR); tags joined with `+` inside a
+/// term must all be present (AND); a term prefixed with `-` excludes any item
+/// carrying that tag. Since `+` decodes to a space in query strings, spaces
+/// join tags the same way. A tag also matches the tags nested under it, so
+/// `lang` matches `lang/rust`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TagFilter {
+    pub any_of: Vec<Vec<String>>,
+    pub none_of: Vec<String>,
+}
+
+impl TagFilter {
+    pub fn parse(expression: &str) -> Self {
+        let mut filter = Self::default();
+
+        for term in expression.split(',').map(str::trim).filter(|term| !term.is_empty()) {
+            if let Some(excluded) = term.strip_prefix('-') {
+                filter.none_of.extend(
+                    excluded
+                        .split(['+', ' '])
+                        .map(str::trim)
+                        .filter(|tag| !tag.is_empty())
+                        .map(String::from),
+                );
+                continue;
+            }
+
+            let mut group: Vec<String> = term
+                .split(['+', ' '])
+                .map(str::trim)
+                .filter(|tag| !tag.is_empty())
+                .map(String::from)
+                .collect();
+            group.sort();
+            group.dedup();
+            if !group.is_empty() {
+                filter.any_of.push(group);
+            }
+        }
+
+        filter
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.any_of.is_empty() && self.none_of.is_empty()
+    }
+}
+
+/// One step of a `POST /api/v1/batch` request
+#[derive(Deserialize, Debug)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum BatchOperation {
+    AddUrl { url: String },
+    TagUrl { url: String, tags: String },
+    DeleteSnippet { id: i32 },
+}
+
+impl BatchOperation {
+    pub fn name(&self) -> &'static str {
+        match self {
+            BatchOperation::AddUrl { .. } => "add_url",
+            BatchOperation::TagUrl { .. } => "tag_url",
+            BatchOperation::DeleteSnippet { .. } => "delete_snippet",
+        }
+    }
+
+    /// The operation with blank tags dropped, or why it was refused
+    pub fn validate(self) -> Result<Self, String> {
+        match self {
+            BatchOperation::TagUrl { url, tags } => {
+                let tags = split_tags(&tags)
+                    .into_iter()
+                    .filter(|tag| !tag.is_empty())
+                    .collect::<Vec<_>>()
+                    .join(",");
+                if tags.is_empty() {
+                    return Err("No tags given".to_string());
+                }
+                Ok(BatchOperation::TagUrl { url, tags })
+            }
+            operation => Ok(operation),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct BatchRequest {
+    pub operations: Vec<BatchOperation>,
+}
+
+/// Why a batch was rolled back; `index` is the failing operation, or `None` if the transaction itself failed
+#[derive(Debug)]
+pub struct BatchError {
+    pub index: Option<usize>,
+    pub error: sqlx::Error,
+}
+
+impl BatchError {
+    pub fn transaction(error: sqlx::Error) -> Self {
+        Self { index: None, error }
+    }
+}
+
+/// A stored copy of a saved page, listed without its content
+#[derive(FromRow, Serialize, Debug, Clone)]
+pub struct Snapshot {
+    pub id: i32,
+    pub datetime: chrono::NaiveDateTime,
+    pub content_type: Option<String>,
+    /// Size of the stored content in bytes
+    pub size: i64,
+    /// The page was longer than `SNAPSHOT_MAX_SIZE`, so only its start is stored
+    pub truncated: bool,
+}
+
+#[derive(FromRow, Debug, Clone)]
+pub struct SnapshotContent {
+    pub datetime: chrono::NaiveDateTime,
+    pub content: String,
+}
+
+/// A snapshot dropped, or due to be dropped, to keep snapshot storage under its cap
+#[derive(FromRow, Serialize, Debug, Clone)]
+pub struct EvictedSnapshot {
+    pub id: i32,
+    pub url: String,
+    pub size: i64,
+}
+
+/// A saved URL rewritten to a cleaner form, without tracking parameters or as its canonical URL
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct CleanedUrl {
+    pub original: String,
+    pub url: String,
+    /// The new form was already saved, so this row was folded into it
+    pub merged: bool,
+}
+
 #[async_trait::async_trait]
 pub trait Database: Send + Sync {
     async fn initialize(&self) -> Result<(), sqlx::Error>;
@@ -68,17 +1399,638 @@ pub trait Database: Send + Sync {
 
     // URL-related operations
     async fn insert_url(&self, url: &str) -> Result<i32, sqlx::Error>;
-    async fn get_urls_with_tags(&self) -> Result<Vec<UrlWithTags>, sqlx::Error>;
-    async fn get_all_urls(&self) -> Result<Vec<Url>, sqlx::Error>;
+    async fn url_exists(&self, url: &str) -> Result<bool, sqlx::Error>;
+    async fn get_url_counts(&self) -> Result<UrlCounts, sqlx::Error>;
+    // Looked up by hash, after the URL is cleaned the same way saving it would be
+    async fn get_url_with_tags(&self, url: &str) -> Result<Option<UrlWithTags>, sqlx::Error>;
+    async fn get_urls_with_tags(&self, filter: &ListFilter) -> Result<Vec<UrlWithTags>, sqlx::Error>;
+    // Ignores the filter's paging, which the request's own URL already tells apart
+    async fn get_listing_fingerprint(&self, filter: &ListFilter) -> Result<ListingFingerprint, sqlx::Error>;
+    async fn get_all_urls(&self, filter: &ListFilter) -> Result<Vec<Url>, sqlx::Error>;
+    // Every saved URL with its tags, oldest first, read from a cursor rather than loaded all at once
+    fn stream_exported_urls(&self) -> BoxStream<'_, Result<ExportedUrl, sqlx::Error>>;
+    // Saved URLs with the same `loose_url` as this one, oldest first
+    async fn get_loose_url_matches(&self, url: &str) -> Result<Vec<String>, sqlx::Error>;
+    // Also deletes, in the same transaction, any of the URL's tags nothing else carries, unless they have details
     async fn delete_url_by_url(&self, url: &str) -> Result<(), sqlx::Error>;
+    async fn get_random_url(&self, filter: &ListFilter) -> Result<Option<Url>, sqlx::Error>;
+    // The head of the listing `filter` describes, pinned URLs first
+    async fn get_next_url(&self, filter: &ListFilter) -> Result<Option<Url>, sqlx::Error>;
+    async fn get_url_by_short_id(&self, short_id: &str) -> Result<Option<Url>, sqlx::Error>;
+    // Fails with `RowNotFound` if the URL isn't saved
+    async fn set_url_read(&self, url: &str, read: bool) -> Result<(), sqlx::Error>;
+    async fn set_url_pinned(&self, url: &str, pinned: bool) -> Result<(), sqlx::Error>;
+    async fn set_url_progress(&self, url: &str, progress: i32) -> Result<(), sqlx::Error>;
+    async fn set_url_metadata(&self, url: &str, metadata: &UrlMetadata) -> Result<(), sqlx::Error>;
+    // Applies every change in one transaction; fails with `RowNotFound` if no URL has this id
+    async fn update_url(&self, url_id: i32, update: &UrlUpdate) -> Result<(), sqlx::Error>;
     async fn insert_tags(&self, url: &str, tags: &[&str]) -> Result<(), sqlx::Error>;
-    async fn remove_unused_tags(&self) -> Result<(), sqlx::Error>;
+    // Replaces the URL's tags with exactly `tags`, saving the URL first if needed
+    async fn set_url_tags(&self, url: &str, tags: &[&str]) -> Result<(), sqlx::Error>;
 
     // Snippet-related operations
-    async fn insert_snippet(&self, url: &str, snippet: &str, tags: &[&str]) -> Result<i32, sqlx::Error>;
+    /// Save a snippet, with the page it was taken from when the browser captured it
+    async fn insert_snippet(
+        &self,
+        url: &str,
+        snippet: &str,
+        tags: &[&str],
+        source: Option<&SnippetSource>,
+    ) -> Result<i32, sqlx::Error>;
+    /// Save a picture as a snippet, with `caption` as its text
+    async fn insert_image_snippet(
+        &self,
+        url: &str,
+        caption: &str,
+        tags: &[&str],
+        image: &SnippetImage,
+    ) -> Result<i32, sqlx::Error>;
+    async fn get_snippet_image(&self, snippet_id: i32) -> Result<Option<SnippetImage>, sqlx::Error>;
+    // Also deletes, in the same transaction, any of the snippet's tags nothing else carries, unless they have details
     async fn delete_snippet(&self, snippet_id: i32) -> Result<(), sqlx::Error>;
-    async fn get_snippets_with_tags(&self) -> Result<Vec<SnippetWithTags>, sqlx::Error>;
+    async fn set_snippet_pinned(&self, snippet_id: i32, pinned: bool) -> Result<(), sqlx::Error>;
+    async fn get_snippets_with_tags(&self, filter: &ListFilter) -> Result<Vec<SnippetWithTags>, sqlx::Error>;
+    async fn get_snippets_for_url(&self, url: &str) -> Result<Vec<SnippetWithTags>, sqlx::Error>;
+    async fn get_snippet(&self, snippet_id: i32) -> Result<Option<SnippetWithTags>, sqlx::Error>;
+    /// Snippets saved before `cutoff`, oldest first; ones saved before save times were kept count as old
+    async fn get_snippets_saved_before(
+        &self,
+        cutoff: chrono::NaiveDateTime,
+    ) -> Result<Vec<SnippetWithTags>, sqlx::Error>;
 
     // Tags-related operations
     async fn get_tags_with_urls_and_snippets(&self) -> Result<Vec<TagWithUrlsAndSnippets>, sqlx::Error>;
+    async fn get_all_tags(&self) -> Result<Vec<String>, sqlx::Error>;
+    // Read from `tag_counts`, which triggers keep current, rather than counted on every call
+    async fn get_tag_counts(&self) -> Result<Vec<TagCount>, sqlx::Error>;
+    async fn get_tag_details(&self) -> Result<Vec<TagDetails>, sqlx::Error>;
+    async fn get_tag(&self, tag: &str) -> Result<Option<TagDetails>, sqlx::Error>;
+    // Fails with `RowNotFound` if the tag doesn't exist
+    async fn set_tag_details(
+        &self,
+        tag: &str,
+        color: Option<&str>,
+        description: Option<&str>,
+    ) -> Result<(), sqlx::Error>;
+    // Creates missing tags, in one transaction; a color or description left out keeps what an existing tag has
+    async fn import_tags(&self, tags: &[TagDetails]) -> Result<TagImport, sqlx::Error>;
+
+    // Batch operations, executed in order in one transaction; returns the new URL id for each `add_url`
+    async fn execute_batch(&self, operations: &[BatchOperation]) -> Result<Vec<Option<i32>>, BatchError>;
+
+    // Activity timeline
+    async fn record_activity(&self, action: &str, subject: &str, detail: Option<&str>) -> Result<(), sqlx::Error>;
+    async fn get_activity(&self, limit: i64) -> Result<Vec<Activity>, sqlx::Error>;
+    // Entries recorded here after `after_id`, oldest first; changes applied from peers are left out
+    async fn get_local_activity_since(&self, after_id: i32, limit: i64) -> Result<Vec<Activity>, sqlx::Error>;
+    // The latest entry about `subject` with one of `actions`, wherever the change was made
+    async fn get_latest_activity(&self, subject: &str, actions: &[&str]) -> Result<Option<Activity>, sqlx::Error>;
+    // Every entry with one of `actions`, oldest first, wherever the change was made
+    async fn get_activity_by_actions(&self, actions: &[&str]) -> Result<Vec<Activity>, sqlx::Error>;
+    // Record a change applied from `peer`, dated when the peer made it
+    async fn record_peer_activity(&self, peer: &str, activity: &Activity) -> Result<(), sqlx::Error>;
+
+    // Peering: the last change applied from each peer, so the next sync carries on from there
+    async fn get_peer_cursor(&self, peer: &str) -> Result<i32, sqlx::Error>;
+    async fn set_peer_cursor(&self, peer: &str, cursor: i32) -> Result<(), sqlx::Error>;
+
+    // Import jobs; items are stored up front so an interrupted import picks up where it stopped
+    async fn create_import_job(&self, source: &str, items: &[ImportItem]) -> Result<i32, sqlx::Error>;
+    async fn get_import_job(&self, id: i32) -> Result<Option<ImportJob>, sqlx::Error>;
+    async fn get_running_import_jobs(&self) -> Result<Vec<i32>, sqlx::Error>;
+    async fn get_pending_import_items(&self, job_id: i32, limit: i64) -> Result<Vec<PendingImportItem>, sqlx::Error>;
+    // Counts the outcome against the job; finishing an item twice is a no-op
+    async fn start_import_item(&self, job_id: i32, item_id: i32) -> Result<(), sqlx::Error>;
+    async fn finish_import_item(&self, job_id: i32, item_id: i32, outcome: &ImportOutcome) -> Result<(), sqlx::Error>;
+    async fn complete_import_job(&self, id: i32) -> Result<(), sqlx::Error>;
+
+    // Page snapshots; an unchanged page isn't stored again, so `insert_snapshot` returns `None`
+    async fn insert_snapshot(
+        &self,
+        url_id: i32,
+        content_type: Option<&str>,
+        content: &str,
+        truncated: bool,
+        keep: i64,
+    ) -> Result<Option<i32>, sqlx::Error>;
+    async fn get_snapshots(&self, url_id: i32) -> Result<Vec<Snapshot>, sqlx::Error>;
+    async fn get_snapshot(&self, url_id: i32, id: i32) -> Result<Option<SnapshotContent>, sqlx::Error>;
+    // Marks the snapshot viewed as `get_snapshot` does without loading it, answering when it was taken
+    async fn touch_snapshot(&self, url_id: i32, id: i32) -> Result<Option<chrono::NaiveDateTime>, sqlx::Error>;
+    async fn get_snapshotted_urls(&self) -> Result<Vec<Url>, sqlx::Error>;
+
+    // Named JSON values about a URL by its id, usually read and written through `MetadataKey`s. Setting a name
+    // again replaces its value; setting one on a URL that isn't saved fails with `RowNotFound`.
+    async fn get_url_metadata_entries(&self, url_id: i32) -> Result<BTreeMap<String, serde_json::Value>, sqlx::Error>;
+    async fn get_url_metadata_entry(&self, url_id: i32, key: &str) -> Result<Option<serde_json::Value>, sqlx::Error>;
+    async fn set_url_metadata_entry(
+        &self,
+        url_id: i32,
+        key: &str,
+        value: &serde_json::Value,
+    ) -> Result<(), sqlx::Error>;
+
+    // Retention rules, returning what they changed; with `dry_run` they only report it. Pinned URLs are left alone.
+    async fn archive_unread_before(
+        &self,
+        cutoff: chrono::NaiveDateTime,
+        dry_run: bool,
+    ) -> Result<Vec<String>, sqlx::Error>;
+    async fn archive_read_before(
+        &self,
+        cutoff: chrono::NaiveDateTime,
+        dry_run: bool,
+    ) -> Result<Vec<String>, sqlx::Error>;
+    async fn delete_read_before(
+        &self,
+        cutoff: chrono::NaiveDateTime,
+        dry_run: bool,
+    ) -> Result<Vec<String>, sqlx::Error>;
+    // Drops the least recently viewed snapshots until the rest fit in `max_bytes`
+    async fn evict_snapshots(&self, max_bytes: i64, dry_run: bool) -> Result<Vec<EvictedSnapshot>, sqlx::Error>;
+
+    // Store a URL under the canonical form its page declares; the old form keeps resolving to it
+    async fn adopt_canonical_url(&self, url_id: i32, canonical: &str) -> Result<Option<CleanedUrl>, sqlx::Error>;
+    // Like `adopt_canonical_url`, for an address given by hand, so the old one doesn't become an alias
+    async fn move_url(&self, url_id: i32, url: &str) -> Result<Option<CleanedUrl>, sqlx::Error>;
+
+    // Maintenance: normalize URLs saved before the current rules applied, merging rows that turn out to be duplicates
+    async fn clean_urls(&self, dry_run: bool) -> Result<Vec<CleanedUrl>, sqlx::Error>;
+    // Maintenance: make each snippet's `tags` column and its `snippet_tags` rows agree, taking the column, without
+    // blanks or repeats, as the snippet's tags. Returns the ids of the snippets that didn't.
+    async fn repair_snippet_tags(&self, dry_run: bool) -> Result<Vec<i32>, sqlx::Error>;
+
+    // Account-related operations
+    async fn delete_all_data(&self) -> Result<(), sqlx::Error>;
+
+    // Copying between backends: rows as stored, values in the order of the table's columns. Writing keeps the
+    // ids it is given and moves the table's id sequence past them.
+    async fn read_rows(
+        &self,
+        table: &CopiedTable,
+        offset: i64,
+        limit: i64,
+    ) -> Result<Vec<Vec<ColumnValue>>, sqlx::Error>;
+    async fn write_rows(&self, table: &CopiedTable, rows: &[Vec<ColumnValue>]) -> Result<(), sqlx::Error>;
+}
+
+/// The name of a `url_metadata` entry along with the type its value is stored as, so enrichment features can keep
+/// what they find without a column of their own
+pub struct MetadataKey<T> {
+    pub name: &'static str,
+    value: PhantomData<fn() -> T>,
+}
+
+impl<T> MetadataKey<T> {
+    pub const fn new(name: &'static str) -> Self {
+        MetadataKey {
+            name,
+            value: PhantomData,
+        }
+    }
+}
+
+impl<'a> dyn Database + 'a {
+    /// Read a metadata entry, failing with `Decode` if what's stored no longer fits `T`
+    pub async fn get_metadata<T: DeserializeOwned>(
+        &self,
+        url_id: i32,
+        key: &MetadataKey<T>,
+    ) -> Result<Option<T>, sqlx::Error> {
+        match self.get_url_metadata_entry(url_id, key.name).await? {
+            Some(value) => serde_json::from_value(value)
+                .map(Some)
+                .map_err(|err| sqlx::Error::Decode(Box::new(err))),
+            None => Ok(None),
+        }
+    }
+
+    pub async fn set_metadata<T: Serialize>(
+        &self,
+        url_id: i32,
+        key: &MetadataKey<T>,
+        value: &T,
+    ) -> Result<(), sqlx::Error> {
+        let value = serde_json::to_value(value).map_err(|err| sqlx::Error::Encode(Box::new(err)))?;
+        self.set_url_metadata_entry(url_id, key.name, &value).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tag_filter_parse() {
+        let filter = TagFilter::parse("rust+async, python ,-video");
+        assert_eq!(
+            filter.any_of,
+            vec![
+                vec!["async".to_string(), "rust".to_string()],
+                vec!["python".to_string()]
+            ]
+        );
+        assert_eq!(filter.none_of, vec!["video".to_string()]);
+    }
+
+    #[test]
+    fn test_tag_taxonomy_validate() {
+        let taxonomy: TagTaxonomy = serde_json::from_str(
+            r##"{"tags": [{"tag": " lang/rust ", "color": "#F80", "description": " "}, {"tag": "web"}]}"##,
+        )
+        .unwrap();
+        assert_eq!(
+            taxonomy.validate().unwrap(),
+            vec![
+                TagDetails {
+                    tag: "lang/rust".to_string(),
+                    color: Some("#ff8800".to_string()),
+                    description: None,
+                },
+                TagDetails {
+                    tag: "web".to_string(),
+                    color: None,
+                    description: None,
+                },
+            ]
+        );
+
+        let blank: TagTaxonomy = serde_json::from_str(r#"{"tags": [{"tag": " "}]}"#).unwrap();
+        assert!(blank.validate().is_err());
+        let listed: TagTaxonomy = serde_json::from_str(r#"{"tags": [{"tag": "rust,web"}]}"#).unwrap();
+        assert!(listed.validate().is_err());
+        let colored: TagTaxonomy = serde_json::from_str(r#"{"tags": [{"tag": "rust", "color": "orange"}]}"#).unwrap();
+        assert!(colored.validate().unwrap_err().starts_with("rust: "));
+    }
+
+    #[test]
+    fn test_tag_filter_parse_decoded_plus() {
+        // `rust+async` arrives as `rust async` once the query string is decoded
+        let filter = TagFilter::parse("rust async");
+        assert_eq!(filter.any_of, vec![vec!["async".to_string(), "rust".to_string()]]);
+        assert!(filter.none_of.is_empty());
+    }
+
+    #[test]
+    fn test_list_filter_date_range() {
+        let query = ListQuery {
+            from: Some("2024-11-01".to_string()),
+            to: Some("2024-11-30".to_string()),
+            ..Default::default()
+        };
+        let filter = ListFilter::try_from(&query).unwrap();
+        assert_eq!(filter.saved_after.unwrap().to_string(), "2024-11-01 00:00:00");
+        assert_eq!(filter.saved_before.unwrap().to_string(), "2024-12-01 00:00:00");
+
+        let same_day = ListQuery {
+            from: Some("2024-11-05".to_string()),
+            to: Some("2024-11-05".to_string()),
+            ..Default::default()
+        };
+        assert!(ListFilter::try_from(&same_day).is_ok());
+    }
+
+    #[test]
+    fn test_list_filter_rejects_bad_dates() {
+        let malformed = ListQuery {
+            from: Some("last month".to_string()),
+            ..Default::default()
+        };
+        assert!(ListFilter::try_from(&malformed).is_err());
+
+        let reversed = ListQuery {
+            from: Some("2024-12-01".to_string()),
+            to: Some("2024-11-01".to_string()),
+            ..Default::default()
+        };
+        assert!(ListFilter::try_from(&reversed).is_err());
+    }
+
+    #[test]
+    fn test_list_filter_type() {
+        let videos = ListQuery {
+            kind: Some("video".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(ListFilter::try_from(&videos).unwrap().kind, Some(UrlKind::Video));
+
+        let unknown = ListQuery {
+            kind: Some("image".to_string()),
+            ..Default::default()
+        };
+        assert!(ListFilter::try_from(&unknown).is_err());
+    }
+
+    #[test]
+    fn test_sort_parse() {
+        assert_eq!(Sort::parse("added").unwrap(), Sort::default());
+        assert_eq!(
+            Sort::parse("domain").unwrap(),
+            Sort {
+                key: SortKey::Domain,
+                descending: false
+            }
+        );
+        assert_eq!(
+            Sort::parse("url:desc").unwrap(),
+            Sort {
+                key: SortKey::Url,
+                descending: true
+            }
+        );
+        assert_eq!(
+            Sort::parse("read").unwrap(),
+            Sort {
+                key: SortKey::Read,
+                descending: true
+            }
+        );
+        assert_eq!(
+            Sort::parse("title").unwrap(),
+            Sort {
+                key: SortKey::Title,
+                descending: false
+            }
+        );
+        assert!(Sort::parse("reading_time").is_err());
+        assert!(Sort::parse("added:sideways").is_err());
+    }
+
+    #[test]
+    fn test_is_short_id() {
+        assert!(is_short_id("48251ffc82"));
+        assert!(!is_short_id("48251FFC82"));
+        assert!(!is_short_id("48251ffc8"));
+        assert!(!is_short_id("48251ffc8%"));
+    }
+
+    #[test]
+    fn test_tag_filter_parse_empty() {
+        assert!(TagFilter::parse("").is_empty());
+        assert!(TagFilter::parse(" , -, + ").is_empty());
+    }
+
+    fn tag_group(tag: &str, urls: &[&str]) -> TagWithUrlsAndSnippets {
+        TagWithUrlsAndSnippets {
+            tag: tag.to_string(),
+            urls: urls.iter().map(|url| url.to_string()).collect(),
+            snippets: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_display_title() {
+        let url = "https://example.com/talk/?utm_source=feed#t=30";
+        let video = UrlMetadata::Video(VideoMetadata {
+            provider: "YouTube".to_string(),
+            title: Some("The talk".to_string()),
+            channel: None,
+            duration: None,
+            thumbnail_url: None,
+        });
+        assert_eq!(
+            display_title(Some("My name for it"), Some(&video), url),
+            "My name for it"
+        );
+        assert_eq!(display_title(None, Some(&video), url), "The talk");
+        assert_eq!(display_title(None, None, url), "example.com/talk");
+        assert_eq!(display_title(Some(" "), None, "https://example.com/"), "example.com");
+    }
+
+    #[test]
+    fn test_unique_tags() {
+        assert_eq!(unique_tags(split_tags("tag1, tag1,tag2, ,")), vec!["tag1", "tag2"]);
+        assert_eq!(repeated_tags(&split_tags("tag1, tag1,tag2,tag1 ,,")), vec!["tag1"]);
+        assert!(repeated_tags(&["tag1", "Tag1"]).is_empty());
+    }
+
+    #[test]
+    fn test_tags_as_a_list_or_a_string() {
+        let joined: UrlTags = serde_json::from_str(r#"{"url": "u", "tags": "rust, web,,"}"#).unwrap();
+        let listed: UrlTags = serde_json::from_str(r#"{"url": "u", "tags": [" rust", "web", ""]}"#).unwrap();
+        assert_eq!(joined.tags, ["rust", "web"]);
+        assert_eq!(listed.tags, joined.tags);
+
+        let snippet: NewSnippet =
+            serde_json::from_str(r#"{"url": "u", "snippet": "s", "tags": ["a"], "selector": "p"}"#).unwrap();
+        assert_eq!(snippet.tags, ["a"]);
+        let untagged: NewUrl = serde_json::from_str(r#"{"url": "u"}"#).unwrap();
+        assert!(untagged.tags.is_empty());
+
+        assert!(serde_json::from_str::<UrlTags>(r#"{"url": "u", "tags": ["a,b"]}"#).is_err());
+        assert!(serde_json::from_str::<UrlTags>(r#"{"url": "u", "tags": [1]}"#).is_err());
+    }
+
+    #[test]
+    fn test_parent_tags() {
+        assert_eq!(parent_tags("rust"), Vec::<&str>::new());
+        assert_eq!(parent_tags("lang/rust"), vec!["lang"]);
+        assert_eq!(parent_tags("a/b/c"), vec!["a", "a/b"]);
+        assert_eq!(parent_tags("/odd//tag"), vec!["/odd"]);
+    }
+
+    #[test]
+    fn test_roll_up_tags() {
+        let groups = roll_up_tags(vec![
+            tag_group("lang-tools", &["https://tools.com"]),
+            tag_group("lang/go", &["https://go.dev", "https://both.com"]),
+            tag_group("lang/rust", &["https://rust-lang.org", "https://both.com"]),
+            tag_group("lang/rust/async", &["https://tokio.rs"]),
+        ]);
+
+        let names: Vec<&str> = groups.iter().map(|group| group.tag.as_str()).collect();
+        assert_eq!(
+            names,
+            vec!["lang", "lang/go", "lang/rust", "lang/rust/async", "lang-tools"]
+        );
+        let mut lang = groups[0].urls.clone();
+        lang.sort();
+        assert_eq!(
+            lang,
+            vec![
+                "https://both.com",
+                "https://go.dev",
+                "https://rust-lang.org",
+                "https://tokio.rs"
+            ]
+        );
+        assert_eq!(groups[2].urls.len(), 3);
+    }
+
+    fn tag_update(color: Option<&str>, description: Option<&str>) -> Result<(Option<String>, Option<String>), String> {
+        TagDetailsUpdate {
+            color: color.map(String::from),
+            description: description.map(String::from),
+        }
+        .validate()
+    }
+
+    #[test]
+    fn test_list_query_without_page() {
+        let query = ListQuery {
+            tags: Some("rust+async,-video".to_string()),
+            to: Some("2024-05-01".to_string()),
+            kind: Some("audio".to_string()),
+            page: Some(3),
+            ..Default::default()
+        };
+        assert_eq!(
+            query.without_page(),
+            "tags=rust%2Basync%2C-video&to=2024-05-01&type=audio"
+        );
+        assert_eq!(ListQuery::default().without_page(), "");
+    }
+
+    #[test]
+    fn test_tag_cloud_weights() {
+        let count = |tag: &str, urls, snippets| TagCount {
+            tag: tag.to_string(),
+            urls,
+            snippets,
+        };
+        let cloud = tag_cloud(vec![
+            count("rust", 90, 10),
+            count("go", 10, 0),
+            count("python", 1, 0),
+            count("gone", 0, 0),
+        ]);
+        let weights: Vec<(&str, i64, u8)> = cloud
+            .iter()
+            .map(|tag| (tag.tag.as_str(), tag.count, tag.weight))
+            .collect();
+        assert_eq!(weights, [("rust", 100, 5), ("go", 10, 3), ("python", 1, 1)]);
+
+        assert_eq!(tag_cloud(vec![count("only", 3, 0)])[0].weight, 1);
+        assert!(tag_cloud(Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn test_reading_progress_validation() {
+        assert_eq!(ReadingProgress { progress: 0 }.validate(), Ok(0));
+        assert_eq!(ReadingProgress { progress: 100 }.validate(), Ok(100));
+        assert!(ReadingProgress { progress: -1 }.validate().is_err());
+        assert!(ReadingProgress { progress: 101 }.validate().is_err());
+    }
+
+    #[test]
+    fn test_url_update_parsing_and_validation() {
+        let parse = |body: &str| serde_json::from_str::<UrlUpdate>(body).map_err(|err| err.to_string());
+
+        // Left out, null and set are three different things
+        let update = parse(r#"{"title": "  A talk ", "notes": null, "read": true}"#)
+            .unwrap()
+            .validate()
+            .unwrap();
+        assert_eq!(update.title, Some(Some("A talk".to_string())));
+        assert_eq!(update.notes, Some(None));
+        assert_eq!(update.snoozed_until, None);
+        assert_eq!(update.read, Some(true));
+
+        let update = parse(r#"{"title": "   ", "snoozed_until": "2026-11-01T09:00:00Z", "tags": "rust, ,web"}"#)
+            .unwrap()
+            .validate()
+            .unwrap();
+        assert_eq!(update.title, Some(None));
+        assert_eq!(
+            update
+                .snoozed_until
+                .flatten()
+                .map(|until| until.naive_utc().to_string()),
+            Some("2026-11-01 09:00:00".to_string())
+        );
+        assert_eq!(update.tag_list(), Some(vec!["rust", "web"]));
+        assert_eq!(
+            parse(r#"{"tags": ""}"#).unwrap().validate().unwrap().tag_list(),
+            Some(vec![])
+        );
+
+        assert!(parse("{}").unwrap().validate().is_err());
+        assert!(parse(r#"{"pinned": true}"#).is_err());
+        assert!(parse(r#"{"snoozed_until": "tomorrow"}"#).is_err());
+        let long = format!(r#"{{"title": "{}"}}"#, "x".repeat(URL_TITLE_LIMIT + 1));
+        assert!(parse(&long).unwrap().validate().is_err());
+    }
+
+    #[test]
+    fn test_batch_tags_drop_blanks() {
+        let tag_url = |tags: &str| BatchOperation::TagUrl {
+            url: "https://example.com".to_string(),
+            tags: tags.to_string(),
+        };
+
+        match tag_url("a,,b").validate() {
+            Ok(BatchOperation::TagUrl { tags, .. }) => assert_eq!(tags, "a,b"),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(tag_url("").validate().is_err());
+        assert!(tag_url(" , ").validate().is_err());
+        assert!(BatchOperation::DeleteSnippet { id: 1 }.validate().is_ok());
+    }
+
+    #[test]
+    fn test_loose_url() {
+        assert_eq!(loose_url("https://Example.com/Post/"), "example.com/post");
+        assert_eq!(loose_url("http://example.com/post"), "example.com/post");
+        assert_eq!(
+            loose_url("https://example.com/post/?page=2#top"),
+            "example.com/post?page=2#top"
+        );
+        assert_eq!(loose_url("https://example.com/"), loose_url("example.com"));
+        assert_ne!(loose_url("https://example.com/a"), loose_url("https://example.com/b"));
+    }
+
+    #[test]
+    fn test_tag_details_update_validation() {
+        assert_eq!(
+            tag_update(Some(" #FF8800 "), Some(" Systems languages ")),
+            Ok((Some("#ff8800".to_string()), Some("Systems languages".to_string())))
+        );
+        assert_eq!(tag_update(Some("#0af"), None), Ok((Some("#00aaff".to_string()), None)));
+        assert_eq!(tag_update(Some(""), Some("  ")), Ok((None, None)));
+        assert!(tag_update(Some("red"), None).is_err());
+        assert!(tag_update(Some("#12345"), None).is_err());
+        assert!(tag_update(None, Some(&"x".repeat(TAG_DESCRIPTION_LIMIT + 1))).is_err());
+    }
+
+    #[test]
+    fn test_snippet_source_sanitizing() {
+        let captured = SnippetSource {
+            html: Some(
+                r#"<p onclick="steal()">Read <a href="/docs">the docs</a><script>alert(1)</script><img src="https://t.example/px.gif"></p>"#
+                    .to_string(),
+            ),
+            title: Some("  The\n  Guide ".to_string()),
+            favicon: Some("/favicon.ico".to_string()),
+            selection: Some(SelectionRange {
+                start: 120,
+                end: 141,
+                prefix: "x".repeat(100),
+                suffix: "y".repeat(100),
+            }),
+        };
+        let source = captured.sanitized("https://example.com/guide/").unwrap();
+        assert_eq!(
+            source.html.as_deref(),
+            Some(r#"<p>Read <a href="https://example.com/docs" rel="noopener noreferrer nofollow">the docs</a></p>"#)
+        );
+        assert_eq!(source.title.as_deref(), Some("The Guide"));
+        assert_eq!(source.favicon.as_deref(), Some("https://example.com/favicon.ico"));
+        let selection = source.selection.unwrap();
+        assert_eq!((selection.start, selection.end), (120, 141));
+        assert_eq!(selection.prefix.len(), SELECTION_CONTEXT_LIMIT);
+        assert_eq!(selection.suffix.len(), SELECTION_CONTEXT_LIMIT);
+
+        let unsafe_only = SnippetSource {
+            html: Some("<script>alert(1)</script>".to_string()),
+            favicon: Some("javascript:alert(1)".to_string()),
+            selection: Some(SelectionRange {
+                start: 10,
+                end: 2,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert_eq!(unsafe_only.sanitized("https://example.com/"), None);
+        assert_eq!(SnippetSource::default().sanitized("https://example.com/"), None);
+    }
 }
@@ -0,0 +1,256 @@
+//! Inline previews of tweets, videos and gists for the reader page, from each provider's oEmbed endpoint
+
+use crate::config;
+use crate::services::{fetcher, video};
+use ammonia::Builder;
+use reqwest::{StatusCode, Url};
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+/// The reader page waits on the lookup, so a slow provider only gets this long
+const FETCH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Previews kept in memory at most; expired ones are dropped first when it fills up
+const CACHE_CAPACITY: usize = 1024;
+
+/// Hosts an embedded player may load from
+const FRAME_HOSTS: [&str; 3] = ["www.youtube.com", "www.youtube-nocookie.com", "player.vimeo.com"];
+
+/// A sanitized preview; `None` remembers that a URL has nothing to embed
+struct Cached {
+    fetched_at: Instant,
+    html: Option<String>,
+}
+
+static CACHE: LazyLock<Mutex<HashMap<String, Cached>>> = LazyLock::new(Default::default);
+
+/// A site whose links can be previewed inline
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Provider {
+    Twitter,
+    YouTube,
+    Vimeo,
+    Gist,
+}
+
+impl Provider {
+    pub const ALL: [Self; 4] = [Self::Twitter, Self::YouTube, Self::Vimeo, Self::Gist];
+
+    /// The name used in `OEMBED_PROVIDERS`
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Twitter => "twitter",
+            Self::YouTube => "youtube",
+            Self::Vimeo => "vimeo",
+            Self::Gist => "gist",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|provider| provider.name().eq_ignore_ascii_case(name.trim()))
+    }
+
+    /// The provider that can preview a link: a single tweet, video or gist
+    pub fn of(url: &str) -> Option<Self> {
+        match video::Provider::of(url) {
+            Some(video::Provider::YouTube) => return Some(Self::YouTube),
+            Some(video::Provider::Vimeo) => return Some(Self::Vimeo),
+            None => {}
+        }
+
+        let url = Url::parse(url).ok()?;
+        let host = url.host_str()?.to_ascii_lowercase();
+        let segments: Vec<&str> = url.path_segments()?.filter(|segment| !segment.is_empty()).collect();
+        match (host.strip_prefix("www.").unwrap_or(&host), segments.as_slice()) {
+            // twitter.com/rustlang/status/1234567890
+            ("twitter.com" | "mobile.twitter.com" | "x.com", [_, "status", id, ..])
+                if id.bytes().all(|byte| byte.is_ascii_digit()) =>
+            {
+                Some(Self::Twitter)
+            }
+            // gist.github.com/octocat/6cad326836d38bd3a7ae
+            ("gist.github.com", [_, id]) if id.bytes().all(|byte| byte.is_ascii_hexdigit()) => Some(Self::Gist),
+            _ => None,
+        }
+    }
+
+    /// Where to ask for the embed; gists have no oEmbed endpoint but serve the same thing as JSON
+    fn endpoint(self, url: &str) -> Result<Url, String> {
+        let endpoint = match self {
+            Self::Twitter => "https://publish.twitter.com/oembed",
+            Self::YouTube => video::Provider::YouTube.oembed_endpoint(),
+            Self::Vimeo => video::Provider::Vimeo.oembed_endpoint(),
+            Self::Gist => {
+                let path = url.split(['?', '#']).next().unwrap_or(url).trim_end_matches('/');
+                return Url::parse(&format!("{}.json", path)).map_err(|err| err.to_string());
+            }
+        };
+        Url::parse_with_params(endpoint, &[("url", url), ("format", "json")]).map_err(|err| err.to_string())
+    }
+}
+
+/// An oEmbed response, or a gist's JSON, which puts the markup in `div` instead of `html`
+#[derive(Deserialize, Debug)]
+struct Embed {
+    html: Option<String>,
+    div: Option<String>,
+}
+
+/// Sanitized markup previewing `url`, when it is a link to an allowed provider and the provider has an embed for it.
+///
+/// Lookups are cached for `cache_ttl`, including the ones that found nothing; failed requests aren't, so they
+/// are retried the next time the page is opened.
+pub async fn preview(settings: &config::Previews, url: &str) -> Option<String> {
+    let provider = Provider::of(url).filter(|provider| settings.providers.contains(provider))?;
+    if let Some(cached) = cached(url, settings.cache_ttl) {
+        return cached;
+    }
+
+    match fetch(provider, url).await {
+        Ok(html) => {
+            let html = html.map(|html| sanitize(&html)).filter(|html| !html.trim().is_empty());
+            remember(url, html.clone(), settings.cache_ttl);
+            html
+        }
+        Err(err) => {
+            eprintln!("Failed to fetch a preview of {}: {}", url, err);
+            None
+        }
+    }
+}
+
+async fn fetch(provider: Provider, url: &str) -> Result<Option<String>, String> {
+    let response = fetcher::api(provider.endpoint(url)?, FETCH_TIMEOUT).await?;
+
+    // Deleted tweets and private videos have nothing to embed, which is worth remembering
+    if matches!(response.status(), StatusCode::NOT_FOUND | StatusCode::FORBIDDEN) {
+        return Ok(None);
+    }
+    let embed: Embed = response.error_for_status()?.json().await?;
+    Ok(embed.html.or(embed.div))
+}
+
+/// Provider markup with scripts and styles removed, keeping iframes only when they load a known player
+pub fn sanitize(html: &str) -> String {
+    Builder::default()
+        .add_tags(["iframe"])
+        .add_tag_attributes("iframe", ["src", "width", "height", "title", "allowfullscreen"])
+        .attribute_filter(|element, attribute, value| {
+            if element == "iframe" && attribute == "src" && !is_player(value) {
+                return None;
+            }
+            Some(Cow::Borrowed(value))
+        })
+        .clean(html)
+        .to_string()
+}
+
+fn is_player(src: &str) -> bool {
+    Url::parse(src)
+        .is_ok_and(|url| url.scheme() == "https" && url.host_str().is_some_and(|host| FRAME_HOSTS.contains(&host)))
+}
+
+fn cached(url: &str, ttl: Duration) -> Option<Option<String>> {
+    let cache = CACHE.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    cache
+        .get(url)
+        .filter(|cached| cached.fetched_at.elapsed() < ttl)
+        .map(|cached| cached.html.clone())
+}
+
+fn remember(url: &str, html: Option<String>, ttl: Duration) {
+    let mut cache = CACHE.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if cache.len() >= CACHE_CAPACITY {
+        cache.retain(|_, cached| cached.fetched_at.elapsed() < ttl);
+    }
+    if cache.len() >= CACHE_CAPACITY {
+        // Everything is fresh, so make room by dropping the oldest
+        if let Some(oldest) = cache
+            .iter()
+            .min_by_key(|(_, cached)| cached.fetched_at)
+            .map(|(url, _)| url.clone())
+        {
+            cache.remove(&oldest);
+        }
+    }
+    cache.insert(
+        url.to_string(),
+        Cached {
+            fetched_at: Instant::now(),
+            html,
+        },
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognises_previewable_links() {
+        assert_eq!(
+            Provider::of("https://twitter.com/rustlang/status/1234567890?s=20"),
+            Some(Provider::Twitter)
+        );
+        assert_eq!(
+            Provider::of("https://x.com/rustlang/status/1234567890"),
+            Some(Provider::Twitter)
+        );
+        assert_eq!(Provider::of("https://youtu.be/dQw4w9WgXcQ"), Some(Provider::YouTube));
+        assert_eq!(Provider::of("https://vimeo.com/76979871"), Some(Provider::Vimeo));
+        assert_eq!(
+            Provider::of("https://gist.github.com/octocat/6cad326836d38bd3a7ae"),
+            Some(Provider::Gist)
+        );
+        assert_eq!(Provider::of("https://twitter.com/rustlang"), None);
+        assert_eq!(Provider::of("https://gist.github.com/octocat"), None);
+        assert_eq!(Provider::of("https://example.com/rustlang/status/1"), None);
+
+        assert_eq!(
+            Provider::Gist
+                .endpoint("https://gist.github.com/octocat/6cad326836d38bd3a7ae/?file=a.rs")
+                .unwrap()
+                .as_str(),
+            "https://gist.github.com/octocat/6cad326836d38bd3a7ae.json"
+        );
+        assert_eq!(Provider::parse(" YouTube "), Some(Provider::YouTube));
+        assert_eq!(Provider::parse("myspace"), None);
+    }
+
+    #[test]
+    fn sanitizing_keeps_only_known_players() {
+        let tweet = r#"<blockquote class="twitter-tweet"><p>Hello</p><a href="https://twitter.com/x/status/1">March 1</a></blockquote>
+            <script async src="https://platform.twitter.com/widgets.js"></script>"#;
+        let cleaned = sanitize(tweet);
+        assert!(cleaned.contains("<blockquote><p>Hello</p>"));
+        assert!(!cleaned.contains("script"));
+
+        let video = r#"<iframe width="200" height="113" src="https://www.youtube.com/embed/abc?feature=oembed" onload="steal()" allowfullscreen></iframe>"#;
+        let cleaned = sanitize(video);
+        assert!(cleaned.contains(r#"src="https://www.youtube.com/embed/abc?feature=oembed""#));
+        assert!(!cleaned.contains("onload"));
+
+        let elsewhere = sanitize(r#"<iframe src="https://evil.example/frame"></iframe>"#);
+        assert!(!elsewhere.contains("evil.example"));
+    }
+
+    #[test]
+    fn cache_entries_expire() {
+        let url = "https://gist.github.com/octocat/cache-test";
+        remember(url, Some("<p>gist</p>".to_string()), Duration::from_secs(60));
+        assert_eq!(
+            cached(url, Duration::from_secs(60)),
+            Some(Some("<p>gist</p>".to_string()))
+        );
+        assert_eq!(cached(url, Duration::ZERO), None);
+        assert_eq!(
+            cached("https://gist.github.com/octocat/unseen", Duration::from_secs(60)),
+            None
+        );
+    }
+}
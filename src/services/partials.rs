@@ -0,0 +1,446 @@
+//! HTML fragments of the index and snippets pages, which HTMX swaps in so saving, tagging and deleting don't
+//! reload the whole page
+
+use crate::config::Config;
+use crate::services::api::{log_activity, sanitize_snippets, tag_details_by_name};
+use crate::services::{metadata, models};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::{delete, get, post, put, web, HttpResponse, Responder};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tera::{Context, Tera};
+
+/// URLs shown per page of the index; the page loads the next one from `/partials/urls` when asked for more
+const PAGE_SIZE: i64 = 100;
+
+/// Snippets shown per page of the snippets page, which loads the next one from `/partials/snippets`, and of
+/// `/api/v1/snippets`
+pub(crate) const SNIPPET_PAGE_SIZE: i64 = 50;
+
+fn render(tmpl: &Tera, template: &str, context: &Context) -> HttpResponse {
+    match tmpl.render(template, context) {
+        Ok(rendered) => HttpResponse::Ok().content_type("text/html").body(rendered),
+        Err(e) => {
+            eprintln!("Template error: {:?}", e);
+            HttpResponse::InternalServerError().body("Template error")
+        }
+    }
+}
+
+/// Tell HTMX to fire `tags-changed` once the response is swapped in, so the index refreshes its tag list
+fn tags_changed(mut response: HttpResponse) -> HttpResponse {
+    response.headers_mut().insert(
+        HeaderName::from_static("hx-trigger"),
+        HeaderValue::from_static("tags-changed"),
+    );
+    response
+}
+
+/// The context `url_rows.html` renders `urls` with
+async fn rows_context(database: &dyn models::Database, urls: &[models::UrlWithTags], base_path: &str) -> Context {
+    // Video and audio lengths by short id, formatted for the badges
+    let durations: HashMap<&str, String> = urls
+        .iter()
+        .filter_map(|url| {
+            let duration = url.metadata.as_ref()?.duration()?;
+            Some((url.short_id.as_str(), metadata::duration_label(duration)))
+        })
+        .collect();
+
+    let mut context = Context::new();
+    context.insert("urls_with_tags", urls);
+    context.insert("durations", &durations);
+    context.insert("tag_details", &tag_details_by_name(database).await);
+    context.insert("base_path", base_path);
+    context
+}
+
+/// The context for one page of the index's URL rows, with a link to the next page when there is one
+#[allow(clippy::result_large_err)]
+pub(crate) async fn url_rows(
+    database: &dyn models::Database,
+    query: &models::ListQuery,
+    base_path: &str,
+) -> Result<Context, HttpResponse> {
+    let page = query.page.unwrap_or(1).max(1);
+    let filter = models::ListFilter::try_from(query).map_err(|err| HttpResponse::BadRequest().json(err))?;
+    let filter = models::ListFilter {
+        // One more than fits on the page, to tell whether there is another
+        limit: Some(PAGE_SIZE + 1),
+        offset: i64::from(page - 1) * PAGE_SIZE,
+        // Archived URLs are out of the way unless asked for
+        archived: Some(filter.archived.unwrap_or(false)),
+        ..filter
+    };
+    let mut urls = database.get_urls_with_tags(&filter).await.map_err(|err| {
+        eprintln!("Failed to fetch URLs with tags: {:?}", err);
+        HttpResponse::InternalServerError().body("Failed to fetch URLs with tags")
+    })?;
+    let more = urls.len() as i64 > PAGE_SIZE;
+    urls.truncate(PAGE_SIZE as usize);
+
+    let mut context = rows_context(database, &urls, base_path).await;
+    context.insert("next_page", &more.then_some(page + 1));
+    context.insert("page_query", &query.without_page());
+    Ok(context)
+}
+
+/// Page `page` of the snippets `filter` matches, sanitized for display, and the number of the next page if there
+/// is one
+pub(crate) async fn snippet_page(
+    database: &dyn models::Database,
+    filter: models::ListFilter,
+    page: u32,
+) -> Result<(Vec<models::SnippetWithTags>, Option<u32>), sqlx::Error> {
+    let page = page.max(1);
+    let filter = models::ListFilter {
+        // One more than fits on the page, to tell whether there is another
+        limit: Some(SNIPPET_PAGE_SIZE + 1),
+        offset: i64::from(page - 1) * SNIPPET_PAGE_SIZE,
+        ..filter
+    };
+    let mut snippets = database.get_snippets_with_tags(&filter).await?;
+    let more = snippets.len() as i64 > SNIPPET_PAGE_SIZE;
+    snippets.truncate(SNIPPET_PAGE_SIZE as usize);
+    Ok((sanitize_snippets(snippets), more.then_some(page + 1)))
+}
+
+/// The context for one page of the snippets page's rows, with a link to the next page when there is one
+#[allow(clippy::result_large_err)]
+pub(crate) async fn snippet_rows(
+    database: &dyn models::Database,
+    query: &models::ListQuery,
+    base_path: &str,
+) -> Result<Context, HttpResponse> {
+    let filter = models::ListFilter::try_from(query).map_err(|err| HttpResponse::BadRequest().json(err))?;
+    let (snippets, next_page) = snippet_page(database, filter, query.page.unwrap_or(1))
+        .await
+        .map_err(|err| {
+            eprintln!("Failed to fetch snippets with tags: {:?}", err);
+            HttpResponse::InternalServerError().body("Failed to fetch snippets with tags")
+        })?;
+
+    let mut context = Context::new();
+    context.insert("snippets_with_tags", &snippets);
+    context.insert("tag_details", &tag_details_by_name(database).await);
+    context.insert("base_path", base_path);
+    context.insert("next_page", &next_page);
+    context.insert("page_query", &query.without_page());
+    Ok(context)
+}
+
+/// Tags in use with how many URLs and snippets carry them, for the index's tag list; empty if they can't be read
+pub(crate) async fn tag_list(database: &dyn models::Database) -> Vec<models::TagCount> {
+    match database.get_tag_counts().await {
+        Ok(counts) => counts
+            .into_iter()
+            .filter(|count| count.urls + count.snippets > 0)
+            .collect(),
+        Err(err) => {
+            eprintln!("Failed to fetch tag counts: {:?}", err);
+            Vec::new()
+        }
+    }
+}
+
+/// The saved URL with `short_id`, with its tags
+pub(crate) async fn find_url(
+    database: &dyn models::Database,
+    short_id: &str,
+) -> Result<Option<models::UrlWithTags>, sqlx::Error> {
+    if !models::is_short_id(short_id) {
+        return Ok(None);
+    }
+    let filter = models::ListFilter {
+        short_id: Some(short_id.to_string()),
+        ..Default::default()
+    };
+    let mut urls = database.get_urls_with_tags(&filter).await?;
+    // As with `get_url_by_short_id`, a short id two URLs share picks neither
+    if urls.len() > 1 {
+        return Ok(None);
+    }
+    Ok(urls.pop())
+}
+
+/// Another page of the index's rows, for appending to the list already shown
+#[get("/partials/urls")]
+async fn url_rows_partial(
+    database: web::Data<Arc<dyn models::Database>>,
+    tmpl: web::Data<Tera>,
+    config: web::Data<Config>,
+    query: web::Query<models::ListQuery>,
+) -> impl Responder {
+    match url_rows(database.get_ref().as_ref(), &query, &config.base_path).await {
+        Ok(context) => render(&tmpl, "url_rows.html", &context),
+        Err(response) => response,
+    }
+}
+
+/// Another page of the snippets page's rows, for appending to the list already shown
+#[get("/partials/snippets")]
+async fn snippet_rows_partial(
+    database: web::Data<Arc<dyn models::Database>>,
+    tmpl: web::Data<Tera>,
+    config: web::Data<Config>,
+    query: web::Query<models::ListQuery>,
+) -> impl Responder {
+    match snippet_rows(database.get_ref().as_ref(), &query, &config.base_path).await {
+        Ok(context) => render(&tmpl, "snippet_rows.html", &context),
+        Err(response) => response,
+    }
+}
+
+/// Save a URL from the index's save box, answering with the first page of rows so it shows up in place
+#[post("/partials/urls")]
+async fn save_url(
+    database: web::Data<Arc<dyn models::Database>>,
+    tmpl: web::Data<Tera>,
+    config: web::Data<Config>,
+    query: web::Query<models::ListQuery>,
+    form: web::Form<models::SaveForm>,
+) -> impl Responder {
+    let database = database.get_ref().as_ref();
+    let url = form.url.trim();
+    if url.is_empty() {
+        return HttpResponse::BadRequest().body("Enter a URL to save");
+    }
+
+    match database.insert_url(url).await {
+        Ok(_) => log_activity(database, "save_url", url, None).await,
+        // Already saved, which is fine when it is being given more tags
+        Err(sqlx::Error::RowNotFound) => {}
+        Err(err) => {
+            eprintln!("Failed to insert record: {:?}", err);
+            return HttpResponse::InternalServerError().body("Failed to save the URL");
+        }
+    }
+
+    let tags: Vec<&str> = models::split_tags(&form.tags)
+        .into_iter()
+        .filter(|tag| !tag.is_empty())
+        .collect();
+    if !tags.is_empty() {
+        if let Err(err) = database.insert_tags(url, &tags).await {
+            eprintln!("Failed to insert tags: {:?}", err);
+            return HttpResponse::InternalServerError().body("Failed to tag the URL");
+        }
+        log_activity(database, "tag_url", url, Some(&tags.join(","))).await;
+    }
+
+    let query = models::ListQuery {
+        page: None,
+        ..query.into_inner()
+    };
+    match url_rows(database, &query, &config.base_path).await {
+        Ok(context) => tags_changed(render(&tmpl, "url_rows.html", &context)),
+        Err(response) => response,
+    }
+}
+
+/// A single row of the index, to re-render after it changes
+#[get("/partials/url-row/{id}")]
+async fn url_row(
+    database: web::Data<Arc<dyn models::Database>>,
+    tmpl: web::Data<Tera>,
+    config: web::Data<Config>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let database = database.get_ref().as_ref();
+    match find_url(database, &path).await {
+        Ok(Some(url)) => render(
+            &tmpl,
+            "url_rows.html",
+            &rows_context(database, &[url], &config.base_path).await,
+        ),
+        Ok(None) => HttpResponse::NotFound().body("URL not found"),
+        Err(err) => {
+            eprintln!("Failed to look up URL: {:?}", err);
+            HttpResponse::InternalServerError().body("Failed to look up URL")
+        }
+    }
+}
+
+/// Mark a row read or unread, pin or unpin it, or add tags to it, answering with the updated row
+#[post("/partials/url-row/{id}")]
+async fn update_url_row(
+    database: web::Data<Arc<dyn models::Database>>,
+    tmpl: web::Data<Tera>,
+    config: web::Data<Config>,
+    path: web::Path<String>,
+    form: web::Form<models::RowUpdate>,
+) -> impl Responder {
+    let database = database.get_ref().as_ref();
+    let url = match find_url(database, &path).await {
+        Ok(Some(url)) => url.url,
+        Ok(None) => return HttpResponse::NotFound().body("URL not found"),
+        Err(err) => {
+            eprintln!("Failed to look up URL: {:?}", err);
+            return HttpResponse::InternalServerError().body("Failed to look up URL");
+        }
+    };
+
+    if let Some(read) = form.read {
+        if let Err(err) = database.set_url_read(&url, read).await {
+            eprintln!("Failed to update read state: {:?}", err);
+            return HttpResponse::InternalServerError().body("Failed to update read state");
+        }
+        log_activity(database, if read { "read_url" } else { "unread_url" }, &url, None).await;
+    }
+    if let Some(pinned) = form.pinned {
+        if let Err(err) = database.set_url_pinned(&url, pinned).await {
+            eprintln!("Failed to update pinned state: {:?}", err);
+            return HttpResponse::InternalServerError().body("Failed to update pinned state");
+        }
+        log_activity(database, if pinned { "pin_url" } else { "unpin_url" }, &url, None).await;
+    }
+    let tags: Vec<&str> = form
+        .tags
+        .as_deref()
+        .map(models::split_tags)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|tag| !tag.is_empty())
+        .collect();
+    if !tags.is_empty() {
+        if let Err(err) = database.insert_tags(&url, &tags).await {
+            eprintln!("Failed to insert tags: {:?}", err);
+            return HttpResponse::InternalServerError().body("Failed to insert tags");
+        }
+        log_activity(database, "tag_url", &url, Some(&tags.join(","))).await;
+    }
+
+    match find_url(database, &path).await {
+        Ok(Some(url)) => {
+            let response = render(
+                &tmpl,
+                "url_rows.html",
+                &rows_context(database, &[url], &config.base_path).await,
+            );
+            if tags.is_empty() {
+                response
+            } else {
+                tags_changed(response)
+            }
+        }
+        Ok(None) => HttpResponse::NotFound().body("URL not found"),
+        Err(err) => {
+            eprintln!("Failed to look up URL: {:?}", err);
+            HttpResponse::InternalServerError().body("Failed to look up URL")
+        }
+    }
+}
+
+/// Replace a row's tags from its chips, answering with the updated row
+#[put("/partials/url-row/{id}/tags")]
+async fn set_url_row_tags(
+    database: web::Data<Arc<dyn models::Database>>,
+    tmpl: web::Data<Tera>,
+    config: web::Data<Config>,
+    path: web::Path<String>,
+    form: web::Form<models::TagSetForm>,
+) -> impl Responder {
+    let database = database.get_ref().as_ref();
+    let url = match find_url(database, &path).await {
+        Ok(Some(url)) => url.url,
+        Ok(None) => return HttpResponse::NotFound().body("URL not found"),
+        Err(err) => {
+            eprintln!("Failed to look up URL: {:?}", err);
+            return HttpResponse::InternalServerError().body("Failed to look up URL");
+        }
+    };
+
+    let mut tags: Vec<&str> = Vec::new();
+    for tag in models::split_tags(&form.tags)
+        .into_iter()
+        .chain(models::split_tags(&form.add))
+    {
+        if !tag.is_empty() && !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    if let Err(err) = database.set_url_tags(&url, &tags).await {
+        eprintln!("Failed to replace tags: {:?}", err);
+        return HttpResponse::InternalServerError().body("Failed to replace tags");
+    }
+    log_activity(database, "set_tags", &url, Some(&tags.join(","))).await;
+
+    match find_url(database, &path).await {
+        Ok(Some(url)) => tags_changed(render(
+            &tmpl,
+            "url_rows.html",
+            &rows_context(database, &[url], &config.base_path).await,
+        )),
+        Ok(None) => HttpResponse::NotFound().body("URL not found"),
+        Err(err) => {
+            eprintln!("Failed to look up URL: {:?}", err);
+            HttpResponse::InternalServerError().body("Failed to look up URL")
+        }
+    }
+}
+
+/// Delete a row's URL, answering with nothing so the row is swapped out
+#[delete("/partials/url-row/{id}")]
+async fn delete_url_row(database: web::Data<Arc<dyn models::Database>>, path: web::Path<String>) -> impl Responder {
+    let database = database.get_ref().as_ref();
+    let url = match find_url(database, &path).await {
+        Ok(Some(url)) => url.url,
+        Ok(None) => return HttpResponse::NotFound().body("URL not found"),
+        Err(err) => {
+            eprintln!("Failed to look up URL: {:?}", err);
+            return HttpResponse::InternalServerError().body("Failed to look up URL");
+        }
+    };
+
+    match database.delete_url_by_url(&url).await {
+        Ok(_) => {
+            log_activity(database, "delete_url", &url, None).await;
+            tags_changed(HttpResponse::Ok().finish())
+        }
+        Err(err) => {
+            eprintln!("Failed to delete URL: {:?}", err);
+            HttpResponse::InternalServerError().body("Failed to delete URL")
+        }
+    }
+}
+
+/// Delete a snippet from the snippets page, answering with nothing so its row is swapped out
+#[delete("/partials/snippet-row/{id}")]
+async fn delete_snippet_row(database: web::Data<Arc<dyn models::Database>>, path: web::Path<i32>) -> impl Responder {
+    let id = path.into_inner();
+    match database.delete_snippet(id).await {
+        Ok(_) => {
+            log_activity(database.get_ref().as_ref(), "delete_snippet", &id.to_string(), None).await;
+            HttpResponse::Ok().finish()
+        }
+        Err(err) => {
+            eprintln!("Failed to delete snippet: {:?}", err);
+            HttpResponse::InternalServerError().body("Failed to delete snippet")
+        }
+    }
+}
+
+/// The index's list of tags in use, refreshed after a change adds or removes tags
+#[get("/partials/tag-list")]
+async fn tag_list_partial(
+    database: web::Data<Arc<dyn models::Database>>,
+    tmpl: web::Data<Tera>,
+    config: web::Data<Config>,
+) -> impl Responder {
+    let mut context = Context::new();
+    context.insert("tag_list", &tag_list(database.get_ref().as_ref()).await);
+    context.insert("base_path", &config.base_path);
+    render(&tmpl, "tag_list.html", &context)
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(url_rows_partial)
+        .service(snippet_rows_partial)
+        .service(save_url)
+        .service(url_row)
+        .service(update_url_row)
+        .service(set_url_row_tags)
+        .service(delete_url_row)
+        .service(delete_snippet_row)
+        .service(tag_list_partial);
+}
@@ -0,0 +1,577 @@
+//! Syncing with other instances: each one serves its change feed to peers holding the shared token and
+//! pulls theirs, keeping whichever write to a field came last
+
+use crate::config::{self, Config};
+use crate::services::{access, changes, models};
+use actix_web::{get, http::header, web, HttpRequest, HttpResponse, Responder};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Changes served per request; a peer that is further behind asks again
+const BATCH_SIZE: i64 = 500;
+
+/// Give up on a peer that takes longer than this to answer
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Actions that each set one field of a URL; the latest of them, wherever it was made, decides its value.
+/// Tagging saves a URL too, so it counts as a write to whether the URL is saved.
+const FIELDS: [&[&str]; 3] = [
+    &["save_url", "delete_url", "tag_url"],
+    &["read_url", "unread_url"],
+    &["pin_url", "unpin_url"],
+];
+
+/// One change from a peer's activity timeline
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Change {
+    pub datetime: chrono::NaiveDateTime,
+    pub operation: Operation,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Operation {
+    SaveUrl {
+        url: String,
+    },
+    DeleteUrl {
+        url: String,
+    },
+    /// Tags added to a URL, which saves it too
+    TagUrl {
+        url: String,
+        tags: Vec<String>,
+    },
+    /// Every tag a URL has, replacing the ones it had before; saves it too
+    SetTags {
+        url: String,
+        tags: Vec<String>,
+    },
+    ReadUrl {
+        url: String,
+        read: bool,
+    },
+    PinUrl {
+        url: String,
+        pinned: bool,
+    },
+    /// Every snippet the URL has on the peer; they are matched on their text, so only new ones are added
+    SaveSnippets {
+        url: String,
+        snippets: Vec<Snippet>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Snippet {
+    pub snippet: String,
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<models::SnippetSource>,
+}
+
+/// A page of `GET /peer/changes`
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Changes {
+    pub changes: Vec<Change>,
+    /// The `since` to send for the next page; entries that aren't synced are skipped past too
+    pub cursor: i32,
+    /// Whether there are more changes after this page
+    pub more: bool,
+}
+
+impl Operation {
+    /// The activity timeline action this operation is recorded as
+    pub fn action(&self) -> &'static str {
+        match self {
+            Self::SaveUrl { .. } => "save_url",
+            Self::DeleteUrl { .. } => "delete_url",
+            Self::TagUrl { .. } => "tag_url",
+            Self::SetTags { .. } => "set_tags",
+            Self::ReadUrl { read: true, .. } => "read_url",
+            Self::ReadUrl { read: false, .. } => "unread_url",
+            Self::PinUrl { pinned: true, .. } => "pin_url",
+            Self::PinUrl { pinned: false, .. } => "unpin_url",
+            Self::SaveSnippets { .. } => "save_snippet",
+        }
+    }
+
+    pub fn url(&self) -> &str {
+        match self {
+            Self::SaveUrl { url }
+            | Self::DeleteUrl { url }
+            | Self::TagUrl { url, .. }
+            | Self::SetTags { url, .. }
+            | Self::ReadUrl { url, .. }
+            | Self::PinUrl { url, .. }
+            | Self::SaveSnippets { url, .. } => url,
+        }
+    }
+
+    fn detail(&self) -> Option<String> {
+        match self {
+            Self::TagUrl { tags, .. } | Self::SetTags { tags, .. } => Some(tags.join(",")),
+            _ => None,
+        }
+    }
+}
+
+/// The change an activity entry describes, or `None` for entries that aren't synced: snapshots, tag
+/// colors, retention runs, and snippet deletions, which only name a snippet id peers don't share
+async fn change(database: &dyn models::Database, activity: models::Activity) -> Result<Option<Change>, sqlx::Error> {
+    let url = activity.subject;
+    let operation = match activity.action.as_str() {
+        "save_url" => Operation::SaveUrl { url },
+        "delete_url" => Operation::DeleteUrl { url },
+        "tag_url" => Operation::TagUrl {
+            tags: tags(activity.detail.as_deref()),
+            url,
+        },
+        "set_tags" => Operation::SetTags {
+            tags: tags(activity.detail.as_deref()),
+            url,
+        },
+        "read_url" | "unread_url" => Operation::ReadUrl {
+            read: activity.action == "read_url",
+            url,
+        },
+        "pin_url" | "unpin_url" => Operation::PinUrl {
+            pinned: activity.action == "pin_url",
+            url,
+        },
+        // The timeline doesn't keep snippet text, so send what the URL has now. Pictures stay on this instance.
+        "save_snippet" => Operation::SaveSnippets {
+            snippets: database
+                .get_snippets_for_url(&url)
+                .await?
+                .into_iter()
+                .filter(|snippet| snippet.image_type.is_none())
+                .map(|snippet| Snippet {
+                    snippet: snippet.snippet,
+                    tags: snippet.tags,
+                    source: snippet.source,
+                })
+                .collect(),
+            url,
+        },
+        _ => return Ok(None),
+    };
+
+    Ok(Some(Change {
+        datetime: activity.datetime,
+        operation,
+    }))
+}
+
+/// The tags in an activity entry's detail
+fn tags(detail: Option<&str>) -> Vec<String> {
+    models::split_tags(detail.unwrap_or_default())
+        .into_iter()
+        .filter(|tag| !tag.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Whether a change replaces the latest one to the same field; ties go to the greater action name so both
+/// instances settle on the same value
+fn wins(change: &Change, latest: &models::Activity) -> bool {
+    (change.datetime, change.operation.action()) > (latest.datetime, latest.action.as_str())
+}
+
+/// Apply a change pulled from `peer`, unless this instance has a later write to the same field.
+/// Returns whether anything changed.
+pub async fn apply(database: &dyn models::Database, peer: &str, change: &Change) -> Result<bool, sqlx::Error> {
+    let action = change.operation.action();
+    let url = change.operation.url();
+
+    if let Some(actions) = FIELDS.into_iter().find(|field| field.contains(&action)) {
+        if let Some(latest) = database.get_latest_activity(url, actions).await? {
+            // Tags are only ever added, so older ones still merge in unless the URL was deleted since
+            let superseded = !wins(change, &latest) && (action != "tag_url" || latest.action == "delete_url");
+            if superseded {
+                return Ok(false);
+            }
+        }
+    }
+    // Tags added before a URL's latest tag set were kept or dropped by it, and a set made before later
+    // additions would drop them
+    let tag_writes: &[&str] = match action {
+        "tag_url" => &["set_tags", "delete_url"],
+        "set_tags" => &["set_tags", "tag_url", "delete_url"],
+        _ => &[],
+    };
+    if !tag_writes.is_empty() {
+        if let Some(latest) = database.get_latest_activity(url, tag_writes).await? {
+            if !wins(change, &latest) {
+                return Ok(false);
+            }
+        }
+    }
+
+    let applied = match &change.operation {
+        Operation::SaveUrl { url } => database.insert_url(url).await.map(|_| ()),
+        Operation::DeleteUrl { url } => database.delete_url_by_url(url).await,
+        Operation::TagUrl { url, tags } => {
+            let tags: Vec<&str> = tags.iter().map(String::as_str).collect();
+            database.insert_tags(url, &tags).await
+        }
+        Operation::SetTags { url, tags } => {
+            let tags: Vec<&str> = tags.iter().map(String::as_str).collect();
+            database.set_url_tags(url, &tags).await
+        }
+        Operation::ReadUrl { url, read } => database.set_url_read(url, *read).await,
+        Operation::PinUrl { url, pinned } => database.set_url_pinned(url, *pinned).await,
+        Operation::SaveSnippets { url, snippets } => {
+            let existing = database.get_snippets_for_url(url).await?;
+            let new: Vec<&Snippet> = snippets
+                .iter()
+                .filter(|snippet| !existing.iter().any(|known| known.snippet == snippet.snippet))
+                .collect();
+            if new.is_empty() {
+                return Ok(false);
+            }
+            for snippet in new {
+                let tags: Vec<&str> = snippet.tags.iter().map(String::as_str).collect();
+                // Peers sanitized the source when it was captured, but it is shown as markup here
+                let source = snippet.source.clone().and_then(|source| source.sanitized(url));
+                database
+                    .insert_snippet(url, &snippet.snippet, &tags, source.as_ref())
+                    .await?;
+            }
+            Ok(())
+        }
+    };
+    match applied {
+        Ok(()) => {}
+        // Marking read or pinning a URL that isn't saved here
+        Err(sqlx::Error::RowNotFound) => return Ok(false),
+        Err(err) => return Err(err),
+    }
+
+    let detail = change.operation.detail();
+    let activity = models::Activity {
+        id: 0,
+        datetime: change.datetime,
+        action: action.to_string(),
+        subject: url.to_string(),
+        detail: detail.clone(),
+    };
+    database.record_peer_activity(peer, &activity).await?;
+    changes::publish(action, url, detail.as_deref());
+    Ok(true)
+}
+
+/// Pull and apply every change `peer` has made since the last sync
+pub async fn sync(database: &dyn models::Database, settings: &config::Peering, peer: &str) -> Result<String, String> {
+    let token = settings.token.as_deref().ok_or("no PEER_TOKEN is set")?;
+    let client = reqwest::Client::builder()
+        .timeout(FETCH_TIMEOUT)
+        .build()
+        .map_err(|err| err.to_string())?;
+
+    let mut cursor = database.get_peer_cursor(peer).await.map_err(|err| err.to_string())?;
+    let (mut applied, mut skipped) = (0, 0);
+    loop {
+        let page: Changes = client
+            .get(format!("{}/peer/changes", peer))
+            .query(&[("since", cursor)])
+            .bearer_auth(token)
+            .header(reqwest::header::USER_AGENT, "read_it_later")
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|err| err.to_string())?
+            .json()
+            .await
+            .map_err(|err| err.to_string())?;
+
+        for change in &page.changes {
+            match apply(database, peer, change).await {
+                Ok(true) => applied += 1,
+                Ok(false) => skipped += 1,
+                Err(err) => return Err(format!("failed to apply {:?}: {:?}", change, err)),
+            }
+        }
+        database
+            .set_peer_cursor(peer, page.cursor)
+            .await
+            .map_err(|err| err.to_string())?;
+
+        if !page.more || page.cursor == cursor {
+            break;
+        }
+        cursor = page.cursor;
+    }
+
+    Ok(format!(
+        "{}: {} changes applied, {} already superseded",
+        peer, applied, skipped
+    ))
+}
+
+/// Whether the request carries the shared token, compared in constant time
+fn authorized(req: &HttpRequest, token: &str) -> bool {
+    let Some(presented) = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+    else {
+        return false;
+    };
+    access::constant_time_eq(presented, token)
+}
+
+#[derive(Deserialize, Debug)]
+struct ChangesQuery {
+    since: Option<i32>,
+}
+
+/// Changes made on this instance, for peers to pull
+#[get("/peer/changes")]
+async fn peer_changes(
+    req: HttpRequest,
+    database: web::Data<Arc<dyn models::Database>>,
+    config: web::Data<Config>,
+    query: web::Query<ChangesQuery>,
+) -> impl Responder {
+    let Some(token) = config.peering.token.as_deref() else {
+        return HttpResponse::NotFound().json("Peering is not enabled");
+    };
+    if !authorized(&req, token) {
+        return HttpResponse::Unauthorized().json("Missing or wrong peer token");
+    }
+
+    let since = query.since.unwrap_or(0);
+    let activity = match database.get_local_activity_since(since, BATCH_SIZE).await {
+        Ok(activity) => activity,
+        Err(err) => {
+            eprintln!("Failed to fetch changes for a peer: {:?}", err);
+            return HttpResponse::InternalServerError().json("Failed to fetch changes");
+        }
+    };
+
+    let more = activity.len() as i64 == BATCH_SIZE;
+    let cursor = activity.last().map_or(since, |entry| entry.id);
+    let mut changes = Vec::new();
+    for entry in activity {
+        match change(database.get_ref().as_ref(), entry).await {
+            Ok(Some(change)) => changes.push(change),
+            Ok(None) => {}
+            Err(err) => {
+                eprintln!("Failed to fetch changes for a peer: {:?}", err);
+                return HttpResponse::InternalServerError().json("Failed to fetch changes");
+            }
+        }
+    }
+
+    HttpResponse::Ok().json(Changes { changes, cursor, more })
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(peer_changes);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::models::Database;
+    use crate::services::sqlite_database::SqliteDatabase;
+
+    async fn database() -> SqliteDatabase {
+        let database = SqliteDatabase::new(":memory:").await.unwrap();
+        database.initialize().await.unwrap();
+        database
+    }
+
+    fn at(offset: chrono::Duration, operation: Operation) -> Change {
+        Change {
+            datetime: chrono::Utc::now().naive_utc() + offset,
+            operation,
+        }
+    }
+
+    #[tokio::test]
+    async fn later_writes_win_per_field() {
+        let database = database().await;
+        let url = "https://example.com/post";
+        database.insert_url(url).await.unwrap();
+        database.set_url_read(url, true).await.unwrap();
+        database.record_activity("read_url", url, None).await.unwrap();
+
+        let earlier = at(
+            chrono::Duration::hours(-1),
+            Operation::ReadUrl {
+                url: url.to_string(),
+                read: false,
+            },
+        );
+        assert!(!apply(&database, "https://peer.example", &earlier).await.unwrap());
+        let pin = at(
+            chrono::Duration::hours(-1),
+            Operation::PinUrl {
+                url: url.to_string(),
+                pinned: true,
+            },
+        );
+        assert!(apply(&database, "https://peer.example", &pin).await.unwrap());
+        let urls = database.get_urls_with_tags(&Default::default()).await.unwrap();
+        assert!(urls[0].read_at.is_some());
+        assert!(urls[0].pinned);
+
+        let later = at(
+            chrono::Duration::hours(1),
+            Operation::ReadUrl {
+                url: url.to_string(),
+                read: false,
+            },
+        );
+        assert!(apply(&database, "https://peer.example", &later).await.unwrap());
+        let urls = database.get_urls_with_tags(&Default::default()).await.unwrap();
+        assert!(urls[0].read_at.is_none());
+
+        // Applying the same change again finds it already recorded
+        assert!(!apply(&database, "https://peer.example", &later).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn tags_merge_but_do_not_undo_a_later_delete() {
+        let database = database().await;
+        let url = "https://example.com/post";
+        database.insert_tags(url, &["rust"]).await.unwrap();
+        database.record_activity("save_url", url, None).await.unwrap();
+
+        let tagged = at(
+            chrono::Duration::hours(-1),
+            Operation::TagUrl {
+                url: url.to_string(),
+                tags: vec!["web".to_string()],
+            },
+        );
+        assert!(apply(&database, "https://peer.example", &tagged).await.unwrap());
+        let urls = database.get_urls_with_tags(&Default::default()).await.unwrap();
+        assert_eq!(urls[0].tags.len(), 2);
+
+        database.delete_url_by_url(url).await.unwrap();
+        database.record_activity("delete_url", url, None).await.unwrap();
+        assert!(!apply(&database, "https://peer.example", &tagged).await.unwrap());
+        assert!(database
+            .get_urls_with_tags(&Default::default())
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn tag_sets_replace_tags_added_before_them() {
+        let database = database().await;
+        let url = "https://example.com/post";
+        database.insert_tags(url, &["rust", "web"]).await.unwrap();
+        database
+            .record_activity("tag_url", url, Some("rust,web"))
+            .await
+            .unwrap();
+
+        let set = at(
+            chrono::Duration::hours(1),
+            Operation::SetTags {
+                url: url.to_string(),
+                tags: vec!["go".to_string()],
+            },
+        );
+        assert!(apply(&database, "https://peer.example", &set).await.unwrap());
+        let urls = database.get_urls_with_tags(&Default::default()).await.unwrap();
+        assert_eq!(urls[0].tags, ["go"]);
+
+        // Tags added before the set don't come back, and an older set doesn't undo it
+        let tagged = at(
+            chrono::Duration::minutes(30),
+            Operation::TagUrl {
+                url: url.to_string(),
+                tags: vec!["web".to_string()],
+            },
+        );
+        assert!(!apply(&database, "https://peer.example", &tagged).await.unwrap());
+        let older = at(
+            chrono::Duration::minutes(45),
+            Operation::SetTags {
+                url: url.to_string(),
+                tags: Vec::new(),
+            },
+        );
+        assert!(!apply(&database, "https://peer.example", &older).await.unwrap());
+        let urls = database.get_urls_with_tags(&Default::default()).await.unwrap();
+        assert_eq!(urls[0].tags, ["go"]);
+    }
+
+    #[tokio::test]
+    async fn snippets_are_added_once() {
+        let database = database().await;
+        let url = "https://example.com/post";
+        database.insert_url(url).await.unwrap();
+        database.insert_snippet(url, "Known quote", &[], None).await.unwrap();
+
+        let saved = at(
+            chrono::Duration::zero(),
+            Operation::SaveSnippets {
+                url: url.to_string(),
+                snippets: vec![
+                    Snippet {
+                        snippet: "Known quote".to_string(),
+                        tags: Vec::new(),
+                        source: None,
+                    },
+                    Snippet {
+                        snippet: "New quote".to_string(),
+                        tags: vec!["rust".to_string()],
+                        source: None,
+                    },
+                ],
+            },
+        );
+        assert!(apply(&database, "https://peer.example", &saved).await.unwrap());
+        assert!(!apply(&database, "https://peer.example", &saved).await.unwrap());
+        assert_eq!(database.get_snippets_for_url(url).await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn applied_changes_are_not_served_back() {
+        let database = database().await;
+        database
+            .record_activity("save_url", "https://example.com/a", None)
+            .await
+            .unwrap();
+        let pulled = at(
+            chrono::Duration::zero(),
+            Operation::SaveUrl {
+                url: "https://example.com/b".to_string(),
+            },
+        );
+        assert!(apply(&database, "https://peer.example", &pulled).await.unwrap());
+        database
+            .record_activity("tag_url", "https://example.com/a", Some("rust, web"))
+            .await
+            .unwrap();
+
+        let local = database.get_local_activity_since(0, BATCH_SIZE).await.unwrap();
+        assert_eq!(local.len(), 2);
+        let mut served = Vec::new();
+        for entry in local {
+            served.extend(change(&database, entry).await.unwrap());
+        }
+        assert_eq!(served[0].operation.action(), "save_url");
+        assert_eq!(
+            served[1].operation,
+            Operation::TagUrl {
+                url: "https://example.com/a".to_string(),
+                tags: vec!["rust".to_string(), "web".to_string()],
+            }
+        );
+
+        assert_eq!(database.get_peer_cursor("https://peer.example").await.unwrap(), 0);
+        database.set_peer_cursor("https://peer.example", 42).await.unwrap();
+        database.set_peer_cursor("https://peer.example", 43).await.unwrap();
+        assert_eq!(database.get_peer_cursor("https://peer.example").await.unwrap(), 43);
+    }
+}
@@ -1,15 +1,116 @@
-use crate::services::models;
+use crate::services::{amp, crypto, display, models, snapshot_cache, tracking};
+use futures_util::stream::{BoxStream, StreamExt, TryStreamExt};
 use sha2::{Digest, Sha256};
-use sqlx::{Error, PgPool, Row};
+use sqlx::postgres::PgRow;
+use sqlx::{Error, PgConnection, PgPool, Postgres, QueryBuilder, Row};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
 pub struct PostgresDatabase {
     pool: PgPool,
+    /// Where listings and searches are read from; the primary unless a read replica is configured
+    read_pool: PgPool,
+    cipher: Option<crypto::SnippetCipher>,
+    tracking: tracking::TrackingParams,
+    unwrap_amp: bool,
+    /// Tags kept when snippet text is sanitized on save; ammonia's defaults and a few more when `None`
+    snippet_html_tags: Option<Vec<String>>,
 }
 
 impl PostgresDatabase {
     pub async fn new(database_url: &str) -> Result<Self, sqlx::Error> {
         let pool = sqlx::PgPool::connect(database_url).await?;
-        Ok(Self { pool })
+        Ok(Self {
+            read_pool: pool.clone(),
+            pool,
+            cipher: None,
+            tracking: Default::default(),
+            unwrap_amp: true,
+            snippet_html_tags: None,
+        })
+    }
+
+    /// Read listings, searches and exports from a replica. Writes, and the reads that decide how to write,
+    /// stay on the primary, so replication lag can't cause a duplicate or a lost update.
+    pub async fn with_read_replica(mut self, database_url: &str) -> Result<Self, sqlx::Error> {
+        self.read_pool = sqlx::PgPool::connect(database_url).await?;
+        Ok(self)
+    }
+
+    /// Encrypt snippet text at rest with the given cipher
+    pub fn with_snippet_cipher(mut self, cipher: Option<crypto::SnippetCipher>) -> Self {
+        self.cipher = cipher;
+        self
+    }
+
+    /// Strip these query parameters from URLs as they are saved and looked up
+    pub fn with_tracking_params(mut self, tracking: tracking::TrackingParams) -> Self {
+        self.tracking = tracking;
+        self
+    }
+
+    /// Rewrite AMP, mobile and Google cache links to the desktop URL as they are saved and looked up
+    pub fn with_amp_unwrapping(mut self, enabled: bool) -> Self {
+        self.unwrap_amp = enabled;
+        self
+    }
+
+    /// Keep only these tags when sanitizing snippet text on save
+    pub fn with_snippet_html_tags(mut self, tags: Option<Vec<String>>) -> Self {
+        self.snippet_html_tags = tags;
+        self
+    }
+
+    /// Snippet text sanitized for showing, then sealed like the text itself
+    fn sealed_html(&self, snippet: &str) -> Result<String, sqlx::Error> {
+        crypto::seal(
+            self.cipher.as_ref(),
+            &models::sanitize_snippet_html(snippet, self.snippet_html_tags.as_deref()),
+        )
+    }
+
+    /// Decrypt snippets read back, sanitizing the text of any saved before it was sanitized on save
+    fn open_snippets(
+        &self,
+        snippets: Vec<models::SnippetWithTags>,
+    ) -> Result<Vec<models::SnippetWithTags>, sqlx::Error> {
+        let mut snippets = crypto::open_snippets(self.cipher.as_ref(), snippets)?;
+        for snippet in &mut snippets {
+            if snippet.snippet_html.is_none() {
+                snippet.snippet_html = Some(models::sanitize_snippet_html(
+                    &snippet.snippet,
+                    self.snippet_html_tags.as_deref(),
+                ));
+            }
+        }
+        Ok(snippets)
+    }
+
+    /// A URL in the form it is stored in, before any alias is looked up
+    fn normalize(&self, url: &str) -> String {
+        let url = if self.unwrap_amp { amp::unwrap(url) } else { url.into() };
+        self.tracking.strip(&url).into_owned()
+    }
+
+    /// The URL as it is stored: normalized, and a known alias replaced by its canonical URL
+    async fn resolve(&self, url: &str) -> Result<String, sqlx::Error> {
+        let normalized = self.normalize(url);
+        Ok(get_aliased_url(&self.pool, &normalized).await?.unwrap_or(normalized))
+    }
+
+    /// Drop the cached bodies left under `url_id` once it has been folded into another saved URL, taking its
+    /// snapshots along
+    async fn forget_merged_snapshots(&self, url_id: i32, moved: Option<&models::CleanedUrl>) {
+        if moved.is_some_and(|moved| moved.merged) {
+            snapshot_cache::forget_deleted(|url_id| get_snapshots(&self.pool, url_id), Some(url_id)).await;
+        }
+    }
+
+    /// Keep the URL as it was given when it was stored under another form
+    async fn keep_original(&self, url: &str, resolved: &str) -> Result<(), sqlx::Error> {
+        if url != resolved {
+            record_original_url(&self.pool, resolved, url).await?;
+        }
+        Ok(())
     }
 }
 
@@ -20,47 +121,491 @@ impl models::Database for PostgresDatabase {
     }
 
     async fn check_health(&self) -> &'static str {
-        check_health(&self.pool).await
+        match check_health(&self.pool).await {
+            "ok" => check_health(&self.read_pool).await,
+            status => status,
+        }
     }
 
     async fn insert_url(&self, url: &str) -> Result<i32, sqlx::Error> {
-        insert_url(&self.pool, url).await
+        let resolved = self.resolve(url).await?;
+        let url_id = insert_url(&self.pool, &resolved).await?;
+        self.keep_original(url, &resolved).await?;
+        Ok(url_id)
+    }
+
+    async fn url_exists(&self, url: &str) -> Result<bool, sqlx::Error> {
+        url_exists(&self.pool, &self.resolve(url).await?).await
+    }
+
+    async fn get_url_counts(&self) -> Result<models::UrlCounts, sqlx::Error> {
+        get_url_counts(&self.read_pool).await
+    }
+
+    // From the primary, as saving reads the URL back straight away
+    async fn get_url_with_tags(&self, url: &str) -> Result<Option<models::UrlWithTags>, sqlx::Error> {
+        get_url_with_tags(&self.pool, &self.resolve(url).await?)
+            .await?
+            .map(|url| crypto::open_url(self.cipher.as_ref(), url))
+            .transpose()
+    }
+
+    async fn get_urls_with_tags(&self, filter: &models::ListFilter) -> Result<Vec<models::UrlWithTags>, sqlx::Error> {
+        get_urls_with_tags(&self.read_pool, filter)
+            .await?
+            .into_iter()
+            .map(|url| crypto::open_url(self.cipher.as_ref(), url))
+            .collect()
     }
 
-    async fn get_urls_with_tags(&self) -> Result<Vec<models::UrlWithTags>, sqlx::Error> {
-        get_urls_with_tags(&self.pool).await
+    async fn insert_snippet(
+        &self,
+        url: &str,
+        snippet: &str,
+        tags: &[&str],
+        source: Option<&models::SnippetSource>,
+    ) -> Result<i32, sqlx::Error> {
+        let html = self.sealed_html(snippet)?;
+        let snippet = crypto::seal(self.cipher.as_ref(), snippet)?;
+        let source = source
+            .map(|source| crypto::seal_source(self.cipher.as_ref(), source))
+            .transpose()?;
+        insert_snippet(
+            &self.pool,
+            &self.resolve(url).await?,
+            &snippet,
+            Some(&html),
+            tags,
+            source.as_ref(),
+        )
+        .await
+    }
+
+    async fn insert_image_snippet(
+        &self,
+        url: &str,
+        caption: &str,
+        tags: &[&str],
+        image: &models::SnippetImage,
+    ) -> Result<i32, sqlx::Error> {
+        let html = self.sealed_html(caption)?;
+        let caption = crypto::seal(self.cipher.as_ref(), caption)?;
+        let image = crypto::seal_image(self.cipher.as_ref(), image)?;
+        insert_image_snippet(
+            &self.pool,
+            &self.resolve(url).await?,
+            &caption,
+            Some(&html),
+            tags,
+            &image,
+        )
+        .await
+    }
+
+    async fn get_snippet_image(&self, snippet_id: i32) -> Result<Option<models::SnippetImage>, sqlx::Error> {
+        get_snippet_image(&self.pool, snippet_id)
+            .await?
+            .map(|image| crypto::open_image(self.cipher.as_ref(), image))
+            .transpose()
+    }
+
+    async fn get_listing_fingerprint(
+        &self,
+        filter: &models::ListFilter,
+    ) -> Result<models::ListingFingerprint, sqlx::Error> {
+        get_listing_fingerprint(&self.read_pool, filter).await
     }
 
-    async fn insert_snippet(&self, url: &str, snippet: &str, tags: &[&str]) -> Result<i32, sqlx::Error> {
-        insert_snippet(&self.pool, url, snippet, tags).await
+    async fn get_all_urls(&self, filter: &models::ListFilter) -> Result<Vec<models::Url>, sqlx::Error> {
+        get_all_urls(&self.read_pool, filter).await
     }
 
-    async fn get_all_urls(&self) -> Result<Vec<models::Url>, sqlx::Error> {
-        get_all_urls(&self.pool).await
+    fn stream_exported_urls(&self) -> BoxStream<'_, Result<models::ExportedUrl, sqlx::Error>> {
+        stream_exported_urls(&self.read_pool)
+    }
+
+    // From the primary, as deletes act on what it finds
+    async fn get_loose_url_matches(&self, url: &str) -> Result<Vec<String>, sqlx::Error> {
+        get_loose_url_matches(&self.pool, &self.resolve(url).await?).await
     }
 
     async fn delete_url_by_url(&self, url: &str) -> Result<(), sqlx::Error> {
-        delete_url_by_url(&self.pool, url).await
+        let url = self.resolve(url).await?;
+        let url_id = if snapshot_cache::enabled() {
+            get_url_with_tags(&self.pool, &url).await?.map(|saved| saved.id)
+        } else {
+            None
+        };
+        delete_url_by_url(&self.pool, &url).await?;
+        if let Some(url_id) = url_id {
+            snapshot_cache::forget_deleted(|url_id| get_snapshots(&self.pool, url_id), Some(url_id)).await;
+        }
+        Ok(())
+    }
+
+    async fn get_random_url(&self, filter: &models::ListFilter) -> Result<Option<models::Url>, sqlx::Error> {
+        get_random_url(&self.read_pool, filter).await
+    }
+
+    async fn get_next_url(&self, filter: &models::ListFilter) -> Result<Option<models::Url>, sqlx::Error> {
+        get_next_url(&self.pool, filter).await
+    }
+
+    async fn get_url_by_short_id(&self, short_id: &str) -> Result<Option<models::Url>, sqlx::Error> {
+        get_url_by_short_id(&self.read_pool, short_id).await
+    }
+
+    async fn set_url_read(&self, url: &str, read: bool) -> Result<(), sqlx::Error> {
+        set_url_read(&self.pool, &self.resolve(url).await?, read).await
+    }
+
+    async fn set_url_progress(&self, url: &str, progress: i32) -> Result<(), sqlx::Error> {
+        set_url_progress(&self.pool, &self.resolve(url).await?, progress).await
+    }
+
+    async fn set_url_metadata(&self, url: &str, metadata: &models::UrlMetadata) -> Result<(), sqlx::Error> {
+        set_url_metadata(&self.pool, &self.resolve(url).await?, metadata).await
+    }
+
+    async fn update_url(&self, url_id: i32, update: &models::UrlUpdate) -> Result<(), sqlx::Error> {
+        update_url(&self.pool, url_id, &crypto::seal_notes(self.cipher.as_ref(), update)?).await
+    }
+
+    async fn set_url_pinned(&self, url: &str, pinned: bool) -> Result<(), sqlx::Error> {
+        set_url_pinned(&self.pool, &self.resolve(url).await?, pinned).await
+    }
+
+    async fn set_snippet_pinned(&self, snippet_id: i32, pinned: bool) -> Result<(), sqlx::Error> {
+        set_snippet_pinned(&self.pool, snippet_id, pinned).await
     }
 
     async fn insert_tags(&self, url: &str, tags: &[&str]) -> Result<(), sqlx::Error> {
-        insert_tags(&self.pool, url, tags).await
+        let resolved = self.resolve(url).await?;
+        insert_tags(&self.pool, &resolved, tags).await?;
+        self.keep_original(url, &resolved).await
     }
 
-    async fn remove_unused_tags(&self) -> Result<(), sqlx::Error> {
-        remove_unused_tags(&self.pool).await
+    async fn set_url_tags(&self, url: &str, tags: &[&str]) -> Result<(), sqlx::Error> {
+        let resolved = self.resolve(url).await?;
+        set_url_tags(&self.pool, &resolved, tags).await?;
+        self.keep_original(url, &resolved).await
     }
 
     async fn delete_snippet(&self, snippet_id: i32) -> Result<(), sqlx::Error> {
         delete_snippet(&self.pool, snippet_id).await
     }
 
-    async fn get_snippets_with_tags(&self) -> Result<Vec<models::SnippetWithTags>, sqlx::Error> {
-        get_snippets_with_tags(&self.pool).await
+    async fn get_snippets_with_tags(
+        &self,
+        filter: &models::ListFilter,
+    ) -> Result<Vec<models::SnippetWithTags>, sqlx::Error> {
+        self.open_snippets(get_snippets_with_tags(&self.read_pool, filter).await?)
+    }
+
+    async fn get_snippets_for_url(&self, url: &str) -> Result<Vec<models::SnippetWithTags>, sqlx::Error> {
+        self.open_snippets(get_snippets_for_url(&self.pool, &self.resolve(url).await?).await?)
+    }
+
+    async fn get_snippet(&self, snippet_id: i32) -> Result<Option<models::SnippetWithTags>, sqlx::Error> {
+        let snippets = get_snippet(&self.pool, snippet_id).await?.into_iter().collect();
+        Ok(self.open_snippets(snippets)?.pop())
+    }
+
+    async fn get_snippets_saved_before(
+        &self,
+        cutoff: chrono::NaiveDateTime,
+    ) -> Result<Vec<models::SnippetWithTags>, sqlx::Error> {
+        self.open_snippets(get_snippets_saved_before(&self.read_pool, cutoff).await?)
     }
 
     async fn get_tags_with_urls_and_snippets(&self) -> Result<Vec<models::TagWithUrlsAndSnippets>, sqlx::Error> {
-        get_tags_with_urls_and_snippets(&self.pool).await
+        get_tags_with_urls_and_snippets(&self.read_pool)
+            .await?
+            .into_iter()
+            .map(|mut tag| {
+                tag.snippets = self.open_snippets(tag.snippets)?;
+                Ok(tag)
+            })
+            .collect()
+    }
+
+    async fn get_all_tags(&self) -> Result<Vec<String>, sqlx::Error> {
+        get_all_tags(&self.read_pool).await
+    }
+
+    async fn get_tag_counts(&self) -> Result<Vec<models::TagCount>, sqlx::Error> {
+        get_tag_counts(&self.read_pool).await
+    }
+
+    async fn get_tag_details(&self) -> Result<Vec<models::TagDetails>, sqlx::Error> {
+        get_tag_details(&self.read_pool).await
+    }
+
+    async fn get_tag(&self, tag: &str) -> Result<Option<models::TagDetails>, sqlx::Error> {
+        get_tag(&self.read_pool, tag).await
+    }
+
+    async fn set_tag_details(
+        &self,
+        tag: &str,
+        color: Option<&str>,
+        description: Option<&str>,
+    ) -> Result<(), sqlx::Error> {
+        set_tag_details(&self.pool, tag, color, description).await
+    }
+
+    async fn import_tags(&self, tags: &[models::TagDetails]) -> Result<models::TagImport, sqlx::Error> {
+        import_tags(&self.pool, tags).await
+    }
+
+    async fn record_activity(&self, action: &str, subject: &str, detail: Option<&str>) -> Result<(), sqlx::Error> {
+        record_activity(&self.pool, action, subject, detail).await
+    }
+
+    async fn get_activity(&self, limit: i64) -> Result<Vec<models::Activity>, sqlx::Error> {
+        get_activity(&self.read_pool, limit).await
+    }
+
+    async fn get_local_activity_since(&self, after_id: i32, limit: i64) -> Result<Vec<models::Activity>, sqlx::Error> {
+        get_local_activity_since(&self.pool, after_id, limit).await
+    }
+
+    async fn get_latest_activity(
+        &self,
+        subject: &str,
+        actions: &[&str],
+    ) -> Result<Option<models::Activity>, sqlx::Error> {
+        get_latest_activity(&self.pool, subject, actions).await
+    }
+
+    async fn get_activity_by_actions(&self, actions: &[&str]) -> Result<Vec<models::Activity>, sqlx::Error> {
+        get_activity_by_actions(&self.read_pool, actions).await
+    }
+
+    async fn record_peer_activity(&self, peer: &str, activity: &models::Activity) -> Result<(), sqlx::Error> {
+        record_peer_activity(&self.pool, peer, activity).await
+    }
+
+    async fn get_peer_cursor(&self, peer: &str) -> Result<i32, sqlx::Error> {
+        get_peer_cursor(&self.pool, peer).await
+    }
+
+    async fn set_peer_cursor(&self, peer: &str, cursor: i32) -> Result<(), sqlx::Error> {
+        set_peer_cursor(&self.pool, peer, cursor).await
+    }
+
+    async fn execute_batch(
+        &self,
+        operations: &[models::BatchOperation],
+    ) -> Result<Vec<Option<i32>>, models::BatchError> {
+        let mut resolved = Vec::with_capacity(operations.len());
+        for operation in operations {
+            let resolve = |url| async move { self.resolve(url).await.map_err(models::BatchError::transaction) };
+            resolved.push(match operation {
+                models::BatchOperation::AddUrl { url } => models::BatchOperation::AddUrl {
+                    url: resolve(url).await?,
+                },
+                models::BatchOperation::TagUrl { url, tags } => models::BatchOperation::TagUrl {
+                    url: resolve(url).await?,
+                    tags: tags.clone(),
+                },
+                models::BatchOperation::DeleteSnippet { id } => models::BatchOperation::DeleteSnippet { id: *id },
+            });
+        }
+        let ids = execute_batch(&self.pool, &resolved).await?;
+
+        for (operation, resolved) in operations.iter().zip(&resolved) {
+            if let (
+                models::BatchOperation::AddUrl { url } | models::BatchOperation::TagUrl { url, .. },
+                models::BatchOperation::AddUrl { url: resolved } | models::BatchOperation::TagUrl { url: resolved, .. },
+            ) = (operation, resolved)
+            {
+                self.keep_original(url, resolved)
+                    .await
+                    .map_err(models::BatchError::transaction)?;
+            }
+        }
+        Ok(ids)
+    }
+
+    async fn create_import_job(&self, source: &str, items: &[models::ImportItem]) -> Result<i32, sqlx::Error> {
+        let items = crypto::seal_import_items(self.cipher.as_ref(), items)?;
+        create_import_job(&self.pool, source, &items).await
+    }
+
+    async fn get_import_job(&self, id: i32) -> Result<Option<models::ImportJob>, sqlx::Error> {
+        get_import_job(&self.pool, id).await
+    }
+
+    async fn get_running_import_jobs(&self) -> Result<Vec<i32>, sqlx::Error> {
+        get_running_import_jobs(&self.pool).await
+    }
+
+    async fn get_pending_import_items(
+        &self,
+        job_id: i32,
+        limit: i64,
+    ) -> Result<Vec<models::PendingImportItem>, sqlx::Error> {
+        crypto::open_import_items(
+            self.cipher.as_ref(),
+            get_pending_import_items(&self.pool, job_id, limit).await?,
+        )
+    }
+
+    async fn start_import_item(&self, job_id: i32, item_id: i32) -> Result<(), sqlx::Error> {
+        start_import_item(&self.pool, job_id, item_id).await
+    }
+
+    async fn finish_import_item(
+        &self,
+        job_id: i32,
+        item_id: i32,
+        outcome: &models::ImportOutcome,
+    ) -> Result<(), sqlx::Error> {
+        finish_import_item(&self.pool, job_id, item_id, outcome).await
+    }
+
+    async fn complete_import_job(&self, id: i32) -> Result<(), sqlx::Error> {
+        complete_import_job(&self.pool, id).await
+    }
+
+    async fn insert_snapshot(
+        &self,
+        url_id: i32,
+        content_type: Option<&str>,
+        content: &str,
+        truncated: bool,
+        keep: i64,
+    ) -> Result<Option<i32>, sqlx::Error> {
+        let id = insert_snapshot(&self.pool, url_id, content_type, content, truncated, keep).await?;
+        // Taking one may have dropped the oldest past the limit
+        if id.is_some() {
+            snapshot_cache::forget_deleted(|url_id| get_snapshots(&self.pool, url_id), Some(url_id)).await;
+        }
+        Ok(id)
+    }
+
+    async fn get_snapshots(&self, url_id: i32) -> Result<Vec<models::Snapshot>, sqlx::Error> {
+        get_snapshots(&self.read_pool, url_id).await
+    }
+
+    async fn get_snapshot(&self, url_id: i32, id: i32) -> Result<Option<models::SnapshotContent>, sqlx::Error> {
+        get_snapshot(&self.pool, url_id, id).await
+    }
+
+    async fn touch_snapshot(&self, url_id: i32, id: i32) -> Result<Option<chrono::NaiveDateTime>, sqlx::Error> {
+        touch_snapshot(&self.pool, url_id, id).await
+    }
+
+    async fn get_snapshotted_urls(&self) -> Result<Vec<models::Url>, sqlx::Error> {
+        get_snapshotted_urls(&self.pool).await
+    }
+
+    async fn get_url_metadata_entries(&self, url_id: i32) -> Result<BTreeMap<String, serde_json::Value>, sqlx::Error> {
+        get_url_metadata_entries(&self.read_pool, url_id).await
+    }
+
+    async fn get_url_metadata_entry(&self, url_id: i32, key: &str) -> Result<Option<serde_json::Value>, sqlx::Error> {
+        get_url_metadata_entry(&self.read_pool, url_id, key).await
+    }
+
+    async fn set_url_metadata_entry(
+        &self,
+        url_id: i32,
+        key: &str,
+        value: &serde_json::Value,
+    ) -> Result<(), sqlx::Error> {
+        set_url_metadata_entry(&self.pool, url_id, key, value).await
+    }
+
+    async fn archive_unread_before(
+        &self,
+        cutoff: chrono::NaiveDateTime,
+        dry_run: bool,
+    ) -> Result<Vec<String>, sqlx::Error> {
+        archive_unread_before(&self.pool, cutoff, dry_run).await
+    }
+
+    async fn archive_read_before(
+        &self,
+        cutoff: chrono::NaiveDateTime,
+        dry_run: bool,
+    ) -> Result<Vec<String>, sqlx::Error> {
+        archive_read_before(&self.pool, cutoff, dry_run).await
+    }
+
+    async fn delete_read_before(
+        &self,
+        cutoff: chrono::NaiveDateTime,
+        dry_run: bool,
+    ) -> Result<Vec<String>, sqlx::Error> {
+        let deleted = delete_read_before(&self.pool, cutoff, dry_run).await?;
+        if !dry_run && !deleted.is_empty() {
+            snapshot_cache::forget_deleted(|url_id| get_snapshots(&self.pool, url_id), None).await;
+        }
+        Ok(deleted)
+    }
+
+    async fn evict_snapshots(
+        &self,
+        max_bytes: i64,
+        dry_run: bool,
+    ) -> Result<Vec<models::EvictedSnapshot>, sqlx::Error> {
+        let evicted = evict_snapshots(&self.pool, max_bytes, dry_run).await?;
+        if !dry_run {
+            let ids: Vec<i32> = evicted.iter().map(|snapshot| snapshot.id).collect();
+            snapshot_cache::forget(&ids).await;
+        }
+        Ok(evicted)
+    }
+
+    async fn adopt_canonical_url(
+        &self,
+        url_id: i32,
+        canonical: &str,
+    ) -> Result<Option<models::CleanedUrl>, sqlx::Error> {
+        let moved = adopt_canonical_url(&self.pool, url_id, &self.normalize(canonical)).await?;
+        self.forget_merged_snapshots(url_id, moved.as_ref()).await;
+        Ok(moved)
+    }
+
+    async fn move_url(&self, url_id: i32, url: &str) -> Result<Option<models::CleanedUrl>, sqlx::Error> {
+        let moved = move_url(&self.pool, url_id, &self.normalize(url)).await?;
+        self.forget_merged_snapshots(url_id, moved.as_ref()).await;
+        Ok(moved)
+    }
+
+    async fn clean_urls(&self, dry_run: bool) -> Result<Vec<models::CleanedUrl>, sqlx::Error> {
+        let cleaned = clean_urls(&self.pool, &|url| self.normalize(url), dry_run).await?;
+        if !dry_run && cleaned.iter().any(|url| url.merged) {
+            snapshot_cache::forget_deleted(|url_id| get_snapshots(&self.pool, url_id), None).await;
+        }
+        Ok(cleaned)
+    }
+
+    async fn repair_snippet_tags(&self, dry_run: bool) -> Result<Vec<i32>, sqlx::Error> {
+        repair_snippet_tags(&self.pool, dry_run).await
+    }
+
+    async fn delete_all_data(&self) -> Result<(), sqlx::Error> {
+        delete_all_data(&self.pool).await
+    }
+
+    async fn read_rows(
+        &self,
+        table: &models::CopiedTable,
+        offset: i64,
+        limit: i64,
+    ) -> Result<Vec<Vec<models::ColumnValue>>, sqlx::Error> {
+        read_rows(&self.pool, table, offset, limit).await
+    }
+
+    async fn write_rows(
+        &self,
+        table: &models::CopiedTable,
+        rows: &[Vec<models::ColumnValue>],
+    ) -> Result<(), sqlx::Error> {
+        write_rows(&self.pool, table, rows).await
     }
 }
 
@@ -79,11 +624,92 @@ pub async fn create_urls_table(db_pool: &PgPool) -> Result<(), Error> {
             id SERIAL PRIMARY KEY,
             datetime TIMESTAMP NOT NULL DEFAULT NOW(),
             url TEXT NOT NULL,
-            url_hash CHAR(64) NOT NULL UNIQUE
+            url_hash CHAR(64) NOT NULL UNIQUE,
+            read_at TIMESTAMP,
+            pinned BOOLEAN NOT NULL DEFAULT FALSE,
+            progress INTEGER NOT NULL DEFAULT 0,
+            original_url TEXT,
+            metadata TEXT,
+            title TEXT,
+            notes TEXT,
+            archived_at TIMESTAMP,
+            snoozed_until TIMESTAMP,
+            normalized_url TEXT,
+            revision INTEGER NOT NULL DEFAULT 0,
+            short_id TEXT
         )
     "#;
 
     sqlx::query(query).execute(db_pool).await?;
+
+    // Columns added after the first release
+    add_column_if_missing(db_pool, "urls", "read_at", "TIMESTAMP").await?;
+    add_column_if_missing(db_pool, "urls", "pinned", "BOOLEAN NOT NULL DEFAULT FALSE").await?;
+    add_column_if_missing(db_pool, "urls", "progress", "INTEGER NOT NULL DEFAULT 0").await?;
+    add_column_if_missing(db_pool, "urls", "original_url", "TEXT").await?;
+    add_column_if_missing(db_pool, "urls", "metadata", "TEXT").await?;
+    add_column_if_missing(db_pool, "urls", "title", "TEXT").await?;
+    add_column_if_missing(db_pool, "urls", "notes", "TEXT").await?;
+    add_column_if_missing(db_pool, "urls", "archived_at", "TIMESTAMP").await?;
+    add_column_if_missing(db_pool, "urls", "snoozed_until", "TIMESTAMP").await?;
+    add_column_if_missing(db_pool, "urls", "normalized_url", "TEXT").await?;
+    add_column_if_missing(db_pool, "urls", "revision", "INTEGER NOT NULL DEFAULT 0").await?;
+    add_column_if_missing(db_pool, "urls", "short_id", "TEXT").await?;
+    fill_normalized_urls(db_pool).await?;
+    // Rows saved before short ids were stored had them worked out from the hash as it is now
+    sqlx::query("UPDATE urls SET short_id = SUBSTR(url_hash, 1, 10) WHERE short_id IS NULL")
+        .execute(db_pool)
+        .await?;
+
+    // Every change to a row counts up its revision, so listing fingerprints see edits that record no activity. Only
+    // updates that change something count, so saving a URL again leaves it alone.
+    let query = r#"
+        CREATE OR REPLACE FUNCTION revise_url() RETURNS trigger AS $$
+        BEGIN
+            NEW.revision := OLD.revision + 1;
+            RETURN NEW;
+        END
+        $$ LANGUAGE plpgsql
+    "#;
+    sqlx::query(query).execute(db_pool).await?;
+    sqlx::query("DROP TRIGGER IF EXISTS urls_revised ON urls")
+        .execute(db_pool)
+        .await?;
+    sqlx::query(
+        "CREATE TRIGGER urls_revised BEFORE UPDATE ON urls FOR EACH ROW WHEN (OLD.* IS DISTINCT FROM NEW.*) \
+         EXECUTE FUNCTION revise_url()",
+    )
+    .execute(db_pool)
+    .await?;
+    Ok(())
+}
+
+/// Work out `normalized_url` for rows saved before the column was added
+async fn fill_normalized_urls(db_pool: &PgPool) -> Result<(), Error> {
+    let rows: Vec<(i32, String)> = sqlx::query_as("SELECT id, url FROM urls WHERE normalized_url IS NULL")
+        .fetch_all(db_pool)
+        .await?;
+    if rows.is_empty() {
+        return Ok(());
+    }
+    let mut tx = db_pool.begin().await?;
+    for (id, url) in rows {
+        sqlx::query("UPDATE urls SET normalized_url = $1 WHERE id = $2")
+            .bind(models::loose_url(&url))
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await
+}
+
+/// Add `column` to an existing `table` unless it is already there
+async fn add_column_if_missing(db_pool: &PgPool, table: &str, column: &str, definition: &str) -> Result<(), Error> {
+    sqlx::query(&format!(
+        "ALTER TABLE {table} ADD COLUMN IF NOT EXISTS {column} {definition}"
+    ))
+    .execute(db_pool)
+    .await?;
     Ok(())
 }
 
@@ -92,7 +718,9 @@ pub async fn create_tags_table(db_pool: &PgPool) -> Result<(), Error> {
     let query = r#"
         CREATE TABLE IF NOT EXISTS tags (
             id SERIAL PRIMARY KEY,
-            tag TEXT NOT NULL UNIQUE
+            tag TEXT NOT NULL UNIQUE,
+            color TEXT,
+            description TEXT
         )
     "#;
 
@@ -116,6 +744,9 @@ pub async fn create_tags_table(db_pool: &PgPool) -> Result<(), Error> {
 
     sqlx::query(constraint_query).execute(db_pool).await?;
 
+    // Columns added after the first release
+    add_column_if_missing(db_pool, "tags", "color", "TEXT").await?;
+    add_column_if_missing(db_pool, "tags", "description", "TEXT").await?;
     Ok(())
 }
 
@@ -131,6 +762,30 @@ pub async fn create_url_tags_table(db_pool: &PgPool) -> Result<(), Error> {
     "#;
 
     sqlx::query(query).execute(db_pool).await?;
+
+    // Tagging or untagging a URL counts up its revision too, as the tags are part of what a listing shows
+    let query = r#"
+        CREATE OR REPLACE FUNCTION revise_tagged_url() RETURNS trigger AS $$
+        BEGIN
+            IF TG_OP = 'INSERT' THEN
+                UPDATE urls SET revision = revision + 1 WHERE id = NEW.url_id;
+                RETURN NEW;
+            END IF;
+            UPDATE urls SET revision = revision + 1 WHERE id = OLD.url_id;
+            RETURN OLD;
+        END
+        $$ LANGUAGE plpgsql
+    "#;
+    sqlx::query(query).execute(db_pool).await?;
+    sqlx::query("DROP TRIGGER IF EXISTS url_tags_revised ON url_tags")
+        .execute(db_pool)
+        .await?;
+    sqlx::query(
+        "CREATE TRIGGER url_tags_revised AFTER INSERT OR DELETE ON url_tags FOR EACH ROW \
+         EXECUTE FUNCTION revise_tagged_url()",
+    )
+    .execute(db_pool)
+    .await?;
     Ok(())
 }
 
@@ -141,7 +796,29 @@ pub async fn create_snippets_table(db_pool: &PgPool) -> Result<(), Error> {
             id SERIAL PRIMARY KEY,
             url TEXT NOT NULL,
             snippet TEXT NOT NULL,
-            tags TEXT[]
+            tags TEXT[],
+            pinned BOOLEAN NOT NULL DEFAULT FALSE,
+            source TEXT
+        )
+    "#;
+
+    sqlx::query(query).execute(db_pool).await?;
+
+    // Columns added after the first release
+    add_column_if_missing(db_pool, "snippets", "pinned", "BOOLEAN NOT NULL DEFAULT FALSE").await?;
+    add_column_if_missing(db_pool, "snippets", "source", "TEXT").await?;
+    add_column_if_missing(db_pool, "snippets", "saved_at", "TIMESTAMP").await?;
+    add_column_if_missing(db_pool, "snippets", "snippet_html", "TEXT").await?;
+    Ok(())
+}
+
+/// Create the `snippet_images` table holding the picture of each image snippet
+pub async fn create_snippet_images_table(db_pool: &PgPool) -> Result<(), Error> {
+    let query = r#"
+        CREATE TABLE IF NOT EXISTS snippet_images (
+            snippet_id INTEGER PRIMARY KEY REFERENCES snippets(id) ON DELETE CASCADE,
+            content_type TEXT NOT NULL,
+            data BYTEA NOT NULL
         )
     "#;
 
@@ -164,78 +841,377 @@ pub async fn create_snippet_tags_table(db_pool: &PgPool) -> Result<(), Error> {
     Ok(())
 }
 
-/// Initialize all database tables
-pub async fn initialize_tables(db_pool: &PgPool) -> Result<(), Error> {
-    create_urls_table(db_pool).await?;
-    create_tags_table(db_pool).await?;
-    create_url_tags_table(db_pool).await?;
-    create_snippets_table(db_pool).await?;
-    create_snippet_tags_table(db_pool).await?;
-    Ok(())
-}
-
-/// Hash a URL to create a unique identifier
-fn calculate_url_hash(url: &str) -> String {
-    let mut hasher = Sha256::new();
-    hasher.update(url);
-    format!("{:x}", hasher.finalize()) // Convert to a hexadecimal string
-}
-
-/// Insert a URL into the database
-pub async fn insert_url(db_pool: &PgPool, url: &str) -> Result<i32, Error> {
-    let url_hash = calculate_url_hash(url);
+/// Create the `tag_counts` table, with triggers keeping it in step with `url_tags` and `snippet_tags`.
+///
+/// The counts are rebuilt from scratch here, which picks up tags saved before the triggers existed.
+pub async fn create_tag_counts_table(db_pool: &PgPool) -> Result<(), Error> {
+    let query = r#"
+        CREATE TABLE IF NOT EXISTS tag_counts (
+            tag_id INTEGER PRIMARY KEY REFERENCES tags(id) ON DELETE CASCADE,
+            url_count BIGINT NOT NULL DEFAULT 0,
+            snippet_count BIGINT NOT NULL DEFAULT 0
+        )
+    "#;
+    sqlx::query(query).execute(db_pool).await?;
 
-    // Try to insert the URL and return its ID. If it already exists, fetch the existing ID.
+    // One function serves both tables; it tells them apart by name
     let query = r#"
-        INSERT INTO urls (url, url_hash)
-        VALUES ($1, $2)
-        ON CONFLICT (url_hash) DO UPDATE SET url_hash = urls.url_hash
-        RETURNING id
+        CREATE OR REPLACE FUNCTION count_tag_use() RETURNS trigger AS $$
+        DECLARE
+            url_delta BIGINT := CASE WHEN TG_TABLE_NAME = 'url_tags' THEN 1 ELSE 0 END;
+            snippet_delta BIGINT := CASE WHEN TG_TABLE_NAME = 'snippet_tags' THEN 1 ELSE 0 END;
+        BEGIN
+            IF TG_OP = 'INSERT' THEN
+                INSERT INTO tag_counts (tag_id, url_count, snippet_count) VALUES (NEW.tag_id, url_delta, snippet_delta)
+                ON CONFLICT (tag_id) DO UPDATE SET
+                    url_count = tag_counts.url_count + url_delta,
+                    snippet_count = tag_counts.snippet_count + snippet_delta;
+                RETURN NEW;
+            END IF;
+            UPDATE tag_counts
+            SET url_count = url_count - url_delta, snippet_count = snippet_count - snippet_delta
+            WHERE tag_id = OLD.tag_id;
+            RETURN OLD;
+        END
+        $$ LANGUAGE plpgsql
     "#;
+    sqlx::query(query).execute(db_pool).await?;
 
-    let url_id: i32 = sqlx::query_scalar(query)
-        .bind(url)
-        .bind(url_hash)
-        .fetch_one(db_pool)
+    for table in ["url_tags", "snippet_tags"] {
+        sqlx::query(&format!("DROP TRIGGER IF EXISTS {table}_counted ON {table}"))
+            .execute(db_pool)
+            .await?;
+        sqlx::query(&format!(
+            "CREATE TRIGGER {table}_counted AFTER INSERT OR DELETE ON {table} FOR EACH ROW EXECUTE FUNCTION count_tag_use()"
+        ))
+        .execute(db_pool)
         .await?;
+    }
 
-    Ok(url_id)
+    let mut tx = db_pool.begin().await?;
+    sqlx::query("DELETE FROM tag_counts").execute(&mut *tx).await?;
+    let query = r#"
+        INSERT INTO tag_counts (tag_id, url_count, snippet_count)
+        SELECT
+            tags.id,
+            (SELECT COUNT(*) FROM url_tags WHERE url_tags.tag_id = tags.id),
+            (SELECT COUNT(*) FROM snippet_tags WHERE snippet_tags.tag_id = tags.id)
+        FROM tags
+    "#;
+    sqlx::query(query).execute(&mut *tx).await?;
+    tx.commit().await?;
+    Ok(())
 }
 
-/// Insert a snippet into the database
-pub async fn insert_snippet(db_pool: &PgPool, url: &str, snippet: &str, tags: &[&str]) -> Result<i32, Error> {
+/// Create the `activity` table backing the activity timeline
+pub async fn create_activity_table(db_pool: &PgPool) -> Result<(), Error> {
     let query = r#"
-        INSERT INTO snippets (url, snippet, tags)
-        VALUES ($1, $2, $3)
-        RETURNING id
+        CREATE TABLE IF NOT EXISTS activity (
+            id SERIAL PRIMARY KEY,
+            datetime TIMESTAMP NOT NULL DEFAULT NOW(),
+            action TEXT NOT NULL,
+            subject TEXT NOT NULL,
+            detail TEXT
+        )
     "#;
+    sqlx::query(query).execute(db_pool).await?;
 
-    let snippet_id: i32 = sqlx::query_scalar(query)
-        .bind(url)
-        .bind(snippet)
-        .bind(tags)
-        .fetch_one(db_pool)
+    // The peer a change was applied from; NULL for changes made here
+    add_column_if_missing(db_pool, "activity", "origin", "TEXT").await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS activity_subject ON activity (subject)")
+        .execute(db_pool)
         .await?;
+    Ok(())
+}
 
-    // Ensure tags are added to the tags table and linked to the snippet
-    for tag in tags {
-        let tag_query = r#"
-            INSERT INTO tags (tag)
-            VALUES ($1)
-            ON CONFLICT (tag) DO NOTHING
-            RETURNING id
-        "#;
+/// Create the `peers` table recording how far through each peer's changes have been applied
+pub async fn create_peers_table(db_pool: &PgPool) -> Result<(), Error> {
+    let query = r#"
+        CREATE TABLE IF NOT EXISTS peers (
+            url TEXT PRIMARY KEY,
+            last_change_id INTEGER NOT NULL DEFAULT 0,
+            synced_at TIMESTAMP
+        )
+    "#;
 
-        let tag_id: i32 = match sqlx::query_scalar(tag_query).bind(tag).fetch_one(db_pool).await {
-            Ok(id) => id,
-            Err(sqlx::Error::RowNotFound) => {
-                // If the tag exists but isn't returned, fetch its ID directly
-                sqlx::query_scalar("SELECT id FROM tags WHERE tag = $1")
-                    .bind(tag)
-                    .fetch_one(db_pool)
-                    .await?
-            }
-            Err(err) => return Err(err),
+    sqlx::query(query).execute(db_pool).await?;
+    Ok(())
+}
+
+/// Create the `import_jobs` table and the `import_job_items` queue behind it
+pub async fn create_import_job_tables(db_pool: &PgPool) -> Result<(), Error> {
+    let query = r#"
+        CREATE TABLE IF NOT EXISTS import_jobs (
+            id SERIAL PRIMARY KEY,
+            source TEXT NOT NULL,
+            status TEXT NOT NULL DEFAULT 'running',
+            total INTEGER NOT NULL,
+            processed INTEGER NOT NULL DEFAULT 0,
+            created INTEGER NOT NULL DEFAULT 0,
+            skipped INTEGER NOT NULL DEFAULT 0,
+            failed INTEGER NOT NULL DEFAULT 0,
+            started_at TIMESTAMP NOT NULL DEFAULT NOW(),
+            finished_at TIMESTAMP
+        )
+    "#;
+    sqlx::query(query).execute(db_pool).await?;
+
+    // `outcome` stays NULL until the item is processed; tags are a JSON array. `started` is set just before its URL
+    // is saved, so a job resumed after a crash finishes the item rather than skipping its own URL.
+    let query = r#"
+        CREATE TABLE IF NOT EXISTS import_job_items (
+            id SERIAL PRIMARY KEY,
+            job_id INTEGER NOT NULL REFERENCES import_jobs(id) ON DELETE CASCADE,
+            url TEXT NOT NULL,
+            tags TEXT NOT NULL,
+            note TEXT,
+            outcome TEXT,
+            error TEXT,
+            started BOOLEAN NOT NULL DEFAULT FALSE
+        )
+    "#;
+    sqlx::query(query).execute(db_pool).await?;
+    add_column_if_missing(db_pool, "import_job_items", "started", "BOOLEAN NOT NULL DEFAULT FALSE").await?;
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS import_job_items_pending ON import_job_items (job_id, outcome)")
+        .execute(db_pool)
+        .await?;
+    Ok(())
+}
+
+/// Initialize all database tables
+pub async fn initialize_tables(db_pool: &PgPool) -> Result<(), Error> {
+    create_urls_table(db_pool).await?;
+    create_tags_table(db_pool).await?;
+    create_url_tags_table(db_pool).await?;
+    create_snippets_table(db_pool).await?;
+    create_snippet_tags_table(db_pool).await?;
+    create_snippet_images_table(db_pool).await?;
+    create_tag_counts_table(db_pool).await?;
+    create_activity_table(db_pool).await?;
+    create_peers_table(db_pool).await?;
+    create_import_job_tables(db_pool).await?;
+    create_snapshots_table(db_pool).await?;
+    create_url_aliases_table(db_pool).await?;
+    create_url_metadata_table(db_pool).await?;
+    create_indexes(db_pool).await?;
+    Ok(())
+}
+
+/// Indexes behind the listing queries: saved-date ranges, the tag filters' lookups from a tag to what carries
+/// it, and a URL's snippets. Lookups the other way are covered by the link tables' unique constraints.
+const INDEXES: [(&str, &str); 6] = [
+    ("urls_datetime", "urls (datetime)"),
+    ("url_tags_tag", "url_tags (tag_id, url_id)"),
+    ("snippet_tags_tag", "snippet_tags (tag_id, snippet_id)"),
+    ("snippets_url", "snippets (url)"),
+    ("urls_normalized_url", "urls (normalized_url)"),
+    ("urls_short_id", "urls (short_id)"),
+];
+
+/// Create any of `INDEXES` that don't exist yet
+pub async fn create_indexes(db_pool: &PgPool) -> Result<(), Error> {
+    for (name, columns) in INDEXES {
+        sqlx::query(&format!("CREATE INDEX IF NOT EXISTS {name} ON {columns}"))
+            .execute(db_pool)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Create the `url_snapshots` table of fetched page copies
+pub async fn create_snapshots_table(db_pool: &PgPool) -> Result<(), Error> {
+    let query = r#"
+        CREATE TABLE IF NOT EXISTS url_snapshots (
+            id SERIAL PRIMARY KEY,
+            url_id INTEGER NOT NULL REFERENCES urls(id) ON DELETE CASCADE,
+            datetime TIMESTAMP NOT NULL DEFAULT NOW(),
+            content_type TEXT,
+            content TEXT NOT NULL,
+            accessed_at TIMESTAMP,
+            truncated BOOLEAN NOT NULL DEFAULT FALSE
+        )
+    "#;
+    sqlx::query(query).execute(db_pool).await?;
+
+    add_column_if_missing(db_pool, "url_snapshots", "accessed_at", "TIMESTAMP").await?;
+    add_column_if_missing(db_pool, "url_snapshots", "truncated", "BOOLEAN NOT NULL DEFAULT FALSE").await?;
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS url_snapshots_url ON url_snapshots (url_id, id)")
+        .execute(db_pool)
+        .await?;
+    Ok(())
+}
+
+/// Create the `url_aliases` table of other addresses for a saved URL, such as the one it was saved under
+/// before its canonical URL was known
+pub async fn create_url_aliases_table(db_pool: &PgPool) -> Result<(), Error> {
+    let query = r#"
+        CREATE TABLE IF NOT EXISTS url_aliases (
+            alias_hash CHAR(64) PRIMARY KEY,
+            url_id INTEGER NOT NULL REFERENCES urls(id) ON DELETE CASCADE
+        )
+    "#;
+    sqlx::query(query).execute(db_pool).await?;
+    Ok(())
+}
+
+/// Create the `url_metadata` table of named JSON values about a saved URL, for details that don't warrant a
+/// column of their own
+pub async fn create_url_metadata_table(db_pool: &PgPool) -> Result<(), Error> {
+    let query = r#"
+        CREATE TABLE IF NOT EXISTS url_metadata (
+            url_id INTEGER NOT NULL REFERENCES urls(id) ON DELETE CASCADE,
+            key TEXT NOT NULL,
+            value JSONB NOT NULL,
+            updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+            PRIMARY KEY (url_id, key)
+        )
+    "#;
+    sqlx::query(query).execute(db_pool).await?;
+    Ok(())
+}
+
+/// Hash a URL to create a unique identifier
+fn calculate_url_hash(url: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(url);
+    format!("{:x}", hasher.finalize()) // Convert to a hexadecimal string
+}
+
+/// Whether a URL (by hash) is already saved
+pub async fn url_exists(db_pool: &PgPool, url: &str) -> Result<bool, Error> {
+    sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM urls WHERE url_hash = $1)")
+        .bind(calculate_url_hash(url))
+        .fetch_one(db_pool)
+        .await
+}
+
+/// Saved URLs with the same `loose_url` as `url`, oldest first
+pub async fn get_loose_url_matches(db_pool: &PgPool, url: &str) -> Result<Vec<String>, Error> {
+    sqlx::query_scalar("SELECT url FROM urls WHERE normalized_url = $1 ORDER BY id")
+        .bind(models::loose_url(url))
+        .fetch_all(db_pool)
+        .await
+}
+
+/// Count the saved URLs and the unread ones among them
+pub async fn get_url_counts(db_pool: &PgPool) -> Result<models::UrlCounts, Error> {
+    let query = r#"
+        SELECT COUNT(*) AS total, COALESCE(SUM(CASE WHEN read_at IS NULL THEN 1 ELSE 0 END), 0) AS unread_count
+        FROM urls
+    "#;
+
+    sqlx::query_as::<_, models::UrlCounts>(query).fetch_one(db_pool).await
+}
+
+/// Insert a URL into the database
+pub async fn insert_url(db_pool: &PgPool, url: &str) -> Result<i32, Error> {
+    insert_url_in(&mut *db_pool.acquire().await?, url).await
+}
+
+/// Insert a URL using an existing connection or transaction
+async fn insert_url_in(conn: &mut PgConnection, url: &str) -> Result<i32, Error> {
+    let url_hash = calculate_url_hash(url);
+
+    // Try to insert the URL and return its ID. If it already exists, fetch the existing ID.
+    let query = r#"
+        INSERT INTO urls (url, url_hash, normalized_url, short_id)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (url_hash) DO UPDATE SET url_hash = urls.url_hash
+        RETURNING id
+    "#;
+    let short_id = free_short_id(&mut *conn, &url_hash).await?;
+
+    let url_id: i32 = sqlx::query_scalar(query)
+        .bind(url)
+        .bind(&url_hash)
+        .bind(models::loose_url(url))
+        .bind(short_id)
+        .fetch_one(&mut *conn)
+        .await?;
+
+    Ok(url_id)
+}
+
+/// A short id for a URL about to be saved: the start of its hash, or in the rare case another URL already has that,
+/// the first later stretch of the hash nobody has. It is kept from then on, even when the URL moves.
+async fn free_short_id(conn: &mut PgConnection, url_hash: &str) -> Result<String, Error> {
+    for start in 0..=url_hash.len().saturating_sub(models::SHORT_ID_LENGTH) {
+        let candidate = &url_hash[start..start + models::SHORT_ID_LENGTH];
+        let taken: bool =
+            sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM urls WHERE short_id = $1 AND url_hash <> $2)")
+                .bind(candidate)
+                .bind(url_hash)
+                .fetch_one(&mut *conn)
+                .await?;
+        if !taken {
+            return Ok(candidate.to_string());
+        }
+    }
+    Ok(url_hash[..models::SHORT_ID_LENGTH].to_string())
+}
+
+/// Insert a snippet into the database, with the page it was taken from when there is one
+pub async fn insert_snippet(
+    db_pool: &PgPool,
+    url: &str,
+    snippet: &str,
+    snippet_html: Option<&str>,
+    tags: &[&str],
+    source: Option<&models::SnippetSource>,
+) -> Result<i32, Error> {
+    insert_snippet_in(&mut *db_pool.acquire().await?, url, snippet, snippet_html, tags, source).await
+}
+
+/// Insert a snippet using an existing connection or transaction
+async fn insert_snippet_in(
+    conn: &mut PgConnection,
+    url: &str,
+    snippet: &str,
+    snippet_html: Option<&str>,
+    tags: &[&str],
+    source: Option<&models::SnippetSource>,
+) -> Result<i32, Error> {
+    let source = source
+        .map(serde_json::to_string)
+        .transpose()
+        .map_err(|err| Error::Encode(Box::new(err)))?;
+    let query = r#"
+        INSERT INTO snippets (url, snippet, snippet_html, tags, source, saved_at)
+        VALUES ($1, $2, $3, $4, $5, CURRENT_TIMESTAMP)
+        RETURNING id
+    "#;
+
+    let tags = models::unique_tags(tags.iter().copied());
+    let snippet_id: i32 = sqlx::query_scalar(query)
+        .bind(url)
+        .bind(snippet)
+        .bind(snippet_html)
+        .bind(&tags)
+        .bind(source)
+        .fetch_one(&mut *conn)
+        .await?;
+
+    // Ensure tags are added to the tags table and linked to the snippet
+    for tag in tags {
+        let tag_query = r#"
+            INSERT INTO tags (tag)
+            VALUES ($1)
+            ON CONFLICT (tag) DO NOTHING
+            RETURNING id
+        "#;
+
+        let tag_id: i32 = match sqlx::query_scalar(tag_query).bind(tag).fetch_one(&mut *conn).await {
+            Ok(id) => id,
+            Err(sqlx::Error::RowNotFound) => {
+                // If the tag exists but isn't returned, fetch its ID directly
+                sqlx::query_scalar("SELECT id FROM tags WHERE tag = $1")
+                    .bind(tag)
+                    .fetch_one(&mut *conn)
+                    .await?
+            }
+            Err(err) => return Err(err),
         };
 
         // Link the snippet and tag in the `snippet_tags` table
@@ -248,21 +1224,67 @@ pub async fn insert_snippet(db_pool: &PgPool, url: &str, snippet: &str, tags: &[
         sqlx::query(snippet_tag_query)
             .bind(snippet_id)
             .bind(tag_id)
-            .execute(db_pool)
+            .execute(&mut *conn)
             .await?;
     }
 
     Ok(snippet_id)
 }
 
+/// Insert a snippet of an image, with `caption` as its text
+pub async fn insert_image_snippet(
+    db_pool: &PgPool,
+    url: &str,
+    caption: &str,
+    caption_html: Option<&str>,
+    tags: &[&str],
+    image: &models::SnippetImage,
+) -> Result<i32, Error> {
+    let mut tx = db_pool.begin().await?;
+    let snippet_id = insert_snippet_in(&mut tx, url, caption, caption_html, tags, None).await?;
+    sqlx::query("INSERT INTO snippet_images (snippet_id, content_type, data) VALUES ($1, $2, $3)")
+        .bind(snippet_id)
+        .bind(&image.content_type)
+        .bind(&image.data)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(snippet_id)
+}
+
+/// Fetch the image of an image snippet, `None` when the snippet is text or doesn't exist
+pub async fn get_snippet_image(db_pool: &PgPool, snippet_id: i32) -> Result<Option<models::SnippetImage>, Error> {
+    sqlx::query_as::<_, models::SnippetImage>("SELECT content_type, data FROM snippet_images WHERE snippet_id = $1")
+        .bind(snippet_id)
+        .fetch_optional(db_pool)
+        .await
+}
+
 /// Insert tags into the database and associate them with a URL
 pub async fn insert_tags(db_pool: &PgPool, url: &str, tags: &[&str]) -> Result<(), Error> {
+    insert_tags_in(&mut *db_pool.acquire().await?, url, tags).await
+}
+
+/// Replace a URL's tags with `tags`, saving the URL if it isn't yet
+pub async fn set_url_tags(db_pool: &PgPool, url: &str, tags: &[&str]) -> Result<(), Error> {
+    let mut tx = db_pool.begin().await?;
+    let url_id = insert_url_in(&mut tx, url).await?;
+    sqlx::query("DELETE FROM url_tags WHERE url_id = $1")
+        .bind(url_id)
+        .execute(&mut *tx)
+        .await?;
+    insert_tags_in(&mut tx, url, tags).await?;
+    tx.commit().await
+}
+
+/// Insert tags for a URL using an existing connection or transaction
+async fn insert_tags_in(conn: &mut PgConnection, url: &str, tags: &[&str]) -> Result<(), Error> {
     if tags.is_empty() {
         return Ok(()); // Nothing to insert
     }
 
     // Insert or retrieve the URL ID
-    let url_id = insert_url(db_pool, url).await?;
+    let url_id = insert_url_in(conn, url).await?;
 
     for tag in tags {
         // Check if the tag already exists or insert it
@@ -274,13 +1296,13 @@ pub async fn insert_tags(db_pool: &PgPool, url: &str, tags: &[&str]) -> Result<(
         "#;
 
         // If the tag already exists, fetch its ID
-        let tag_id: i32 = match sqlx::query_scalar(tag_query).bind(tag).fetch_one(db_pool).await {
+        let tag_id: i32 = match sqlx::query_scalar(tag_query).bind(tag).fetch_one(&mut *conn).await {
             Ok(id) => id,
             Err(sqlx::Error::RowNotFound) => {
                 // If the tag exists but isn't returned, fetch its ID directly
                 sqlx::query_scalar("SELECT id FROM tags WHERE tag = $1")
                     .bind(tag)
-                    .fetch_one(db_pool)
+                    .fetch_one(&mut *conn)
                     .await?
             }
             Err(err) => return Err(err),
@@ -296,103 +1318,577 @@ pub async fn insert_tags(db_pool: &PgPool, url: &str, tags: &[&str]) -> Result<(
         sqlx::query(url_tag_query)
             .bind(url_id)
             .bind(tag_id)
-            .execute(db_pool)
+            .execute(&mut *conn)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Fetch one saved URL with its tags
+pub async fn get_url_with_tags(db_pool: &PgPool, url: &str) -> Result<Option<models::UrlWithTags>, sqlx::Error> {
+    let filter = models::ListFilter {
+        url_hash: Some(calculate_url_hash(url)),
+        ..Default::default()
+    };
+    Ok(get_urls_with_tags(db_pool, &filter).await?.pop())
+}
+
+/// Pick one URL matching `filter` at random
+pub async fn get_random_url(db_pool: &PgPool, filter: &models::ListFilter) -> Result<Option<models::Url>, Error> {
+    let mut query = QueryBuilder::<Postgres>::new(format!("SELECT {URL_COLUMNS} FROM urls"));
+    push_list_filter(&mut query, filter, "urls", "url_tags", "url_id");
+    push_url_filter(&mut query, filter);
+    query.push(" ORDER BY RANDOM() LIMIT 1");
+
+    query.build_query_as::<models::Url>().fetch_optional(db_pool).await
+}
+
+/// The first URL a listing with `filter` would show
+pub async fn get_next_url(db_pool: &PgPool, filter: &models::ListFilter) -> Result<Option<models::Url>, Error> {
+    let mut query = QueryBuilder::<Postgres>::new(format!("SELECT {URL_COLUMNS} FROM urls"));
+    push_list_filter(&mut query, filter, "urls", "url_tags", "url_id");
+    push_url_filter(&mut query, filter);
+    push_url_sort(&mut query, &filter.sort);
+    query.push(" LIMIT 1");
+
+    query.build_query_as::<models::Url>().fetch_optional(db_pool).await
+}
+
+/// Look up a URL by its short id
+pub async fn get_url_by_short_id(db_pool: &PgPool, short_id: &str) -> Result<Option<models::Url>, Error> {
+    if !models::is_short_id(short_id) {
+        return Ok(None);
+    }
+
+    let mut urls =
+        sqlx::query_as::<_, models::Url>(&format!("SELECT {URL_COLUMNS} FROM urls WHERE short_id = $1 LIMIT 2"))
+            .bind(short_id)
+            .fetch_all(db_pool)
             .await?;
+    // Rows given the same short id before they were stored can't be told apart, so neither is picked
+    if urls.len() > 1 {
+        return Ok(None);
     }
+    Ok(urls.pop())
+}
 
+/// Mark a URL read now, or unread again
+pub async fn set_url_read(db_pool: &PgPool, url: &str, read: bool) -> Result<(), Error> {
+    let query = if read {
+        "UPDATE urls SET read_at = NOW() WHERE url_hash = $1"
+    } else {
+        "UPDATE urls SET read_at = NULL WHERE url_hash = $1"
+    };
+    let result = sqlx::query(query)
+        .bind(calculate_url_hash(url))
+        .execute(db_pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(Error::RowNotFound);
+    }
     Ok(())
 }
 
+/// Apply a `PATCH` to one URL: its own columns in a single `UPDATE`, then its tags, in one transaction
+pub async fn update_url(db_pool: &PgPool, url_id: i32, update: &models::UrlUpdate) -> Result<(), Error> {
+    let mut tx = db_pool.begin().await?;
+
+    let mut query = QueryBuilder::<Postgres>::new("UPDATE urls SET ");
+    let mut columns = query.separated(", ");
+    // Keeps the statement valid when only the tags change, while still finding out whether the URL exists
+    columns.push("id = id");
+    if let Some(title) = &update.title {
+        columns.push("title = ").push_bind_unseparated(title.as_deref());
+    }
+    if let Some(notes) = &update.notes {
+        columns.push("notes = ").push_bind_unseparated(notes.as_deref());
+    }
+    // Reading or archiving again keeps the first time it happened
+    if let Some(read) = update.read {
+        columns.push(if read {
+            "read_at = COALESCE(read_at, NOW())"
+        } else {
+            "read_at = NULL"
+        });
+    }
+    if let Some(archived) = update.archived {
+        columns.push(if archived {
+            "archived_at = COALESCE(archived_at, NOW())"
+        } else {
+            "archived_at = NULL"
+        });
+    }
+    if let Some(until) = update.snoozed_until {
+        columns
+            .push("snoozed_until = ")
+            .push_bind_unseparated(until.map(|until| until.naive_utc()));
+    }
+    query.push(" WHERE id = ").push_bind(url_id).push(" RETURNING url");
+    let url: String = query
+        .build_query_scalar()
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(Error::RowNotFound)?;
+
+    if let Some(tags) = update.tag_list() {
+        sqlx::query("DELETE FROM url_tags WHERE url_id = $1")
+            .bind(url_id)
+            .execute(&mut *tx)
+            .await?;
+        insert_tags_in(&mut tx, &url, &tags).await?;
+    }
+    tx.commit().await
+}
+
 /// Delete a URL by its string value
 pub async fn delete_url_by_url(db_pool: &PgPool, url: &str) -> Result<(), Error> {
     let url_hash = calculate_url_hash(url);
-    let query = "DELETE FROM urls WHERE url_hash = $1";
-    sqlx::query(query).bind(url_hash).execute(db_pool).await?;
-    Ok(())
+    let mut tx = db_pool.begin().await?;
+    let tag_ids: Vec<i32> =
+        sqlx::query_scalar("SELECT tag_id FROM url_tags WHERE url_id = (SELECT id FROM urls WHERE url_hash = $1)")
+            .bind(&url_hash)
+            .fetch_all(&mut *tx)
+            .await?;
+    sqlx::query("DELETE FROM urls WHERE url_hash = $1")
+        .bind(url_hash)
+        .execute(&mut *tx)
+        .await?;
+    remove_unused_tags_in(&mut tx, &tag_ids).await?;
+    tx.commit().await
 }
 
 /// Delete a snippet by its string value
 pub async fn delete_snippet(db_pool: &PgPool, id: i32) -> Result<(), Error> {
-    let query = "DELETE FROM snippets WHERE id = $1";
-    sqlx::query(query).bind(id).execute(db_pool).await?;
+    let mut tx = db_pool.begin().await?;
+    let tag_ids = delete_snippet_in(&mut tx, id).await?;
+    remove_unused_tags_in(&mut tx, &tag_ids).await?;
+    tx.commit().await
+}
+
+/// Delete a snippet, returning the ids of the tags it carried
+async fn delete_snippet_in(conn: &mut PgConnection, id: i32) -> Result<Vec<i32>, Error> {
+    let tag_ids = sqlx::query_scalar("SELECT tag_id FROM snippet_tags WHERE snippet_id = $1")
+        .bind(id)
+        .fetch_all(&mut *conn)
+        .await?;
+    sqlx::query("DELETE FROM snippets WHERE id = $1")
+        .bind(id)
+        .execute(conn)
+        .await?;
+    Ok(tag_ids)
+}
+
+/// Delete whichever of `tag_ids` no URL or snippet carries any more, unless they have a color or description to
+/// keep. Only the tags a delete just unlinked are checked, so the link tables' tag indexes answer each lookup rather than a scan of every tag.
+async fn remove_unused_tags_in(conn: &mut PgConnection, tag_ids: &[i32]) -> Result<(), Error> {
+    if tag_ids.is_empty() {
+        return Ok(());
+    }
+    let query = r#"
+        DELETE FROM tags
+        WHERE id = ANY($1)
+          AND NOT EXISTS (SELECT 1 FROM url_tags WHERE tag_id = tags.id)
+          AND NOT EXISTS (SELECT 1 FROM snippet_tags WHERE tag_id = tags.id)
+          AND color IS NULL AND description IS NULL
+    "#;
+    sqlx::query(query).bind(tag_ids).execute(conn).await?;
     Ok(())
 }
 
-/// Remove unused tags from the database
-pub async fn remove_unused_tags(db_pool: &PgPool) -> Result<(), Error> {
+/// Delete every tag no URL or snippet carries and nobody gave a color or description, for maintenance runs that already visit the whole table
+async fn remove_all_unused_tags_in(conn: &mut PgConnection) -> Result<(), Error> {
     let query = r#"
         DELETE FROM tags
         WHERE id NOT IN (SELECT tag_id FROM url_tags)
           AND id NOT IN (SELECT tag_id FROM snippet_tags)
+          AND color IS NULL AND description IS NULL
     "#;
 
-    sqlx::query(query).execute(db_pool).await?;
+    sqlx::query(query).execute(conn).await?;
     Ok(())
 }
 
+/// Run a batch of operations in order inside one transaction, rolling everything back on the first failure
+pub async fn execute_batch(
+    db_pool: &PgPool,
+    operations: &[models::BatchOperation],
+) -> Result<Vec<Option<i32>>, models::BatchError> {
+    let mut tx = db_pool.begin().await.map_err(models::BatchError::transaction)?;
+    let mut results = Vec::with_capacity(operations.len());
+    let mut unlinked_tags = Vec::new();
+
+    for (index, operation) in operations.iter().enumerate() {
+        let result = match operation {
+            models::BatchOperation::AddUrl { url } => insert_url_in(&mut tx, url).await.map(Some),
+            models::BatchOperation::TagUrl { url, tags } => insert_tags_in(&mut tx, url, &models::split_tags(tags))
+                .await
+                .map(|_| None),
+            models::BatchOperation::DeleteSnippet { id } => delete_snippet_in(&mut tx, *id).await.map(|tag_ids| {
+                unlinked_tags.extend(tag_ids);
+                None
+            }),
+        };
+
+        let result = match result {
+            Ok(id) => record_batch_activity_in(&mut tx, operation).await.map(|_| id),
+            Err(error) => Err(error),
+        };
+
+        match result {
+            Ok(id) => results.push(id),
+            Err(error) => {
+                return Err(models::BatchError {
+                    index: Some(index),
+                    error,
+                })
+            }
+        }
+    }
+
+    remove_unused_tags_in(&mut tx, &unlinked_tags)
+        .await
+        .map_err(models::BatchError::transaction)?;
+    tx.commit().await.map_err(models::BatchError::transaction)?;
+    Ok(results)
+}
+
+/// Columns selected into `models::Url`
+const URL_COLUMNS: &str =
+    "urls.id, urls.datetime, urls.url, urls.url_hash, urls.read_at, urls.pinned, urls.progress, urls.original_url, \
+     urls.short_id";
+
+/// Append `WHERE` conditions for `filter` to a listing over `table`, whose tags live in `link_table`
+fn push_list_filter(
+    query: &mut QueryBuilder<'_, Postgres>,
+    filter: &models::ListFilter,
+    table: &str,
+    link_table: &str,
+    link_column: &str,
+) {
+    // Ids of items carrying any of `tags` or a tag nested under one of them
+    let tagged_with = |query: &mut QueryBuilder<'_, Postgres>, tags: &[String]| {
+        query.push(format!(
+            "SELECT lt.{link_column} FROM {link_table} lt JOIN tags ft ON ft.id = lt.tag_id WHERE "
+        ));
+        for (position, tag) in tags.iter().enumerate() {
+            if position > 0 {
+                query.push(" OR ");
+            }
+            let prefix = format!("{}{}", tag, models::TAG_SEPARATOR);
+            query
+                .push("ft.tag = ")
+                .push_bind(tag.clone())
+                .push(" OR SUBSTR(ft.tag, 1, ")
+                .push_bind(prefix.chars().count() as i32)
+                .push(") = ")
+                .push_bind(prefix);
+        }
+    };
+
+    query.push(" WHERE 1 = 1");
+
+    if !filter.tags.any_of.is_empty() {
+        query.push(" AND (");
+        for (position, group) in filter.tags.any_of.iter().enumerate() {
+            if position > 0 {
+                query.push(" OR ");
+            }
+            // Each tag of the group is checked on its own, since `lang` and `lang/rust` can match the same tag
+            query.push("(");
+            for (index, tag) in group.iter().enumerate() {
+                if index > 0 {
+                    query.push(" AND ");
+                }
+                query.push(format!("{table}.id IN ("));
+                tagged_with(query, std::slice::from_ref(tag));
+                query.push(")");
+            }
+            query.push(")");
+        }
+        query.push(")");
+    }
+
+    if !filter.tags.none_of.is_empty() {
+        query.push(format!(" AND {table}.id NOT IN ("));
+        tagged_with(query, &filter.tags.none_of);
+        query.push(")");
+    }
+
+    // Blank tags are what an empty tag field produces, so they don't count as tagged
+    if filter.untagged {
+        query.push(format!(
+            " AND {table}.id NOT IN (SELECT lt.{link_column} FROM {link_table} lt JOIN tags ft ON ft.id = lt.tag_id WHERE ft.tag <> '')"
+        ));
+    }
+}
+
+/// Append the URL-only conditions (date range, read state); call after `push_list_filter`
+fn push_url_filter(query: &mut QueryBuilder<'_, Postgres>, filter: &models::ListFilter) {
+    if let Some(after) = filter.saved_after {
+        query.push(" AND urls.datetime >= ").push_bind(after);
+    }
+    if let Some(before) = filter.saved_before {
+        query.push(" AND urls.datetime < ").push_bind(before);
+    }
+    if filter.unread {
+        query.push(" AND urls.read_at IS NULL");
+    }
+    if let Some(kind) = filter.kind {
+        query
+            .push(" AND ((urls.metadata::jsonb ->> 'type') = ")
+            .push_bind(kind.as_str())
+            .push(" OR EXISTS (SELECT 1 FROM url_metadata WHERE url_metadata.url_id = urls.id AND url_metadata.key = ")
+            .push_bind(models::URL_KIND.name)
+            .push(" AND (url_metadata.value #>> '{}') = ")
+            .push_bind(kind.as_str())
+            .push("))");
+    }
+    if let Some(anniversary) = &filter.anniversary {
+        query
+            .push(" AND EXTRACT(MONTH FROM urls.datetime)::int = ")
+            .push_bind(anniversary.month as i32)
+            .push(" AND EXTRACT(DAY FROM urls.datetime)::int = ")
+            .push_bind(anniversary.day as i32)
+            .push(" AND EXTRACT(YEAR FROM urls.datetime)::int = ANY(")
+            .push_bind(anniversary.years.clone())
+            .push(")");
+    }
+    match filter.archived {
+        Some(true) => {
+            query.push(" AND urls.archived_at IS NOT NULL");
+        }
+        Some(false) => {
+            query.push(" AND urls.archived_at IS NULL");
+        }
+        None => {}
+    }
+    if let Some(short_id) = &filter.short_id {
+        query.push(" AND urls.short_id = ").push_bind(short_id.clone());
+    }
+    if let Some(url_hash) = &filter.url_hash {
+        query.push(" AND urls.url_hash = ").push_bind(url_hash.clone());
+    }
+}
+
+/// Append an `ORDER BY` over `urls` for `sort`, breaking ties by id
+fn push_url_sort(query: &mut QueryBuilder<'_, Postgres>, sort: &models::Sort) {
+    let column = match sort.key {
+        models::SortKey::Added => "urls.datetime",
+        models::SortKey::Read => "urls.read_at",
+        // Titled or not, the listing reads as one alphabet rather than splitting on case or scheme
+        models::SortKey::Title => {
+            "LOWER(COALESCE(urls.title, REPLACE(REPLACE(urls.url, 'https://', ''), 'http://', '')))"
+        }
+        // Strip the scheme and `www.` so URLs group by host
+        models::SortKey::Domain => "REPLACE(REPLACE(REPLACE(urls.url, 'https://', ''), 'http://', ''), 'www.', '')",
+        models::SortKey::Url => "urls.url",
+    };
+    let direction = if sort.descending { "DESC" } else { "ASC" };
+    // Pinned URLs stay on top whatever the sort, and unread ones go last when sorting by when they were read
+    query.push(format!(
+        " ORDER BY urls.pinned DESC, {column} {direction} NULLS LAST, urls.id {direction}"
+    ));
+}
+
 /// Fetch all URLs from the database
-pub async fn get_all_urls(db_pool: &PgPool) -> Result<Vec<models::Url>, Error> {
-    let query = r#"
-        SELECT id, datetime, url, url_hash
-        FROM urls
-        ORDER BY datetime DESC
-    "#;
+pub async fn get_all_urls(db_pool: &PgPool, filter: &models::ListFilter) -> Result<Vec<models::Url>, Error> {
+    let mut query = QueryBuilder::<Postgres>::new(format!("SELECT {URL_COLUMNS} FROM urls"));
+    push_list_filter(&mut query, filter, "urls", "url_tags", "url_id");
+    push_url_filter(&mut query, filter);
+    push_url_sort(&mut query, &filter.sort);
 
-    let urls = sqlx::query_as::<_, models::Url>(query).fetch_all(db_pool).await?;
+    query.build_query_as::<models::Url>().fetch_all(db_pool).await
+}
 
-    Ok(urls)
+/// Count, highest id and summed revisions of the URLs matching `filter`, with the newest activity id
+pub async fn get_listing_fingerprint(
+    db_pool: &PgPool,
+    filter: &models::ListFilter,
+) -> Result<models::ListingFingerprint, Error> {
+    let mut query = QueryBuilder::<Postgres>::new(
+        "SELECT COUNT(*) AS count, MAX(urls.id) AS max_id, SUM(urls.revision) AS revisions, \
+         (SELECT MAX(id) FROM activity) AS activity_id FROM urls",
+    );
+    push_list_filter(&mut query, filter, "urls", "url_tags", "url_id");
+    push_url_filter(&mut query, filter);
+
+    query
+        .build_query_as::<models::ListingFingerprint>()
+        .fetch_one(db_pool)
+        .await
 }
 
-/// Fetch all URLs with their associated tags
-/// Fetch all URLs with their associated tags
-pub async fn get_urls_with_tags(db_pool: &PgPool) -> Result<Vec<models::UrlWithTags>, sqlx::Error> {
-    let query = r#"
-        SELECT urls.url, COALESCE(ARRAY_AGG(tags.tag), ARRAY[]::TEXT[]) AS tags
+/// Stream every URL with its tags, oldest first
+pub fn stream_exported_urls(db_pool: &PgPool) -> BoxStream<'_, Result<models::ExportedUrl, Error>> {
+    sqlx::query(
+        r#"
+        SELECT urls.url, urls.url_hash, urls.datetime, urls.read_at, ARRAY_REMOVE(ARRAY_AGG(tags.tag), NULL) AS tags
         FROM urls
         LEFT JOIN url_tags ON urls.id = url_tags.url_id
         LEFT JOIN tags ON url_tags.tag_id = tags.id
-        GROUP BY urls.id, urls.datetime, urls.url
-        ORDER BY urls.datetime DESC
-    "#;
+        GROUP BY urls.id, urls.url, urls.url_hash, urls.datetime, urls.read_at
+        ORDER BY urls.id
+        "#,
+    )
+    .fetch(db_pool)
+    .map_ok(|row| models::ExportedUrl {
+        url: row.get("url"),
+        url_hash: row.get("url_hash"),
+        datetime: row.get("datetime"),
+        read_at: row.get("read_at"),
+        tags: row.get("tags"),
+    })
+    .boxed()
+}
 
-    let rows = sqlx::query(query).fetch_all(db_pool).await?;
+/// Fetch the URLs matching `filter` with their associated tags
+pub async fn get_urls_with_tags(
+    db_pool: &PgPool,
+    filter: &models::ListFilter,
+) -> Result<Vec<models::UrlWithTags>, sqlx::Error> {
+    let mut query = QueryBuilder::<Postgres>::new(
+        r#"
+        SELECT urls.id, urls.url, urls.url_hash, urls.datetime, urls.read_at, urls.pinned, urls.progress, urls.metadata, urls.title, urls.notes, urls.archived_at, urls.snoozed_until, urls.short_id, COALESCE(ARRAY_AGG(tags.tag), ARRAY[]::TEXT[]) AS tags
+        FROM urls
+        LEFT JOIN url_tags ON urls.id = url_tags.url_id
+        LEFT JOIN tags ON url_tags.tag_id = tags.id
+        "#,
+    );
+    push_list_filter(&mut query, filter, "urls", "url_tags", "url_id");
+    push_url_filter(&mut query, filter);
+    query.push(" GROUP BY urls.id, urls.datetime, urls.url, urls.url_hash, urls.read_at, urls.pinned, urls.progress");
+    push_url_sort(&mut query, &filter.sort);
+    if let Some(limit) = filter.limit {
+        query
+            .push(" LIMIT ")
+            .push_bind(limit)
+            .push(" OFFSET ")
+            .push_bind(filter.offset);
+    }
+
+    let rows = query.build().fetch_all(db_pool).await?;
     let mut results = Vec::new();
 
     for row in rows {
         let url: String = row.get("url");
         let tags: Vec<String> = row.try_get("tags").unwrap_or_default(); // Ensure tags is never null
-        let display_url = url.split('?').next().unwrap_or(url.as_str()).to_string();
-        results.push(models::UrlWithTags { url, tags, display_url });
+        let display_url = display::display_url(&url);
+        let datetime = row.get("datetime");
+        let read_at = row.get("read_at");
+        let pinned = row.get("pinned");
+        let progress = row.get("progress");
+        let short_id = row.get("short_id");
+        let metadata = models::UrlMetadata::from_column(row.get("metadata"));
+        let title: Option<String> = row.get("title");
+        let display_title = models::display_title(title.as_deref(), metadata.as_ref(), &url);
+
+        results.push(models::UrlWithTags {
+            id: row.get("id"),
+            url_hash: row.get("url_hash"),
+            url,
+            datetime,
+            tags,
+            display_url,
+            display_title,
+            read_at,
+            short_id,
+            pinned,
+            progress,
+            metadata,
+            title,
+            notes: row.get("notes"),
+            archived_at: row.get("archived_at"),
+            snoozed_until: row.get("snoozed_until"),
+        });
     }
 
     Ok(results)
 }
 
 /// Fetch all snippets with their associated tags
-pub async fn get_snippets_with_tags(db_pool: &PgPool) -> Result<Vec<models::SnippetWithTags>, Error> {
-    let query = r#"
-        SELECT id, snippet, url, COALESCE(tags, ARRAY[]::TEXT[]) AS tags
-        FROM snippets
-        ORDER BY id DESC
-    "#;
+pub async fn get_snippets_with_tags(
+    db_pool: &PgPool,
+    filter: &models::ListFilter,
+) -> Result<Vec<models::SnippetWithTags>, Error> {
+    let mut query = QueryBuilder::<Postgres>::new(format!("SELECT {SNIPPET_COLUMNS} FROM snippets"));
+    push_list_filter(&mut query, filter, "snippets", "snippet_tags", "snippet_id");
+    if let Some(url) = &filter.url {
+        query.push(" AND snippets.url = ").push_bind(url.clone());
+    }
+    query.push(" ORDER BY pinned DESC, id DESC");
+    if let Some(limit) = filter.limit {
+        query
+            .push(" LIMIT ")
+            .push_bind(limit)
+            .push(" OFFSET ")
+            .push_bind(filter.offset);
+    }
 
-    let rows = sqlx::query(query).fetch_all(db_pool).await?;
-    let mut results = Vec::new();
+    let rows = query.build().fetch_all(db_pool).await?;
+    Ok(rows.into_iter().map(snippet_from_row).collect())
+}
 
-    for row in rows {
-        let id: i32 = row.get("id");
-        let snippet: String = row.get("snippet");
-        let url: String = row.get("url");
-        let tags: Vec<String> = row.try_get("tags").unwrap_or_default();
-        results.push(models::SnippetWithTags { id, snippet, url, tags });
+/// Columns selected into `models::SnippetWithTags`, including the content type of the snippet's image if it has one
+const SNIPPET_COLUMNS: &str =
+    "id, snippet, snippet_html, url, COALESCE(tags, ARRAY[]::TEXT[]) AS tags, pinned, source, \
+    (SELECT content_type FROM snippet_images WHERE snippet_images.snippet_id = snippets.id) AS image_type";
+
+/// A `SELECT {SNIPPET_COLUMNS} FROM snippets` row
+fn snippet_from_row(row: PgRow) -> models::SnippetWithTags {
+    models::SnippetWithTags {
+        id: row.get("id"),
+        snippet: row.get("snippet"),
+        snippet_html: row.get("snippet_html"),
+        url: row.get("url"),
+        tags: row.try_get("tags").unwrap_or_default(),
+        pinned: row.get("pinned"),
+        source: models::SnippetSource::from_column(row.get("source")),
+        image_type: row.get("image_type"),
     }
+}
 
-    Ok(results)
+/// Snippets saved from one URL, pinned first and then in the order they were taken
+pub async fn get_snippets_for_url(db_pool: &PgPool, url: &str) -> Result<Vec<models::SnippetWithTags>, Error> {
+    let rows = sqlx::query(&format!(
+        "SELECT {SNIPPET_COLUMNS} FROM snippets WHERE url = $1 ORDER BY pinned DESC, id"
+    ))
+    .bind(url)
+    .fetch_all(db_pool)
+    .await?;
+
+    Ok(rows.into_iter().map(snippet_from_row).collect())
 }
 
-pub async fn get_tags_with_urls_and_snippets(db_pool: &PgPool) -> Result<Vec<models::TagWithUrlsAndSnippets>, Error> {
-    let query = r#"
-        WITH all_tags AS (
+/// Snippets saved before `cutoff` or at an unknown time, oldest first
+pub async fn get_snippets_saved_before(
+    db_pool: &PgPool,
+    cutoff: chrono::NaiveDateTime,
+) -> Result<Vec<models::SnippetWithTags>, Error> {
+    let rows = sqlx::query(&format!(
+        "SELECT {SNIPPET_COLUMNS} FROM snippets WHERE saved_at IS NULL OR saved_at < $1 ORDER BY id"
+    ))
+    .bind(cutoff)
+    .fetch_all(db_pool)
+    .await?;
+
+    Ok(rows.into_iter().map(snippet_from_row).collect())
+}
+
+/// Fetch one snippet by id
+pub async fn get_snippet(db_pool: &PgPool, snippet_id: i32) -> Result<Option<models::SnippetWithTags>, Error> {
+    let row = sqlx::query(&format!("SELECT {SNIPPET_COLUMNS} FROM snippets WHERE id = $1"))
+        .bind(snippet_id)
+        .fetch_optional(db_pool)
+        .await?;
+
+    Ok(row.map(snippet_from_row))
+}
+
+pub async fn get_tags_with_urls_and_snippets(db_pool: &PgPool) -> Result<Vec<models::TagWithUrlsAndSnippets>, Error> {
+    let query = r#"
+        WITH all_tags AS (
             SELECT 
                 tags.tag,
                 COALESCE(ARRAY_AGG(DISTINCT urls.url), ARRAY[]::TEXT[]) AS urls,
@@ -431,15 +1927,1130 @@ pub async fn get_tags_with_urls_and_snippets(db_pool: &PgPool) -> Result<Vec<mod
         let urls: Vec<String> = row.try_get("urls").unwrap_or_default();
         let snippet_ids: Vec<i32> = row.try_get("snippet_ids").unwrap_or_default();
 
-        let snippets = sqlx::query_as::<_, models::SnippetWithTags>(
-            "SELECT id, snippet, url, COALESCE(tags, ARRAY[]::TEXT[]) AS tags FROM snippets WHERE id = ANY($1)",
+        let snippets = sqlx::query(&format!("SELECT {SNIPPET_COLUMNS} FROM snippets WHERE id = ANY($1)"))
+            .bind(&snippet_ids)
+            .fetch_all(db_pool)
+            .await?
+            .into_iter()
+            .map(snippet_from_row)
+            .collect();
+
+        results.push(models::TagWithUrlsAndSnippets { tag, urls, snippets });
+    }
+
+    Ok(results)
+}
+
+/// Fetch how many URLs and snippets carry each tag, alphabetically
+pub async fn get_tag_counts(db_pool: &PgPool) -> Result<Vec<models::TagCount>, Error> {
+    let query = r#"
+        SELECT tags.tag, COALESCE(tag_counts.url_count, 0) AS urls, COALESCE(tag_counts.snippet_count, 0) AS snippets
+        FROM tags
+        LEFT JOIN tag_counts ON tag_counts.tag_id = tags.id
+        ORDER BY tags.tag
+    "#;
+
+    sqlx::query_as::<_, models::TagCount>(query).fetch_all(db_pool).await
+}
+
+/// Fetch every tag name, alphabetically
+pub async fn get_all_tags(db_pool: &PgPool) -> Result<Vec<String>, Error> {
+    sqlx::query_scalar("SELECT tag FROM tags ORDER BY tag")
+        .fetch_all(db_pool)
+        .await
+}
+
+/// Store how far through a URL the reader got; fails with `RowNotFound` if the URL isn't saved
+pub async fn set_url_progress(db_pool: &PgPool, url: &str, progress: i32) -> Result<(), Error> {
+    let result = sqlx::query("UPDATE urls SET progress = $1 WHERE url_hash = $2")
+        .bind(progress)
+        .bind(calculate_url_hash(url))
+        .execute(db_pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(Error::RowNotFound);
+    }
+    Ok(())
+}
+
+/// Store what was looked up about a URL; fails with `RowNotFound` if the URL isn't saved
+pub async fn set_url_metadata(db_pool: &PgPool, url: &str, metadata: &models::UrlMetadata) -> Result<(), Error> {
+    let json = serde_json::to_string(metadata).map_err(|err| Error::Encode(Box::new(err)))?;
+    let result = sqlx::query("UPDATE urls SET metadata = $1 WHERE url_hash = $2")
+        .bind(json)
+        .bind(calculate_url_hash(url))
+        .execute(db_pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(Error::RowNotFound);
+    }
+    Ok(())
+}
+
+/// Pin or unpin a URL; fails with `RowNotFound` if the URL isn't saved
+pub async fn set_url_pinned(db_pool: &PgPool, url: &str, pinned: bool) -> Result<(), Error> {
+    let result = sqlx::query("UPDATE urls SET pinned = $1 WHERE url_hash = $2")
+        .bind(pinned)
+        .bind(calculate_url_hash(url))
+        .execute(db_pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(Error::RowNotFound);
+    }
+    Ok(())
+}
+
+/// Pin or unpin a snippet; fails with `RowNotFound` if there's no such snippet
+pub async fn set_snippet_pinned(db_pool: &PgPool, snippet_id: i32, pinned: bool) -> Result<(), Error> {
+    let result = sqlx::query("UPDATE snippets SET pinned = $1 WHERE id = $2")
+        .bind(pinned)
+        .bind(snippet_id)
+        .execute(db_pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(Error::RowNotFound);
+    }
+    Ok(())
+}
+
+/// Fetch every tag with its color and description, alphabetically
+pub async fn get_tag_details(db_pool: &PgPool) -> Result<Vec<models::TagDetails>, Error> {
+    sqlx::query_as::<_, models::TagDetails>("SELECT tag, color, description FROM tags ORDER BY tag")
+        .fetch_all(db_pool)
+        .await
+}
+
+/// Fetch one tag with its color and description
+pub async fn get_tag(db_pool: &PgPool, tag: &str) -> Result<Option<models::TagDetails>, Error> {
+    sqlx::query_as::<_, models::TagDetails>("SELECT tag, color, description FROM tags WHERE tag = $1")
+        .bind(tag)
+        .fetch_optional(db_pool)
+        .await
+}
+
+/// Set or clear a tag's color and description; fails with `RowNotFound` if the tag doesn't exist
+pub async fn set_tag_details(
+    db_pool: &PgPool,
+    tag: &str,
+    color: Option<&str>,
+    description: Option<&str>,
+) -> Result<(), Error> {
+    let result = sqlx::query("UPDATE tags SET color = $1, description = $2 WHERE tag = $3")
+        .bind(color)
+        .bind(description)
+        .bind(tag)
+        .execute(db_pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(Error::RowNotFound);
+    }
+    Ok(())
+}
+
+/// Create or update tags from another instance's taxonomy; details left out keep what an existing tag has
+pub async fn import_tags(db_pool: &PgPool, tags: &[models::TagDetails]) -> Result<models::TagImport, Error> {
+    let mut tx = db_pool.begin().await?;
+    let mut imported = models::TagImport::default();
+
+    for details in tags {
+        let exists: bool = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM tags WHERE tag = $1)")
+            .bind(&details.tag)
+            .fetch_one(&mut *tx)
+            .await?;
+        sqlx::query(
+            "INSERT INTO tags (tag, color, description) VALUES ($1, $2, $3) \
+             ON CONFLICT (tag) DO UPDATE SET color = COALESCE(excluded.color, tags.color), \
+             description = COALESCE(excluded.description, tags.description)",
         )
-        .bind(&snippet_ids)
+        .bind(&details.tag)
+        .bind(&details.color)
+        .bind(&details.description)
+        .execute(&mut *tx)
+        .await?;
+        if exists {
+            imported.updated += 1;
+        } else {
+            imported.created += 1;
+        }
+    }
+
+    tx.commit().await?;
+    Ok(imported)
+}
+
+/// Store a new copy of a URL's page unless it matches the latest one, keeping only the newest `keep` copies.
+/// Returns the new snapshot's id, or `None` when the page hasn't changed
+pub async fn insert_snapshot(
+    db_pool: &PgPool,
+    url_id: i32,
+    content_type: Option<&str>,
+    content: &str,
+    truncated: bool,
+    keep: i64,
+) -> Result<Option<i32>, Error> {
+    let mut tx = db_pool.begin().await?;
+
+    let latest: Option<String> =
+        sqlx::query_scalar("SELECT content FROM url_snapshots WHERE url_id = $1 ORDER BY id DESC LIMIT 1")
+            .bind(url_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+    if latest.as_deref() == Some(content) {
+        return Ok(None);
+    }
+
+    let id: i32 = sqlx::query_scalar(
+        "INSERT INTO url_snapshots (url_id, content_type, content, truncated) VALUES ($1, $2, $3, $4) RETURNING id",
+    )
+    .bind(url_id)
+    .bind(content_type)
+    .bind(content)
+    .bind(truncated)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    sqlx::query(
+        "DELETE FROM url_snapshots WHERE url_id = $1 AND id NOT IN \
+         (SELECT id FROM url_snapshots WHERE url_id = $2 ORDER BY id DESC LIMIT $3)",
+    )
+    .bind(url_id)
+    .bind(url_id)
+    .bind(keep)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(Some(id))
+}
+
+/// A URL's snapshots without their content, newest first
+pub async fn get_snapshots(db_pool: &PgPool, url_id: i32) -> Result<Vec<models::Snapshot>, Error> {
+    sqlx::query_as::<_, models::Snapshot>(
+        "SELECT id, datetime, content_type, OCTET_LENGTH(content)::BIGINT AS size, truncated FROM url_snapshots \
+         WHERE url_id = $1 ORDER BY id DESC",
+    )
+    .bind(url_id)
+    .fetch_all(db_pool)
+    .await
+}
+
+/// One snapshot with its content, if it belongs to `url_id`; marks it as just viewed
+pub async fn get_snapshot(db_pool: &PgPool, url_id: i32, id: i32) -> Result<Option<models::SnapshotContent>, Error> {
+    sqlx::query_as::<_, models::SnapshotContent>(
+        // Reading a snapshot counts as using it, so the storage cap drops unread ones first
+        "UPDATE url_snapshots SET accessed_at = CURRENT_TIMESTAMP WHERE url_id = $1 AND id = $2 RETURNING datetime, content",
+    )
+    .bind(url_id)
+    .bind(id)
+    .fetch_optional(db_pool)
+    .await
+}
+
+/// When a snapshot of `url_id` was taken, if it exists; marks it as just viewed, like `get_snapshot`
+pub async fn touch_snapshot(db_pool: &PgPool, url_id: i32, id: i32) -> Result<Option<chrono::NaiveDateTime>, Error> {
+    sqlx::query_scalar(
+        "UPDATE url_snapshots SET accessed_at = CURRENT_TIMESTAMP WHERE url_id = $1 AND id = $2 RETURNING datetime",
+    )
+    .bind(url_id)
+    .bind(id)
+    .fetch_optional(db_pool)
+    .await
+}
+
+/// URLs with at least one snapshot, which the scheduled refresh keeps up to date
+pub async fn get_snapshotted_urls(db_pool: &PgPool) -> Result<Vec<models::Url>, Error> {
+    sqlx::query_as::<_, models::Url>(&format!(
+        "SELECT {URL_COLUMNS} FROM urls WHERE id IN (SELECT url_id FROM url_snapshots) ORDER BY id"
+    ))
+    .fetch_all(db_pool)
+    .await
+}
+
+/// Decode a metadata value as stored; the column only ever holds JSON written by `set_url_metadata_entry`
+fn metadata_value(text: &str) -> Result<serde_json::Value, Error> {
+    serde_json::from_str(text).map_err(|err| Error::Decode(Box::new(err)))
+}
+
+/// All metadata entries for a URL by its id, by name
+pub async fn get_url_metadata_entries(
+    db_pool: &PgPool,
+    url_id: i32,
+) -> Result<BTreeMap<String, serde_json::Value>, Error> {
+    let rows: Vec<(String, String)> = sqlx::query_as("SELECT key, value::text FROM url_metadata WHERE url_id = $1")
+        .bind(url_id)
         .fetch_all(db_pool)
         .await?;
+    rows.into_iter()
+        .map(|(key, value)| Ok((key, metadata_value(&value)?)))
+        .collect()
+}
 
-        results.push(models::TagWithUrlsAndSnippets { tag, urls, snippets });
+pub async fn get_url_metadata_entry(
+    db_pool: &PgPool,
+    url_id: i32,
+    key: &str,
+) -> Result<Option<serde_json::Value>, Error> {
+    let value: Option<String> =
+        sqlx::query_scalar("SELECT value::text FROM url_metadata WHERE url_id = $1 AND key = $2")
+            .bind(url_id)
+            .bind(key)
+            .fetch_optional(db_pool)
+            .await?;
+    value.as_deref().map(metadata_value).transpose()
+}
+
+/// Store or replace a metadata entry; fails with `RowNotFound` if the URL isn't saved
+pub async fn set_url_metadata_entry(
+    db_pool: &PgPool,
+    url_id: i32,
+    key: &str,
+    value: &serde_json::Value,
+) -> Result<(), Error> {
+    let result = sqlx::query(
+        "INSERT INTO url_metadata (url_id, key, value) SELECT $1, $2, $3::jsonb WHERE EXISTS (SELECT 1 FROM urls WHERE id = $1) \
+         ON CONFLICT (url_id, key) DO UPDATE SET value = excluded.value, updated_at = NOW()",
+    )
+    .bind(url_id)
+    .bind(key)
+    .bind(value.to_string())
+    .execute(db_pool)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(Error::RowNotFound);
     }
+    Ok(())
+}
 
-    Ok(results)
+/// Archive unread, unpinned URLs saved before `cutoff`, leaving them unread
+pub async fn archive_unread_before(
+    db_pool: &PgPool,
+    cutoff: chrono::NaiveDateTime,
+    dry_run: bool,
+) -> Result<Vec<String>, Error> {
+    let condition = "read_at IS NULL AND archived_at IS NULL AND NOT pinned AND datetime < $1";
+    let mut tx = db_pool.begin().await?;
+
+    let urls: Vec<String> = sqlx::query_scalar(&format!("SELECT url FROM urls WHERE {condition} ORDER BY id"))
+        .bind(cutoff)
+        .fetch_all(&mut *tx)
+        .await?;
+    if !dry_run && !urls.is_empty() {
+        sqlx::query(&format!(
+            "UPDATE urls SET archived_at = CURRENT_TIMESTAMP WHERE {condition}"
+        ))
+        .bind(cutoff)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+    Ok(urls)
+}
+
+/// Archive unpinned URLs read before `cutoff` that aren't archived yet
+pub async fn archive_read_before(
+    db_pool: &PgPool,
+    cutoff: chrono::NaiveDateTime,
+    dry_run: bool,
+) -> Result<Vec<String>, Error> {
+    let condition = "read_at < $1 AND archived_at IS NULL AND NOT pinned";
+    let mut tx = db_pool.begin().await?;
+
+    let urls: Vec<String> = sqlx::query_scalar(&format!("SELECT url FROM urls WHERE {condition} ORDER BY id"))
+        .bind(cutoff)
+        .fetch_all(&mut *tx)
+        .await?;
+    if !dry_run && !urls.is_empty() {
+        sqlx::query(&format!(
+            "UPDATE urls SET archived_at = CURRENT_TIMESTAMP WHERE {condition}"
+        ))
+        .bind(cutoff)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+    Ok(urls)
+}
+
+/// Delete unpinned URLs read before `cutoff`, along with tags nothing else uses
+pub async fn delete_read_before(
+    db_pool: &PgPool,
+    cutoff: chrono::NaiveDateTime,
+    dry_run: bool,
+) -> Result<Vec<String>, Error> {
+    let condition = "read_at < $1 AND NOT pinned";
+    let mut tx = db_pool.begin().await?;
+
+    let urls: Vec<String> = sqlx::query_scalar(&format!("SELECT url FROM urls WHERE {condition} ORDER BY id"))
+        .bind(cutoff)
+        .fetch_all(&mut *tx)
+        .await?;
+    if !dry_run && !urls.is_empty() {
+        let tag_ids: Vec<i32> = sqlx::query_scalar(&format!(
+            "SELECT DISTINCT tag_id FROM url_tags WHERE url_id IN (SELECT id FROM urls WHERE {condition})"
+        ))
+        .bind(cutoff)
+        .fetch_all(&mut *tx)
+        .await?;
+        sqlx::query(&format!("DELETE FROM urls WHERE {condition}"))
+            .bind(cutoff)
+            .execute(&mut *tx)
+            .await?;
+        remove_unused_tags_in(&mut tx, &tag_ids).await?;
+    }
+
+    tx.commit().await?;
+    Ok(urls)
+}
+
+/// Drop the least recently viewed snapshots, counting a new snapshot as viewed when taken, until the rest fit
+pub async fn evict_snapshots(
+    db_pool: &PgPool,
+    max_bytes: i64,
+    dry_run: bool,
+) -> Result<Vec<models::EvictedSnapshot>, Error> {
+    let mut tx = db_pool.begin().await?;
+
+    let snapshots = sqlx::query_as::<_, models::EvictedSnapshot>(
+        "SELECT s.id, u.url, OCTET_LENGTH(s.content)::BIGINT AS size FROM url_snapshots s JOIN urls u ON u.id = s.url_id \
+         ORDER BY COALESCE(s.accessed_at, s.datetime) DESC, s.id DESC",
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let mut total = 0;
+    let evicted: Vec<models::EvictedSnapshot> = snapshots
+        .into_iter()
+        .filter(|snapshot| {
+            total += snapshot.size;
+            total > max_bytes
+        })
+        .collect();
+
+    if !dry_run && !evicted.is_empty() {
+        let mut query = QueryBuilder::<Postgres>::new("DELETE FROM url_snapshots WHERE id IN (");
+        let mut ids = query.separated(", ");
+        for snapshot in &evicted {
+            ids.push_bind(snapshot.id);
+        }
+        query.push(")").build().execute(&mut *tx).await?;
+    }
+
+    tx.commit().await?;
+    Ok(evicted)
+}
+
+/// The saved URL `url` is an alias of, if any
+pub async fn get_aliased_url(db_pool: &PgPool, url: &str) -> Result<Option<String>, Error> {
+    sqlx::query_scalar(
+        "SELECT urls.url FROM url_aliases JOIN urls ON urls.id = url_aliases.url_id WHERE url_aliases.alias_hash = $1",
+    )
+    .bind(calculate_url_hash(url))
+    .fetch_optional(db_pool)
+    .await
+}
+
+/// Store a URL under the canonical form its page declares, keeping the old form as an alias.
+///
+/// If the canonical URL is already saved the two rows are merged. Returns `None` when nothing changed.
+pub async fn adopt_canonical_url(
+    db_pool: &PgPool,
+    url_id: i32,
+    canonical: &str,
+) -> Result<Option<models::CleanedUrl>, Error> {
+    rewrite_url(db_pool, url_id, canonical, true).await
+}
+
+/// Store a URL under an address given by hand, such as after its site moved. The old address isn't kept as an
+/// alias, since it may have been saved by mistake.
+///
+/// If the new address is already saved the two rows are merged. Returns `None` when nothing changed.
+pub async fn move_url(db_pool: &PgPool, url_id: i32, url: &str) -> Result<Option<models::CleanedUrl>, Error> {
+    rewrite_url(db_pool, url_id, url, false).await
+}
+
+async fn rewrite_url(
+    db_pool: &PgPool,
+    url_id: i32,
+    target: &str,
+    keep_alias: bool,
+) -> Result<Option<models::CleanedUrl>, Error> {
+    let mut tx = db_pool.begin().await?;
+    let Some((url, pinned)) = sqlx::query_as::<_, (String, bool)>("SELECT url, pinned FROM urls WHERE id = $1")
+        .bind(url_id)
+        .fetch_optional(&mut *tx)
+        .await?
+    else {
+        return Ok(None);
+    };
+    if url == target {
+        return Ok(None);
+    }
+
+    let url_hash = calculate_url_hash(target);
+    let existing = sqlx::query_scalar::<_, i32>("SELECT id FROM urls WHERE url_hash = $1")
+        .bind(&url_hash)
+        .fetch_optional(&mut *tx)
+        .await?;
+    merge_or_rename(&mut tx, url_id, &url, target, &url_hash, pinned, existing).await?;
+
+    if keep_alias {
+        sqlx::query(
+            "INSERT INTO url_aliases (alias_hash, url_id) VALUES ($1, $2) \
+             ON CONFLICT (alias_hash) DO UPDATE SET url_id = excluded.url_id",
+        )
+        .bind(calculate_url_hash(&url))
+        .bind(existing.unwrap_or(url_id))
+        .execute(&mut *tx)
+        .await?;
+    }
+    // The new form is stored directly now, so it must not also resolve through an alias
+    sqlx::query("DELETE FROM url_aliases WHERE alias_hash = $1")
+        .bind(&url_hash)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(Some(models::CleanedUrl {
+        original: url,
+        url: target.to_string(),
+        merged: existing.is_some(),
+    }))
+}
+
+/// Remember the URL a saved one was cleaned from; the first original seen is kept
+pub async fn record_original_url(db_pool: &PgPool, url: &str, original: &str) -> Result<(), Error> {
+    sqlx::query("UPDATE urls SET original_url = $1 WHERE url_hash = $2 AND original_url IS NULL")
+        .bind(original)
+        .bind(calculate_url_hash(url))
+        .execute(db_pool)
+        .await?;
+    Ok(())
+}
+
+/// Normalize URLs saved before `normalize` applied to them, such as before tracking parameters were stripped.
+///
+/// A URL whose clean form is already saved is folded into that row: its tags and snapshots move over, a pin
+/// carries across, and the duplicate is deleted. Snippets follow the URL either way. Cleaned rows get a new short id.
+pub async fn clean_urls(
+    db_pool: &PgPool,
+    normalize: &(dyn Fn(&str) -> String + Sync),
+    dry_run: bool,
+) -> Result<Vec<models::CleanedUrl>, Error> {
+    let mut tx = db_pool.begin().await?;
+    let rows: Vec<(i32, String, bool)> = sqlx::query_as("SELECT id, url, pinned FROM urls ORDER BY id")
+        .fetch_all(&mut *tx)
+        .await?;
+
+    // Clean URLs claimed earlier in this run, so a dry run spots duplicates it hasn't written yet
+    let mut claimed: HashMap<String, i32> = HashMap::new();
+    let mut cleaned = Vec::new();
+
+    for (id, url, pinned) in rows {
+        let stripped = normalize(&url);
+        let url_hash = calculate_url_hash(&stripped);
+        if stripped == url {
+            claimed.insert(url_hash, id);
+            continue;
+        }
+
+        let existing = match claimed.get(&url_hash) {
+            Some(existing) => Some(*existing),
+            None => {
+                sqlx::query_scalar::<_, i32>("SELECT id FROM urls WHERE url_hash = $1")
+                    .bind(&url_hash)
+                    .fetch_optional(&mut *tx)
+                    .await?
+            }
+        };
+
+        if !dry_run {
+            merge_or_rename(&mut tx, id, &url, &stripped, &url_hash, pinned, existing).await?;
+        }
+        claimed.entry(url_hash).or_insert(id);
+        cleaned.push(models::CleanedUrl {
+            original: url,
+            url: stripped,
+            merged: existing.is_some(),
+        });
+    }
+
+    if !dry_run {
+        remove_all_unused_tags_in(&mut tx).await?;
+    }
+    tx.commit().await?;
+    Ok(cleaned)
+}
+
+async fn merge_or_rename(
+    conn: &mut PgConnection,
+    id: i32,
+    url: &str,
+    stripped: &str,
+    url_hash: &str,
+    pinned: bool,
+    existing: Option<i32>,
+) -> Result<(), Error> {
+    match existing {
+        Some(keep) => {
+            sqlx::query("INSERT INTO url_tags (url_id, tag_id) SELECT $1, tag_id FROM url_tags WHERE url_id = $2 ON CONFLICT DO NOTHING")
+                .bind(keep)
+                .bind(id)
+                .execute(&mut *conn)
+                .await?;
+            sqlx::query(
+                "INSERT INTO url_metadata (url_id, key, value, updated_at) \
+                 SELECT $1, key, value, updated_at FROM url_metadata WHERE url_id = $2 ON CONFLICT DO NOTHING",
+            )
+            .bind(keep)
+            .bind(id)
+            .execute(&mut *conn)
+            .await?;
+            for table in ["url_snapshots", "url_aliases"] {
+                sqlx::query(&format!("UPDATE {table} SET url_id = $1 WHERE url_id = $2"))
+                    .bind(keep)
+                    .bind(id)
+                    .execute(&mut *conn)
+                    .await?;
+            }
+            sqlx::query(
+                "UPDATE urls SET pinned = (pinned OR $1), original_url = COALESCE(original_url, $2), \
+                 title = COALESCE(title, (SELECT title FROM urls WHERE id = $4)), \
+                 notes = COALESCE(notes, (SELECT notes FROM urls WHERE id = $4)) WHERE id = $3",
+            )
+            .bind(pinned)
+            .bind(url)
+            .bind(keep)
+            .bind(id)
+            .execute(&mut *conn)
+            .await?;
+            sqlx::query("DELETE FROM urls WHERE id = $1")
+                .bind(id)
+                .execute(&mut *conn)
+                .await?;
+        }
+        None => {
+            sqlx::query(
+                "UPDATE urls SET url = $1, url_hash = $2, original_url = COALESCE(original_url, $3), normalized_url = $4 \
+                 WHERE id = $5",
+            )
+            .bind(stripped)
+            .bind(url_hash)
+            .bind(url)
+            .bind(models::loose_url(stripped))
+            .bind(id)
+            .execute(&mut *conn)
+            .await?;
+        }
+    }
+
+    sqlx::query("UPDATE snippets SET url = $1 WHERE url = $2")
+        .bind(stripped)
+        .bind(url)
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+/// Remove every URL, snippet and tag in a single transaction
+pub async fn delete_all_data(db_pool: &PgPool) -> Result<(), Error> {
+    let mut tx = db_pool.begin().await?;
+
+    for table in [
+        "snippet_tags",
+        "url_tags",
+        "snippet_images",
+        "snippets",
+        "url_snapshots",
+        "url_aliases",
+        "url_metadata",
+        "urls",
+        "tags",
+        "activity",
+        "import_job_items",
+        "import_jobs",
+        "peers",
+    ] {
+        sqlx::query(&format!("DELETE FROM {}", table)).execute(&mut *tx).await?;
+    }
+
+    tx.commit().await?;
+    Ok(())
+}
+
+/// A page of `table`'s rows as stored, for copying to another database
+pub async fn read_rows(
+    db_pool: &PgPool,
+    table: &models::CopiedTable,
+    offset: i64,
+    limit: i64,
+) -> Result<Vec<Vec<models::ColumnValue>>, Error> {
+    // JSON comes out as text, the form SQLite keeps it in
+    let columns: Vec<String> = table
+        .columns
+        .iter()
+        .map(|(name, kind)| match kind {
+            models::ColumnKind::Json => format!("{name}::text"),
+            _ => name.to_string(),
+        })
+        .collect();
+    let query = format!(
+        "SELECT {} FROM {} ORDER BY {} LIMIT $1 OFFSET $2",
+        columns.join(", "),
+        table.name,
+        table.order
+    );
+    let rows = sqlx::query(&query).bind(limit).bind(offset).fetch_all(db_pool).await?;
+
+    rows.iter()
+        .map(|row| {
+            table
+                .columns
+                .iter()
+                .enumerate()
+                .map(|(index, (_, kind))| read_value(row, index, *kind))
+                .collect()
+        })
+        .collect()
+}
+
+fn read_value(row: &PgRow, index: usize, kind: models::ColumnKind) -> Result<models::ColumnValue, Error> {
+    use models::{ColumnKind, ColumnValue};
+    Ok(match kind {
+        ColumnKind::Integer => row
+            .try_get::<Option<i32>, _>(index)?
+            .map(|number| ColumnValue::Integer(number.into())),
+        ColumnKind::Text | ColumnKind::Json => row.try_get::<Option<String>, _>(index)?.map(ColumnValue::Text),
+        ColumnKind::Timestamp => row
+            .try_get::<Option<chrono::NaiveDateTime>, _>(index)?
+            .map(ColumnValue::Timestamp),
+        ColumnKind::Boolean => row.try_get::<Option<bool>, _>(index)?.map(ColumnValue::Boolean),
+        ColumnKind::Bytes => row.try_get::<Option<Vec<u8>>, _>(index)?.map(ColumnValue::Bytes),
+        ColumnKind::TagList => row.try_get::<Option<Vec<String>>, _>(index)?.map(ColumnValue::TagList),
+    }
+    .unwrap_or(ColumnValue::Null))
+}
+
+/// Insert rows read from another database as they are, ids included, in one transaction
+pub async fn write_rows(
+    db_pool: &PgPool,
+    table: &models::CopiedTable,
+    rows: &[Vec<models::ColumnValue>],
+) -> Result<(), Error> {
+    use models::{ColumnKind, ColumnValue};
+    if rows.is_empty() {
+        return Ok(());
+    }
+    // Columns here are INTEGER, so SQLite's wider numbers have to fit
+    let too_large = rows.iter().flatten().any(|value| match value {
+        ColumnValue::Integer(number) => i32::try_from(*number).is_err(),
+        _ => false,
+    });
+    if too_large {
+        return Err(Error::Protocol(format!(
+            "{} has a number too large for Postgres",
+            table.name
+        )));
+    }
+
+    let columns: Vec<&str> = table.columns.iter().map(|(name, _)| *name).collect();
+    let mut query = QueryBuilder::<Postgres>::new(format!("INSERT INTO {} ({}) ", table.name, columns.join(", ")));
+    query.push_values(rows, |mut values, row| {
+        for (value, (_, kind)) in row.iter().zip(table.columns) {
+            match value {
+                ColumnValue::Null => values.push("NULL"),
+                ColumnValue::Integer(number) => values.push_bind(*number as i32),
+                ColumnValue::Text(text) if *kind == ColumnKind::Json => {
+                    values.push_bind(text.clone()).push_unseparated("::jsonb")
+                }
+                ColumnValue::Text(text) => values.push_bind(text.clone()),
+                ColumnValue::Timestamp(datetime) => values.push_bind(*datetime),
+                ColumnValue::Boolean(flag) => values.push_bind(*flag),
+                ColumnValue::Bytes(bytes) => values.push_bind(bytes.clone()),
+                ColumnValue::TagList(tags) => values.push_bind(tags.clone()),
+            };
+        }
+    });
+
+    let mut tx = db_pool.begin().await?;
+    query.build().execute(&mut *tx).await?;
+    if table.serial {
+        sqlx::query(&format!(
+            "SELECT setval(pg_get_serial_sequence('{0}', 'id'), MAX(id)) FROM {0} HAVING MAX(id) IS NOT NULL",
+            table.name
+        ))
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await
+}
+
+/// Record an entry in the activity timeline
+pub async fn record_activity(db_pool: &PgPool, action: &str, subject: &str, detail: Option<&str>) -> Result<(), Error> {
+    record_activity_in(&mut *db_pool.acquire().await?, action, subject, detail).await
+}
+
+async fn record_activity_in(
+    conn: &mut PgConnection,
+    action: &str,
+    subject: &str,
+    detail: Option<&str>,
+) -> Result<(), Error> {
+    sqlx::query("INSERT INTO activity (action, subject, detail) VALUES ($1, $2, $3)")
+        .bind(action)
+        .bind(subject)
+        .bind(detail)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+/// Record the timeline entry for a batch operation
+async fn record_batch_activity_in(conn: &mut PgConnection, operation: &models::BatchOperation) -> Result<(), Error> {
+    match operation {
+        models::BatchOperation::AddUrl { url } => record_activity_in(conn, "save_url", url, Some("batch")).await,
+        models::BatchOperation::TagUrl { url, tags } => record_activity_in(conn, "tag_url", url, Some(tags)).await,
+        models::BatchOperation::DeleteSnippet { id } => {
+            record_activity_in(conn, "delete_snippet", &id.to_string(), Some("batch")).await
+        }
+    }
+}
+
+/// Tags linked to each snippet through `snippet_tags`
+async fn linked_snippet_tags(conn: &mut PgConnection) -> Result<HashMap<i32, BTreeSet<String>>, Error> {
+    let rows: Vec<(i32, String)> = sqlx::query_as(
+        "SELECT snippet_tags.snippet_id, tags.tag FROM snippet_tags JOIN tags ON tags.id = snippet_tags.tag_id",
+    )
+    .fetch_all(conn)
+    .await?;
+    let mut linked: HashMap<i32, BTreeSet<String>> = HashMap::new();
+    for (snippet_id, tag) in rows {
+        linked.entry(snippet_id).or_default().insert(tag);
+    }
+    Ok(linked)
+}
+
+/// Rewrite the `tags` column of snippets that have blanks or repeats in it, and relink their `snippet_tags` rows
+/// to match
+pub async fn repair_snippet_tags(db_pool: &PgPool, dry_run: bool) -> Result<Vec<i32>, Error> {
+    let mut tx = db_pool.begin().await?;
+    let columns: Vec<(i32, Option<Vec<String>>)> = sqlx::query_as("SELECT id, tags FROM snippets ORDER BY id")
+        .fetch_all(&mut *tx)
+        .await?;
+    let linked = linked_snippet_tags(&mut tx).await?;
+
+    let mut repaired = Vec::new();
+    for (snippet_id, column) in columns {
+        let links = linked.get(&snippet_id).cloned().unwrap_or_default();
+        // A column left NULL is rebuilt from the links, the only record left of the tags
+        let (stored, readable) = match column {
+            Some(stored) => (stored, true),
+            None => (links.iter().cloned().collect(), false),
+        };
+        let tags = models::unique_tags(stored.iter().map(String::as_str));
+        let agrees = readable
+            && tags.iter().copied().eq(stored.iter().map(String::as_str))
+            && tags.len() == links.len()
+            && tags.iter().all(|tag| links.contains(*tag));
+        if agrees {
+            continue;
+        }
+        repaired.push(snippet_id);
+        if dry_run {
+            continue;
+        }
+
+        sqlx::query("UPDATE snippets SET tags = $1 WHERE id = $2")
+            .bind(&tags)
+            .bind(snippet_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM snippet_tags WHERE snippet_id = $1")
+            .bind(snippet_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("INSERT INTO tags (tag) SELECT UNNEST($1::text[]) ON CONFLICT (tag) DO NOTHING")
+            .bind(&tags)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(
+            "INSERT INTO snippet_tags (snippet_id, tag_id) SELECT $1, id FROM tags WHERE tag = ANY($2) \
+             ON CONFLICT (snippet_id, tag_id) DO NOTHING",
+        )
+        .bind(snippet_id)
+        .bind(&tags)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    if !repaired.is_empty() && !dry_run {
+        remove_all_unused_tags_in(&mut tx).await?;
+    }
+    tx.commit().await?;
+    Ok(repaired)
+}
+
+/// Fetch the most recent activity, newest first
+pub async fn get_activity(db_pool: &PgPool, limit: i64) -> Result<Vec<models::Activity>, Error> {
+    let query = r#"
+        SELECT id, datetime, action, subject, detail
+        FROM activity
+        ORDER BY id DESC
+        LIMIT $1
+    "#;
+
+    sqlx::query_as::<_, models::Activity>(query)
+        .bind(limit)
+        .fetch_all(db_pool)
+        .await
+}
+
+/// Fetch entries recorded after `after_id`, oldest first, leaving out changes applied from peers
+pub async fn get_local_activity_since(
+    db_pool: &PgPool,
+    after_id: i32,
+    limit: i64,
+) -> Result<Vec<models::Activity>, Error> {
+    let query = r#"
+        SELECT id, datetime, action, subject, detail
+        FROM activity
+        WHERE id > $1 AND origin IS NULL
+        ORDER BY id
+        LIMIT $2
+    "#;
+
+    sqlx::query_as::<_, models::Activity>(query)
+        .bind(after_id)
+        .bind(limit)
+        .fetch_all(db_pool)
+        .await
+}
+
+/// Fetch the latest entry about `subject` with one of `actions`, made here or applied from a peer
+pub async fn get_latest_activity(
+    db_pool: &PgPool,
+    subject: &str,
+    actions: &[&str],
+) -> Result<Option<models::Activity>, Error> {
+    let mut query =
+        QueryBuilder::<Postgres>::new("SELECT id, datetime, action, subject, detail FROM activity WHERE subject = ");
+    query.push_bind(subject).push(" AND action IN (");
+    let mut separated = query.separated(", ");
+    for action in actions {
+        separated.push_bind(*action);
+    }
+    query.push(") ORDER BY datetime DESC, id DESC LIMIT 1");
+
+    query.build_query_as::<models::Activity>().fetch_optional(db_pool).await
+}
+
+/// Fetch every entry with one of `actions`, oldest first, made here or applied from a peer
+pub async fn get_activity_by_actions(db_pool: &PgPool, actions: &[&str]) -> Result<Vec<models::Activity>, Error> {
+    let mut query =
+        QueryBuilder::<Postgres>::new("SELECT id, datetime, action, subject, detail FROM activity WHERE action IN (");
+    let mut separated = query.separated(", ");
+    for action in actions {
+        separated.push_bind(*action);
+    }
+    query.push(") ORDER BY datetime, id");
+
+    query.build_query_as::<models::Activity>().fetch_all(db_pool).await
+}
+
+/// Record a change a peer made, dated when the peer made it
+pub async fn record_peer_activity(db_pool: &PgPool, peer: &str, activity: &models::Activity) -> Result<(), Error> {
+    sqlx::query("INSERT INTO activity (datetime, action, subject, detail, origin) VALUES ($1, $2, $3, $4, $5)")
+        .bind(activity.datetime)
+        .bind(&activity.action)
+        .bind(&activity.subject)
+        .bind(&activity.detail)
+        .bind(peer)
+        .execute(db_pool)
+        .await?;
+    Ok(())
+}
+
+/// The id of the last change applied from `peer`, 0 before the first sync
+pub async fn get_peer_cursor(db_pool: &PgPool, peer: &str) -> Result<i32, Error> {
+    let cursor = sqlx::query_scalar("SELECT last_change_id FROM peers WHERE url = $1")
+        .bind(peer)
+        .fetch_optional(db_pool)
+        .await?;
+    Ok(cursor.unwrap_or(0))
+}
+
+pub async fn set_peer_cursor(db_pool: &PgPool, peer: &str, cursor: i32) -> Result<(), Error> {
+    let query = r#"
+        INSERT INTO peers (url, last_change_id, synced_at)
+        VALUES ($1, $2, NOW())
+        ON CONFLICT (url) DO UPDATE SET last_change_id = excluded.last_change_id, synced_at = excluded.synced_at
+    "#;
+
+    sqlx::query(query).bind(peer).bind(cursor).execute(db_pool).await?;
+    Ok(())
+}
+
+/// Store a new import job and all of its items in one transaction
+pub async fn create_import_job(db_pool: &PgPool, source: &str, items: &[models::ImportItem]) -> Result<i32, Error> {
+    let mut tx = db_pool.begin().await?;
+
+    let job_id: i32 = sqlx::query_scalar("INSERT INTO import_jobs (source, total) VALUES ($1, $2) RETURNING id")
+        .bind(source)
+        .bind(items.len() as i32)
+        .fetch_one(&mut *tx)
+        .await?;
+
+    for item in items {
+        let tags = serde_json::to_string(&item.tags).map_err(|err| Error::Encode(Box::new(err)))?;
+        sqlx::query("INSERT INTO import_job_items (job_id, url, tags, note) VALUES ($1, $2, $3, $4)")
+            .bind(job_id)
+            .bind(&item.url)
+            .bind(tags)
+            .bind(&item.note)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    tx.commit().await?;
+    Ok(job_id)
+}
+
+/// Fetch an import job with its first few errors
+pub async fn get_import_job(db_pool: &PgPool, id: i32) -> Result<Option<models::ImportJob>, Error> {
+    let query = r#"
+        SELECT id, source, status, total, processed, created, skipped, failed, started_at, finished_at
+        FROM import_jobs
+        WHERE id = $1
+    "#;
+    let Some(mut job) = sqlx::query_as::<_, models::ImportJob>(query)
+        .bind(id)
+        .fetch_optional(db_pool)
+        .await?
+    else {
+        return Ok(None);
+    };
+
+    job.errors = sqlx::query_scalar(
+        "SELECT url || ': ' || error FROM import_job_items WHERE job_id = $1 AND error IS NOT NULL ORDER BY id LIMIT $2",
+    )
+    .bind(id)
+    .bind(models::IMPORT_JOB_ERROR_LIMIT)
+    .fetch_all(db_pool)
+    .await?;
+
+    Ok(Some(job))
+}
+
+/// Ids of jobs that haven't finished, e.g. because the server restarted mid-import
+pub async fn get_running_import_jobs(db_pool: &PgPool) -> Result<Vec<i32>, Error> {
+    sqlx::query_scalar("SELECT id FROM import_jobs WHERE status = 'running' ORDER BY id")
+        .fetch_all(db_pool)
+        .await
+}
+
+/// The next unprocessed items of a job, in the order they were submitted
+pub async fn get_pending_import_items(
+    db_pool: &PgPool,
+    job_id: i32,
+    limit: i64,
+) -> Result<Vec<models::PendingImportItem>, Error> {
+    let rows = sqlx::query(
+        "SELECT id, url, tags, note, started FROM import_job_items WHERE job_id = $1 AND outcome IS NULL ORDER BY id LIMIT $2",
+    )
+    .bind(job_id)
+    .bind(limit)
+    .fetch_all(db_pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            let tags: String = row.get("tags");
+            models::PendingImportItem {
+                id: row.get("id"),
+                started: row.get("started"),
+                item: models::ImportItem {
+                    url: row.get("url"),
+                    tags: serde_json::from_str(&tags).unwrap_or_default(),
+                    note: row.get("note"),
+                },
+            }
+        })
+        .collect())
+}
+
+/// Record that an item's URL is about to be saved by its job
+pub async fn start_import_item(db_pool: &PgPool, job_id: i32, item_id: i32) -> Result<(), Error> {
+    sqlx::query("UPDATE import_job_items SET started = TRUE WHERE id = $1 AND job_id = $2")
+        .bind(item_id)
+        .bind(job_id)
+        .execute(db_pool)
+        .await?;
+    Ok(())
+}
+
+/// Mark an item processed and count it against its job, in one transaction
+pub async fn finish_import_item(
+    db_pool: &PgPool,
+    job_id: i32,
+    item_id: i32,
+    outcome: &models::ImportOutcome,
+) -> Result<(), Error> {
+    let (outcome, error) = match outcome {
+        models::ImportOutcome::Created => ("created", None),
+        models::ImportOutcome::Skipped => ("skipped", None),
+        models::ImportOutcome::Failed(error) => ("failed", Some(error.as_str())),
+    };
+    let mut tx = db_pool.begin().await?;
+
+    let updated = sqlx::query(
+        "UPDATE import_job_items SET outcome = $1, error = $2 WHERE id = $3 AND job_id = $4 AND outcome IS NULL",
+    )
+    .bind(outcome)
+    .bind(error)
+    .bind(item_id)
+    .bind(job_id)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+
+    // `outcome` is one of the three counter columns
+    if updated > 0 {
+        sqlx::query(&format!(
+            "UPDATE import_jobs SET processed = processed + 1, {outcome} = {outcome} + 1 WHERE id = $1"
+        ))
+        .bind(job_id)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await
+}
+
+/// Mark a job completed and drop its successful items; failed ones stay so their errors can be reported, without
+/// the notes they came with
+pub async fn complete_import_job(db_pool: &PgPool, id: i32) -> Result<(), Error> {
+    let mut tx = db_pool.begin().await?;
+
+    sqlx::query("UPDATE import_jobs SET status = 'completed', finished_at = NOW() WHERE id = $1")
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("DELETE FROM import_job_items WHERE job_id = $1 AND error IS NULL")
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("UPDATE import_job_items SET note = NULL WHERE job_id = $1")
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await
 }
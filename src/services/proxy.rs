@@ -0,0 +1,132 @@
+use crate::config::Config;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, FORWARDED};
+use actix_web::middleware::Next;
+use actix_web::web;
+use std::fmt;
+use std::net::IpAddr;
+
+/// Headers a reverse proxy uses to describe the original client request
+const FORWARDED_HEADERS: [&str; 4] = ["x-forwarded-for", "x-forwarded-proto", "x-forwarded-host", "x-real-ip"];
+
+/// Proxies whose `X-Forwarded-*` headers are believed, as IPs or CIDR ranges
+#[derive(Clone, Debug, Default)]
+pub struct TrustedProxies {
+    networks: Vec<(IpAddr, u8)>,
+}
+
+impl TrustedProxies {
+    /// Parse a comma-separated list such as `127.0.0.1, 172.16.0.0/12, ::1`
+    pub fn parse(list: &str) -> Result<Self, String> {
+        let mut networks = Vec::new();
+
+        for entry in list.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+            let (address, prefix) = match entry.split_once('/') {
+                Some((address, prefix)) => (address, Some(prefix)),
+                None => (entry, None),
+            };
+            let address: IpAddr = address
+                .parse()
+                .map_err(|_| format!("invalid trusted proxy address: {}", entry))?;
+            let max_prefix = if address.is_ipv4() { 32 } else { 128 };
+            let prefix = match prefix {
+                Some(prefix) => prefix
+                    .parse::<u8>()
+                    .ok()
+                    .filter(|prefix| *prefix <= max_prefix)
+                    .ok_or_else(|| format!("invalid trusted proxy prefix: {}", entry))?,
+                None => max_prefix,
+            };
+            networks.push((address, prefix));
+        }
+
+        Ok(Self { networks })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.networks.is_empty()
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        self.networks.iter().any(|(network, prefix)| match (network, ip) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                prefix_matches(u32::from(*network) as u128, u32::from(ip) as u128, *prefix, 32)
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => prefix_matches(u128::from(*network), u128::from(ip), *prefix, 128),
+            _ => false,
+        })
+    }
+}
+
+impl fmt::Display for TrustedProxies {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let entries: Vec<String> = self
+            .networks
+            .iter()
+            .map(|(network, prefix)| format!("{}/{}", network, prefix))
+            .collect();
+        write!(f, "{}", entries.join(", "))
+    }
+}
+
+fn prefix_matches(network: u128, ip: u128, prefix: u8, bits: u32) -> bool {
+    if prefix == 0 {
+        return true;
+    }
+    let shift = bits - prefix as u32;
+    (network >> shift) == (ip >> shift)
+}
+
+/// Drop forwarding headers unless the request came from a trusted proxy.
+///
+/// Actix's `ConnectionInfo` (used for logging and absolute URLs) believes these headers unconditionally, so
+/// they are removed from requests that did not pass through a configured proxy.
+pub async fn strip_untrusted_forwarded_headers(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let trusted = match (req.app_data::<web::Data<Config>>(), req.peer_addr()) {
+        (Some(config), Some(peer)) => config.trusted_proxies.contains(peer.ip()),
+        _ => false,
+    };
+
+    if !trusted {
+        let headers = req.headers_mut();
+        headers.remove(FORWARDED);
+        for name in FORWARDED_HEADERS {
+            headers.remove(HeaderName::from_static(name));
+        }
+    }
+
+    next.call(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exact_addresses() {
+        let proxies = TrustedProxies::parse("127.0.0.1, ::1").unwrap();
+        assert!(proxies.contains("127.0.0.1".parse().unwrap()));
+        assert!(proxies.contains("::1".parse().unwrap()));
+        assert!(!proxies.contains("127.0.0.2".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_cidr_ranges() {
+        let proxies = TrustedProxies::parse("172.16.0.0/12,fd00::/8").unwrap();
+        assert!(proxies.contains("172.18.0.5".parse().unwrap()));
+        assert!(!proxies.contains("172.32.0.1".parse().unwrap()));
+        assert!(proxies.contains("fd12::1".parse().unwrap()));
+        assert!(!proxies.contains("10.0.0.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_empty_and_invalid() {
+        assert!(TrustedProxies::parse("").unwrap().is_empty());
+        assert!(TrustedProxies::parse("not-an-ip").is_err());
+        assert!(TrustedProxies::parse("10.0.0.0/33").is_err());
+    }
+}
@@ -0,0 +1,74 @@
+use crate::config::Config;
+use actix_web::{get, web, HttpResponse, Responder};
+use serde_json::json;
+use tera::{Context, Tera};
+
+const ICON_192: &[u8] = include_bytes!("../../static/icons/icon-192.png");
+const ICON_512: &[u8] = include_bytes!("../../static/icons/icon-512.png");
+
+/// Web app manifest, including the share target used by the mobile share sheet
+#[get("/manifest.webmanifest")]
+async fn manifest(config: web::Data<Config>) -> impl Responder {
+    let base_path = &config.base_path;
+
+    let manifest = json!({
+        "name": "Read it Later",
+        "short_name": "Read Later",
+        "start_url": format!("{}/", base_path),
+        "scope": format!("{}/", base_path),
+        "display": "standalone",
+        "background_color": "#1a202c",
+        "theme_color": "#1a202c",
+        "icons": [
+            { "src": format!("{}/icons/icon-192.png", base_path), "sizes": "192x192", "type": "image/png" },
+            { "src": format!("{}/icons/icon-512.png", base_path), "sizes": "512x512", "type": "image/png" },
+        ],
+        "share_target": {
+            "action": format!("{}/share-target", base_path),
+            "method": "POST",
+            "enctype": "application/x-www-form-urlencoded",
+            "params": { "title": "title", "text": "text", "url": "url" },
+        },
+    });
+
+    HttpResponse::Ok()
+        .content_type("application/manifest+json")
+        .body(manifest.to_string())
+}
+
+#[get("/service-worker.js")]
+async fn service_worker(tmpl: web::Data<Tera>, config: web::Data<Config>) -> impl Responder {
+    let mut context = Context::new();
+    context.insert("base_path", &config.base_path);
+    context.insert("version", env!("CARGO_PKG_VERSION"));
+
+    match tmpl.render("service-worker.js", &context) {
+        Ok(rendered) => HttpResponse::Ok()
+            .content_type("application/javascript")
+            // Browsers must always revalidate the worker so updates roll out
+            .insert_header(("Cache-Control", "no-cache"))
+            .body(rendered),
+        Err(e) => {
+            eprintln!("Template error: {:?}", e);
+            HttpResponse::InternalServerError().body("Template error")
+        }
+    }
+}
+
+#[get("/icons/{name}")]
+async fn icon(name: web::Path<String>) -> impl Responder {
+    let bytes = match name.as_str() {
+        "icon-192.png" => ICON_192,
+        "icon-512.png" => ICON_512,
+        _ => return HttpResponse::NotFound().finish(),
+    };
+
+    HttpResponse::Ok()
+        .content_type("image/png")
+        .insert_header(("Cache-Control", "public, max-age=604800"))
+        .body(bytes)
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(manifest).service(service_worker).service(icon);
+}
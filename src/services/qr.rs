@@ -0,0 +1,58 @@
+use qrcode::{Color, QrCode};
+
+/// Pixels per QR module
+const SCALE: usize = 8;
+/// Blank modules around the code, as the spec asks for
+const QUIET_ZONE: usize = 4;
+
+/// Encode `data` as a black-on-white grayscale PNG QR code
+pub fn png(data: &str) -> Result<Vec<u8>, String> {
+    let code = QrCode::new(data.as_bytes()).map_err(|err| format!("Failed to encode QR code: {}", err))?;
+    let modules = code.width();
+    let colors = code.to_colors();
+    let size = (modules + 2 * QUIET_ZONE) * SCALE;
+
+    let mut pixels = vec![0xFF_u8; size * size];
+    for (index, color) in colors.iter().enumerate() {
+        if *color != Color::Dark {
+            continue;
+        }
+        let (column, row) = (index % modules + QUIET_ZONE, index / modules + QUIET_ZONE);
+        for y in row * SCALE..(row + 1) * SCALE {
+            pixels[y * size + column * SCALE..y * size + (column + 1) * SCALE].fill(0x00);
+        }
+    }
+
+    let mut encoded = Vec::new();
+    let mut encoder = png::Encoder::new(&mut encoded, size as u32, size as u32);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(&pixels))
+        .map_err(|err| format!("Failed to write QR code PNG: {}", err))?;
+
+    Ok(encoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_png_is_square_with_quiet_zone() {
+        let encoded = png("https://example.com/some/article").unwrap();
+
+        let decoder = png::Decoder::new(std::io::Cursor::new(encoded));
+        let mut reader = decoder.read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size().unwrap()];
+        let frame = reader.next_frame(&mut pixels).unwrap();
+
+        assert_eq!(frame.width, frame.height);
+        assert_eq!(frame.width as usize % SCALE, 0);
+        // The top-left corner sits in the quiet zone, the finder pattern starts just inside it
+        assert_eq!(pixels[0], 0xFF);
+        let inside = QUIET_ZONE * SCALE;
+        assert_eq!(pixels[inside * frame.width as usize + inside], 0x00);
+    }
+}
@@ -0,0 +1,124 @@
+use crate::config::{self, Config};
+use crate::services::{api, models};
+use actix_web::{get, post, web, HttpResponse, Responder};
+use serde::Serialize;
+use std::sync::Arc;
+
+/// What one rule changed, or with `dry_run` would change
+#[derive(Serialize, Debug)]
+pub struct RuleReport {
+    pub rule: &'static str,
+    pub dry_run: bool,
+    /// The URLs affected; for the snapshot rule, the URL of each dropped snapshot
+    pub items: Vec<String>,
+    /// Bytes freed by the snapshot rule
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<i64>,
+}
+
+impl RuleReport {
+    fn summary(&self) -> String {
+        match self.bytes {
+            Some(bytes) => format!("{}: {} snapshots, {} bytes", self.rule, self.items.len(), bytes),
+            None => format!("{}: {} URLs", self.rule, self.items.len()),
+        }
+    }
+}
+
+/// Apply every configured rule in turn, recording each one that changed something in the activity timeline
+pub async fn apply(
+    database: &dyn models::Database,
+    settings: &config::Retention,
+    dry_run: bool,
+) -> Result<Vec<RuleReport>, sqlx::Error> {
+    let now = chrono::Utc::now().naive_utc();
+    let cutoff = |age| {
+        chrono::Duration::from_std(age)
+            .ok()
+            .and_then(|age| now.checked_sub_signed(age))
+            .unwrap_or(chrono::NaiveDateTime::MIN)
+    };
+    let mut reports = Vec::new();
+
+    if let Some(age) = settings.archive_unread_after {
+        reports.push(RuleReport {
+            rule: "archive_unread",
+            dry_run,
+            items: database.archive_unread_before(cutoff(age), dry_run).await?,
+            bytes: None,
+        });
+    }
+    if let Some(age) = settings.archive_read_after {
+        reports.push(RuleReport {
+            rule: "archive_read",
+            dry_run,
+            items: database.archive_read_before(cutoff(age), dry_run).await?,
+            bytes: None,
+        });
+    }
+    if let Some(age) = settings.delete_read_after {
+        reports.push(RuleReport {
+            rule: "delete_read",
+            dry_run,
+            items: database.delete_read_before(cutoff(age), dry_run).await?,
+            bytes: None,
+        });
+    }
+    if let Some(max_bytes) = settings.snapshot_storage {
+        let evicted = database
+            .evict_snapshots(i64::try_from(max_bytes).unwrap_or(i64::MAX), dry_run)
+            .await?;
+        reports.push(RuleReport {
+            rule: "snapshot_storage",
+            dry_run,
+            bytes: Some(evicted.iter().map(|snapshot| snapshot.size).sum()),
+            items: evicted.into_iter().map(|snapshot| snapshot.url).collect(),
+        });
+    }
+
+    if !dry_run {
+        for report in reports.iter().filter(|report| !report.items.is_empty()) {
+            api::log_activity(database, "retention", report.rule, Some(&report.summary())).await;
+        }
+    }
+    Ok(reports)
+}
+
+/// Scheduled run; `RETENTION_DRY_RUN` makes it log what it would do instead
+pub async fn apply_scheduled(database: &dyn models::Database, settings: &config::Retention) -> Result<String, String> {
+    let reports = apply(database, settings, settings.dry_run)
+        .await
+        .map_err(|err| err.to_string())?;
+    let summary = reports.iter().map(RuleReport::summary).collect::<Vec<_>>().join(", ");
+    Ok(if settings.dry_run {
+        format!("dry run, would change {}", summary)
+    } else {
+        summary
+    })
+}
+
+/// What the configured rules would change right now, without changing anything
+#[get("/retention")]
+async fn preview(database: web::Data<Arc<dyn models::Database>>, config: web::Data<Config>) -> impl Responder {
+    respond(apply(database.get_ref().as_ref(), &config.retention, true).await)
+}
+
+/// Apply the configured rules now, whatever `RETENTION_INTERVAL` says
+#[post("/retention/run")]
+async fn run(database: web::Data<Arc<dyn models::Database>>, config: web::Data<Config>) -> impl Responder {
+    respond(apply(database.get_ref().as_ref(), &config.retention, false).await)
+}
+
+fn respond(result: Result<Vec<RuleReport>, sqlx::Error>) -> HttpResponse {
+    match result {
+        Ok(reports) => HttpResponse::Ok().json(reports),
+        Err(err) => {
+            eprintln!("Failed to apply retention rules: {:?}", err);
+            HttpResponse::InternalServerError().json("Failed to apply retention rules")
+        }
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(preview).service(run);
+}
@@ -0,0 +1,133 @@
+//! Resurfacing old snippets: a few each day, picked afresh every morning, so highlights saved months ago come
+//! back round instead of sitting unread
+
+use crate::config::Config;
+use crate::services::{api, models};
+use actix_web::{get, web, HttpResponse, Responder};
+use chrono::{Days, NaiveDate};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use tera::{Context, Tera};
+
+/// How many snippets come up each day
+const REVIEW_SIZE: usize = 5;
+
+/// How old a snippet has to be to come up; anything newer is still fresh in mind
+const MIN_AGE_DAYS: u64 = 30;
+
+#[derive(Deserialize)]
+struct ReviewQuery {
+    /// Only pinned snippets
+    #[serde(default)]
+    pinned: bool,
+}
+
+/// The day's selection from `snippets`: the same all day, a different mix the next
+pub fn pick(mut snippets: Vec<models::SnippetWithTags>, day: NaiveDate, count: usize) -> Vec<models::SnippetWithTags> {
+    snippets.sort_by_cached_key(|snippet| Sha256::digest(format!("{}:{}", day, snippet.id)));
+    snippets.truncate(count);
+    snippets
+}
+
+/// Today's snippets to review, in UTC
+async fn todays_review(
+    database: &dyn models::Database,
+    pinned: bool,
+) -> Result<Vec<models::SnippetWithTags>, sqlx::Error> {
+    let now = chrono::Utc::now().naive_utc();
+    let cutoff = now - Days::new(MIN_AGE_DAYS);
+    let mut snippets = database.get_snippets_saved_before(cutoff).await?;
+    if pinned {
+        snippets.retain(|snippet| snippet.pinned);
+    }
+    Ok(api::sanitize_snippets(pick(snippets, now.date(), REVIEW_SIZE)))
+}
+
+#[get("/review")]
+async fn review_page(
+    database: web::Data<Arc<dyn models::Database>>,
+    tmpl: web::Data<Tera>,
+    database_type: web::Data<String>,
+    config: web::Data<Config>,
+    query: web::Query<ReviewQuery>,
+) -> impl Responder {
+    let snippets = match todays_review(database.get_ref().as_ref(), query.pinned).await {
+        Ok(snippets) => snippets,
+        Err(err) => {
+            eprintln!("Failed to pick snippets to review: {:?}", err);
+            return HttpResponse::InternalServerError().body("Failed to pick snippets to review");
+        }
+    };
+
+    let mut context = Context::new();
+    context.insert("snippets_with_tags", &snippets);
+    context.insert("pinned", &query.pinned);
+    context.insert("min_age_days", &MIN_AGE_DAYS);
+    context.insert(
+        "tag_details",
+        &api::tag_details_by_name(database.get_ref().as_ref()).await,
+    );
+    context.insert("title", "Review");
+    context.insert("database_type", &**database_type);
+    context.insert("base_path", &config.base_path);
+
+    match tmpl.render("review.html", &context) {
+        Ok(rendered) => HttpResponse::Ok().content_type("text/html").body(rendered),
+        Err(e) => {
+            eprintln!("Template error: {:?}", e);
+            HttpResponse::InternalServerError().body("Template error")
+        }
+    }
+}
+
+#[get("/snippets/review")]
+async fn review_json(database: web::Data<Arc<dyn models::Database>>, query: web::Query<ReviewQuery>) -> impl Responder {
+    match todays_review(database.get_ref().as_ref(), query.pinned).await {
+        Ok(snippets) => HttpResponse::Ok().json(snippets),
+        Err(err) => {
+            eprintln!("Failed to pick snippets to review: {:?}", err);
+            HttpResponse::InternalServerError().json("Failed to pick snippets to review")
+        }
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(review_page).service(review_json);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snippet(id: i32) -> models::SnippetWithTags {
+        models::SnippetWithTags {
+            id,
+            snippet: format!("Snippet {}", id),
+            snippet_html: None,
+            url: "https://example.com".to_string(),
+            tags: Vec::new(),
+            pinned: false,
+            source: None,
+            image_type: None,
+        }
+    }
+
+    #[test]
+    fn picks_stay_put_for_the_day_and_rotate_after() {
+        let snippets: Vec<_> = (1..=40).map(snippet).collect();
+        let ids = |day: NaiveDate| -> Vec<i32> {
+            pick(snippets.clone(), day, REVIEW_SIZE)
+                .iter()
+                .map(|snippet| snippet.id)
+                .collect()
+        };
+        let today = NaiveDate::from_ymd_opt(2024, 3, 14).unwrap();
+
+        assert_eq!(ids(today).len(), REVIEW_SIZE);
+        assert_eq!(ids(today), ids(today));
+        assert_ne!(ids(today), ids(today.succ_opt().unwrap()));
+        // Fewer snippets than a day's worth all come up
+        assert_eq!(pick(snippets[..2].to_vec(), today, REVIEW_SIZE).len(), 2);
+    }
+}
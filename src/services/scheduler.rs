@@ -0,0 +1,86 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
+use tokio::time::{interval_at, Instant, MissedTickBehavior};
+
+/// How a scheduled job has been doing, so `/health` can show one that has stopped running
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Heartbeat {
+    pub every_seconds: u64,
+    /// When the last run finished; a job that has never run yet has none
+    pub last_run: Option<DateTime<Utc>>,
+    pub last_succeeded: Option<bool>,
+}
+
+static HEARTBEATS: LazyLock<Mutex<BTreeMap<&'static str, Heartbeat>>> = LazyLock::new(Default::default);
+
+fn beat(name: &'static str, succeeded: bool) {
+    if let Some(heartbeat) = HEARTBEATS.lock().unwrap().get_mut(name) {
+        heartbeat.last_run = Some(Utc::now());
+        heartbeat.last_succeeded = Some(succeeded);
+    }
+}
+
+/// Every scheduled job by name
+pub fn heartbeats() -> BTreeMap<&'static str, Heartbeat> {
+    HEARTBEATS.lock().unwrap().clone()
+}
+
+/// Run `task` in the background every `period`, starting one period after startup.
+///
+/// Runs never overlap: a slow run delays the next one instead of stacking up.
+pub fn every<F, Fut>(name: &'static str, period: Duration, task: F)
+where
+    F: Fn() -> Fut + 'static,
+    Fut: Future<Output = Result<String, String>> + 'static,
+{
+    println!("Scheduled {} every {:?}", name, period);
+    HEARTBEATS.lock().unwrap().insert(
+        name,
+        Heartbeat {
+            every_seconds: period.as_secs(),
+            last_run: None,
+            last_succeeded: None,
+        },
+    );
+
+    actix_web::rt::spawn(async move {
+        let mut ticker = interval_at(Instant::now() + period, period);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            ticker.tick().await;
+            match task().await {
+                Ok(outcome) => {
+                    println!("{}: {}", name, outcome);
+                    beat(name, true);
+                }
+                Err(err) => {
+                    eprintln!("{} failed: {}", name, err);
+                    beat(name, false);
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_web::test]
+    async fn runs_are_recorded() {
+        every("Test job", Duration::from_millis(10), || async {
+            Err("nope".to_string())
+        });
+        assert_eq!(heartbeats()["Test job"].last_run, None);
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let heartbeat = &heartbeats()["Test job"];
+        assert!(heartbeat.last_run.is_some());
+        assert_eq!(heartbeat.last_succeeded, Some(false));
+    }
+}
@@ -0,0 +1,166 @@
+//! Boot-time check that every page template is there and renders, so a bad deploy stops at startup with the
+//! template named instead of answering the first visit with a 500
+
+use crate::services::{briefing, models, stats};
+use chrono::NaiveDateTime;
+use serde_json::json;
+use tera::{Context, Tera};
+
+/// Templates handlers render by name, including the rows `partials` renders on their own
+pub const TEMPLATES: [&str; 20] = [
+    "activity.html",
+    "briefing.html",
+    "briefing_email.html",
+    "error.html",
+    "inbox.html",
+    "index.html",
+    "listen.html",
+    "listen_feed.xml",
+    "login.html",
+    "reader.html",
+    "review.html",
+    "service-worker.js",
+    "share_target.html",
+    "snapshot_diff.html",
+    "snippet_rows.html",
+    "snippets.html",
+    "stats.html",
+    "tag_list.html",
+    "tags.html",
+    "url_rows.html",
+];
+
+fn sample_url(datetime: NaiveDateTime) -> models::UrlWithTags {
+    models::UrlWithTags {
+        id: 1,
+        url: "https://example.com/post?id=1".to_string(),
+        url_hash: "0".repeat(64),
+        datetime,
+        tags: vec!["rust".to_string()],
+        display_url: "https://example.com/post".to_string(),
+        display_title: "example.com/post".to_string(),
+        read_at: None,
+        short_id: "0".repeat(10),
+        pinned: false,
+        progress: 0,
+        metadata: None,
+        title: None,
+        notes: None,
+        archived_at: None,
+        snoozed_until: None,
+    }
+}
+
+/// Stand-ins for everything any page's handler puts in its context, one URL where a page lists them
+fn sample_context() -> Context {
+    let now = chrono::Utc::now().naive_utc();
+    let url = sample_url(now);
+
+    let mut context = Context::new();
+    context.insert("title", "Self-check");
+    context.insert("database_type", "sqlite");
+    context.insert("base_path", "");
+    context.insert("version", env!("CARGO_PKG_VERSION"));
+    context.insert("tag_details", &json!({}));
+    context.insert("tag_list", &json!([]));
+    context.insert("tag_cloud", &json!([]));
+    context.insert("tag_filter", "");
+    context.insert("urls_with_tags", &[&url]);
+    context.insert("urls", &[&url]);
+    context.insert("url", &url);
+    context.insert("display_title", &url.display_title);
+    context.insert("durations", &json!({}));
+    context.insert("memories", &json!([]));
+    context.insert("list_query", &json!({}));
+    context.insert("sort_options", &json!([]));
+    context.insert("type_options", &json!([]));
+    context.insert("next_page", &None::<u32>);
+    context.insert("page_query", "");
+    context.insert("snippets_with_tags", &json!([]));
+    context.insert("snippets", &json!([]));
+    context.insert("tags_with_urls_and_snippets", &json!([]));
+    context.insert("activity", &json!([]));
+    context.insert("episodes", &json!([]));
+    context.insert("queue_url", "http://localhost/listen");
+    context.insert("stats", &stats::compute(&[], now.date()));
+    context.insert(
+        "briefing",
+        &briefing::Briefing {
+            since: now,
+            items: Vec::new(),
+            total_minutes: 0,
+        },
+    );
+    context.insert("pinned", &false);
+    context.insert("min_age_days", &30);
+    context.insert("signed_in", &false);
+    context.insert("error", &None::<String>);
+    context.insert("message", &None::<String>);
+    context.insert("status", &500);
+    context.insert("saved_url", &None::<String>);
+    context.insert("snapshot_count", &0);
+    context.insert("snapshot_error", &None::<String>);
+    context.insert("preview", &None::<String>);
+    context.insert("a", &1);
+    context.insert("b", &2);
+    context.insert("old_datetime", &now);
+    context.insert("new_datetime", &now);
+    context.insert("lines", &json!([]));
+    context
+}
+
+/// Every problem found: templates that are missing, and ones that fail to render with stand-in values
+pub fn templates(tera: &Tera) -> Result<(), Vec<String>> {
+    let loaded: Vec<&str> = tera.get_template_names().collect();
+    let context = sample_context();
+    let problems: Vec<String> = TEMPLATES
+        .iter()
+        .filter_map(|name| {
+            if !loaded.contains(name) {
+                return Some(format!("{}: missing", name));
+            }
+            tera.render(name, &context).err().map(|err| {
+                // The cause says which variable or filter went wrong, the error itself only which template
+                let mut message = err.to_string();
+                let mut source = std::error::Error::source(&err);
+                while let Some(cause) = source {
+                    message.push_str(&format!(": {}", cause));
+                    source = cause.source();
+                }
+                message
+            })
+        })
+        .collect();
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(problems)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shipped_templates_pass() {
+        let tera = Tera::new(concat!(env!("CARGO_MANIFEST_DIR"), "/templates/**/*")).unwrap();
+        assert_eq!(templates(&tera), Ok(()));
+    }
+
+    #[test]
+    fn missing_and_broken_templates_are_named() {
+        let mut tera = Tera::default();
+        tera.add_raw_template("index.html", "{{ no_such_variable }}").unwrap();
+        let problems = templates(&tera).unwrap_err();
+
+        assert_eq!(problems.len(), TEMPLATES.len());
+        assert!(problems.contains(&"activity.html: missing".to_string()));
+        let index = problems
+            .iter()
+            .find(|problem| problem.contains("'index.html'"))
+            .unwrap();
+        assert!(index.contains("no_such_variable"), "{}", index);
+    }
+}
@@ -0,0 +1,220 @@
+//! `/sitemap.xml` for a library shared with `PUBLIC_READ_ONLY`, listing the reader page of every saved URL and a
+//! feed per tag so search engines can find them
+
+use crate::config::Config;
+use crate::services::exporters::epub;
+use crate::services::models;
+use actix_web::{get, web, HttpResponse, Responder};
+use std::sync::Arc;
+
+/// Most addresses one sitemap may list; a longer one is split into pages listed by a sitemap index
+const URL_LIMIT: usize = 50_000;
+
+/// One page in the sitemap, with the day it last changed if known
+pub struct Entry {
+    pub location: String,
+    pub modified: Option<chrono::NaiveDate>,
+}
+
+fn push_entry(xml: &mut String, tag: &str, entry: &Entry) {
+    xml.push_str(&format!("  <{}><loc>{}</loc>", tag, epub::escape(&entry.location)));
+    if let Some(modified) = entry.modified {
+        xml.push_str(&format!("<lastmod>{}</lastmod>", modified.format("%Y-%m-%d")));
+    }
+    xml.push_str(&format!("</{}>\n", tag));
+}
+
+pub fn render(entries: &[Entry]) -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n",
+    );
+    for entry in entries.iter().take(URL_LIMIT) {
+        push_entry(&mut xml, "url", entry);
+    }
+    xml.push_str("</urlset>\n");
+    xml
+}
+
+/// A sitemap index pointing at `sitemap-1.xml`, `sitemap-2.xml` and so on, one per `URL_LIMIT` entries
+pub fn render_index(entries: &[Entry], home: &str) -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <sitemapindex xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n",
+    );
+    for (index, page) in entries.chunks(URL_LIMIT).enumerate() {
+        let listed = Entry {
+            location: format!("{}sitemap-{}.xml", home, index + 1),
+            modified: page.iter().filter_map(|entry| entry.modified).max(),
+        };
+        push_entry(&mut xml, "sitemap", &listed);
+    }
+    xml.push_str("</sitemapindex>\n");
+    xml
+}
+
+/// The home page, each saved URL's reader page, newest first, and the JSON Feed of each tag
+async fn entries(database: &dyn models::Database, home: &str) -> Result<Vec<Entry>, sqlx::Error> {
+    let urls = database.get_urls_with_tags(&models::ListFilter::default()).await?;
+    let tags = database.get_all_tags().await?;
+
+    let mut entries = vec![Entry {
+        location: home.to_string(),
+        modified: urls.iter().map(|url| url.datetime.date()).max(),
+    }];
+    entries.extend(urls.iter().map(|url| Entry {
+        location: format!("{}read/{}", home, url.short_id),
+        modified: Some(url.datetime.date()),
+    }));
+    for tag in tags {
+        if let Ok(mut feed) = reqwest::Url::parse(&format!("{}feed.json", home)) {
+            feed.query_pairs_mut().append_pair("tags", &tag);
+            entries.push(Entry {
+                location: feed.to_string(),
+                modified: None,
+            });
+        }
+    }
+    Ok(entries)
+}
+
+/// Build the entries and hand them to `respond`; only there when the library is public and `PUBLIC_URL` says
+/// where, since the request's own `Host` is whatever the client sent
+async fn serve(
+    database: &dyn models::Database,
+    config: &Config,
+    respond: impl FnOnce(&[Entry], &str) -> Option<String>,
+) -> HttpResponse {
+    if !config.access.public_read_only {
+        return HttpResponse::NotFound().body("The sitemap is only served with PUBLIC_READ_ONLY");
+    }
+    let Some(public_url) = &config.access.public_url else {
+        return HttpResponse::NotFound().body("The sitemap is only served with PUBLIC_URL");
+    };
+
+    let home = format!("{}/", public_url);
+    match entries(database, &home).await {
+        Ok(entries) => match respond(&entries, &home) {
+            Some(xml) => HttpResponse::Ok()
+                .content_type("application/xml; charset=utf-8")
+                .body(xml),
+            None => HttpResponse::NotFound().body("No such sitemap page"),
+        },
+        Err(err) => {
+            eprintln!("Failed to build the sitemap: {:?}", err);
+            HttpResponse::InternalServerError().body("Failed to build the sitemap")
+        }
+    }
+}
+
+/// The sitemap itself, or an index of its pages once there are more than `URL_LIMIT` entries
+#[get("/sitemap.xml")]
+async fn sitemap(database: web::Data<Arc<dyn models::Database>>, config: web::Data<Config>) -> impl Responder {
+    serve(database.get_ref().as_ref(), &config, |entries, home| {
+        Some(match entries.len() > URL_LIMIT {
+            true => render_index(entries, home),
+            false => render(entries),
+        })
+    })
+    .await
+}
+
+/// One page of a sitemap too long for a single file, counted from 1
+#[get("/sitemap-{page}.xml")]
+async fn sitemap_page(
+    page: web::Path<usize>,
+    database: web::Data<Arc<dyn models::Database>>,
+    config: web::Data<Config>,
+) -> impl Responder {
+    let page = page.into_inner();
+    serve(database.get_ref().as_ref(), &config, |entries, _| {
+        let chunk = entries.chunks(URL_LIMIT).nth(page.checked_sub(1)?)?;
+        Some(render(chunk))
+    })
+    .await
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(sitemap).service(sitemap_page);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_are_escaped_and_dated() {
+        let xml = render(&[
+            Entry {
+                location: "https://example.com/read/1a2b3c4d5e".to_string(),
+                modified: chrono::NaiveDate::from_ymd_opt(2024, 5, 1),
+            },
+            Entry {
+                location: "https://example.com/feed.json?tags=rust&sort=added".to_string(),
+                modified: None,
+            },
+        ]);
+        assert!(xml.contains("<url><loc>https://example.com/read/1a2b3c4d5e</loc><lastmod>2024-05-01</lastmod></url>"));
+        assert!(xml.contains("<url><loc>https://example.com/feed.json?tags=rust&amp;sort=added</loc></url>"));
+        assert!(xml.ends_with("</urlset>\n"));
+    }
+
+    #[test]
+    fn long_sitemaps_are_indexed_by_page() {
+        let day = chrono::NaiveDate::from_ymd_opt(2024, 5, 1);
+        let entries: Vec<Entry> = (0..URL_LIMIT + 1)
+            .map(|n| Entry {
+                location: format!("https://example.com/read/{}", n),
+                modified: if n == URL_LIMIT { day } else { None },
+            })
+            .collect();
+        let xml = render_index(&entries, "https://example.com/");
+        assert!(xml.contains("<sitemap><loc>https://example.com/sitemap-1.xml</loc></sitemap>"));
+        assert!(xml
+            .contains("<sitemap><loc>https://example.com/sitemap-2.xml</loc><lastmod>2024-05-01</lastmod></sitemap>"));
+        assert!(!xml.contains("sitemap-3.xml"));
+        assert!(xml.ends_with("</sitemapindex>\n"));
+    }
+
+    #[actix_web::test]
+    async fn links_use_the_public_url_not_the_host_header() {
+        use crate::services::models::Database;
+        use crate::services::sqlite_database::SqliteDatabase;
+        use actix_web::{test, App};
+
+        let database = SqliteDatabase::new(":memory:").await.unwrap();
+        database.initialize().await.unwrap();
+        database.insert_url("https://example.org/post").await.unwrap();
+        let database: Arc<dyn models::Database> = Arc::new(database);
+
+        std::env::set_var("SQLITE_URL", "sqlite::memory:");
+        let mut config = Config::from_env().unwrap();
+        config.access.public_read_only = true;
+        for public_url in [None, Some("https://later.example/readlater".to_string())] {
+            config.access.public_url = public_url.clone();
+            let app = test::init_service(
+                App::new()
+                    .app_data(web::Data::new(database.clone()))
+                    .app_data(web::Data::new(config.clone()))
+                    .configure(configure_routes),
+            )
+            .await;
+            let request = test::TestRequest::get()
+                .uri("/sitemap.xml")
+                .insert_header(("Host", "evil.example"))
+                .to_request();
+            let response = test::call_service(&app, request).await;
+            if public_url.is_none() {
+                assert_eq!(response.status(), 404);
+                continue;
+            }
+            let xml = String::from_utf8(test::read_body(response).await.to_vec()).unwrap();
+            assert!(xml.contains("<loc>https://later.example/readlater/</loc>"));
+            assert!(xml.contains("<loc>https://later.example/readlater/read/"));
+            assert!(!xml.contains("evil.example"));
+
+            let request = test::TestRequest::get().uri("/sitemap-2.xml").to_request();
+            assert_eq!(test::call_service(&app, request).await.status(), 404);
+        }
+    }
+}
@@ -0,0 +1,328 @@
+//! Snapshot bodies kept as files once they have been read, so the reader view, diffs and briefings opening the
+//! same copy again don't pull megabytes of page out of the database each time. A snapshot never changes after it
+//! is taken, so a cached body never goes stale; its file name carries when it was taken, in case an id is reused.
+//! A body is removed along with its snapshot, so the cache never serves one the database no longer has.
+
+use crate::config::SnapshotCache;
+use crate::services::models;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::SystemTime;
+
+static SETTINGS: OnceLock<SnapshotCache> = OnceLock::new();
+
+/// Cache snapshot bodies with `settings` from now on; only the first call has any effect
+pub fn configure(settings: &SnapshotCache) {
+    let _ = SETTINGS.set(settings.clone());
+}
+
+fn file_name(url_id: i32, id: i32, datetime: chrono::NaiveDateTime) -> String {
+    format!("{}-{}-{}", url_id, id, datetime.and_utc().timestamp_micros())
+}
+
+/// The `ETag` a snapshot is served with, naming the same copy its cached file does
+pub fn etag(url_id: i32, id: i32, datetime: chrono::NaiveDateTime) -> String {
+    format!("\"snapshot-{}\"", file_name(url_id, id, datetime))
+}
+
+/// The URL and snapshot ids a cached body's file name was made from, or `None` for anything else there
+fn parse_file_name(name: &str) -> Option<(i32, i32)> {
+    let mut parts = name.split('-');
+    let url_id = parts.next()?.parse().ok()?;
+    let id = parts.next()?.parse().ok()?;
+    parts.next()?.parse::<i64>().ok()?;
+    parts.next().is_none().then_some((url_id, id))
+}
+
+fn cache_dir() -> Option<&'static PathBuf> {
+    SETTINGS.get().and_then(|settings| settings.dir.as_ref())
+}
+
+/// Whether bodies are being cached at all, so callers can skip looking up what to remove
+pub fn enabled() -> bool {
+    cache_dir().is_some()
+}
+
+/// A snapshot of `url_id` with its content, from the cache when it is there and otherwise from `database`, caching
+/// it on the way. Either way the snapshot is marked as just viewed, so storage caps still drop the least-read ones.
+pub async fn get(
+    database: &dyn models::Database,
+    url_id: i32,
+    id: i32,
+) -> Result<Option<models::SnapshotContent>, sqlx::Error> {
+    let settings = match SETTINGS.get() {
+        Some(settings) if settings.dir.is_some() => settings,
+        _ => return database.get_snapshot(url_id, id).await,
+    };
+
+    let datetime = match database.touch_snapshot(url_id, id).await? {
+        Some(datetime) => datetime,
+        None => return Ok(None),
+    };
+    let name = file_name(url_id, id, datetime);
+    if let Some(content) = read(settings, &name).await {
+        return Ok(Some(models::SnapshotContent { datetime, content }));
+    }
+
+    let snapshot = database.get_snapshot(url_id, id).await?;
+    if let Some(snapshot) = &snapshot {
+        store(settings, &name, &snapshot.content).await;
+    }
+    Ok(snapshot)
+}
+
+/// The cached body named `name`; a file that can't be read is reported and treated as a miss
+async fn read(settings: &SnapshotCache, name: &str) -> Option<String> {
+    let dir = settings.dir.as_ref()?;
+    match tokio::fs::read_to_string(dir.join(name)).await {
+        Ok(content) => Some(content),
+        Err(err) => {
+            if err.kind() != ErrorKind::NotFound {
+                eprintln!("Failed to read cached snapshot {}: {}", name, err);
+            }
+            None
+        }
+    }
+}
+
+/// Cache `content` as `name`, then trim the cache back under its size. Bodies bigger than the whole cache are left
+/// out, and failures are only reported, as the database still has every snapshot.
+async fn store(settings: &SnapshotCache, name: &str, content: &str) {
+    let Some(dir) = settings.dir.as_ref() else {
+        return;
+    };
+    if content.len() > settings.max_size {
+        return;
+    }
+
+    // Written aside and renamed into place, so a reader never sees half a body
+    let partial = dir.join(format!("{}.partial", name));
+    let written = async {
+        tokio::fs::create_dir_all(dir).await?;
+        tokio::fs::write(&partial, content).await?;
+        tokio::fs::rename(&partial, dir.join(name)).await
+    };
+    if let Err(err) = written.await {
+        eprintln!("Failed to cache snapshot {}: {}", name, err);
+        let _ = tokio::fs::remove_file(&partial).await;
+        return;
+    }
+
+    if let Err(err) = prune(dir, settings.max_size).await {
+        eprintln!("Failed to trim the snapshot cache: {}", err);
+    }
+}
+
+/// Remove the earliest cached bodies until the rest fit in `max_size` bytes
+async fn prune(dir: &Path, max_size: usize) -> std::io::Result<()> {
+    let mut files = Vec::new();
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let metadata = entry.metadata().await?;
+        if metadata.is_file() && entry.path().extension().is_none() {
+            let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            files.push((modified, metadata.len(), entry.path()));
+        }
+    }
+
+    let mut total: u64 = files.iter().map(|(_, size, _)| size).sum();
+    files.sort();
+    for (_, size, path) in files {
+        if total <= max_size as u64 {
+            break;
+        }
+        match tokio::fs::remove_file(&path).await {
+            Ok(()) => total -= size,
+            // Another request trimming at the same time got there first
+            Err(err) if err.kind() == ErrorKind::NotFound => total -= size,
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
+
+/// Every cached body in `dir`, with the URL and snapshot ids it belongs to
+async fn cached_files(dir: &Path) -> std::io::Result<Vec<(i32, i32, PathBuf)>> {
+    let mut files = Vec::new();
+    let mut entries = match tokio::fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(files),
+        Err(err) => return Err(err),
+    };
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name();
+        if let Some((url_id, id)) = name.to_str().and_then(parse_file_name) {
+            files.push((url_id, id, entry.path()));
+        }
+    }
+    Ok(files)
+}
+
+async fn remove(path: &Path) {
+    match tokio::fs::remove_file(path).await {
+        Ok(()) => {}
+        Err(err) if err.kind() == ErrorKind::NotFound => {}
+        Err(err) => eprintln!("Failed to remove cached snapshot {}: {}", path.display(), err),
+    }
+}
+
+/// Remove the cached bodies of the snapshots `ids`, once they have been deleted
+pub async fn forget(ids: &[i32]) {
+    let Some(dir) = cache_dir() else {
+        return;
+    };
+    if ids.is_empty() {
+        return;
+    }
+    let ids: HashSet<i32> = ids.iter().copied().collect();
+    match cached_files(dir).await {
+        Ok(files) => {
+            for (_, id, path) in files {
+                if ids.contains(&id) {
+                    remove(&path).await;
+                }
+            }
+        }
+        Err(err) => eprintln!("Failed to look through the snapshot cache: {}", err),
+    }
+}
+
+/// Remove the cached bodies of `url_id`, or of every URL, whose snapshot `snapshots` no longer lists under that URL;
+/// for deletes that don't say which snapshots went, such as a URL's rows going with it or moving to another URL
+pub async fn forget_deleted<F, Fut>(snapshots: F, url_id: Option<i32>)
+where
+    F: Fn(i32) -> Fut,
+    Fut: Future<Output = Result<Vec<models::Snapshot>, sqlx::Error>>,
+{
+    if let Some(dir) = cache_dir() {
+        forget_deleted_in(dir, snapshots, url_id).await;
+    }
+}
+
+async fn forget_deleted_in<F, Fut>(dir: &Path, snapshots: F, url_id: Option<i32>)
+where
+    F: Fn(i32) -> Fut,
+    Fut: Future<Output = Result<Vec<models::Snapshot>, sqlx::Error>>,
+{
+    let files = match cached_files(dir).await {
+        Ok(files) => files,
+        Err(err) => {
+            eprintln!("Failed to look through the snapshot cache: {}", err);
+            return;
+        }
+    };
+
+    let mut by_url: HashMap<i32, Vec<(i32, PathBuf)>> = HashMap::new();
+    for (cached_url_id, id, path) in files {
+        if url_id.is_none_or(|url_id| url_id == cached_url_id) {
+            by_url.entry(cached_url_id).or_default().push((id, path));
+        }
+    }
+    for (cached_url_id, files) in by_url {
+        let kept: HashSet<i32> = match snapshots(cached_url_id).await {
+            Ok(snapshots) => snapshots.into_iter().map(|snapshot| snapshot.id).collect(),
+            Err(err) => {
+                eprintln!("Failed to check cached snapshots of URL {}: {:?}", cached_url_id, err);
+                continue;
+            }
+        };
+        for (id, path) in files {
+            if !kept.contains(&id) {
+                remove(&path).await;
+            }
+        }
+    }
+}
+
+/// Empty the cache, as when every snapshot has been deleted
+pub async fn clear() {
+    let Some(dir) = cache_dir() else {
+        return;
+    };
+    match tokio::fs::remove_dir_all(dir).await {
+        Ok(()) => {}
+        Err(err) if err.kind() == ErrorKind::NotFound => {}
+        Err(err) => eprintln!("Failed to clear the snapshot cache: {}", err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn stored_bodies_read_back_and_the_earliest_go_past_the_size() {
+        let dir = std::env::temp_dir().join(format!("ril_test_snapshot_cache_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let settings = SnapshotCache {
+            dir: Some(dir.clone()),
+            max_size: 10,
+        };
+        let datetime = chrono::NaiveDate::from_ymd_opt(2024, 5, 1)
+            .unwrap()
+            .and_hms_opt(9, 30, 0)
+            .unwrap();
+        let (first, second) = (file_name(1, 1, datetime), file_name(1, 2, datetime));
+
+        assert_eq!(read(&settings, &first).await, None);
+        store(&settings, &first, "<p>one</p>").await;
+        assert_eq!(read(&settings, &first).await.as_deref(), Some("<p>one</p>"));
+
+        // Modification times can tie on coarse clocks, which would leave the order to the file names
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        store(&settings, &second, "<p>2</p>").await;
+        assert_eq!(read(&settings, &first).await, None);
+        assert_eq!(read(&settings, &second).await.as_deref(), Some("<p>2</p>"));
+
+        store(&settings, &file_name(1, 3, datetime), "too long for the cache").await;
+        assert_eq!(read(&settings, &file_name(1, 3, datetime)).await, None);
+        assert_eq!(read(&settings, &second).await.as_deref(), Some("<p>2</p>"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn bodies_go_once_their_snapshot_is_gone() {
+        let dir = std::env::temp_dir().join(format!("ril_test_snapshot_cache_forget_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let settings = SnapshotCache {
+            dir: Some(dir.clone()),
+            max_size: 1000,
+        };
+        let datetime = chrono::NaiveDate::from_ymd_opt(2024, 5, 1)
+            .unwrap()
+            .and_hms_opt(9, 30, 0)
+            .unwrap();
+        for (url_id, id) in [(1, 1), (1, 2), (2, 3)] {
+            store(&settings, &file_name(url_id, id, datetime), "<p>body</p>").await;
+        }
+        assert_eq!(parse_file_name(&file_name(1, 2, datetime)), Some((1, 2)));
+        assert_eq!(parse_file_name("1-2-3.partial"), None);
+        assert_eq!(
+            etag(1, 2, datetime),
+            format!("\"snapshot-{}\"", file_name(1, 2, datetime))
+        );
+
+        // URL 1 kept only its newer snapshot; URL 2 was left alone
+        let snapshot = models::Snapshot {
+            id: 2,
+            datetime,
+            content_type: None,
+            size: 11,
+            truncated: false,
+        };
+        forget_deleted_in(&dir, |_| async { Ok(vec![snapshot.clone()]) }, Some(1)).await;
+        assert_eq!(read(&settings, &file_name(1, 1, datetime)).await, None);
+        assert!(read(&settings, &file_name(1, 2, datetime)).await.is_some());
+        assert!(read(&settings, &file_name(2, 3, datetime)).await.is_some());
+
+        forget_deleted_in(&dir, |_| async { Ok(Vec::new()) }, None).await;
+        assert_eq!(read(&settings, &file_name(1, 2, datetime)).await, None);
+        assert_eq!(read(&settings, &file_name(2, 3, datetime)).await, None);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,509 @@
+//! Copies of saved pages, taken on request or on a schedule, so changes to a page can be looked back on
+
+use crate::config::{self, Config};
+use crate::services::{api, canonical, fetcher, models, snapshot_cache};
+use actix_web::http::header::{CACHE_CONTROL, ETAG, LAST_MODIFIED};
+use actix_web::{get, post, web, HttpRequest, HttpResponse, Responder};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use similar::{ChangeTag, TextDiff};
+use std::sync::Arc;
+use tera::{Context, Tera};
+
+/// Content types snapshotted unless `SNAPSHOT_CONTENT_TYPES` says otherwise: text, not media or downloads
+pub const DEFAULT_CONTENT_TYPES: [&str; 6] = [
+    "text/*",
+    "application/xhtml+xml",
+    "application/xml",
+    "application/rss+xml",
+    "application/atom+xml",
+    "application/json",
+];
+
+/// Unchanged lines shown around each change in a diff
+const DIFF_CONTEXT: usize = 3;
+
+/// Tags that start a new line when a page is flattened to text, so minified HTML still diffs line by line
+const BLOCK_TAGS: [&str; 17] = [
+    "p",
+    "div",
+    "br",
+    "li",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "tr",
+    "pre",
+    "blockquote",
+    "section",
+    "article",
+    "table",
+    "hr",
+];
+
+/// A fetched page: its content type and body, and whether the body was cut off at the size limit
+pub struct Page {
+    pub content_type: Option<String>,
+    pub body: String,
+    pub truncated: bool,
+}
+
+/// Download a page within `limits`, refusing content types it doesn't allow. A page past `max_size` is
+/// cut off there rather than refused, so a huge page still leaves its start.
+pub async fn fetch(url: &str, limits: &config::PageLimits) -> Result<Page, String> {
+    let mut response = fetcher::page(url, limits.timeout).await?.error_for_status()?;
+
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(String::from);
+    if let Some(content_type) = &content_type {
+        if !allows_content_type(&limits.content_types, content_type) {
+            return Err(format!("{} pages aren't fetched", content_type));
+        }
+    }
+
+    // Counted while reading, as the length header can be missing or wrong
+    let (mut body, mut truncated) = (Vec::new(), false);
+    while let Some(chunk) = response.chunk().await.map_err(|err| err.to_string())? {
+        let room = limits.max_size - body.len();
+        if chunk.len() > room {
+            body.extend_from_slice(&chunk[..room]);
+            truncated = true;
+            break;
+        }
+        body.extend_from_slice(&chunk);
+    }
+    // Don't leave half a character at the cut
+    if let Err(err) = std::str::from_utf8(&body) {
+        if truncated && err.error_len().is_none() {
+            body.truncate(err.valid_up_to());
+        }
+    }
+
+    Ok(Page {
+        content_type,
+        body: String::from_utf8_lossy(&body).into_owned(),
+        truncated,
+    })
+}
+
+/// Whether a `Content-Type` header names one of `allowed`; parameters such as `charset` are ignored
+pub fn allows_content_type(allowed: &[String], content_type: &str) -> bool {
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    allowed.is_empty()
+        || allowed.iter().any(|pattern| match pattern.strip_suffix('*') {
+            Some(prefix) => mime.starts_with(prefix),
+            None => mime == *pattern,
+        })
+}
+
+/// Snapshot one URL; `None` means the page was the same as last time.
+///
+/// An HTML page naming a different canonical URL for itself moves the saved URL there.
+/// How the last attempt to snapshot a URL went, kept so a page that keeps failing can say so
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LastFetch {
+    pub at: chrono::NaiveDateTime,
+    pub error: Option<String>,
+}
+
+pub const LAST_FETCH: models::MetadataKey<LastFetch> = models::MetadataKey::new("last_fetch");
+
+/// Snapshot a URL, recording how it went whether or not anything was stored
+pub async fn take(
+    database: &dyn models::Database,
+    url: &models::Url,
+    settings: &config::Snapshots,
+) -> Result<Option<i32>, String> {
+    let taken = snapshot(database, url, settings).await;
+    let outcome = LastFetch {
+        at: chrono::Utc::now().naive_utc(),
+        error: taken.as_ref().err().cloned(),
+    };
+    if let Err(err) = database.set_metadata(url.id, &LAST_FETCH, &outcome).await {
+        eprintln!("Failed to record snapshot outcome for {}: {:?}", url.url, err);
+    }
+    taken
+}
+
+async fn snapshot(
+    database: &dyn models::Database,
+    url: &models::Url,
+    settings: &config::Snapshots,
+) -> Result<Option<i32>, String> {
+    let page = fetch(&url.url, &settings.page).await?;
+    let id = database
+        .insert_snapshot(
+            url.id,
+            page.content_type.as_deref(),
+            &page.body,
+            page.truncated,
+            settings.limit,
+        )
+        .await
+        .map_err(|err| err.to_string())?;
+
+    let is_html = page
+        .content_type
+        .as_deref()
+        .is_none_or(|content_type| content_type.contains("html"));
+    if settings.canonical_urls && is_html {
+        if let Some(found) = canonical::find(&page.body, &url.url) {
+            match database.adopt_canonical_url(url.id, &found).await {
+                Ok(Some(moved)) => {
+                    api::log_activity(database, "canonical_url", &moved.original, Some(&moved.url)).await
+                }
+                Ok(None) => {}
+                Err(err) => eprintln!("Failed to store canonical URL for {}: {:?}", url.url, err),
+            }
+        }
+    }
+    Ok(id)
+}
+
+/// Re-snapshot every URL that already has a snapshot, for the scheduler
+pub async fn refresh_all(database: &dyn models::Database, settings: &config::Snapshots) -> Result<String, String> {
+    let urls = database.get_snapshotted_urls().await.map_err(|err| err.to_string())?;
+    let (mut changed, mut failed) = (0, 0);
+
+    for url in &urls {
+        match take(database, url, settings).await {
+            Ok(Some(_)) => {
+                changed += 1;
+                api::log_activity(database, "snapshot_url", &url.url, Some("scheduled")).await;
+            }
+            Ok(None) => {}
+            Err(err) => {
+                failed += 1;
+                eprintln!("Failed to snapshot {}: {}", url.url, err);
+            }
+        }
+    }
+
+    Ok(format!(
+        "{} URLs checked, {} changed, {} failed",
+        urls.len(),
+        changed,
+        failed
+    ))
+}
+
+/// The readable text of a page: tags and scripts dropped, one block per line, blank lines removed.
+/// Every line ends in a newline, so text added at the end doesn't show the old last line as changed.
+pub fn page_text(html: &str) -> String {
+    let mut spaced = String::with_capacity(html.len());
+    for (index, ch) in html.char_indices() {
+        if ch == '<' {
+            let rest = html[index + 1..].trim_start_matches('/');
+            let name_length = rest.find(|ch: char| !ch.is_ascii_alphanumeric()).unwrap_or(rest.len());
+            if BLOCK_TAGS.contains(&rest[..name_length].to_ascii_lowercase().as_str()) {
+                spaced.push('\n');
+            }
+        }
+        spaced.push(ch);
+    }
+
+    let text = ammonia::Builder::empty().clean(&spaced).to_string();
+    text.lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            line.replace("&nbsp;", " ")
+                .replace("&lt;", "<")
+                .replace("&gt;", ">")
+                .replace("&quot;", "\"")
+                .replace("&amp;", "&")
+                + "\n"
+        })
+        .collect()
+}
+
+/// One line of a rendered diff; `Gap` stands for unchanged lines left out between hunks
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(tag = "kind", content = "text", rename_all = "lowercase")]
+pub enum DiffLine {
+    Equal(String),
+    Delete(String),
+    Insert(String),
+    Gap,
+}
+
+/// Line diff of two texts, with `DIFF_CONTEXT` lines of context around each change
+pub fn diff_lines(old: &str, new: &str) -> Vec<DiffLine> {
+    let diff = TextDiff::from_lines(old, new);
+    let mut lines = Vec::new();
+
+    for (index, group) in diff.grouped_ops(DIFF_CONTEXT).iter().enumerate() {
+        if index > 0 {
+            lines.push(DiffLine::Gap);
+        }
+        for op in group {
+            for change in diff.iter_changes(op) {
+                let text = change.value().trim_end_matches('\n').to_string();
+                lines.push(match change.tag() {
+                    ChangeTag::Equal => DiffLine::Equal(text),
+                    ChangeTag::Delete => DiffLine::Delete(text),
+                    ChangeTag::Insert => DiffLine::Insert(text),
+                });
+            }
+        }
+    }
+
+    lines
+}
+
+/// Look up the URL a `/urls/{id}/...` path refers to by its short id
+async fn find_url(database: &dyn models::Database, short_id: &str) -> Result<models::Url, HttpResponse> {
+    match database.get_url_by_short_id(short_id).await {
+        Ok(Some(url)) => Ok(url),
+        Ok(None) => Err(HttpResponse::NotFound().json("URL not found")),
+        Err(err) => {
+            eprintln!("Failed to look up URL: {:?}", err);
+            Err(HttpResponse::InternalServerError().json("Failed to look up URL"))
+        }
+    }
+}
+
+/// Take a snapshot now; `201` with its id, or `200` with `"changed": false` when the page is unchanged
+#[post("/urls/{id}/snapshots")]
+async fn create_snapshot(
+    database: web::Data<Arc<dyn models::Database>>,
+    config: web::Data<Config>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let url = match find_url(database.get_ref().as_ref(), &path).await {
+        Ok(url) => url,
+        Err(response) => return response,
+    };
+
+    match take(database.get_ref().as_ref(), &url, &config.snapshots).await {
+        Ok(Some(id)) => {
+            api::log_activity(database.get_ref().as_ref(), "snapshot_url", &url.url, None).await;
+            HttpResponse::Created().json(json!({ "id": id, "changed": true }))
+        }
+        Ok(None) => HttpResponse::Ok().json(json!({ "changed": false })),
+        Err(err) => {
+            eprintln!("Failed to snapshot {}: {}", url.url, err);
+            HttpResponse::BadGateway().json(format!("Failed to snapshot the page: {}", err))
+        }
+    }
+}
+
+#[get("/urls/{id}/snapshots")]
+async fn list_snapshots(database: web::Data<Arc<dyn models::Database>>, path: web::Path<String>) -> impl Responder {
+    let url = match find_url(database.get_ref().as_ref(), &path).await {
+        Ok(url) => url,
+        Err(response) => return response,
+    };
+
+    match database.get_snapshots(url.id).await {
+        Ok(snapshots) => HttpResponse::Ok().json(snapshots),
+        Err(err) => {
+            eprintln!("Failed to fetch snapshots: {:?}", err);
+            HttpResponse::InternalServerError().json("Failed to fetch snapshots")
+        }
+    }
+}
+
+/// Query string for the diff view; defaults compare the newest snapshot with the one before it
+#[derive(Deserialize, Debug)]
+pub struct DiffQuery {
+    pub a: Option<i32>,
+    pub b: Option<i32>,
+    /// `unified` for a plain-text unified diff instead of the page
+    pub format: Option<String>,
+}
+
+/// What changed between two snapshots, comparing the pages' text rather than their markup
+#[get("/urls/{id}/snapshots/diff")]
+async fn diff_snapshots(
+    database: web::Data<Arc<dyn models::Database>>,
+    tmpl: web::Data<Tera>,
+    database_type: web::Data<String>,
+    config: web::Data<Config>,
+    path: web::Path<String>,
+    query: web::Query<DiffQuery>,
+) -> impl Responder {
+    let url = match find_url(database.get_ref().as_ref(), &path).await {
+        Ok(url) => url,
+        Err(response) => return response,
+    };
+
+    let (a, b) = match (query.a, query.b) {
+        (Some(a), Some(b)) => (a, b),
+        _ => match database.get_snapshots(url.id).await {
+            Ok(snapshots) => {
+                let newest = query.b.or(snapshots.first().map(|snapshot| snapshot.id));
+                let before = newest.and_then(|newest| snapshots.iter().find(|snapshot| snapshot.id < newest));
+                match (query.a.or(before.map(|snapshot| snapshot.id)), newest) {
+                    (Some(a), Some(b)) => (a, b),
+                    _ => return HttpResponse::NotFound().json("Two snapshots are needed for a diff"),
+                }
+            }
+            Err(err) => {
+                eprintln!("Failed to fetch snapshots: {:?}", err);
+                return HttpResponse::InternalServerError().json("Failed to fetch snapshots");
+            }
+        },
+    };
+
+    let (old, new) = match (
+        snapshot_cache::get(database.get_ref().as_ref(), url.id, a).await,
+        snapshot_cache::get(database.get_ref().as_ref(), url.id, b).await,
+    ) {
+        (Ok(Some(old)), Ok(Some(new))) => (old, new),
+        (Ok(_), Ok(_)) => return HttpResponse::NotFound().json("Snapshot not found"),
+        (Err(err), _) | (_, Err(err)) => {
+            eprintln!("Failed to fetch snapshot: {:?}", err);
+            return HttpResponse::InternalServerError().json("Failed to fetch snapshot");
+        }
+    };
+    let (old_text, new_text) = (page_text(&old.content), page_text(&new.content));
+
+    if query.format.as_deref() == Some("unified") {
+        let old_header = format!("snapshot {} ({})", a, old.datetime.format("%Y-%m-%d %H:%M"));
+        let new_header = format!("snapshot {} ({})", b, new.datetime.format("%Y-%m-%d %H:%M"));
+        let unified = TextDiff::from_lines(&old_text, &new_text)
+            .unified_diff()
+            .context_radius(DIFF_CONTEXT)
+            .header(&old_header, &new_header)
+            .to_string();
+        return HttpResponse::Ok()
+            .content_type("text/plain; charset=utf-8")
+            .body(unified);
+    }
+
+    let mut context = Context::new();
+    context.insert("url", &url);
+    context.insert("a", &a);
+    context.insert("b", &b);
+    context.insert("old_datetime", &old.datetime);
+    context.insert("new_datetime", &new.datetime);
+    context.insert("lines", &diff_lines(&old_text, &new_text));
+    context.insert("title", "Snapshot changes");
+    context.insert("database_type", &**database_type);
+    context.insert("base_path", &config.base_path);
+
+    match tmpl.render("snapshot_diff.html", &context) {
+        Ok(rendered) => HttpResponse::Ok().content_type("text/html").body(rendered),
+        Err(e) => {
+            eprintln!("Template error: {:?}", e);
+            HttpResponse::InternalServerError().body("Template error")
+        }
+    }
+}
+
+/// The stored copy as plain text; serving the page's own HTML from this origin would run its scripts. A snapshot
+/// never changes, so browsers may keep it for good and revalidate with its `ETag`, which names when it was taken the
+/// same as its cached file does.
+#[get("/urls/{id}/snapshots/{snapshot_id}")]
+async fn get_snapshot(
+    req: HttpRequest,
+    database: web::Data<Arc<dyn models::Database>>,
+    path: web::Path<(String, i32)>,
+) -> impl Responder {
+    let (short_id, snapshot_id) = path.into_inner();
+    let url = match find_url(database.get_ref().as_ref(), &short_id).await {
+        Ok(url) => url,
+        Err(response) => return response,
+    };
+
+    let datetime = match database.touch_snapshot(url.id, snapshot_id).await {
+        Ok(Some(datetime)) => datetime,
+        Ok(None) => return HttpResponse::NotFound().json("Snapshot not found"),
+        Err(err) => {
+            eprintln!("Failed to fetch snapshot: {:?}", err);
+            return HttpResponse::InternalServerError().json("Failed to fetch snapshot");
+        }
+    };
+    let etag = snapshot_cache::etag(url.id, snapshot_id, datetime);
+    let cache_control = (CACHE_CONTROL, "private, max-age=31536000, immutable");
+    if api::etag_matches(&req, &etag) {
+        return HttpResponse::NotModified()
+            .insert_header((ETAG, etag))
+            .insert_header(cache_control)
+            .finish();
+    }
+
+    match snapshot_cache::get(database.get_ref().as_ref(), url.id, snapshot_id).await {
+        Ok(Some(snapshot)) => HttpResponse::Ok()
+            .content_type("text/plain; charset=utf-8")
+            .insert_header((
+                LAST_MODIFIED,
+                snapshot
+                    .datetime
+                    .and_utc()
+                    .format("%a, %d %b %Y %H:%M:%S GMT")
+                    .to_string(),
+            ))
+            .insert_header((ETAG, etag))
+            .insert_header(cache_control)
+            .body(snapshot.content),
+        Ok(None) => HttpResponse::NotFound().json("Snapshot not found"),
+        Err(err) => {
+            eprintln!("Failed to fetch snapshot: {:?}", err);
+            HttpResponse::InternalServerError().json("Failed to fetch snapshot")
+        }
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    // The diff route has to be registered before `/snapshots/{snapshot_id}` would claim it
+    cfg.service(create_snapshot)
+        .service(list_snapshots)
+        .service(diff_snapshots)
+        .service(get_snapshot);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn content_types_match_exactly_or_by_prefix() {
+        let allowed: Vec<String> = DEFAULT_CONTENT_TYPES
+            .iter()
+            .map(|pattern| pattern.to_string())
+            .collect();
+        assert!(allows_content_type(&allowed, "text/html; charset=utf-8"));
+        assert!(allows_content_type(&allowed, "Text/Plain"));
+        assert!(allows_content_type(&allowed, "application/xhtml+xml"));
+        assert!(!allows_content_type(&allowed, "application/pdf"));
+        assert!(!allows_content_type(&allowed, "video/mp4"));
+        assert!(!allows_content_type(&allowed, "application/xml-dtd"));
+        assert!(allows_content_type(&[], "video/mp4"));
+    }
+
+    #[test]
+    fn page_text_keeps_one_block_per_line() {
+        let html = "<html><head><style>p { color: red }</style><script>alert(1)</script></head>\
+                    <body><h1>Title</h1><p>First   paragraph &amp; more</p><div>Second<br>line</div></body></html>";
+        assert_eq!(page_text(html), "Title\nFirst paragraph & more\nSecond\nline\n");
+    }
+
+    #[test]
+    fn diff_lines_marks_changes_and_elides_distant_context() {
+        let old = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\nk";
+        let new = "a\nB\nc\nd\ne\nf\ng\nh\ni\nj\nk\nl";
+        let lines = diff_lines(old, new);
+
+        assert_eq!(lines[0], DiffLine::Equal("a".to_string()));
+        assert_eq!(lines[1], DiffLine::Delete("b".to_string()));
+        assert_eq!(lines[2], DiffLine::Insert("B".to_string()));
+        assert!(lines.contains(&DiffLine::Gap));
+        assert_eq!(lines.last(), Some(&DiffLine::Insert("l".to_string())));
+        assert!(!lines.contains(&DiffLine::Equal("f".to_string())));
+
+        assert!(diff_lines("same", "same").is_empty());
+    }
+}
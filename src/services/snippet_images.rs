@@ -0,0 +1,222 @@
+//! Snippets that are pictures, such as a screenshot of part of a page, uploaded as base64 JSON or a multipart form
+
+use crate::config::Config;
+use crate::services::{api, limits, models};
+use actix_multipart::form::{bytes::Bytes, text::Text, MultipartForm, MultipartFormConfig};
+use actix_web::http::header::{CACHE_CONTROL, CONTENT_TYPE};
+use actix_web::{get, guard, web, HttpResponse, Responder};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use std::sync::Arc;
+
+/// Largest picture accepted, in bytes
+pub const IMAGE_LIMIT: usize = 5 * 1024 * 1024;
+
+/// Room for the URL, tags and caption sent alongside the picture
+const FIELDS_LIMIT: usize = 64 * 1024;
+
+/// Picture formats browsers show, by the bytes they start with. SVG is left out as it can carry scripts.
+const IMAGE_TYPES: [(&[u8], &str); 4] = [
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"GIF8", "image/gif"),
+    (b"RIFF", "image/webp"),
+];
+
+/// The content type of a picture, from its first bytes rather than what the client claimed
+pub fn image_type(data: &[u8]) -> Option<&'static str> {
+    IMAGE_TYPES
+        .iter()
+        .find(|(magic, content_type)| {
+            data.starts_with(magic) && (*content_type != "image/webp" || data.get(8..12) == Some(b"WEBP"))
+        })
+        .map(|(_, content_type)| *content_type)
+}
+
+/// Decode a base64 picture, either bare or as a `data:` URL like the ones `canvas.toDataURL()` returns
+pub fn decode(image: &str) -> Result<Vec<u8>, String> {
+    let encoded = match image.strip_prefix("data:") {
+        Some(data_url) => match data_url.split_once(',') {
+            Some((header, encoded)) if header.ends_with(";base64") => encoded,
+            _ => return Err("image data URLs must be base64 encoded".to_string()),
+        },
+        None => image,
+    };
+    let encoded: String = encoded.chars().filter(|ch| !ch.is_ascii_whitespace()).collect();
+    STANDARD
+        .decode(encoded)
+        .map_err(|err| format!("image is not valid base64: {}", err))
+}
+
+/// Check an uploaded picture is small enough and in a format that can be shown
+pub fn validate(data: Vec<u8>) -> Result<models::SnippetImage, String> {
+    if data.len() > IMAGE_LIMIT {
+        return Err(format!("image exceeds the {} byte limit", IMAGE_LIMIT));
+    }
+    let content_type = image_type(&data).ok_or("image must be a PNG, JPEG, GIF or WebP")?;
+    Ok(models::SnippetImage {
+        content_type: content_type.to_string(),
+        data,
+    })
+}
+
+async fn save(
+    database: &dyn models::Database,
+    config: &Config,
+    url: &str,
+    caption: &str,
+    tags: &str,
+    data: Vec<u8>,
+) -> HttpResponse {
+    let tags = match api::snippet_tags(config, &models::split_tags(tags)) {
+        Ok(tags) => tags,
+        Err(response) => return response,
+    };
+    let image = match validate(data) {
+        Ok(image) => image,
+        Err(err) => return HttpResponse::BadRequest().json(err),
+    };
+
+    match database.insert_image_snippet(url, caption.trim(), &tags, &image).await {
+        Ok(snippet_id) => {
+            api::log_activity(database, "save_snippet", url, None).await;
+            api::created_snippet(database, &config.base_path, snippet_id).await
+        }
+        Err(err) => {
+            eprintln!("Failed to insert image snippet: {:?}", err);
+            HttpResponse::InternalServerError().json("Failed to insert image snippet")
+        }
+    }
+}
+
+async fn upload_json(
+    database: web::Data<Arc<dyn models::Database>>,
+    config: web::Data<Config>,
+    req: web::Json<models::NewImageSnippet>,
+) -> impl Responder {
+    match decode(&req.image) {
+        Ok(data) => {
+            save(
+                database.get_ref().as_ref(),
+                &config,
+                &req.url,
+                &req.caption,
+                &req.tags,
+                data,
+            )
+            .await
+        }
+        Err(err) => HttpResponse::BadRequest().json(err),
+    }
+}
+
+/// A picture uploaded as the `file` field, with the snippet's other fields alongside
+#[derive(MultipartForm)]
+struct ImageUpload {
+    file: Bytes,
+    url: Text<String>,
+    tags: Option<Text<String>>,
+    caption: Option<Text<String>>,
+}
+
+async fn upload_form(
+    database: web::Data<Arc<dyn models::Database>>,
+    config: web::Data<Config>,
+    form: MultipartForm<ImageUpload>,
+) -> impl Responder {
+    let form = form.into_inner();
+    let text = |field: Option<Text<String>>| field.map(Text::into_inner).unwrap_or_default();
+    save(
+        database.get_ref().as_ref(),
+        &config,
+        &form.url,
+        &text(form.caption),
+        &text(form.tags),
+        form.file.data.to_vec(),
+    )
+    .await
+}
+
+#[get("/snippets/{id}/image")]
+async fn get_image(database: web::Data<Arc<dyn models::Database>>, path: web::Path<i32>) -> impl Responder {
+    match database.get_snippet_image(path.into_inner()).await {
+        Ok(Some(image)) => HttpResponse::Ok()
+            .content_type(image.content_type)
+            // Pictures never change once saved
+            .insert_header((CACHE_CONTROL, "private, max-age=604800, immutable"))
+            .body(image.data),
+        Ok(None) => HttpResponse::NotFound().json("Image not found"),
+        Err(err) => {
+            eprintln!("Failed to fetch snippet image: {:?}", err);
+            HttpResponse::InternalServerError().json("Failed to fetch snippet image")
+        }
+    }
+}
+
+/// Whether a request body is a multipart form rather than JSON
+fn is_multipart(ctx: &guard::GuardContext) -> bool {
+    ctx.head()
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("multipart/form-data"))
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/snippets/image")
+            // Base64 is a third bigger than the picture it encodes
+            .app_data(limits::json_config(IMAGE_LIMIT / 3 * 4 + FIELDS_LIMIT))
+            .app_data(
+                MultipartFormConfig::default()
+                    .total_limit(IMAGE_LIMIT + FIELDS_LIMIT)
+                    .memory_limit(IMAGE_LIMIT + FIELDS_LIMIT),
+            )
+            .route(web::post().guard(guard::fn_guard(is_multipart)).to(upload_form))
+            .route(web::post().to(upload_json)),
+    )
+    .service(get_image);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+
+    #[test]
+    fn pictures_are_recognised_by_their_bytes() {
+        assert_eq!(image_type(PNG), Some("image/png"));
+        assert_eq!(image_type(b"\xff\xd8\xff\xe0\0\x10JFIF"), Some("image/jpeg"));
+        assert_eq!(image_type(b"GIF89a\x01\0"), Some("image/gif"));
+        assert_eq!(image_type(b"RIFF\x24\0\0\0WEBPVP8 "), Some("image/webp"));
+        assert_eq!(image_type(b"RIFF\x24\0\0\0WAVEfmt "), None);
+        assert_eq!(
+            image_type(b"<svg xmlns=\"http://www.w3.org/2000/svg\"><script/></svg>"),
+            None
+        );
+        assert_eq!(image_type(b""), None);
+    }
+
+    #[test]
+    fn base64_and_data_urls_decode() {
+        let encoded = STANDARD.encode(PNG);
+        assert_eq!(decode(&encoded).unwrap(), PNG);
+        assert_eq!(decode(&format!("data:image/png;base64,{}", encoded)).unwrap(), PNG);
+        // Line-wrapped base64, as some tools write it
+        let wrapped = format!("{}\n{}", &encoded[..8], &encoded[8..]);
+        assert_eq!(decode(&wrapped).unwrap(), PNG);
+
+        assert!(decode("data:image/png,%89PNG").is_err());
+        assert!(decode("not base64!").is_err());
+    }
+
+    #[test]
+    fn validation_checks_format_and_size() {
+        assert_eq!(validate(PNG.to_vec()).unwrap().content_type, "image/png");
+        assert!(validate(b"plain text".to_vec()).is_err());
+
+        let mut oversized = PNG.to_vec();
+        oversized.resize(IMAGE_LIMIT + 1, 0);
+        assert!(validate(oversized).is_err());
+    }
+}
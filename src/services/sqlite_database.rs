@@ -1,11 +1,19 @@
-use crate::services::models;
+use crate::services::{amp, crypto, display, models, snapshot_cache, tracking};
+use futures_util::stream::{BoxStream, StreamExt, TryStreamExt};
 use sha2::{Digest, Sha256};
-use sqlx::{Error, Row, SqlitePool};
+use sqlx::sqlite::SqliteRow;
+use sqlx::{Error, QueryBuilder, Row, Sqlite, SqliteConnection, SqlitePool};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fs;
 use std::path::Path;
 
 pub struct SqliteDatabase {
     pool: SqlitePool,
+    cipher: Option<crypto::SnippetCipher>,
+    tracking: tracking::TrackingParams,
+    unwrap_amp: bool,
+    /// Tags kept when snippet text is sanitized on save; ammonia's defaults and a few more when `None`
+    snippet_html_tags: Option<Vec<String>>,
 }
 
 impl SqliteDatabase {
@@ -17,7 +25,90 @@ impl SqliteDatabase {
 
         // Connect to the SQLite database
         let pool = sqlx::SqlitePool::connect(database_url).await?;
-        Ok(Self { pool })
+        Ok(Self {
+            pool,
+            cipher: None,
+            tracking: Default::default(),
+            unwrap_amp: true,
+            snippet_html_tags: None,
+        })
+    }
+
+    /// Encrypt snippet text at rest with the given cipher
+    pub fn with_snippet_cipher(mut self, cipher: Option<crypto::SnippetCipher>) -> Self {
+        self.cipher = cipher;
+        self
+    }
+
+    /// Strip these query parameters from URLs as they are saved and looked up
+    pub fn with_tracking_params(mut self, tracking: tracking::TrackingParams) -> Self {
+        self.tracking = tracking;
+        self
+    }
+
+    /// Rewrite AMP, mobile and Google cache links to the desktop URL as they are saved and looked up
+    pub fn with_amp_unwrapping(mut self, enabled: bool) -> Self {
+        self.unwrap_amp = enabled;
+        self
+    }
+
+    /// Keep only these tags when sanitizing snippet text on save
+    pub fn with_snippet_html_tags(mut self, tags: Option<Vec<String>>) -> Self {
+        self.snippet_html_tags = tags;
+        self
+    }
+
+    /// Snippet text sanitized for showing, then sealed like the text itself
+    fn sealed_html(&self, snippet: &str) -> Result<String, sqlx::Error> {
+        crypto::seal(
+            self.cipher.as_ref(),
+            &models::sanitize_snippet_html(snippet, self.snippet_html_tags.as_deref()),
+        )
+    }
+
+    /// Decrypt snippets read back, sanitizing the text of any saved before it was sanitized on save
+    fn open_snippets(
+        &self,
+        snippets: Vec<models::SnippetWithTags>,
+    ) -> Result<Vec<models::SnippetWithTags>, sqlx::Error> {
+        let mut snippets = crypto::open_snippets(self.cipher.as_ref(), snippets)?;
+        for snippet in &mut snippets {
+            if snippet.snippet_html.is_none() {
+                snippet.snippet_html = Some(models::sanitize_snippet_html(
+                    &snippet.snippet,
+                    self.snippet_html_tags.as_deref(),
+                ));
+            }
+        }
+        Ok(snippets)
+    }
+
+    /// A URL in the form it is stored in, before any alias is looked up
+    fn normalize(&self, url: &str) -> String {
+        let url = if self.unwrap_amp { amp::unwrap(url) } else { url.into() };
+        self.tracking.strip(&url).into_owned()
+    }
+
+    /// The URL as it is stored: normalized, and a known alias replaced by its canonical URL
+    async fn resolve(&self, url: &str) -> Result<String, sqlx::Error> {
+        let normalized = self.normalize(url);
+        Ok(get_aliased_url(&self.pool, &normalized).await?.unwrap_or(normalized))
+    }
+
+    /// Drop the cached bodies left under `url_id` once it has been folded into another saved URL, taking its
+    /// snapshots along
+    async fn forget_merged_snapshots(&self, url_id: i32, moved: Option<&models::CleanedUrl>) {
+        if moved.is_some_and(|moved| moved.merged) {
+            snapshot_cache::forget_deleted(|url_id| get_snapshots(&self.pool, url_id), Some(url_id)).await;
+        }
+    }
+
+    /// Keep the URL as it was given when it was stored under another form
+    async fn keep_original(&self, url: &str, resolved: &str) -> Result<(), sqlx::Error> {
+        if url != resolved {
+            record_original_url(&self.pool, resolved, url).await?;
+        }
+        Ok(())
     }
 
     fn create_sqlite_file_if_needed(path: &str) -> Result<(), std::io::Error> {
@@ -46,43 +137,482 @@ impl models::Database for SqliteDatabase {
     }
 
     async fn insert_url(&self, url: &str) -> Result<i32, sqlx::Error> {
-        insert_url(&self.pool, url).await
+        let resolved = self.resolve(url).await?;
+        let url_id = insert_url(&self.pool, &resolved).await?;
+        self.keep_original(url, &resolved).await?;
+        Ok(url_id)
+    }
+
+    async fn url_exists(&self, url: &str) -> Result<bool, sqlx::Error> {
+        url_exists(&self.pool, &self.resolve(url).await?).await
+    }
+
+    async fn get_url_counts(&self) -> Result<models::UrlCounts, sqlx::Error> {
+        get_url_counts(&self.pool).await
+    }
+
+    async fn get_url_with_tags(&self, url: &str) -> Result<Option<models::UrlWithTags>, sqlx::Error> {
+        get_url_with_tags(&self.pool, &self.resolve(url).await?)
+            .await?
+            .map(|url| crypto::open_url(self.cipher.as_ref(), url))
+            .transpose()
     }
 
-    async fn get_urls_with_tags(&self) -> Result<Vec<models::UrlWithTags>, sqlx::Error> {
-        get_urls_with_tags(&self.pool).await
+    async fn get_urls_with_tags(&self, filter: &models::ListFilter) -> Result<Vec<models::UrlWithTags>, sqlx::Error> {
+        get_urls_with_tags(&self.pool, filter)
+            .await?
+            .into_iter()
+            .map(|url| crypto::open_url(self.cipher.as_ref(), url))
+            .collect()
     }
 
-    async fn insert_snippet(&self, url: &str, snippet: &str, tags: &[&str]) -> Result<i32, sqlx::Error> {
-        insert_snippet(&self.pool, url, snippet, tags).await
+    async fn insert_snippet(
+        &self,
+        url: &str,
+        snippet: &str,
+        tags: &[&str],
+        source: Option<&models::SnippetSource>,
+    ) -> Result<i32, sqlx::Error> {
+        let html = self.sealed_html(snippet)?;
+        let snippet = crypto::seal(self.cipher.as_ref(), snippet)?;
+        let source = source
+            .map(|source| crypto::seal_source(self.cipher.as_ref(), source))
+            .transpose()?;
+        insert_snippet(
+            &self.pool,
+            &self.resolve(url).await?,
+            &snippet,
+            Some(&html),
+            tags,
+            source.as_ref(),
+        )
+        .await
+    }
+
+    async fn insert_image_snippet(
+        &self,
+        url: &str,
+        caption: &str,
+        tags: &[&str],
+        image: &models::SnippetImage,
+    ) -> Result<i32, sqlx::Error> {
+        let html = self.sealed_html(caption)?;
+        let caption = crypto::seal(self.cipher.as_ref(), caption)?;
+        let image = crypto::seal_image(self.cipher.as_ref(), image)?;
+        insert_image_snippet(
+            &self.pool,
+            &self.resolve(url).await?,
+            &caption,
+            Some(&html),
+            tags,
+            &image,
+        )
+        .await
+    }
+
+    async fn get_snippet_image(&self, snippet_id: i32) -> Result<Option<models::SnippetImage>, sqlx::Error> {
+        get_snippet_image(&self.pool, snippet_id)
+            .await?
+            .map(|image| crypto::open_image(self.cipher.as_ref(), image))
+            .transpose()
+    }
+
+    async fn get_listing_fingerprint(
+        &self,
+        filter: &models::ListFilter,
+    ) -> Result<models::ListingFingerprint, sqlx::Error> {
+        get_listing_fingerprint(&self.pool, filter).await
     }
 
-    async fn get_all_urls(&self) -> Result<Vec<models::Url>, sqlx::Error> {
-        get_all_urls(&self.pool).await
+    async fn get_all_urls(&self, filter: &models::ListFilter) -> Result<Vec<models::Url>, sqlx::Error> {
+        get_all_urls(&self.pool, filter).await
+    }
+
+    fn stream_exported_urls(&self) -> BoxStream<'_, Result<models::ExportedUrl, sqlx::Error>> {
+        stream_exported_urls(&self.pool)
+    }
+
+    async fn get_loose_url_matches(&self, url: &str) -> Result<Vec<String>, sqlx::Error> {
+        get_loose_url_matches(&self.pool, &self.resolve(url).await?).await
     }
 
     async fn delete_url_by_url(&self, url: &str) -> Result<(), sqlx::Error> {
-        delete_url_by_url(&self.pool, url).await
+        let url = self.resolve(url).await?;
+        let url_id = if snapshot_cache::enabled() {
+            get_url_with_tags(&self.pool, &url).await?.map(|saved| saved.id)
+        } else {
+            None
+        };
+        delete_url_by_url(&self.pool, &url).await?;
+        if let Some(url_id) = url_id {
+            snapshot_cache::forget_deleted(|url_id| get_snapshots(&self.pool, url_id), Some(url_id)).await;
+        }
+        Ok(())
+    }
+
+    async fn get_random_url(&self, filter: &models::ListFilter) -> Result<Option<models::Url>, sqlx::Error> {
+        get_random_url(&self.pool, filter).await
+    }
+
+    async fn get_next_url(&self, filter: &models::ListFilter) -> Result<Option<models::Url>, sqlx::Error> {
+        get_next_url(&self.pool, filter).await
+    }
+
+    async fn get_url_by_short_id(&self, short_id: &str) -> Result<Option<models::Url>, sqlx::Error> {
+        get_url_by_short_id(&self.pool, short_id).await
+    }
+
+    async fn set_url_read(&self, url: &str, read: bool) -> Result<(), sqlx::Error> {
+        set_url_read(&self.pool, &self.resolve(url).await?, read).await
+    }
+
+    async fn set_url_progress(&self, url: &str, progress: i32) -> Result<(), sqlx::Error> {
+        set_url_progress(&self.pool, &self.resolve(url).await?, progress).await
+    }
+
+    async fn set_url_metadata(&self, url: &str, metadata: &models::UrlMetadata) -> Result<(), sqlx::Error> {
+        set_url_metadata(&self.pool, &self.resolve(url).await?, metadata).await
+    }
+
+    async fn update_url(&self, url_id: i32, update: &models::UrlUpdate) -> Result<(), sqlx::Error> {
+        update_url(&self.pool, url_id, &crypto::seal_notes(self.cipher.as_ref(), update)?).await
+    }
+
+    async fn set_url_pinned(&self, url: &str, pinned: bool) -> Result<(), sqlx::Error> {
+        set_url_pinned(&self.pool, &self.resolve(url).await?, pinned).await
+    }
+
+    async fn set_snippet_pinned(&self, snippet_id: i32, pinned: bool) -> Result<(), sqlx::Error> {
+        set_snippet_pinned(&self.pool, snippet_id, pinned).await
     }
 
     async fn insert_tags(&self, url: &str, tags: &[&str]) -> Result<(), sqlx::Error> {
-        insert_tags(&self.pool, url, tags).await
+        let resolved = self.resolve(url).await?;
+        insert_tags(&self.pool, &resolved, tags).await?;
+        self.keep_original(url, &resolved).await
     }
 
-    async fn remove_unused_tags(&self) -> Result<(), sqlx::Error> {
-        remove_unused_tags(&self.pool).await
+    async fn set_url_tags(&self, url: &str, tags: &[&str]) -> Result<(), sqlx::Error> {
+        let resolved = self.resolve(url).await?;
+        set_url_tags(&self.pool, &resolved, tags).await?;
+        self.keep_original(url, &resolved).await
     }
 
     async fn delete_snippet(&self, snippet_id: i32) -> Result<(), sqlx::Error> {
         delete_snippet(&self.pool, snippet_id).await
     }
 
-    async fn get_snippets_with_tags(&self) -> Result<Vec<models::SnippetWithTags>, sqlx::Error> {
-        get_snippets_with_tags(&self.pool).await
+    async fn get_snippets_with_tags(
+        &self,
+        filter: &models::ListFilter,
+    ) -> Result<Vec<models::SnippetWithTags>, sqlx::Error> {
+        self.open_snippets(get_snippets_with_tags(&self.pool, filter).await?)
+    }
+
+    async fn get_snippets_for_url(&self, url: &str) -> Result<Vec<models::SnippetWithTags>, sqlx::Error> {
+        self.open_snippets(get_snippets_for_url(&self.pool, &self.resolve(url).await?).await?)
+    }
+
+    async fn get_snippet(&self, snippet_id: i32) -> Result<Option<models::SnippetWithTags>, sqlx::Error> {
+        let snippets = get_snippet(&self.pool, snippet_id).await?.into_iter().collect();
+        Ok(self.open_snippets(snippets)?.pop())
+    }
+
+    async fn get_snippets_saved_before(
+        &self,
+        cutoff: chrono::NaiveDateTime,
+    ) -> Result<Vec<models::SnippetWithTags>, sqlx::Error> {
+        self.open_snippets(get_snippets_saved_before(&self.pool, cutoff).await?)
     }
 
     async fn get_tags_with_urls_and_snippets(&self) -> Result<Vec<models::TagWithUrlsAndSnippets>, sqlx::Error> {
-        get_tags_with_urls_and_snippets(&self.pool).await
+        get_tags_with_urls_and_snippets(&self.pool)
+            .await?
+            .into_iter()
+            .map(|mut tag| {
+                tag.snippets = self.open_snippets(tag.snippets)?;
+                Ok(tag)
+            })
+            .collect()
+    }
+
+    async fn get_all_tags(&self) -> Result<Vec<String>, sqlx::Error> {
+        get_all_tags(&self.pool).await
+    }
+
+    async fn get_tag_counts(&self) -> Result<Vec<models::TagCount>, sqlx::Error> {
+        get_tag_counts(&self.pool).await
+    }
+
+    async fn get_tag_details(&self) -> Result<Vec<models::TagDetails>, sqlx::Error> {
+        get_tag_details(&self.pool).await
+    }
+
+    async fn get_tag(&self, tag: &str) -> Result<Option<models::TagDetails>, sqlx::Error> {
+        get_tag(&self.pool, tag).await
+    }
+
+    async fn set_tag_details(
+        &self,
+        tag: &str,
+        color: Option<&str>,
+        description: Option<&str>,
+    ) -> Result<(), sqlx::Error> {
+        set_tag_details(&self.pool, tag, color, description).await
+    }
+
+    async fn import_tags(&self, tags: &[models::TagDetails]) -> Result<models::TagImport, sqlx::Error> {
+        import_tags(&self.pool, tags).await
+    }
+
+    async fn record_activity(&self, action: &str, subject: &str, detail: Option<&str>) -> Result<(), sqlx::Error> {
+        record_activity(&self.pool, action, subject, detail).await
+    }
+
+    async fn get_activity(&self, limit: i64) -> Result<Vec<models::Activity>, sqlx::Error> {
+        get_activity(&self.pool, limit).await
+    }
+
+    async fn get_local_activity_since(&self, after_id: i32, limit: i64) -> Result<Vec<models::Activity>, sqlx::Error> {
+        get_local_activity_since(&self.pool, after_id, limit).await
+    }
+
+    async fn get_latest_activity(
+        &self,
+        subject: &str,
+        actions: &[&str],
+    ) -> Result<Option<models::Activity>, sqlx::Error> {
+        get_latest_activity(&self.pool, subject, actions).await
+    }
+
+    async fn get_activity_by_actions(&self, actions: &[&str]) -> Result<Vec<models::Activity>, sqlx::Error> {
+        get_activity_by_actions(&self.pool, actions).await
+    }
+
+    async fn record_peer_activity(&self, peer: &str, activity: &models::Activity) -> Result<(), sqlx::Error> {
+        record_peer_activity(&self.pool, peer, activity).await
+    }
+
+    async fn get_peer_cursor(&self, peer: &str) -> Result<i32, sqlx::Error> {
+        get_peer_cursor(&self.pool, peer).await
+    }
+
+    async fn set_peer_cursor(&self, peer: &str, cursor: i32) -> Result<(), sqlx::Error> {
+        set_peer_cursor(&self.pool, peer, cursor).await
+    }
+
+    async fn execute_batch(
+        &self,
+        operations: &[models::BatchOperation],
+    ) -> Result<Vec<Option<i32>>, models::BatchError> {
+        let mut resolved = Vec::with_capacity(operations.len());
+        for operation in operations {
+            let resolve = |url| async move { self.resolve(url).await.map_err(models::BatchError::transaction) };
+            resolved.push(match operation {
+                models::BatchOperation::AddUrl { url } => models::BatchOperation::AddUrl {
+                    url: resolve(url).await?,
+                },
+                models::BatchOperation::TagUrl { url, tags } => models::BatchOperation::TagUrl {
+                    url: resolve(url).await?,
+                    tags: tags.clone(),
+                },
+                models::BatchOperation::DeleteSnippet { id } => models::BatchOperation::DeleteSnippet { id: *id },
+            });
+        }
+        let ids = execute_batch(&self.pool, &resolved).await?;
+
+        for (operation, resolved) in operations.iter().zip(&resolved) {
+            if let (
+                models::BatchOperation::AddUrl { url } | models::BatchOperation::TagUrl { url, .. },
+                models::BatchOperation::AddUrl { url: resolved } | models::BatchOperation::TagUrl { url: resolved, .. },
+            ) = (operation, resolved)
+            {
+                self.keep_original(url, resolved)
+                    .await
+                    .map_err(models::BatchError::transaction)?;
+            }
+        }
+        Ok(ids)
+    }
+
+    async fn create_import_job(&self, source: &str, items: &[models::ImportItem]) -> Result<i32, sqlx::Error> {
+        let items = crypto::seal_import_items(self.cipher.as_ref(), items)?;
+        create_import_job(&self.pool, source, &items).await
+    }
+
+    async fn get_import_job(&self, id: i32) -> Result<Option<models::ImportJob>, sqlx::Error> {
+        get_import_job(&self.pool, id).await
+    }
+
+    async fn get_running_import_jobs(&self) -> Result<Vec<i32>, sqlx::Error> {
+        get_running_import_jobs(&self.pool).await
+    }
+
+    async fn get_pending_import_items(
+        &self,
+        job_id: i32,
+        limit: i64,
+    ) -> Result<Vec<models::PendingImportItem>, sqlx::Error> {
+        crypto::open_import_items(
+            self.cipher.as_ref(),
+            get_pending_import_items(&self.pool, job_id, limit).await?,
+        )
+    }
+
+    async fn start_import_item(&self, job_id: i32, item_id: i32) -> Result<(), sqlx::Error> {
+        start_import_item(&self.pool, job_id, item_id).await
+    }
+
+    async fn finish_import_item(
+        &self,
+        job_id: i32,
+        item_id: i32,
+        outcome: &models::ImportOutcome,
+    ) -> Result<(), sqlx::Error> {
+        finish_import_item(&self.pool, job_id, item_id, outcome).await
+    }
+
+    async fn complete_import_job(&self, id: i32) -> Result<(), sqlx::Error> {
+        complete_import_job(&self.pool, id).await
+    }
+
+    async fn insert_snapshot(
+        &self,
+        url_id: i32,
+        content_type: Option<&str>,
+        content: &str,
+        truncated: bool,
+        keep: i64,
+    ) -> Result<Option<i32>, sqlx::Error> {
+        let id = insert_snapshot(&self.pool, url_id, content_type, content, truncated, keep).await?;
+        // Taking one may have dropped the oldest past the limit
+        if id.is_some() {
+            snapshot_cache::forget_deleted(|url_id| get_snapshots(&self.pool, url_id), Some(url_id)).await;
+        }
+        Ok(id)
+    }
+
+    async fn get_snapshots(&self, url_id: i32) -> Result<Vec<models::Snapshot>, sqlx::Error> {
+        get_snapshots(&self.pool, url_id).await
+    }
+
+    async fn get_snapshot(&self, url_id: i32, id: i32) -> Result<Option<models::SnapshotContent>, sqlx::Error> {
+        get_snapshot(&self.pool, url_id, id).await
+    }
+
+    async fn touch_snapshot(&self, url_id: i32, id: i32) -> Result<Option<chrono::NaiveDateTime>, sqlx::Error> {
+        touch_snapshot(&self.pool, url_id, id).await
+    }
+
+    async fn get_snapshotted_urls(&self) -> Result<Vec<models::Url>, sqlx::Error> {
+        get_snapshotted_urls(&self.pool).await
+    }
+
+    async fn get_url_metadata_entries(&self, url_id: i32) -> Result<BTreeMap<String, serde_json::Value>, sqlx::Error> {
+        get_url_metadata_entries(&self.pool, url_id).await
+    }
+
+    async fn get_url_metadata_entry(&self, url_id: i32, key: &str) -> Result<Option<serde_json::Value>, sqlx::Error> {
+        get_url_metadata_entry(&self.pool, url_id, key).await
+    }
+
+    async fn set_url_metadata_entry(
+        &self,
+        url_id: i32,
+        key: &str,
+        value: &serde_json::Value,
+    ) -> Result<(), sqlx::Error> {
+        set_url_metadata_entry(&self.pool, url_id, key, value).await
+    }
+
+    async fn archive_unread_before(
+        &self,
+        cutoff: chrono::NaiveDateTime,
+        dry_run: bool,
+    ) -> Result<Vec<String>, sqlx::Error> {
+        archive_unread_before(&self.pool, cutoff, dry_run).await
+    }
+
+    async fn archive_read_before(
+        &self,
+        cutoff: chrono::NaiveDateTime,
+        dry_run: bool,
+    ) -> Result<Vec<String>, sqlx::Error> {
+        archive_read_before(&self.pool, cutoff, dry_run).await
+    }
+
+    async fn delete_read_before(
+        &self,
+        cutoff: chrono::NaiveDateTime,
+        dry_run: bool,
+    ) -> Result<Vec<String>, sqlx::Error> {
+        let deleted = delete_read_before(&self.pool, cutoff, dry_run).await?;
+        if !dry_run && !deleted.is_empty() {
+            snapshot_cache::forget_deleted(|url_id| get_snapshots(&self.pool, url_id), None).await;
+        }
+        Ok(deleted)
+    }
+
+    async fn evict_snapshots(
+        &self,
+        max_bytes: i64,
+        dry_run: bool,
+    ) -> Result<Vec<models::EvictedSnapshot>, sqlx::Error> {
+        let evicted = evict_snapshots(&self.pool, max_bytes, dry_run).await?;
+        if !dry_run {
+            let ids: Vec<i32> = evicted.iter().map(|snapshot| snapshot.id).collect();
+            snapshot_cache::forget(&ids).await;
+        }
+        Ok(evicted)
+    }
+
+    async fn adopt_canonical_url(
+        &self,
+        url_id: i32,
+        canonical: &str,
+    ) -> Result<Option<models::CleanedUrl>, sqlx::Error> {
+        let moved = adopt_canonical_url(&self.pool, url_id, &self.normalize(canonical)).await?;
+        self.forget_merged_snapshots(url_id, moved.as_ref()).await;
+        Ok(moved)
+    }
+
+    async fn move_url(&self, url_id: i32, url: &str) -> Result<Option<models::CleanedUrl>, sqlx::Error> {
+        let moved = move_url(&self.pool, url_id, &self.normalize(url)).await?;
+        self.forget_merged_snapshots(url_id, moved.as_ref()).await;
+        Ok(moved)
+    }
+
+    async fn clean_urls(&self, dry_run: bool) -> Result<Vec<models::CleanedUrl>, sqlx::Error> {
+        let cleaned = clean_urls(&self.pool, &|url| self.normalize(url), dry_run).await?;
+        if !dry_run && cleaned.iter().any(|url| url.merged) {
+            snapshot_cache::forget_deleted(|url_id| get_snapshots(&self.pool, url_id), None).await;
+        }
+        Ok(cleaned)
+    }
+
+    async fn repair_snippet_tags(&self, dry_run: bool) -> Result<Vec<i32>, sqlx::Error> {
+        repair_snippet_tags(&self.pool, dry_run).await
+    }
+
+    async fn delete_all_data(&self) -> Result<(), sqlx::Error> {
+        delete_all_data(&self.pool).await
+    }
+
+    async fn read_rows(
+        &self,
+        table: &models::CopiedTable,
+        offset: i64,
+        limit: i64,
+    ) -> Result<Vec<Vec<models::ColumnValue>>, sqlx::Error> {
+        read_rows(&self.pool, table, offset, limit).await
+    }
+
+    async fn write_rows(
+        &self,
+        table: &models::CopiedTable,
+        rows: &[Vec<models::ColumnValue>],
+    ) -> Result<(), sqlx::Error> {
+        write_rows(&self.pool, table, rows).await
     }
 }
 
@@ -101,11 +631,112 @@ pub async fn create_urls_table(db_pool: &SqlitePool) -> Result<(), Error> {
             id INTEGER PRIMARY KEY AUTOINCREMENT,
             datetime TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
             url TEXT NOT NULL,
-            url_hash CHAR(64) NOT NULL UNIQUE
+            url_hash CHAR(64) NOT NULL UNIQUE,
+            read_at TIMESTAMP,
+            pinned BOOLEAN NOT NULL DEFAULT FALSE,
+            progress INTEGER NOT NULL DEFAULT 0,
+            original_url TEXT,
+            metadata TEXT,
+            title TEXT,
+            notes TEXT,
+            archived_at TIMESTAMP,
+            snoozed_until TIMESTAMP,
+            normalized_url TEXT,
+            revision INTEGER NOT NULL DEFAULT 0,
+            short_id TEXT
         )
     "#;
 
     sqlx::query(query).execute(db_pool).await?;
+
+    // Columns added after the first release
+    add_column_if_missing(db_pool, "urls", "read_at", "TIMESTAMP").await?;
+    add_column_if_missing(db_pool, "urls", "pinned", "BOOLEAN NOT NULL DEFAULT FALSE").await?;
+    add_column_if_missing(db_pool, "urls", "progress", "INTEGER NOT NULL DEFAULT 0").await?;
+    add_column_if_missing(db_pool, "urls", "original_url", "TEXT").await?;
+    add_column_if_missing(db_pool, "urls", "metadata", "TEXT").await?;
+    add_column_if_missing(db_pool, "urls", "title", "TEXT").await?;
+    add_column_if_missing(db_pool, "urls", "notes", "TEXT").await?;
+    add_column_if_missing(db_pool, "urls", "archived_at", "TIMESTAMP").await?;
+    add_column_if_missing(db_pool, "urls", "snoozed_until", "TIMESTAMP").await?;
+    add_column_if_missing(db_pool, "urls", "normalized_url", "TEXT").await?;
+    add_column_if_missing(db_pool, "urls", "revision", "INTEGER NOT NULL DEFAULT 0").await?;
+    add_column_if_missing(db_pool, "urls", "short_id", "TEXT").await?;
+    fill_normalized_urls(db_pool).await?;
+    // Rows saved before short ids were stored had them worked out from the hash as it is now
+    sqlx::query("UPDATE urls SET short_id = SUBSTR(url_hash, 1, 10) WHERE short_id IS NULL")
+        .execute(db_pool)
+        .await?;
+
+    // Every change to a row counts up its revision, so listing fingerprints see edits that record no activity. Only
+    // updates that change something count, so saving a URL again leaves it alone; it was counted up on every update
+    // before, so it is replaced rather than kept.
+    let changed = URL_REVISED_COLUMNS
+        .iter()
+        .map(|column| format!("NEW.{column} IS NOT OLD.{column}"))
+        .collect::<Vec<_>>()
+        .join(" OR ");
+    sqlx::query("DROP TRIGGER IF EXISTS urls_revised")
+        .execute(db_pool)
+        .await?;
+    let query = format!(
+        "CREATE TRIGGER urls_revised AFTER UPDATE ON urls WHEN NEW.revision = OLD.revision AND ({changed}) \
+         BEGIN UPDATE urls SET revision = OLD.revision + 1 WHERE id = NEW.id; END"
+    );
+    sqlx::query(&query).execute(db_pool).await?;
+    Ok(())
+}
+
+/// The columns of `urls` whose changes count up its revision: all but the revision itself
+const URL_REVISED_COLUMNS: [&str; 14] = [
+    "datetime",
+    "url",
+    "url_hash",
+    "read_at",
+    "pinned",
+    "progress",
+    "original_url",
+    "metadata",
+    "title",
+    "notes",
+    "archived_at",
+    "snoozed_until",
+    "normalized_url",
+    "short_id",
+];
+
+/// Work out `normalized_url` for rows saved before the column was added
+async fn fill_normalized_urls(db_pool: &SqlitePool) -> Result<(), Error> {
+    let rows: Vec<(i32, String)> = sqlx::query_as("SELECT id, url FROM urls WHERE normalized_url IS NULL")
+        .fetch_all(db_pool)
+        .await?;
+    if rows.is_empty() {
+        return Ok(());
+    }
+    let mut tx = db_pool.begin().await?;
+    for (id, url) in rows {
+        sqlx::query("UPDATE urls SET normalized_url = ? WHERE id = ?")
+            .bind(models::loose_url(&url))
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await
+}
+
+/// Add `column` to an existing `table` unless it is already there
+async fn add_column_if_missing(db_pool: &SqlitePool, table: &str, column: &str, definition: &str) -> Result<(), Error> {
+    let exists: bool = sqlx::query_scalar("SELECT COUNT(*) > 0 FROM pragma_table_info(?) WHERE name = ?")
+        .bind(table)
+        .bind(column)
+        .fetch_one(db_pool)
+        .await?;
+
+    if !exists {
+        sqlx::query(&format!("ALTER TABLE {table} ADD COLUMN {column} {definition}"))
+            .execute(db_pool)
+            .await?;
+    }
     Ok(())
 }
 
@@ -114,11 +745,17 @@ pub async fn create_tags_table(db_pool: &SqlitePool) -> Result<(), Error> {
     let query = r#"
         CREATE TABLE IF NOT EXISTS tags (
             id INTEGER PRIMARY KEY AUTOINCREMENT,
-            tag TEXT NOT NULL UNIQUE
+            tag TEXT NOT NULL UNIQUE,
+            color TEXT,
+            description TEXT
         )
     "#;
 
     sqlx::query(query).execute(db_pool).await?;
+
+    // Columns added after the first release
+    add_column_if_missing(db_pool, "tags", "color", "TEXT").await?;
+    add_column_if_missing(db_pool, "tags", "description", "TEXT").await?;
     Ok(())
 }
 
@@ -134,6 +771,19 @@ pub async fn create_url_tags_table(db_pool: &SqlitePool) -> Result<(), Error> {
     "#;
 
     sqlx::query(query).execute(db_pool).await?;
+
+    // Tagging or untagging a URL counts up its revision too, as the tags are part of what a listing shows
+    for (event, row) in [("INSERT", "NEW"), ("DELETE", "OLD")] {
+        let query = format!(
+            r#"
+            CREATE TRIGGER IF NOT EXISTS url_tags_revised_on_{event} AFTER {event} ON url_tags
+            BEGIN
+                UPDATE urls SET revision = revision + 1 WHERE id = {row}.url_id;
+            END
+            "#
+        );
+        sqlx::query(&query).execute(db_pool).await?;
+    }
     Ok(())
 }
 
@@ -144,7 +794,29 @@ pub async fn create_snippets_table(db_pool: &SqlitePool) -> Result<(), Error> {
             id INTEGER PRIMARY KEY AUTOINCREMENT,
             url TEXT NOT NULL,
             snippet TEXT NOT NULL,
-            tags TEXT
+            tags TEXT,
+            pinned BOOLEAN NOT NULL DEFAULT FALSE,
+            source TEXT
+        )
+    "#;
+
+    sqlx::query(query).execute(db_pool).await?;
+
+    // Columns added after the first release
+    add_column_if_missing(db_pool, "snippets", "pinned", "BOOLEAN NOT NULL DEFAULT FALSE").await?;
+    add_column_if_missing(db_pool, "snippets", "source", "TEXT").await?;
+    add_column_if_missing(db_pool, "snippets", "saved_at", "TIMESTAMP").await?;
+    add_column_if_missing(db_pool, "snippets", "snippet_html", "TEXT").await?;
+    Ok(())
+}
+
+/// Create the `snippet_images` table holding the picture of each image snippet
+pub async fn create_snippet_images_table(db_pool: &SqlitePool) -> Result<(), Error> {
+    let query = r#"
+        CREATE TABLE IF NOT EXISTS snippet_images (
+            snippet_id INTEGER PRIMARY KEY REFERENCES snippets(id) ON DELETE CASCADE,
+            content_type TEXT NOT NULL,
+            data BLOB NOT NULL
         )
     "#;
 
@@ -167,6 +839,133 @@ pub async fn create_snippet_tags_table(db_pool: &SqlitePool) -> Result<(), Error
     Ok(())
 }
 
+/// Create the `tag_counts` table, with triggers keeping it in step with `url_tags` and `snippet_tags`.
+///
+/// The counts are rebuilt from scratch here, which picks up tags saved before the triggers existed.
+pub async fn create_tag_counts_table(db_pool: &SqlitePool) -> Result<(), Error> {
+    let query = r#"
+        CREATE TABLE IF NOT EXISTS tag_counts (
+            tag_id INTEGER PRIMARY KEY REFERENCES tags(id) ON DELETE CASCADE,
+            url_count INTEGER NOT NULL DEFAULT 0,
+            snippet_count INTEGER NOT NULL DEFAULT 0
+        )
+    "#;
+    sqlx::query(query).execute(db_pool).await?;
+
+    for (table, column) in [("url_tags", "url_count"), ("snippet_tags", "snippet_count")] {
+        let added = format!(
+            r#"
+            CREATE TRIGGER IF NOT EXISTS {table}_counted AFTER INSERT ON {table}
+            BEGIN
+                INSERT INTO tag_counts (tag_id, {column}) VALUES (NEW.tag_id, 1)
+                ON CONFLICT (tag_id) DO UPDATE SET {column} = {column} + 1;
+            END
+            "#
+        );
+        sqlx::query(&added).execute(db_pool).await?;
+
+        let removed = format!(
+            r#"
+            CREATE TRIGGER IF NOT EXISTS {table}_uncounted AFTER DELETE ON {table}
+            BEGIN
+                UPDATE tag_counts SET {column} = {column} - 1 WHERE tag_id = OLD.tag_id;
+            END
+            "#
+        );
+        sqlx::query(&removed).execute(db_pool).await?;
+    }
+
+    let mut tx = db_pool.begin().await?;
+    sqlx::query("DELETE FROM tag_counts").execute(&mut *tx).await?;
+    let query = r#"
+        INSERT INTO tag_counts (tag_id, url_count, snippet_count)
+        SELECT
+            tags.id,
+            (SELECT COUNT(*) FROM url_tags WHERE url_tags.tag_id = tags.id),
+            (SELECT COUNT(*) FROM snippet_tags WHERE snippet_tags.tag_id = tags.id)
+        FROM tags
+    "#;
+    sqlx::query(query).execute(&mut *tx).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Create the `activity` table backing the activity timeline
+pub async fn create_activity_table(db_pool: &SqlitePool) -> Result<(), Error> {
+    let query = r#"
+        CREATE TABLE IF NOT EXISTS activity (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            datetime TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            action TEXT NOT NULL,
+            subject TEXT NOT NULL,
+            detail TEXT
+        )
+    "#;
+    sqlx::query(query).execute(db_pool).await?;
+
+    // The peer a change was applied from; NULL for changes made here
+    add_column_if_missing(db_pool, "activity", "origin", "TEXT").await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS activity_subject ON activity (subject)")
+        .execute(db_pool)
+        .await?;
+    Ok(())
+}
+
+/// Create the `peers` table recording how far through each peer's changes have been applied
+pub async fn create_peers_table(db_pool: &SqlitePool) -> Result<(), Error> {
+    let query = r#"
+        CREATE TABLE IF NOT EXISTS peers (
+            url TEXT PRIMARY KEY,
+            last_change_id INTEGER NOT NULL DEFAULT 0,
+            synced_at TIMESTAMP
+        )
+    "#;
+
+    sqlx::query(query).execute(db_pool).await?;
+    Ok(())
+}
+
+/// Create the `import_jobs` table and the `import_job_items` queue behind it
+pub async fn create_import_job_tables(db_pool: &SqlitePool) -> Result<(), Error> {
+    let query = r#"
+        CREATE TABLE IF NOT EXISTS import_jobs (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            source TEXT NOT NULL,
+            status TEXT NOT NULL DEFAULT 'running',
+            total INTEGER NOT NULL,
+            processed INTEGER NOT NULL DEFAULT 0,
+            created INTEGER NOT NULL DEFAULT 0,
+            skipped INTEGER NOT NULL DEFAULT 0,
+            failed INTEGER NOT NULL DEFAULT 0,
+            started_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            finished_at TIMESTAMP
+        )
+    "#;
+    sqlx::query(query).execute(db_pool).await?;
+
+    // `outcome` stays NULL until the item is processed; tags are a JSON array. `started` is set just before its URL
+    // is saved, so a job resumed after a crash finishes the item rather than skipping its own URL.
+    let query = r#"
+        CREATE TABLE IF NOT EXISTS import_job_items (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            job_id INTEGER NOT NULL REFERENCES import_jobs(id) ON DELETE CASCADE,
+            url TEXT NOT NULL,
+            tags TEXT NOT NULL,
+            note TEXT,
+            outcome TEXT,
+            error TEXT,
+            started BOOLEAN NOT NULL DEFAULT FALSE
+        )
+    "#;
+    sqlx::query(query).execute(db_pool).await?;
+    add_column_if_missing(db_pool, "import_job_items", "started", "BOOLEAN NOT NULL DEFAULT FALSE").await?;
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS import_job_items_pending ON import_job_items (job_id, outcome)")
+        .execute(db_pool)
+        .await?;
+    Ok(())
+}
+
 /// Initialize all database tables
 pub async fn initialize_tables(db_pool: &SqlitePool) -> Result<(), Error> {
     create_urls_table(db_pool).await?;
@@ -174,39 +973,173 @@ pub async fn initialize_tables(db_pool: &SqlitePool) -> Result<(), Error> {
     create_url_tags_table(db_pool).await?;
     create_snippets_table(db_pool).await?;
     create_snippet_tags_table(db_pool).await?;
+    create_snippet_images_table(db_pool).await?;
+    create_tag_counts_table(db_pool).await?;
+    create_activity_table(db_pool).await?;
+    create_peers_table(db_pool).await?;
+    create_import_job_tables(db_pool).await?;
+    create_snapshots_table(db_pool).await?;
+    create_url_aliases_table(db_pool).await?;
+    create_url_metadata_table(db_pool).await?;
+    create_indexes(db_pool).await?;
     Ok(())
 }
 
-/// Hash a URL to create a unique identifier
-fn calculate_url_hash(url: &str) -> String {
-    let mut hasher = Sha256::new();
-    hasher.update(url);
-    format!("{:x}", hasher.finalize()) // Convert to a hexadecimal string
+/// Indexes behind the listing queries: saved-date ranges, the tag filters' lookups from a tag to what carries
+/// it, and a URL's snippets. Lookups the other way are covered by the link tables' unique constraints.
+const INDEXES: [(&str, &str); 6] = [
+    ("urls_datetime", "urls (datetime)"),
+    ("url_tags_tag", "url_tags (tag_id, url_id)"),
+    ("snippet_tags_tag", "snippet_tags (tag_id, snippet_id)"),
+    ("snippets_url", "snippets (url)"),
+    ("urls_normalized_url", "urls (normalized_url)"),
+    ("urls_short_id", "urls (short_id)"),
+];
+
+/// Create any of `INDEXES` that don't exist yet
+pub async fn create_indexes(db_pool: &SqlitePool) -> Result<(), Error> {
+    for (name, columns) in INDEXES {
+        sqlx::query(&format!("CREATE INDEX IF NOT EXISTS {name} ON {columns}"))
+            .execute(db_pool)
+            .await?;
+    }
+    Ok(())
 }
 
-/// Insert a URL into the database
-pub async fn insert_url(db_pool: &SqlitePool, url: &str) -> Result<i32, Error> {
+/// Create the `url_snapshots` table of fetched page copies
+pub async fn create_snapshots_table(db_pool: &SqlitePool) -> Result<(), Error> {
+    let query = r#"
+        CREATE TABLE IF NOT EXISTS url_snapshots (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            url_id INTEGER NOT NULL REFERENCES urls(id) ON DELETE CASCADE,
+            datetime TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            content_type TEXT,
+            content TEXT NOT NULL,
+            accessed_at TIMESTAMP,
+            truncated BOOLEAN NOT NULL DEFAULT FALSE
+        )
+    "#;
+    sqlx::query(query).execute(db_pool).await?;
+
+    add_column_if_missing(db_pool, "url_snapshots", "accessed_at", "TIMESTAMP").await?;
+    add_column_if_missing(db_pool, "url_snapshots", "truncated", "BOOLEAN NOT NULL DEFAULT FALSE").await?;
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS url_snapshots_url ON url_snapshots (url_id, id)")
+        .execute(db_pool)
+        .await?;
+    Ok(())
+}
+
+/// Create the `url_aliases` table of other addresses for a saved URL, such as the one it was saved under
+/// before its canonical URL was known
+pub async fn create_url_aliases_table(db_pool: &SqlitePool) -> Result<(), Error> {
+    let query = r#"
+        CREATE TABLE IF NOT EXISTS url_aliases (
+            alias_hash CHAR(64) PRIMARY KEY,
+            url_id INTEGER NOT NULL REFERENCES urls(id) ON DELETE CASCADE
+        )
+    "#;
+    sqlx::query(query).execute(db_pool).await?;
+    Ok(())
+}
+
+/// Create the `url_metadata` table of named JSON values about a saved URL, for details that don't warrant a
+/// column of their own
+pub async fn create_url_metadata_table(db_pool: &SqlitePool) -> Result<(), Error> {
+    let query = r#"
+        CREATE TABLE IF NOT EXISTS url_metadata (
+            url_id INTEGER NOT NULL REFERENCES urls(id) ON DELETE CASCADE,
+            key TEXT NOT NULL,
+            value TEXT NOT NULL,
+            updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            PRIMARY KEY (url_id, key)
+        )
+    "#;
+    sqlx::query(query).execute(db_pool).await?;
+    Ok(())
+}
+
+/// Hash a URL to create a unique identifier
+fn calculate_url_hash(url: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(url);
+    format!("{:x}", hasher.finalize()) // Convert to a hexadecimal string
+}
+
+/// Whether a URL (by hash) is already saved
+pub async fn url_exists(db_pool: &SqlitePool, url: &str) -> Result<bool, Error> {
+    sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM urls WHERE url_hash = ?)")
+        .bind(calculate_url_hash(url))
+        .fetch_one(db_pool)
+        .await
+}
+
+/// Saved URLs with the same `loose_url` as `url`, oldest first
+pub async fn get_loose_url_matches(db_pool: &SqlitePool, url: &str) -> Result<Vec<String>, Error> {
+    sqlx::query_scalar("SELECT url FROM urls WHERE normalized_url = ? ORDER BY id")
+        .bind(models::loose_url(url))
+        .fetch_all(db_pool)
+        .await
+}
+
+/// Count the saved URLs and the unread ones among them
+pub async fn get_url_counts(db_pool: &SqlitePool) -> Result<models::UrlCounts, Error> {
+    let query = r#"
+        SELECT COUNT(*) AS total, COALESCE(SUM(CASE WHEN read_at IS NULL THEN 1 ELSE 0 END), 0) AS unread_count
+        FROM urls
+    "#;
+
+    sqlx::query_as::<_, models::UrlCounts>(query).fetch_one(db_pool).await
+}
+
+/// Insert a URL into the database
+pub async fn insert_url(db_pool: &SqlitePool, url: &str) -> Result<i32, Error> {
+    insert_url_in(&mut *db_pool.acquire().await?, url).await
+}
+
+/// Insert a URL using an existing connection or transaction
+async fn insert_url_in(conn: &mut SqliteConnection, url: &str) -> Result<i32, Error> {
     let url_hash = calculate_url_hash(url);
 
     // Try to insert the URL and return its ID. If it already exists, fetch the existing ID.
     let query = r#"
-        INSERT INTO urls (url, url_hash)
-        VALUES (?, ?)
+        INSERT INTO urls (url, url_hash, normalized_url, short_id)
+        VALUES (?, ?, ?, ?)
         ON CONFLICT (url_hash) DO UPDATE SET url_hash = urls.url_hash
         RETURNING id
     "#;
+    let short_id = free_short_id(&mut *conn, &url_hash).await?;
 
     let url_id: i32 = sqlx::query_scalar(query)
         .bind(url)
-        .bind(url_hash)
-        .fetch_one(db_pool)
+        .bind(&url_hash)
+        .bind(models::loose_url(url))
+        .bind(short_id)
+        .fetch_one(&mut *conn)
         .await?;
 
     Ok(url_id)
 }
 
+/// A short id for a URL about to be saved: the start of its hash, or in the rare case another URL already has that,
+/// the first later stretch of the hash nobody has. It is kept from then on, even when the URL moves.
+async fn free_short_id(conn: &mut SqliteConnection, url_hash: &str) -> Result<String, Error> {
+    for start in 0..=url_hash.len().saturating_sub(models::SHORT_ID_LENGTH) {
+        let candidate = &url_hash[start..start + models::SHORT_ID_LENGTH];
+        let taken: bool = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM urls WHERE short_id = ? AND url_hash <> ?)")
+            .bind(candidate)
+            .bind(url_hash)
+            .fetch_one(&mut *conn)
+            .await?;
+        if !taken {
+            return Ok(candidate.to_string());
+        }
+    }
+    Ok(url_hash[..models::SHORT_ID_LENGTH].to_string())
+}
+
 /// Helper: Insert or fetch a tag ID
-async fn get_or_create_tag(db_pool: &SqlitePool, tag: &str) -> Result<i32, Error> {
+async fn get_or_create_tag(conn: &mut SqliteConnection, tag: &str) -> Result<i32, Error> {
     match sqlx::query_scalar::<_, i32>(
         r#"
         INSERT INTO tags (tag)
@@ -216,7 +1149,7 @@ async fn get_or_create_tag(db_pool: &SqlitePool, tag: &str) -> Result<i32, Error
         "#,
     )
     .bind(tag)
-    .fetch_optional(db_pool)
+    .fetch_optional(&mut *conn)
     .await?
     {
         Some(id) => Ok(id),
@@ -224,7 +1157,7 @@ async fn get_or_create_tag(db_pool: &SqlitePool, tag: &str) -> Result<i32, Error
             // If the tag exists, fetch its ID
             sqlx::query_scalar("SELECT id FROM tags WHERE tag = ?")
                 .bind(tag)
-                .fetch_one(db_pool)
+                .fetch_one(conn)
                 .await
         }
     }
@@ -232,7 +1165,7 @@ async fn get_or_create_tag(db_pool: &SqlitePool, tag: &str) -> Result<i32, Error
 
 /// Helper: Link a tag to a snippet or URL
 async fn link_to_tag(
-    db_pool: &SqlitePool,
+    conn: &mut SqliteConnection,
     tag_id: i32,
     target_id: i32,
     table: &str,
@@ -248,112 +1181,429 @@ async fn link_to_tag(
         column = column
     );
 
-    sqlx::query(&query)
-        .bind(target_id)
-        .bind(tag_id)
-        .execute(db_pool)
-        .await?;
+    sqlx::query(&query).bind(target_id).bind(tag_id).execute(conn).await?;
     Ok(())
 }
 
-/// Insert a snippet into the database
-pub async fn insert_snippet(db_pool: &SqlitePool, url: &str, snippet: &str, tags: &[&str]) -> Result<i32, Error> {
-    let tags_json = serde_json::to_string(tags).unwrap_or("[]".to_string());
+/// Insert a snippet into the database, with the page it was taken from when there is one
+pub async fn insert_snippet(
+    db_pool: &SqlitePool,
+    url: &str,
+    snippet: &str,
+    snippet_html: Option<&str>,
+    tags: &[&str],
+    source: Option<&models::SnippetSource>,
+) -> Result<i32, Error> {
+    insert_snippet_in(&mut *db_pool.acquire().await?, url, snippet, snippet_html, tags, source).await
+}
+
+/// Insert a snippet using an existing connection or transaction
+async fn insert_snippet_in(
+    conn: &mut SqliteConnection,
+    url: &str,
+    snippet: &str,
+    snippet_html: Option<&str>,
+    tags: &[&str],
+    source: Option<&models::SnippetSource>,
+) -> Result<i32, Error> {
+    let tags = models::unique_tags(tags.iter().copied());
+    let tags_json = serde_json::to_string(&tags).unwrap_or("[]".to_string());
+    let source = source
+        .map(serde_json::to_string)
+        .transpose()
+        .map_err(|err| Error::Encode(Box::new(err)))?;
 
     // Insert the snippet
     let snippet_id: i32 = sqlx::query_scalar(
         r#"
-        INSERT INTO snippets (url, snippet, tags)
-        VALUES (?, ?, ?)
+        INSERT INTO snippets (url, snippet, snippet_html, tags, source, saved_at)
+        VALUES (?, ?, ?, ?, ?, CURRENT_TIMESTAMP)
         RETURNING id
         "#,
     )
     .bind(url)
     .bind(snippet)
+    .bind(snippet_html)
     .bind(tags_json)
-    .fetch_one(db_pool)
+    .bind(source)
+    .fetch_one(&mut *conn)
     .await?;
 
     // Link tags to the snippet
     for tag in tags {
-        let tag_id = get_or_create_tag(db_pool, tag).await?;
-        link_to_tag(db_pool, tag_id, snippet_id, "snippet_tags", "snippet_id").await?;
+        let tag_id = get_or_create_tag(conn, tag).await?;
+        link_to_tag(conn, tag_id, snippet_id, "snippet_tags", "snippet_id").await?;
     }
 
     Ok(snippet_id)
 }
 
+/// Insert a snippet of an image, with `caption` as its text
+pub async fn insert_image_snippet(
+    db_pool: &SqlitePool,
+    url: &str,
+    caption: &str,
+    caption_html: Option<&str>,
+    tags: &[&str],
+    image: &models::SnippetImage,
+) -> Result<i32, Error> {
+    let mut tx = db_pool.begin().await?;
+    let snippet_id = insert_snippet_in(&mut tx, url, caption, caption_html, tags, None).await?;
+    sqlx::query("INSERT INTO snippet_images (snippet_id, content_type, data) VALUES (?, ?, ?)")
+        .bind(snippet_id)
+        .bind(&image.content_type)
+        .bind(&image.data)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(snippet_id)
+}
+
+/// Fetch the image of an image snippet, `None` when the snippet is text or doesn't exist
+pub async fn get_snippet_image(db_pool: &SqlitePool, snippet_id: i32) -> Result<Option<models::SnippetImage>, Error> {
+    sqlx::query_as::<_, models::SnippetImage>("SELECT content_type, data FROM snippet_images WHERE snippet_id = ?")
+        .bind(snippet_id)
+        .fetch_optional(db_pool)
+        .await
+}
+
 /// Insert tags for a URL
 pub async fn insert_tags(db_pool: &SqlitePool, url: &str, tags: &[&str]) -> Result<(), Error> {
+    insert_tags_in(&mut *db_pool.acquire().await?, url, tags).await
+}
+
+/// Replace a URL's tags with `tags`, saving the URL if it isn't yet
+pub async fn set_url_tags(db_pool: &SqlitePool, url: &str, tags: &[&str]) -> Result<(), Error> {
+    let mut tx = db_pool.begin().await?;
+    let url_id = insert_url_in(&mut tx, url).await?;
+    sqlx::query("DELETE FROM url_tags WHERE url_id = ?")
+        .bind(url_id)
+        .execute(&mut *tx)
+        .await?;
+    insert_tags_in(&mut tx, url, tags).await?;
+    tx.commit().await
+}
+
+/// Insert tags for a URL using an existing connection or transaction
+async fn insert_tags_in(conn: &mut SqliteConnection, url: &str, tags: &[&str]) -> Result<(), Error> {
     if tags.is_empty() {
         return Ok(()); // Nothing to insert
     }
 
     // Insert or retrieve the URL ID
-    let url_id = insert_url(db_pool, url).await?;
+    let url_id = insert_url_in(conn, url).await?;
 
     // Link tags to the URL
     for tag in tags {
-        let tag_id = get_or_create_tag(db_pool, tag).await?;
-        link_to_tag(db_pool, tag_id, url_id, "url_tags", "url_id").await?;
+        let tag_id = get_or_create_tag(conn, tag).await?;
+        link_to_tag(conn, tag_id, url_id, "url_tags", "url_id").await?;
     }
 
     Ok(())
 }
 
 /// Fetch all snippets with their associated tags
-pub async fn get_snippets_with_tags(db_pool: &SqlitePool) -> Result<Vec<models::SnippetWithTags>, Error> {
-    let query = r#"
-        SELECT id, snippet, url, tags
-        FROM snippets
-        ORDER BY id DESC
-    "#;
+pub async fn get_snippets_with_tags(
+    db_pool: &SqlitePool,
+    filter: &models::ListFilter,
+) -> Result<Vec<models::SnippetWithTags>, Error> {
+    let mut query = QueryBuilder::<Sqlite>::new(format!("SELECT {SNIPPET_COLUMNS} FROM snippets"));
+    push_list_filter(&mut query, filter, "snippets", "snippet_tags", "snippet_id");
+    if let Some(url) = &filter.url {
+        query.push(" AND snippets.url = ").push_bind(url.clone());
+    }
+    query.push(" ORDER BY pinned DESC, id DESC");
+    if let Some(limit) = filter.limit {
+        query
+            .push(" LIMIT ")
+            .push_bind(limit)
+            .push(" OFFSET ")
+            .push_bind(filter.offset);
+    }
 
-    let rows = sqlx::query(query).fetch_all(db_pool).await?;
-    let mut results = Vec::new();
+    let rows = query.build().fetch_all(db_pool).await?;
+    Ok(rows.into_iter().map(snippet_from_row).collect())
+}
 
-    for row in rows {
-        let id: i32 = row.get("id");
-        let snippet: String = row.get("snippet");
-        let url: String = row.get("url");
-        let tags: String = row.get("tags");
-        let tags_vec: Vec<String> = serde_json::from_str(&tags).unwrap_or_default();
-        results.push(models::SnippetWithTags {
-            id,
-            snippet,
-            url,
-            tags: tags_vec,
-        });
+/// Columns selected into `models::SnippetWithTags`, including the content type of the snippet's image if it has one
+const SNIPPET_COLUMNS: &str = "id, snippet, snippet_html, url, tags, pinned, source, \
+    (SELECT content_type FROM snippet_images WHERE snippet_images.snippet_id = snippets.id) AS image_type";
+
+/// A `SELECT {SNIPPET_COLUMNS} FROM snippets` row
+fn snippet_from_row(row: SqliteRow) -> models::SnippetWithTags {
+    let tags: String = row.get("tags");
+    models::SnippetWithTags {
+        id: row.get("id"),
+        snippet: row.get("snippet"),
+        snippet_html: row.get("snippet_html"),
+        url: row.get("url"),
+        tags: serde_json::from_str(&tags).unwrap_or_default(),
+        pinned: row.get("pinned"),
+        source: models::SnippetSource::from_column(row.get("source")),
+        image_type: row.get("image_type"),
     }
+}
 
-    Ok(results)
+/// Snippets saved from one URL, pinned first and then in the order they were taken
+pub async fn get_snippets_for_url(db_pool: &SqlitePool, url: &str) -> Result<Vec<models::SnippetWithTags>, Error> {
+    let rows = sqlx::query(&format!(
+        "SELECT {SNIPPET_COLUMNS} FROM snippets WHERE url = ? ORDER BY pinned DESC, id"
+    ))
+    .bind(url)
+    .fetch_all(db_pool)
+    .await?;
+
+    Ok(rows.into_iter().map(snippet_from_row).collect())
 }
 
-pub async fn get_all_urls(db_pool: &SqlitePool) -> Result<Vec<models::Url>, sqlx::Error> {
-    let query = r#"
-        SELECT id, datetime, url, url_hash
-        FROM urls
-        ORDER BY datetime DESC
-    "#;
+/// Snippets saved before `cutoff` or at an unknown time, oldest first
+pub async fn get_snippets_saved_before(
+    db_pool: &SqlitePool,
+    cutoff: chrono::NaiveDateTime,
+) -> Result<Vec<models::SnippetWithTags>, Error> {
+    let rows = sqlx::query(&format!(
+        "SELECT {SNIPPET_COLUMNS} FROM snippets WHERE saved_at IS NULL OR saved_at < ? ORDER BY id"
+    ))
+    .bind(cutoff)
+    .fetch_all(db_pool)
+    .await?;
 
-    // Use the `query_as` method to map rows to the `Url` struct.
-    let urls = sqlx::query_as::<_, models::Url>(query).fetch_all(db_pool).await?;
+    Ok(rows.into_iter().map(snippet_from_row).collect())
+}
 
-    Ok(urls)
+/// Fetch one snippet by id
+pub async fn get_snippet(db_pool: &SqlitePool, snippet_id: i32) -> Result<Option<models::SnippetWithTags>, Error> {
+    let row = sqlx::query(&format!("SELECT {SNIPPET_COLUMNS} FROM snippets WHERE id = ?"))
+        .bind(snippet_id)
+        .fetch_optional(db_pool)
+        .await?;
+
+    Ok(row.map(snippet_from_row))
 }
 
-pub async fn get_urls_with_tags(db_pool: &SqlitePool) -> Result<Vec<models::UrlWithTags>, sqlx::Error> {
-    let query = r#"
-        SELECT urls.url, 
-               COALESCE(GROUP_CONCAT(tags.tag, ','), '') AS tags
+/// Columns selected into `models::Url`
+const URL_COLUMNS: &str =
+    "urls.id, urls.datetime, urls.url, urls.url_hash, urls.read_at, urls.pinned, urls.progress, urls.original_url, \
+     urls.short_id";
+
+/// Append `WHERE` conditions for `filter` to a listing over `table`, whose tags live in `link_table`
+fn push_list_filter(
+    query: &mut QueryBuilder<'_, Sqlite>,
+    filter: &models::ListFilter,
+    table: &str,
+    link_table: &str,
+    link_column: &str,
+) {
+    // Ids of items carrying any of `tags` or a tag nested under one of them
+    let tagged_with = |query: &mut QueryBuilder<'_, Sqlite>, tags: &[String]| {
+        query.push(format!(
+            "SELECT lt.{link_column} FROM {link_table} lt JOIN tags ft ON ft.id = lt.tag_id WHERE "
+        ));
+        for (position, tag) in tags.iter().enumerate() {
+            if position > 0 {
+                query.push(" OR ");
+            }
+            let prefix = format!("{}{}", tag, models::TAG_SEPARATOR);
+            query
+                .push("ft.tag = ")
+                .push_bind(tag.clone())
+                .push(" OR SUBSTR(ft.tag, 1, ")
+                .push_bind(prefix.chars().count() as i32)
+                .push(") = ")
+                .push_bind(prefix);
+        }
+    };
+
+    query.push(" WHERE 1 = 1");
+
+    if !filter.tags.any_of.is_empty() {
+        query.push(" AND (");
+        for (position, group) in filter.tags.any_of.iter().enumerate() {
+            if position > 0 {
+                query.push(" OR ");
+            }
+            // Each tag of the group is checked on its own, since `lang` and `lang/rust` can match the same tag
+            query.push("(");
+            for (index, tag) in group.iter().enumerate() {
+                if index > 0 {
+                    query.push(" AND ");
+                }
+                query.push(format!("{table}.id IN ("));
+                tagged_with(query, std::slice::from_ref(tag));
+                query.push(")");
+            }
+            query.push(")");
+        }
+        query.push(")");
+    }
+
+    if !filter.tags.none_of.is_empty() {
+        query.push(format!(" AND {table}.id NOT IN ("));
+        tagged_with(query, &filter.tags.none_of);
+        query.push(")");
+    }
+
+    // Blank tags are what an empty tag field produces, so they don't count as tagged
+    if filter.untagged {
+        query.push(format!(
+            " AND {table}.id NOT IN (SELECT lt.{link_column} FROM {link_table} lt JOIN tags ft ON ft.id = lt.tag_id WHERE ft.tag <> '')"
+        ));
+    }
+}
+
+/// Append the URL-only conditions (date range, read state); call after `push_list_filter`
+fn push_url_filter(query: &mut QueryBuilder<'_, Sqlite>, filter: &models::ListFilter) {
+    if let Some(after) = filter.saved_after {
+        query.push(" AND urls.datetime >= ").push_bind(after);
+    }
+    if let Some(before) = filter.saved_before {
+        query.push(" AND urls.datetime < ").push_bind(before);
+    }
+    if filter.unread {
+        query.push(" AND urls.read_at IS NULL");
+    }
+    if let Some(kind) = filter.kind {
+        query
+            .push(" AND (json_extract(urls.metadata, '$.type') = ")
+            .push_bind(kind.as_str())
+            .push(" OR EXISTS (SELECT 1 FROM url_metadata WHERE url_metadata.url_id = urls.id AND url_metadata.key = ")
+            .push_bind(models::URL_KIND.name)
+            .push(" AND json_extract(url_metadata.value, '$') = ")
+            .push_bind(kind.as_str())
+            .push("))");
+    }
+    if let Some(anniversary) = &filter.anniversary {
+        query
+            .push(" AND CAST(strftime('%m', urls.datetime) AS INTEGER) = ")
+            .push_bind(anniversary.month)
+            .push(" AND CAST(strftime('%d', urls.datetime) AS INTEGER) = ")
+            .push_bind(anniversary.day)
+            .push(" AND CAST(strftime('%Y', urls.datetime) AS INTEGER) IN (");
+        let mut separated = query.separated(", ");
+        for year in &anniversary.years {
+            separated.push_bind(*year);
+        }
+        query.push(")");
+    }
+    match filter.archived {
+        Some(true) => {
+            query.push(" AND urls.archived_at IS NOT NULL");
+        }
+        Some(false) => {
+            query.push(" AND urls.archived_at IS NULL");
+        }
+        None => {}
+    }
+    if let Some(short_id) = &filter.short_id {
+        query.push(" AND urls.short_id = ").push_bind(short_id.clone());
+    }
+    if let Some(url_hash) = &filter.url_hash {
+        query.push(" AND urls.url_hash = ").push_bind(url_hash.clone());
+    }
+}
+
+/// Append an `ORDER BY` over `urls` for `sort`, breaking ties by id
+fn push_url_sort(query: &mut QueryBuilder<'_, Sqlite>, sort: &models::Sort) {
+    let column = match sort.key {
+        models::SortKey::Added => "urls.datetime",
+        models::SortKey::Read => "urls.read_at",
+        // Titled or not, the listing reads as one alphabet rather than splitting on case or scheme
+        models::SortKey::Title => {
+            "LOWER(COALESCE(urls.title, REPLACE(REPLACE(urls.url, 'https://', ''), 'http://', '')))"
+        }
+        // Strip the scheme and `www.` so URLs group by host
+        models::SortKey::Domain => "REPLACE(REPLACE(REPLACE(urls.url, 'https://', ''), 'http://', ''), 'www.', '')",
+        models::SortKey::Url => "urls.url",
+    };
+    let direction = if sort.descending { "DESC" } else { "ASC" };
+    // Pinned URLs stay on top whatever the sort, and unread ones go last when sorting by when they were read
+    query.push(format!(
+        " ORDER BY urls.pinned DESC, {column} {direction} NULLS LAST, urls.id {direction}"
+    ));
+}
+
+pub async fn get_all_urls(db_pool: &SqlitePool, filter: &models::ListFilter) -> Result<Vec<models::Url>, Error> {
+    let mut query = QueryBuilder::<Sqlite>::new(format!("SELECT {URL_COLUMNS} FROM urls"));
+    push_list_filter(&mut query, filter, "urls", "url_tags", "url_id");
+    push_url_filter(&mut query, filter);
+    push_url_sort(&mut query, &filter.sort);
+
+    query.build_query_as::<models::Url>().fetch_all(db_pool).await
+}
+
+/// Count, highest id and summed revisions of the URLs matching `filter`, with the newest activity id
+pub async fn get_listing_fingerprint(
+    db_pool: &SqlitePool,
+    filter: &models::ListFilter,
+) -> Result<models::ListingFingerprint, Error> {
+    let mut query = QueryBuilder::<Sqlite>::new(
+        "SELECT COUNT(*) AS count, MAX(urls.id) AS max_id, SUM(urls.revision) AS revisions, \
+         (SELECT MAX(id) FROM activity) AS activity_id FROM urls",
+    );
+    push_list_filter(&mut query, filter, "urls", "url_tags", "url_id");
+    push_url_filter(&mut query, filter);
+
+    query
+        .build_query_as::<models::ListingFingerprint>()
+        .fetch_one(db_pool)
+        .await
+}
+
+/// Stream every URL with its tags, oldest first
+pub fn stream_exported_urls(db_pool: &SqlitePool) -> BoxStream<'_, Result<models::ExportedUrl, Error>> {
+    sqlx::query(
+        r#"
+        SELECT urls.url, urls.url_hash, urls.datetime, urls.read_at, COALESCE(GROUP_CONCAT(tags.tag, ','), '') AS tags
         FROM urls
         LEFT JOIN url_tags ON urls.id = url_tags.url_id
         LEFT JOIN tags ON url_tags.tag_id = tags.id
-        GROUP BY urls.id, urls.datetime, urls.url
-        ORDER BY urls.datetime DESC
-    "#;
+        GROUP BY urls.id, urls.url, urls.url_hash, urls.datetime, urls.read_at
+        ORDER BY urls.id
+        "#,
+    )
+    .fetch(db_pool)
+    .map_ok(|row| {
+        let tags: String = row.get("tags");
+        models::ExportedUrl {
+            url: row.get("url"),
+            url_hash: row.get("url_hash"),
+            datetime: row.get("datetime"),
+            read_at: row.get("read_at"),
+            tags: tags
+                .split(',')
+                .filter(|tag| !tag.is_empty())
+                .map(String::from)
+                .collect(),
+        }
+    })
+    .boxed()
+}
 
-    let rows = sqlx::query(query).fetch_all(db_pool).await?;
+pub async fn get_urls_with_tags(
+    db_pool: &SqlitePool,
+    filter: &models::ListFilter,
+) -> Result<Vec<models::UrlWithTags>, sqlx::Error> {
+    let mut query = QueryBuilder::<Sqlite>::new(
+        r#"
+        SELECT urls.id, urls.url, urls.url_hash, urls.datetime, urls.read_at, urls.pinned, urls.progress, urls.metadata, urls.title, urls.notes, urls.archived_at, urls.snoozed_until, urls.short_id, COALESCE(GROUP_CONCAT(tags.tag, ','), '') AS tags
+        FROM urls
+        LEFT JOIN url_tags ON urls.id = url_tags.url_id
+        LEFT JOIN tags ON url_tags.tag_id = tags.id
+        "#,
+    );
+    push_list_filter(&mut query, filter, "urls", "url_tags", "url_id");
+    push_url_filter(&mut query, filter);
+    query.push(" GROUP BY urls.id, urls.datetime, urls.url, urls.url_hash, urls.read_at, urls.pinned, urls.progress");
+    push_url_sort(&mut query, &filter.sort);
+    if let Some(limit) = filter.limit {
+        query
+            .push(" LIMIT ")
+            .push_bind(limit)
+            .push(" OFFSET ")
+            .push_bind(filter.offset);
+    }
+
+    let rows = query.build().fetch_all(db_pool).await?;
     let mut results = Vec::new();
 
     for row in rows {
@@ -364,35 +1614,269 @@ pub async fn get_urls_with_tags(db_pool: &SqlitePool) -> Result<Vec<models::UrlW
         } else {
             tags_string.split(',').map(String::from).collect()
         };
-        let display_url = url.split('?').next().unwrap_or(&url).to_string();
-
-        results.push(models::UrlWithTags { url, tags, display_url });
+        let display_url = display::display_url(&url);
+
+        let datetime = row.get("datetime");
+        let read_at = row.get("read_at");
+        let pinned = row.get("pinned");
+        let progress = row.get("progress");
+        let short_id = row.get("short_id");
+        let metadata = models::UrlMetadata::from_column(row.get("metadata"));
+        let title: Option<String> = row.get("title");
+        let display_title = models::display_title(title.as_deref(), metadata.as_ref(), &url);
+
+        results.push(models::UrlWithTags {
+            id: row.get("id"),
+            url_hash: row.get("url_hash"),
+            url,
+            datetime,
+            tags,
+            display_url,
+            display_title,
+            read_at,
+            short_id,
+            pinned,
+            progress,
+            metadata,
+            title,
+            notes: row.get("notes"),
+            archived_at: row.get("archived_at"),
+            snoozed_until: row.get("snoozed_until"),
+        });
     }
 
     Ok(results)
 }
 
+/// Fetch one saved URL with its tags
+pub async fn get_url_with_tags(db_pool: &SqlitePool, url: &str) -> Result<Option<models::UrlWithTags>, sqlx::Error> {
+    let filter = models::ListFilter {
+        url_hash: Some(calculate_url_hash(url)),
+        ..Default::default()
+    };
+    Ok(get_urls_with_tags(db_pool, &filter).await?.pop())
+}
+
+/// Pick one URL matching `filter` at random
+pub async fn get_random_url(db_pool: &SqlitePool, filter: &models::ListFilter) -> Result<Option<models::Url>, Error> {
+    let mut query = QueryBuilder::<Sqlite>::new(format!("SELECT {URL_COLUMNS} FROM urls"));
+    push_list_filter(&mut query, filter, "urls", "url_tags", "url_id");
+    push_url_filter(&mut query, filter);
+    query.push(" ORDER BY RANDOM() LIMIT 1");
+
+    query.build_query_as::<models::Url>().fetch_optional(db_pool).await
+}
+
+/// The first URL a listing with `filter` would show
+pub async fn get_next_url(db_pool: &SqlitePool, filter: &models::ListFilter) -> Result<Option<models::Url>, Error> {
+    let mut query = QueryBuilder::<Sqlite>::new(format!("SELECT {URL_COLUMNS} FROM urls"));
+    push_list_filter(&mut query, filter, "urls", "url_tags", "url_id");
+    push_url_filter(&mut query, filter);
+    push_url_sort(&mut query, &filter.sort);
+    query.push(" LIMIT 1");
+
+    query.build_query_as::<models::Url>().fetch_optional(db_pool).await
+}
+
+/// Look up a URL by its short id
+pub async fn get_url_by_short_id(db_pool: &SqlitePool, short_id: &str) -> Result<Option<models::Url>, Error> {
+    if !models::is_short_id(short_id) {
+        return Ok(None);
+    }
+
+    let mut urls =
+        sqlx::query_as::<_, models::Url>(&format!("SELECT {URL_COLUMNS} FROM urls WHERE short_id = ? LIMIT 2"))
+            .bind(short_id)
+            .fetch_all(db_pool)
+            .await?;
+    // Rows given the same short id before they were stored can't be told apart, so neither is picked
+    if urls.len() > 1 {
+        return Ok(None);
+    }
+    Ok(urls.pop())
+}
+
+/// Mark a URL read now, or unread again
+pub async fn set_url_read(db_pool: &SqlitePool, url: &str, read: bool) -> Result<(), Error> {
+    let query = if read {
+        "UPDATE urls SET read_at = CURRENT_TIMESTAMP WHERE url_hash = ?"
+    } else {
+        "UPDATE urls SET read_at = NULL WHERE url_hash = ?"
+    };
+    let result = sqlx::query(query)
+        .bind(calculate_url_hash(url))
+        .execute(db_pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(Error::RowNotFound);
+    }
+    Ok(())
+}
+
+/// Apply a `PATCH` to one URL: its own columns in a single `UPDATE`, then its tags, in one transaction
+pub async fn update_url(db_pool: &SqlitePool, url_id: i32, update: &models::UrlUpdate) -> Result<(), Error> {
+    let mut tx = db_pool.begin().await?;
+
+    let mut query = QueryBuilder::<Sqlite>::new("UPDATE urls SET ");
+    let mut columns = query.separated(", ");
+    // Keeps the statement valid when only the tags change, while still finding out whether the URL exists
+    columns.push("id = id");
+    if let Some(title) = &update.title {
+        columns.push("title = ").push_bind_unseparated(title.as_deref());
+    }
+    if let Some(notes) = &update.notes {
+        columns.push("notes = ").push_bind_unseparated(notes.as_deref());
+    }
+    // Reading or archiving again keeps the first time it happened
+    if let Some(read) = update.read {
+        columns.push(if read {
+            "read_at = COALESCE(read_at, CURRENT_TIMESTAMP)"
+        } else {
+            "read_at = NULL"
+        });
+    }
+    if let Some(archived) = update.archived {
+        columns.push(if archived {
+            "archived_at = COALESCE(archived_at, CURRENT_TIMESTAMP)"
+        } else {
+            "archived_at = NULL"
+        });
+    }
+    if let Some(until) = update.snoozed_until {
+        columns
+            .push("snoozed_until = ")
+            .push_bind_unseparated(until.map(|until| until.naive_utc()));
+    }
+    query.push(" WHERE id = ").push_bind(url_id).push(" RETURNING url");
+    let url: String = query
+        .build_query_scalar()
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(Error::RowNotFound)?;
+
+    if let Some(tags) = update.tag_list() {
+        sqlx::query("DELETE FROM url_tags WHERE url_id = ?")
+            .bind(url_id)
+            .execute(&mut *tx)
+            .await?;
+        insert_tags_in(&mut tx, &url, &tags).await?;
+    }
+    tx.commit().await
+}
+
 pub async fn delete_url_by_url(db_pool: &SqlitePool, url: &str) -> Result<(), Error> {
     let url_hash = calculate_url_hash(url);
-    let query = "DELETE FROM urls WHERE url_hash = ?";
-    sqlx::query(query).bind(url_hash).execute(db_pool).await?;
+    let mut tx = db_pool.begin().await?;
+    let tag_ids =
+        sqlx::query_scalar("SELECT tag_id FROM url_tags WHERE url_id = (SELECT id FROM urls WHERE url_hash = ?)")
+            .bind(&url_hash)
+            .fetch_all(&mut *tx)
+            .await?;
+    sqlx::query("DELETE FROM urls WHERE url_hash = ?")
+        .bind(url_hash)
+        .execute(&mut *tx)
+        .await?;
+    remove_unused_tags_in(&mut tx, &tag_ids).await?;
+    tx.commit().await
+}
+
+/// Most tag ids checked by one cleanup query, well under SQLite's limit on bound parameters
+const TAG_CLEANUP_CHUNK: usize = 500;
+
+/// Delete whichever of `tag_ids` no URL or snippet carries any more, unless they have a color or description to
+/// keep. Only the tags a delete just unlinked are checked, so the link tables' tag indexes answer each lookup rather than a scan of every tag.
+async fn remove_unused_tags_in(conn: &mut SqliteConnection, tag_ids: &[i32]) -> Result<(), Error> {
+    for chunk in tag_ids.chunks(TAG_CLEANUP_CHUNK) {
+        let mut query = QueryBuilder::<Sqlite>::new("DELETE FROM tags WHERE id IN (");
+        let mut ids = query.separated(", ");
+        for id in chunk {
+            ids.push_bind(id);
+        }
+        query.push(
+            ") AND NOT EXISTS (SELECT 1 FROM url_tags WHERE tag_id = tags.id) \
+             AND NOT EXISTS (SELECT 1 FROM snippet_tags WHERE tag_id = tags.id) \
+             AND color IS NULL AND description IS NULL",
+        );
+        query.build().execute(&mut *conn).await?;
+    }
     Ok(())
 }
 
-pub async fn remove_unused_tags(db_pool: &SqlitePool) -> Result<(), Error> {
+/// Delete every tag no URL or snippet carries and nobody gave a color or description, for maintenance runs that already visit the whole table
+async fn remove_all_unused_tags_in(conn: &mut SqliteConnection) -> Result<(), Error> {
     let query = r#"
         DELETE FROM tags
         WHERE id NOT IN (SELECT tag_id FROM url_tags)
           AND id NOT IN (SELECT tag_id FROM snippet_tags)
+          AND color IS NULL AND description IS NULL
     "#;
-    sqlx::query(query).execute(db_pool).await?;
+    sqlx::query(query).execute(conn).await?;
     Ok(())
 }
 
 pub async fn delete_snippet(db_pool: &SqlitePool, snippet_id: i32) -> Result<(), Error> {
-    let query = "DELETE FROM snippets WHERE id = ?";
-    sqlx::query(query).bind(snippet_id).execute(db_pool).await?;
-    Ok(())
+    let mut tx = db_pool.begin().await?;
+    let tag_ids = delete_snippet_in(&mut tx, snippet_id).await?;
+    remove_unused_tags_in(&mut tx, &tag_ids).await?;
+    tx.commit().await
+}
+
+/// Delete a snippet, returning the ids of the tags it carried
+async fn delete_snippet_in(conn: &mut SqliteConnection, snippet_id: i32) -> Result<Vec<i32>, Error> {
+    let tag_ids = sqlx::query_scalar("SELECT tag_id FROM snippet_tags WHERE snippet_id = ?")
+        .bind(snippet_id)
+        .fetch_all(&mut *conn)
+        .await?;
+    sqlx::query("DELETE FROM snippets WHERE id = ?")
+        .bind(snippet_id)
+        .execute(conn)
+        .await?;
+    Ok(tag_ids)
+}
+
+/// Run a batch of operations in order inside one transaction, rolling everything back on the first failure
+pub async fn execute_batch(
+    db_pool: &SqlitePool,
+    operations: &[models::BatchOperation],
+) -> Result<Vec<Option<i32>>, models::BatchError> {
+    let mut tx = db_pool.begin().await.map_err(models::BatchError::transaction)?;
+    let mut results = Vec::with_capacity(operations.len());
+    let mut unlinked_tags = Vec::new();
+
+    for (index, operation) in operations.iter().enumerate() {
+        let result = match operation {
+            models::BatchOperation::AddUrl { url } => insert_url_in(&mut tx, url).await.map(Some),
+            models::BatchOperation::TagUrl { url, tags } => insert_tags_in(&mut tx, url, &models::split_tags(tags))
+                .await
+                .map(|_| None),
+            models::BatchOperation::DeleteSnippet { id } => delete_snippet_in(&mut tx, *id).await.map(|tag_ids| {
+                unlinked_tags.extend(tag_ids);
+                None
+            }),
+        };
+
+        let result = match result {
+            Ok(id) => record_batch_activity_in(&mut tx, operation).await.map(|_| id),
+            Err(error) => Err(error),
+        };
+
+        match result {
+            Ok(id) => results.push(id),
+            Err(error) => {
+                return Err(models::BatchError {
+                    index: Some(index),
+                    error,
+                })
+            }
+        }
+    }
+
+    remove_unused_tags_in(&mut tx, &unlinked_tags)
+        .await
+        .map_err(models::BatchError::transaction)?;
+    tx.commit().await.map_err(models::BatchError::transaction)?;
+    Ok(results)
 }
 
 pub async fn get_tags_with_urls_and_snippets(
@@ -455,10 +1939,7 @@ pub async fn get_tags_with_urls_and_snippets(
         let snippets = if !snippet_ids_vec.is_empty() {
             let placeholders = snippet_ids_vec.iter().map(|_| "?").collect::<Vec<&str>>().join(",");
 
-            let snippet_query = format!(
-                "SELECT id, snippet, url, tags FROM snippets WHERE id IN ({})",
-                placeholders
-            );
+            let snippet_query = format!("SELECT {SNIPPET_COLUMNS} FROM snippets WHERE id IN ({})", placeholders);
 
             let mut query = sqlx::query(&snippet_query);
 
@@ -468,23 +1949,7 @@ pub async fn get_tags_with_urls_and_snippets(
 
             let snippet_rows = query.fetch_all(db_pool).await?;
 
-            snippet_rows
-                .into_iter()
-                .map(|row| {
-                    let id: i32 = row.get("id");
-                    let snippet: String = row.get("snippet");
-                    let url: String = row.get("url");
-                    let tags: String = row.get("tags");
-                    let tags_vec: Vec<String> = serde_json::from_str(&tags).unwrap_or_default();
-
-                    Ok(models::SnippetWithTags {
-                        id,
-                        snippet,
-                        url,
-                        tags: tags_vec,
-                    })
-                })
-                .collect::<Result<Vec<models::SnippetWithTags>, sqlx::Error>>()?
+            snippet_rows.into_iter().map(snippet_from_row).collect()
         } else {
             Vec::new()
         };
@@ -499,311 +1964,3375 @@ pub async fn get_tags_with_urls_and_snippets(
     Ok(results)
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use sqlx::SqlitePool;
+/// Fetch how many URLs and snippets carry each tag, alphabetically
+pub async fn get_tag_counts(db_pool: &SqlitePool) -> Result<Vec<models::TagCount>, Error> {
+    let query = r#"
+        SELECT tags.tag, COALESCE(tag_counts.url_count, 0) AS urls, COALESCE(tag_counts.snippet_count, 0) AS snippets
+        FROM tags
+        LEFT JOIN tag_counts ON tag_counts.tag_id = tags.id
+        ORDER BY tags.tag
+    "#;
 
-    async fn setup_test_db() -> SqlitePool {
-        let pool = SqlitePool::connect(":memory:").await.unwrap();
-        initialize_tables(&pool).await.unwrap();
-        pool
-    }
+    sqlx::query_as::<_, models::TagCount>(query).fetch_all(db_pool).await
+}
 
-    #[tokio::test]
-    async fn test_initialize_tables() {
-        let db_pool = setup_test_db().await;
-        assert_eq!(check_health(&db_pool).await, "ok");
-    }
+/// Fetch every tag name, alphabetically
+pub async fn get_all_tags(db_pool: &SqlitePool) -> Result<Vec<String>, Error> {
+    sqlx::query_scalar("SELECT tag FROM tags ORDER BY tag")
+        .fetch_all(db_pool)
+        .await
+}
 
-    #[tokio::test]
-    async fn test_insert_url() {
-        let db_pool = setup_test_db().await;
-        let url = "https://example.com";
+/// Store how far through a URL the reader got; fails with `RowNotFound` if the URL isn't saved
+pub async fn set_url_progress(db_pool: &SqlitePool, url: &str, progress: i32) -> Result<(), Error> {
+    let result = sqlx::query("UPDATE urls SET progress = ? WHERE url_hash = ?")
+        .bind(progress)
+        .bind(calculate_url_hash(url))
+        .execute(db_pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(Error::RowNotFound);
+    }
+    Ok(())
+}
+
+/// Store what was looked up about a URL; fails with `RowNotFound` if the URL isn't saved
+pub async fn set_url_metadata(db_pool: &SqlitePool, url: &str, metadata: &models::UrlMetadata) -> Result<(), Error> {
+    let json = serde_json::to_string(metadata).map_err(|err| Error::Encode(Box::new(err)))?;
+    let result = sqlx::query("UPDATE urls SET metadata = ? WHERE url_hash = ?")
+        .bind(json)
+        .bind(calculate_url_hash(url))
+        .execute(db_pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(Error::RowNotFound);
+    }
+    Ok(())
+}
+
+/// Pin or unpin a URL; fails with `RowNotFound` if the URL isn't saved
+pub async fn set_url_pinned(db_pool: &SqlitePool, url: &str, pinned: bool) -> Result<(), Error> {
+    let result = sqlx::query("UPDATE urls SET pinned = ? WHERE url_hash = ?")
+        .bind(pinned)
+        .bind(calculate_url_hash(url))
+        .execute(db_pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(Error::RowNotFound);
+    }
+    Ok(())
+}
+
+/// Pin or unpin a snippet; fails with `RowNotFound` if there's no such snippet
+pub async fn set_snippet_pinned(db_pool: &SqlitePool, snippet_id: i32, pinned: bool) -> Result<(), Error> {
+    let result = sqlx::query("UPDATE snippets SET pinned = ? WHERE id = ?")
+        .bind(pinned)
+        .bind(snippet_id)
+        .execute(db_pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(Error::RowNotFound);
+    }
+    Ok(())
+}
+
+/// Fetch every tag with its color and description, alphabetically
+pub async fn get_tag_details(db_pool: &SqlitePool) -> Result<Vec<models::TagDetails>, Error> {
+    sqlx::query_as::<_, models::TagDetails>("SELECT tag, color, description FROM tags ORDER BY tag")
+        .fetch_all(db_pool)
+        .await
+}
+
+/// Fetch one tag with its color and description
+pub async fn get_tag(db_pool: &SqlitePool, tag: &str) -> Result<Option<models::TagDetails>, Error> {
+    sqlx::query_as::<_, models::TagDetails>("SELECT tag, color, description FROM tags WHERE tag = ?")
+        .bind(tag)
+        .fetch_optional(db_pool)
+        .await
+}
+
+/// Set or clear a tag's color and description; fails with `RowNotFound` if the tag doesn't exist
+pub async fn set_tag_details(
+    db_pool: &SqlitePool,
+    tag: &str,
+    color: Option<&str>,
+    description: Option<&str>,
+) -> Result<(), Error> {
+    let result = sqlx::query("UPDATE tags SET color = ?, description = ? WHERE tag = ?")
+        .bind(color)
+        .bind(description)
+        .bind(tag)
+        .execute(db_pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(Error::RowNotFound);
+    }
+    Ok(())
+}
+
+/// Create or update tags from another instance's taxonomy; details left out keep what an existing tag has
+pub async fn import_tags(db_pool: &SqlitePool, tags: &[models::TagDetails]) -> Result<models::TagImport, Error> {
+    let mut tx = db_pool.begin().await?;
+    let mut imported = models::TagImport::default();
+
+    for details in tags {
+        let exists: bool = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM tags WHERE tag = ?)")
+            .bind(&details.tag)
+            .fetch_one(&mut *tx)
+            .await?;
+        sqlx::query(
+            "INSERT INTO tags (tag, color, description) VALUES (?, ?, ?) \
+             ON CONFLICT (tag) DO UPDATE SET color = COALESCE(excluded.color, tags.color), \
+             description = COALESCE(excluded.description, tags.description)",
+        )
+        .bind(&details.tag)
+        .bind(&details.color)
+        .bind(&details.description)
+        .execute(&mut *tx)
+        .await?;
+        if exists {
+            imported.updated += 1;
+        } else {
+            imported.created += 1;
+        }
+    }
+
+    tx.commit().await?;
+    Ok(imported)
+}
+
+/// Store a new copy of a URL's page unless it matches the latest one, keeping only the newest `keep` copies.
+/// Returns the new snapshot's id, or `None` when the page hasn't changed
+pub async fn insert_snapshot(
+    db_pool: &SqlitePool,
+    url_id: i32,
+    content_type: Option<&str>,
+    content: &str,
+    truncated: bool,
+    keep: i64,
+) -> Result<Option<i32>, Error> {
+    let mut tx = db_pool.begin().await?;
+
+    let latest: Option<String> =
+        sqlx::query_scalar("SELECT content FROM url_snapshots WHERE url_id = ? ORDER BY id DESC LIMIT 1")
+            .bind(url_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+    if latest.as_deref() == Some(content) {
+        return Ok(None);
+    }
+
+    let id: i32 = sqlx::query_scalar(
+        "INSERT INTO url_snapshots (url_id, content_type, content, truncated) VALUES (?, ?, ?, ?) RETURNING id",
+    )
+    .bind(url_id)
+    .bind(content_type)
+    .bind(content)
+    .bind(truncated)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    sqlx::query(
+        "DELETE FROM url_snapshots WHERE url_id = ? AND id NOT IN \
+         (SELECT id FROM url_snapshots WHERE url_id = ? ORDER BY id DESC LIMIT ?)",
+    )
+    .bind(url_id)
+    .bind(url_id)
+    .bind(keep)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(Some(id))
+}
+
+/// A URL's snapshots without their content, newest first
+pub async fn get_snapshots(db_pool: &SqlitePool, url_id: i32) -> Result<Vec<models::Snapshot>, Error> {
+    sqlx::query_as::<_, models::Snapshot>(
+        "SELECT id, datetime, content_type, LENGTH(CAST(content AS BLOB)) AS size, truncated FROM url_snapshots \
+         WHERE url_id = ? ORDER BY id DESC",
+    )
+    .bind(url_id)
+    .fetch_all(db_pool)
+    .await
+}
+
+/// One snapshot with its content, if it belongs to `url_id`; marks it as just viewed
+pub async fn get_snapshot(
+    db_pool: &SqlitePool,
+    url_id: i32,
+    id: i32,
+) -> Result<Option<models::SnapshotContent>, Error> {
+    sqlx::query_as::<_, models::SnapshotContent>(
+        // Reading a snapshot counts as using it, so the storage cap drops unread ones first
+        "UPDATE url_snapshots SET accessed_at = CURRENT_TIMESTAMP WHERE url_id = ? AND id = ? RETURNING datetime, content",
+    )
+    .bind(url_id)
+    .bind(id)
+    .fetch_optional(db_pool)
+    .await
+}
+
+/// When a snapshot of `url_id` was taken, if it exists; marks it as just viewed, like `get_snapshot`
+pub async fn touch_snapshot(
+    db_pool: &SqlitePool,
+    url_id: i32,
+    id: i32,
+) -> Result<Option<chrono::NaiveDateTime>, Error> {
+    sqlx::query_scalar(
+        "UPDATE url_snapshots SET accessed_at = CURRENT_TIMESTAMP WHERE url_id = ? AND id = ? RETURNING datetime",
+    )
+    .bind(url_id)
+    .bind(id)
+    .fetch_optional(db_pool)
+    .await
+}
+
+/// URLs with at least one snapshot, which the scheduled refresh keeps up to date
+pub async fn get_snapshotted_urls(db_pool: &SqlitePool) -> Result<Vec<models::Url>, Error> {
+    sqlx::query_as::<_, models::Url>(&format!(
+        "SELECT {URL_COLUMNS} FROM urls WHERE id IN (SELECT url_id FROM url_snapshots) ORDER BY id"
+    ))
+    .fetch_all(db_pool)
+    .await
+}
+
+/// Decode a metadata value as stored; the column only ever holds JSON written by `set_url_metadata_entry`
+fn metadata_value(text: &str) -> Result<serde_json::Value, Error> {
+    serde_json::from_str(text).map_err(|err| Error::Decode(Box::new(err)))
+}
+
+/// All metadata entries for a URL by its id, by name
+pub async fn get_url_metadata_entries(
+    db_pool: &SqlitePool,
+    url_id: i32,
+) -> Result<BTreeMap<String, serde_json::Value>, Error> {
+    let rows: Vec<(String, String)> = sqlx::query_as("SELECT key, value FROM url_metadata WHERE url_id = ?")
+        .bind(url_id)
+        .fetch_all(db_pool)
+        .await?;
+    rows.into_iter()
+        .map(|(key, value)| Ok((key, metadata_value(&value)?)))
+        .collect()
+}
+
+pub async fn get_url_metadata_entry(
+    db_pool: &SqlitePool,
+    url_id: i32,
+    key: &str,
+) -> Result<Option<serde_json::Value>, Error> {
+    let value: Option<String> = sqlx::query_scalar("SELECT value FROM url_metadata WHERE url_id = ? AND key = ?")
+        .bind(url_id)
+        .bind(key)
+        .fetch_optional(db_pool)
+        .await?;
+    value.as_deref().map(metadata_value).transpose()
+}
+
+/// Store or replace a metadata entry; fails with `RowNotFound` if the URL isn't saved
+pub async fn set_url_metadata_entry(
+    db_pool: &SqlitePool,
+    url_id: i32,
+    key: &str,
+    value: &serde_json::Value,
+) -> Result<(), Error> {
+    let result = sqlx::query(
+        "INSERT INTO url_metadata (url_id, key, value) SELECT ?, ?, ? WHERE EXISTS (SELECT 1 FROM urls WHERE id = ?) \
+         ON CONFLICT (url_id, key) DO UPDATE SET value = excluded.value, updated_at = CURRENT_TIMESTAMP",
+    )
+    .bind(url_id)
+    .bind(key)
+    .bind(value.to_string())
+    .bind(url_id)
+    .execute(db_pool)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(Error::RowNotFound);
+    }
+    Ok(())
+}
+
+/// Archive unread, unpinned URLs saved before `cutoff`, leaving them unread
+pub async fn archive_unread_before(
+    db_pool: &SqlitePool,
+    cutoff: chrono::NaiveDateTime,
+    dry_run: bool,
+) -> Result<Vec<String>, Error> {
+    let condition = "read_at IS NULL AND archived_at IS NULL AND NOT pinned AND datetime < ?";
+    let mut tx = db_pool.begin().await?;
+
+    let urls: Vec<String> = sqlx::query_scalar(&format!("SELECT url FROM urls WHERE {condition} ORDER BY id"))
+        .bind(cutoff)
+        .fetch_all(&mut *tx)
+        .await?;
+    if !dry_run && !urls.is_empty() {
+        sqlx::query(&format!(
+            "UPDATE urls SET archived_at = CURRENT_TIMESTAMP WHERE {condition}"
+        ))
+        .bind(cutoff)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+    Ok(urls)
+}
+
+/// Archive unpinned URLs read before `cutoff` that aren't archived yet
+pub async fn archive_read_before(
+    db_pool: &SqlitePool,
+    cutoff: chrono::NaiveDateTime,
+    dry_run: bool,
+) -> Result<Vec<String>, Error> {
+    let condition = "read_at < ? AND archived_at IS NULL AND NOT pinned";
+    let mut tx = db_pool.begin().await?;
+
+    let urls: Vec<String> = sqlx::query_scalar(&format!("SELECT url FROM urls WHERE {condition} ORDER BY id"))
+        .bind(cutoff)
+        .fetch_all(&mut *tx)
+        .await?;
+    if !dry_run && !urls.is_empty() {
+        sqlx::query(&format!(
+            "UPDATE urls SET archived_at = CURRENT_TIMESTAMP WHERE {condition}"
+        ))
+        .bind(cutoff)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+    Ok(urls)
+}
+
+/// Delete unpinned URLs read before `cutoff`, along with tags nothing else uses
+pub async fn delete_read_before(
+    db_pool: &SqlitePool,
+    cutoff: chrono::NaiveDateTime,
+    dry_run: bool,
+) -> Result<Vec<String>, Error> {
+    let condition = "read_at < ? AND NOT pinned";
+    let mut tx = db_pool.begin().await?;
+
+    let urls: Vec<String> = sqlx::query_scalar(&format!("SELECT url FROM urls WHERE {condition} ORDER BY id"))
+        .bind(cutoff)
+        .fetch_all(&mut *tx)
+        .await?;
+    if !dry_run && !urls.is_empty() {
+        let tag_ids = sqlx::query_scalar(&format!(
+            "SELECT DISTINCT tag_id FROM url_tags WHERE url_id IN (SELECT id FROM urls WHERE {condition})"
+        ))
+        .bind(cutoff)
+        .fetch_all(&mut *tx)
+        .await?;
+        sqlx::query(&format!("DELETE FROM urls WHERE {condition}"))
+            .bind(cutoff)
+            .execute(&mut *tx)
+            .await?;
+        remove_unused_tags_in(&mut tx, &tag_ids).await?;
+    }
+
+    tx.commit().await?;
+    Ok(urls)
+}
+
+/// Drop the least recently viewed snapshots, counting a new snapshot as viewed when taken, until the rest fit
+pub async fn evict_snapshots(
+    db_pool: &SqlitePool,
+    max_bytes: i64,
+    dry_run: bool,
+) -> Result<Vec<models::EvictedSnapshot>, Error> {
+    let mut tx = db_pool.begin().await?;
+
+    let snapshots = sqlx::query_as::<_, models::EvictedSnapshot>(
+        "SELECT s.id, u.url, LENGTH(CAST(s.content AS BLOB)) AS size FROM url_snapshots s JOIN urls u ON u.id = s.url_id \
+         ORDER BY COALESCE(s.accessed_at, s.datetime) DESC, s.id DESC",
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let mut total = 0;
+    let evicted: Vec<models::EvictedSnapshot> = snapshots
+        .into_iter()
+        .filter(|snapshot| {
+            total += snapshot.size;
+            total > max_bytes
+        })
+        .collect();
+
+    if !dry_run && !evicted.is_empty() {
+        let mut query = QueryBuilder::<Sqlite>::new("DELETE FROM url_snapshots WHERE id IN (");
+        let mut ids = query.separated(", ");
+        for snapshot in &evicted {
+            ids.push_bind(snapshot.id);
+        }
+        query.push(")").build().execute(&mut *tx).await?;
+    }
+
+    tx.commit().await?;
+    Ok(evicted)
+}
+
+/// The saved URL `url` is an alias of, if any
+pub async fn get_aliased_url(db_pool: &SqlitePool, url: &str) -> Result<Option<String>, Error> {
+    sqlx::query_scalar(
+        "SELECT urls.url FROM url_aliases JOIN urls ON urls.id = url_aliases.url_id WHERE url_aliases.alias_hash = ?",
+    )
+    .bind(calculate_url_hash(url))
+    .fetch_optional(db_pool)
+    .await
+}
+
+/// Store a URL under the canonical form its page declares, keeping the old form as an alias.
+///
+/// If the canonical URL is already saved the two rows are merged. Returns `None` when nothing changed.
+pub async fn adopt_canonical_url(
+    db_pool: &SqlitePool,
+    url_id: i32,
+    canonical: &str,
+) -> Result<Option<models::CleanedUrl>, Error> {
+    rewrite_url(db_pool, url_id, canonical, true).await
+}
+
+/// Store a URL under an address given by hand, such as after its site moved. The old address isn't kept as an
+/// alias, since it may have been saved by mistake.
+///
+/// If the new address is already saved the two rows are merged. Returns `None` when nothing changed.
+pub async fn move_url(db_pool: &SqlitePool, url_id: i32, url: &str) -> Result<Option<models::CleanedUrl>, Error> {
+    rewrite_url(db_pool, url_id, url, false).await
+}
+
+async fn rewrite_url(
+    db_pool: &SqlitePool,
+    url_id: i32,
+    target: &str,
+    keep_alias: bool,
+) -> Result<Option<models::CleanedUrl>, Error> {
+    let mut tx = db_pool.begin().await?;
+    let Some((url, pinned)) = sqlx::query_as::<_, (String, bool)>("SELECT url, pinned FROM urls WHERE id = ?")
+        .bind(url_id)
+        .fetch_optional(&mut *tx)
+        .await?
+    else {
+        return Ok(None);
+    };
+    if url == target {
+        return Ok(None);
+    }
+
+    let url_hash = calculate_url_hash(target);
+    let existing = sqlx::query_scalar::<_, i32>("SELECT id FROM urls WHERE url_hash = ?")
+        .bind(&url_hash)
+        .fetch_optional(&mut *tx)
+        .await?;
+    merge_or_rename(&mut tx, url_id, &url, target, &url_hash, pinned, existing).await?;
+
+    if keep_alias {
+        sqlx::query(
+            "INSERT INTO url_aliases (alias_hash, url_id) VALUES (?, ?) \
+             ON CONFLICT (alias_hash) DO UPDATE SET url_id = excluded.url_id",
+        )
+        .bind(calculate_url_hash(&url))
+        .bind(existing.unwrap_or(url_id))
+        .execute(&mut *tx)
+        .await?;
+    }
+    // The new form is stored directly now, so it must not also resolve through an alias
+    sqlx::query("DELETE FROM url_aliases WHERE alias_hash = ?")
+        .bind(&url_hash)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(Some(models::CleanedUrl {
+        original: url,
+        url: target.to_string(),
+        merged: existing.is_some(),
+    }))
+}
+
+/// Remember the URL a saved one was cleaned from; the first original seen is kept
+pub async fn record_original_url(db_pool: &SqlitePool, url: &str, original: &str) -> Result<(), Error> {
+    sqlx::query("UPDATE urls SET original_url = ? WHERE url_hash = ? AND original_url IS NULL")
+        .bind(original)
+        .bind(calculate_url_hash(url))
+        .execute(db_pool)
+        .await?;
+    Ok(())
+}
+
+/// Normalize URLs saved before `normalize` applied to them, such as before tracking parameters were stripped.
+///
+/// A URL whose clean form is already saved is folded into that row: its tags and snapshots move over, a pin
+/// carries across, and the duplicate is deleted. Snippets follow the URL either way. Cleaned rows get a new short id.
+pub async fn clean_urls(
+    db_pool: &SqlitePool,
+    normalize: &(dyn Fn(&str) -> String + Sync),
+    dry_run: bool,
+) -> Result<Vec<models::CleanedUrl>, Error> {
+    let mut tx = db_pool.begin().await?;
+    let rows: Vec<(i32, String, bool)> = sqlx::query_as("SELECT id, url, pinned FROM urls ORDER BY id")
+        .fetch_all(&mut *tx)
+        .await?;
+
+    // Clean URLs claimed earlier in this run, so a dry run spots duplicates it hasn't written yet
+    let mut claimed: HashMap<String, i32> = HashMap::new();
+    let mut cleaned = Vec::new();
+
+    for (id, url, pinned) in rows {
+        let stripped = normalize(&url);
+        let url_hash = calculate_url_hash(&stripped);
+        if stripped == url {
+            claimed.insert(url_hash, id);
+            continue;
+        }
+
+        let existing = match claimed.get(&url_hash) {
+            Some(existing) => Some(*existing),
+            None => {
+                sqlx::query_scalar::<_, i32>("SELECT id FROM urls WHERE url_hash = ?")
+                    .bind(&url_hash)
+                    .fetch_optional(&mut *tx)
+                    .await?
+            }
+        };
+
+        if !dry_run {
+            merge_or_rename(&mut tx, id, &url, &stripped, &url_hash, pinned, existing).await?;
+        }
+        claimed.entry(url_hash).or_insert(id);
+        cleaned.push(models::CleanedUrl {
+            original: url,
+            url: stripped,
+            merged: existing.is_some(),
+        });
+    }
+
+    if !dry_run {
+        remove_all_unused_tags_in(&mut tx).await?;
+    }
+    tx.commit().await?;
+    Ok(cleaned)
+}
+
+async fn merge_or_rename(
+    conn: &mut SqliteConnection,
+    id: i32,
+    url: &str,
+    stripped: &str,
+    url_hash: &str,
+    pinned: bool,
+    existing: Option<i32>,
+) -> Result<(), Error> {
+    match existing {
+        Some(keep) => {
+            sqlx::query(
+                "INSERT OR IGNORE INTO url_tags (url_id, tag_id) SELECT ?, tag_id FROM url_tags WHERE url_id = ?",
+            )
+            .bind(keep)
+            .bind(id)
+            .execute(&mut *conn)
+            .await?;
+            sqlx::query(
+                "INSERT OR IGNORE INTO url_metadata (url_id, key, value, updated_at) \
+                 SELECT ?, key, value, updated_at FROM url_metadata WHERE url_id = ?",
+            )
+            .bind(keep)
+            .bind(id)
+            .execute(&mut *conn)
+            .await?;
+            for table in ["url_snapshots", "url_aliases"] {
+                sqlx::query(&format!("UPDATE {table} SET url_id = ? WHERE url_id = ?"))
+                    .bind(keep)
+                    .bind(id)
+                    .execute(&mut *conn)
+                    .await?;
+            }
+            sqlx::query(
+                "UPDATE urls SET pinned = (pinned OR ?), original_url = COALESCE(original_url, ?), \
+                 title = COALESCE(title, (SELECT title FROM urls WHERE id = ?)), \
+                 notes = COALESCE(notes, (SELECT notes FROM urls WHERE id = ?)) WHERE id = ?",
+            )
+            .bind(pinned)
+            .bind(url)
+            .bind(id)
+            .bind(id)
+            .bind(keep)
+            .execute(&mut *conn)
+            .await?;
+            sqlx::query("DELETE FROM urls WHERE id = ?")
+                .bind(id)
+                .execute(&mut *conn)
+                .await?;
+        }
+        None => {
+            sqlx::query(
+                "UPDATE urls SET url = ?, url_hash = ?, original_url = COALESCE(original_url, ?), normalized_url = ? \
+                 WHERE id = ?",
+            )
+            .bind(stripped)
+            .bind(url_hash)
+            .bind(url)
+            .bind(models::loose_url(stripped))
+            .bind(id)
+            .execute(&mut *conn)
+            .await?;
+        }
+    }
+
+    sqlx::query("UPDATE snippets SET url = ? WHERE url = ?")
+        .bind(stripped)
+        .bind(url)
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+/// Remove every URL, snippet and tag in a single transaction
+pub async fn delete_all_data(db_pool: &SqlitePool) -> Result<(), Error> {
+    let mut tx = db_pool.begin().await?;
+
+    for table in [
+        "snippet_tags",
+        "url_tags",
+        "snippet_images",
+        "snippets",
+        "url_snapshots",
+        "url_aliases",
+        "url_metadata",
+        "urls",
+        "tags",
+        "activity",
+        "import_job_items",
+        "import_jobs",
+        "peers",
+    ] {
+        sqlx::query(&format!("DELETE FROM {}", table)).execute(&mut *tx).await?;
+    }
+
+    tx.commit().await?;
+    Ok(())
+}
+
+/// A page of `table`'s rows as stored, for copying to another database
+pub async fn read_rows(
+    db_pool: &SqlitePool,
+    table: &models::CopiedTable,
+    offset: i64,
+    limit: i64,
+) -> Result<Vec<Vec<models::ColumnValue>>, Error> {
+    let columns: Vec<&str> = table.columns.iter().map(|(name, _)| *name).collect();
+    let query = format!(
+        "SELECT {} FROM {} ORDER BY {} LIMIT ? OFFSET ?",
+        columns.join(", "),
+        table.name,
+        table.order
+    );
+    let rows = sqlx::query(&query).bind(limit).bind(offset).fetch_all(db_pool).await?;
+
+    rows.iter()
+        .map(|row| {
+            table
+                .columns
+                .iter()
+                .enumerate()
+                .map(|(index, (_, kind))| read_value(row, index, *kind))
+                .collect()
+        })
+        .collect()
+}
+
+fn read_value(row: &SqliteRow, index: usize, kind: models::ColumnKind) -> Result<models::ColumnValue, Error> {
+    use models::{ColumnKind, ColumnValue};
+    Ok(match kind {
+        ColumnKind::Integer => row.try_get::<Option<i64>, _>(index)?.map(ColumnValue::Integer),
+        ColumnKind::Text | ColumnKind::Json => row.try_get::<Option<String>, _>(index)?.map(ColumnValue::Text),
+        ColumnKind::Timestamp => row
+            .try_get::<Option<chrono::NaiveDateTime>, _>(index)?
+            .map(ColumnValue::Timestamp),
+        ColumnKind::Boolean => row.try_get::<Option<bool>, _>(index)?.map(ColumnValue::Boolean),
+        ColumnKind::Bytes => row.try_get::<Option<Vec<u8>>, _>(index)?.map(ColumnValue::Bytes),
+        ColumnKind::TagList => row
+            .try_get::<Option<String>, _>(index)?
+            .map(|tags| ColumnValue::TagList(serde_json::from_str(&tags).unwrap_or_default())),
+    }
+    .unwrap_or(ColumnValue::Null))
+}
+
+/// Insert rows read from another database as they are, ids included, in one transaction
+pub async fn write_rows(
+    db_pool: &SqlitePool,
+    table: &models::CopiedTable,
+    rows: &[Vec<models::ColumnValue>],
+) -> Result<(), Error> {
+    use models::ColumnValue;
+    if rows.is_empty() {
+        return Ok(());
+    }
+    let columns: Vec<&str> = table.columns.iter().map(|(name, _)| *name).collect();
+    let mut query = QueryBuilder::<Sqlite>::new(format!("INSERT INTO {} ({}) ", table.name, columns.join(", ")));
+    query.push_values(rows, |mut values, row| {
+        for value in row {
+            match value {
+                ColumnValue::Null => values.push("NULL"),
+                ColumnValue::Integer(number) => values.push_bind(*number),
+                ColumnValue::Text(text) => values.push_bind(text.clone()),
+                ColumnValue::Timestamp(datetime) => values.push_bind(*datetime),
+                ColumnValue::Boolean(flag) => values.push_bind(*flag),
+                ColumnValue::Bytes(bytes) => values.push_bind(bytes.clone()),
+                ColumnValue::TagList(tags) => values.push_bind(serde_json::to_string(tags).unwrap_or_default()),
+            };
+        }
+    });
+
+    // AUTOINCREMENT keeps its counter past the highest id inserted, so there is no sequence to move
+    let mut tx = db_pool.begin().await?;
+    query.build().execute(&mut *tx).await?;
+    tx.commit().await
+}
+
+/// Record an entry in the activity timeline
+pub async fn record_activity(
+    db_pool: &SqlitePool,
+    action: &str,
+    subject: &str,
+    detail: Option<&str>,
+) -> Result<(), Error> {
+    record_activity_in(&mut *db_pool.acquire().await?, action, subject, detail).await
+}
+
+async fn record_activity_in(
+    conn: &mut SqliteConnection,
+    action: &str,
+    subject: &str,
+    detail: Option<&str>,
+) -> Result<(), Error> {
+    sqlx::query("INSERT INTO activity (action, subject, detail) VALUES (?, ?, ?)")
+        .bind(action)
+        .bind(subject)
+        .bind(detail)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+/// Record the timeline entry for a batch operation
+async fn record_batch_activity_in(
+    conn: &mut SqliteConnection,
+    operation: &models::BatchOperation,
+) -> Result<(), Error> {
+    match operation {
+        models::BatchOperation::AddUrl { url } => record_activity_in(conn, "save_url", url, Some("batch")).await,
+        models::BatchOperation::TagUrl { url, tags } => record_activity_in(conn, "tag_url", url, Some(tags)).await,
+        models::BatchOperation::DeleteSnippet { id } => {
+            record_activity_in(conn, "delete_snippet", &id.to_string(), Some("batch")).await
+        }
+    }
+}
+
+/// Tags linked to each snippet through `snippet_tags`
+async fn linked_snippet_tags(conn: &mut SqliteConnection) -> Result<HashMap<i32, BTreeSet<String>>, Error> {
+    let rows: Vec<(i32, String)> = sqlx::query_as(
+        "SELECT snippet_tags.snippet_id, tags.tag FROM snippet_tags JOIN tags ON tags.id = snippet_tags.tag_id",
+    )
+    .fetch_all(conn)
+    .await?;
+    let mut linked: HashMap<i32, BTreeSet<String>> = HashMap::new();
+    for (snippet_id, tag) in rows {
+        linked.entry(snippet_id).or_default().insert(tag);
+    }
+    Ok(linked)
+}
+
+/// Rewrite the `tags` column of snippets that have blanks or repeats in it, and relink their `snippet_tags` rows
+/// to match
+pub async fn repair_snippet_tags(db_pool: &SqlitePool, dry_run: bool) -> Result<Vec<i32>, Error> {
+    let mut tx = db_pool.begin().await?;
+    let columns: Vec<(i32, String)> = sqlx::query_as("SELECT id, tags FROM snippets ORDER BY id")
+        .fetch_all(&mut *tx)
+        .await?;
+    let linked = linked_snippet_tags(&mut tx).await?;
+
+    let mut repaired = Vec::new();
+    for (snippet_id, column) in columns {
+        let links = linked.get(&snippet_id).cloned().unwrap_or_default();
+        // A column that can't be read is rebuilt from the links, the only record left of the tags
+        let (stored, readable) = match serde_json::from_str::<Vec<String>>(&column) {
+            Ok(stored) => (stored, true),
+            Err(_) => (links.iter().cloned().collect(), false),
+        };
+        let tags = models::unique_tags(stored.iter().map(String::as_str));
+        let agrees = readable
+            && tags.iter().copied().eq(stored.iter().map(String::as_str))
+            && tags.len() == links.len()
+            && tags.iter().all(|tag| links.contains(*tag));
+        if agrees {
+            continue;
+        }
+        repaired.push(snippet_id);
+        if dry_run {
+            continue;
+        }
+
+        sqlx::query("UPDATE snippets SET tags = ? WHERE id = ?")
+            .bind(serde_json::to_string(&tags).unwrap_or("[]".to_string()))
+            .bind(snippet_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM snippet_tags WHERE snippet_id = ?")
+            .bind(snippet_id)
+            .execute(&mut *tx)
+            .await?;
+        for tag in &tags {
+            let tag_id = get_or_create_tag(&mut tx, tag).await?;
+            link_to_tag(&mut tx, tag_id, snippet_id, "snippet_tags", "snippet_id").await?;
+        }
+    }
+
+    if !repaired.is_empty() && !dry_run {
+        remove_all_unused_tags_in(&mut tx).await?;
+    }
+    tx.commit().await?;
+    Ok(repaired)
+}
+
+/// Fetch the most recent activity, newest first
+pub async fn get_activity(db_pool: &SqlitePool, limit: i64) -> Result<Vec<models::Activity>, Error> {
+    let query = r#"
+        SELECT id, datetime, action, subject, detail
+        FROM activity
+        ORDER BY id DESC
+        LIMIT ?
+    "#;
+
+    sqlx::query_as::<_, models::Activity>(query)
+        .bind(limit)
+        .fetch_all(db_pool)
+        .await
+}
+
+/// Fetch entries recorded after `after_id`, oldest first, leaving out changes applied from peers
+pub async fn get_local_activity_since(
+    db_pool: &SqlitePool,
+    after_id: i32,
+    limit: i64,
+) -> Result<Vec<models::Activity>, Error> {
+    let query = r#"
+        SELECT id, datetime, action, subject, detail
+        FROM activity
+        WHERE id > ? AND origin IS NULL
+        ORDER BY id
+        LIMIT ?
+    "#;
+
+    sqlx::query_as::<_, models::Activity>(query)
+        .bind(after_id)
+        .bind(limit)
+        .fetch_all(db_pool)
+        .await
+}
+
+/// Fetch the latest entry about `subject` with one of `actions`, made here or applied from a peer
+pub async fn get_latest_activity(
+    db_pool: &SqlitePool,
+    subject: &str,
+    actions: &[&str],
+) -> Result<Option<models::Activity>, Error> {
+    let mut query =
+        QueryBuilder::<Sqlite>::new("SELECT id, datetime, action, subject, detail FROM activity WHERE subject = ");
+    query.push_bind(subject).push(" AND action IN (");
+    let mut separated = query.separated(", ");
+    for action in actions {
+        separated.push_bind(*action);
+    }
+    query.push(") ORDER BY datetime DESC, id DESC LIMIT 1");
+
+    query.build_query_as::<models::Activity>().fetch_optional(db_pool).await
+}
+
+/// Fetch every entry with one of `actions`, oldest first, made here or applied from a peer
+pub async fn get_activity_by_actions(db_pool: &SqlitePool, actions: &[&str]) -> Result<Vec<models::Activity>, Error> {
+    let mut query =
+        QueryBuilder::<Sqlite>::new("SELECT id, datetime, action, subject, detail FROM activity WHERE action IN (");
+    let mut separated = query.separated(", ");
+    for action in actions {
+        separated.push_bind(*action);
+    }
+    query.push(") ORDER BY datetime, id");
+
+    query.build_query_as::<models::Activity>().fetch_all(db_pool).await
+}
+
+/// Record a change a peer made, dated when the peer made it
+pub async fn record_peer_activity(db_pool: &SqlitePool, peer: &str, activity: &models::Activity) -> Result<(), Error> {
+    sqlx::query("INSERT INTO activity (datetime, action, subject, detail, origin) VALUES (?, ?, ?, ?, ?)")
+        .bind(activity.datetime)
+        .bind(&activity.action)
+        .bind(&activity.subject)
+        .bind(&activity.detail)
+        .bind(peer)
+        .execute(db_pool)
+        .await?;
+    Ok(())
+}
+
+/// The id of the last change applied from `peer`, 0 before the first sync
+pub async fn get_peer_cursor(db_pool: &SqlitePool, peer: &str) -> Result<i32, Error> {
+    let cursor = sqlx::query_scalar("SELECT last_change_id FROM peers WHERE url = ?")
+        .bind(peer)
+        .fetch_optional(db_pool)
+        .await?;
+    Ok(cursor.unwrap_or(0))
+}
+
+pub async fn set_peer_cursor(db_pool: &SqlitePool, peer: &str, cursor: i32) -> Result<(), Error> {
+    let query = r#"
+        INSERT INTO peers (url, last_change_id, synced_at)
+        VALUES (?, ?, CURRENT_TIMESTAMP)
+        ON CONFLICT (url) DO UPDATE SET last_change_id = excluded.last_change_id, synced_at = excluded.synced_at
+    "#;
+
+    sqlx::query(query).bind(peer).bind(cursor).execute(db_pool).await?;
+    Ok(())
+}
+
+/// Store a new import job and all of its items in one transaction
+pub async fn create_import_job(db_pool: &SqlitePool, source: &str, items: &[models::ImportItem]) -> Result<i32, Error> {
+    let mut tx = db_pool.begin().await?;
+
+    let job_id: i32 = sqlx::query_scalar("INSERT INTO import_jobs (source, total) VALUES (?, ?) RETURNING id")
+        .bind(source)
+        .bind(items.len() as i32)
+        .fetch_one(&mut *tx)
+        .await?;
+
+    for item in items {
+        let tags = serde_json::to_string(&item.tags).map_err(|err| Error::Encode(Box::new(err)))?;
+        sqlx::query("INSERT INTO import_job_items (job_id, url, tags, note) VALUES (?, ?, ?, ?)")
+            .bind(job_id)
+            .bind(&item.url)
+            .bind(tags)
+            .bind(&item.note)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    tx.commit().await?;
+    Ok(job_id)
+}
+
+/// Fetch an import job with its first few errors
+pub async fn get_import_job(db_pool: &SqlitePool, id: i32) -> Result<Option<models::ImportJob>, Error> {
+    let query = r#"
+        SELECT id, source, status, total, processed, created, skipped, failed, started_at, finished_at
+        FROM import_jobs
+        WHERE id = ?
+    "#;
+    let Some(mut job) = sqlx::query_as::<_, models::ImportJob>(query)
+        .bind(id)
+        .fetch_optional(db_pool)
+        .await?
+    else {
+        return Ok(None);
+    };
+
+    job.errors = sqlx::query_scalar(
+        "SELECT url || ': ' || error FROM import_job_items WHERE job_id = ? AND error IS NOT NULL ORDER BY id LIMIT ?",
+    )
+    .bind(id)
+    .bind(models::IMPORT_JOB_ERROR_LIMIT)
+    .fetch_all(db_pool)
+    .await?;
+
+    Ok(Some(job))
+}
+
+/// Ids of jobs that haven't finished, e.g. because the server restarted mid-import
+pub async fn get_running_import_jobs(db_pool: &SqlitePool) -> Result<Vec<i32>, Error> {
+    sqlx::query_scalar("SELECT id FROM import_jobs WHERE status = 'running' ORDER BY id")
+        .fetch_all(db_pool)
+        .await
+}
+
+/// The next unprocessed items of a job, in the order they were submitted
+pub async fn get_pending_import_items(
+    db_pool: &SqlitePool,
+    job_id: i32,
+    limit: i64,
+) -> Result<Vec<models::PendingImportItem>, Error> {
+    let rows = sqlx::query(
+        "SELECT id, url, tags, note, started FROM import_job_items WHERE job_id = ? AND outcome IS NULL ORDER BY id LIMIT ?",
+    )
+    .bind(job_id)
+    .bind(limit)
+    .fetch_all(db_pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            let tags: String = row.get("tags");
+            models::PendingImportItem {
+                id: row.get("id"),
+                started: row.get("started"),
+                item: models::ImportItem {
+                    url: row.get("url"),
+                    tags: serde_json::from_str(&tags).unwrap_or_default(),
+                    note: row.get("note"),
+                },
+            }
+        })
+        .collect())
+}
+
+/// Record that an item's URL is about to be saved by its job
+pub async fn start_import_item(db_pool: &SqlitePool, job_id: i32, item_id: i32) -> Result<(), Error> {
+    sqlx::query("UPDATE import_job_items SET started = TRUE WHERE id = ? AND job_id = ?")
+        .bind(item_id)
+        .bind(job_id)
+        .execute(db_pool)
+        .await?;
+    Ok(())
+}
+
+/// Mark an item processed and count it against its job, in one transaction
+pub async fn finish_import_item(
+    db_pool: &SqlitePool,
+    job_id: i32,
+    item_id: i32,
+    outcome: &models::ImportOutcome,
+) -> Result<(), Error> {
+    let (outcome, error) = match outcome {
+        models::ImportOutcome::Created => ("created", None),
+        models::ImportOutcome::Skipped => ("skipped", None),
+        models::ImportOutcome::Failed(error) => ("failed", Some(error.as_str())),
+    };
+    let mut tx = db_pool.begin().await?;
+
+    let updated = sqlx::query(
+        "UPDATE import_job_items SET outcome = ?, error = ? WHERE id = ? AND job_id = ? AND outcome IS NULL",
+    )
+    .bind(outcome)
+    .bind(error)
+    .bind(item_id)
+    .bind(job_id)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+
+    // `outcome` is one of the three counter columns
+    if updated > 0 {
+        sqlx::query(&format!(
+            "UPDATE import_jobs SET processed = processed + 1, {outcome} = {outcome} + 1 WHERE id = ?"
+        ))
+        .bind(job_id)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await
+}
+
+/// Mark a job completed and drop its successful items; failed ones stay so their errors can be reported, without
+/// the notes they came with
+pub async fn complete_import_job(db_pool: &SqlitePool, id: i32) -> Result<(), Error> {
+    let mut tx = db_pool.begin().await?;
+
+    sqlx::query("UPDATE import_jobs SET status = 'completed', finished_at = CURRENT_TIMESTAMP WHERE id = ?")
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("DELETE FROM import_job_items WHERE job_id = ? AND error IS NULL")
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("UPDATE import_job_items SET note = NULL WHERE job_id = ?")
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::migrate_data;
+    use sqlx::SqlitePool;
+
+    async fn setup_test_db() -> SqlitePool {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        initialize_tables(&pool).await.unwrap();
+        pool
+    }
+
+    #[tokio::test]
+    async fn test_copied_tables_match_the_schema() {
+        let db_pool = setup_test_db().await;
+
+        let tables: Vec<String> = sqlx::query_scalar(
+            "SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' \
+             AND name != 'tag_counts' ORDER BY name",
+        )
+        .fetch_all(&db_pool)
+        .await
+        .unwrap();
+        let mut copied: Vec<&str> = migrate_data::TABLES.iter().map(|table| table.name).collect();
+        copied.sort();
+        assert_eq!(tables, copied);
+
+        for table in &migrate_data::TABLES {
+            let columns: Vec<String> = sqlx::query_scalar("SELECT name FROM pragma_table_info(?) ORDER BY cid")
+                .bind(table.name)
+                .fetch_all(&db_pool)
+                .await
+                .unwrap();
+            let listed: Vec<&str> = table.columns.iter().map(|(name, _)| *name).collect();
+            assert_eq!(columns, listed, "{}", table.name);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rows_copy_with_their_ids() {
+        let from = setup_test_db().await;
+        let to = setup_test_db().await;
+        insert_url(&from, "https://example.com/gone").await.unwrap();
+        insert_snippet(&from, "https://example.com/kept", "Quote", None, &["rust"], None)
+            .await
+            .unwrap();
+        delete_url_by_url(&from, "https://example.com/gone").await.unwrap();
+        insert_tags(&from, "https://example.com/kept", &["rust", "web"])
+            .await
+            .unwrap();
+
+        for table in &migrate_data::TABLES {
+            let rows = read_rows(&from, table, 0, 100).await.unwrap();
+            write_rows(&to, table, &rows).await.unwrap();
+            assert_eq!(read_rows(&to, table, 0, 100).await.unwrap(), rows, "{}", table.name);
+        }
+        let urls = get_urls_with_tags(&to, &Default::default()).await.unwrap();
+        assert_eq!(urls.len(), 1);
+        assert_eq!(urls[0].id, 2);
+        assert_eq!(urls[0].tags, vec!["rust", "web"]);
+        let tag_counts: i64 = sqlx::query_scalar("SELECT url_count + snippet_count FROM tag_counts WHERE tag_id = 1")
+            .fetch_one(&to)
+            .await
+            .unwrap();
+        assert_eq!(tag_counts, 2);
+        // New rows carry on after the copied ids
+        assert_eq!(insert_url(&to, "https://example.com/new").await.unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_initialize_tables() {
+        let db_pool = setup_test_db().await;
+        assert_eq!(check_health(&db_pool).await, "ok");
+    }
+
+    #[tokio::test]
+    async fn test_insert_url() {
+        let db_pool = setup_test_db().await;
+        let url = "https://example.com";
 
         let url_id = insert_url(&db_pool, url).await.unwrap();
         assert!(url_id > 0);
 
-        let inserted_url: (String,) = sqlx::query_as("SELECT url FROM urls WHERE id = ?")
-            .bind(url_id)
+        let inserted_url: (String,) = sqlx::query_as("SELECT url FROM urls WHERE id = ?")
+            .bind(url_id)
+            .fetch_one(&db_pool)
+            .await
+            .unwrap();
+        assert_eq!(inserted_url.0, url);
+    }
+
+    #[tokio::test]
+    async fn test_insert_snippet() {
+        let db_pool = setup_test_db().await;
+        let url = "https://example.com";
+        let snippet = "This is a test snippet.";
+        let tags = vec!["tag1", "tag2"];
+
+        let snippet_id = insert_snippet(&db_pool, url, snippet, None, &tags, None).await.unwrap();
+        assert!(snippet_id > 0);
+
+        let inserted_snippet: (String, String, String) =
+            sqlx::query_as("SELECT url, snippet, tags FROM snippets WHERE id = ?")
+                .bind(snippet_id)
+                .fetch_one(&db_pool)
+                .await
+                .unwrap();
+        assert_eq!(inserted_snippet.0, url);
+        assert_eq!(inserted_snippet.1, snippet);
+
+        let stored_tags: Vec<String> = serde_json::from_str(&inserted_snippet.2).unwrap_or_default();
+        assert_eq!(stored_tags, tags);
+    }
+
+    #[tokio::test]
+    async fn test_get_snippets_with_tags() {
+        let db_pool = setup_test_db().await;
+        let url = "https://example.com";
+        let snippet = "This is a test snippet.";
+        let tags = vec!["tag1", "tag2"];
+
+        insert_snippet(&db_pool, url, snippet, None, &tags, None).await.unwrap();
+
+        let snippets = get_snippets_with_tags(&db_pool, &Default::default()).await.unwrap();
+        assert_eq!(snippets.len(), 1);
+
+        let retrieved_snippet = &snippets[0];
+        assert_eq!(retrieved_snippet.url, url);
+        assert_eq!(retrieved_snippet.snippet, snippet);
+        assert_eq!(retrieved_snippet.tags, tags);
+    }
+
+    #[tokio::test]
+    async fn test_check_health() {
+        let db_pool = setup_test_db().await;
+        let health = check_health(&db_pool).await;
+        assert_eq!(health, "ok");
+    }
+    #[tokio::test]
+    async fn test_get_all_urls() {
+        let db_pool = setup_test_db().await;
+
+        let url1 = "https://example1.com";
+        let url2 = "https://example2.com";
+
+        insert_url(&db_pool, url1).await.unwrap();
+        insert_url(&db_pool, url2).await.unwrap();
+
+        let urls = get_all_urls(&db_pool, &Default::default()).await.unwrap();
+        assert_eq!(urls.len(), 2);
+        assert!(urls.iter().any(|u| u.url == url1));
+        assert!(urls.iter().any(|u| u.url == url2));
+    }
+
+    #[tokio::test]
+    async fn test_get_urls_with_tags() {
+        let db_pool = setup_test_db().await;
+
+        let url = "https://example.com";
+        let tags = vec!["tag1", "tag2"];
+        insert_tags(&db_pool, url, &tags).await.unwrap();
+
+        let urls_with_tags = get_urls_with_tags(&db_pool, &Default::default()).await.unwrap();
+        assert_eq!(urls_with_tags.len(), 1);
+        let retrieved = &urls_with_tags[0];
+        assert_eq!(retrieved.url, url);
+        assert_eq!(retrieved.tags, tags);
+    }
+
+    #[tokio::test]
+    async fn test_get_listing_fingerprint() {
+        let db_pool = setup_test_db().await;
+        let unread = models::ListFilter {
+            unread: true,
+            ..Default::default()
+        };
+
+        let empty = get_listing_fingerprint(&db_pool, &unread).await.unwrap();
+        assert_eq!(empty.etag(), "\"0-0-0-0\"");
+
+        let url = "https://example.com";
+        insert_url(&db_pool, url).await.unwrap();
+        let saved = get_listing_fingerprint(&db_pool, &unread).await.unwrap();
+        assert_eq!((saved.count, saved.max_id.is_some()), (1, true));
+
+        // Reading it takes it out of the unread listing, and logging that changes the whole library's fingerprint
+        let all = get_listing_fingerprint(&db_pool, &Default::default()).await.unwrap();
+        set_url_read(&db_pool, url, true).await.unwrap();
+        record_activity(&db_pool, "read_url", url, None).await.unwrap();
+        assert_eq!(get_listing_fingerprint(&db_pool, &unread).await.unwrap().count, 0);
+        assert_ne!(
+            get_listing_fingerprint(&db_pool, &Default::default()).await.unwrap(),
+            all
+        );
+
+        // Reading progress records no activity, but still changes the row and so the ETag
+        let before = get_listing_fingerprint(&db_pool, &Default::default()).await.unwrap();
+        set_url_progress(&db_pool, url, 40).await.unwrap();
+        let after = get_listing_fingerprint(&db_pool, &Default::default()).await.unwrap();
+        assert_ne!(after.etag(), before.etag());
+        set_url_progress(&db_pool, url, 60).await.unwrap();
+        let after = get_listing_fingerprint(&db_pool, &Default::default()).await.unwrap();
+        assert_ne!(after.etag(), before.etag());
+
+        // Saving it again or setting what it already has changes nothing, so neither does the ETag
+        insert_url(&db_pool, url).await.unwrap();
+        set_url_progress(&db_pool, url, 60).await.unwrap();
+        assert_eq!(
+            get_listing_fingerprint(&db_pool, &Default::default()).await.unwrap(),
+            after
+        );
+
+        // Tagging it records no activity here either, but the tags are part of the listing
+        insert_tags(&db_pool, url, &["rust"]).await.unwrap();
+        let tagged = get_listing_fingerprint(&db_pool, &Default::default()).await.unwrap();
+        assert_ne!(tagged, after);
+        set_url_tags(&db_pool, url, &[]).await.unwrap();
+        assert_ne!(
+            get_listing_fingerprint(&db_pool, &Default::default()).await.unwrap(),
+            tagged
+        );
+    }
+
+    #[tokio::test]
+    async fn test_revised_columns_are_every_column_but_the_revision() {
+        let db_pool = setup_test_db().await;
+        let mut columns: Vec<String> = sqlx::query_scalar("SELECT name FROM pragma_table_info('urls')")
+            .fetch_all(&db_pool)
+            .await
+            .unwrap();
+        columns.retain(|column| column != "id" && column != "revision");
+        columns.sort();
+        let mut revised = URL_REVISED_COLUMNS.to_vec();
+        revised.sort();
+        assert_eq!(columns, revised);
+    }
+
+    #[tokio::test]
+    async fn test_delete_url_by_url() {
+        let db_pool = setup_test_db().await;
+
+        let url = "https://example.com";
+        insert_url(&db_pool, url).await.unwrap();
+        delete_url_by_url(&db_pool, url).await.unwrap();
+
+        let urls = get_all_urls(&db_pool, &Default::default()).await.unwrap();
+        assert!(urls.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_get_loose_url_matches() {
+        let db_pool = setup_test_db().await;
+
+        insert_url(&db_pool, "http://Example.com/post/").await.unwrap();
+        insert_url(&db_pool, "https://example.com/other").await.unwrap();
+        assert_eq!(
+            get_loose_url_matches(&db_pool, "https://example.com/post")
+                .await
+                .unwrap(),
+            vec!["http://Example.com/post/"]
+        );
+
+        // Rows saved before the column existed get it filled in at startup
+        sqlx::query("UPDATE urls SET normalized_url = NULL")
+            .execute(&db_pool)
+            .await
+            .unwrap();
+        create_urls_table(&db_pool).await.unwrap();
+        insert_url(&db_pool, "https://example.com/post").await.unwrap();
+        assert_eq!(
+            get_loose_url_matches(&db_pool, "example.com/POST").await.unwrap(),
+            vec!["http://Example.com/post/", "https://example.com/post"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_insert_tags() {
+        let db_pool = setup_test_db().await;
+
+        let url = "https://example.com";
+        let tags = vec!["tag1", "tag2"];
+        insert_tags(&db_pool, url, &tags).await.unwrap();
+
+        let urls_with_tags = get_urls_with_tags(&db_pool, &Default::default()).await.unwrap();
+        assert_eq!(urls_with_tags.len(), 1);
+        assert_eq!(urls_with_tags[0].tags, tags);
+    }
+
+    #[tokio::test]
+    async fn test_remove_unused_tags() {
+        let db_pool = setup_test_db().await;
+
+        let remaining_tags = || async {
+            sqlx::query_scalar::<_, String>("SELECT tag FROM tags ORDER BY tag")
+                .fetch_all(&db_pool)
+                .await
+                .unwrap()
+        };
+
+        let url = "https://example.com";
+        insert_tags(&db_pool, url, &["tag1", "tag2", "shared"]).await.unwrap();
+        insert_tags(&db_pool, "https://example.com/other", &["shared"])
+            .await
+            .unwrap();
+        let snippet_id = insert_snippet(&db_pool, url, "A quote", None, &["tag2", "quoted"], None)
+            .await
+            .unwrap();
+        // Left over from before, and not this delete's to clear
+        sqlx::query("INSERT INTO tags (tag) VALUES ('stray')")
+            .execute(&db_pool)
+            .await
+            .unwrap();
+
+        delete_url_by_url(&db_pool, url).await.unwrap();
+        assert_eq!(remaining_tags().await, ["quoted", "shared", "stray", "tag2"]);
+
+        delete_snippet(&db_pool, snippet_id).await.unwrap();
+        assert_eq!(remaining_tags().await, ["shared", "stray"]);
+    }
+
+    #[tokio::test]
+    async fn test_delete_snippet() {
+        let db_pool = setup_test_db().await;
+
+        let url = "https://example.com";
+        let snippet = "This is a test snippet.";
+        let tags = vec!["tag1", "tag2"];
+        let snippet_id = insert_snippet(&db_pool, url, snippet, None, &tags, None).await.unwrap();
+
+        delete_snippet(&db_pool, snippet_id).await.unwrap();
+        let snippets = get_snippets_with_tags(&db_pool, &Default::default()).await.unwrap();
+        assert!(snippets.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_get_tags_with_urls_and_snippets() {
+        let db_pool = setup_test_db().await;
+
+        // Insert multiple URLs with overlapping and unique tags
+        let url1 = "https://example1.com";
+        let url2 = "https://example2.com";
+        let untagged_url = "https://untagged.com";
+        let snippet1 = "Test snippet content 1.";
+        let snippet2 = "Test snippet content 2.";
+        let snippet3 = "Test snippet content 3.";
+        let untagged_snippet = "Untagged snippet content.";
+
+        let tags_url1 = vec!["tag1", "tag2"];
+        let tags_url2 = vec!["tag2", "tag3"];
+        let tags_snippet1 = vec!["tag1", "tag4"];
+        let tags_snippet2 = vec!["tag2", "tag3"];
+        let tags_snippet3 = vec!["tag4"];
+
+        // Insert URLs and tags
+        insert_tags(&db_pool, url1, &tags_url1).await.unwrap();
+        insert_tags(&db_pool, url2, &tags_url2).await.unwrap();
+
+        // Insert snippets and their tags
+        insert_snippet(&db_pool, url1, snippet1, None, &tags_snippet1, None)
+            .await
+            .unwrap();
+        insert_snippet(&db_pool, url1, snippet2, None, &tags_snippet2, None)
+            .await
+            .unwrap();
+        insert_snippet(&db_pool, url2, snippet3, None, &tags_snippet3, None)
+            .await
+            .unwrap();
+
+        // Insert untagged URL and snippet
+        insert_url(&db_pool, untagged_url).await.unwrap();
+        insert_snippet(&db_pool, untagged_url, untagged_snippet, None, &[], None)
+            .await
+            .unwrap();
+
+        // Fetch all tags with associated URLs and snippets
+        let tags_with_urls_and_snippets = get_tags_with_urls_and_snippets(&db_pool).await.unwrap();
+
+        // Assertions
+        println!("Tags with URLs and snippets: {:?}", tags_with_urls_and_snippets);
+
+        // Verify untagged group exists
+        let untagged_group = tags_with_urls_and_snippets
+            .iter()
+            .find(|group| group.tag.is_empty())
+            .expect("Untagged group not found!");
+        assert!(
+            untagged_group.urls.contains(&untagged_url.to_string()),
+            "Untagged URL not found in the untagged group"
+        );
+        assert!(
+            untagged_group
+                .snippets
+                .iter()
+                .any(|snippet| snippet.snippet == untagged_snippet),
+            "Untagged snippet not found in the untagged group"
+        );
+
+        // Verify tagged groups and their content
+        for tag_group in tags_with_urls_and_snippets {
+            match tag_group.tag.as_str() {
+                "tag1" => {
+                    assert!(tag_group.urls.contains(&url1.to_string()));
+                    assert!(tag_group.snippets.iter().any(|s| s.snippet == snippet1));
+                }
+                "tag2" => {
+                    assert!(tag_group.urls.contains(&url1.to_string()));
+                    assert!(tag_group.urls.contains(&url2.to_string()));
+                    assert!(tag_group.snippets.iter().any(|s| s.snippet == snippet2));
+                }
+                "tag3" => {
+                    assert!(tag_group.urls.contains(&url2.to_string()));
+                    assert!(tag_group.snippets.iter().any(|s| s.snippet == snippet2));
+                }
+                "tag4" => {
+                    assert!(tag_group.snippets.iter().any(|s| s.snippet == snippet1));
+                    assert!(tag_group.snippets.iter().any(|s| s.snippet == snippet3));
+                }
+                _ => {} // Ignore unexpected groups
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_untagged_urls_are_included() {
+        let db_pool = setup_test_db().await;
+
+        // Insert URLs with and without tags
+        let tagged_url = "https://tagged-url.com";
+        let untagged_url = "https://untagged-url.com";
+
+        let snippet_for_untagged = "Snippet for untagged URL.";
+
+        // Insert a tagged URL
+        let tags = vec!["example-tag"];
+        insert_tags(&db_pool, tagged_url, &tags).await.unwrap();
+
+        // Insert an untagged URL
+        insert_url(&db_pool, untagged_url).await.unwrap();
+
+        // Insert a snippet associated with the untagged URL
+        insert_snippet(&db_pool, untagged_url, snippet_for_untagged, None, &[], None)
+            .await
+            .unwrap();
+
+        // Fetch all tags with associated URLs and snippets
+        let tags_with_urls_and_snippets = get_tags_with_urls_and_snippets(&db_pool).await.unwrap();
+
+        // Print the results for debugging
+        println!("Tags with URLs and snippets: {:?}", tags_with_urls_and_snippets);
+
+        // Verify untagged URLs are included under a group with an empty tag
+        let untagged_group = tags_with_urls_and_snippets
+            .iter()
+            .find(|group| group.tag.is_empty())
+            .expect("No untagged group found!");
+
+        assert!(
+            untagged_group.urls.contains(&untagged_url.to_string()),
+            "Untagged URL not found in the untagged group"
+        );
+
+        assert!(
+            untagged_group
+                .snippets
+                .iter()
+                .any(|snippet| snippet.snippet == snippet_for_untagged),
+            "Snippet for untagged URL not found in the untagged group"
+        );
+
+        // Verify tagged URL is present in the correct group
+        let tagged_group = tags_with_urls_and_snippets
+            .iter()
+            .find(|group| group.tag == "example-tag")
+            .expect("Tagged group not found!");
+
+        assert!(
+            tagged_group.urls.contains(&tagged_url.to_string()),
+            "Tagged URL not found in the tagged group"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_all_tags() {
+        let db_pool = setup_test_db().await;
+
+        insert_tags(&db_pool, "https://example.com", &["rust", "async"])
+            .await
+            .unwrap();
+        insert_snippet(&db_pool, "https://example.com", "snippet", None, &["notes"], None)
+            .await
+            .unwrap();
+
+        let tags = get_all_tags(&db_pool).await.unwrap();
+        assert_eq!(tags, vec!["async", "notes", "rust"]);
+    }
+
+    #[tokio::test]
+    async fn test_delete_all_data() {
+        let db_pool = setup_test_db().await;
+
+        insert_tags(&db_pool, "https://example.com", &["tag1"]).await.unwrap();
+        insert_snippet(&db_pool, "https://example.com", "snippet", None, &["tag2"], None)
+            .await
+            .unwrap();
+        set_peer_cursor(&db_pool, "https://peer.example", 42).await.unwrap();
+
+        delete_all_data(&db_pool).await.unwrap();
+
+        assert!(get_all_urls(&db_pool, &Default::default()).await.unwrap().is_empty());
+        assert!(get_snippets_with_tags(&db_pool, &Default::default())
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(get_all_tags(&db_pool).await.unwrap().is_empty());
+        assert_eq!(get_peer_cursor(&db_pool, "https://peer.example").await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_execute_batch() {
+        let db_pool = setup_test_db().await;
+        let snippet_id = insert_snippet(&db_pool, "https://example.com", "snippet", None, &["old"], None)
+            .await
+            .unwrap();
+
+        let operations = vec![
+            models::BatchOperation::AddUrl {
+                url: "https://example1.com".to_string(),
+            },
+            models::BatchOperation::TagUrl {
+                url: "https://example2.com".to_string(),
+                tags: "rust, async".to_string(),
+            },
+            models::BatchOperation::DeleteSnippet { id: snippet_id },
+        ];
+        let results = execute_batch(&db_pool, &operations).await.unwrap();
+
+        assert_eq!(results.len(), 3);
+        assert!(results[0].is_some());
+        assert_eq!(get_all_urls(&db_pool, &Default::default()).await.unwrap().len(), 2);
+        assert!(get_snippets_with_tags(&db_pool, &Default::default())
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(get_all_tags(&db_pool).await.unwrap(), vec!["async", "rust"]);
+    }
+
+    #[tokio::test]
+    async fn test_execute_batch_rolls_back_on_failure() {
+        let db_pool = setup_test_db().await;
+        sqlx::query("DROP TABLE url_tags").execute(&db_pool).await.unwrap();
+
+        let operations = vec![
+            models::BatchOperation::AddUrl {
+                url: "https://example1.com".to_string(),
+            },
+            models::BatchOperation::TagUrl {
+                url: "https://example2.com".to_string(),
+                tags: "rust".to_string(),
+            },
+        ];
+        let err = execute_batch(&db_pool, &operations).await.unwrap_err();
+
+        assert_eq!(err.index, Some(1));
+        assert!(get_all_urls(&db_pool, &Default::default()).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_activity_timeline() {
+        let db_pool = setup_test_db().await;
+
+        record_activity(&db_pool, "save_url", "https://example.com", None)
+            .await
+            .unwrap();
+        record_activity(&db_pool, "tag_url", "https://example.com", Some("rust"))
+            .await
+            .unwrap();
+
+        let activity = get_activity(&db_pool, 10).await.unwrap();
+        assert_eq!(activity.len(), 2);
+        assert_eq!(activity[0].action, "tag_url");
+        assert_eq!(activity[0].detail.as_deref(), Some("rust"));
+        assert_eq!(activity[1].action, "save_url");
+
+        assert_eq!(get_activity(&db_pool, 1).await.unwrap().len(), 1);
+
+        let saves = get_activity_by_actions(&db_pool, &["save_url", "read_url"])
+            .await
+            .unwrap();
+        assert_eq!(saves.len(), 1);
+        assert_eq!(saves[0].action, "save_url");
+    }
+
+    #[tokio::test]
+    async fn test_anniversary_filter() {
+        let db_pool = setup_test_db().await;
+
+        for (url, datetime) in [
+            ("https://a.example", "2023-03-14 23:30:00"),
+            ("https://b.example", "2021-03-14 08:00:00"),
+            ("https://c.example", "2023-03-15 00:00:00"),
+            ("https://d.example", "2019-03-14 08:00:00"),
+        ] {
+            insert_url(&db_pool, url).await.unwrap();
+            sqlx::query("UPDATE urls SET datetime = ? WHERE url = ?")
+                .bind(datetime)
+                .bind(url)
+                .execute(&db_pool)
+                .await
+                .unwrap();
+        }
+
+        let today = chrono::NaiveDate::from_ymd_opt(2024, 3, 14).unwrap();
+        let filter = models::ListFilter {
+            anniversary: Some(models::Anniversary::of(today, &[1, 2, 3])),
+            ..Default::default()
+        };
+        let urls: Vec<String> = get_all_urls(&db_pool, &filter)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|url| url.url)
+            .collect();
+        assert_eq!(urls, ["https://a.example", "https://b.example"]);
+    }
+
+    #[tokio::test]
+    async fn test_tag_filter_expressions() {
+        let db_pool = setup_test_db().await;
+
+        insert_tags(&db_pool, "https://a.com", &["rust", "async"])
+            .await
+            .unwrap();
+        insert_tags(&db_pool, "https://b.com", &["rust", "video"])
+            .await
+            .unwrap();
+        insert_tags(&db_pool, "https://c.com", &["python"]).await.unwrap();
+        insert_url(&db_pool, "https://d.com").await.unwrap();
+        insert_snippet(&db_pool, "https://a.com", "Snippet", None, &["rust", "async"], None)
+            .await
+            .unwrap();
+
+        let urls_for = |expression: &str| {
+            let filter = models::ListFilter {
+                tags: models::TagFilter::parse(expression),
+                ..Default::default()
+            };
+            let db_pool = db_pool.clone();
+            async move {
+                let mut urls: Vec<String> = get_all_urls(&db_pool, &filter)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|url| url.url)
+                    .collect();
+                urls.sort();
+                urls
+            }
+        };
+
+        assert_eq!(urls_for("rust").await, vec!["https://a.com", "https://b.com"]);
+        assert_eq!(urls_for("rust+async").await, vec!["https://a.com"]);
+        assert_eq!(urls_for("async,python").await, vec!["https://a.com", "https://c.com"]);
+        assert_eq!(urls_for("rust,-video").await, vec!["https://a.com"]);
+        assert_eq!(urls_for("-rust").await, vec!["https://c.com", "https://d.com"]);
+
+        let filter = models::ListFilter {
+            tags: models::TagFilter::parse("rust+async"),
+            ..Default::default()
+        };
+        let urls_with_tags = get_urls_with_tags(&db_pool, &filter).await.unwrap();
+        assert_eq!(urls_with_tags.len(), 1);
+        assert_eq!(urls_with_tags[0].tags.len(), 2);
+        assert_eq!(get_snippets_with_tags(&db_pool, &filter).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_untagged_filter() {
+        let db_pool = setup_test_db().await;
+
+        insert_tags(&db_pool, "https://a.com", &["rust"]).await.unwrap();
+        insert_url(&db_pool, "https://b.com").await.unwrap();
+        insert_snippet(&db_pool, "https://a.com", "Tagged", None, &["rust"], None)
+            .await
+            .unwrap();
+        insert_snippet(&db_pool, "https://a.com", "Untagged", None, &[""], None)
+            .await
+            .unwrap();
+
+        let filter = models::ListFilter::untagged();
+        let urls = get_urls_with_tags(&db_pool, &filter).await.unwrap();
+        assert_eq!(urls.len(), 1);
+        assert_eq!(urls[0].url, "https://b.com");
+
+        let snippets = get_snippets_with_tags(&db_pool, &filter).await.unwrap();
+        assert_eq!(snippets.len(), 1);
+        assert_eq!(snippets[0].snippet, "Untagged");
+    }
+
+    #[tokio::test]
+    async fn test_date_range_filter() {
+        let db_pool = setup_test_db().await;
+
+        for (url, saved) in [
+            ("https://october.com", "2024-10-31 23:59:59"),
+            ("https://november.com", "2024-11-15 12:00:00"),
+            ("https://december.com", "2024-12-01 00:00:00"),
+        ] {
+            insert_url(&db_pool, url).await.unwrap();
+            sqlx::query("UPDATE urls SET datetime = ? WHERE url = ?")
+                .bind(saved)
+                .bind(url)
+                .execute(&db_pool)
+                .await
+                .unwrap();
+        }
+
+        let query = models::ListQuery {
+            from: Some("2024-11-01".to_string()),
+            to: Some("2024-11-30".to_string()),
+            ..Default::default()
+        };
+        let filter = models::ListFilter::try_from(&query).unwrap();
+
+        let urls = get_all_urls(&db_pool, &filter).await.unwrap();
+        assert_eq!(urls.len(), 1);
+        assert_eq!(urls[0].url, "https://november.com");
+
+        let urls_with_tags = get_urls_with_tags(&db_pool, &filter).await.unwrap();
+        assert_eq!(urls_with_tags.len(), 1);
+        assert_eq!(urls_with_tags[0].url, "https://november.com");
+    }
+
+    #[tokio::test]
+    async fn test_url_sort() {
+        let db_pool = setup_test_db().await;
+
+        insert_url(&db_pool, "https://www.zeta.com/a").await.unwrap();
+        insert_url(&db_pool, "http://alpha.org/b").await.unwrap();
+        insert_url(&db_pool, "https://mid.net/c").await.unwrap();
+
+        let urls_sorted_by = |sort: &str| {
+            let filter = models::ListFilter {
+                sort: models::Sort::parse(sort).unwrap(),
+                ..Default::default()
+            };
+            let db_pool = db_pool.clone();
+            async move {
+                get_all_urls(&db_pool, &filter)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|url| url.url)
+                    .collect::<Vec<_>>()
+            }
+        };
+
+        assert_eq!(
+            urls_sorted_by("domain").await,
+            vec!["http://alpha.org/b", "https://mid.net/c", "https://www.zeta.com/a"]
+        );
+        assert_eq!(
+            urls_sorted_by("url:desc").await,
+            vec!["https://www.zeta.com/a", "https://mid.net/c", "http://alpha.org/b"]
+        );
+        // Saved within the same second, so the id tie-breaker decides
+        assert_eq!(
+            urls_sorted_by("added").await,
+            vec!["https://mid.net/c", "http://alpha.org/b", "https://www.zeta.com/a"]
+        );
+
+        // Titled or not, URLs sort as one alphabet
+        let mid = get_url_with_tags(&db_pool, "https://mid.net/c").await.unwrap().unwrap();
+        let retitle = models::UrlUpdate {
+            title: Some(Some("Bees".to_string())),
+            ..Default::default()
+        };
+        update_url(&db_pool, mid.id, &retitle).await.unwrap();
+        assert_eq!(
+            urls_sorted_by("title").await,
+            vec!["http://alpha.org/b", "https://mid.net/c", "https://www.zeta.com/a"]
+        );
+
+        // Latest read first, and unread ones last either way
+        sqlx::query("UPDATE urls SET read_at = '2024-01-01 00:00:00' WHERE url = 'https://www.zeta.com/a'")
+            .execute(&db_pool)
+            .await
+            .unwrap();
+        sqlx::query("UPDATE urls SET read_at = '2024-02-01 00:00:00' WHERE url = 'http://alpha.org/b'")
+            .execute(&db_pool)
+            .await
+            .unwrap();
+        assert_eq!(
+            urls_sorted_by("read").await,
+            vec!["http://alpha.org/b", "https://www.zeta.com/a", "https://mid.net/c"]
+        );
+        assert_eq!(
+            urls_sorted_by("read:asc").await,
+            vec!["https://www.zeta.com/a", "http://alpha.org/b", "https://mid.net/c"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_read_state_and_random_pick() {
+        let db_pool = setup_test_db().await;
+
+        insert_tags(&db_pool, "https://read.com", &["rust"]).await.unwrap();
+        insert_tags(&db_pool, "https://unread.com", &["rust"]).await.unwrap();
+        insert_url(&db_pool, "https://other.com").await.unwrap();
+        set_url_read(&db_pool, "https://read.com", true).await.unwrap();
+
+        let filter = models::ListFilter {
+            tags: models::TagFilter::parse("rust"),
+            unread: true,
+            ..Default::default()
+        };
+        for _ in 0..5 {
+            let picked = get_random_url(&db_pool, &filter).await.unwrap().unwrap();
+            assert_eq!(picked.url, "https://unread.com");
+        }
+
+        set_url_read(&db_pool, "https://unread.com", true).await.unwrap();
+        assert!(get_random_url(&db_pool, &filter).await.unwrap().is_none());
+
+        set_url_read(&db_pool, "https://read.com", false).await.unwrap();
+        let urls = get_all_urls(&db_pool, &Default::default()).await.unwrap();
+        let read = urls.iter().find(|url| url.url == "https://read.com").unwrap();
+        assert!(read.read_at.is_none());
+
+        assert!(matches!(
+            set_url_read(&db_pool, "https://missing.com", true).await,
+            Err(Error::RowNotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_initialize_adds_read_at_to_existing_urls_table() {
+        let db_pool = SqlitePool::connect(":memory:").await.unwrap();
+        sqlx::query(
+            "CREATE TABLE urls (id INTEGER PRIMARY KEY AUTOINCREMENT, datetime TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP, url TEXT NOT NULL, url_hash CHAR(64) NOT NULL UNIQUE)",
+        )
+        .execute(&db_pool)
+        .await
+        .unwrap();
+
+        initialize_tables(&db_pool).await.unwrap();
+        initialize_tables(&db_pool).await.unwrap();
+
+        insert_url(&db_pool, "https://example.com").await.unwrap();
+        set_url_read(&db_pool, "https://example.com", true).await.unwrap();
+        let urls = get_all_urls(&db_pool, &Default::default()).await.unwrap();
+        assert!(urls[0].read_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_get_url_by_short_id() {
+        let db_pool = setup_test_db().await;
+
+        insert_url(&db_pool, "https://example.com").await.unwrap();
+        let url = get_all_urls(&db_pool, &Default::default()).await.unwrap().remove(0);
+        assert_eq!(url.short_id.len(), models::SHORT_ID_LENGTH);
+        assert!(url.url_hash.starts_with(&url.short_id));
+
+        let found = get_url_by_short_id(&db_pool, &url.short_id).await.unwrap().unwrap();
+        assert_eq!(found.url, "https://example.com");
+
+        let urls_with_tags = get_urls_with_tags(&db_pool, &Default::default()).await.unwrap();
+        assert_eq!(urls_with_tags[0].short_id, url.short_id);
+
+        assert!(get_url_by_short_id(&db_pool, "0000000000").await.unwrap().is_none());
+        assert!(get_url_by_short_id(&db_pool, "%").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_short_ids_are_kept_and_never_shared() {
+        let db_pool = setup_test_db().await;
+
+        let id = insert_url(&db_pool, "https://old.example/post").await.unwrap();
+        let before = get_url_by_short_id(&db_pool, &calculate_url_hash("https://old.example/post")[..10])
+            .await
+            .unwrap()
+            .unwrap();
+        move_url(&db_pool, id, "https://new.example/post").await.unwrap();
+        let after = get_url_by_short_id(&db_pool, &before.short_id).await.unwrap().unwrap();
+        assert_eq!((after.id, after.url.as_str()), (id, "https://new.example/post"));
+
+        // A URL whose hash starts the same way as a saved short id is given another one
+        let hash = calculate_url_hash("https://other.example");
+        sqlx::query("UPDATE urls SET short_id = ? WHERE id = ?")
+            .bind(&hash[..10])
+            .bind(id)
+            .execute(&db_pool)
+            .await
+            .unwrap();
+        insert_url(&db_pool, "https://other.example").await.unwrap();
+        let other = get_url_with_tags(&db_pool, "https://other.example")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(other.short_id, &hash[1..11]);
+
+        // Ones shared from before short ids were stored find neither URL
+        sqlx::query("UPDATE urls SET short_id = ?")
+            .bind(&hash[..10])
+            .execute(&db_pool)
+            .await
+            .unwrap();
+        assert!(get_url_by_short_id(&db_pool, &hash[..10]).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_get_url_with_tags() {
+        use crate::services::models::Database;
+
+        let database = SqliteDatabase::new(":memory:").await.unwrap();
+        database.initialize().await.unwrap();
+        database
+            .insert_tags("https://news.example/story?id=7", &["news", "rust"])
+            .await
+            .unwrap();
+        database.insert_url("https://news.example/story?id=8").await.unwrap();
+
+        // Found under the form saving would store it as
+        let saved = database
+            .get_url_with_tags("https://news.example/story?id=7&utm_source=feed")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(saved.url, "https://news.example/story?id=7");
+        let mut tags = saved.tags;
+        tags.sort();
+        assert_eq!(tags, ["news", "rust"]);
+        assert!(saved.read_at.is_none());
+
+        assert!(database
+            .get_url_with_tags("https://news.example/story?id=9")
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_update_url() {
+        let db_pool = setup_test_db().await;
+        insert_tags(&db_pool, "https://example.com/talk", &["old"])
+            .await
+            .unwrap();
+        let saved = || async {
+            get_url_with_tags(&db_pool, "https://example.com/talk")
+                .await
+                .unwrap()
+                .unwrap()
+        };
+        let url_id = saved().await.id;
+
+        let update = models::UrlUpdate {
+            title: Some(Some("A talk".to_string())),
+            notes: Some(Some("Watch the second half".to_string())),
+            read: Some(true),
+            archived: Some(true),
+            snoozed_until: Some(Some("2026-11-01T09:00:00Z".parse().unwrap())),
+            tags: Some("rust, web".to_string()),
+        };
+        update_url(&db_pool, url_id, &update).await.unwrap();
+        let url = saved().await;
+        assert_eq!(url.title.as_deref(), Some("A talk"));
+        assert_eq!(url.notes.as_deref(), Some("Watch the second half"));
+        assert!(url.read_at.is_some() && url.archived_at.is_some());
+        assert_eq!(url.snoozed_until.unwrap().to_string(), "2026-11-01 09:00:00");
+        let mut tags = url.tags;
+        tags.sort();
+        assert_eq!(tags, ["rust", "web"]);
+
+        // Only what is sent changes
+        let update = models::UrlUpdate {
+            notes: Some(None),
+            read: Some(false),
+            snoozed_until: Some(None),
+            ..Default::default()
+        };
+        update_url(&db_pool, url_id, &update).await.unwrap();
+        let url = saved().await;
+        assert_eq!(url.title.as_deref(), Some("A talk"));
+        assert_eq!(url.notes, None);
+        assert!(url.read_at.is_none() && url.archived_at.is_some());
+        assert_eq!(url.snoozed_until, None);
+        assert_eq!(url.tags.len(), 2);
+
+        let tags_only = models::UrlUpdate {
+            tags: Some(String::new()),
+            ..Default::default()
+        };
+        update_url(&db_pool, url_id, &tags_only).await.unwrap();
+        assert!(saved().await.tags.is_empty());
+
+        assert!(matches!(
+            update_url(&db_pool, url_id + 1, &tags_only).await,
+            Err(Error::RowNotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_import_job_progress() {
+        let db_pool = setup_test_db().await;
+        let items = vec![
+            models::ImportItem {
+                url: "https://example.com".to_string(),
+                tags: vec!["rust".to_string(), "a,b".to_string()],
+                note: Some("note".to_string()),
+            },
+            models::ImportItem {
+                url: "https://broken.com".to_string(),
+                tags: Vec::new(),
+                note: None,
+            },
+        ];
+        let id = create_import_job(&db_pool, "test", &items).await.unwrap();
+
+        let pending = get_pending_import_items(&db_pool, id, 10).await.unwrap();
+        assert_eq!(pending.len(), 2);
+        assert_eq!(pending[0].item, items[0]);
+
+        finish_import_item(&db_pool, id, pending[0].id, &models::ImportOutcome::Created)
+            .await
+            .unwrap();
+        // Finishing the same item again doesn't count it twice
+        finish_import_item(&db_pool, id, pending[0].id, &models::ImportOutcome::Created)
+            .await
+            .unwrap();
+        finish_import_item(
+            &db_pool,
+            id,
+            pending[1].id,
+            &models::ImportOutcome::Failed("timed out".to_string()),
+        )
+        .await
+        .unwrap();
+        assert!(get_pending_import_items(&db_pool, id, 10).await.unwrap().is_empty());
+
+        let job = get_import_job(&db_pool, id).await.unwrap().unwrap();
+        assert_eq!(job.status, "running");
+        assert_eq!((job.total, job.processed, job.created, job.failed), (2, 2, 1, 1));
+        assert_eq!(job.errors, vec!["https://broken.com: timed out"]);
+        assert_eq!(get_running_import_jobs(&db_pool).await.unwrap(), vec![id]);
+
+        complete_import_job(&db_pool, id).await.unwrap();
+        let job = get_import_job(&db_pool, id).await.unwrap().unwrap();
+        assert_eq!(job.status, "completed");
+        assert!(job.finished_at.is_some());
+        assert_eq!(job.errors.len(), 1);
+        assert!(get_running_import_jobs(&db_pool).await.unwrap().is_empty());
+        assert!(get_import_job(&db_pool, id + 1).await.unwrap().is_none());
+
+        // Notes aren't kept once the job is over
+        let notes: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM import_job_items WHERE note IS NOT NULL")
             .fetch_one(&db_pool)
             .await
             .unwrap();
-        assert_eq!(inserted_url.0, url);
+        assert_eq!(notes, 0);
     }
 
     #[tokio::test]
-    async fn test_insert_snippet() {
+    async fn test_tag_filter_includes_nested_tags() {
         let db_pool = setup_test_db().await;
-        let url = "https://example.com";
-        let snippet = "This is a test snippet.";
-        let tags = vec!["tag1", "tag2"];
 
-        let snippet_id = insert_snippet(&db_pool, url, snippet, &tags).await.unwrap();
-        assert!(snippet_id > 0);
+        insert_tags(&db_pool, "https://rust.com", &["lang/rust"]).await.unwrap();
+        insert_tags(&db_pool, "https://go.com", &["lang/go", "video"])
+            .await
+            .unwrap();
+        insert_tags(&db_pool, "https://lang.com", &["lang"]).await.unwrap();
+        insert_tags(&db_pool, "https://tools.com", &["lang-tools"])
+            .await
+            .unwrap();
+        insert_snippet(&db_pool, "https://rust.com", "Snippet", None, &["lang/rust"], None)
+            .await
+            .unwrap();
 
-        let inserted_snippet: (String, String, String) =
-            sqlx::query_as("SELECT url, snippet, tags FROM snippets WHERE id = ?")
-                .bind(snippet_id)
-                .fetch_one(&db_pool)
+        let urls_for = |expression: &str| {
+            let filter = models::ListFilter {
+                tags: models::TagFilter::parse(expression),
+                ..Default::default()
+            };
+            let db_pool = db_pool.clone();
+            async move {
+                let mut urls: Vec<String> = get_all_urls(&db_pool, &filter)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|url| url.url)
+                    .collect();
+                urls.sort();
+                urls
+            }
+        };
+
+        assert_eq!(
+            urls_for("lang").await,
+            vec!["https://go.com", "https://lang.com", "https://rust.com"]
+        );
+        assert_eq!(urls_for("lang/rust").await, vec!["https://rust.com"]);
+        assert_eq!(urls_for("lang+video").await, vec!["https://go.com"]);
+        assert_eq!(
+            urls_for("lang,-lang/go").await,
+            vec!["https://lang.com", "https://rust.com"]
+        );
+        assert_eq!(urls_for("-lang").await, vec!["https://tools.com"]);
+
+        let filter = models::ListFilter {
+            tags: models::TagFilter::parse("lang"),
+            ..Default::default()
+        };
+        let snippets = get_snippets_with_tags(&db_pool, &filter).await.unwrap();
+        assert_eq!(snippets.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_tag_details() {
+        let db_pool = setup_test_db().await;
+        insert_tags(&db_pool, "https://example.com", &["rust", "web"])
+            .await
+            .unwrap();
+
+        set_tag_details(&db_pool, "rust", Some("#ff8800"), Some("Systems language"))
+            .await
+            .unwrap();
+        assert!(matches!(
+            set_tag_details(&db_pool, "missing", Some("#ff8800"), None).await,
+            Err(Error::RowNotFound)
+        ));
+
+        let tags = get_tag_details(&db_pool).await.unwrap();
+        assert_eq!(
+            tags,
+            vec![
+                models::TagDetails {
+                    tag: "rust".to_string(),
+                    color: Some("#ff8800".to_string()),
+                    description: Some("Systems language".to_string()),
+                },
+                models::TagDetails {
+                    tag: "web".to_string(),
+                    color: None,
+                    description: None,
+                },
+            ]
+        );
+
+        set_tag_details(&db_pool, "rust", None, None).await.unwrap();
+        let rust = get_tag(&db_pool, "rust").await.unwrap().unwrap();
+        assert_eq!((rust.color, rust.description), (None, None));
+        assert!(get_tag(&db_pool, "missing").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_import_tags() {
+        let db_pool = setup_test_db().await;
+        insert_tags(&db_pool, "https://example.com", &["rust", "web"])
+            .await
+            .unwrap();
+        set_tag_details(&db_pool, "web", Some("#0088ff"), Some("Browsers"))
+            .await
+            .unwrap();
+
+        let details = |tag: &str, color: Option<&str>, description: Option<&str>| models::TagDetails {
+            tag: tag.to_string(),
+            color: color.map(String::from),
+            description: description.map(String::from),
+        };
+        let imported = import_tags(
+            &db_pool,
+            &[
+                details("rust", Some("#ff8800"), None),
+                details("web", None, Some("The web platform")),
+                details("lang/go", Some("#00add8"), Some("Go")),
+            ],
+        )
+        .await
+        .unwrap();
+        assert_eq!(imported, models::TagImport { created: 1, updated: 2 });
+
+        // A detail the import leaves out keeps the local value
+        assert_eq!(
+            get_tag_details(&db_pool).await.unwrap(),
+            vec![
+                details("lang/go", Some("#00add8"), Some("Go")),
+                details("rust", Some("#ff8800"), None),
+                details("web", Some("#0088ff"), Some("The web platform")),
+            ]
+        );
+
+        // Tags with details outlast the last URL carrying them, and maintenance sweeps
+        delete_url_by_url(&db_pool, "https://example.com").await.unwrap();
+        let mut tx = db_pool.begin().await.unwrap();
+        remove_all_unused_tags_in(&mut tx).await.unwrap();
+        tx.commit().await.unwrap();
+        let tags: Vec<_> = get_tag_details(&db_pool)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|tag| tag.tag)
+            .collect();
+        assert_eq!(tags, ["lang/go", "rust", "web"]);
+    }
+
+    #[tokio::test]
+    async fn test_pinned_items_sort_first() {
+        let db_pool = setup_test_db().await;
+
+        insert_url(&db_pool, "https://www.zeta.com/a").await.unwrap();
+        insert_url(&db_pool, "http://alpha.org/b").await.unwrap();
+        insert_url(&db_pool, "https://mid.net/c").await.unwrap();
+        set_url_pinned(&db_pool, "https://www.zeta.com/a", true).await.unwrap();
+
+        for sort in ["domain", "url:desc", "added:asc"] {
+            let filter = models::ListFilter {
+                sort: models::Sort::parse(sort).unwrap(),
+                ..Default::default()
+            };
+            let urls_with_tags = get_urls_with_tags(&db_pool, &filter).await.unwrap();
+            assert_eq!(urls_with_tags[0].url, "https://www.zeta.com/a", "sort={}", sort);
+            assert!(urls_with_tags[0].pinned);
+            assert!(!urls_with_tags[1].pinned);
+        }
+
+        set_url_pinned(&db_pool, "https://www.zeta.com/a", false).await.unwrap();
+        let filter = models::ListFilter {
+            sort: models::Sort::parse("domain").unwrap(),
+            ..Default::default()
+        };
+        let urls = get_all_urls(&db_pool, &filter).await.unwrap();
+        assert_eq!(urls[0].url, "http://alpha.org/b");
+
+        assert!(matches!(
+            set_url_pinned(&db_pool, "https://missing.example", true).await,
+            Err(Error::RowNotFound)
+        ));
+
+        let first = insert_snippet(&db_pool, "https://mid.net/c", "first", None, &[], None)
+            .await
+            .unwrap();
+        insert_snippet(&db_pool, "https://mid.net/c", "second", None, &[], None)
+            .await
+            .unwrap();
+        set_snippet_pinned(&db_pool, first, true).await.unwrap();
+
+        let snippets = get_snippets_with_tags(&db_pool, &models::ListFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(snippets[0].snippet, "first");
+        assert!(snippets[0].pinned);
+        assert!(matches!(
+            set_snippet_pinned(&db_pool, 9999, true).await,
+            Err(Error::RowNotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_get_snippets_for_url() {
+        let db_pool = setup_test_db().await;
+
+        let first = insert_snippet(&db_pool, "https://example.com/post", "first", None, &["rust"], None)
+            .await
+            .unwrap();
+        let second = insert_snippet(&db_pool, "https://example.com/post", "second", None, &[], None)
+            .await
+            .unwrap();
+        insert_snippet(&db_pool, "https://other.example", "elsewhere", None, &[], None)
+            .await
+            .unwrap();
+        set_snippet_pinned(&db_pool, second, true).await.unwrap();
+
+        let snippets = get_snippets_for_url(&db_pool, "https://example.com/post")
+            .await
+            .unwrap();
+        let ids: Vec<_> = snippets.iter().map(|snippet| snippet.id).collect();
+        assert_eq!(ids, vec![second, first]);
+        assert_eq!(snippets[1].tags, vec!["rust".to_string()]);
+
+        assert!(get_snippets_for_url(&db_pool, "https://missing.example")
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_get_snippets_saved_before() {
+        let db_pool = setup_test_db().await;
+
+        let old = insert_snippet(&db_pool, "https://example.com/post", "old", None, &[], None)
+            .await
+            .unwrap();
+        let undated = insert_snippet(&db_pool, "https://example.com/post", "undated", None, &[], None)
+            .await
+            .unwrap();
+        insert_snippet(&db_pool, "https://example.com/post", "new", None, &[], None)
+            .await
+            .unwrap();
+        sqlx::query("UPDATE snippets SET saved_at = '2024-01-01 09:00:00' WHERE id = ?")
+            .bind(old)
+            .execute(&db_pool)
+            .await
+            .unwrap();
+        sqlx::query("UPDATE snippets SET saved_at = NULL WHERE id = ?")
+            .bind(undated)
+            .execute(&db_pool)
+            .await
+            .unwrap();
+
+        let cutoff = chrono::NaiveDate::from_ymd_opt(2024, 6, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+        let ids: Vec<_> = get_snippets_saved_before(&db_pool, cutoff)
+            .await
+            .unwrap()
+            .iter()
+            .map(|snippet| snippet.id)
+            .collect();
+        assert_eq!(ids, vec![old, undated]);
+    }
+
+    #[tokio::test]
+    async fn test_reading_progress() {
+        let db_pool = setup_test_db().await;
+
+        insert_url(&db_pool, "https://example.com/long-read").await.unwrap();
+        set_url_progress(&db_pool, "https://example.com/long-read", 40)
+            .await
+            .unwrap();
+
+        let urls = get_all_urls(&db_pool, &models::ListFilter::default()).await.unwrap();
+        assert_eq!(urls[0].progress, 40);
+        let urls_with_tags = get_urls_with_tags(&db_pool, &models::ListFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(urls_with_tags[0].progress, 40);
+
+        assert!(matches!(
+            set_url_progress(&db_pool, "https://missing.example", 10).await,
+            Err(Error::RowNotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_get_next_url() {
+        let db_pool = setup_test_db().await;
+
+        insert_url(&db_pool, "https://first.example").await.unwrap();
+        insert_url(&db_pool, "https://second.example").await.unwrap();
+        insert_tags(&db_pool, "https://second.example", &["rust"])
+            .await
+            .unwrap();
+
+        let unread_oldest_first = models::ListFilter {
+            unread: true,
+            sort: models::Sort::parse("added:asc").unwrap(),
+            ..Default::default()
+        };
+        let next = get_next_url(&db_pool, &unread_oldest_first).await.unwrap().unwrap();
+        assert_eq!(next.url, "https://first.example");
+
+        set_url_read(&db_pool, "https://first.example", true).await.unwrap();
+        let next = get_next_url(&db_pool, &unread_oldest_first).await.unwrap().unwrap();
+        assert_eq!(next.url, "https://second.example");
+
+        let untagged_unread = models::ListFilter {
+            untagged: true,
+            ..unread_oldest_first.clone()
+        };
+        assert!(get_next_url(&db_pool, &untagged_unread).await.unwrap().is_none());
+
+        set_url_read(&db_pool, "https://second.example", true).await.unwrap();
+        assert!(get_next_url(&db_pool, &unread_oldest_first).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_snapshots() {
+        let db_pool = setup_test_db().await;
+
+        let url_id = insert_url(&db_pool, "https://docs.example/page").await.unwrap();
+        insert_url(&db_pool, "https://other.example").await.unwrap();
+
+        let first = insert_snapshot(&db_pool, url_id, Some("text/html"), "v1", false, 2)
+            .await
+            .unwrap();
+        assert!(first.is_some());
+        // Unchanged pages aren't stored twice
+        assert_eq!(
+            insert_snapshot(&db_pool, url_id, Some("text/html"), "v1", false, 2)
                 .await
-                .unwrap();
-        assert_eq!(inserted_snippet.0, url);
-        assert_eq!(inserted_snippet.1, snippet);
+                .unwrap(),
+            None
+        );
+        let second = insert_snapshot(&db_pool, url_id, None, "v2", false, 2)
+            .await
+            .unwrap()
+            .unwrap();
+        let third = insert_snapshot(&db_pool, url_id, None, "version 3", true, 2)
+            .await
+            .unwrap()
+            .unwrap();
 
-        let stored_tags: Vec<String> = serde_json::from_str(&inserted_snippet.2).unwrap_or_default();
-        assert_eq!(stored_tags, tags);
+        // Only the newest two are kept
+        let snapshots = get_snapshots(&db_pool, url_id).await.unwrap();
+        let ids: Vec<_> = snapshots.iter().map(|snapshot| snapshot.id).collect();
+        assert_eq!(ids, vec![third, second]);
+        assert_eq!(snapshots[0].size, 9);
+        assert!(snapshots[0].truncated);
+        assert!(!snapshots[1].truncated);
+
+        assert_eq!(
+            get_snapshot(&db_pool, url_id, second).await.unwrap().unwrap().content,
+            "v2"
+        );
+        assert!(get_snapshot(&db_pool, url_id, first.unwrap()).await.unwrap().is_none());
+        assert!(get_snapshot(&db_pool, url_id + 1, second).await.unwrap().is_none());
+
+        let tracked = get_snapshotted_urls(&db_pool).await.unwrap();
+        assert_eq!(tracked.len(), 1);
+        assert_eq!(tracked[0].url, "https://docs.example/page");
+
+        delete_url_by_url(&db_pool, "https://docs.example/page").await.unwrap();
+        assert!(get_snapshots(&db_pool, url_id).await.unwrap().is_empty());
     }
 
     #[tokio::test]
-    async fn test_get_snippets_with_tags() {
+    async fn test_url_metadata_entries() {
         let db_pool = setup_test_db().await;
-        let url = "https://example.com";
-        let snippet = "This is a test snippet.";
-        let tags = vec!["tag1", "tag2"];
+        let url_id = insert_url(&db_pool, "https://docs.example/page").await.unwrap();
 
-        insert_snippet(&db_pool, url, snippet, &tags).await.unwrap();
+        assert_eq!(
+            get_url_metadata_entry(&db_pool, url_id, "language").await.unwrap(),
+            None
+        );
+        set_url_metadata_entry(&db_pool, url_id, "language", &serde_json::json!("en"))
+            .await
+            .unwrap();
+        set_url_metadata_entry(&db_pool, url_id, "reading_time", &serde_json::json!({ "minutes": 4 }))
+            .await
+            .unwrap();
+        // Setting a name again replaces its value
+        set_url_metadata_entry(&db_pool, url_id, "language", &serde_json::json!("de"))
+            .await
+            .unwrap();
 
-        let snippets = get_snippets_with_tags(&db_pool).await.unwrap();
-        assert_eq!(snippets.len(), 1);
+        assert_eq!(
+            get_url_metadata_entry(&db_pool, url_id, "language").await.unwrap(),
+            Some(serde_json::json!("de"))
+        );
+        let entries = get_url_metadata_entries(&db_pool, url_id).await.unwrap();
+        assert_eq!(entries.keys().collect::<Vec<_>>(), vec!["language", "reading_time"]);
+        assert_eq!(entries["reading_time"]["minutes"], 4);
+
+        assert!(matches!(
+            set_url_metadata_entry(&db_pool, url_id + 1, "language", &serde_json::json!("en")).await,
+            Err(Error::RowNotFound)
+        ));
+
+        // Entries go with the URL
+        delete_url_by_url(&db_pool, "https://docs.example/page").await.unwrap();
+        assert!(get_url_metadata_entries(&db_pool, url_id).await.unwrap().is_empty());
+    }
 
-        let retrieved_snippet = &snippets[0];
-        assert_eq!(retrieved_snippet.url, url);
-        assert_eq!(retrieved_snippet.snippet, snippet);
-        assert_eq!(retrieved_snippet.tags, tags);
+    #[tokio::test]
+    async fn test_retention_never_deletes_what_it_archived_unread() {
+        use crate::services::models::Database;
+
+        let database = SqliteDatabase::new(":memory:").await.unwrap();
+        database.initialize().await.unwrap();
+        insert_url(&database.pool, "https://unread.example").await.unwrap();
+        insert_snippet(&database.pool, "https://unread.example", "Quote", None, &[], None)
+            .await
+            .unwrap();
+        sqlx::query("UPDATE urls SET datetime = '2020-01-01 00:00:00'")
+            .execute(&database.pool)
+            .await
+            .unwrap();
+
+        let day = std::time::Duration::from_secs(24 * 60 * 60);
+        let settings = crate::config::Retention {
+            archive_unread_after: Some(day),
+            delete_read_after: Some(day),
+            ..Default::default()
+        };
+        // Twice, so the second run sees what the first archived
+        for _ in 0..2 {
+            crate::services::retention::apply(&database, &settings, false)
+                .await
+                .unwrap();
+        }
+
+        let kept = get_url_with_tags(&database.pool, "https://unread.example")
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(kept.archived_at.is_some());
+        assert!(kept.read_at.is_none());
+        assert_eq!(
+            get_snippets_for_url(&database.pool, "https://unread.example")
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
     }
 
     #[tokio::test]
-    async fn test_check_health() {
+    async fn test_retention_rules() {
         let db_pool = setup_test_db().await;
-        let health = check_health(&db_pool).await;
-        assert_eq!(health, "ok");
+
+        for url in [
+            "https://old.example",
+            "https://old-pinned.example",
+            "https://new.example",
+        ] {
+            insert_url(&db_pool, url).await.unwrap();
+        }
+        set_url_pinned(&db_pool, "https://old-pinned.example", true)
+            .await
+            .unwrap();
+        sqlx::query("UPDATE urls SET datetime = '2020-01-01 00:00:00' WHERE url != 'https://new.example'")
+            .execute(&db_pool)
+            .await
+            .unwrap();
+        let cutoff = chrono::NaiveDate::from_ymd_opt(2024, 1, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+
+        // A dry run reports without changing anything
+        assert_eq!(
+            archive_unread_before(&db_pool, cutoff, true).await.unwrap(),
+            vec!["https://old.example"]
+        );
+        assert!(get_all_urls(&db_pool, &Default::default())
+            .await
+            .unwrap()
+            .iter()
+            .all(|url| url.read_at.is_none()));
+
+        assert_eq!(
+            archive_unread_before(&db_pool, cutoff, false).await.unwrap(),
+            vec!["https://old.example"]
+        );
+        assert!(archive_unread_before(&db_pool, cutoff, false).await.unwrap().is_empty());
+        let archived = get_url_with_tags(&db_pool, "https://old.example")
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(archived.archived_at.is_some());
+        assert!(archived.read_at.is_none());
+
+        insert_tags(&db_pool, "https://old.example", &["stale"]).await.unwrap();
+        sqlx::query(
+            "UPDATE urls SET read_at = '2020-06-01 00:00:00', archived_at = NULL WHERE url = 'https://old.example'",
+        )
+        .execute(&db_pool)
+        .await
+        .unwrap();
+        assert_eq!(
+            archive_read_before(&db_pool, cutoff, false).await.unwrap(),
+            vec!["https://old.example"]
+        );
+        assert!(archive_read_before(&db_pool, cutoff, false).await.unwrap().is_empty());
+        let active = models::ListFilter {
+            archived: Some(false),
+            ..Default::default()
+        };
+        assert_eq!(get_all_urls(&db_pool, &active).await.unwrap().len(), 2);
+        assert_eq!(
+            delete_read_before(&db_pool, cutoff, false).await.unwrap(),
+            vec!["https://old.example"]
+        );
+        let remaining: Vec<_> = get_all_urls(&db_pool, &Default::default())
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|url| url.url)
+            .collect();
+        assert_eq!(remaining.len(), 2);
+        assert!(get_all_tags(&db_pool).await.unwrap().is_empty());
+
+        // Snapshots: the one viewed most recently survives even though it is the oldest
+        let url_id = insert_url(&db_pool, "https://snap.example").await.unwrap();
+        let oldest = insert_snapshot(&db_pool, url_id, None, "aaaa", false, 10)
+            .await
+            .unwrap()
+            .unwrap();
+        let middle = insert_snapshot(&db_pool, url_id, None, "bbbb", false, 10)
+            .await
+            .unwrap()
+            .unwrap();
+        let newest = insert_snapshot(&db_pool, url_id, None, "cccc", false, 10)
+            .await
+            .unwrap()
+            .unwrap();
+        sqlx::query("UPDATE url_snapshots SET datetime = '2020-01-01 00:00:00'")
+            .execute(&db_pool)
+            .await
+            .unwrap();
+        get_snapshot(&db_pool, url_id, oldest).await.unwrap();
+
+        let evicted = evict_snapshots(&db_pool, 8, true).await.unwrap();
+        assert_eq!(
+            evicted.iter().map(|snapshot| snapshot.id).collect::<Vec<_>>(),
+            vec![middle]
+        );
+        assert_eq!(get_snapshots(&db_pool, url_id).await.unwrap().len(), 3);
+
+        evict_snapshots(&db_pool, 8, false).await.unwrap();
+        let kept: Vec<_> = get_snapshots(&db_pool, url_id)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|snapshot| snapshot.id)
+            .collect();
+        assert_eq!(kept, vec![newest, oldest]);
     }
+
     #[tokio::test]
-    async fn test_get_all_urls() {
+    async fn test_tracking_params_stripped_on_save() {
+        use crate::services::models::Database;
+
+        let database = SqliteDatabase::new(":memory:").await.unwrap();
+        database.initialize().await.unwrap();
+
+        let tracked = "https://news.example/story?id=7&utm_source=feed&fbclid=abc";
+        let url_id = database.insert_url(tracked).await.unwrap();
+        // The same page shared with different tracking is the same URL
+        assert_eq!(
+            database
+                .insert_url("https://news.example/story?id=7&utm_medium=social")
+                .await
+                .unwrap(),
+            url_id
+        );
+        database.set_url_read(tracked, true).await.unwrap();
+
+        let urls = database.get_all_urls(&Default::default()).await.unwrap();
+        assert_eq!(urls.len(), 1);
+        assert_eq!(urls[0].url, "https://news.example/story?id=7");
+        assert_eq!(urls[0].original_url.as_deref(), Some(tracked));
+        assert!(urls[0].read_at.is_some());
+
+        let untracked = SqliteDatabase::new(":memory:")
+            .await
+            .unwrap()
+            .with_tracking_params(tracking::TrackingParams::parse(""));
+        untracked.initialize().await.unwrap();
+        untracked.insert_url(tracked).await.unwrap();
+        let urls = untracked.get_all_urls(&Default::default()).await.unwrap();
+        assert_eq!(urls[0].url, tracked);
+        assert_eq!(urls[0].original_url, None);
+    }
+
+    #[tokio::test]
+    async fn test_snippet_tags_stay_in_agreement() {
         let db_pool = setup_test_db().await;
 
-        let url1 = "https://example1.com";
-        let url2 = "https://example2.com";
+        let id = insert_snippet(
+            &db_pool,
+            "https://a.example",
+            "Quote",
+            None,
+            &["tag1", " tag1", ""],
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(get_snippet(&db_pool, id).await.unwrap().unwrap().tags, vec!["tag1"]);
+        assert!(repair_snippet_tags(&db_pool, false).await.unwrap().is_empty());
+
+        // Rows written before tags were deduplicated, and a link the column doesn't mention
+        let stale = insert_snippet(&db_pool, "https://b.example", "Other", None, &["tag2"], None)
+            .await
+            .unwrap();
+        sqlx::query("UPDATE snippets SET tags = '[\"tag2\",\"tag2\",\" tag3\"]' WHERE id = ?")
+            .bind(stale)
+            .execute(&db_pool)
+            .await
+            .unwrap();
+        let mut conn = db_pool.acquire().await.unwrap();
+        let extra = get_or_create_tag(&mut conn, "extra").await.unwrap();
+        link_to_tag(&mut conn, extra, stale, "snippet_tags", "snippet_id")
+            .await
+            .unwrap();
+        drop(conn);
+
+        assert_eq!(repair_snippet_tags(&db_pool, true).await.unwrap(), vec![stale]);
+        assert_eq!(repair_snippet_tags(&db_pool, false).await.unwrap(), vec![stale]);
+        assert_eq!(
+            get_snippet(&db_pool, stale).await.unwrap().unwrap().tags,
+            vec!["tag2", "tag3"]
+        );
+        let mut conn = db_pool.acquire().await.unwrap();
+        let linked = linked_snippet_tags(&mut conn).await.unwrap();
+        assert_eq!(linked[&stale].iter().collect::<Vec<_>>(), vec!["tag2", "tag3"]);
+        drop(conn);
+        assert!(!get_all_tags(&db_pool).await.unwrap().contains(&"extra".to_string()));
+        assert!(repair_snippet_tags(&db_pool, false).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_clean_urls() {
+        let db_pool = setup_test_db().await;
+        let tracking = tracking::TrackingParams::default();
+        let normalize = |url: &str| tracking.strip(url).into_owned();
 
-        insert_url(&db_pool, url1).await.unwrap();
-        insert_url(&db_pool, url2).await.unwrap();
+        insert_tags(&db_pool, "https://a.example/?utm_source=x", &["one"])
+            .await
+            .unwrap();
+        insert_tags(&db_pool, "https://b.example/", &["two"]).await.unwrap();
+        let duplicate = insert_url(&db_pool, "https://b.example/?gclid=1").await.unwrap();
+        insert_tags(&db_pool, "https://b.example/?gclid=1", &["three"])
+            .await
+            .unwrap();
+        set_url_pinned(&db_pool, "https://b.example/?gclid=1", true)
+            .await
+            .unwrap();
+        insert_snapshot(&db_pool, duplicate, None, "page", false, 10)
+            .await
+            .unwrap();
+        insert_snippet(&db_pool, "https://a.example/?utm_source=x", "quote", None, &[], None)
+            .await
+            .unwrap();
 
-        let urls = get_all_urls(&db_pool).await.unwrap();
+        let expected = vec![
+            models::CleanedUrl {
+                original: "https://a.example/?utm_source=x".to_string(),
+                url: "https://a.example/".to_string(),
+                merged: false,
+            },
+            models::CleanedUrl {
+                original: "https://b.example/?gclid=1".to_string(),
+                url: "https://b.example/".to_string(),
+                merged: true,
+            },
+        ];
+        assert_eq!(clean_urls(&db_pool, &normalize, true).await.unwrap(), expected);
+        assert_eq!(get_all_urls(&db_pool, &Default::default()).await.unwrap().len(), 3);
+
+        assert_eq!(clean_urls(&db_pool, &normalize, false).await.unwrap(), expected);
+        let urls = get_urls_with_tags(&db_pool, &Default::default()).await.unwrap();
         assert_eq!(urls.len(), 2);
-        assert!(urls.iter().any(|u| u.url == url1));
-        assert!(urls.iter().any(|u| u.url == url2));
+        let b = urls.iter().find(|url| url.url == "https://b.example/").unwrap();
+        assert!(b.pinned);
+        assert_eq!(b.tags.len(), 2);
+
+        let kept = get_all_urls(&db_pool, &Default::default()).await.unwrap();
+        let b_id = kept.iter().find(|url| url.url == "https://b.example/").unwrap().id;
+        assert_eq!(get_snapshots(&db_pool, b_id).await.unwrap().len(), 1);
+        assert_eq!(
+            get_snippets_for_url(&db_pool, "https://a.example/")
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        assert!(clean_urls(&db_pool, &normalize, false).await.unwrap().is_empty());
     }
 
     #[tokio::test]
-    async fn test_get_urls_with_tags() {
-        let db_pool = setup_test_db().await;
+    async fn test_move_url() {
+        use crate::services::models::Database;
 
-        let url = "https://example.com";
-        let tags = vec!["tag1", "tag2"];
-        insert_tags(&db_pool, url, &tags).await.unwrap();
+        let database = SqliteDatabase::new(":memory:").await.unwrap();
+        database.initialize().await.unwrap();
 
-        let urls_with_tags = get_urls_with_tags(&db_pool).await.unwrap();
-        assert_eq!(urls_with_tags.len(), 1);
-        let retrieved = &urls_with_tags[0];
-        assert_eq!(retrieved.url, url);
-        assert_eq!(retrieved.tags, tags);
+        let url_id = database.insert_url("https://old.example/post").await.unwrap();
+        database
+            .insert_tags("https://old.example/post", &["rust"])
+            .await
+            .unwrap();
+        database
+            .insert_snippet("https://old.example/post", "A quote", &[], None)
+            .await
+            .unwrap();
+        let moved = database
+            .move_url(url_id, "https://new.example/post?utm_source=feed")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(moved.url, "https://new.example/post");
+        assert!(!moved.merged);
+
+        // Unlike a canonical URL, the old address is let go
+        assert!(!database.url_exists("https://old.example/post").await.unwrap());
+        let saved = database
+            .get_url_with_tags("https://new.example/post")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!((saved.id, saved.tags), (url_id, vec!["rust".to_string()]));
+        assert_eq!(
+            database
+                .get_snippets_for_url("https://new.example/post")
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        // Moving onto a saved URL folds this one into it, keeping notes it didn't have
+        let other_id = database.insert_url("https://other.example/").await.unwrap();
+        database.insert_tags("https://other.example/", &["web"]).await.unwrap();
+        let notes = models::UrlUpdate {
+            notes: Some(Some("Worth a reread".to_string())),
+            ..Default::default()
+        };
+        database.update_url(url_id, &notes).await.unwrap();
+        let moved = database
+            .move_url(url_id, "https://other.example/")
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(moved.merged);
+        let merged = database
+            .get_url_with_tags("https://other.example/")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(merged.id, other_id);
+        assert_eq!(merged.notes.as_deref(), Some("Worth a reread"));
+        let mut tags = merged.tags;
+        tags.sort();
+        assert_eq!(tags, ["rust", "web"]);
+        assert_eq!(database.get_urls_with_tags(&Default::default()).await.unwrap().len(), 1);
+        assert_eq!(
+            database
+                .get_snippets_for_url("https://other.example/")
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        assert!(database
+            .move_url(other_id, "https://other.example/")
+            .await
+            .unwrap()
+            .is_none());
     }
 
     #[tokio::test]
-    async fn test_delete_url_by_url() {
-        let db_pool = setup_test_db().await;
+    async fn test_adopt_canonical_url() {
+        use crate::services::models::Database;
 
-        let url = "https://example.com";
-        insert_url(&db_pool, url).await.unwrap();
-        delete_url_by_url(&db_pool, url).await.unwrap();
+        let database = SqliteDatabase::new(":memory:").await.unwrap();
+        database.initialize().await.unwrap();
 
-        let urls = get_all_urls(&db_pool).await.unwrap();
-        assert!(urls.is_empty());
+        let url_id = database.insert_url("https://news.example/s/7").await.unwrap();
+        database
+            .insert_tags("https://news.example/s/7", &["news"])
+            .await
+            .unwrap();
+        let moved = database
+            .adopt_canonical_url(url_id, "https://news.example/story?utm_source=feed")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(moved.url, "https://news.example/story");
+        assert!(!moved.merged);
+        assert!(database
+            .adopt_canonical_url(url_id, "https://news.example/story")
+            .await
+            .unwrap()
+            .is_none());
+
+        // The old address still finds the URL, and saving it again doesn't make a duplicate
+        assert!(database.url_exists("https://news.example/s/7").await.unwrap());
+        assert_eq!(database.insert_url("https://news.example/s/7").await.unwrap(), url_id);
+        database.set_url_read("https://news.example/s/7", true).await.unwrap();
+        let urls = database.get_all_urls(&Default::default()).await.unwrap();
+        assert_eq!(urls.len(), 1);
+        assert_eq!(urls[0].url, "https://news.example/story");
+        assert_eq!(urls[0].original_url.as_deref(), Some("https://news.example/s/7"));
+        assert!(urls[0].read_at.is_some());
+
+        // A second address for an already saved page is merged into it
+        let amp_id = database.insert_url("https://news.example/print/7").await.unwrap();
+        database
+            .insert_tags("https://news.example/print/7", &["amp"])
+            .await
+            .unwrap();
+        let merged = database
+            .adopt_canonical_url(amp_id, "https://news.example/story")
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(merged.merged);
+        let urls = database.get_urls_with_tags(&Default::default()).await.unwrap();
+        assert_eq!(urls.len(), 1);
+        assert_eq!(urls[0].tags.len(), 2);
+        assert_eq!(
+            database.insert_url("https://news.example/print/7").await.unwrap(),
+            url_id
+        );
     }
 
     #[tokio::test]
-    async fn test_insert_tags() {
-        let db_pool = setup_test_db().await;
-
-        let url = "https://example.com";
-        let tags = vec!["tag1", "tag2"];
-        insert_tags(&db_pool, url, &tags).await.unwrap();
+    async fn test_amp_urls_unwrapped_on_save() {
+        use crate::services::models::Database;
+
+        let database = SqliteDatabase::new(":memory:").await.unwrap();
+        database.initialize().await.unwrap();
+
+        let url_id = database.insert_url("https://news.example/story").await.unwrap();
+        for shared in [
+            "https://www.google.com/amp/s/news.example/story",
+            "https://news-example.cdn.ampproject.org/c/s/news.example/story/amp?usqp=mq331AQ",
+            "https://m.news.example/story?utm_source=app",
+        ] {
+            assert_eq!(database.insert_url(shared).await.unwrap(), url_id, "{}", shared);
+        }
+        assert_eq!(database.get_all_urls(&Default::default()).await.unwrap().len(), 1);
 
-        let urls_with_tags = get_urls_with_tags(&db_pool).await.unwrap();
-        assert_eq!(urls_with_tags.len(), 1);
-        assert_eq!(urls_with_tags[0].tags, tags);
+        let kept = SqliteDatabase::new(":memory:")
+            .await
+            .unwrap()
+            .with_amp_unwrapping(false);
+        kept.initialize().await.unwrap();
+        kept.insert_url("https://m.news.example/story").await.unwrap();
+        assert_eq!(
+            kept.get_all_urls(&Default::default()).await.unwrap()[0].url,
+            "https://m.news.example/story"
+        );
     }
 
     #[tokio::test]
-    async fn test_remove_unused_tags() {
-        let db_pool = setup_test_db().await;
+    async fn test_video_metadata_and_type_filter() {
+        use crate::services::models::Database;
 
-        let url = "https://example.com";
-        let tags = vec!["tag1", "tag2"];
-        insert_tags(&db_pool, url, &tags).await.unwrap();
-        delete_url_by_url(&db_pool, url).await.unwrap();
-        remove_unused_tags(&db_pool).await.unwrap();
+        let database = SqliteDatabase::new(":memory:").await.unwrap();
+        database.initialize().await.unwrap();
 
-        let remaining_tags: Vec<String> = sqlx::query_scalar("SELECT tag FROM tags")
-            .fetch_all(&db_pool)
+        database
+            .insert_url("https://www.youtube.com/watch?v=abc123")
+            .await
+            .unwrap();
+        database.insert_url("https://example.com/article").await.unwrap();
+        let video = models::UrlMetadata::Video(models::VideoMetadata {
+            provider: "YouTube".to_string(),
+            title: Some("A talk".to_string()),
+            channel: Some("Rust".to_string()),
+            duration: Some(253),
+            thumbnail_url: Some("https://i.ytimg.com/vi/abc123/hqdefault.jpg".to_string()),
+        });
+        // Stored under the saved form of the URL, tracking parameters and all
+        database
+            .set_url_metadata("https://www.youtube.com/watch?v=abc123&utm_source=share", &video)
             .await
             .unwrap();
-        assert!(remaining_tags.is_empty());
+        assert!(matches!(
+            database.set_url_metadata("https://unsaved.example", &video).await,
+            Err(Error::RowNotFound)
+        ));
+
+        let filter = models::ListFilter {
+            kind: Some(models::UrlKind::Video),
+            ..Default::default()
+        };
+        let videos = database.get_urls_with_tags(&filter).await.unwrap();
+        assert_eq!(videos.len(), 1);
+        assert_eq!(videos[0].metadata.as_ref(), Some(&video));
+        assert_eq!(database.get_all_urls(&filter).await.unwrap().len(), 1);
+
+        let all = database.get_urls_with_tags(&Default::default()).await.unwrap();
+        let article = all.iter().find(|url| url.url == "https://example.com/article").unwrap();
+        assert_eq!(article.metadata, None);
     }
 
     #[tokio::test]
-    async fn test_delete_snippet() {
-        let db_pool = setup_test_db().await;
-
-        let url = "https://example.com";
-        let snippet = "This is a test snippet.";
-        let tags = vec!["tag1", "tag2"];
-        let snippet_id = insert_snippet(&db_pool, url, snippet, &tags).await.unwrap();
+    async fn test_audio_type_filter() {
+        use crate::services::models::Database;
+
+        let database = SqliteDatabase::new(":memory:").await.unwrap();
+        database.initialize().await.unwrap();
+
+        let episode = models::UrlMetadata::Audio(models::AudioMetadata {
+            audio_url: "https://cdn.example/ep12.mp3".to_string(),
+            mime_type: Some("audio/mpeg".to_string()),
+            length: Some(31_457_280),
+            duration: Some(3723),
+            title: Some("Episode 12".to_string()),
+        });
+        for url in ["https://podcast.example/12", "https://podcast.example/11"] {
+            database.insert_url(url).await.unwrap();
+            database.set_url_metadata(url, &episode).await.unwrap();
+        }
+        database
+            .insert_url("https://www.youtube.com/watch?v=abc123")
+            .await
+            .unwrap();
+        database.set_url_read("https://podcast.example/11", true).await.unwrap();
 
-        delete_snippet(&db_pool, snippet_id).await.unwrap();
-        let snippets = get_snippets_with_tags(&db_pool).await.unwrap();
-        assert!(snippets.is_empty());
+        let queue = models::ListFilter {
+            kind: Some(models::UrlKind::Audio),
+            unread: true,
+            ..Default::default()
+        };
+        let queued = database.get_urls_with_tags(&queue).await.unwrap();
+        assert_eq!(queued.len(), 1);
+        assert_eq!(queued[0].url, "https://podcast.example/12");
+        assert_eq!(
+            queued[0].metadata.as_ref().and_then(models::UrlMetadata::duration),
+            Some(3723)
+        );
     }
 
     #[tokio::test]
-    async fn test_get_tags_with_urls_and_snippets() {
-        let db_pool = setup_test_db().await;
+    async fn test_kind_filter_uses_classification() {
+        use crate::services::models::Database;
 
-        // Insert multiple URLs with overlapping and unique tags
-        let url1 = "https://example1.com";
-        let url2 = "https://example2.com";
-        let untagged_url = "https://untagged.com";
-        let snippet1 = "Test snippet content 1.";
-        let snippet2 = "Test snippet content 2.";
-        let snippet3 = "Test snippet content 3.";
-        let untagged_snippet = "Untagged snippet content.";
+        let database = SqliteDatabase::new(":memory:").await.unwrap();
+        database.initialize().await.unwrap();
 
-        let tags_url1 = vec!["tag1", "tag2"];
-        let tags_url2 = vec!["tag2", "tag3"];
-        let tags_snippet1 = vec!["tag1", "tag4"];
-        let tags_snippet2 = vec!["tag2", "tag3"];
-        let tags_snippet3 = vec!["tag4"];
+        database
+            .insert_url("https://github.com/hortonew/read_it_later")
+            .await
+            .unwrap();
+        database.insert_url("https://example.com/article").await.unwrap();
+        let repo = database
+            .get_url_with_tags("https://github.com/hortonew/read_it_later")
+            .await
+            .unwrap()
+            .unwrap();
+        let metadata: &dyn Database = &database;
+        metadata
+            .set_metadata(repo.id, &models::URL_KIND, &models::UrlKind::Code)
+            .await
+            .unwrap();
 
-        // Insert URLs and tags
-        insert_tags(&db_pool, url1, &tags_url1).await.unwrap();
-        insert_tags(&db_pool, url2, &tags_url2).await.unwrap();
+        let kind = |kind| models::ListFilter {
+            kind: Some(kind),
+            ..Default::default()
+        };
+        let code = database.get_urls_with_tags(&kind(models::UrlKind::Code)).await.unwrap();
+        assert_eq!(code.len(), 1);
+        assert_eq!(code[0].url, "https://github.com/hortonew/read_it_later");
+        assert!(database
+            .get_all_urls(&kind(models::UrlKind::Article))
+            .await
+            .unwrap()
+            .is_empty());
+    }
 
-        // Insert snippets and their tags
-        insert_snippet(&db_pool, url1, snippet1, &tags_snippet1).await.unwrap();
-        insert_snippet(&db_pool, url1, snippet2, &tags_snippet2).await.unwrap();
-        insert_snippet(&db_pool, url2, snippet3, &tags_snippet3).await.unwrap();
+    #[tokio::test]
+    async fn test_tag_counts_follow_writes() {
+        use crate::services::models::Database;
 
-        // Insert untagged URL and snippet
-        insert_url(&db_pool, untagged_url).await.unwrap();
-        insert_snippet(&db_pool, untagged_url, untagged_snippet, &[])
+        let database = SqliteDatabase::new(":memory:").await.unwrap();
+        database.initialize().await.unwrap();
+
+        let counts = |counts: Vec<models::TagCount>| -> Vec<(String, i64, i64)> {
+            counts
+                .into_iter()
+                .map(|count| (count.tag, count.urls, count.snippets))
+                .collect()
+        };
+
+        database
+            .insert_tags("https://example.com/a", &["rust", "go"])
+            .await
+            .unwrap();
+        database.insert_tags("https://example.com/b", &["rust"]).await.unwrap();
+        // Tagging again changes nothing
+        database.insert_tags("https://example.com/b", &["rust"]).await.unwrap();
+        let snippet = database
+            .insert_snippet("https://example.com/a", "A quote", &["rust"], None)
             .await
             .unwrap();
+        assert_eq!(
+            counts(database.get_tag_counts().await.unwrap()),
+            [("go".to_string(), 1, 0), ("rust".to_string(), 2, 1)]
+        );
 
-        // Fetch all tags with associated URLs and snippets
-        let tags_with_urls_and_snippets = get_tags_with_urls_and_snippets(&db_pool).await.unwrap();
+        database.delete_url_by_url("https://example.com/a").await.unwrap();
+        // The snippet outlives its URL
+        assert_eq!(
+            counts(database.get_tag_counts().await.unwrap()),
+            [("rust".to_string(), 1, 1)]
+        );
 
-        // Assertions
-        println!("Tags with URLs and snippets: {:?}", tags_with_urls_and_snippets);
+        database.delete_snippet(snippet).await.unwrap();
+        assert_eq!(
+            counts(database.get_tag_counts().await.unwrap()),
+            [("rust".to_string(), 1, 0)]
+        );
+    }
 
-        // Verify untagged group exists
-        let untagged_group = tags_with_urls_and_snippets
-            .iter()
-            .find(|group| group.tag.is_empty())
-            .expect("Untagged group not found!");
-        assert!(
-            untagged_group.urls.contains(&untagged_url.to_string()),
-            "Untagged URL not found in the untagged group"
+    #[tokio::test]
+    async fn test_paging_and_short_id_filters() {
+        use crate::services::models::Database;
+
+        let database = SqliteDatabase::new(":memory:").await.unwrap();
+        database.initialize().await.unwrap();
+        for n in 1..=5 {
+            database.insert_url(&format!("https://example.com/{n}")).await.unwrap();
+        }
+
+        let page = |offset, limit| models::ListFilter {
+            sort: models::Sort::parse("url:asc").unwrap(),
+            offset,
+            limit: Some(limit),
+            ..Default::default()
+        };
+        let urls = |urls: Vec<models::UrlWithTags>| -> Vec<String> { urls.into_iter().map(|url| url.url).collect() };
+        assert_eq!(
+            urls(database.get_urls_with_tags(&page(2, 2)).await.unwrap()),
+            ["https://example.com/3", "https://example.com/4"]
         );
-        assert!(
-            untagged_group
-                .snippets
-                .iter()
-                .any(|snippet| snippet.snippet == untagged_snippet),
-            "Untagged snippet not found in the untagged group"
+        assert_eq!(
+            urls(database.get_urls_with_tags(&page(4, 2)).await.unwrap()),
+            ["https://example.com/5"]
         );
 
-        // Verify tagged groups and their content
-        for tag_group in tags_with_urls_and_snippets {
-            match tag_group.tag.as_str() {
-                "tag1" => {
-                    assert!(tag_group.urls.contains(&url1.to_string()));
-                    assert!(tag_group.snippets.iter().any(|s| s.snippet == snippet1));
-                }
-                "tag2" => {
-                    assert!(tag_group.urls.contains(&url1.to_string()));
-                    assert!(tag_group.urls.contains(&url2.to_string()));
-                    assert!(tag_group.snippets.iter().any(|s| s.snippet == snippet2));
-                }
-                "tag3" => {
-                    assert!(tag_group.urls.contains(&url2.to_string()));
-                    assert!(tag_group.snippets.iter().any(|s| s.snippet == snippet2));
-                }
-                "tag4" => {
-                    assert!(tag_group.snippets.iter().any(|s| s.snippet == snippet1));
-                    assert!(tag_group.snippets.iter().any(|s| s.snippet == snippet3));
-                }
-                _ => {} // Ignore unexpected groups
-            }
-        }
+        let saved = database.get_urls_with_tags(&Default::default()).await.unwrap();
+        let wanted = saved.iter().find(|url| url.url == "https://example.com/2").unwrap();
+        let filter = models::ListFilter {
+            short_id: Some(wanted.short_id.clone()),
+            ..Default::default()
+        };
+        assert_eq!(
+            urls(database.get_urls_with_tags(&filter).await.unwrap()),
+            ["https://example.com/2"]
+        );
     }
 
     #[tokio::test]
-    async fn test_untagged_urls_are_included() {
-        let db_pool = setup_test_db().await;
-
-        // Insert URLs with and without tags
-        let tagged_url = "https://tagged-url.com";
-        let untagged_url = "https://untagged-url.com";
+    async fn test_snippet_paging_and_url_filter() {
+        let pool = setup_test_db().await;
+        for n in 1..=5 {
+            let url = if n % 2 == 0 {
+                "https://example.com/even"
+            } else {
+                "https://example.com/odd"
+            };
+            insert_snippet(&pool, url, &format!("Snippet {n}"), None, &["rust"], None)
+                .await
+                .unwrap();
+        }
 
-        let snippet_for_untagged = "Snippet for untagged URL.";
+        let texts = |snippets: Vec<models::SnippetWithTags>| -> Vec<String> {
+            snippets.into_iter().map(|snippet| snippet.snippet).collect()
+        };
+        let page = models::ListFilter {
+            offset: 1,
+            limit: Some(2),
+            ..Default::default()
+        };
+        // Newest first
+        assert_eq!(
+            texts(get_snippets_with_tags(&pool, &page).await.unwrap()),
+            ["Snippet 4", "Snippet 3"]
+        );
 
-        // Insert a tagged URL
-        let tags = vec!["example-tag"];
-        insert_tags(&db_pool, tagged_url, &tags).await.unwrap();
+        let from_url = models::ListFilter {
+            url: Some("https://example.com/even".to_string()),
+            tags: models::TagFilter::parse("rust"),
+            ..Default::default()
+        };
+        assert_eq!(
+            texts(get_snippets_with_tags(&pool, &from_url).await.unwrap()),
+            ["Snippet 4", "Snippet 2"]
+        );
+    }
 
-        // Insert an untagged URL
-        insert_url(&db_pool, untagged_url).await.unwrap();
+    #[tokio::test]
+    async fn test_snippet_html_is_sanitized_on_save() {
+        use crate::services::models::Database;
 
-        // Insert a snippet associated with the untagged URL
-        insert_snippet(&db_pool, untagged_url, snippet_for_untagged, &[])
+        let database = SqliteDatabase::new(":memory:")
+            .await
+            .unwrap()
+            .with_snippet_html_tags(Some(vec!["code".to_string()]));
+        database.initialize().await.unwrap();
+        let raw = "<code>x</code> <b>bold</b><script>alert(1)</script>";
+        let snippet_id = database
+            .insert_snippet("https://example.com", raw, &[], None)
             .await
             .unwrap();
 
-        // Fetch all tags with associated URLs and snippets
-        let tags_with_urls_and_snippets = get_tags_with_urls_and_snippets(&db_pool).await.unwrap();
-
-        // Print the results for debugging
-        println!("Tags with URLs and snippets: {:?}", tags_with_urls_and_snippets);
+        let saved = database.get_snippet(snippet_id).await.unwrap().unwrap();
+        assert_eq!(saved.snippet, raw);
+        assert_eq!(saved.snippet_html.as_deref(), Some("<code>x</code> bold"));
 
-        // Verify untagged URLs are included under a group with an empty tag
-        let untagged_group = tags_with_urls_and_snippets
-            .iter()
-            .find(|group| group.tag.is_empty())
-            .expect("No untagged group found!");
+        // Snippets from before the column are sanitized as they are read
+        sqlx::query("UPDATE snippets SET snippet_html = NULL")
+            .execute(&database.pool)
+            .await
+            .unwrap();
+        let saved = database.get_snippet(snippet_id).await.unwrap().unwrap();
+        assert_eq!(saved.snippet_html.as_deref(), Some("<code>x</code> bold"));
+    }
 
-        assert!(
-            untagged_group.urls.contains(&untagged_url.to_string()),
-            "Untagged URL not found in the untagged group"
-        );
+    #[tokio::test]
+    async fn test_snippet_source_is_stored() {
+        use crate::services::models::Database;
+
+        let database = SqliteDatabase::new(":memory:").await.unwrap();
+        database.initialize().await.unwrap();
+        let url = "https://example.com/post";
+        let source = models::SnippetSource {
+            html: Some("<p>A <em>quote</em></p>".to_string()),
+            title: Some("A post".to_string()),
+            favicon: Some("https://example.com/favicon.ico".to_string()),
+            selection: Some(models::SelectionRange {
+                start: 4,
+                end: 11,
+                prefix: "So ".to_string(),
+                suffix: ", said".to_string(),
+            }),
+        };
+        let snippet_id = database
+            .insert_snippet(url, "A quote", &["rust"], Some(&source))
+            .await
+            .unwrap();
+        database.insert_snippet(url, "Plain", &[], None).await.unwrap();
 
-        assert!(
-            untagged_group
-                .snippets
-                .iter()
-                .any(|snippet| snippet.snippet == snippet_for_untagged),
-            "Snippet for untagged URL not found in the untagged group"
+        let saved = database.get_snippet(snippet_id).await.unwrap().unwrap();
+        assert_eq!(
+            (saved.snippet.as_str(), saved.tags),
+            ("A quote", vec!["rust".to_string()])
         );
+        assert_eq!(saved.source.as_ref(), Some(&source));
+        assert!(database.get_snippet(snippet_id + 100).await.unwrap().is_none());
+
+        let snippets = database.get_snippets_for_url(url).await.unwrap();
+        assert_eq!(snippets[0].source.as_ref(), Some(&source));
+        assert_eq!(snippets[1].source, None);
+
+        let listed = database.get_snippets_with_tags(&Default::default()).await.unwrap();
+        assert!(listed.iter().any(|snippet| snippet.source.as_ref() == Some(&source)));
+        let tags = database.get_tags_with_urls_and_snippets().await.unwrap();
+        let rust = tags.iter().find(|tag| tag.tag == "rust").unwrap();
+        assert_eq!(rust.snippets[0].source.as_ref(), Some(&source));
+    }
 
-        // Verify tagged URL is present in the correct group
-        let tagged_group = tags_with_urls_and_snippets
-            .iter()
-            .find(|group| group.tag == "example-tag")
-            .expect("Tagged group not found!");
+    #[tokio::test]
+    async fn test_image_snippets() {
+        use crate::services::models::Database;
+
+        let database = SqliteDatabase::new(":memory:").await.unwrap();
+        database.initialize().await.unwrap();
+        let url = "https://example.com/chart";
+        let image = models::SnippetImage {
+            content_type: "image/png".to_string(),
+            data: b"\x89PNG\r\n\x1a\npixels".to_vec(),
+        };
+        let id = database
+            .insert_image_snippet(url, "The chart", &["data"], &image)
+            .await
+            .unwrap();
+        database
+            .insert_snippet(url, "Some text", &["data"], None)
+            .await
+            .unwrap();
 
-        assert!(
-            tagged_group.urls.contains(&tagged_url.to_string()),
-            "Tagged URL not found in the tagged group"
-        );
+        assert_eq!(database.get_snippet_image(id).await.unwrap(), Some(image));
+        let snippets = database.get_snippets_for_url(url).await.unwrap();
+        assert_eq!(snippets[0].snippet, "The chart");
+        assert_eq!(snippets[0].image_type.as_deref(), Some("image/png"));
+        assert_eq!(snippets[1].image_type, None);
+        assert_eq!(database.get_snippet_image(snippets[1].id).await.unwrap(), None);
+
+        // Tagged like any other snippet
+        let filter = models::ListFilter::try_from(&models::ListQuery {
+            tags: Some("data".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(database.get_snippets_with_tags(&filter).await.unwrap().len(), 2);
+
+        database.delete_snippet(id).await.unwrap();
+        assert_eq!(database.get_snippet_image(id).await.unwrap(), None);
     }
 }
@@ -0,0 +1,277 @@
+//! Reading stats from the activity timeline: links saved, opened through `/s/{id}` and marked read, as a
+//! streak of reading days and a week-by-week tally
+
+use crate::config::Config;
+use crate::services::models;
+use actix_web::{get, web, HttpResponse, Responder};
+use chrono::{Datelike, Days, NaiveDate, NaiveDateTime};
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::sync::Arc;
+use tera::{Context, Tera};
+
+/// Logged when a link is opened through its short link
+pub const OPEN_ACTION: &str = "open_url";
+
+const ACTIONS: [&str; 3] = ["save_url", OPEN_ACTION, "read_url"];
+
+/// How many weeks the tally goes back, this one included
+const WEEKS: u64 = 8;
+
+/// Longest gap between opening a link and marking it read that still counts as one sitting
+const SITTING_MINUTES: i64 = 3 * 60;
+
+/// One Monday-to-Sunday week, counting each link once however often it was opened or marked read
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Week {
+    pub starting: NaiveDate,
+    pub saved: usize,
+    pub opened: usize,
+    pub read: usize,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct ReadingStats {
+    /// Days in a row with something marked read, up to today; a streak ending yesterday holds until today is
+    /// over
+    pub current_streak: u32,
+    pub longest_streak: u32,
+    pub read_today: usize,
+    /// Newest last
+    pub weeks: Vec<Week>,
+    /// Median minutes from opening a link to marking it read, when that happened in one sitting
+    pub minutes_to_read: Option<i64>,
+    /// Median days from saving a link to marking it read
+    pub days_to_read: Option<i64>,
+}
+
+fn median(mut values: Vec<i64>) -> Option<i64> {
+    values.sort_unstable();
+    values.get(values.len() / 2).copied()
+}
+
+fn streak_ending(days: &BTreeSet<NaiveDate>, mut day: NaiveDate) -> u32 {
+    let mut streak = 0;
+    while days.contains(&day) {
+        streak += 1;
+        day = day.pred_opt().unwrap_or(NaiveDate::MIN);
+    }
+    streak
+}
+
+/// Work the stats out from `save_url`, `open_url` and `read_url` entries, oldest first, dated in UTC
+pub fn compute(activity: &[models::Activity], today: NaiveDate) -> ReadingStats {
+    let read_days: BTreeSet<NaiveDate> = activity
+        .iter()
+        .filter(|entry| entry.action == "read_url")
+        .map(|entry| entry.datetime.date())
+        .collect();
+    let current_streak = match streak_ending(&read_days, today) {
+        0 => streak_ending(&read_days, today.pred_opt().unwrap_or(today)),
+        streak => streak,
+    };
+    let (mut longest_streak, mut run) = (0, 0);
+    let mut previous: Option<NaiveDate> = None;
+    for day in &read_days {
+        run = if previous.and_then(|previous| previous.succ_opt()) == Some(*day) {
+            run + 1
+        } else {
+            1
+        };
+        longest_streak = longest_streak.max(run);
+        previous = Some(*day);
+    }
+
+    let this_week = today - Days::new(today.weekday().num_days_from_monday().into());
+    let first_week = this_week - Days::new(7 * (WEEKS - 1));
+    let mut tally: Vec<[HashSet<&str>; 3]> = (0..WEEKS).map(|_| Default::default()).collect();
+    let mut read_today = HashSet::new();
+
+    let mut opened: HashMap<&str, NaiveDateTime> = HashMap::new();
+    let mut saved: HashMap<&str, NaiveDateTime> = HashMap::new();
+    let (mut minutes, mut days) = (Vec::new(), Vec::new());
+    for entry in activity {
+        let day = entry.datetime.date();
+        let subject = entry.subject.as_str();
+        let Some(column) = ACTIONS.iter().position(|action| *action == entry.action) else {
+            continue;
+        };
+        if day >= first_week {
+            let week = (day - first_week).num_days() as usize / 7;
+            if let Some(week) = tally.get_mut(week) {
+                week[column].insert(subject);
+            }
+        }
+
+        match column {
+            0 => {
+                saved.entry(subject).or_insert(entry.datetime);
+            }
+            1 => {
+                opened.insert(subject, entry.datetime);
+            }
+            _ => {
+                if day == today {
+                    read_today.insert(subject);
+                }
+                if let Some(at) = opened.remove(subject) {
+                    let taken = (entry.datetime - at).num_minutes();
+                    if taken <= SITTING_MINUTES {
+                        minutes.push(taken);
+                    }
+                }
+                if let Some(at) = saved.remove(subject) {
+                    days.push((entry.datetime - at).num_days());
+                }
+            }
+        }
+    }
+
+    let weeks = tally
+        .into_iter()
+        .zip(0..)
+        .map(|([saved, opened, read], index)| Week {
+            starting: first_week + Days::new(7 * index),
+            saved: saved.len(),
+            opened: opened.len(),
+            read: read.len(),
+        })
+        .collect();
+
+    ReadingStats {
+        current_streak,
+        longest_streak,
+        read_today: read_today.len(),
+        weeks,
+        minutes_to_read: median(minutes),
+        days_to_read: median(days),
+    }
+}
+
+/// Stats as of now, from the whole timeline
+pub async fn reading_stats(database: &dyn models::Database) -> Result<ReadingStats, sqlx::Error> {
+    let activity = database.get_activity_by_actions(&ACTIONS).await?;
+    Ok(compute(&activity, chrono::Utc::now().date_naive()))
+}
+
+#[get("/stats")]
+async fn stats_page(
+    database: web::Data<Arc<dyn models::Database>>,
+    tmpl: web::Data<Tera>,
+    database_type: web::Data<String>,
+    config: web::Data<Config>,
+) -> impl Responder {
+    let stats = match reading_stats(database.get_ref().as_ref()).await {
+        Ok(stats) => stats,
+        Err(err) => {
+            eprintln!("Failed to work out reading stats: {:?}", err);
+            return HttpResponse::InternalServerError().body("Failed to work out reading stats");
+        }
+    };
+
+    let mut context = Context::new();
+    context.insert("stats", &stats);
+    context.insert("title", "Reading stats");
+    context.insert("database_type", &**database_type);
+    context.insert("base_path", &config.base_path);
+
+    match tmpl.render("stats.html", &context) {
+        Ok(rendered) => HttpResponse::Ok().content_type("text/html").body(rendered),
+        Err(e) => {
+            eprintln!("Template error: {:?}", e);
+            HttpResponse::InternalServerError().body("Template error")
+        }
+    }
+}
+
+#[get("/api/v1/stats")]
+async fn stats_json(database: web::Data<Arc<dyn models::Database>>) -> impl Responder {
+    match reading_stats(database.get_ref().as_ref()).await {
+        Ok(stats) => HttpResponse::Ok().json(stats),
+        Err(err) => {
+            eprintln!("Failed to work out reading stats: {:?}", err);
+            HttpResponse::InternalServerError().json("Failed to work out reading stats")
+        }
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(stats_page).service(stats_json);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(action: &str, subject: &str, datetime: &str) -> models::Activity {
+        models::Activity {
+            id: 0,
+            datetime: NaiveDateTime::parse_from_str(datetime, "%Y-%m-%d %H:%M").unwrap(),
+            action: action.to_string(),
+            subject: subject.to_string(),
+            detail: None,
+        }
+    }
+
+    #[test]
+    fn streaks_count_days_with_reads() {
+        let today = NaiveDate::from_ymd_opt(2024, 3, 14).unwrap();
+        let activity = [
+            entry("read_url", "a", "2024-03-01 09:00"),
+            entry("read_url", "b", "2024-03-02 09:00"),
+            entry("read_url", "c", "2024-03-03 09:00"),
+            entry("read_url", "d", "2024-03-12 09:00"),
+            entry("read_url", "e", "2024-03-13 22:00"),
+        ];
+        let stats = compute(&activity, today);
+        // Nothing yet today, but yesterday's streak still stands
+        assert_eq!(
+            (stats.current_streak, stats.longest_streak, stats.read_today),
+            (2, 3, 0)
+        );
+
+        let stats = compute(&activity[..4], today);
+        assert_eq!(stats.current_streak, 0);
+    }
+
+    #[test]
+    fn weeks_and_times_to_read() {
+        // A Thursday
+        let today = NaiveDate::from_ymd_opt(2024, 3, 14).unwrap();
+        let activity = [
+            entry("save_url", "a", "2024-03-04 08:00"),
+            entry("save_url", "b", "2024-03-11 08:00"),
+            entry(OPEN_ACTION, "a", "2024-03-11 12:00"),
+            entry(OPEN_ACTION, "a", "2024-03-11 12:05"),
+            entry("read_url", "a", "2024-03-11 12:15"),
+            // Opened and left for a day, so not a sitting
+            entry(OPEN_ACTION, "b", "2024-03-12 12:00"),
+            entry("read_url", "b", "2024-03-14 12:00"),
+            entry("read_url", "b", "2024-03-14 13:00"),
+        ];
+        let stats = compute(&activity, today);
+
+        assert_eq!(stats.weeks.len(), WEEKS as usize);
+        let last_two = &stats.weeks[stats.weeks.len() - 2..];
+        assert_eq!(
+            last_two,
+            [
+                Week {
+                    starting: NaiveDate::from_ymd_opt(2024, 3, 4).unwrap(),
+                    saved: 1,
+                    opened: 0,
+                    read: 0,
+                },
+                Week {
+                    starting: NaiveDate::from_ymd_opt(2024, 3, 11).unwrap(),
+                    saved: 1,
+                    opened: 2,
+                    read: 2,
+                },
+            ]
+        );
+        assert_eq!(stats.read_today, 1);
+        assert_eq!(stats.minutes_to_read, Some(10));
+        assert_eq!(stats.days_to_read, Some(7));
+    }
+}
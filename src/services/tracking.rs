@@ -0,0 +1,138 @@
+use std::borrow::Cow;
+
+/// Query parameters used only to track where a click came from; a trailing `*` matches any suffix
+pub const DEFAULT_PARAMS: [&str; 27] = [
+    "utm_*",
+    "fbclid",
+    "gclid",
+    "gclsrc",
+    "dclid",
+    "gbraid",
+    "wbraid",
+    "msclkid",
+    "yclid",
+    "twclid",
+    "ttclid",
+    "igshid",
+    "li_fat_id",
+    "mc_cid",
+    "mc_eid",
+    "_hsenc",
+    "_hsmi",
+    "hsa_*",
+    "mkt_tok",
+    "oly_anon_id",
+    "oly_enc_id",
+    "vero_id",
+    "vero_conv",
+    "_openstat",
+    "wickedid",
+    "rb_clickid",
+    "s_cid",
+];
+
+/// Query parameters removed from URLs before they are saved or looked up
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrackingParams {
+    patterns: Vec<String>,
+}
+
+impl Default for TrackingParams {
+    fn default() -> Self {
+        Self::parse(&DEFAULT_PARAMS.join(","))
+    }
+}
+
+impl TrackingParams {
+    /// Parse a comma-separated list such as `utm_*, fbclid`; an empty list strips nothing
+    pub fn parse(list: &str) -> Self {
+        let patterns = list
+            .split(',')
+            .map(|pattern| pattern.trim().to_ascii_lowercase())
+            .filter(|pattern| !pattern.is_empty())
+            .collect();
+        Self { patterns }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// Whether a parameter name is on the list, ignoring case
+    pub fn matches(&self, name: &str) -> bool {
+        let name = name.to_ascii_lowercase();
+        self.patterns.iter().any(|pattern| match pattern.strip_suffix('*') {
+            Some(prefix) => name.starts_with(prefix),
+            None => name == *pattern,
+        })
+    }
+
+    /// The URL without tracking parameters; everything else, including order and encoding, is left as it was
+    pub fn strip<'a>(&self, url: &'a str) -> Cow<'a, str> {
+        let (rest, fragment) = url.split_at(url.find('#').unwrap_or(url.len()));
+        let Some((base, query)) = rest.split_once('?') else {
+            return Cow::Borrowed(url);
+        };
+
+        let pairs: Vec<&str> = query.split('&').collect();
+        let kept: Vec<&str> = pairs
+            .iter()
+            .copied()
+            .filter(|pair| !self.matches(pair.split('=').next().unwrap_or(pair)))
+            .collect();
+        if kept.len() == pairs.len() {
+            return Cow::Borrowed(url);
+        }
+
+        let mut stripped = base.to_string();
+        if !kept.is_empty() {
+            stripped.push('?');
+            stripped.push_str(&kept.join("&"));
+        }
+        stripped.push_str(fragment);
+        Cow::Owned(stripped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_only_tracking_parameters() {
+        let params = TrackingParams::default();
+
+        assert_eq!(
+            params.strip("https://example.com/a?id=3&utm_source=news&UTM_Medium=mail&fbclid=x#part"),
+            "https://example.com/a?id=3#part"
+        );
+        assert_eq!(
+            params.strip("https://example.com/a?utm_source=news"),
+            "https://example.com/a"
+        );
+        assert!(matches!(
+            params.strip("https://example.com/a?q=rust%20lang&page=2"),
+            Cow::Borrowed("https://example.com/a?q=rust%20lang&page=2")
+        ));
+        assert!(matches!(
+            params.strip("https://example.com/#utm_source"),
+            Cow::Borrowed(_)
+        ));
+    }
+
+    #[test]
+    fn custom_lists_replace_the_defaults() {
+        let params = TrackingParams::parse(" ref , src_* ");
+        assert_eq!(
+            params.strip("https://example.com/?ref=hn&src_id=1&utm_source=x"),
+            "https://example.com/?utm_source=x"
+        );
+
+        let disabled = TrackingParams::parse("");
+        assert!(disabled.is_empty());
+        assert_eq!(
+            disabled.strip("https://example.com/?utm_source=x"),
+            "https://example.com/?utm_source=x"
+        );
+    }
+}
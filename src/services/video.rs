@@ -0,0 +1,166 @@
+//! Duration, channel and thumbnail for links to video platforms, looked up over oEmbed
+
+use crate::config;
+use crate::services::{fetcher, metadata, models, snapshots};
+use reqwest::Url;
+use serde::Deserialize;
+use std::time::Duration;
+
+/// Give up on an oEmbed lookup that takes longer than this
+const FETCH_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// How much of a YouTube watch page is read while looking for the duration
+const WATCH_PAGE_LIMIT: usize = 4 * 1024 * 1024;
+
+/// Give up on a watch page that takes longer than this to download
+const WATCH_PAGE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A video platform whose links get metadata
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Provider {
+    YouTube,
+    Vimeo,
+}
+
+impl Provider {
+    /// The platform a link plays a single video on; channel, search and home pages don't count
+    pub fn of(url: &str) -> Option<Self> {
+        let url = Url::parse(url).ok()?;
+        let host = url.host_str()?.to_ascii_lowercase();
+        let host = host.strip_prefix("www.").unwrap_or(&host);
+        let mut segments = url.path_segments()?.filter(|segment| !segment.is_empty());
+
+        match host {
+            "youtube.com" | "m.youtube.com" | "music.youtube.com" => {
+                let is_video = match segments.next() {
+                    Some("watch") => url.query_pairs().any(|(key, value)| key == "v" && !value.is_empty()),
+                    Some("shorts" | "live" | "embed") => segments.next().is_some(),
+                    _ => false,
+                };
+                is_video.then_some(Self::YouTube)
+            }
+            "youtu.be" => segments.next().map(|_| Self::YouTube),
+            // vimeo.com/76979871, vimeo.com/channels/staffpicks/76979871, player.vimeo.com/video/76979871
+            "vimeo.com" | "player.vimeo.com" => segments
+                .any(|segment| segment.bytes().all(|byte| byte.is_ascii_digit()))
+                .then_some(Self::Vimeo),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::YouTube => "YouTube",
+            Self::Vimeo => "Vimeo",
+        }
+    }
+
+    pub fn oembed_endpoint(self) -> &'static str {
+        match self {
+            Self::YouTube => "https://www.youtube.com/oembed",
+            Self::Vimeo => "https://vimeo.com/api/oembed.json",
+        }
+    }
+}
+
+/// The fields of an oEmbed response worth keeping; Vimeo includes `duration`, YouTube doesn't
+#[derive(Deserialize, Debug)]
+struct OEmbed {
+    title: Option<String>,
+    author_name: Option<String>,
+    thumbnail_url: Option<String>,
+    duration: Option<u64>,
+}
+
+/// Ask the platform about a video.
+///
+/// YouTube's oEmbed leaves out the length, so for YouTube the watch page is fetched as well and the
+/// duration read from it; when that fails the metadata is stored without one.
+pub async fn look_up(provider: Provider, url: &str) -> Result<models::VideoMetadata, String> {
+    let endpoint = Url::parse_with_params(provider.oembed_endpoint(), &[("url", url), ("format", "json")])
+        .map_err(|err| err.to_string())?;
+    let oembed: OEmbed = fetcher::api(endpoint, FETCH_TIMEOUT)
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    let mut duration = oembed.duration;
+    if duration.is_none() && provider == Provider::YouTube {
+        let limits = config::PageLimits {
+            max_size: WATCH_PAGE_LIMIT,
+            timeout: WATCH_PAGE_TIMEOUT,
+            content_types: Vec::new(),
+        };
+        match snapshots::fetch(url, &limits).await {
+            Ok(page) => duration = watch_page_duration(&page.body),
+            Err(err) => eprintln!("Failed to fetch {} for its duration: {}", url, err),
+        }
+    }
+
+    Ok(models::VideoMetadata {
+        provider: provider.name().to_string(),
+        title: oembed.title,
+        channel: oembed.author_name,
+        duration,
+        thumbnail_url: oembed.thumbnail_url,
+    })
+}
+
+/// The length a YouTube watch page gives, from `<meta itemprop="duration">` or else the player's `lengthSeconds`
+fn watch_page_duration(html: &str) -> Option<u64> {
+    let from_meta = html.find(r#"itemprop="duration""#).and_then(|start| {
+        let tag = &html[start..];
+        let tag = &tag[..tag.find('>')?];
+        let content = tag.split_once(r#"content=""#)?.1;
+        metadata::parse_iso_duration(&content[..content.find('"')?])
+    });
+
+    from_meta.or_else(|| {
+        let rest = html.split_once(r#""lengthSeconds":""#)?.1;
+        rest[..rest.find('"')?].parse().ok()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognises_single_video_links() {
+        for url in [
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ&t=42",
+            "https://youtu.be/dQw4w9WgXcQ",
+            "https://www.youtube.com/shorts/abc123",
+            "https://music.youtube.com/watch?v=abc123",
+        ] {
+            assert_eq!(Provider::of(url), Some(Provider::YouTube), "{}", url);
+        }
+        for url in [
+            "https://vimeo.com/76979871",
+            "https://vimeo.com/channels/staffpicks/76979871",
+            "https://player.vimeo.com/video/76979871",
+        ] {
+            assert_eq!(Provider::of(url), Some(Provider::Vimeo), "{}", url);
+        }
+        for url in [
+            "https://www.youtube.com/",
+            "https://www.youtube.com/@rustlang",
+            "https://www.youtube.com/watch",
+            "https://vimeo.com/channels/staffpicks",
+            "https://example.com/watch?v=abc",
+            "not a url",
+        ] {
+            assert_eq!(Provider::of(url), None, "{}", url);
+        }
+    }
+
+    #[test]
+    fn reads_the_duration_from_a_watch_page() {
+        let page = r#"<meta itemprop="name" content="Talk"><meta itemprop="duration" content="PT1H2M3S"><body>"#;
+        assert_eq!(watch_page_duration(page), Some(3723));
+        let player_only = r#"<script>var ytInitialPlayerResponse = {"videoDetails":{"lengthSeconds":"212"}}</script>"#;
+        assert_eq!(watch_page_duration(player_only), Some(212));
+        assert_eq!(watch_page_duration("<p>No video here</p>"), None);
+    }
+}
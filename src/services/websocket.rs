@@ -0,0 +1,329 @@
+use crate::config::Config;
+use crate::services::{api, changes, models};
+use actix_web::{get, web, HttpRequest, HttpResponse};
+use actix_ws::{AggregatedMessage, Session};
+use futures_util::StreamExt;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tokio::sync::broadcast;
+
+// JSON-RPC 2.0 error codes, plus one of ours for duplicates
+const PARSE_ERROR: i32 = -32700;
+const METHOD_NOT_FOUND: i32 = -32601;
+const INVALID_PARAMS: i32 = -32602;
+const SERVER_ERROR: i32 = -32000;
+const CONFLICT: i32 = -32001;
+
+/// `{"id": 1, "method": "save", "params": {"url": "..."}}`; `id` is echoed back on the reply
+#[derive(Deserialize)]
+struct Request {
+    #[serde(default)]
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Deserialize)]
+struct SaveParams {
+    url: String,
+    /// A list or a comma-separated string, as on `POST /urls/tags`
+    #[serde(default, deserialize_with = "models::tag_list")]
+    tags: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct DeleteParams {
+    url: String,
+}
+
+/// A method's failure, sent back as the reply's `error`
+struct RpcError {
+    code: i32,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i32, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+fn params<T: serde::de::DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    serde_json::from_value(params).map_err(|err| RpcError::new(INVALID_PARAMS, err.to_string()))
+}
+
+/// Live connection for interactive clients: one reply per request, plus `change` notifications once subscribed
+#[get("/ws")]
+async fn websocket(
+    req: HttpRequest,
+    body: web::Payload,
+    database: web::Data<Arc<dyn models::Database>>,
+    config: web::Data<Config>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (response, session, stream) = actix_ws::handle(&req, body)?;
+    let stream = stream
+        .max_frame_size(config.json_body_limit)
+        .aggregate_continuations()
+        .max_continuation_size(config.json_body_limit);
+
+    actix_web::rt::spawn(run_session(database.get_ref().clone(), session, stream));
+    Ok(response)
+}
+
+async fn run_session(
+    database: Arc<dyn models::Database>,
+    mut session: Session,
+    mut stream: actix_ws::AggregatedMessageStream,
+) {
+    let mut subscription: Option<broadcast::Receiver<changes::Change>> = None;
+
+    loop {
+        tokio::select! {
+            message = stream.next() => match message {
+                Some(Ok(AggregatedMessage::Text(text))) => {
+                    let reply = handle_message(database.as_ref(), &text, &mut subscription).await;
+                    if session.text(reply.to_string()).await.is_err() {
+                        return;
+                    }
+                }
+                Some(Ok(AggregatedMessage::Ping(bytes))) => {
+                    if session.pong(&bytes).await.is_err() {
+                        return;
+                    }
+                }
+                Some(Ok(AggregatedMessage::Close(reason))) => {
+                    let _ = session.close(reason).await;
+                    return;
+                }
+                Some(Ok(_)) => {}
+                Some(Err(err)) => {
+                    eprintln!("WebSocket protocol error: {:?}", err);
+                    break;
+                }
+                None => break,
+            },
+            change = next_change(&mut subscription) => {
+                let notification = match change {
+                    Ok(change) => json!({ "method": "change", "params": change }),
+                    // The client fell behind; it should refetch rather than trust its view
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        json!({ "method": "lagged", "params": { "missed": missed } })
+                    }
+                    Err(broadcast::error::RecvError::Closed) => {
+                        subscription = None;
+                        continue;
+                    }
+                };
+                if session.text(notification.to_string()).await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+
+    let _ = session.close(None).await;
+}
+
+/// Wait for the next change, or forever when the client hasn't subscribed
+async fn next_change(
+    subscription: &mut Option<broadcast::Receiver<changes::Change>>,
+) -> Result<changes::Change, broadcast::error::RecvError> {
+    match subscription {
+        Some(receiver) => receiver.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Turn one text frame into its reply
+async fn handle_message(
+    database: &dyn models::Database,
+    text: &str,
+    subscription: &mut Option<broadcast::Receiver<changes::Change>>,
+) -> Value {
+    let request: Request = match serde_json::from_str(text) {
+        Ok(request) => request,
+        Err(err) => return json!({ "id": null, "error": { "code": PARSE_ERROR, "message": err.to_string() } }),
+    };
+
+    match call(database, request.method.as_str(), request.params, subscription).await {
+        Ok(result) => json!({ "id": request.id, "result": result }),
+        Err(err) => json!({ "id": request.id, "error": { "code": err.code, "message": err.message } }),
+    }
+}
+
+async fn call(
+    database: &dyn models::Database,
+    method: &str,
+    raw_params: Value,
+    subscription: &mut Option<broadcast::Receiver<changes::Change>>,
+) -> Result<Value, RpcError> {
+    match method {
+        "ping" => Ok(json!("pong")),
+        "subscribe" => {
+            subscription.get_or_insert_with(changes::subscribe);
+            Ok(json!("subscribed"))
+        }
+        "unsubscribe" => {
+            *subscription = None;
+            Ok(json!("unsubscribed"))
+        }
+        "save" => {
+            let SaveParams { url, tags } = params(raw_params)?;
+            let tags: Vec<&str> = tags.iter().map(String::as_str).collect();
+
+            // Tagging saves the URL too, the same as `POST /urls/tags`
+            let (result, action) = if tags.is_empty() {
+                (database.insert_url(&url).await.map(|_| ()), "save_url")
+            } else {
+                (database.insert_tags(&url, &tags).await, "tag_url")
+            };
+
+            match result {
+                Ok(()) => {
+                    let detail = (!tags.is_empty()).then(|| tags.join(","));
+                    api::log_activity(database, action, &url, detail.as_deref()).await;
+                    Ok(json!("saved"))
+                }
+                Err(sqlx::Error::RowNotFound) => Err(RpcError::new(CONFLICT, "Record already exists")),
+                Err(err) => {
+                    eprintln!("Failed to save URL over WebSocket: {:?}", err);
+                    Err(RpcError::new(SERVER_ERROR, "Failed to save URL"))
+                }
+            }
+        }
+        "delete" => {
+            let DeleteParams { url } = params(raw_params)?;
+            match database.delete_url_by_url(&url).await {
+                Ok(()) => {
+                    api::log_activity(database, "delete_url", &url, None).await;
+                    Ok(json!("deleted"))
+                }
+                Err(err) => {
+                    eprintln!("Failed to delete URL over WebSocket: {:?}", err);
+                    Err(RpcError::new(SERVER_ERROR, "Failed to delete URL"))
+                }
+            }
+        }
+        _ => Err(RpcError::new(
+            METHOD_NOT_FOUND,
+            format!("Unknown method \"{}\"", method),
+        )),
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(websocket);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::models::Database;
+    use crate::services::sqlite_database::SqliteDatabase;
+
+    async fn setup_test_db() -> SqliteDatabase {
+        let database = SqliteDatabase::new(":memory:").await.unwrap();
+        database.initialize().await.unwrap();
+        database
+    }
+
+    #[tokio::test]
+    async fn replies_echo_the_id_and_report_errors() {
+        let database = setup_test_db().await;
+        let mut subscription = None;
+
+        let reply = handle_message(&database, r#"{"id": 7, "method": "ping"}"#, &mut subscription).await;
+        assert_eq!(reply, json!({ "id": 7, "result": "pong" }));
+
+        let reply = handle_message(&database, "not json", &mut subscription).await;
+        assert_eq!(reply["error"]["code"], PARSE_ERROR);
+
+        let reply = handle_message(&database, r#"{"id": "a", "method": "nope"}"#, &mut subscription).await;
+        assert_eq!(reply["id"], "a");
+        assert_eq!(reply["error"]["code"], METHOD_NOT_FOUND);
+
+        let reply = handle_message(
+            &database,
+            r#"{"id": 1, "method": "save", "params": {}}"#,
+            &mut subscription,
+        )
+        .await;
+        assert_eq!(reply["error"]["code"], INVALID_PARAMS);
+
+        let reply = handle_message(
+            &database,
+            r#"{"id": 2, "method": "save", "params": {"url": "https://ws.example/comma", "tags": ["a,b"]}}"#,
+            &mut subscription,
+        )
+        .await;
+        assert_eq!(reply["error"]["code"], INVALID_PARAMS);
+    }
+
+    #[tokio::test]
+    async fn blank_tags_are_dropped_on_save() {
+        let database = setup_test_db().await;
+        let mut subscription = None;
+
+        let save = r#"{"id": 1, "method": "save", "params": {"url": "https://ws.example/none", "tags": ""}}"#;
+        assert_eq!(
+            handle_message(&database, save, &mut subscription).await["result"],
+            "saved"
+        );
+        let save = r#"{"id": 2, "method": "save", "params": {"url": "https://ws.example/trailing", "tags": "rust,"}}"#;
+        assert_eq!(
+            handle_message(&database, save, &mut subscription).await["result"],
+            "saved"
+        );
+
+        let none = database
+            .get_url_with_tags("https://ws.example/none")
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(none.tags.is_empty());
+        let trailing = database
+            .get_url_with_tags("https://ws.example/trailing")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(trailing.tags, vec!["rust"]);
+        assert_eq!(database.get_all_tags().await.unwrap(), vec!["rust"]);
+    }
+
+    #[tokio::test]
+    async fn save_and_delete_are_published_to_subscribers() {
+        let database = setup_test_db().await;
+        let mut subscription = None;
+
+        let reply = handle_message(&database, r#"{"id": 1, "method": "subscribe"}"#, &mut subscription).await;
+        assert_eq!(reply["result"], "subscribed");
+
+        let save = r#"{"id": 2, "method": "save", "params": {"url": "https://ws.example", "tags": "rust, live"}}"#;
+        assert_eq!(
+            handle_message(&database, save, &mut subscription).await["result"],
+            "saved"
+        );
+        let delete = r#"{"id": 3, "method": "delete", "params": {"url": "https://ws.example"}}"#;
+        assert_eq!(
+            handle_message(&database, delete, &mut subscription).await["result"],
+            "deleted"
+        );
+
+        // The feed is shared with other tests, so only look at changes to our URL
+        let receiver = subscription.as_mut().unwrap();
+        let mut actions = Vec::new();
+        while actions.len() < 2 {
+            let change = receiver.recv().await.unwrap();
+            if change.subject == "https://ws.example" {
+                actions.push(change.action);
+            }
+        }
+        assert_eq!(actions, vec!["tag_url", "delete_url"]);
+        assert!(database.get_all_urls(&Default::default()).await.unwrap().is_empty());
+    }
+}
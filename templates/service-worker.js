@@ -0,0 +1,79 @@
+// Offline support for Read it Later: precaches the app shell and keeps the
+// most recently viewed pages (including the saved URL list) for offline use.
+const BASE_PATH = '{{ base_path | safe }}';
+const CACHE_NAME = 'read-it-later-{{ version }}';
+const TAILWIND_URL = 'https://cdn.jsdelivr.net/npm/tailwindcss@2.2.19/dist/tailwind.min.css';
+const HTMX_URL = 'https://cdn.jsdelivr.net/npm/htmx.org@2.0.4/dist/htmx.min.js';
+const CDN_ASSETS = [TAILWIND_URL, HTMX_URL];
+const SHELL = [
+    `${BASE_PATH}/`,
+    `${BASE_PATH}/tags`,
+    `${BASE_PATH}/snippets`,
+    `${BASE_PATH}/manifest.webmanifest`,
+    `${BASE_PATH}/icons/icon-192.png`,
+    `${BASE_PATH}/icons/icon-512.png`,
+];
+
+self.addEventListener('install', event => {
+    event.waitUntil(
+        caches.open(CACHE_NAME)
+            .then(cache => Promise.all([
+                cache.addAll(SHELL),
+                // The stylesheet and HTMX are nice-to-haves; don't fail the install if the CDN is unreachable
+                ...CDN_ASSETS.map(asset => cache.add(new Request(asset, { mode: 'no-cors' })).catch(() => undefined)),
+            ]))
+            .then(() => self.skipWaiting())
+    );
+});
+
+self.addEventListener('activate', event => {
+    event.waitUntil(
+        caches.keys()
+            .then(keys => Promise.all(keys.filter(key => key !== CACHE_NAME).map(key => caches.delete(key))))
+            .then(() => self.clients.claim())
+    );
+});
+
+// Pages and listing data: try the network, fall back to the last cached copy
+async function networkFirst(request) {
+    const cache = await caches.open(CACHE_NAME);
+    try {
+        const response = await fetch(request);
+        if (response.ok) {
+            cache.put(request, response.clone());
+        }
+        return response;
+    } catch (error) {
+        const cached = await cache.match(request, { ignoreSearch: request.mode === 'navigate' });
+        return cached || cache.match(`${BASE_PATH}/`);
+    }
+}
+
+// Static assets: serve from the cache when present
+async function cacheFirst(request) {
+    const cached = await caches.match(request);
+    return cached || fetch(request);
+}
+
+self.addEventListener('fetch', event => {
+    const request = event.request;
+    if (request.method !== 'GET') {
+        return;
+    }
+
+    if (CDN_ASSETS.includes(request.url)) {
+        event.respondWith(cacheFirst(request));
+        return;
+    }
+
+    const url = new URL(request.url);
+    if (url.origin !== self.location.origin || !url.pathname.startsWith(`${BASE_PATH}/`)) {
+        return;
+    }
+
+    if (url.pathname.startsWith(`${BASE_PATH}/icons/`) || url.pathname === `${BASE_PATH}/manifest.webmanifest`) {
+        event.respondWith(cacheFirst(request));
+    } else if (request.mode === 'navigate' || url.pathname === `${BASE_PATH}/urls_with_tags`) {
+        event.respondWith(networkFirst(request));
+    }
+});